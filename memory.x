@@ -1,10 +1,32 @@
 MEMORY
 {
   /* NOTE 1 K = 1 KiBi = 1024 bytes */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 2M
+  /* Bank 1, sectors 0-11: the whole image (.text, .rodata, .data load
+     image) must fit, so it cannot reach the settings store behind it.
+     Bank 2 sectors 14-21 are free. */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 1M
+  /* Settings store (`SETTINGS_SECTOR_OFFSETS`), erased at run time: no code */
+  SETTINGS : ORIGIN = 0x08100000, LENGTH = 32K
+  /* Snapshot log (`SNAPSHOT_SECTOR_OFFSETS`), erased at run time: no code */
+  SNAPSHOT : ORIGIN = 0x081C0000, LENGTH = 256K
   RAM : ORIGIN = 0x20000000, LENGTH = 320K
+  /* External SDRAM on FMC bank 1, usable after `sdram::init` */
+  SDRAM : ORIGIN = 0xC0000000, LENGTH = 16M
 }
 
+/* Large buffers placed with `sdram_singleton!`. Not loaded or zeroed by
+   the runtime: the SDRAM only works once the FMC is set up. */
+SECTIONS
+{
+  .sdram (NOLOAD) : ALIGN(4)
+  {
+    __ssdram = .;
+    *(.sdram .sdram.*);
+    . = ALIGN(4);
+    __esdram = .;
+  } > SDRAM
+} INSERT AFTER .bss;
+
 /* This is where the call stack will be allocated. */
 /* The stack is of the full descending type. */
 /* NOTE Do NOT modify `_stack_start` unless you know what you are doing */
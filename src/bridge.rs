@@ -0,0 +1,253 @@
+//! # Bridge Builder
+//!
+//! Entry point for applications embedding the USB-UART bridge in their own
+//! RTIC app. `BridgeBuilder` brings up the hardware through `init_peripherals`
+//! and applies the chosen runtime options on top of the `config` defaults and
+//! the settings saved in flash:
+//! - UART port (route of the data port), baud rate and receiver timeout
+//! - USB class set and chunk size
+//! - Enabled optional subsystems and error push notifications
+//! - RS-485 driver enable polarity and turnaround delays (`rs485` feature)
+//!
+//! Buffer capacities (`RX_RING_BUFFER_LEN`, `TX_RING_BUFFER_LEN`,
+//! `DMA_BUFFER_LEN`, `DATA_PACKET_SIZE`) are compile-time constants in
+//! `config`; the builder only validates runtime values against them.
+//!
+//! # Example
+//! ```rust
+//! let bridge = BridgeBuilder::new()
+//!     .baud_rate(460_800)
+//!     .rx_timeout_bit_times(35)
+//!     .subsystems(Subsystem::STATS_SNAPSHOT)
+//!     .build(ctx.device)?;
+//! ```
+
+use crate::config::{
+    DATA_PACKET_SIZE, DEFAULT_CHUNK_SIZE, DEFAULT_ENABLED_SUBSYSTEMS, ERROR_NOTIFY_ENABLED,
+    RX_TIMEOUT_BIT_TIMES, USART3_BAUD_RATE, USART6_BAUD_RATE,
+};
+use crate::errors::errors::InitError;
+#[cfg(feature = "rs485")]
+use crate::peripherals::rs485::Rs485Config;
+use crate::peripherals::stm32f469_init::{init_peripherals, InitializedPeripherals};
+use crate::task_handlers::error_notify;
+use crate::task_handlers::task_registry::{self, Subsystem};
+use crate::task_handlers::uart_route;
+use bitflags::bitflags;
+use stm32f4xx_hal::pac;
+
+/// UART ports the bridge can drive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UartPort {
+    /// USART6 on PG14 (TX) / PG9 (RX), DMA2 streams 6/1
+    Usart6,
+    /// USART3 on PB10 (TX) / PB11 (RX), DMA1 streams 3/1
+    Usart3,
+}
+
+impl UartPort {
+    /// USART number, as used by `AT+UART=` and the console
+    pub const fn number(self) -> u8 {
+        match self {
+            Self::Usart6 => 6,
+            Self::Usart3 => 3,
+        }
+    }
+
+    /// Looks up a port by its USART number
+    pub const fn from_number(number: u8) -> Option<Self> {
+        match number {
+            6 => Some(Self::Usart6),
+            3 => Some(Self::Usart3),
+            _ => None,
+        }
+    }
+
+    /// Name for reports
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Usart6 => "usart6",
+            Self::Usart3 => "usart3",
+        }
+    }
+}
+
+bitflags! {
+    /// USB functions exposed to the host
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct UsbClasses: u8 {
+        /// CDC-ACM data port (bridged UART)
+        const CDC_ACM = 1 << 0;
+    }
+}
+
+/// Options applied by `BridgeBuilder::build`
+#[derive(Debug, Clone, Copy)]
+pub struct BridgeOptions {
+    pub uart: UartPort,
+    pub baud_rate: u32,
+    pub rx_timeout_bit_times: u16,
+    pub usb_classes: UsbClasses,
+    pub usb_chunk_size: usize,
+    pub subsystems: Subsystem,
+    pub error_notifications: bool,
+    #[cfg(feature = "rs485")]
+    pub rs485: Rs485Config,
+}
+
+/// Builder for the bridge hardware and policies
+pub struct BridgeBuilder {
+    options: BridgeOptions,
+}
+
+impl Default for BridgeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BridgeBuilder {
+    /// Creates a builder with the `config` defaults
+    pub fn new() -> Self {
+        Self {
+            options: BridgeOptions {
+                uart: UartPort::Usart6,
+                baud_rate: USART6_BAUD_RATE,
+                rx_timeout_bit_times: RX_TIMEOUT_BIT_TIMES,
+                usb_classes: UsbClasses::CDC_ACM,
+                usb_chunk_size: DEFAULT_CHUNK_SIZE,
+                subsystems: Subsystem::from_bits_truncate(DEFAULT_ENABLED_SUBSYSTEMS),
+                error_notifications: ERROR_NOTIFY_ENABLED,
+                #[cfg(feature = "rs485")]
+                rs485: Rs485Config::DEFAULT,
+            },
+        }
+    }
+
+    /// Selects the UART bridged to the data port at start
+    ///
+    /// Both UARTs are always initialized; the route can change at runtime.
+    pub fn uart(mut self, uart: UartPort) -> Self {
+        self.options.uart = uart;
+        self
+    }
+
+    /// Sets the UART baud rate in bits per second
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.options.baud_rate = baud_rate;
+        self
+    }
+
+    /// Sets the RX timeout in bit times (0 disables it)
+    pub fn rx_timeout_bit_times(mut self, bit_times: u16) -> Self {
+        self.options.rx_timeout_bit_times = bit_times;
+        self
+    }
+
+    /// Selects the USB functions exposed to the host
+    pub fn usb_classes(mut self, classes: UsbClasses) -> Self {
+        self.options.usb_classes = classes;
+        self
+    }
+
+    /// Sets the number of bytes moved to USB per write
+    pub fn usb_chunk_size(mut self, size: usize) -> Self {
+        self.options.usb_chunk_size = size;
+        self
+    }
+
+    /// Sets the optional subsystems enabled at start
+    pub fn subsystems(mut self, subsystems: Subsystem) -> Self {
+        self.options.subsystems = subsystems;
+        self
+    }
+
+    /// Enables or disables error push notifications
+    pub fn error_notifications(mut self, enabled: bool) -> Self {
+        self.options.error_notifications = enabled;
+        self
+    }
+
+    /// Sets the RS-485 driver enable polarity and turnaround delays of USART6
+    #[cfg(feature = "rs485")]
+    pub fn rs485(mut self, config: Rs485Config) -> Self {
+        self.options.rs485 = config;
+        self
+    }
+
+    /// Options collected so far
+    pub fn options(&self) -> &BridgeOptions {
+        &self.options
+    }
+
+    /// Initializes the hardware and applies the options
+    ///
+    /// # Arguments
+    /// * `device` - Peripheral access crate structure
+    ///
+    /// # Errors
+    /// Returns `InitError` if:
+    /// - An option is outside what this build supports (the RX timeout
+    ///   timer is only wired to USART6)
+    /// - Peripheral initialization fails (see `init_peripherals`)
+    pub fn build(self, device: pac::Peripherals) -> Result<InitializedPeripherals, InitError> {
+        let options = self.options;
+
+        if options.usb_chunk_size == 0 || options.usb_chunk_size > DATA_PACKET_SIZE {
+            return Err(InitError::UsbError);
+        }
+        if !options.usb_classes.contains(UsbClasses::CDC_ACM) {
+            // The bridge data path needs the CDC-ACM function
+            return Err(InitError::UsbError);
+        }
+        if options.uart == UartPort::Usart3 && options.rx_timeout_bit_times != RX_TIMEOUT_BIT_TIMES
+        {
+            // Only USART6 RX is jumpered to the timeout timer
+            return Err(InitError::UsartError);
+        }
+
+        let mut peripherals = init_peripherals(device)?;
+
+        match options.uart {
+            UartPort::Usart6 => {
+                if options.baud_rate != USART6_BAUD_RATE {
+                    peripherals
+                        .usart_6
+                        .set_baud_rate(options.baud_rate)
+                        .map_err(|_| InitError::UsartError)?;
+                }
+                if options.rx_timeout_bit_times != RX_TIMEOUT_BIT_TIMES {
+                    peripherals
+                        .usart_6
+                        .set_rx_timeout(options.rx_timeout_bit_times)
+                        .map_err(|_| InitError::UsartError)?;
+                }
+            }
+            UartPort::Usart3 => {
+                if options.baud_rate != USART3_BAUD_RATE {
+                    peripherals
+                        .usart_3
+                        .set_baud_rate(options.baud_rate)
+                        .map_err(|_| InitError::UsartError)?;
+                }
+            }
+        }
+        #[cfg(feature = "rs485")]
+        if options.rs485 != Rs485Config::DEFAULT {
+            peripherals
+                .usart_6
+                .set_rs485_config(options.rs485)
+                .map_err(|_| InitError::UsartError)?;
+        }
+        // Default options leave the saved route in place
+        if options.uart != UartPort::Usart6 {
+            uart_route::select(options.uart);
+        }
+
+        peripherals.otg_fs.set_chunk_size(options.usb_chunk_size);
+        task_registry::set_enabled_mask(options.subsystems);
+        error_notify::set_enabled(options.error_notifications);
+
+        Ok(peripherals)
+    }
+}
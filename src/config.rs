@@ -1,25 +1,89 @@
 /// Length of the DMA buffer (Direct Memory Access buffer size).
 /// This constant defines the number of bytes that the DMA buffer can hold.
+/// USART6 RX runs in double-buffer mode over two halves of this size.
 pub const DMA_BUFFER_LEN: usize = 128;
 
 /// Length of the USB OTG FS buffer.
 /// This constant sets the maximum size for the USB OTG FS buffer in bytes.
 pub const OTG_FS_BUFFER_LEN: usize = 1024;
 
-/// Length of the ring buffer.
-/// This constant specifies the number of elements the ring buffer can store.
-pub const RING_BUFFER_LEN: usize = 512;
+/// Length of the RX ring buffer (UART to USB).
+/// This constant specifies the number of bytes received from the UART that can wait for USB.
+pub const RX_RING_BUFFER_LEN: usize = 512;
+
+/// Length of the TX ring buffer (USB to UART).
+/// This constant specifies the number of bytes from the host that can wait for the UART.
+pub const TX_RING_BUFFER_LEN: usize = 512;
+
+/// RX ring buffer level that deasserts RTS (bytes).
+/// Leaves room for the two DMA buffers the UART peer may still send before it sees RTS.
+pub const RX_HIGH_WATERMARK: usize = RX_RING_BUFFER_LEN - 2 * DMA_BUFFER_LEN;
+
+/// RX ring buffer level that asserts RTS again after a high watermark (bytes).
+pub const RX_LOW_WATERMARK: usize = RX_RING_BUFFER_LEN / 4;
+
+/// TX ring buffer level at which host packets are left unread, so the endpoint NAKs (bytes).
+/// Leaves room for one packet doubled by CR to CRLF translation.
+pub const TX_HIGH_WATERMARK: usize = TX_RING_BUFFER_LEN - 2 * CDC_MAX_PACKET_SIZE;
+
+/// TX ring buffer level at which host packets are read again after a high watermark (bytes).
+pub const TX_LOW_WATERMARK: usize = TX_RING_BUFFER_LEN / 4;
 
 /// Size of each data packet.
-/// This constant defines the size of each data packet, typically used in data communication.
-/// It is set to 256 bytes, which is often used in custom communication protocols.
-pub const DATA_PACKET_SIZE: usize = 128;
+/// Capacity of the USB staging buffers and upper bound for the runtime-tunable chunk size.
+/// It is set to 256 bytes and is independent of the CDC endpoint packet size.
+pub const DATA_PACKET_SIZE: usize = 256;
+
+/// Default internal chunk size.
+/// Number of bytes moved from the RX ring buffer to USB per write. Tunable at runtime
+/// (up to `DATA_PACKET_SIZE`) to find the best throughput for a given host OS.
+pub const DEFAULT_CHUNK_SIZE: usize = 128;
+
+/// CDC bulk endpoint max packet size.
+/// Fixed at 64 bytes for USB full speed. Transfers that end exactly on a packet boundary
+/// are terminated with a zero-length packet.
+pub const CDC_MAX_PACKET_SIZE: usize = 64;
+
+/// HID keyboard report interval (milliseconds, `usb-hid` feature).
+/// Every character takes a press and a release report, so 1 ms types 500 characters per second.
+pub const HID_POLL_INTERVAL_MS: u8 = 1;
+
+/// Keystrokes buffered by the HID keyboard (`usb-hid` feature).
+/// UART data beyond this waits in the RX ring buffer.
+pub const HID_KEY_QUEUE_LEN: usize = 64;
+
+/// Mass storage bulk endpoint max packet size (`usb-msc` feature).
+/// Fixed at 64 bytes for USB full speed.
+pub const MSC_MAX_PACKET_SIZE: usize = 64;
+
+/// RAM holding the log volume files (`usb-msc` feature).
+/// Each file starts on a 512-byte sector; a report cut off at the end is marked as truncated.
+pub const MSC_IMAGE_LEN: usize = 4096;
+
+/// Log volume refresh check interval (milliseconds, `usb-msc` feature).
+/// The files are only rendered after a host attached or `msc refresh` was entered.
+pub const MSC_REFRESH_CHECK_MS: u32 = 500;
+
+/// Largest packet payload on the framed UART link.
+/// A frame adds a 4-byte CRC32, one COBS code byte and the delimiter, so a packet of
+/// this size fills the DMA TX buffer exactly. Longer host chunks are dropped in framed mode.
+pub const FRAME_MAX_PAYLOAD_LEN: usize = DMA_BUFFER_LEN - 6;
+
+/// Host-bound bytes queued by the transform stage.
+/// Holds the local echo and translated bytes left over by a partial USB write; beyond it the
+/// echo is dropped.
+pub const TRANSFORM_OUTBOX_LEN: usize = 512;
 
 /// USART6 baud rate.
 /// Sets the data transmission speed for USART6 communication, typically in bits per second.
 /// The baud rate is set to 115200, which is a common rate for serial communication.
 pub const USART6_BAUD_RATE: u32 = 115200;
 
+/// USART3 baud rate.
+/// Initial speed of the second bridged UART (PB10/PB11), used when the data
+/// port is routed to it.
+pub const USART3_BAUD_RATE: u32 = 115200;
+
 /// High-Speed External clock frequency (HSE).
 /// Represents the external oscillator frequency connected to the microcontroller.
 /// This value is typically set to 8 MHz for STM32F4 series microcontrollers.
@@ -40,7 +104,544 @@ pub const PCLK1: u32 = 45_000_000;
 /// It is set to 90 MHz and is derived from SYSCLK with the appropriate dividers.
 pub const PCLK2: u32 = 90_000_000;
 
+/// Clock outputs from boot.
+/// With `true`, MCO1 (PA8) puts out the HSE and MCO2 (PC9, not with `sd-log`) SYSCLK / 4 for
+/// scope checks. The console `mco` command switches them at runtime either way.
+pub const MCO_OUTPUT: bool = false;
+
+/// QSPI kernel clock divider minus one.
+/// The flash clock is SYSCLK / (QSPI_PRESCALER + 1): 90 MHz, within the 108 MHz the
+/// N25Q128A allows for quad fast reads.
+pub const QSPI_PRESCALER: u32 = 1;
+
+/// LTDC pixel clock in kHz (`display` feature).
+/// 60 Hz refresh of the 800x480 OTM8009A panel including its porches; generated by PLLSAI.
+pub const DISPLAY_PIXEL_CLOCK_KHZ: u32 = 27_429;
+
+/// Status screen redraw interval (milliseconds, `display` feature).
+/// Only lines whose text changed are redrawn, so a short interval is cheap.
+pub const DISPLAY_REFRESH_MS: u32 = 500;
+
+/// Limit of every I2C flag wait (microseconds, `i2c` feature).
+/// A byte takes 25 us at 400 kHz; a device stretching the clock longer is treated as stuck.
+pub const I2C_TIMEOUT_US: u32 = 2_000;
+
+/// Decoded USB-to-I2C requests waiting for the dispatcher (`i2c-bridge` feature).
+/// Four covers a burst from one USB packet; the host normally waits for each response.
+pub const I2C_BRIDGE_QUEUE_LEN: usize = 4;
+
+/// Limit of a CAN controller mode change (microseconds, `can` feature).
+/// Joining the bus waits for 11 recessive bits, 1.1 ms at 10 kbit/s.
+pub const CAN_TIMEOUT_US: u32 = 10_000;
+
+/// SLCAN command lines waiting for the dispatcher (`can` feature).
+/// One USB packet carries at most three transmit commands of standard frames.
+pub const SLCAN_QUEUE_LEN: usize = 8;
+
+/// SPI clock of a chip select until its device sets its own (Hz, `spi` feature).
+/// 1 MHz suits most sensors and converters; rounded down to a `PCLK1` power-of-two divider.
+pub const SPI_DEFAULT_FREQUENCY_HZ: u32 = 1_000_000;
+
+/// Margin of every SPI wait on top of the transfer's own bus time (microseconds, `spi` feature).
+pub const SPI_TIMEOUT_US: u32 = 1_000;
+
+/// Limit of one ADC scan (microseconds, `adc` feature).
+/// All eight channels take about 190 us at 22.5 MHz with the internal sensors' long sampling.
+pub const ADC_TIMEOUT_US: u32 = 1_000;
+
+/// ADC sampling period until the console sets another (milliseconds, `adc` feature).
+pub const ADC_SAMPLE_MS: u32 = 1_000;
+
+/// Shortest ADC sampling period accepted by the console (milliseconds, `adc` feature).
+/// Each scan busy-waits, so faster rates would starve the other priority-1 tasks.
+pub const ADC_MIN_SAMPLE_MS: u32 = 10;
+
+/// ADC stream buffer size in bytes (`adc` feature).
+/// Holds the records produced while no terminal is reading; newer records are dropped when full.
+pub const ADC_STREAM_BUFFER_LEN: usize = 512;
+
+/// Default die temperature limits of the health monitor (degrees C, `adc` feature).
+/// The sensor is accurate to about 1.5 C after calibration; 85 C is the ambient rating.
+pub const HEALTH_TEMP_MIN_C: i16 = -20;
+pub const HEALTH_TEMP_MAX_C: i16 = 85;
+
+/// Default VDDA limits of the health monitor (millivolts, `adc` feature).
+/// The board runs from 3.3 V; outside +-10% the flash and USB timings are at risk.
+pub const HEALTH_VDD_MIN_MV: u16 = 3_000;
+pub const HEALTH_VDD_MAX_MV: u16 = 3_600;
+
+/// Margins a reading must regain inside its limits before its alarm re-arms (`adc` feature).
+pub const HEALTH_TEMP_HYSTERESIS_C: i32 = 2;
+pub const HEALTH_VDD_HYSTERESIS_MV: u32 = 50;
+
+/// Waveform frequency range of the DAC (Hz, `dac` feature).
+/// 64 points per period: at 10 kHz the output steps every 1.6 us, still enough for the buffer to settle small steps.
+pub const DAC_MIN_FREQUENCY_HZ: u32 = 1;
+pub const DAC_MAX_FREQUENCY_HZ: u32 = 10_000;
+
+/// Limit of one RNG read (microseconds).
+/// A number is ready 40 PLL48CLK cycles after the previous one; none in this time means the generator stopped.
+pub const RNG_TIMEOUT_US: u32 = 10;
+
+/// Touch poll interval while a finger is down (milliseconds, `touch` feature).
+/// A contact is signalled by the FT6206 interrupt, the release is polled.
+pub const TOUCH_POLL_MS: u32 = 20;
+
+/// Hold time of on-screen buttons that need a long press (milliseconds, `touch` feature).
+/// Keeps a brushed REBOOT button from resetting the board.
+pub const TOUCH_HOLD_MS: u32 = 1_000;
+
+/// Baud rates stepped through by the on-screen NEXT BAUD button (`touch` feature).
+pub const TOUCH_BAUD_RATES: [u32; 8] = [
+    9_600, 19_200, 38_400, 57_600, 115_200, 230_400, 460_800, 921_600,
+];
+
 /// Maximum Morse code sequence length.
 /// Defines the maximum allowed length for a Morse code sequence, measured in characters or signals.
 /// This is typically used for buffer allocation and validation purposes.
 pub const MAX_MORSE_LENGTH: usize = 100;
+
+/// Default Morse dot duration (milliseconds).
+/// Dashes and gaps follow from it with the standard 1:3:7 ratios. Changed at runtime with the
+/// console `morse` command, within `MORSE_DOT_MIN_MS..=MORSE_DOT_MAX_MS`.
+pub const MORSE_DOT_MS: u32 = 200;
+
+/// Shortest accepted Morse dot duration (milliseconds).
+pub const MORSE_DOT_MIN_MS: u32 = 50;
+
+/// Longest accepted Morse dot duration (milliseconds).
+pub const MORSE_DOT_MAX_MS: u32 = 1_000;
+
+/// Default number of times an error code is played before it leaves the error store.
+pub const MORSE_REPEATS: u8 = 1;
+
+/// Outputs that signal error codes at boot.
+/// Bit mask over `signal_handler::SignalOutputs`: bit 0 the red LED, bit 1 the buzzer.
+/// Changed at runtime with the console `signal` command.
+pub const DEFAULT_SIGNAL_OUTPUTS: u8 = 0b01;
+
+/// Modem output lines driven high while the host asserts them, at boot.
+/// Bit mask over `modem_lines::ControlLines`: bit 0 DTR (PA1), bit 1 RTS (PA2); the others are
+/// active low like the outputs of USB-serial converter chips. Changed with the console `lines`.
+pub const DEFAULT_MODEM_ACTIVE_HIGH: u8 = 0b00;
+
+/// Buzzer tone frequency (Hz).
+/// Near the resonance of common 12 mm piezo transducers.
+pub const BUZZER_FREQUENCY_HZ: u32 = 2_700;
+
+/// Red LED brightness while a Morse symbol is on, out of 255 (`led-pwm` feature).
+/// Below full brightness, the red LED is hard to look at on the bench otherwise.
+pub const LED_PWM_RED_LEVEL: u8 = 160;
+
+/// Subsystems enabled at boot.
+/// Bit mask over `task_registry::Subsystem`; all optional subsystems start enabled
+/// and can be silenced at runtime through control commands.
+pub const DEFAULT_ENABLED_SUBSYSTEMS: u32 = 0xFFFF_FFFF;
+
+/// Deferred log queue length.
+/// Number of log records interrupt handlers can queue before the idle loop drains them.
+/// Records beyond this limit are dropped and counted rather than blocking the ISR.
+pub const DEFERRED_LOG_LEN: usize = 32;
+
+/// Error push notifications enabled at boot.
+/// When enabled, each newly enqueued error produces a notification frame on the debug console port.
+pub const ERROR_NOTIFY_ENABLED: bool = false;
+
+/// Maximum error notification frames per second.
+/// Errors arriving faster than this are coalesced into a single frame with a repeat count.
+pub const ERROR_NOTIFY_MAX_PER_SEC: u32 = 4;
+
+/// Statistics snapshot interval (milliseconds).
+/// A snapshot of key counters and the error summary is appended to flash this often.
+pub const SNAPSHOT_INTERVAL_MS: u32 = 600_000;
+
+/// Bridge statistics dump interval (milliseconds).
+/// Only runs while the `stats` subsystem is enabled.
+pub const STATS_REPORT_INTERVAL_MS: u32 = 10_000;
+
+/// Flash sectors reserved for the statistics snapshot history.
+/// Both sectors live in bank 2 so erasing them does not stall code running from bank 1.
+/// They are used alternately, which halves the erase count of each one.
+pub const SNAPSHOT_SECTORS: [u8; 2] = [22, 23];
+
+/// Byte offsets (from the flash base) of `SNAPSHOT_SECTORS`.
+/// Kept out of the code region by the `SNAPSHOT` region of `memory.x`.
+pub const SNAPSHOT_SECTOR_OFFSETS: [usize; 2] = [0x1C_0000, 0x1E_0000];
+
+/// Size of each snapshot sector in bytes.
+pub const SNAPSHOT_SECTOR_SIZE: usize = 128 * 1024;
+
+/// Flash sectors reserved for the persistent settings store.
+/// The two smallest free bank 2 sectors; the store switches to the other one,
+/// copying only the live values, each time the active one fills up.
+pub const SETTINGS_SECTORS: [u8; 2] = [12, 13];
+
+/// Byte offsets (from the flash base) of `SETTINGS_SECTORS`.
+/// Kept out of the code region by the `SETTINGS` region of `memory.x`.
+pub const SETTINGS_SECTOR_OFFSETS: [usize; 2] = [0x10_0000, 0x10_4000];
+
+/// Size of each settings sector in bytes.
+pub const SETTINGS_SECTOR_SIZE: usize = 16 * 1024;
+
+/// Maximum length of a configured USB serial number in characters.
+/// Holds the default serial number, the 96-bit unique device ID in hex; another one
+/// is saved with `AT+SERIAL=` or the console `serial` command.
+pub const SERIAL_NUMBER_LEN: usize = 24;
+
+/// USB vendor and product ID until others are saved with the console `usb id` command.
+/// 0x16C0/0x27DD is the shared V-USB pair for CDC-ACM devices; rebranded units should use their own.
+pub const USB_VID: u16 = 0x16C0;
+pub const USB_PID: u16 = 0x27DD;
+
+/// USB manufacturer and product string descriptors until others are saved with the
+/// console `usb maker` and `usb product` commands.
+pub const USB_MANUFACTURER: &str = "xvi.xv.xii.ix.xxii.ix.xiv";
+pub const USB_PRODUCT: &str = "USB-Serial Bridge";
+
+/// Maximum length of a configured USB manufacturer or product string in characters.
+/// The longest value that fits into one settings record.
+pub const USB_STRING_LEN: usize = 26;
+
+/// USART6 receive timeout in bit times.
+/// Buffered RX data is flushed after this much line silence. The default of 35
+/// matches the Modbus RTU 3.5 character gap at 10 bits per character.
+pub const RX_TIMEOUT_BIT_TIMES: u16 = 35;
+
+/// Poll interval of the RX idle flush task (milliseconds).
+/// Bytes left in the DMA buffer are flushed after at most twice this time,
+/// also without the TIM3 jumper or an IDLE interrupt.
+pub const RX_IDLE_POLL_MS: u32 = 5;
+
+/// Longest wait for the UART output to drain before a host break (milliseconds).
+/// The break request is dropped if the transmitter is still busy after it.
+pub const BREAK_DRAIN_MS: u32 = 20;
+
+/// Longest wait for a running UART DMA TX transfer to finish (milliseconds).
+/// Covers a full 512-byte transfer at 9600 baud; a stream still busy after it
+/// is considered wedged and stopped with `UsartError::Timeout`.
+pub const UART_STOP_TIMEOUT_MS: u32 = 1_000;
+
+/// Baud rates tried by the bridge-to-bridge negotiation, fastest first.
+/// The last entry should equal `USART6_BAUD_RATE`, the safe rate both ends start at.
+pub const NEGOTIATION_BAUD_RATES: [u32; 4] = [921_600, 460_800, 230_400, 115_200];
+
+/// Timeout for each negotiation step (milliseconds).
+pub const NEGOTIATION_STEP_TIMEOUT_MS: u32 = 200;
+
+/// How long a responder listens for a proposal before giving up (milliseconds).
+pub const NEGOTIATION_LISTEN_TIMEOUT_MS: u32 = 10_000;
+
+/// Standard rates an automatic baud rate detection snaps to, slowest first.
+pub const AUTOBAUD_BAUD_RATES: [u32; 11] = [
+    1_200, 2_400, 4_800, 9_600, 19_200, 38_400, 57_600, 115_200, 230_400, 460_800, 921_600,
+];
+
+/// Slowest rate the capture timer resolves (one bit fits its 16-bit counter).
+pub const AUTOBAUD_MIN_BAUD: u32 = 1_200;
+
+/// Fastest rate the detection measures; shorter pulses count as glitches.
+pub const AUTOBAUD_MAX_BAUD: u32 = 921_600;
+
+/// RX edge intervals measured before the shortest one is taken as the bit time.
+pub const AUTOBAUD_EDGES: u16 = 32;
+
+/// Largest deviation of a measured rate from its standard rate (percent).
+pub const AUTOBAUD_TOLERANCE_PCT: u32 = 5;
+
+/// How long a detection waits for RX traffic before keeping the old rate
+/// (milliseconds).
+pub const AUTOBAUD_TIMEOUT_MS: u32 = 10_000;
+
+/// RS-485 driver enable polarity (`rs485` feature): `true` drives DE high
+/// while transmitting, as the DE input of MAX485-type transceivers expects.
+pub const RS485_DE_ACTIVE_HIGH: bool = true;
+
+/// Time between asserting the RS-485 driver enable and the first start bit
+/// (bit times). Covers the driver enable time of slow transceivers.
+pub const RS485_PRE_DELAY_BITS: u16 = 1;
+
+/// Time the RS-485 driver stays enabled after the last stop bit (bit times).
+/// Holds the bus in the idle state until the failsafe biasing takes over.
+pub const RS485_POST_DELAY_BITS: u16 = 1;
+
+/// Longest turnaround delay, whatever the bit times and the baud rate
+/// (microseconds). The delays busy-wait, the one after TX in the USART interrupt.
+pub const RS485_MAX_DELAY_US: u32 = 1_000;
+
+/// Time a Modbus RTU slave has to start and finish its response (milliseconds).
+pub const MODBUS_RESPONSE_TIMEOUT_MS: u32 = 1_000;
+
+/// Most registers or coils one Modbus read returns; the JSON result of a full
+/// read fits the console reply.
+pub const MODBUS_MAX_READ_VALUES: usize = 64;
+
+/// Most registers one Modbus write carries; the console line limits it as well.
+pub const MODBUS_MAX_WRITE_VALUES: usize = 16;
+
+/// Whether USART6 data still reaches the host while the NMEA parser reads it;
+/// `false` leaves the host only the parsed fix (`AT+GPS?`). Changed at runtime
+/// with `AT+GPS=PASS|QUERY`.
+pub const NMEA_PASSTHROUGH: bool = true;
+
+/// Length of a throughput benchmark started by the user button (seconds).
+pub const BENCH_DEFAULT_SECONDS: u32 = 10;
+
+/// Longest throughput benchmark the console accepts (seconds).
+pub const BENCH_MAX_SECONDS: u32 = 300;
+
+/// Base of the random pause before a DMA stream restart (microseconds).
+/// The pause is drawn below this value doubled per consecutive failure, so at most 16 us before
+/// the retry limit, keeping the recovery inside the USART interrupt budget.
+pub const DMA_RESTART_BACKOFF_US: u32 = 4;
+
+/// Maximum number of buffers tracked by the memory inventory.
+pub const MEMINFO_MAX_ENTRIES: usize = 16;
+
+/// Number of in-flight USB chunks tracked by the TX sequence checker.
+/// Only used with the `tx-seq-check` feature.
+pub const TX_SEQUENCE_DEPTH: usize = 16;
+
+/// Maximum number of jobs handled by the periodic scheduler.
+pub const MAX_PERIODIC_JOBS: usize = 12;
+
+/// Pins never touched by unused-pin parking, as (port, pin) pairs.
+/// Covers the SWD debug pins, SWO and the HSE/LSE oscillator pins. Add board
+/// pins here that must keep their reset state (straps, externally driven nets).
+pub const PIN_PARK_EXCLUDE: &[(char, u8)] = &[
+    ('A', 13), // SWDIO
+    ('A', 14), // SWCLK
+    ('B', 3),  // SWO
+    ('C', 14), // LSE OSC32_IN
+    ('C', 15), // LSE OSC32_OUT
+    ('H', 0),  // HSE OSC_IN
+    ('H', 1),  // HSE OSC_OUT
+];
+
+/// Duration of the crash LED pattern played before a panic reset (milliseconds).
+pub const CRASH_SIGNAL_MS: u32 = 3_000;
+
+/// Half period of the crash LED pattern (milliseconds).
+/// Much faster than the Morse dot, so a crash cannot be mistaken for an error code.
+pub const CRASH_FLASH_MS: u32 = 50;
+
+/// Modem status input sampling interval (milliseconds).
+/// Changes of DSR/DCD/RI reach the host at most this much later.
+pub const MODEM_POLL_MS: u32 = 10;
+
+/// User button settling time after a press edge (milliseconds).
+pub const BUTTON_DEBOUNCE_MS: u32 = 30;
+
+/// Hold time that turns a button press into a long press (milliseconds).
+pub const BUTTON_LONG_PRESS_MS: u32 = 1_000;
+
+/// Button level sampling interval while held (milliseconds).
+pub const BUTTON_POLL_MS: u32 = 10;
+
+/// Activity LED sampling interval (milliseconds).
+/// A traffic burst keeps its LED on for at least this long.
+pub const ACTIVITY_LED_MS: u32 = 50;
+
+/// Length of the boot error-storm window (milliseconds).
+pub const BOOT_STORM_WINDOW_MS: u32 = 5_000;
+
+/// Errors within the boot window that trigger safe mode.
+/// A healthy boot records none; faulty wiring typically floods DMA/USART errors.
+pub const BOOT_STORM_ERROR_LIMIT: u32 = 32;
+
+/// Blue LED toggle interval while in safe mode (milliseconds).
+pub const SAFE_MODE_BLINK_MS: u32 = 150;
+
+/// Interval between SysTick/LSE clock cross-checks (milliseconds).
+pub const CLOCK_CHECK_INTERVAL_MS: u32 = 60_000;
+
+/// Time allowed for the LSE crystal to start (milliseconds).
+/// The RTC and drift estimation are disabled if it is not stable by then.
+pub const LSE_STARTUP_TIMEOUT_MS: u32 = 3_000;
+
+/// Crystal drift above which a warning is raised (parts per million).
+pub const CLOCK_DRIFT_LIMIT_PPM: u32 = 100;
+
+/// UART strap window after boot (milliseconds).
+/// During this window USART6 RX is interrupt-driven and watches for `STRAP_MAGIC`.
+pub const STRAP_WINDOW_MS: u32 = 500;
+
+/// Byte sequence that introduces a strap preset selection.
+/// It is followed by one ASCII digit indexing `STRAP_PRESETS`.
+pub const STRAP_MAGIC: [u8; 4] = *b"\x1bCFG";
+
+/// Configuration presets selectable by UART strap, as (baud rate, RX timeout bit times).
+pub const STRAP_PRESETS: [(u32, u16); 4] = [
+    (115_200, 35), // Default
+    (9_600, 35),   // Legacy instruments
+    (460_800, 35), // Fast link
+    (921_600, 0),  // Fast link, flush on IDLE only
+];
+
+/// Upper bound on the playback time of one chained Morse run (milliseconds).
+/// Further queued codes wait for the next run; a single code is always played.
+pub const MORSE_CHAIN_MAX_MS: u32 = 30_000;
+
+/// Baud rates tried for the STM32 ROM bootloader (0x7F autobaud, 8E1).
+pub const PROBE_STM32_BAUD_RATES: &[u32] = &[115_200, 57_600, 9_600];
+
+/// Baud rates tried for AVR STK500 bootloaders (Optiboot and older variants).
+pub const PROBE_AVR_BAUD_RATES: &[u32] = &[115_200, 57_600, 19_200];
+
+/// Baud rates tried for the ESP32 ROM bootloader SYNC command.
+pub const PROBE_ESP32_BAUD_RATES: &[u32] = &[115_200];
+
+/// Time a probed target has to answer one handshake (milliseconds).
+pub const PROBE_STEP_TIMEOUT_MS: u32 = 100;
+
+/// Consecutive budget overruns after which a task counts as chronically late.
+/// Isolated overruns are only counted; a streak raises `BudgetOverrun`.
+pub const BUDGET_CHRONIC_STREAK: u32 = 8;
+
+/// Debug console poll interval (milliseconds).
+/// The console port is serviced by a low-priority task; commands are rarely time critical.
+pub const CONSOLE_POLL_MS: u32 = 20;
+
+/// Longest debug console command line in bytes.
+/// Longer input is discarded up to the next line end.
+pub const CONSOLE_LINE_LEN: usize = 64;
+
+/// USB log enabled at boot (`usb-log` feature).
+/// Log frames are sent on the debug console port while a terminal has it open.
+pub const USB_LOG_ENABLED: bool = true;
+
+/// USB log buffer size in bytes (`usb-log` feature).
+/// Holds the frames logged while no terminal is reading; newer frames are dropped when full.
+pub const USB_LOG_BUFFER_LEN: usize = 1024;
+
+/// Longest USB log frame in bytes, including the header and line end.
+pub const USB_LOG_LINE_LEN: usize = 96;
+
+/// SD card log buffer size in bytes (`sd-log` feature).
+/// Holds the lines queued between two writes; newer lines are dropped when full.
+pub const SD_LOG_BUFFER_LEN: usize = 4096;
+
+/// Longest SD card log line in bytes, including the line end (`sd-log` feature).
+pub const SD_LOG_LINE_LEN: usize = 128;
+
+/// SD card log write interval (milliseconds, `sd-log` feature).
+/// Each write that added data also rewrites the directory entry, so short intervals wear the card.
+pub const SD_LOG_FLUSH_MS: u32 = 5_000;
+
+/// Interval of the traffic counter lines in the SD card log (milliseconds, `sd-log` feature).
+pub const SD_LOG_TRAFFIC_MS: u32 = 60_000;
+
+/// Silence before a `+++` escape on the data port (milliseconds).
+/// Keeps `+++` inside bridged data from switching to AT command mode.
+pub const AT_GUARD_MS: u32 = 1_000;
+
+/// Longest AT command line in bytes.
+pub const AT_LINE_LEN: usize = 32;
+
+/// Capacity of one AT command reply in bytes.
+pub const AT_REPLY_LEN: usize = 128;
+
+/// Time the host gets to collect the reply to a restart request (milliseconds).
+/// Covers the `AT+RESET` `OK`, the console `reboot` or `bootloader` reply and the status stage
+/// of a USB DFU_DETACH.
+pub const SHUTDOWN_REPLY_MS: u32 = 50;
+
+/// Longest wait for the bridge buffers to drain before a restart (milliseconds).
+/// Data still queued after it is dropped. With `SHUTDOWN_REPLY_MS` and `SHUTDOWN_DETACH_MS`,
+/// must stay below `DFU_DETACH_TIMEOUT_MS`.
+pub const SHUTDOWN_DRAIN_MS: u32 = 500;
+
+/// Time off the USB bus before the reset (milliseconds).
+/// Long enough for the host to register the disconnect before the device attaches again.
+pub const SHUTDOWN_DETACH_MS: u32 = 10;
+
+/// Bootloader entered after a USB DFU_DETACH or the console `bootloader` (vector table address).
+/// 0x1FFF_0000 is the STM32F469 ROM bootloader; a custom bootloader in flash can be used instead.
+pub const DFU_BOOTLOADER_ADDRESS: u32 = 0x1FFF_0000;
+
+/// Size of a custom bootloader image at `DFU_BOOTLOADER_ADDRESS` (bytes).
+/// Its last word holds the CRC32 (CRC-32/MPEG-2, little-endian) of the bytes before it, checked
+/// before each jump. Not used with the ROM bootloader.
+pub const DFU_BOOTLOADER_LEN: usize = 32 * 1024;
+
+/// Time the host allows between DFU_DETACH and the device leaving the bus (milliseconds).
+/// Reported in the DFU functional descriptor.
+pub const DFU_DETACH_TIMEOUT_MS: u32 = 1_000;
+
+/// Maximum DFU transfer size reported to the host (bytes).
+/// Matches the block size of the STM32 ROM DFU bootloader.
+pub const DFU_TRANSFER_SIZE: u16 = 2_048;
+
+/// Vendor request code of the Microsoft OS 2.0 descriptor set.
+/// Any value works; it only has to differ from the vendor requests of our own interfaces.
+pub const MS_OS_VENDOR_CODE: u8 = 0x20;
+
+/// Interface GUID of the DFU run-time interface, registered with WinUSB on Windows.
+/// Lets `dfu-util` open the interface without an INF file or a driver installer.
+pub const DFU_INTERFACE_GUID: &str = "{80CA1CBE-67B5-41B1-9955-496E3BC8DEFD}";
+
+/// Interface GUID of the vendor bulk bridge interface (`usb-vendor` feature), registered with WinUSB.
+/// Host programs look the device up by it through the WinUSB API; libusb finds it by VID:PID.
+pub const VENDOR_INTERFACE_GUID: &str = "{9323E22F-477E-4A95-B898-AAFC2BE3F3DF}";
+
+/// Independent watchdog timeout (milliseconds, LSI based so approximate).
+/// Must exceed the longest blocking operation at priority 1 (a 128 KB flash
+/// sector erase takes up to 4 s) plus one check window.
+pub const IWDG_TIMEOUT_MS: u32 = 8_000;
+
+/// Interval between watchdog check-in evaluations (milliseconds).
+/// Every task must check in at least once per window for the watchdog to be fed.
+pub const IWDG_CHECK_MS: u32 = 1_000;
+
+/// Window watchdog, in addition to the IWDG. Resets the MCU when its refresh
+/// task runs too early or not within ~14 ms of its period.
+pub const WWDG_ENABLED: bool = true;
+
+/// Window watchdog refresh period (milliseconds).
+/// At most 37 ms: the WWDG timeout at the full PCLK1 is 46.6 ms at most.
+pub const WWDG_REFRESH_MS: u32 = 32;
+
+/// Longest Stop mode period while the USB bus is suspended (milliseconds).
+/// The IWDG keeps counting in Stop; the RTC wakeup timer ends each period in
+/// time to reload it.
+pub const SUSPEND_WAKE_MS: u32 = IWDG_TIMEOUT_MS / 2;
+
+/// Time kept out of Stop mode after UART activity during a USB suspend
+/// (milliseconds). Lets the rest of a burst reach the RX ring buffer.
+pub const SUSPEND_UART_HOLD_MS: u32 = 100;
+
+/// Time without bridge traffic after which the `low-power` profile halves
+/// the core clock (milliseconds).
+pub const POWER_DOWNCLOCK_IDLE_MS: u32 = 10_000;
+
+/// VBUS sensing on PA9. Boards without PA9 on the USB bus voltage set this
+/// to `false`, so the core clock never drops for a missing host.
+pub const VBUS_SENSING: bool = true;
+
+/// VBUS sampling interval (milliseconds).
+pub const VBUS_POLL_MS: u32 = 10;
+
+/// Samples in a row a VBUS change must show before it counts.
+/// Together with `VBUS_POLL_MS`, well below the 100 ms attach debounce of
+/// the host, so the PLL runs again before the bus reset.
+pub const VBUS_DEBOUNCE_POLLS: u8 = 3;
+
+/// Supply voltage warnings from the programmable voltage detector (PVD).
+/// The detector draws a few microamps, also in Stop mode.
+pub const PVD_ENABLED: bool = true;
+
+/// VDD level below which a `SupplyDip` warning is raised (millivolts).
+/// The highest PVD level at or below it is used: 2000, 2100, 2300, 2500,
+/// 2600, 2700, 2800 or 2900. Keep it above the brown-out reset level in the
+/// option bytes, or the reset comes first.
+pub const PVD_THRESHOLD_MV: u16 = 2_900;
+
+/// Length of the remote wakeup resume signalling (milliseconds).
+/// USB 2.0 allows 1 to 15 ms.
+pub const REMOTE_WAKEUP_SIGNAL_MS: u32 = 10;
+
+/// Number of error codes kept in the persistent error log.
+/// Each entry takes 8 bytes of backup SRAM.
+pub const ERROR_LOG_LEN: usize = 32;
+
+/// Byte offset of the persistent error log in backup SRAM.
+pub const BACKUP_SRAM_ERROR_LOG_OFFSET: usize = 0;
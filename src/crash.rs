@@ -0,0 +1,77 @@
+//! # Crash Signalling
+//!
+//! Release-build panic handler that makes a panic reset visible on the board:
+//! - Interrupts are disabled; RTIC and the monotonic are considered dead
+//! - Both LEDs flash alternately at a rapid rate for `CRASH_SIGNAL_MS`
+//! - The MCU is then reset through the SCB
+//!
+//! ## LED Signal Codes
+//! | Pattern                                  | Meaning                      |
+//! |------------------------------------------|------------------------------|
+//! | Blue 4 s on / 1 s off                    | Normal operation (heartbeat) |
+//! | Red Morse digits                         | Queued error code            |
+//! | Blue rapid blink (150 ms)                | Safe mode after error storm  |
+//! | Red and blue alternating, 50 ms each     | Panic, reset follows         |
+//!
+//! A power glitch resets silently, so the pattern distinguishes it from a panic.
+//!
+//! ## Safety Considerations
+//! - LEDs are driven through BSRR writes because the HAL objects are owned by RTIC
+//! - Timing is busy-wait based on `SYSCLK`, assuming the clock tree is still configured
+
+use crate::config::{CRASH_FLASH_MS, CRASH_SIGNAL_MS, SYSCLK};
+use core::panic::PanicInfo;
+use stm32f4xx_hal::pac;
+
+/// Red LED pin (PD5)
+const RED_LED_PIN: u32 = 5;
+
+/// Blue LED pin (PK3)
+const BLUE_LED_PIN: u32 = 3;
+
+/// Busy-waits for the given number of milliseconds
+fn busy_wait_ms(ms: u32) {
+    cortex_m::asm::delay((SYSCLK / 1_000) * ms);
+}
+
+/// Sets the LED outputs
+///
+/// Both LEDs are active low: the pin is reset to light them.
+fn set_leds(red: bool, blue: bool) {
+    let bsrr = |on: bool, pin: u32| if on { 1 << (pin + 16) } else { 1 << pin };
+
+    // SAFETY: BSRR writes are atomic and only touch the two LED pins
+    unsafe {
+        (*pac::GPIOD::ptr())
+            .bsrr()
+            .write(|w| w.bits(bsrr(red, RED_LED_PIN)));
+        (*pac::GPIOK::ptr())
+            .bsrr()
+            .write(|w| w.bits(bsrr(blue, BLUE_LED_PIN)));
+    }
+}
+
+/// Plays the crash pattern and resets the MCU
+pub fn signal_and_reset() -> ! {
+    cortex_m::interrupt::disable();
+
+    let mut elapsed = 0;
+    let mut red = true;
+    while elapsed < CRASH_SIGNAL_MS {
+        set_leds(red, !red);
+        busy_wait_ms(CRASH_FLASH_MS);
+        red = !red;
+        elapsed += CRASH_FLASH_MS;
+    }
+
+    set_leds(false, false);
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Production panic handler
+///
+/// Debug builds keep `panic-probe` so the attached probe can halt and print a backtrace.
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    signal_and_reset()
+}
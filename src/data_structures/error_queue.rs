@@ -1,10 +1,212 @@
+use crate::utils::datetime::DateTime;
 use core::cell::RefCell;
 use cortex_m::interrupt::Mutex;
-use heapless::spsc::Queue;
+use heapless::Vec;
 
-/// A channel for transmitting errors, protected by a Mutex.
+/// Number of distinct error codes the store can track at a time.
+pub const ERROR_RECORD_CAPACITY: usize = 32;
+
+/// Severity of a reported error, least severe first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Transient condition, the device keeps working normally
+    Warning,
+    /// A function failed and was recovered
+    #[default]
+    Error,
+    /// The bridge or the device is impaired
+    Critical,
+}
+
+impl Severity {
+    /// All severities, most severe first
+    pub const BY_PRIORITY: [Severity; 3] = [Severity::Critical, Severity::Error, Severity::Warning];
+
+    /// Single-letter tag used in error dumps
+    pub const fn tag(self) -> char {
+        match self {
+            Severity::Warning => 'W',
+            Severity::Error => 'E',
+            Severity::Critical => 'C',
+        }
+    }
+}
+
+/// One error code with its occurrences coalesced.
+///
+/// Timestamps are RTC calendar times, so the host can line failures up with
+/// wall-clock time; they are `None` while the RTC is not running.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ErrorRecord {
+    /// Error code
+    pub code: u16,
+    /// Highest severity the code was reported with
+    pub severity: Severity,
+    /// Number of reports not yet consumed
+    pub count: u32,
+    /// Calendar time of the first report
+    pub first_seen: Option<DateTime>,
+    /// Calendar time of the most recent report
+    pub last_seen: Option<DateTime>,
+    /// Number of times the code was played on the red LED
+    pub plays: u8,
+}
+
+/// Error record store.
+///
+/// Holds one record per distinct code, in order of first report. A repeat of
+/// a pending code only bumps its count and `last_seen`, so a flood of the
+/// same transient error takes a single slot.
+#[derive(Debug)]
+pub struct ErrorStore {
+    records: Vec<ErrorRecord, ERROR_RECORD_CAPACITY>,
+}
+
+impl ErrorStore {
+    /// Creates an empty store
+    pub const fn new() -> Self {
+        Self {
+            records: Vec::new(),
+        }
+    }
+
+    /// Records one report of `code`
+    ///
+    /// When the store is full, the oldest record of a lower severity is
+    /// evicted to make room.
+    ///
+    /// # Returns
+    /// `false` if the report was dropped because no slot could be freed
+    pub fn record(&mut self, code: u16, severity: Severity, now: Option<DateTime>) -> bool {
+        if let Some(record) = self.records.iter_mut().find(|r| r.code == code) {
+            record.count = record.count.saturating_add(1);
+            record.severity = record.severity.max(severity);
+            record.last_seen = now;
+            return true;
+        }
+
+        let record = ErrorRecord {
+            code,
+            severity,
+            count: 1,
+            first_seen: now,
+            last_seen: now,
+            plays: 0,
+        };
+        if self.records.is_full() {
+            let weakest = Severity::BY_PRIORITY.iter().rev().find_map(|&level| {
+                if level >= severity {
+                    return None;
+                }
+                self.records.iter().position(|r| r.severity == level)
+            });
+            match weakest {
+                Some(index) => {
+                    self.records.remove(index);
+                }
+                None => return false,
+            }
+        }
+        self.records.push(record).is_ok()
+    }
+
+    /// Copies the records into `buf`, most severe first
+    ///
+    /// Records of equal severity keep the order of their first report.
+    ///
+    /// # Returns
+    /// Number of records copied, at most `buf.len()`
+    pub fn copy_by_priority(&self, buf: &mut [ErrorRecord]) -> usize {
+        let ordered = Severity::BY_PRIORITY
+            .iter()
+            .flat_map(|&level| self.records.iter().filter(move |r| r.severity == level));
+
+        let mut count = 0;
+        for (slot, record) in buf.iter_mut().zip(ordered) {
+            *slot = *record;
+            count += 1;
+        }
+        count
+    }
+
+    /// Looks up the record of `code`
+    pub fn get(&self, code: u16) -> Option<ErrorRecord> {
+        self.records.iter().find(|r| r.code == code).copied()
+    }
+
+    /// Consumes `count` reports of `code`
+    ///
+    /// The record is removed once all of its reports are consumed; reports
+    /// that arrived after the caller observed it stay pending, with the play
+    /// count restarted.
+    ///
+    /// # Returns
+    /// `false` if no record of `code` exists
+    pub fn consume(&mut self, code: u16, count: u32) -> bool {
+        let Some(index) = self.records.iter().position(|r| r.code == code) else {
+            return false;
+        };
+        if self.records[index].count > count {
+            self.records[index].count -= count;
+            self.records[index].plays = 0;
+        } else {
+            self.records.remove(index);
+        }
+        true
+    }
+
+    /// Counts one more playback of `code`
+    ///
+    /// # Returns
+    /// `false` if no record of `code` exists
+    pub fn mark_played(&mut self, code: u16) -> bool {
+        match self.records.iter_mut().find(|r| r.code == code) {
+            Some(record) => {
+                record.plays = record.plays.saturating_add(1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes and returns the most severe record
+    pub fn take_first(&mut self) -> Option<ErrorRecord> {
+        let index = Severity::BY_PRIORITY
+            .iter()
+            .find_map(|&level| self.records.iter().position(|r| r.severity == level))?;
+        Some(self.records.remove(index))
+    }
+
+    /// Removes all records
+    ///
+    /// # Returns
+    /// Number of records removed
+    pub fn clear(&mut self) -> usize {
+        let count = self.records.len();
+        self.records.clear();
+        count
+    }
+
+    /// Number of records
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Checks whether no record is pending
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+impl Default for ErrorStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pending errors, protected by a Mutex.
 ///
-/// The `ERROR_QUEUE` is a statically allocated, single-producer, single-consumer (SPSC) queue
-/// for error codes of type `u16`. The queue can hold up to 256 error codes at a time.
-/// The queue is protected by a `Mutex` to ensure safe access across interrupts and other contexts.
-pub static ERROR_QUEUE: Mutex<RefCell<Queue<u16, 256>>> = Mutex::new(RefCell::new(Queue::new()));
+/// The `ERROR_STORE` is statically allocated and holds up to
+/// `ERROR_RECORD_CAPACITY` distinct codes. It is protected by a `Mutex` to
+/// ensure safe access across interrupts and other contexts.
+pub static ERROR_STORE: Mutex<RefCell<ErrorStore>> = Mutex::new(RefCell::new(ErrorStore::new()));
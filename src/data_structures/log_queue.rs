@@ -0,0 +1,92 @@
+//! # Deferred Log Record Queue
+//!
+//! Interrupt handlers must not block on RTT writes. Instead of formatting in place,
+//! they push a compact record here and the idle loop prints it later:
+//! - Fixed-size records (level, static message, optional numeric value)
+//! - Records are dropped and counted when the queue is full
+//! - Every push requires an `IsrContext` token
+//!
+//! ## Audit Rule
+//! Each `#[task(binds = ...)]` handler starts with `IsrContext::enter()` and logs only
+//! through `isr_log!`. A CI check can therefore grep the handler bodies: any
+//! `defmt::` call next to an `IsrContext` is a latency regression.
+//! Helpers running inside a handler take its `IsrContext` as an argument
+//! (`handle_usb`, `handle_usart_error`, the error handler of `main`) and log
+//! the same way; direct `defmt` output from them needs `isr-direct-log`.
+
+use crate::config::DEFERRED_LOG_LEN;
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::spsc::Queue;
+
+/// Marker proving the caller runs in hardware interrupt context
+///
+/// Zero-sized; exists only so that interrupt-context logging is visible in the
+/// source and checkable by tooling.
+pub struct IsrContext {
+    _private: (),
+}
+
+impl IsrContext {
+    /// Declares the current handler body as interrupt context
+    #[inline(always)]
+    pub const fn enter() -> Self {
+        Self { _private: () }
+    }
+}
+
+/// Severity of a deferred record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Single deferred log entry
+#[derive(Debug, Clone, Copy)]
+pub struct DeferredRecord {
+    /// Severity used when the record is finally printed
+    pub level: LogLevel,
+    /// Static message text (never formatted in the ISR)
+    pub message: &'static str,
+    /// Optional value printed after the message
+    pub value: Option<u32>,
+}
+
+/// Queue of records waiting to be printed from idle
+static LOG_QUEUE: Mutex<RefCell<Queue<DeferredRecord, DEFERRED_LOG_LEN>>> =
+    Mutex::new(RefCell::new(Queue::new()));
+
+/// Number of records lost because the queue was full
+static DROPPED_RECORDS: AtomicU32 = AtomicU32::new(0);
+
+/// Queues a record from interrupt context
+///
+/// Never blocks; on overflow the record is discarded and counted.
+pub fn push(_ctx: &IsrContext, level: LogLevel, message: &'static str, value: Option<u32>) {
+    let record = DeferredRecord {
+        level,
+        message,
+        value,
+    };
+
+    let queued = interrupt::free(|cs| LOG_QUEUE.borrow(cs).borrow_mut().enqueue(record).is_ok());
+
+    if !queued {
+        DROPPED_RECORDS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Takes the oldest pending record
+pub fn pop() -> Option<DeferredRecord> {
+    interrupt::free(|cs| LOG_QUEUE.borrow(cs).borrow_mut().dequeue())
+}
+
+/// Returns and resets the dropped-record counter
+pub fn take_dropped() -> u32 {
+    DROPPED_RECORDS.swap(0, Ordering::Relaxed)
+}
@@ -1,3 +1,9 @@
 pub mod error_queue;
+pub mod log_queue;
 pub mod ring_buffer;
-pub mod typedefs;
\ No newline at end of file
+pub mod spsc;
+pub mod tx_pingpong;
+#[cfg(target_os = "none")]
+pub mod typedefs;
+#[cfg(feature = "tx-seq-check")]
+pub mod tx_sequence;
@@ -3,31 +3,145 @@
 //! Provides a no-std compatible circular buffer with:
 //! - Constant-time operations
 //! - Thread-unsafe but interrupt-safe design
-//! - Configurable buffer size
+//! - Capacity fixed at compile time by a const generic parameter
 //! - Detailed error handling
+//! - Optional high/low watermarks with a callback on each crossing, for
+//!   flow control before the buffer overflows
+//!
+//! The bridge data path itself uses the lock-free queues of `spsc`, which
+//! share `Watermarks`; the `RxRingBuffer` and `TxRingBuffer` aliases keep
+//! the same sizes from `config` for use within one context.
 
-use crate::config::RING_BUFFER_LEN;
+use crate::config::{RX_RING_BUFFER_LEN, TX_RING_BUFFER_LEN};
 use crate::errors::errors::RingBufferError;
 use core::fmt;
 use heapless::Vec;
 
-/// Circular buffer for USART communication
-pub struct RingBuffer {
-    buffer: [u8; RING_BUFFER_LEN],
+/// UART to USB buffer
+pub type RxRingBuffer = RingBuffer<RX_RING_BUFFER_LEN>;
+
+/// USB to UART buffer
+pub type TxRingBuffer = RingBuffer<TX_RING_BUFFER_LEN>;
+
+/// Fill level crossing reported to a watermark callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillLevel {
+    /// The buffer filled up to the high watermark
+    High,
+    /// The buffer drained to the low watermark after a `High`
+    Low,
+}
+
+/// Receiver of the watermark crossings, called inside `push`, `pop` and `clear`
+pub type WatermarkCallback = fn(FillLevel);
+
+/// High and low watermarks with hysteresis between them
+#[derive(Debug, Clone, Copy)]
+pub struct Watermarks {
+    high: usize,
+    low: usize,
+    callback: WatermarkCallback,
+}
+
+impl Watermarks {
+    /// Creates a watermark pair
+    ///
+    /// # Arguments
+    /// * `high` - Fill level (bytes) that reports `FillLevel::High`
+    /// * `low` - Fill level at or below which `FillLevel::Low` follows;
+    ///   clamped below `high`
+    /// * `callback` - Receiver of the crossings
+    pub const fn new(high: usize, low: usize, callback: WatermarkCallback) -> Self {
+        let low = if low < high {
+            low
+        } else {
+            high.saturating_sub(1)
+        };
+        Self {
+            high,
+            low,
+            callback,
+        }
+    }
+
+    /// Crossing of the watermarks at a fill level, if any
+    ///
+    /// # Arguments
+    /// * `count` - Current fill level (bytes)
+    /// * `above_high` - Whether `High` was the last crossing reported
+    pub const fn crossing(&self, count: usize, above_high: bool) -> Option<FillLevel> {
+        if !above_high && count >= self.high {
+            Some(FillLevel::High)
+        } else if above_high && count <= self.low {
+            Some(FillLevel::Low)
+        } else {
+            None
+        }
+    }
+
+    /// Reports a crossing to the callback
+    #[inline]
+    pub fn report(&self, level: FillLevel) {
+        (self.callback)(level);
+    }
+}
+
+/// Circular byte buffer holding up to `N` bytes
+pub struct RingBuffer<const N: usize> {
+    buffer: [u8; N],
     write_pos: usize,
     read_pos: usize,
     count: usize,
+    watermarks: Option<Watermarks>,
+    /// Reported `High` and not yet `Low`
+    above_high: bool,
 }
 
-impl RingBuffer {
+impl<const N: usize> RingBuffer<N> {
     /// Creates new empty buffer
     #[inline]
     pub const fn new() -> Self {
         Self {
-            buffer: [0u8; RING_BUFFER_LEN],
+            buffer: [0u8; N],
             write_pos: 0,
             read_pos: 0,
             count: 0,
+            watermarks: None,
+            above_high: false,
+        }
+    }
+
+    /// Creates new empty buffer with watermarks
+    #[inline]
+    pub const fn with_watermarks(watermarks: Watermarks) -> Self {
+        let mut buffer = Self::new();
+        buffer.watermarks = Some(watermarks);
+        buffer
+    }
+
+    /// Replaces the watermarks, `None` to remove them
+    ///
+    /// The new pair starts from the current fill level without a callback.
+    pub fn set_watermarks(&mut self, watermarks: Option<Watermarks>) {
+        self.above_high = watermarks.is_some_and(|marks| self.count >= marks.high);
+        self.watermarks = watermarks;
+    }
+
+    /// Checks whether the fill level reached the high watermark and has not
+    /// drained to the low one since
+    #[inline]
+    pub const fn is_above_high(&self) -> bool {
+        self.above_high
+    }
+
+    /// Reports a watermark crossing of the current fill level
+    fn check_watermarks(&mut self) {
+        let Some(marks) = self.watermarks else {
+            return;
+        };
+        if let Some(level) = marks.crossing(self.count, self.above_high) {
+            self.above_high = level == FillLevel::High;
+            marks.report(level);
         }
     }
 
@@ -47,7 +161,7 @@ impl RingBuffer {
             return Err(RingBufferError::BufferOverflow);
         }
 
-        let first_chunk_len = core::cmp::min(data_len, RING_BUFFER_LEN - self.write_pos);
+        let first_chunk_len = core::cmp::min(data_len, N - self.write_pos);
         let second_chunk_len = data_len - first_chunk_len;
 
         // Copy data in 1 or 2 operations
@@ -58,8 +172,9 @@ impl RingBuffer {
             self.buffer[..second_chunk_len].copy_from_slice(&data[first_chunk_len..]);
         }
 
-        self.write_pos = (self.write_pos + data_len) % RING_BUFFER_LEN;
+        self.write_pos = (self.write_pos + data_len) % N;
         self.count += data_len;
+        self.check_watermarks();
 
         #[cfg(feature = "debug")]
         defmt::debug!("Pushed {} bytes. New count: {}", data_len, self.count);
@@ -71,7 +186,7 @@ impl RingBuffer {
     ///
     /// # Errors
     /// Returns `RingBufferError::BufferOverflow` if insufficient space
-    pub fn push_n<const N: usize>(&mut self, data: &Vec<u8, N>) -> Result<(), RingBufferError> {
+    pub fn push_n<const M: usize>(&mut self, data: &Vec<u8, M>) -> Result<(), RingBufferError> {
         self.push(data.as_slice())
     }
 
@@ -85,7 +200,7 @@ impl RingBuffer {
             return 0;
         }
 
-        let first_chunk_len = core::cmp::min(to_read, RING_BUFFER_LEN - self.read_pos);
+        let first_chunk_len = core::cmp::min(to_read, N - self.read_pos);
         let second_chunk_len = to_read - first_chunk_len;
 
         output[..first_chunk_len]
@@ -95,8 +210,9 @@ impl RingBuffer {
             output[first_chunk_len..to_read].copy_from_slice(&self.buffer[..second_chunk_len]);
         }
 
-        self.read_pos = (self.read_pos + to_read) % RING_BUFFER_LEN;
+        self.read_pos = (self.read_pos + to_read) % N;
         self.count -= to_read;
+        self.check_watermarks();
 
         #[cfg(feature = "debug")]
         defmt::debug!("Popped {} bytes. Remaining: {}", to_read, self.count);
@@ -105,21 +221,18 @@ impl RingBuffer {
     }
 
     /// Extracts bytes into heapless::Vec
-    pub fn pop_n<const N: usize>(&mut self, count: usize) -> Vec<u8, N> {
+    pub fn pop_n<const M: usize>(&mut self, count: usize) -> Vec<u8, M> {
         let mut result = Vec::new();
-        let to_read = core::cmp::min(count, self.count).min(N);
+        let to_read = core::cmp::min(count, self.count).min(M);
 
         if to_read == 0 {
             return result;
         }
 
-        let mut temp_buf = [0u8; RING_BUFFER_LEN];
-        let bytes_read = self.pop(&mut temp_buf[..to_read]);
-
-        if result.extend_from_slice(&temp_buf[..bytes_read]).is_err() {
-            #[cfg(feature = "debug")]
-            defmt::error!("Failed to populate result vector");
-        }
+        // Cannot fail: `to_read` is bounded by the vector capacity
+        let _ = result.resize(to_read, 0);
+        let bytes_read = self.pop(&mut result);
+        result.truncate(bytes_read);
 
         result
     }
@@ -139,7 +252,13 @@ impl RingBuffer {
     /// Calculates available space
     #[inline]
     pub const fn available_space(&self) -> usize {
-        RING_BUFFER_LEN - self.count
+        N - self.count
+    }
+
+    /// Total capacity in bytes
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
     }
 
     /// Clears buffer contents and zeros memory
@@ -148,20 +267,21 @@ impl RingBuffer {
         self.read_pos = 0;
         self.count = 0;
         self.buffer.iter_mut().for_each(|x| *x = 0);
+        self.check_watermarks();
 
         #[cfg(feature = "debug")]
         defmt::info!("Buffer cleared and zeroized");
     }
 }
 
-impl Default for RingBuffer {
+impl<const N: usize> Default for RingBuffer<N> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 /// Automatically clear buffer when dropped
-impl Drop for RingBuffer {
+impl<const N: usize> Drop for RingBuffer<N> {
     fn drop(&mut self) {
         self.clear();
         #[cfg(feature = "debug")]
@@ -170,8 +290,8 @@ impl Drop for RingBuffer {
 }
 
 /// Debug implementation showing key metrics
-impl fmt::Debug for RingBuffer {
+impl<const N: usize> fmt::Debug for RingBuffer<N> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "RingBuffer[used: {}/{}]", self.count, RING_BUFFER_LEN)
+        write!(f, "RingBuffer[used: {}/{}]", self.count, N)
     }
 }
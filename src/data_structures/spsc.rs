@@ -0,0 +1,398 @@
+//! # Lock-Free SPSC Byte Queue
+//!
+//! Single-producer single-consumer byte pipe for the bridge data path, with
+//! `bbqueue`-like split semantics: the queue lives in static memory and is
+//! split once into a `Producer` and a `Consumer` half. The halves are
+//! separate RTIC resources, so the tasks filling a queue never lock out the
+//! tasks draining it:
+//! - The producer only advances the write index, the consumer only the
+//!   read index; each publishes its index with `Release` and loads the
+//!   other's with `Acquire`
+//! - Indices run over `0..2N`, so a full queue and an empty one differ
+//!   without a spare slot
+//! - Optional watermarks as on `RingBuffer`, checked by both halves
+//! - Besides copying `push`/`pop`, a grant API in the style of `bbqueue`:
+//!   `grant_max_remaining` + `commit` hand the producer contiguous free
+//!   space, `read` + `release` hand the consumer contiguous data in place,
+//!   e.g. for the UART TX DMA to send straight out of the queue
+//!
+//! The bridge data path uses the `RxQueue` (UART to USB) and `TxQueue`
+//! (USB to UART) aliases, sized in `config` like the ring buffers.
+
+use crate::config::{RX_RING_BUFFER_LEN, TX_RING_BUFFER_LEN};
+use crate::data_structures::ring_buffer::{FillLevel, Watermarks};
+use crate::errors::errors::RingBufferError;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// UART to USB queue
+pub type RxQueue = SpscQueue<RX_RING_BUFFER_LEN>;
+
+/// Filling half of the UART to USB queue
+pub type RxProducer = Producer<'static, RX_RING_BUFFER_LEN>;
+
+/// Draining half of the UART to USB queue
+pub type RxConsumer = Consumer<'static, RX_RING_BUFFER_LEN>;
+
+/// USB to UART queue
+pub type TxQueue = SpscQueue<TX_RING_BUFFER_LEN>;
+
+/// Filling half of the USB to UART queue
+pub type TxProducer = Producer<'static, TX_RING_BUFFER_LEN>;
+
+/// Draining half of the USB to UART queue
+pub type TxConsumer = Consumer<'static, TX_RING_BUFFER_LEN>;
+
+/// Byte queue holding up to `N` bytes, used through its split halves
+pub struct SpscQueue<const N: usize> {
+    buffer: UnsafeCell<[u8; N]>,
+    /// Next index to write, in `0..2N`; stored by the producer only
+    write: AtomicUsize,
+    /// Next index to read, in `0..2N`; stored by the consumer only
+    read: AtomicUsize,
+    watermarks: Option<Watermarks>,
+    /// Reported `High` and not yet `Low`
+    above_high: AtomicBool,
+}
+
+// SAFETY: The halves own disjoint regions of the buffer (written and not yet
+// read for the consumer, the rest for the producer) and hand bytes over
+// only through the atomic indices
+unsafe impl<const N: usize> Sync for SpscQueue<N> {}
+
+impl<const N: usize> SpscQueue<N> {
+    /// Creates new empty queue
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            buffer: UnsafeCell::new([0u8; N]),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            watermarks: None,
+            above_high: AtomicBool::new(false),
+        }
+    }
+
+    /// Creates new empty queue with watermarks
+    #[inline]
+    pub const fn with_watermarks(watermarks: Watermarks) -> Self {
+        Self {
+            buffer: UnsafeCell::new([0u8; N]),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            watermarks: Some(watermarks),
+            above_high: AtomicBool::new(false),
+        }
+    }
+
+    /// Splits the queue into its producer and consumer halves
+    ///
+    /// The halves borrow the queue, so it can be split again only once both
+    /// are gone; a queue in an RTIC `init` local splits for `'static`.
+    pub fn split(&mut self) -> (Producer<'_, N>, Consumer<'_, N>) {
+        let queue = &*self;
+        (Producer { queue }, Consumer { queue })
+    }
+
+    /// Bytes between a write and a read index
+    #[inline]
+    const fn distance(write: usize, read: usize) -> usize {
+        (write + 2 * N - read) % (2 * N)
+    }
+
+    /// Index `count` bytes after `index`
+    #[inline]
+    const fn advance(index: usize, count: usize) -> usize {
+        (index + count) % (2 * N)
+    }
+
+    /// Gets current data count, as seen from either half
+    #[inline]
+    fn len(&self) -> usize {
+        let read = self.read.load(Ordering::Acquire);
+        let write = self.write.load(Ordering::Acquire);
+        Self::distance(write, read)
+    }
+
+    /// Reports a watermark crossing of the current fill level
+    ///
+    /// The halves may run at different priorities and cross at the same
+    /// time; the state change and its callback form one critical section so
+    /// that the callbacks arrive in order. The common case, no crossing,
+    /// takes no critical section.
+    fn check_watermarks(&self) {
+        let Some(marks) = self.watermarks else {
+            return;
+        };
+        let above_high = self.above_high.load(Ordering::Relaxed);
+        if marks.crossing(self.len(), above_high).is_none() {
+            return;
+        }
+
+        cortex_m::interrupt::free(|_| {
+            let above_high = self.above_high.load(Ordering::Relaxed);
+            if let Some(level) = marks.crossing(self.len(), above_high) {
+                self.above_high
+                    .store(level == FillLevel::High, Ordering::Relaxed);
+                marks.report(level);
+            }
+        });
+    }
+
+    /// Copies `output.len()` bytes starting at index `read` into `output`
+    ///
+    /// # Safety
+    /// The bytes must be written and not yet consumed, so that the producer
+    /// does not touch them
+    unsafe fn copy_out(&self, read: usize, output: &mut [u8]) {
+        let base = self.buffer.get().cast::<u8>();
+        let position = read % N;
+        let first_chunk_len = core::cmp::min(output.len(), N - position);
+        let second_chunk_len = output.len() - first_chunk_len;
+
+        // Copy data in 1 or 2 operations
+        core::ptr::copy_nonoverlapping(base.add(position), output.as_mut_ptr(), first_chunk_len);
+        if second_chunk_len > 0 {
+            core::ptr::copy_nonoverlapping(
+                base,
+                output.as_mut_ptr().add(first_chunk_len),
+                second_chunk_len,
+            );
+        }
+    }
+}
+
+impl<const N: usize> Default for SpscQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Debug implementation showing key metrics
+impl<const N: usize> fmt::Debug for SpscQueue<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpscQueue[used: {}/{}]", self.len(), N)
+    }
+}
+
+/// Filling half of an `SpscQueue`
+pub struct Producer<'a, const N: usize> {
+    queue: &'a SpscQueue<N>,
+}
+
+impl<const N: usize> Producer<'_, N> {
+    /// Appends data to the queue
+    ///
+    /// # Errors
+    /// Returns `RingBufferError::BufferOverflow` if insufficient space
+    pub fn push(&mut self, data: &[u8]) -> Result<(), RingBufferError> {
+        let queue = self.queue;
+        let read = queue.read.load(Ordering::Acquire);
+        let write = queue.write.load(Ordering::Relaxed);
+        let space = N - SpscQueue::<N>::distance(write, read);
+
+        let data_len = data.len();
+        if data_len > space {
+            #[cfg(feature = "debug")]
+            defmt::warn!("Queue overflow attempt: {} > {}", data_len, space);
+            return Err(RingBufferError::BufferOverflow);
+        }
+
+        let base = queue.buffer.get().cast::<u8>();
+        let position = write % N;
+        let first_chunk_len = core::cmp::min(data_len, N - position);
+        let second_chunk_len = data_len - first_chunk_len;
+
+        // SAFETY: The `space` bytes from `write` on are not readable until
+        // the index store below, so the consumer does not touch them
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), base.add(position), first_chunk_len);
+            if second_chunk_len > 0 {
+                core::ptr::copy_nonoverlapping(
+                    data.as_ptr().add(first_chunk_len),
+                    base,
+                    second_chunk_len,
+                );
+            }
+        }
+
+        self.commit(data_len);
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("Pushed {} bytes. New count: {}", data_len, queue.len());
+
+        Ok(())
+    }
+
+    /// Write grant: the contiguous free space after the newest byte
+    ///
+    /// Ends at the physical end of the buffer or at the oldest unread byte,
+    /// so a producer such as a DMA stream can fill it in place. Bytes
+    /// written into it become readable with `commit`; an empty grant means
+    /// the queue is full up to the end of the buffer.
+    ///
+    /// # Arguments
+    /// * `max` - Upper bound of the grant length
+    pub fn grant_max_remaining(&mut self, max: usize) -> &mut [u8] {
+        let queue = self.queue;
+        let read = queue.read.load(Ordering::Acquire);
+        let write = queue.write.load(Ordering::Relaxed);
+        let space = N - SpscQueue::<N>::distance(write, read);
+        let position = write % N;
+        let len = space.min(N - position).min(max);
+
+        // SAFETY: Free bytes are not readable until `commit`, so the consumer
+        // does not touch them; the borrow of `self` keeps out a second grant
+        unsafe {
+            core::slice::from_raw_parts_mut(queue.buffer.get().cast::<u8>().add(position), len)
+        }
+    }
+
+    /// Makes the first `count` bytes of the last write grant readable
+    ///
+    /// # Returns
+    /// Number of bytes committed, at most the free space
+    pub fn commit(&mut self, count: usize) -> usize {
+        let queue = self.queue;
+        let read = queue.read.load(Ordering::Acquire);
+        let write = queue.write.load(Ordering::Relaxed);
+        let count = core::cmp::min(count, N - SpscQueue::<N>::distance(write, read));
+        if count == 0 {
+            return 0;
+        }
+
+        queue
+            .write
+            .store(SpscQueue::<N>::advance(write, count), Ordering::Release);
+        queue.check_watermarks();
+        count
+    }
+
+    /// Gets current data count
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Checks if the queue is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calculates available space; only grows until the next `push`
+    #[inline]
+    pub fn available_space(&self) -> usize {
+        N - self.queue.len()
+    }
+
+    /// Total capacity in bytes
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+/// Draining half of an `SpscQueue`
+pub struct Consumer<'a, const N: usize> {
+    queue: &'a SpscQueue<N>,
+}
+
+impl<const N: usize> Consumer<'_, N> {
+    /// Copies the oldest bytes into `output` without removing them
+    ///
+    /// # Returns
+    /// Number of bytes copied
+    pub fn peek(&self, output: &mut [u8]) -> usize {
+        let queue = self.queue;
+        let write = queue.write.load(Ordering::Acquire);
+        let read = queue.read.load(Ordering::Relaxed);
+        let to_read = core::cmp::min(output.len(), SpscQueue::<N>::distance(write, read));
+
+        // SAFETY: Bytes up to `write` were published by the producer, which
+        // does not overwrite them before the read index passes them
+        unsafe { queue.copy_out(read, &mut output[..to_read]) };
+        to_read
+    }
+
+    /// Read grant: the contiguous oldest bytes
+    ///
+    /// Ends at the newest byte or at the physical end of the buffer; wrapped
+    /// data follows in the next grant. The bytes stay in place and unchanged
+    /// until `release`, so they can be handed to a DMA stream as they are.
+    pub fn read(&self) -> &[u8] {
+        let queue = self.queue;
+        let write = queue.write.load(Ordering::Acquire);
+        let read = queue.read.load(Ordering::Relaxed);
+        let position = read % N;
+        let len = SpscQueue::<N>::distance(write, read).min(N - position);
+
+        // SAFETY: Bytes up to `write` were published by the producer, which
+        // does not overwrite them before the read index passes them
+        unsafe { core::slice::from_raw_parts(queue.buffer.get().cast::<u8>().add(position), len) }
+    }
+
+    /// Removes up to `count` of the oldest bytes, after a `peek` or a read
+    /// grant
+    ///
+    /// # Returns
+    /// Number of bytes removed
+    pub fn release(&mut self, count: usize) -> usize {
+        let queue = self.queue;
+        let write = queue.write.load(Ordering::Acquire);
+        let read = queue.read.load(Ordering::Relaxed);
+        let count = core::cmp::min(count, SpscQueue::<N>::distance(write, read));
+        if count == 0 {
+            return 0;
+        }
+
+        queue
+            .read
+            .store(SpscQueue::<N>::advance(read, count), Ordering::Release);
+        queue.check_watermarks();
+        count
+    }
+
+    /// Removes data from the queue into slice
+    ///
+    /// # Returns
+    /// Number of bytes actually read
+    pub fn pop(&mut self, output: &mut [u8]) -> usize {
+        let to_read = self.peek(output);
+        self.release(to_read);
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("Popped {} bytes. Remaining: {}", to_read, self.len());
+
+        to_read
+    }
+
+    /// Gets current data count; only grows until the next `release`
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Checks if the queue is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total capacity in bytes
+    #[inline]
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Drops all queued bytes
+    ///
+    /// Bytes the producer pushes meanwhile may survive.
+    pub fn clear(&mut self) {
+        self.release(N);
+
+        #[cfg(feature = "debug")]
+        defmt::info!("Queue cleared");
+    }
+}
@@ -0,0 +1,125 @@
+//! # TX Ping-Pong Buffers
+//!
+//! Zero-copy staging for the USB → UART path. USB packets are read straight
+//! into one of two DMA-capable buffers while the other one is transmitted:
+//! - Producers append to the filling buffer (`spare_mut` + `commit`)
+//! - `start` hands the filled buffer to the DMA and switches producers to
+//!   the other one
+//! - `release` returns the transmitted buffer once the stream is idle
+//!
+//! The buffer in flight is never handed out to producers, so the DMA reads
+//! stable memory without any further copy. The structure must stay at a
+//! fixed address while a buffer is in flight (it lives in an RTIC resource).
+
+use crate::config::DMA_BUFFER_LEN;
+use core::fmt;
+
+/// Pair of DMA TX buffers filled and transmitted alternately
+pub struct TxPingPong {
+    buffers: [[u8; DMA_BUFFER_LEN]; 2],
+    fill: [usize; 2],
+    filling: usize,
+    in_flight: bool,
+}
+
+impl TxPingPong {
+    /// Creates both buffers empty
+    pub const fn new() -> Self {
+        Self {
+            buffers: [[0; DMA_BUFFER_LEN]; 2],
+            fill: [0; 2],
+            filling: 0,
+            in_flight: false,
+        }
+    }
+
+    /// Bytes waiting in the filling buffer
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.fill[self.filling]
+    }
+
+    /// Checks whether no bytes wait for transmission
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Free space in the filling buffer
+    #[inline]
+    pub fn space(&self) -> usize {
+        DMA_BUFFER_LEN - self.len()
+    }
+
+    /// Free tail of the filling buffer for a producer to write into
+    pub fn spare_mut(&mut self) -> &mut [u8] {
+        let fill = self.fill[self.filling];
+        &mut self.buffers[self.filling][fill..]
+    }
+
+    /// Appends `len` bytes written into `spare_mut`
+    pub fn commit(&mut self, len: usize) {
+        let fill = &mut self.fill[self.filling];
+        *fill = (*fill + len).min(DMA_BUFFER_LEN);
+    }
+
+    /// Hands the filled buffer to the DMA
+    ///
+    /// # Returns
+    /// The bytes to transmit; `None` if nothing is staged or the other
+    /// buffer is still in flight
+    pub fn start(&mut self) -> Option<&[u8]> {
+        if self.in_flight || self.is_empty() {
+            return None;
+        }
+
+        let sent = self.filling;
+        self.filling ^= 1;
+        self.fill[self.filling] = 0;
+        self.in_flight = true;
+        Some(&self.buffers[sent][..self.fill[sent]])
+    }
+
+    /// Takes back a buffer from `start` whose transmission did not begin
+    ///
+    /// Only valid before any byte was committed to the new filling buffer.
+    pub fn abort(&mut self) {
+        if self.in_flight && self.is_empty() {
+            self.filling ^= 1;
+            self.in_flight = false;
+        }
+    }
+
+    /// Frees the buffer in flight
+    ///
+    /// Call only once the DMA stream is idle.
+    pub fn release(&mut self) {
+        if self.in_flight {
+            self.fill[self.filling ^ 1] = 0;
+            self.in_flight = false;
+        }
+    }
+
+    /// Discards all staged data
+    ///
+    /// The buffer in flight, if any, is left to the DMA.
+    pub fn clear(&mut self) {
+        self.fill[self.filling] = 0;
+    }
+}
+
+impl Default for TxPingPong {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Debug implementation showing key metrics
+impl fmt::Debug for TxPingPong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TxPingPong")
+            .field("staged", &self.len())
+            .field("in_flight", &self.in_flight)
+            .finish()
+    }
+}
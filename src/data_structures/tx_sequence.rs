@@ -0,0 +1,82 @@
+//! # TX Chunk Sequence Checker
+//!
+//! Debug aid (feature `tx-seq-check`) for the spawn-based USB → UART pipeline:
+//! - Every chunk accepted from USB is tagged with a sequence number and length
+//! - The DMA TX stage verifies that chunks arrive in order, exactly once, with
+//!   the length they were tagged with
+//! - Violations are reported as `DmaError::SequenceViolation`
+//!
+//! The module is compiled out entirely when the feature is disabled.
+
+use crate::config::TX_SEQUENCE_DEPTH;
+use crate::errors::errors::DmaError;
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+use heapless::spsc::Queue;
+
+/// Tag attached to one USB chunk
+#[derive(Debug, Clone, Copy)]
+struct ChunkTag {
+    sequence: u32,
+    length: usize,
+}
+
+/// Checker state shared by producer and consumer
+struct SequenceState {
+    pending: Queue<ChunkTag, TX_SEQUENCE_DEPTH>,
+    next_tag: u32,
+    next_expected: u32,
+}
+
+static STATE: Mutex<RefCell<SequenceState>> = Mutex::new(RefCell::new(SequenceState {
+    pending: Queue::new(),
+    next_tag: 0,
+    next_expected: 0,
+}));
+
+/// Tags a chunk at the producer (USB) stage
+///
+/// # Returns
+/// The sequence number assigned to the chunk
+pub fn tag(length: usize) -> u32 {
+    interrupt::free(|cs| {
+        let mut state = STATE.borrow(cs).borrow_mut();
+        let sequence = state.next_tag;
+        state.next_tag = sequence.wrapping_add(1);
+
+        if state.pending.enqueue(ChunkTag { sequence, length }).is_err() {
+            #[cfg(feature = "debug")]
+            defmt::warn!("TX sequence tag queue full, chunk {} untracked", sequence);
+        }
+        sequence
+    })
+}
+
+/// Verifies a chunk at the consumer (DMA TX) stage
+///
+/// # Errors
+/// Returns `DmaError::SequenceViolation` if the chunk is missing its tag, skips
+/// or repeats a sequence number, or has a different length than tagged.
+/// The checker resynchronizes on the received chunk afterwards.
+pub fn verify(length: usize) -> Result<u32, DmaError> {
+    interrupt::free(|cs| {
+        let mut state = STATE.borrow(cs).borrow_mut();
+        let tag = state.pending.dequeue().ok_or(DmaError::SequenceViolation)?;
+        let expected = state.next_expected;
+        state.next_expected = tag.sequence.wrapping_add(1);
+
+        if tag.sequence != expected || tag.length != length {
+            #[cfg(feature = "debug")]
+            defmt::error!(
+                "TX sequence violation: got #{} ({} bytes), expected #{} ({} bytes)",
+                tag.sequence,
+                tag.length,
+                expected,
+                length
+            );
+            return Err(DmaError::SequenceViolation);
+        }
+
+        Ok(tag.sequence)
+    })
+}
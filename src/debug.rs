@@ -4,6 +4,8 @@ use defmt_rtt as _; // Important! This initializes RTT
 /// Global logger configuration
 use defmt::*;
 
+use crate::errors::errors::DeviceError;
+
 /// Custom timestamp function for defmt logging
 ///
 /// This function generates a unique timestamp for each log entry. It uses an
@@ -48,8 +50,50 @@ fn panic() -> ! {
 
 /// Global error handler
 ///
-/// This function logs a custom error message using the `defmt` error log level.
+/// This function logs an error using the `defmt` error log level: the whole
+/// error with its source error, its code and its description.
 /// It provides a centralized way to report errors in the system.
-pub fn log_error(msg: &str) {
-    error!("Error occurred: {}", msg);
+pub fn log_error(error: &DeviceError) {
+    error!(
+        "Error occurred: {} (code {=u16}): {=str}",
+        error,
+        error.code(),
+        error.description()
+    );
+}
+
+/// Prints records queued from interrupt context
+///
+/// Called from `idle`, the only place where blocking on RTT cannot delay an ISR.
+/// Reports how many records were lost if the queue overflowed in the meantime.
+/// With the `usb-log` feature, each record is also queued for the USB log.
+pub fn drain_deferred() {
+    use crate::data_structures::log_queue::{self, LogLevel};
+
+    macro_rules! emit {
+        ($level:ident, $record:expr) => {
+            match $record.value {
+                Some(v) => $level!("{=str}: {=u32}", $record.message, v),
+                None => $level!("{=str}", $record.message),
+            }
+        };
+    }
+
+    while let Some(record) = log_queue::pop() {
+        #[cfg(feature = "usb-log")]
+        crate::task_handlers::usb_log::log_record(&record);
+
+        match record.level {
+            LogLevel::Trace => emit!(trace, record),
+            LogLevel::Debug => emit!(debug, record),
+            LogLevel::Info => emit!(info, record),
+            LogLevel::Warn => emit!(warn, record),
+            LogLevel::Error => emit!(error, record),
+        }
+    }
+
+    let dropped = log_queue::take_dropped();
+    if dropped > 0 {
+        warn!("{} deferred log records dropped", dropped);
+    }
 }
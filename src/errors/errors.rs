@@ -1,13 +1,113 @@
-use crate::{define_peripheral_error_enum, impl_error_conversion};
+use crate::data_structures::error_queue::Severity;
+use crate::{define_device_error_enum, define_peripheral_error_enum, impl_error_conversion};
+
+// ======================
+// Error Code Registry
+// ======================
+
+/// Error code registry
+///
+/// Every error code is a domain base plus a fixed offset from 1 to 99, so
+/// the hundreds name the domain: USB 1xx, DMA 2xx, USART 3xx and so on.
+/// Codes below 100 are conditions of the device itself (`DeviceError`).
+///
+/// The codes are blinked on the red LED, sent in notification frames and
+/// kept in the persistent error log, so they never change: a new variant
+/// takes the next free offset of its domain, a removed one leaves its offset
+/// unused, and a new domain takes the next free base.
+pub mod domain {
+    /// Codes per domain
+    pub const SIZE: u16 = 100;
+
+    /// Conditions of the device as a whole
+    pub const DEVICE: u16 = 0;
+    pub const USB: u16 = 100;
+    pub const DMA: u16 = 200;
+    pub const USART: u16 = 300;
+    pub const RING_BUFFER: u16 = 400;
+    pub const LED: u16 = 500;
+    pub const FLASH: u16 = 600;
+    pub const QSPI: u16 = 700;
+    pub const SDRAM: u16 = 800;
+    pub const DISPLAY: u16 = 900;
+    pub const TOUCH: u16 = 1000;
+    pub const I2C: u16 = 1100;
+    pub const SPI: u16 = 1200;
+    pub const CAN: u16 = 1300;
+    pub const ADC: u16 = 1400;
+    pub const DAC: u16 = 1500;
+    pub const RNG: u16 = 1600;
+    pub const SD_CARD: u16 = 1700;
+    pub const PROTOCOL: u16 = 1800;
+    pub const MODBUS: u16 = 1900;
+    pub const NMEA: u16 = 2000;
+    pub const INIT: u16 = 2100;
+
+    /// Registered domains by base, with their report names
+    const DOMAINS: [(u16, &str); 22] = [
+        (DEVICE, "device"),
+        (USB, "USB"),
+        (DMA, "DMA"),
+        (USART, "USART"),
+        (RING_BUFFER, "ring buffer"),
+        (LED, "LED"),
+        (FLASH, "flash"),
+        (QSPI, "QSPI"),
+        (SDRAM, "SDRAM"),
+        (DISPLAY, "display"),
+        (TOUCH, "touch"),
+        (I2C, "I2C"),
+        (SPI, "SPI"),
+        (CAN, "CAN"),
+        (ADC, "ADC"),
+        (DAC, "DAC"),
+        (RNG, "RNG"),
+        (SD_CARD, "SD card"),
+        (PROTOCOL, "protocol"),
+        (MODBUS, "Modbus"),
+        (NMEA, "NMEA"),
+        (INIT, "init"),
+    ];
+
+    // Each base starts a block of its own
+    const _: () = {
+        let mut i = 0;
+        while i < DOMAINS.len() {
+            assert!(DOMAINS[i].0 == i as u16 * SIZE);
+            i += 1;
+        }
+    };
+
+    /// Checks whether `base` is the base of a registered domain
+    pub const fn is_registered(base: u16) -> bool {
+        let mut i = 0;
+        while i < DOMAINS.len() {
+            if DOMAINS[i].0 == base {
+                return true;
+            }
+            i += 1;
+        }
+        false
+    }
+
+    /// Name of the domain `code` belongs to, `None` outside the registry
+    pub fn name(code: u16) -> Option<&'static str> {
+        DOMAINS
+            .get(usize::from(code / SIZE))
+            .map(|&(_, name)| name)
+    }
+}
+
 // ========================
 // Ring Buffer Error Domain
 // ========================
 
 define_peripheral_error_enum!(
     RingBufferError,
-    BufferOverflow => "Ring buffer overflow occurred",
-    InsufficientSpace => "Insufficient space in ring buffer",
-    BufferEmpty => "Ring buffer is empty"
+    base = domain::RING_BUFFER,
+    BufferOverflow = 1 => "Ring buffer overflow occurred",
+    InsufficientSpace = 2 => "Insufficient space in ring buffer",
+    BufferEmpty = 3 => "Ring buffer is empty"
 );
 
 // =================
@@ -16,8 +116,9 @@ define_peripheral_error_enum!(
 
 define_peripheral_error_enum!(
     LedError,
-    SetStateError => "Failed to set LED state",
-    ReadStateError => "Failed to read LED state"
+    base = domain::LED,
+    SetStateError = 1 => "Failed to set LED state",
+    ReadStateError = 2 => "Failed to read LED state"
 );
 
 // ==================
@@ -26,12 +127,18 @@ define_peripheral_error_enum!(
 
 define_peripheral_error_enum!(
     UsartError,
-    DmaError => "DMA error occurred in USART",
-    TransferError => "Transfer error in USART",
-    Timeout => "USART operation timed out",
-    NotInitialized => "USART not initialized",
-    BufferOverflow => "USART buffer overflow",
-    FlagNotSet => "USART flag not set",
+    base = domain::USART,
+    DmaError = 1 => "DMA error occurred in USART",
+    TransferError = 2 => "Transfer error in USART",
+    Timeout = 3 => "USART operation timed out",
+    NotInitialized = 4 => "USART not initialized",
+    BufferOverflow = 5 => "USART buffer overflow",
+    FlagNotSet = 6 => "USART flag not set",
+    InvalidConfig = 7 => "USART frame format not supported",
+    Overrun = 8 => "USART receiver overrun",
+    Framing = 9 => "USART framing error",
+    Noise = 10 => "USART noise detected",
+    Parity = 11 => "USART parity error",
 );
 
 // ================
@@ -40,12 +147,13 @@ define_peripheral_error_enum!(
 
 define_peripheral_error_enum!(
     UsbError,
-    NotInitialized => "USB device is not initialized",
-    ReadError => "Failed to read from USB",
-    WriteError => "Failed to write to USB",
-    BufferOverflow => "USB buffer overflow",
-    InitError => "Failed to initialize USB",
-    PollError => "Failed to poll USB"
+    base = domain::USB,
+    NotInitialized = 1 => "USB device is not initialized",
+    ReadError = 2 => "Failed to read from USB",
+    WriteError = 3 => "Failed to write to USB",
+    BufferOverflow = 4 => "USB buffer overflow",
+    InitError = 5 => "Failed to initialize USB",
+    PollError = 6 => "Failed to poll USB"
 );
 
 // =================
@@ -54,50 +162,443 @@ define_peripheral_error_enum!(
 
 define_peripheral_error_enum!(
     DmaError,
-    InitError => "Failed to initialize DMA",
-    TransferError => "DMA transfer error",
-    RetryLimitExceeded => "DMA retry limit exceeded",
-    BufferOverflow => "DMA buffer overflow",
-    BufferUnderflow => "DMA buffer underflow",
-    WriteError => "Failed to write using DMA",
-    ReadError => "Failed to read using DMA"
+    base = domain::DMA,
+    InitError = 1 => "Failed to initialize DMA",
+    TransferError = 2 => "DMA transfer error",
+    RetryLimitExceeded = 3 => "DMA retry limit exceeded",
+    BufferOverflow = 4 => "DMA buffer overflow",
+    BufferUnderflow = 5 => "DMA buffer underflow",
+    WriteError = 6 => "Failed to write using DMA",
+    ReadError = 7 => "Failed to read using DMA",
+    SequenceViolation = 8 => "TX chunk sequence violation"
+);
+
+// ===================
+// Flash Error Domain
+// ===================
+
+define_peripheral_error_enum!(
+    FlashError,
+    base = domain::FLASH,
+    EraseError = 1 => "Failed to erase flash sector",
+    ProgramError = 2 => "Failed to program flash",
+    OutOfBounds = 3 => "Flash access out of bounds",
+    NotErased = 4 => "Flash destination not erased"
+);
+
+// ==================
+// QSPI Error Domain
+// ==================
+
+define_peripheral_error_enum!(
+    QspiError,
+    base = domain::QSPI,
+    Timeout = 1 => "QSPI flash operation timed out",
+    OutOfBounds = 2 => "QSPI flash access out of bounds",
+    WrongId = 3 => "QSPI flash JEDEC ID not recognized",
+    EraseError = 4 => "QSPI flash erase failed",
+    ProgramError = 5 => "QSPI flash program failed",
+    WriteProtected = 6 => "QSPI flash write enable not latched",
+    MemoryMapped = 7 => "QSPI flash is memory-mapped"
+);
+
+// ===================
+// SDRAM Error Domain
+// ===================
+
+define_peripheral_error_enum!(
+    SdramError,
+    base = domain::SDRAM,
+    Timeout = 1 => "SDRAM controller stayed busy",
+    DataBus = 2 => "SDRAM data bus test failed",
+    AddressBus = 3 => "SDRAM address bus test failed"
+);
+
+// =====================
+// Display Error Domain
+// =====================
+
+define_peripheral_error_enum!(
+    DisplayError,
+    base = domain::DISPLAY,
+    NoFramebuffer = 1 => "No SDRAM framebuffer for the display",
+    DsiHost = 2 => "DSI host failed to start",
+    Panel = 3 => "OTM8009A panel setup failed"
+);
+
+define_peripheral_error_enum!(
+    TouchError,
+    base = domain::TOUCH,
+    NotFound = 1 => "FT6206 touch controller not found",
+    Bus = 2 => "Touch controller I2C transfer failed"
+);
+
+// =================
+// I2C Error Domain
+// =================
+
+define_peripheral_error_enum!(
+    I2cError,
+    base = domain::I2C,
+    Timeout = 1 => "I2C transfer timed out",
+    Nack = 2 => "I2C device did not acknowledge",
+    ArbitrationLost = 3 => "I2C arbitration lost",
+    Bus = 4 => "I2C bus error",
+    Busy = 5 => "I2C bus held busy"
+);
+
+// =================
+// SPI Error Domain
+// =================
+
+define_peripheral_error_enum!(
+    SpiError,
+    base = domain::SPI,
+    Timeout = 1 => "SPI transfer timed out",
+    ModeFault = 2 => "SPI mode fault",
+    Overrun = 3 => "SPI receive overrun",
+    Dma = 4 => "SPI DMA transfer error",
+    InvalidChipSelect = 5 => "No such SPI chip select",
+    LoopbackMismatch = 6 => "SPI loopback data mismatch, is MOSI jumpered to MISO?"
+);
+
+// =================
+// CAN Error Domain
+// =================
+
+define_peripheral_error_enum!(
+    CanError,
+    base = domain::CAN,
+    Timeout = 1 => "CAN controller mode change timed out, is a transceiver connected?",
+    Bitrate = 2 => "CAN bitrate not reachable from PCLK1",
+    MailboxFull = 3 => "All CAN transmit mailboxes busy",
+    BusOff = 4 => "CAN controller is bus-off",
+    Closed = 5 => "CAN channel not open for transmission"
+);
+
+// =================
+// ADC Error Domain
+// =================
+
+define_peripheral_error_enum!(
+    AdcError,
+    base = domain::ADC,
+    Timeout = 1 => "ADC scan did not complete in time",
+    Overrun = 2 => "ADC result overwritten before the DMA read it",
+    Dma = 3 => "ADC DMA transfer error",
+    NoChannels = 4 => "No ADC channel selected"
+);
+
+// =================
+// DAC Error Domain
+// =================
+
+define_peripheral_error_enum!(
+    DacError,
+    base = domain::DAC,
+    Level = 1 => "DAC level above VREF+",
+    Frequency = 2 => "DAC waveform frequency out of range"
+);
+
+// =================
+// RNG Error Domain
+// =================
+
+define_peripheral_error_enum!(
+    RngError,
+    base = domain::RNG,
+    Seed = 1 => "RNG noise source failed, generator restarted",
+    Clock = 2 => "RNG clock too slow",
+    Repeated = 3 => "RNG repeated a number",
+    Timeout = 4 => "RNG number not ready in time",
+    Busy = 5 => "RNG not enabled or in use"
+);
+
+// =====================
+// SD Card Error Domain
+// =====================
+
+define_peripheral_error_enum!(
+    SdCardError,
+    base = domain::SD_CARD,
+    NoCard = 1 => "No SD card inserted",
+    Init = 2 => "SD card did not initialize",
+    Io = 3 => "SD card block transfer failed",
+    NoFilesystem = 4 => "No FAT16/FAT32 file system on the SD card",
+    DirectoryFull = 5 => "SD card root directory is full",
+    DiskFull = 6 => "SD card is full"
 );
 
 // ======================
-// Device Error Domain
+// Protocol Error Domain
 // ======================
 
 define_peripheral_error_enum!(
+    ProtocolError,
+    base = domain::PROTOCOL,
+    FrameTooLong = 1 => "Frame exceeds the maximum packet length",
+    Malformed = 2 => "Invalid COBS frame",
+    CrcMismatch = 3 => "Packet CRC32 mismatch",
+    BufferTooSmall = 4 => "Output buffer too small for the frame"
+);
+
+define_peripheral_error_enum!(
+    ModbusError,
+    base = domain::MODBUS,
+    InvalidRequest = 1 => "Request outside the Modbus limits",
+    Timeout = 2 => "No response from the slave",
+    CrcMismatch = 3 => "Response CRC16 mismatch",
+    Malformed = 4 => "Response of the wrong length",
+    UnexpectedReply = 5 => "Response from another slave or function",
+    TransmitFailed = 6 => "Request could not be sent"
+);
+
+define_peripheral_error_enum!(
+    NmeaError,
+    base = domain::NMEA,
+    Checksum = 1 => "NMEA sentence checksum mismatch",
+    Malformed = 2 => "Malformed NMEA sentence",
+    TooLong = 3 => "NMEA sentence longer than 82 characters"
+);
+
+// ======================
+// Device Error Domain
+// ======================
+
+// Wraps the domain errors, so each keeps its own code and message; the
+// device's own conditions are numbered in the `domain::DEVICE` block.
+// Offsets 5 and 6 (bus and analog errors) are retired, those errors are
+// wrapped now.
+define_device_error_enum!(
     DeviceError,
-    UsbError => "USB device error occurred",
-    DmaError => "DMA error occurred",
-    BufferOverflow => "Device buffer overflow",
-    Timeout => "Operation timed out",
-    LedError => "LED error occurred"
+    base = domain::DEVICE,
+    wraps {
+        Usb(UsbError),
+        Dma(DmaError),
+        Usart(UsartError),
+        RingBuffer(RingBufferError),
+        Led(LedError),
+        Flash(FlashError),
+        Qspi(QspiError),
+        Sdram(SdramError),
+        Display(DisplayError),
+        Touch(TouchError),
+        I2c(I2cError),
+        Spi(SpiError),
+        Can(CanError),
+        Adc(AdcError),
+        Dac(DacError),
+        Rng(RngError),
+        SdCard(SdCardError),
+    },
+    Timeout = 1 => "Operation timed out",
+    ClockDrift = 2 => "Crystal drift exceeds limit",
+    BudgetOverrun = 3 => "Task execution budget chronically exceeded",
+    ImageCorrupt = 4 => "Bootloader image failed its CRC32 check",
+    TemperatureLimit = 7 => "Die temperature outside its limits",
+    SupplyLimit = 8 => "Supply voltage outside its limits",
+    ClockSwitch = 9 => "PLL did not lock, USB stays down at the low core clock",
+    WatchdogEarlyRefresh = 10 => "Window watchdog refreshed before its window opened",
+    WatchdogTimeout = 11 => "Window watchdog not refreshed in time",
+    SupplyDip = 12 => "Supply voltage dipped below the PVD threshold"
 );
 
+impl DeviceError {
+    /// Returns the severity the error is recorded with in the error store
+    ///
+    /// Critical errors take the bridge or the device down and are shown
+    /// first on the red LED; warnings are transient and recover by themselves.
+    /// UART line errors are warnings, any other USART error stops the data
+    /// path like a DMA error.
+    pub fn severity(&self) -> Severity {
+        match self {
+            DeviceError::Usart(
+                UsartError::Overrun | UsartError::Framing | UsartError::Noise | UsartError::Parity,
+            ) => Severity::Warning,
+            DeviceError::Usb(_)
+            | DeviceError::Dma(_)
+            | DeviceError::Usart(_)
+            | DeviceError::ClockSwitch
+            | DeviceError::WatchdogEarlyRefresh
+            | DeviceError::WatchdogTimeout => Severity::Critical,
+            DeviceError::Flash(_)
+            | DeviceError::Qspi(_)
+            | DeviceError::Sdram(_)
+            | DeviceError::ImageCorrupt
+            | DeviceError::TemperatureLimit
+            | DeviceError::SupplyLimit
+            | DeviceError::SupplyDip => Severity::Error,
+            DeviceError::RingBuffer(_)
+            | DeviceError::Led(_)
+            | DeviceError::Display(_)
+            | DeviceError::Touch(_)
+            | DeviceError::I2c(_)
+            | DeviceError::Spi(_)
+            | DeviceError::Can(_)
+            | DeviceError::Adc(_)
+            | DeviceError::Dac(_)
+            | DeviceError::Rng(_)
+            | DeviceError::SdCard(_)
+            | DeviceError::Timeout
+            | DeviceError::ClockDrift
+            | DeviceError::BudgetOverrun => Severity::Warning,
+        }
+    }
+}
+
 // ========================
 // Initialization Errors
 // ========================
 
-define_peripheral_error_enum!(
-    InitError,
-    UsartError => "USART initialization error",
-    UsbError => "USB initialization error",
-    RccError => "RCC initialization error",
-    LutError => "LUT initialization error"
-);
+/// Configuration register that did not read back as expected
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(any(test, feature = "debug"), derive(defmt::Format))]
+pub struct RegisterMismatch {
+    /// Register name, e.g. "USART6.CR1"
+    pub register: &'static str,
+    /// Bits checked by the verification
+    pub mask: u32,
+    /// Expected value of the masked bits
+    pub expected: u32,
+    /// Value read back (masked)
+    pub actual: u32,
+}
+
+/// Initialization errors
+///
+/// Written out by hand instead of `define_peripheral_error_enum!` because
+/// `RegisterMismatch` carries a payload; the generated API is mirrored.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "debug"), derive(defmt::Format))]
+pub enum InitError {
+    UsartError,
+    UsbError,
+    RccError,
+    LutError,
+    RegisterMismatch(RegisterMismatch),
+}
+
+impl core::fmt::Display for InitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InitError::RegisterMismatch(m) => write!(
+                f,
+                "Register read-back mismatch: {} expected {:#010x}, read {:#010x} (mask {:#010x})",
+                m.register, m.expected, m.actual, m.mask
+            ),
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl InitError {
+    /// Returns the static error message associated with this variant
+    pub fn description(&self) -> &'static str {
+        match self {
+            InitError::UsartError => "USART initialization error",
+            InitError::UsbError => "USB initialization error",
+            InitError::RccError => "RCC initialization error",
+            InitError::LutError => "LUT initialization error",
+            InitError::RegisterMismatch(_) => "Register read-back mismatch",
+        }
+    }
+
+    /// Returns the numeric error code corresponding to this variant
+    ///
+    /// Codes are fixed offsets in the `domain::INIT` block
+    pub fn code(&self) -> u16 {
+        domain::INIT
+            + match self {
+                InitError::UsartError => 1,
+                InitError::UsbError => 2,
+                InitError::RccError => 3,
+                InitError::LutError => 4,
+                InitError::RegisterMismatch(_) => 5,
+            }
+    }
+}
+
+impl core::error::Error for InitError {}
 
 // ==============================
 // Error Conversion Implementations
 // ==============================
 
-impl_error_conversion!(UsbError, DeviceError, { UsbError });
+impl_error_conversion!(UsbError, DeviceError, Usb);
+
+impl_error_conversion!(DmaError, DeviceError, Dma);
+
+impl_error_conversion!(UsartError, DeviceError, Usart);
+
+impl_error_conversion!(LedError, DeviceError, Led);
+
+impl_error_conversion!(RingBufferError, DeviceError, RingBuffer);
 
-impl_error_conversion!(DmaError, DeviceError, { DmaError });
+impl_error_conversion!(FlashError, DeviceError, Flash);
+
+impl_error_conversion!(QspiError, DeviceError, Qspi);
+
+impl_error_conversion!(SdramError, DeviceError, Sdram);
+
+impl_error_conversion!(DisplayError, DeviceError, Display);
+
+impl_error_conversion!(TouchError, DeviceError, Touch);
+
+impl_error_conversion!(I2cError, DeviceError, I2c);
+
+impl_error_conversion!(SpiError, DeviceError, Spi);
+
+impl_error_conversion!(CanError, DeviceError, Can);
+
+impl_error_conversion!(AdcError, DeviceError, Adc);
+
+impl_error_conversion!(DacError, DeviceError, Dac);
+
+impl_error_conversion!(RngError, DeviceError, Rng);
+
+impl_error_conversion!(SdCardError, DeviceError, SdCard);
+
+// ==============================
+// Embedded HAL Error Kinds
+// ==============================
 
-impl_error_conversion!(UsartError, DeviceError, { DmaError });
+/// Kinds for drivers layered on the `embedded_io` traits of the UARTs
+impl embedded_io::Error for UsartError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            UsartError::Overrun | UsartError::Framing | UsartError::Noise | UsartError::Parity => {
+                embedded_io::ErrorKind::InvalidData
+            }
+            UsartError::Timeout => embedded_io::ErrorKind::TimedOut,
+            UsartError::NotInitialized => embedded_io::ErrorKind::NotConnected,
+            UsartError::BufferOverflow => embedded_io::ErrorKind::OutOfMemory,
+            UsartError::InvalidConfig => embedded_io::ErrorKind::InvalidInput,
+            _ => embedded_io::ErrorKind::Other,
+        }
+    }
+}
 
-impl_error_conversion!(LedError, DeviceError, { LedError });
+/// Kinds for drivers layered on the `embedded_hal_nb` serial traits
+impl embedded_hal_nb::serial::Error for UsartError {
+    fn kind(&self) -> embedded_hal_nb::serial::ErrorKind {
+        match self {
+            UsartError::Overrun => embedded_hal_nb::serial::ErrorKind::Overrun,
+            UsartError::Framing => embedded_hal_nb::serial::ErrorKind::FrameFormat,
+            UsartError::Noise => embedded_hal_nb::serial::ErrorKind::Noise,
+            UsartError::Parity => embedded_hal_nb::serial::ErrorKind::Parity,
+            _ => embedded_hal_nb::serial::ErrorKind::Other,
+        }
+    }
+}
 
-impl_error_conversion!(RingBufferError, DeviceError, { BufferOverflow });
\ No newline at end of file
+/// Kinds for drivers layered on the `embedded_io` traits of the USB bridge
+/// port; `NotInitialized` also stands for a port the host has not opened
+impl embedded_io::Error for UsbError {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            UsbError::NotInitialized => embedded_io::ErrorKind::NotConnected,
+            UsbError::BufferOverflow => embedded_io::ErrorKind::OutOfMemory,
+            _ => embedded_io::ErrorKind::Other,
+        }
+    }
+}
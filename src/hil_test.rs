@@ -0,0 +1,239 @@
+//! # On-Target Integration Tests (HIL)
+//!
+//! Sequential test suite compiled in with the `hil-test` feature so CI with a
+//! connected Discovery board can validate firmware end-to-end:
+//! - Ring buffer, SPSC queue and Morse encoder logic on the real target
+//! - Error store path (record, repeat count, LED display)
+//! - USART6 DMA loopback (requires PG14 jumpered to PG9)
+//! - USB echo (requires the host harness to echo the probe back)
+//!
+//! Each result is reported over RTT and as a `ESC "!HIL "` frame on the CDC port.
+//! While tests run, the bridge forwarding tasks leave data in the ring buffers so
+//! the runner can inspect it.
+
+use crate::config::{RX_RING_BUFFER_LEN, TX_RING_BUFFER_LEN};
+use crate::data_structures::ring_buffer::RingBuffer;
+use crate::data_structures::spsc::{RxConsumer, SpscQueue, TxConsumer};
+use crate::task_handlers::error_handlers::{add_error_code, find_record, has_errors};
+use crate::utils::morse::number_to_morse;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use heapless::String;
+
+/// Delay before the suite starts, giving the host time to open the port (ms)
+pub const HIL_START_DELAY_MS: u32 = 2_000;
+
+/// Time allowed for loopback data to arrive (ms)
+pub const HIL_LOOPBACK_TIMEOUT_MS: u32 = 100;
+
+/// Time allowed for the host to echo the USB probe (ms)
+pub const HIL_USB_ECHO_TIMEOUT_MS: u32 = 1_000;
+
+/// Pattern sent through the UART loopback
+pub const LOOPBACK_PATTERN: &[u8] = b"HIL-LOOPBACK-0123456789";
+
+/// Probe the host harness must echo back
+pub const USB_ECHO_PROBE: &[u8] = b"HIL-ECHO-PROBE";
+
+/// Error code injected by the error path test
+pub const HIL_TEST_ERROR_CODE: u16 = 9_999;
+
+/// Maximum length of one report frame
+pub const HIL_REPORT_LEN: usize = 64;
+
+/// Capacity of the buffers exercised by `test_ring_buffer` and `test_spsc_queue`
+const TEST_RING_LEN: usize = 64;
+
+/// Set while the suite owns the bridge data path
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+/// Result of a single test
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Pass,
+    Fail(&'static str),
+    Skip(&'static str),
+}
+
+/// Aggregated suite results
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub passed: u8,
+    pub failed: u8,
+    pub skipped: u8,
+}
+
+impl Summary {
+    /// Counts one test outcome
+    pub fn record(&mut self, outcome: &Outcome) {
+        match outcome {
+            Outcome::Pass => self.passed += 1,
+            Outcome::Fail(_) => self.failed += 1,
+            Outcome::Skip(_) => self.skipped += 1,
+        }
+    }
+
+    /// Encodes the final summary frame
+    pub fn format(&self) -> String<HIL_REPORT_LEN> {
+        let mut frame = String::new();
+        let _ = write!(
+            frame,
+            "\x1b!HIL DONE passed={} failed={} skipped={}\r\n",
+            self.passed, self.failed, self.skipped
+        );
+        frame
+    }
+}
+
+/// Encodes a single result frame
+pub fn format_result(name: &str, outcome: &Outcome) -> String<HIL_REPORT_LEN> {
+    let mut frame = String::new();
+    let _ = match outcome {
+        Outcome::Pass => write!(frame, "\x1b!HIL {} PASS\r\n", name),
+        Outcome::Fail(why) => write!(frame, "\x1b!HIL {} FAIL {}\r\n", name, why),
+        Outcome::Skip(why) => write!(frame, "\x1b!HIL {} SKIP {}\r\n", name, why),
+    };
+    frame
+}
+
+/// Hands the bridge data path to (or back from) the test runner
+///
+/// Host data is kept in the TX ring buffer while capturing.
+pub fn set_capture(active: bool) {
+    CAPTURING.store(active, Ordering::SeqCst);
+    crate::task_handlers::otg_fs::set_tx_staging(!active);
+}
+
+/// Checks whether forwarding tasks must leave ring buffer data untouched
+pub fn is_capturing() -> bool {
+    CAPTURING.load(Ordering::SeqCst)
+}
+
+/// Ring buffer push/pop, wrap-around and overflow behaviour
+pub fn test_ring_buffer() -> Outcome {
+    let mut buffer = RingBuffer::<TEST_RING_LEN>::new();
+    let mut out = [0u8; 8];
+
+    if buffer.push(b"abcdef").is_err() || buffer.len() != 6 {
+        return Outcome::Fail("push");
+    }
+    if buffer.pop(&mut out[..4]) != 4 || &out[..4] != b"abcd" {
+        return Outcome::Fail("pop");
+    }
+
+    // Fill past the physical end to force a wrap
+    let filler = [0x55u8; TEST_RING_LEN - 8];
+    if buffer.push(&filler).is_err() || buffer.push(b"wrap").is_err() {
+        return Outcome::Fail("wrap push");
+    }
+    if buffer.push(&[0u8; 8]).is_ok() {
+        return Outcome::Fail("overflow not detected");
+    }
+
+    let mut skip = [0u8; TEST_RING_LEN];
+    buffer.pop(&mut skip[..TEST_RING_LEN - 6]);
+    if buffer.pop(&mut out[..4]) != 4 || &out[..4] != b"wrap" || !buffer.is_empty() {
+        return Outcome::Fail("wrap pop");
+    }
+
+    Outcome::Pass
+}
+
+/// SPSC queue push/pop, peek/release, grants, wrap-around and overflow behaviour
+pub fn test_spsc_queue() -> Outcome {
+    let mut queue = SpscQueue::<TEST_RING_LEN>::new();
+    let (mut producer, mut consumer) = queue.split();
+    let mut out = [0u8; 8];
+
+    if producer.push(b"abcdef").is_err() || consumer.len() != 6 {
+        return Outcome::Fail("push");
+    }
+    if consumer.peek(&mut out[..4]) != 4 || consumer.len() != 6 {
+        return Outcome::Fail("peek");
+    }
+    if consumer.release(2) != 2 || consumer.pop(&mut out[..2]) != 2 || &out[..2] != b"cd" {
+        return Outcome::Fail("release");
+    }
+
+    // Grants stop at the physical end of the buffer
+    let grant = producer.grant_max_remaining(usize::MAX);
+    if grant.len() != TEST_RING_LEN - 6 {
+        return Outcome::Fail("write grant");
+    }
+    grant[..2].copy_from_slice(b"gh");
+    if producer.commit(2) != 2 || consumer.read() != b"efgh" || consumer.release(4) != 4 {
+        return Outcome::Fail("read grant");
+    }
+
+    // Fill past the physical end to force a wrap
+    let filler = [0x55u8; TEST_RING_LEN - 4];
+    if producer.push(&filler).is_err() || producer.push(b"wrap").is_err() {
+        return Outcome::Fail("wrap push");
+    }
+    if producer.available_space() != 0 || producer.push(b"x").is_ok() {
+        return Outcome::Fail("overflow not detected");
+    }
+
+    let mut skip = [0u8; TEST_RING_LEN];
+    consumer.pop(&mut skip[..TEST_RING_LEN - 4]);
+    if consumer.pop(&mut out[..4]) != 4 || &out[..4] != b"wrap" || !consumer.is_empty() {
+        return Outcome::Fail("wrap pop");
+    }
+
+    Outcome::Pass
+}
+
+/// Morse encoder output for a known code
+pub fn test_morse() -> Outcome {
+    let mut buffer = [0u8; 32];
+    match number_to_morse(123, &mut buffer) {
+        Ok(length) if &buffer[..length] == b".---- ..--- ...--" => Outcome::Pass,
+        Ok(_) => Outcome::Fail("wrong encoding"),
+        Err(why) => Outcome::Fail(why),
+    }
+}
+
+/// Error record path, leaving the report for the LED display to consume
+pub fn test_error_queue() -> Outcome {
+    let reports = || find_record(HIL_TEST_ERROR_CODE).map_or(0, |record| record.count);
+    let before = reports();
+
+    if add_error_code(HIL_TEST_ERROR_CODE).is_err() {
+        return Outcome::Skip("store full");
+    }
+    if !has_errors() || reports() != before + 1 {
+        return Outcome::Fail("code not recorded");
+    }
+
+    Outcome::Pass
+}
+
+/// Verifies that the loopback pattern arrived in the RX ring buffer
+pub fn check_loopback(rx: &mut RxConsumer) -> Outcome {
+    if rx.is_empty() {
+        return Outcome::Skip("no data (jumper PG14-PG9?)");
+    }
+
+    let mut received = [0u8; RX_RING_BUFFER_LEN];
+    let length = rx.pop(&mut received);
+    if &received[..length] == LOOPBACK_PATTERN {
+        Outcome::Pass
+    } else {
+        Outcome::Fail("pattern mismatch")
+    }
+}
+
+/// Verifies that the host echoed the USB probe into the TX ring buffer
+pub fn check_usb_echo(tx: &mut TxConsumer) -> Outcome {
+    if tx.is_empty() {
+        return Outcome::Skip("no echo from host");
+    }
+
+    let mut received = [0u8; TX_RING_BUFFER_LEN];
+    let length = tx.pop(&mut received);
+    if &received[..length] == USB_ECHO_PROBE {
+        Outcome::Pass
+    } else {
+        Outcome::Fail("echo mismatch")
+    }
+}
@@ -0,0 +1,52 @@
+//! # STM32F469 USB-UART Bridge Library
+//!
+//! Hardware drivers, data structures and task logic of the bridge firmware,
+//! usable from any RTIC application on the STM32F469:
+//! - `bridge::BridgeBuilder` brings up the hardware with chosen options
+//! - `peripherals` wraps the HAL drivers (USART6/USART3 + DMA, USB CDC, flash, RTC, ...)
+//! - `protocol` provides COBS/CRC32 packet framing for the UART link, protocol nonces and the SLCAN codec
+//! - `task_handlers` holds the logic invoked from RTIC tasks
+//! - `data_structures`, `errors` and `utils` are shared infrastructure
+//!
+//! The binary in `main.rs` is the reference RTIC application built on top.
+//!
+//! ## Host Builds
+//! Built for any target other than bare metal (`target_os = "none"`), only
+//! the hardware-independent parts remain: `config`, `errors`, the
+//! `data_structures`, the COBS/CRC32 framer and the Modbus and NMEA codecs
+//! in `protocol`, the Morse encoder, `DateTime`, statistics and retry
+//! policies in `utils`, and the handlers without peripheral access (baud
+//! negotiation, auto-baud matching, error notifications, task registry,
+//! GNSS fix, Modbus requests). Of `peripherals`, only the `SerialLink` and
+//! `UsbLink` traits remain, with mock implementations (`peripherals::mock`)
+//! that drive the link-level steps of the data path (`data_path`).
+//! The `tests` directory runs them with the standard test harness:
+//!
+//! ```text
+//! cargo test-host --features modbus,nmea
+//! ```
+//!
+//! ## Integration Requirements
+//! - `Mono` (SysTick, 1 kHz) is defined here; start it with `Mono::start`
+//! - Hardware tasks must bind the interrupts unmasked by `init_peripherals`
+//! - The application provides the panic handler and, with `debug`, the defmt logger
+
+#![no_std]
+
+#[cfg(target_os = "none")]
+pub mod bridge; // Builder facade for embedding applications
+pub mod config; // System constants and clock configuration
+pub mod data_structures; // Circular buffers and data containers
+pub mod errors; // Error type definitions and conversions
+pub mod macros; // Procedural macros for code generation
+pub mod peripherals; // Hardware abstraction layer implementation
+pub mod protocol; // Packet framing for the UART link
+pub mod task_handlers; // RTIC task implementations
+pub mod utils; // Helper functions and utilities
+
+#[cfg(target_os = "none")]
+use rtic_monotonics::systick::prelude::*;
+
+// System timer configuration: 1ms timebase using SysTick
+#[cfg(target_os = "none")]
+systick_monotonic!(Mono, 1000);
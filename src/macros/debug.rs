@@ -4,3 +4,62 @@ macro_rules! debug_print {
         ::defmt::debug!($($arg)*);
     };
 }
+
+/// Logs from hardware interrupt context without touching RTT
+///
+/// Requires an `IsrContext` token so every call site is tied to a handler that
+/// declared itself as interrupt context. By default only a deferred record is
+/// queued (printed later from `idle`); the `isr-direct-log` feature restores
+/// immediate `defmt` output. With the `usb-log` feature the record is always
+/// queued, for the USB log.
+///
+/// # Example
+/// ```
+/// let isr = IsrContext::enter();
+/// isr_log!(isr, warn, "DMA RX error", e.code());
+/// isr_log!(isr, trace, "DMA RX active");
+/// ```
+#[macro_export]
+macro_rules! isr_log {
+    (@level trace) => { $crate::data_structures::log_queue::LogLevel::Trace };
+    (@level debug) => { $crate::data_structures::log_queue::LogLevel::Debug };
+    (@level info) => { $crate::data_structures::log_queue::LogLevel::Info };
+    (@level warn) => { $crate::data_structures::log_queue::LogLevel::Warn };
+    (@level error) => { $crate::data_structures::log_queue::LogLevel::Error };
+    ($ctx:expr, $level:ident, $msg:literal $(, $value:expr)?) => {{
+        let _ctx: &$crate::data_structures::log_queue::IsrContext = &$ctx;
+        let _value: Option<u32> = None $(.or(Some(($value) as u32)))?;
+
+        #[cfg(any(
+            all(feature = "debug", not(feature = "isr-direct-log")),
+            feature = "usb-log"
+        ))]
+        $crate::data_structures::log_queue::push(_ctx, $crate::isr_log!(@level $level), $msg, _value);
+
+        #[cfg(all(feature = "debug", feature = "isr-direct-log"))]
+        match _value {
+            Some(v) => ::defmt::$level!("{=str}: {=u32}", $msg, v),
+            None => ::defmt::$level!("{=str}", $msg),
+        }
+    }};
+}
+
+/// Logs a formatted line to the USB log
+///
+/// `usb_log!(info, "Baud rate {}", baud)`; compiles to nothing without the
+/// `usb-log` feature. Not for interrupt handlers, which log with `isr_log!`.
+#[macro_export]
+macro_rules! usb_log {
+    (@level trace) => { $crate::data_structures::log_queue::LogLevel::Trace };
+    (@level debug) => { $crate::data_structures::log_queue::LogLevel::Debug };
+    (@level info) => { $crate::data_structures::log_queue::LogLevel::Info };
+    (@level warn) => { $crate::data_structures::log_queue::LogLevel::Warn };
+    (@level error) => { $crate::data_structures::log_queue::LogLevel::Error };
+    ($level:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "usb-log")]
+        $crate::task_handlers::usb_log::log(
+            $crate::usb_log!(@level $level),
+            format_args!($($arg)+),
+        );
+    }};
+}
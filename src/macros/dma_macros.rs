@@ -20,4 +20,29 @@ macro_rules! dma_cfg {
             .memory_increment(true)
             .priority(stm32f4xx_hal::dma::config::Priority::High)
     };
-}
\ No newline at end of file
+}
+/// Macro for creating the circular DMA RX configuration
+///
+/// Generates a preconfigured `DmaConfig` with:
+/// - Half Transfer and Transfer Complete Interrupts enabled
+/// - Direct mode (FIFO disabled), so NDTR matches the bytes written to memory
+/// - High priority
+/// - Memory increment mode
+///
+/// Used together with a second buffer, which puts the stream in double-buffer
+/// (circular) mode.
+#[macro_export]
+macro_rules! dma_rx_cfg {
+    () => {
+        stm32f4xx_hal::dma::config::DmaConfig::default()
+            .transfer_complete_interrupt(true)
+            .transfer_error_interrupt(false)
+            .half_transfer_interrupt(true)
+            .fifo_enable(false)
+            .peripheral_burst(stm32f4xx_hal::dma::config::BurstMode::NoBurst)
+            .peripheral_increment(false)
+            .memory_burst(stm32f4xx_hal::dma::config::BurstMode::NoBurst)
+            .memory_increment(true)
+            .priority(stm32f4xx_hal::dma::config::Priority::High)
+    };
+}
@@ -4,28 +4,52 @@
 /// - `Display` implementation using provided error messages
 /// - `Error` trait implementation
 /// - `description()` method returning static error messages
-/// - `code()` method returning variant-specific numeric codes
+/// - `code()` method returning the variant's registered numeric code
+/// - `from_code()` method mapping a numeric code back to its variant
+/// - `ALL` listing the variants in declaration order
 /// - Optional defmt::Format derivation for test/debug configurations
 ///
+/// Codes come from the registry in `errors::domain`: a domain enum names its
+/// base, and every variant a fixed offset from 1 to 99 that is never reused.
+/// A variant added later takes a new offset, so no existing code moves. The
+/// macro rejects a base that is not registered and an offset outside the
+/// domain; the compiler rejects two variants with the same code.
+///
 /// # Arguments
 /// * `name` - The name of the enum to create
-/// * `variant => message` - Variants and their associated error messages
+/// * `base` - Registered domain base, see `errors::domain`
+/// * `variant = offset => message` - Variants, their offsets and error messages
 ///
 /// # Example
 /// ```
-/// define_peripheral_error_enum!(UartError,
-///     Timeout => "Transaction timed out",
-///     Framing => "Framing error detected",
-///     Parity => "Invalid parity configuration"
+/// define_peripheral_error_enum!(UartError, base = domain::USART,
+///     Timeout = 1 => "Transaction timed out",
+///     Framing = 2 => "Framing error detected",
+///     Parity = 3 => "Invalid parity configuration"
 /// );
 /// ```
 #[macro_export]
 macro_rules! define_peripheral_error_enum {
-    ($name:ident, $( $variant:ident => $message:expr ),* $(,)?) => {
-        #[derive(Debug, PartialEq)]
+    ($name:ident, base = $base:expr, $( $variant:ident = $offset:expr => $message:expr ),* $(,)?) => {
+        $crate::define_peripheral_error_enum!($name, $( $variant = $base + $offset => $message ),*);
+
+        // Codes stay inside a registered domain
+        const _: () = {
+            assert!($crate::errors::errors::domain::is_registered($base), "unregistered error domain");
+            $( assert!($offset >= 1 && $offset < $crate::errors::errors::domain::SIZE, "error code offset outside 1..=99"); )*
+        };
+
+        impl $name {
+            /// Registered base of the error domain
+            pub const BASE: u16 = $base;
+        }
+    };
+    ($name:ident, $( $variant:ident = $code:expr => $message:expr ),* $(,)?) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
         #[cfg_attr(any(test, feature = "debug"), derive(defmt::Format))]
+        #[repr(u16)]
         pub enum $name {
-            $( $variant, )*
+            $( $variant = $code, )*
         }
 
         impl core::fmt::Display for $name {
@@ -37,6 +61,9 @@ macro_rules! define_peripheral_error_enum {
         }
 
         impl $name {
+            /// All variants, in declaration order
+            pub const ALL: &'static [$name] = &[$( $name::$variant, )*];
+
             /// Returns the static error message associated with this variant
             pub fn description(&self) -> &'static str {
                 match self {
@@ -46,38 +73,169 @@ macro_rules! define_peripheral_error_enum {
 
             /// Returns the numeric error code corresponding to this variant
             ///
-            /// Codes are assigned based on the variant's ordinal position
+            /// Codes are fixed in the variant list, see `errors::domain`
             pub fn code(&self) -> u16 {
+                *self as u16
+            }
+
+            /// Returns the variant with the numeric error code `code`, if any
+            pub fn from_code(code: u16) -> Option<Self> {
+                $(
+                    if code == $name::$variant as u16 {
+                        return Some($name::$variant);
+                    }
+                )*
+                None
+            }
+        }
+
+        impl core::error::Error for $name {}
+    };
+}
+
+/// Macro for defining the device error enum, which wraps the domain errors
+///
+/// Generates an error enum with a payload variant per wrapped domain error,
+/// which keeps the source error and with it the precise cause, and unit
+/// variants for conditions of the device itself, along with:
+/// - `Display` implementation, the source's message for wrapped errors
+/// - `Error` trait implementation, the wrapped error as `source()`
+/// - `description()` method returning static error messages
+/// - `code()` method: the source's code for wrapped errors, the registered
+///   code of the unit variants otherwise
+/// - `from_code()` method mapping any code of the device block or a wrapped
+///   domain back to its error
+/// - Optional defmt::Format derivation for test/debug configurations
+///
+/// The unit variants are numbered like `define_peripheral_error_enum!`, with
+/// the same checks. The conversions into the payload variants come from
+/// `impl_error_conversion!`.
+///
+/// # Arguments
+/// * `name` - The name of the enum to create
+/// * `base` - Registered domain base of the unit variants
+/// * `Variant(Source)` - Wrapped domain errors, defined with `define_peripheral_error_enum!`
+/// * `variant = offset => message` - Unit variants, their offsets and error messages
+///
+/// # Example
+/// ```
+/// define_device_error_enum!(AppError, base = domain::DEVICE,
+///     wraps { Spi(SpiError), Usb(UsbError) },
+///     Overheat = 1 => "Temperature limit exceeded"
+/// );
+/// ```
+#[macro_export]
+macro_rules! define_device_error_enum {
+    (
+        $name:ident,
+        base = $base:expr,
+        wraps { $( $wrap:ident($source:ident) ),* $(,)? },
+        $( $variant:ident = $offset:expr => $message:expr ),* $(,)?
+    ) => {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        #[cfg_attr(any(test, feature = "debug"), derive(defmt::Format))]
+        pub enum $name {
+            $( $wrap($source), )*
+            $( $variant, )*
+        }
+
+        // Unit variant codes stay inside a registered domain and apart
+        const _: () = {
+            use $crate::errors::errors::domain;
+            assert!(domain::is_registered($base), "unregistered error domain");
+            let offsets: &[u16] = &[$( $offset, )*];
+            let mut i = 0;
+            while i < offsets.len() {
+                assert!(offsets[i] >= 1 && offsets[i] < domain::SIZE, "error code offset outside 1..=99");
+                let mut j = i + 1;
+                while j < offsets.len() {
+                    assert!(offsets[i] != offsets[j], "error code offset used twice");
+                    j += 1;
+                }
+                i += 1;
+            }
+        };
+
+        impl core::fmt::Display for $name {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
                 match self {
-                    $( $name::$variant => $name::$variant as u16, )*
+                    $( $name::$wrap(error) => core::fmt::Display::fmt(error, f), )*
+                    $( $name::$variant => write!(f, $message), )*
                 }
             }
         }
 
-        impl core::error::Error for $name {}
+        impl $name {
+            /// Registered base of the unit variants
+            pub const BASE: u16 = $base;
+
+            /// Returns the static error message associated with this error
+            pub fn description(&self) -> &'static str {
+                match self {
+                    $( $name::$wrap(error) => error.description(), )*
+                    $( $name::$variant => $message, )*
+                }
+            }
+
+            /// Returns the numeric error code of this error
+            ///
+            /// Wrapped errors keep the code of their domain, see `errors::domain`
+            pub fn code(&self) -> u16 {
+                match self {
+                    $( $name::$wrap(error) => error.code(), )*
+                    $( $name::$variant => $base + $offset, )*
+                }
+            }
+
+            /// Returns the error with the numeric error code `code`, if any
+            pub fn from_code(code: u16) -> Option<Self> {
+                $(
+                    if code == $base + $offset {
+                        return Some($name::$variant);
+                    }
+                )*
+                let base = code / $crate::errors::errors::domain::SIZE
+                    * $crate::errors::errors::domain::SIZE;
+                $(
+                    if base == $source::BASE {
+                        return $source::from_code(code).map($name::$wrap);
+                    }
+                )*
+                None
+            }
+        }
+
+        impl core::error::Error for $name {
+            fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+                match self {
+                    $( $name::$wrap(error) => Some(error), )*
+                    _ => None,
+                }
+            }
+        }
     };
 }
 
-/// Macro for implementing error conversion with a default variant
+/// Macro for implementing a wrapping error conversion
 ///
-/// Creates a From implementation that converts from one error type to another,
-/// always mapping to a specified default variant of the target error type
+/// Creates a From implementation that wraps the source error in a payload
+/// variant of the target error type, so its code and message are kept
 ///
 /// # Arguments
 /// * `from` - Source error type to convert from
 /// * `to` - Target error type to convert into
-/// * `default` - Default variant to use for conversion
+/// * `variant` - Payload variant of the target that holds the source
 ///
 /// # Example
 /// ```
-/// impl_error_conversion!(hal::spi::Error, AppError, { CommunicationFailure });
+/// impl_error_conversion!(SpiError, AppError, Spi);
 /// ```
 #[macro_export]
 macro_rules! impl_error_conversion {
-    ($from:ty, $to:ty, { $default:ident }) => {
+    ($from:ty, $to:ty, $variant:ident) => {
         impl From<$from> for $to {
-            fn from(_error: $from) -> Self {
-                Self::$default
+            fn from(error: $from) -> Self {
+                Self::$variant(error)
             }
         }
     };
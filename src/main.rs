@@ -5,46 +5,124 @@
 //!
 //! ## Key Features
 //! - USB 2.0 Full Speed communication with OTG controller
-//! - UART communication via USART6 with DMA transfers
+//! - UART communication via USART6 or USART3 with DMA transfers, selectable
+//!   at runtime
 //! - Dual LED status indication system (blue operational status, red error reporting)
-//! - Thread-safe ring buffers for data management
+//! - Lock-free SPSC ring buffers for data management
 //! - Comprehensive error handling with persistent error codes
-//! - Low-power idle mode with interrupt wakeup
+//! - Low-power idle mode with interrupt wakeup, its depth and core clock
+//!   scaling chosen by the power profile
+//! - Stop mode while the host keeps the USB bus suspended
+//! - Core clock from the HSE while VBUS is absent, PLL locked again on attach
+//! - Supply dip warnings from the programmable voltage detector, recorded
+//!   before a brown-out reset
 //!
 //! ## Hardware Requirements
 //! - STM32F469NI-Discovery board
-//! - Blue LED connected on PK3 (LD4 on discovery board)
-//! - Red LED connected on PD5 (LD5 on discovery board)
+//! - Green LED on PG6 (LD1): USB activity
+//! - Orange LED on PD4 (LD2): UART activity
+//! - Red LED on PD5 (LD3): error codes
+//! - Blue LED on PK3 (LD4): system status
+//! - Blue user button B1 on PA0: configurable short/long press actions
 //! - USART6 peripheral using:
 //!   - TX: PG14 (connected to external UART converter)
 //!   - RX: PG9 (connected to external UART converter)
+//! - USART3 peripheral (second bridge UART) using:
+//!   - TX: PB10
+//!   - RX: PB11
 //! - USB OTG FS port configured in device mode
+//! - PA9: USB VBUS sensing
+//! - Clock outputs for scope checks: MCO1 on PA8, MCO2 on PC9 (not with
+//!   `sd-log`)
+//! - PA6 (TIM3_CH1) jumpered to PG9 for the programmable RX timeout
+//! - Optional active-low modem status inputs: DSR PG13, DCD PG12, RI PG11,
+//!   CTS PA3
+//! - Modem control outputs set by the host: DTR PA1, RTS PA2
+//! - Optional 800x480 DSI display (`display` feature), LCD reset on PH7
+//! - Optional I2C1 master (`i2c` feature): SCL PB8, SDA PB9
+//! - Optional USB-to-I2C bridge mode (`i2c-bridge` feature): framed I2C
+//!   transactions from the data port run on I2C1
+//! - Optional FT6206 touch (`touch` feature): on I2C1, INT on PJ5
+//! - Optional CAN1 (`can` feature, instead of I2C1): RX PB8, TX PB9 to an
+//!   external transceiver, SLCAN adapter mode on the data port
+//! - Optional SPI2 master (`spi` feature): SCK PD3, MISO PB14, MOSI PB15,
+//!   chip selects PH6 and PG10
+//! - Optional ADC1 (`adc` feature): Arduino A0-A5 on PB1, PC2-PC5, PA4,
+//!   plus VREFINT and the temperature sensor, streamed on the console port
+//! - Optional DAC (`dac` feature): channel 1 on PA4, channel 2 on PA5, set
+//!   from the console or with `AT+DAC=` on the data port
+//! - Optional microSD card log (`sd-log` feature): SDIO on PC8-PC12/PD2,
+//!   card detect on PG2
 //!
 //! ## Architecture Overview
+//! This binary is a thin RTIC application over the `stm32f469_base_rtic` library,
+//! which other projects can embed through `bridge::BridgeBuilder`.
 //! The application follows these design principles:
 //! - RTIC framework for concurrency management
 //! - Separation of hardware abstraction layers (HAL) and application logic
 //! - Non-blocking DMA-driven data transfers
 //! - Atomic resource sharing between tasks
-//! - Error queue system with visual feedback
+//! - Error record store with severities and visual feedback
 //!
 //! ## Task Priorities
-//! | Task                  | Priority | Description                              |
-//! |-----------------------|----------|------------------------------------------|
-//! | USB Handling          | 4        | Highest priority for USB communication   |
-//! | DMA Stream Handlers   | 3        | Data transfer completion handling        |
-//! | USART6 Handler        | 3        | Serial communication management          |
-//! | Error Display         | 5        | Critical error visualization             |
-//! | LED Status            | 1        | Lowest priority for status indication    |
+//! | Task                  | Priority | Budget  | Description                              |
+//! |-----------------------|----------|---------|------------------------------------------|
+//! | USB Handling          | 4        | 100 us  | Highest priority for USB communication   |
+//! | DMA Stream Handlers   | 3        | 50 us   | Data transfer completion handling        |
+//! | USART6 Handler        | 3        | 50 us   | Serial communication management          |
+//! | USART3 Handler        | 3        | 50 us   | Second UART, same as USART6              |
+//! | RX Timeout (TIM3)     | 3        | 50 us   | Partial DMA buffer flush, auto-baud edges|
+//! | RX Idle Flush         | 1        | -       | Polled partial DMA buffer flush          |
+//! | Ring Buffer Transfers | 3        | 100 us  | RX buffer to USB, TX buffer to UART DMA  |
+//! | USB Remote Wakeup     | 3        | -       | Resume signalling for UART data          |
+//! | Echo Data             | 3        | -       | Loopback of the echo bridge modes        |
+//! | Error Display         | 5        | 20 us   | Critical error visualization (per step)  |
+//! | Latency Probe (EXTI3) | 6        | -       | Interrupt latency measurement target     |
+//! | Throughput Benchmark  | 1        | -       | PRBS loopback run, samples and report    |
+//! | Baud Rate Detection   | 1        | -       | USART6 rate from RX edges, then applied  |
+//! | Modbus Transaction    | 1        | -       | RTU request and response (`modbus`)      |
+//! | User Button (EXTI0)   | 2        | -       | Press edge, starts the button task       |
+//! | Stop Mode Wakeups     | 2        | -       | Clear the USB/UART/RTC wakeup lines      |
+//! | Supply Monitor (PVD)  | 5        | -       | Records supply dips below the threshold  |
+//! | LED Status            | 1        | -       | Lowest priority for status indication    |
+//! | Debug Console         | 1        | -       | Commands on the second CDC port          |
+//! | Shutdown              | 1        | -       | Drain, stop DMA, detach USB, then reset  |
+//! | AT Commands           | 1        | -       | In-band control on the data port         |
+//! | Line Coding           | 1        | -       | Host frame format applied to the UART    |
+//! | UART Break            | 1        | -       | Host SEND_BREAK held on the routed UART  |
+//! | Watchdog              | 1        | -       | Feeds the IWDG once all tasks checked in |
+//! | Window Watchdog       | 2        | -       | Refreshes the WWDG every 32 ms           |
+//! | WWDG Early Wakeup     | 7        | -       | Records the timeout before the reset     |
+//! | Button Actions        | 1        | -       | Debounce, press classification, actions  |
+//! | Status Screen         | 1        | -       | Display redraw (`display` feature)       |
+//! | Touch Input           | 1        | -       | Touch button actions (`touch` feature)   |
+//! | SD Card Log           | 1        | -       | Log file writes (`sd-log` feature)       |
+//! | I2C Scan              | 1        | -       | Console bus scan (`i2c` feature)         |
+//! | I2C Bridge Dispatch   | 1        | -       | USB-to-I2C requests (`i2c-bridge`)       |
+//! | CAN1 RX (CAN1_RX0)    | 3        | -       | Received frames to the host (`can`)      |
+//! | SLCAN Dispatch        | 2        | -       | SLCAN commands on CAN1 (`can` feature)   |
+//! | SPI Self-Test         | 1        | -       | Console loopback test (`spi` feature)    |
+//! | ADC Sampler           | 1        | -       | Periodic ADC1 scans (`adc` feature)      |
+//! | DAC Output            | 1        | -       | Switches a DAC channel (`dac` feature)   |
+//!
+//! Budgets are enforced at runtime by `utils::budget` (exact values in
+//! `budget::Task::budget_us`); a chronic overrun raises `BudgetOverrun`.
 //!
 //! ## Safety Considerations
 //! - All shared resources use RTIC's mutex protection
 //! - Critical sections keep interrupts disabled <100 cycles
+//! - Hardware ISRs log through `isr_log!` only; records are printed from `idle`
+//!   so RTT writes never extend interrupt latency (opt out with `isr-direct-log`)
 //! - DMA transfers use hardware-verified buffer boundaries
 //! - Error states trigger failsafe LED patterns
+//! - An error storm right after boot enters safe mode (UART off, USB up)
+//! - A hung USB, USART or LED task stops the watchdog feed and resets the board
+//! - The window watchdog resets the board on a refresh too early or too late,
+//!   with the cause recorded in the persistent error log first
+//! - Release-build panics flash both LEDs rapidly, then reset (see `crash`)
 
-#![no_main]
-#![no_std]
+#![cfg_attr(target_os = "none", no_main)]
+#![cfg_attr(target_os = "none", no_std)]
 
 #[cfg(feature = "debug")]
 use defmt_rtt as _; // Global logger for RTT-based debugging
@@ -58,54 +136,195 @@ use debug::{init as debug_init, log_error};
 #[cfg(feature = "debug")]
 use panic_probe as _; // Panic handler with defmt integration
 
-#[cfg(not(feature = "debug"))]
-use panic_halt as _; // Production panic handler (system freeze)
+#[cfg(all(target_os = "none", not(feature = "debug")))]
+mod crash; // Production panic handler (LED crash pattern, then reset)
+#[cfg(all(target_os = "none", feature = "hil-test"))]
+mod hil_test; // On-target integration test suite
 
-mod config; // System constants and clock configuration
-mod data_structures; // Circular buffers and data containers
-mod errors; // Error type definitions and conversions
-mod macros; // Procedural macros for code generation
-mod peripherals; // Hardware abstraction layer implementation
-mod task_handlers; // RTIC task implementations
-mod utils; // Helper functions and utilities
+#[cfg(feature = "debug")]
+use stm32f469_base_rtic::debug_print;
+#[cfg(target_os = "none")]
+use stm32f469_base_rtic::{
+    bridge, config, data_structures, errors, isr_log, peripherals, protocol, task_handlers,
+    usb_log, utils, Mono,
+};
 
+#[cfg(target_os = "none")]
+use crate::data_structures::log_queue::IsrContext;
+#[cfg(target_os = "none")]
 use crate::errors::errors::{DeviceError, UsbError};
-use crate::task_handlers::error_handlers::add_error_code;
+#[cfg(target_os = "none")]
+use crate::task_handlers::error_handlers::add_error;
+#[cfg(target_os = "none")]
 use rtic::app;
+#[cfg(target_os = "none")]
 use rtic_monotonics::systick::prelude::*;
 
-// System timer configuration: 1ms timebase using SysTick
-systick_monotonic!(Mono, 1000);
-
-#[app(device = stm32f4xx_hal::pac, peripherals = true, dispatchers = [EXTI0, EXTI1, EXTI2])]
+#[cfg(target_os = "none")]
+#[app(device = stm32f4xx_hal::pac, peripherals = true, dispatchers = [EXTI1, EXTI2, EXTI4, EXTI15_10])]
 mod app {
     use super::*;
-    use crate::config::{SYSCLK};
-    use crate::peripherals::stm32f469_init::init_peripherals;
+    use crate::bridge::{BridgeBuilder, UartPort};
+    use crate::config::{
+        AT_REPLY_LEN, AUTOBAUD_TIMEOUT_MS, BENCH_DEFAULT_SECONDS, BREAK_DRAIN_MS,
+        BUTTON_DEBOUNCE_MS, BUTTON_LONG_PRESS_MS, BUTTON_POLL_MS, CDC_MAX_PACKET_SIZE, CONSOLE_POLL_MS,
+        DEFERRED_LOG_LEN, IWDG_CHECK_MS, REMOTE_WAKEUP_SIGNAL_MS, RX_IDLE_POLL_MS,
+        RX_RING_BUFFER_LEN, SHUTDOWN_DETACH_MS, SHUTDOWN_DRAIN_MS, SHUTDOWN_REPLY_MS,
+        STRAP_WINDOW_MS, SYSCLK, TX_RING_BUFFER_LEN, WWDG_ENABLED, WWDG_REFRESH_MS,
+    };
+    #[cfg(feature = "display")]
+    use crate::config::DISPLAY_REFRESH_MS;
+    #[cfg(feature = "sd-log")]
+    use crate::config::{SD_LOG_FLUSH_MS, SD_LOG_TRAFFIC_MS};
+    #[cfg(feature = "touch")]
+    use crate::config::{TOUCH_HOLD_MS, TOUCH_POLL_MS};
+    #[cfg(feature = "modbus")]
+    use crate::config::MODBUS_RESPONSE_TIMEOUT_MS;
+    #[cfg(not(feature = "led-pwm"))]
+    use crate::config::SAFE_MODE_BLINK_MS;
+    use crate::data_structures::error_queue::ErrorStore;
+    #[cfg(feature = "spi")]
+    use crate::errors::errors::SpiError;
+    #[cfg(feature = "modbus")]
+    use crate::errors::errors::ModbusError;
+    use crate::data_structures::log_queue::IsrContext;
+    use crate::isr_log;
+    use crate::peripherals::button;
+    use crate::peripherals::cdc_acm::BreakRequest;
+    #[cfg(feature = "dac")]
+    use crate::peripherals::dac::{DacChannel, Waveform};
+    use crate::peripherals::iwdg::{self, CheckIn};
+    use crate::peripherals::low_power::{self, WakeSource};
+    use crate::peripherals::otg_fs::PowerEvent;
+    use crate::peripherals::pvd::{self, SupplyEvent};
+    use crate::peripherals::rx_timeout::RxTimeoutEvent;
+    #[cfg(feature = "touch")]
+    use crate::peripherals::touch;
     use crate::peripherals::traits::GpioPin;
-    use crate::task_handlers::blue_led::{toggle_led, LED_CHECK_INTERVAL};
-    use crate::task_handlers::dma2::{handle_dma_rx, handle_dma_tx, handle_usart_error};
-    use crate::task_handlers::error_handlers::{has_errors};
-    use crate::task_handlers::otg_fs::{handle_usb, process_rx_buffer};
-    use crate::task_handlers::red_led_handler::update_red_led;
+    use crate::peripherals::uart::{BridgeUart, UartConfig};
+    use crate::peripherals::wwdg;
+    #[cfg(feature = "modbus")]
+    use crate::protocol::modbus::{
+        decode_response, is_complete, silence_bit_times, Adu, Request as ModbusRequest,
+    };
+    use crate::protocol::{nonce, transform, xon_xoff};
+    use crate::task_handlers::activity_leds::ActivityLeds;
+    #[cfg(feature = "adc")]
+    use crate::task_handlers::adc_stream;
+    use crate::task_handlers::auto_baud;
+    use crate::task_handlers::backpressure;
+    use crate::task_handlers::baud_negotiation::{self, FrameAssembler, Negotiator, Op, Role};
+    use crate::task_handlers::benchmark::{self, RateMeter};
+    use crate::task_handlers::bridge_mode::{self, Sink};
+    #[cfg(not(feature = "led-pwm"))]
+    use crate::task_handlers::blue_led::{toggle_led, LED_CHECK_INTERVAL, LED_SUSPEND_POLL};
+    #[cfg(feature = "led-pwm")]
+    use crate::task_handlers::blue_led::{BluePattern, Breather};
+    use crate::task_handlers::button::{action_for, ButtonAction};
+    use crate::task_handlers::command::{self, AtCommand, Outcome};
+    use crate::task_handlers::console::{self, Action, LineEditor};
+    use crate::task_handlers::data_path::handle_usart_error;
+    use crate::task_handlers::dma2::{handle_dma_tx, handle_uart_rx, transmit_direct, RxIdleWatch};
+    use crate::task_handlers::error_handlers::{clear_error_queue, has_errors};
+    #[cfg(feature = "i2c-bridge")]
+    use crate::task_handlers::i2c_bridge;
+    #[cfg(feature = "touch")]
+    use crate::task_handlers::input::{self, InputEvent, TouchAction};
+    use crate::task_handlers::line_break;
+    #[cfg(feature = "modbus")]
+    use crate::task_handlers::modbus;
+    use crate::task_handlers::otg_fs::{handle_usb, process_rx_buffer, USB_RECONNECT};
+    use crate::task_handlers::periodic::{
+        register_jobs, run_activity_leds, run_budget_check, run_clock_health, run_clock_switch,
+        run_error_notify, run_modem_status, run_safe_mode_guard, run_stats_report,
+        run_stats_snapshot, send_error_notify, PeriodicJob, PeriodicScheduler,
+    };
+    #[cfg(feature = "adc")]
+    use crate::task_handlers::periodic::run_health_monitor;
+    #[cfg(feature = "usb-msc")]
+    use crate::task_handlers::periodic::run_log_volume;
+    use crate::task_handlers::power;
+    #[cfg(feature = "sd-log")]
+    use crate::task_handlers::sd_log::{self, SdLogWriter};
+    use crate::task_handlers::signal_handler::{update_signal, Signal, MORSE_UPDATE_MS};
+    #[cfg(feature = "can")]
+    use crate::task_handlers::slcan;
+    use crate::task_handlers::safe_mode::{self, BootGuard};
+    use crate::task_handlers::settings::{self, Settings};
+    use crate::task_handlers::shutdown::{self, Restart};
+    use crate::task_handlers::snapshot::SnapshotLog;
+    #[cfg(feature = "display")]
+    use crate::task_handlers::status_screen::{Status, StatusScreen};
+    #[cfg(feature = "display")]
+    use crate::task_handlers::task_registry::{self, Subsystem};
+    use crate::task_handlers::target_probe::{self, Prober};
+    use crate::task_handlers::uart_route;
+    use crate::task_handlers::uart_strap::{self, StrapDetector};
+    use crate::task_handlers::usb_suspend;
+    use crate::utils::budget::{self, BudgetGuard, Task};
+    use crate::utils::profiler::{self, ProfileGuard, Slot};
+    use crate::utils::clock_health::ClockHealth;
+    use crate::utils::latency::{self, LatencyStats};
+    use crate::utils::meminfo;
+    use crate::utils::retry::{RetryState, Verdict};
+    use crate::utils::statistics;
+    #[cfg(feature = "debug")]
+    use crate::utils::sysinfo::write_sysinfo;
+    use core::fmt::Write;
 
     /// Shared system resources protected by RTIC mutexes
     #[shared]
     struct Shared {
-        blue_led: peripherals::blue_led::BlueLed, // Status LED controller
+        blue_led: peripherals::led::BlueLed, // Status LED controller
         red_led: peripherals::red_led::RedLed,    // Error LED controller
-        usart_6: peripherals::usart_6::Usart6Controller, // UART interface with DMA
+        usart_6: peripherals::uart::Usart6Controller, // UART interface with DMA
+        usart_3: peripherals::uart::Usart3Controller, // Second UART interface with DMA
         otg_fs: peripherals::otg_fs::OtgFsController<'static>, // USB device controller
+        flash: peripherals::flash::FlashController, // Internal flash data sectors
+        qspi: Option<peripherals::qspi::QspiFlash>, // 16 MB QSPI NOR flash, if present
+        button: peripherals::button::UserButton,  // User button B1 (EXTI0)
+        #[cfg(feature = "i2c")]
+        i2c_1: peripherals::i2c::I2c1Bus, // I2C1 master bus
+        #[cfg(feature = "can")]
+        can_1: peripherals::can::Can1, // CAN1 controller
+        #[cfg(feature = "spi")]
+        spi_2: peripherals::spi::Spi2Master, // SPI2 master bus
         is_red_led_active: bool,                  // Error display state flag
         is_blue_led_blinking: bool,               // Normal operation indicator flag
-        ring_buffer_rx: data_structures::ring_buffer::RingBuffer, // Incoming data buffer
-        ring_buffer_tx: data_structures::ring_buffer::RingBuffer, // Outgoing data buffer
+        rx_producer: data_structures::spsc::RxProducer, // Incoming data buffer, filling half
+        rx_consumer: data_structures::spsc::RxConsumer, // Incoming data buffer, draining half
+        tx_producer: data_structures::spsc::TxProducer, // Outgoing data buffer, filling half
+        tx_consumer: data_structures::spsc::TxConsumer, // Outgoing data buffer, draining half
+        tx_staging: data_structures::tx_pingpong::TxPingPong, // Zero-copy DMA TX buffers
+        snapshot_log: SnapshotLog, // Flash snapshot write position
     }
 
     /// Local task-specific resources (unshared state)
     #[local]
     struct Local {
-        retry_count: u8, // Counter for communication retries
+        dma_retry: RetryState,     // Consecutive DMA recovery attempts (USART6)
+        dma_retry_3: RetryState,   // Consecutive DMA recovery attempts (USART3)
+        strap: StrapDetector,      // UART strap sequence matcher
+        modem_lines: peripherals::modem_lines::ModemLines, // DSR/DCD/RI/CTS inputs
+        control_outputs: peripherals::modem_lines::ControlOutputs, // DTR/RTS outputs
+        vbus: peripherals::vbus::VbusSense, // USB bus voltage on PA9
+        clock_health: ClockHealth, // HSE/LSE drift estimator
+        activity_leds: ActivityLeds, // Green/orange traffic LEDs
+        iwdg: peripherals::iwdg::Iwdg, // Independent watchdog
+        wwdg: peripherals::wwdg::Wwdg, // Window watchdog
+        buzzer: peripherals::buzzer::Buzzer, // Audible error code output
+        #[cfg(feature = "led-pwm")]
+        led_pwm: peripherals::led_pwm::LedPwm, // Blue/red LED brightness
+        #[cfg(feature = "display")]
+        display: Option<peripherals::display::Display>, // Status display, if it came up
+        #[cfg(feature = "touch")]
+        touch: Option<peripherals::touch::Touch>, // Touch controller, if it answered
+        #[cfg(feature = "sd-log")]
+        sd_card: peripherals::sdcard::SdCard, // microSD card slot
+        #[cfg(feature = "adc")]
+        adc_1: peripherals::adc::Adc1, // ADC1 scanner
+        #[cfg(feature = "dac")]
+        dac: peripherals::dac::Dac, // DAC channels and trigger timers
     }
 
     /// System initialization routine
@@ -113,36 +332,151 @@ mod app {
     /// # Safety
     /// - Must be first function executed after reset
     /// - Configures all critical hardware peripherals
-    #[init]
+    #[init(local = [
+        rx_queue: data_structures::spsc::RxQueue =
+            data_structures::spsc::RxQueue::with_watermarks(backpressure::RX_WATERMARKS),
+        tx_queue: data_structures::spsc::TxQueue =
+            data_structures::spsc::TxQueue::with_watermarks(backpressure::TX_WATERMARKS),
+    ])]
     fn init(ctx: init::Context) -> (Shared, Local) {
+        // Leave for the bootloader before touching clocks or peripherals
+        let bootloader = peripherals::dfu_runtime::enter_bootloader_if_requested();
+
         #[cfg(feature = "debug")]
         debug_init(); // Initialize debug channel if enabled
 
-        let peripherals = init_peripherals(ctx.device)
+        let mut peripherals = BridgeBuilder::new()
+            .build(ctx.device)
             .expect("Peripheral initialization failed - check hardware configuration");
+        task_handlers::error_log::init();
+        if let Err(e) = bootloader {
+            handle_error(e);
+        }
+        if let Err(e) = peripherals.sdram {
+            handle_error(e.into());
+        }
+        #[cfg(feature = "display")]
+        let display = peripherals.display.map_err(|e| handle_error(e.into())).ok();
+        #[cfg(feature = "touch")]
+        let touch = peripherals.touch.map_err(|e| handle_error(e.into())).ok();
+        nonce::init();
+
+        // Enable the DWT cycle counter for latency measurement
+        let mut core = ctx.core;
+        core.DCB.enable_trace();
+        core.DWT.enable_cycle_counter();
+        latency::init();
 
         // Configure monotonic timer for async delays
-        Mono::start(ctx.core.SYST, SYSCLK);
+        Mono::start(core.SYST, SYSCLK);
+
+        let snapshot_log = SnapshotLog::recover(&peripherals.flash);
+
+        // Receive byte-wise until the strap window closes, then start DMA RX
+        uart_strap::open_window(&mut peripherals.usart_6);
+
+        // The halves of each queue are separate resources: producers and
+        // consumers never lock each other out
+        let (rx_producer, rx_consumer) = ctx.local.rx_queue.split();
+        let (tx_producer, tx_consumer) = ctx.local.tx_queue.split();
+
+        // Statically allocated buffers owned by the application
+        meminfo::register("ring buffer rx", RX_RING_BUFFER_LEN);
+        meminfo::register("ring buffer tx", TX_RING_BUFFER_LEN);
+        meminfo::register(
+            "tx staging",
+            core::mem::size_of::<data_structures::tx_pingpong::TxPingPong>(),
+        );
+        meminfo::register("error store", core::mem::size_of::<ErrorStore>());
+        meminfo::register(
+            "deferred log",
+            DEFERRED_LOG_LEN * core::mem::size_of::<data_structures::log_queue::DeferredRecord>(),
+        );
 
         // Spawn persistent background tasks
         blue_led_blink::spawn().ok();
         task_display_error_codes::spawn().ok();
+        periodic_jobs::spawn().ok();
+        strap_window::spawn().ok();
+        debug_console::spawn().ok();
+        rx_idle_flush::spawn().ok();
+        watchdog::spawn().ok();
+        if WWDG_ENABLED {
+            window_watchdog::spawn().ok();
+        }
+
+        #[cfg(feature = "hil-test")]
+        hil_runner::spawn().ok();
+
+        #[cfg(feature = "display")]
+        display_status::spawn().ok();
+
+        #[cfg(feature = "sd-log")]
+        sd_logger::spawn().ok();
+
+        #[cfg(feature = "adc")]
+        adc_sampler::spawn().ok();
 
         #[cfg(feature = "debug")]
-        debug_print!("System initialized at {} Hz", SYSCLK);
+        {
+            debug_print!("System initialized at {} Hz", SYSCLK);
+
+            let mut report: heapless::String<1024> = heapless::String::new();
+            if write_sysinfo(&mut report).is_ok() {
+                defmt::info!("{=str}", report.as_str());
+            }
+        }
 
         (
             Shared {
                 blue_led: peripherals.blue_led,
                 red_led: peripherals.red_led,
                 usart_6: peripherals.usart_6,
+                usart_3: peripherals.usart_3,
                 otg_fs: peripherals.otg_fs,
+                flash: peripherals.flash,
+                qspi: peripherals.qspi,
+                button: peripherals.button,
+                #[cfg(feature = "i2c")]
+                i2c_1: peripherals.i2c_1,
+                #[cfg(feature = "can")]
+                can_1: peripherals.can_1,
+                #[cfg(feature = "spi")]
+                spi_2: peripherals.spi_2,
                 is_red_led_active: false,
                 is_blue_led_blinking: true,
-                ring_buffer_rx: data_structures::ring_buffer::RingBuffer::new(),
-                ring_buffer_tx: data_structures::ring_buffer::RingBuffer::new(),
+                rx_producer,
+                rx_consumer,
+                tx_producer,
+                tx_consumer,
+                tx_staging: data_structures::tx_pingpong::TxPingPong::new(),
+                snapshot_log,
+            },
+            Local {
+                dma_retry: RetryState::new(),
+                dma_retry_3: RetryState::new(),
+                strap: StrapDetector::new(),
+                modem_lines: peripherals.modem_lines,
+                control_outputs: peripherals.control_outputs,
+                vbus: peripherals.vbus,
+                clock_health: ClockHealth::new(peripherals.rtc),
+                activity_leds: ActivityLeds::new(peripherals.green_led, peripherals.orange_led),
+                iwdg: peripherals.iwdg,
+                wwdg: peripherals.wwdg,
+                buzzer: peripherals.buzzer,
+                #[cfg(feature = "led-pwm")]
+                led_pwm: peripherals.led_pwm,
+                #[cfg(feature = "display")]
+                display,
+                #[cfg(feature = "touch")]
+                touch,
+                #[cfg(feature = "sd-log")]
+                sd_card: peripherals.sd_card,
+                #[cfg(feature = "adc")]
+                adc_1: peripherals.adc_1,
+                #[cfg(feature = "dac")]
+                dac: peripherals.dac,
             },
-            Local { retry_count: 0 },
         )
     }
 
@@ -150,7 +484,8 @@ mod app {
     ///
     /// # Behavior
     /// - Runs with lowest priority when no tasks are active
-    /// - Uses WFI instruction to minimize power consumption
+    /// - Sleeps as deep as the power profile allows: WFI, Stop mode while
+    ///   the USB bus is suspended, half core clock after a quiet period
     /// - Wakeup occurs via interrupt triggers
     #[idle]
     fn idle(_ctx: idle::Context) -> ! {
@@ -158,7 +493,15 @@ mod app {
         debug_print!("Entering low-power idle mode");
 
         loop {
-            cortex_m::asm::wfi();
+            profiler::measure(Slot::Idle, || {
+                // Print records deferred by interrupt handlers before sleeping
+                #[cfg(feature = "debug")]
+                crate::debug::drain_deferred();
+                #[cfg(all(feature = "usb-log", not(feature = "debug")))]
+                crate::task_handlers::usb_log::drain_deferred();
+            });
+
+            power::idle();
         }
     }
 
@@ -168,115 +511,495 @@ mod app {
     /// - Handle DMA transfer completion events
     /// - Manage UART error conditions
     /// - Trigger data processing tasks
-    #[task(binds = USART6, shared = [usart_6, ring_buffer_rx], local = [retry_count], priority = 3)]
+    #[task(
+        binds = USART6,
+        shared = [usart_6, rx_producer],
+        local = [dma_retry, strap],
+        priority = 3
+    )]
     fn usart6(mut ctx: usart6::Context) {
-        #[cfg(feature = "debug")]
-        defmt::info!("USART6 IRQ: Checking DMA state");
+        let _budget = BudgetGuard::start(Task::Usart6);
+        let isr = IsrContext::enter();
 
-        ctx.shared.usart_6.lock(|usart| {
-            ctx.shared.ring_buffer_rx.lock(|rx| {
-                match usart.is_dma_rx_is_idle() {
-                    Ok(true) => match handle_dma_rx(usart, rx) {
-                        Err(e) => {
-                            #[cfg(feature = "debug")]
-                            defmt::warn!("DMA RX error: {:?}", e);
-                            handle_error(e.into());
-                        }
-                        Ok(()) => {
-                            #[cfg(feature = "debug")]
-                            defmt::debug!("Spawning buffer processing task");
-                            ring_buffer_rx_to_serial::spawn().ok();
-                        }
-                    },
-                    Ok(false) => {
-                        #[cfg(feature = "debug")]
-                        defmt::trace!("DMA RX active - no action");
-                    }
-                    Err(e) => {
-                        #[cfg(feature = "debug")]
-                        defmt::error!("DMA state check failed: {:?}", e);
-                        handle_error(e.into());
-                    }
-                }
+        // RS-485: the last stop bit is out, hand the bus back
+        if ctx.shared.usart_6.lock(|usart| usart.poll_rs485()) {
+            isr_log!(isr, trace, "RS-485 driver released");
+        }
 
-                if let Err(e) = handle_usart_error(usart, ctx.local.retry_count) {
-                    #[cfg(feature = "debug")]
-                    defmt::warn!("USART error: {:?}", e);
-                    handle_error(e.into());
+        // Strap window: byte-wise RX, nothing is bridged
+        if uart_strap::is_window_open() {
+            let strap = ctx.local.strap;
+            ctx.shared.usart_6.lock(|usart| {
+                if let Some(byte) = usart.read_byte() {
+                    if let Some(index) = strap.feed(byte) {
+                        isr_log!(isr, info, "UART strap preset selected", index);
+                    }
                 }
             });
-        });
+            return;
+        }
+
+        isr_log!(isr, info, "USART6 IRQ: line idle, draining DMA RX");
+        usb_suspend::note_uart_activity();
+
+        // Circular DMA keeps running; take what arrived since the last drain
+        match handle_uart_rx(
+            UartPort::Usart6,
+            &mut ctx.shared.usart_6,
+            &mut ctx.shared.rx_producer,
+        ) {
+            Err(e) => {
+                isr_log!(isr, warn, "DMA RX error", e.code());
+                handle_isr_error(&isr, e.into());
+            }
+            Ok(0) => {
+                isr_log!(isr, trace, "No new DMA RX data");
+            }
+            Ok(_) => {
+                isr_log!(isr, debug, "Spawning buffer processing task");
+                forward_uart_data();
+            }
+        }
+
+        let retry = ctx.local.dma_retry;
+        if let Err(e) = ctx
+            .shared
+            .usart_6
+            .lock(|usart| handle_usart_error(&isr, usart, retry))
+        {
+            isr_log!(isr, warn, "USART error", e.code());
+            handle_isr_error(&isr, e);
+        }
     }
 
     /// DMA2 Stream6 (TX) interrupt handler
     ///
     /// # Behavior
     /// - Clears transfer complete flag
-    /// - Does NOT restart transfers automatically (handled by tasks)
-    #[task(binds = DMA2_STREAM6, shared = [usart_6], priority = 3)]
+    /// - Respawns the TX task while staged or overflow data is pending, or a
+    ///   benchmark is sending its pattern
+    #[task(binds = DMA2_STREAM6, shared = [usart_6, tx_staging, tx_consumer], priority = 3)]
     fn dma2_stream6(mut ctx: dma2_stream6::Context) {
-        #[cfg(feature = "debug")]
-        defmt::trace!("DMA2 Stream6 (TX) complete");
+        let _budget = BudgetGuard::start(Task::DmaTx);
+        let isr = IsrContext::enter();
+        isr_log!(isr, trace, "DMA2 Stream6 (TX) complete");
+        iwdg::check_in(CheckIn::USART);
 
         ctx.shared.usart_6.lock(|usart| {
             usart.clear_dma_tx_complete_flag();
         });
+
+        let pending = benchmark::is_running()
+            || !ctx.shared.tx_staging.lock(|staging| staging.is_empty())
+            || !ctx.shared.tx_consumer.lock(|tx| tx.is_empty());
+        if pending {
+            ring_buffer_tx_to_usart_dma::spawn(0).ok();
+        }
     }
 
     /// DMA2 Stream1 (RX) interrupt handler
     ///
     /// # Responsibilities
-    /// - Handle incoming data from UART RX DMA
+    /// - Runs at every half and end of each circular RX half buffer
+    /// - Moves the new bytes into the RX ring buffer
     /// - Trigger buffer processing task
-    #[task(binds = DMA2_STREAM1, shared = [usart_6, ring_buffer_rx], priority = 3)]
+    #[task(binds = DMA2_STREAM1, shared = [usart_6, rx_producer], priority = 3)]
     fn dma2_stream1(mut ctx: dma2_stream1::Context) {
-        #[cfg(feature = "debug")]
-        defmt::debug!("DMA2 Stream1 (RX) complete");
+        let _budget = BudgetGuard::start(Task::DmaRx);
+        let isr = IsrContext::enter();
+        isr_log!(isr, debug, "DMA2 Stream1 (RX) half/complete");
+        iwdg::check_in(CheckIn::USART);
 
-        ctx.shared.usart_6.lock(|usart| {
-            ctx.shared.ring_buffer_rx.lock(|rx| {
-                if let Err(e) = handle_dma_rx(usart, rx) {
-                    handle_error(e.into());
+        match handle_uart_rx(
+            UartPort::Usart6,
+            &mut ctx.shared.usart_6,
+            &mut ctx.shared.rx_producer,
+        ) {
+            Err(e) => handle_isr_error(&isr, e.into()),
+            Ok(0) => {}
+            Ok(_) => forward_uart_data(),
+        }
+    }
+
+    /// TIM3 (USART6 RX timeout) interrupt handler
+    ///
+    /// # Behavior
+    /// - Activity: first edge of a burst, nothing to do
+    /// - Timeout: line went quiet, flush partial DMA data to the RX buffer
+    /// - During a baud rate detection the timer captures RX edges instead;
+    ///   the measured rate is posted to `auto_baud`
+    /// - During a Modbus transaction the timeout ends the response frame
+    #[task(binds = TIM3, shared = [usart_6, rx_producer], priority = 3)]
+    fn tim3(mut ctx: tim3::Context) {
+        let _budget = BudgetGuard::start(Task::RxTimeout);
+        let isr = IsrContext::enter();
+
+        // Only one of the two timer drivers is installed at a time
+        let (measured, event) = ctx
+            .shared
+            .usart_6
+            .lock(|usart| (usart.poll_baud_capture(), usart.poll_rx_timeout()));
+        if let Some(measured) = measured {
+            isr_log!(isr, info, "Baud rate measured", measured);
+            auto_baud::post(measured);
+        }
+        if event != Some(RxTimeoutEvent::Timeout) {
+            return;
+        }
+
+        isr_log!(isr, debug, "RX timeout - flushing DMA buffer");
+        match handle_uart_rx(
+            UartPort::Usart6,
+            &mut ctx.shared.usart_6,
+            &mut ctx.shared.rx_producer,
+        ) {
+            Err(e) => handle_isr_error(&isr, e.into()),
+            Ok(0) => {}
+            Ok(_) => forward_uart_data(),
+        }
+        // Posted after the flush, so the whole frame is in the RX buffer
+        #[cfg(feature = "modbus")]
+        modbus::post_silence();
+    }
+
+    /// RX idle flush task
+    ///
+    /// # Behavior
+    /// - Every `RX_IDLE_POLL_MS`, compares the unread DMA RX byte count with
+    ///   the previous poll
+    /// - Flushes to the RX buffer once no new byte arrived in between, so the
+    ///   tail of a burst never waits for the next IDLE interrupt
+    /// - Watches the UART the data port is routed to
+    #[task(shared = [usart_6, usart_3, rx_producer], priority = 1)]
+    async fn rx_idle_flush(mut ctx: rx_idle_flush::Context) {
+        let mut watch = RxIdleWatch::new();
+
+        loop {
+            Mono::delay(RX_IDLE_POLL_MS.millis()).await;
+
+            // RX DMA is not running during the strap window
+            if uart_strap::is_window_open() {
+                continue;
+            }
+
+            let port = uart_route::current();
+            let unread = match port {
+                UartPort::Usart6 => ctx.shared.usart_6.lock(|usart| usart.unread_rx_len()),
+                UartPort::Usart3 => ctx.shared.usart_3.lock(|usart| usart.unread_rx_len()),
+            }
+            .unwrap_or(0);
+            if !watch.poll(unread) {
+                continue;
+            }
+
+            let result = match port {
+                UartPort::Usart6 => {
+                    handle_uart_rx(port, &mut ctx.shared.usart_6, &mut ctx.shared.rx_producer)
                 }
-                ring_buffer_rx_to_serial::spawn().ok();
-            });
+                UartPort::Usart3 => {
+                    handle_uart_rx(port, &mut ctx.shared.usart_3, &mut ctx.shared.rx_producer)
+                }
+            };
+            match result {
+                Err(e) => handle_error(e.into()),
+                Ok(0) => {}
+                Ok(_) => forward_uart_data(),
+            }
+        }
+    }
+
+    /// USART3 interrupt handler
+    ///
+    /// # Responsibilities
+    /// - Drains DMA RX on line idle while the data port is routed to USART3
+    /// - Manage UART error conditions
+    #[task(
+        binds = USART3,
+        shared = [usart_3, rx_producer],
+        local = [dma_retry_3],
+        priority = 3
+    )]
+    fn usart3(mut ctx: usart3::Context) {
+        let _budget = BudgetGuard::start(Task::Usart3);
+        let isr = IsrContext::enter();
+        isr_log!(isr, info, "USART3 IRQ: line idle, draining DMA RX");
+        usb_suspend::note_uart_activity();
+
+        match handle_uart_rx(
+            UartPort::Usart3,
+            &mut ctx.shared.usart_3,
+            &mut ctx.shared.rx_producer,
+        ) {
+            Err(e) => {
+                isr_log!(isr, warn, "DMA RX error", e.code());
+                handle_isr_error(&isr, e.into());
+            }
+            Ok(0) => {}
+            Ok(_) => forward_uart_data(),
+        }
+
+        let retry = ctx.local.dma_retry_3;
+        if let Err(e) = ctx
+            .shared
+            .usart_3
+            .lock(|usart| handle_usart_error(&isr, usart, retry))
+        {
+            isr_log!(isr, warn, "USART error", e.code());
+            handle_isr_error(&isr, e);
+        }
+    }
+
+    /// DMA1 Stream3 (USART3 TX) interrupt handler
+    ///
+    /// # Behavior
+    /// - Clears transfer complete flag
+    /// - Respawns the TX task while staged or overflow data is pending, or a
+    ///   benchmark is sending its pattern
+    #[task(binds = DMA1_STREAM3, shared = [usart_3, tx_staging, tx_consumer], priority = 3)]
+    fn dma1_stream3(mut ctx: dma1_stream3::Context) {
+        let _budget = BudgetGuard::start(Task::DmaTx);
+        let isr = IsrContext::enter();
+        isr_log!(isr, trace, "DMA1 Stream3 (TX) complete");
+        iwdg::check_in(CheckIn::USART);
+
+        ctx.shared.usart_3.lock(|usart| {
+            usart.clear_dma_tx_complete_flag();
         });
+
+        let pending = benchmark::is_running()
+            || !ctx.shared.tx_staging.lock(|staging| staging.is_empty())
+            || !ctx.shared.tx_consumer.lock(|tx| tx.is_empty());
+        if pending {
+            ring_buffer_tx_to_usart_dma::spawn(0).ok();
+        }
+    }
+
+    /// DMA1 Stream1 (USART3 RX) interrupt handler
+    ///
+    /// # Responsibilities
+    /// - Runs at every half and end of each circular RX half buffer
+    /// - Moves the new bytes into the RX ring buffer while routed to USART3
+    #[task(binds = DMA1_STREAM1, shared = [usart_3, rx_producer], priority = 3)]
+    fn dma1_stream1(mut ctx: dma1_stream1::Context) {
+        let _budget = BudgetGuard::start(Task::DmaRx);
+        let isr = IsrContext::enter();
+        isr_log!(isr, debug, "DMA1 Stream1 (RX) half/complete");
+        iwdg::check_in(CheckIn::USART);
+
+        match handle_uart_rx(
+            UartPort::Usart3,
+            &mut ctx.shared.usart_3,
+            &mut ctx.shared.rx_producer,
+        ) {
+            Err(e) => handle_isr_error(&isr, e.into()),
+            Ok(0) => {}
+            Ok(_) => forward_uart_data(),
+        }
+    }
+
+    /// EXTI3 handler used as the latency probe target
+    ///
+    /// # Behavior
+    /// - Pended in software by `latency_measurement`
+    /// - Highest priority but the WWDG early wakeup, so the sample reflects
+    ///   raw entry latency
+    #[task(binds = EXTI3, priority = 6)]
+    fn latency_probe(_ctx: latency_probe::Context) {
+        latency::on_interrupt();
+    }
+
+    /// EXTI0 handler of the user button
+    ///
+    /// # Behavior
+    /// - Fires on the press edge only
+    /// - Starts the button task; edges while it runs (bounces, repeated
+    ///   presses) are ignored because the task cannot be spawned twice
+    #[task(binds = EXTI0, shared = [button], priority = 2)]
+    fn user_button(mut ctx: user_button::Context) {
+        let _profile = ProfileGuard::start(Slot::Button);
+        let isr = IsrContext::enter();
+        ctx.shared.button.lock(|b| b.clear_interrupt());
+
+        if button_press::spawn().is_ok() {
+            isr_log!(isr, debug, "Button: press edge");
+        }
+    }
+
+    /// USB resume wakeup from Stop mode (EXTI line 18)
+    #[task(binds = OTG_FS_WKUP, priority = 2)]
+    fn usb_wakeup(_ctx: usb_wakeup::Context) {
+        low_power::clear_wakeup(WakeSource::Usb);
+    }
+
+    /// UART start bit wakeup from Stop mode (EXTI line 9)
+    ///
+    /// Keeps the MCU awake for the rest of the burst. The touch controller
+    /// INT (EXTI line 5) shares the vector and starts the touch task.
+    #[task(binds = EXTI9_5, priority = 2)]
+    fn uart_wakeup(_ctx: uart_wakeup::Context) {
+        #[cfg(feature = "touch")]
+        if touch::take_interrupt() {
+            touch_input::spawn().ok();
+        }
+
+        if low_power::uart_wakeup_pending() {
+            low_power::clear_wakeup(WakeSource::Uart);
+            usb_suspend::note_uart_activity();
+        }
+    }
+
+    /// RTC wakeup timer: end of a Stop mode period
+    #[task(binds = RTC_WKUP, priority = 2)]
+    fn rtc_wakeup(_ctx: rtc_wakeup::Context) {
+        low_power::clear_wakeup(WakeSource::Rtc);
+    }
+
+    /// PVD supply threshold crossing (EXTI line 16)
+    ///
+    /// # Behavior
+    /// - A dip is recorded as `SupplyDip` at once, before a brown-out reset
+    ///   can follow
+    /// - The recovery is only logged
+    #[task(binds = PVD, priority = 5)]
+    fn supply_monitor(_ctx: supply_monitor::Context) {
+        let isr = IsrContext::enter();
+        match pvd::take_event() {
+            Some(SupplyEvent::Dip) => handle_isr_error(&isr, DeviceError::SupplyDip),
+            Some(SupplyEvent::Recovered) => {
+                let threshold_mv = pvd::threshold_mv();
+                isr_log!(isr, info, "Supply back above the PVD threshold (mV)", threshold_mv);
+            }
+            None => {}
+        }
     }
 
     /// USB OTG FS interrupt handler
     ///
     /// # Behavior
     /// - Handles USB enumeration and configuration
+    /// - Drives the DTR/RTS outputs to the host's control line states, RTS
+    ///   deasserted while the RX ring buffer is above its high watermark
+    /// - Tracks bus suspend/resume for the low-power handling
+    /// - Restarts UART TX for XON/XOFF flow control (`xon_xoff`)
     /// - Manages USB data transfers to/from TX buffer
-    /// - Triggers UART forwarding when data received
-    #[task(binds = OTG_FS, shared = [otg_fs, ring_buffer_tx], priority = 4)]
+    /// - Hands received data to its sink in the bridge mode
+    #[task(
+        binds = OTG_FS,
+        shared = [otg_fs, tx_producer, tx_staging],
+        local = [control_outputs],
+        priority = 4
+    )]
     fn otg_fs(mut ctx: otg_fs::Context) {
-        ctx.shared.otg_fs.lock(|usb| {
-            if !usb.poll() {
-                handle_error(UsbError::PollError.into());
-                return;
+        let _budget = BudgetGuard::start(Task::OtgFs);
+        let isr = IsrContext::enter();
+
+        let (polled, configured, detach, power, line_coding, break_request, lines) =
+            ctx.shared.otg_fs.lock(|usb| {
+                (
+                    usb.poll(),
+                    usb.is_configured(),
+                    usb.take_dfu_detach(),
+                    usb.take_power_event(),
+                    usb.take_line_coding(),
+                    usb.take_break_request(),
+                    usb.control_lines(),
+                )
+            });
+        // Every control request interrupts, so the outputs follow each
+        // SET_CONTROL_LINE_STATE; a bus reset deasserts both. The RX
+        // watermark callbacks pend this interrupt for RTS.
+        let (dtr, rts) = lines;
+        ctx.local
+            .control_outputs
+            .drive((dtr, rts && !backpressure::is_rx_held()));
+        if !polled {
+            statistics::add_usb_error();
+            handle_isr_error(&isr, UsbError::PollError.into());
+            return;
+        }
+        iwdg::check_in(CheckIn::USB);
+
+        match power {
+            Some(PowerEvent::Suspend) => {
+                isr_log!(isr, info, "USB suspend: entering low power");
+                usb_suspend::on_suspend();
+            }
+            Some(PowerEvent::Resume) => {
+                isr_log!(isr, info, "USB resume: restarting data flow");
+                usb_suspend::on_resume();
+                ring_buffer_tx_to_usart_dma::spawn(0).ok();
+                ring_buffer_rx_to_serial::spawn().ok();
             }
+            None => {}
+        }
 
-            if usb.is_configured() {
-                ctx.shared
-                    .ring_buffer_tx
-                    .lock(|tx| match handle_usb(usb, tx) {
-                        Ok(bytes_processed) => {
-                            #[cfg(feature = "debug")]
-                            defmt::info!("USB processed {} bytes", bytes_processed);
-                            if bytes_processed > 0 {
-                                ring_buffer_tx_to_usart_dma::spawn(bytes_processed).ok();
-                            }
-                        }
-                        Err(e) => {
-                            handle_error(e.into());
-                        }
-                    });
-            } else {
-                #[cfg(feature = "debug")]
-                defmt::warn!("USB not configured");
+        // An XON from the UART peer, or an XON/XOFF of ours to send
+        if xon_xoff::take_kick() {
+            ring_buffer_tx_to_usart_dma::spawn(0).ok();
+        }
+
+        if let Some(coding) = line_coding {
+            match UartConfig::from_line_coding(&coding) {
+                Some(config) => {
+                    apply_line_coding::spawn(config).ok();
+                }
+                None => isr_log!(isr, warn, "Line coding not supported by the UART"),
             }
-        });
+        }
+
+        if let Some(request) = break_request {
+            line_break::post(request);
+            uart_break::spawn().ok();
+        }
+
+        if detach {
+            isr_log!(isr, warn, "DFU detach requested");
+            orderly_shutdown::spawn(Restart::Bootloader).ok();
+        }
+
+        if !configured {
+            isr_log!(isr, warn, "USB not configured");
+            return;
+        }
+
+        // The first error of a notification window pends this interrupt
+        if let Err(code) = ctx.shared.otg_fs.lock(send_error_notify) {
+            isr_log!(isr, warn, "Error notification not delivered, code", code as u32);
+        }
+
+        match handle_usb(
+            &isr,
+            &mut ctx.shared.otg_fs,
+            &mut ctx.shared.tx_producer,
+            &mut ctx.shared.tx_staging,
+        ) {
+            Ok(bytes_processed) => {
+                isr_log!(isr, info, "USB processed bytes", bytes_processed);
+                if bytes_processed > 0 {
+                    forward_host_data(bytes_processed);
+                }
+                if transform::has_outbox() {
+                    ring_buffer_rx_to_serial::spawn().ok();
+                }
+            }
+            Err(e) => {
+                handle_isr_error(&isr, e.into());
+            }
+        }
+
+        #[cfg(feature = "i2c-bridge")]
+        if i2c_bridge::has_pending() {
+            i2c_bridge_dispatch::spawn().ok();
+        }
+        #[cfg(feature = "can")]
+        if slcan::has_pending() {
+            slcan_dispatch::spawn().ok();
+        }
+
+        if let Some(line) = command::take_line() {
+            isr_log!(isr, debug, "AT command line received");
+            at_command::spawn(line).ok();
+        }
     }
 
     /// Process RX buffer and send to USB serial
@@ -284,25 +1007,121 @@ mod app {
     /// # Execution Context
     /// - Triggered by DMA completion or USART idle detection
     /// - Runs as async task to allow non-blocking operation
-    #[task(shared = [otg_fs, ring_buffer_rx], priority = 3)]
+    #[task(shared = [otg_fs, rx_consumer], priority = 3)]
     async fn ring_buffer_rx_to_serial(mut ctx: ring_buffer_rx_to_serial::Context) {
         #[cfg(feature = "debug")]
         defmt::debug!("Processing RX buffer");
 
-        ctx.shared.otg_fs.lock(|usb| {
-            ctx.shared.ring_buffer_rx.lock(|rx| {
-                if let Err(e) = process_rx_buffer(usb, rx) {
-                    handle_error(e.into());
-                }
-            });
-        });
+        // Leave received data for the HIL runner to inspect
+        #[cfg(feature = "hil-test")]
+        if crate::hil_test::is_capturing() {
+            return;
+        }
+
+        // Negotiation frames are consumed by the negotiator
+        if baud_negotiation::is_active() {
+            return;
+        }
+
+        // Target responses are consumed by the probe
+        if target_probe::is_active() {
+            return;
+        }
+
+        // Slave responses are consumed by the Modbus transaction
+        #[cfg(feature = "modbus")]
+        if modbus::is_active() {
+            return;
+        }
+
+        // UART data is looped back by `echo_data` in `uart-echo`
+        if !bridge_mode::routes().feeds_usb() {
+            return;
+        }
+
+        // Data for a suspended host: ask it to resume the bus
+        if usb_suspend::is_suspended() {
+            usb_remote_wakeup::spawn().ok();
+        }
+
+        // Keep the data buffered while the host re-enumerates the device
+        let mut retry = RetryState::new();
+        while !ctx.shared.otg_fs.lock(|usb| usb.is_configured()) {
+            match USB_RECONNECT.on_failure(&mut retry) {
+                Verdict::Retry { delay_ms } => Mono::delay(delay_ms.millis()).await,
+                Verdict::Exhausted => return,
+            }
+        }
+        USB_RECONNECT.on_success(&mut retry);
+
+        let _budget = BudgetGuard::start(Task::RxToUsb);
+        if let Err(e) = process_rx_buffer(&mut ctx.shared.otg_fs, &mut ctx.shared.rx_consumer) {
+            handle_error(e.into());
+        }
+    }
+
+    /// USB remote wakeup task
+    ///
+    /// # Behavior
+    /// - Spawned by the UART RX path while the bus is suspended
+    /// - Drives resume signalling for `REMOTE_WAKEUP_SIGNAL_MS` if the host
+    ///   enabled remote wakeup; otherwise the data waits for the host
+    #[task(shared = [otg_fs], priority = 3)]
+    async fn usb_remote_wakeup(mut ctx: usb_remote_wakeup::Context) {
+        if !ctx.shared.otg_fs.lock(|usb| usb.set_remote_wakeup(true)) {
+            return;
+        }
+        usb_suspend::note_remote_wakeup();
+
+        Mono::delay(REMOTE_WAKEUP_SIGNAL_MS.millis()).await;
+        ctx.shared.otg_fs.lock(|usb| usb.set_remote_wakeup(false));
+    }
+
+    /// Echo task of the `uart-echo` and `usb-echo` bridge modes
+    ///
+    /// # Behavior
+    /// - Moves UART data from the RX to the TX ring buffer and starts the
+    ///   UART TX, or host data from the TX to the RX ring buffer and starts
+    ///   the USB TX, as the routing table of the mode says
+    /// - Retries every millisecond while the sending side is full
+    #[task(shared = [rx_producer, rx_consumer, tx_producer, tx_consumer], priority = 3)]
+    async fn echo_data(mut ctx: echo_data::Context) {
+        loop {
+            let routes = bridge_mode::routes();
+            let pending = if routes.uart_data == Sink::Uart {
+                bridge_mode::loop_back(
+                    &mut ctx.shared.rx_consumer,
+                    &mut ctx.shared.tx_producer,
+                );
+                ring_buffer_tx_to_usart_dma::spawn(0).ok();
+                !ctx.shared.rx_consumer.lock(|rx| rx.is_empty())
+            } else if routes.host_data == Sink::Usb {
+                bridge_mode::loop_back(
+                    &mut ctx.shared.tx_consumer,
+                    &mut ctx.shared.rx_producer,
+                );
+                ring_buffer_rx_to_serial::spawn().ok();
+                !ctx.shared.tx_consumer.lock(|tx| tx.is_empty())
+            } else {
+                false
+            };
+            if !pending {
+                return;
+            }
+            Mono::delay(1.millis()).await;
+        }
     }
 
-    /// Transmit TX buffer contents via UART DMA
+    /// Transmit staged and TX buffer contents via UART DMA
     ///
     /// # Parameters
-    /// - `bytes_processed`: Number of bytes to transmit from buffer
-    #[task(shared = [usart_6, ring_buffer_tx], priority = 3)]
+    /// - `bytes_processed`: Number of bytes just received from the host; 0
+    ///   when respawned by the TX complete interrupt
+    ///
+    /// Data goes to the UART selected by `uart_route`. Nothing starts while
+    /// either UART is still transmitting: after a route switch, a staging
+    /// buffer may still be in flight on the previous UART.
+    #[task(shared = [usart_6, usart_3, tx_consumer, tx_staging], priority = 3)]
     async fn ring_buffer_tx_to_usart_dma(
         mut ctx: ring_buffer_tx_to_usart_dma::Context,
         bytes_processed: usize,
@@ -310,31 +1129,141 @@ mod app {
         #[cfg(feature = "debug")]
         defmt::debug!("TX DMA starting with {} bytes", bytes_processed);
 
-        ctx.shared.usart_6.lock(|usart| {
-            ctx.shared.ring_buffer_tx.lock(|tx| {
-                if let Err(e) = handle_dma_tx(usart, tx, bytes_processed) {
-                    handle_error(e.into());
-                }
-            });
-        });
+        // Leave host data for the HIL runner to inspect
+        #[cfg(feature = "hil-test")]
+        if crate::hil_test::is_capturing() {
+            return;
+        }
+
+        // Hold bridged data until the link rate is agreed
+        if baud_negotiation::is_active() {
+            return;
+        }
+
+        // Keep bridged data off the line while probing the target
+        if target_probe::is_active() {
+            return;
+        }
+
+        // Keep bridged data off the bus during a Modbus transaction
+        #[cfg(feature = "modbus")]
+        if modbus::is_active() {
+            return;
+        }
+
+        // UART is suspended in safe mode
+        if safe_mode::is_active() {
+            return;
+        }
+
+        // Host data is looped back by `echo_data` in `usb-echo`
+        if !bridge_mode::routes().feeds_uart() {
+            return;
+        }
+
+        // UART TX DMA is gated while the USB bus is suspended
+        if usb_suspend::is_suspended() {
+            return;
+        }
+
+        #[cfg(feature = "tx-seq-check")]
+        if bytes_processed > 0 {
+            if let Err(e) = data_structures::tx_sequence::verify(bytes_processed) {
+                handle_error(e.into());
+            }
+        }
+
+        // The TX complete interrupt of the busy UART respawns this task
+        if ctx.shared.usart_6.lock(|usart| usart.is_tx_busy())
+            || ctx.shared.usart_3.lock(|usart| usart.is_tx_busy())
+        {
+            return;
+        }
+
+        let _budget = BudgetGuard::start(Task::TxToUart);
+        let result = match uart_route::current() {
+            UartPort::Usart6 => handle_dma_tx(
+                &mut ctx.shared.usart_6,
+                &mut ctx.shared.tx_consumer,
+                &mut ctx.shared.tx_staging,
+            ),
+            UartPort::Usart3 => handle_dma_tx(
+                &mut ctx.shared.usart_3,
+                &mut ctx.shared.tx_consumer,
+                &mut ctx.shared.tx_staging,
+            ),
+        };
+        if let Err(e) = result {
+            handle_error(e.into());
+        }
+    }
+
+    /// TIM4 (LED PWM) interrupt handler
+    ///
+    /// Ends the on-time of dimmed LEDs and starts each PWM period.
+    #[cfg(feature = "led-pwm")]
+    #[task(binds = TIM4, local = [led_pwm], priority = 2)]
+    fn tim4(ctx: tim4::Context) {
+        ctx.local.led_pwm.on_interrupt();
     }
 
     /// Blue LED status indication task
     ///
     /// # Behavior Patterns
     /// - Normal operation: 1Hz blink
+    /// - Safe mode: rapid blink
     /// - Error active: Solid off
     /// - Manual override: Solid on
+    /// - USB suspended: Off, blinking stops until resume
+    ///
+    /// With the `led-pwm` feature, normal operation breathes (faster during
+    /// bridge traffic) and an active error shows solid on.
     #[task(shared = [blue_led, is_red_led_active, is_blue_led_blinking], priority = 1)]
     async fn blue_led_blink(mut ctx: blue_led_blink::Context) {
+        #[cfg(feature = "led-pwm")]
+        let mut breather = Breather::new();
+
         loop {
+            let red_active = ctx.shared.is_red_led_active.lock(|active| *active);
+            let blinking = ctx.shared.is_blue_led_blinking.lock(|blinking| *blinking);
+
+            #[cfg(feature = "led-pwm")]
+            let delay = {
+                let pattern = if usb_suspend::is_suspended() {
+                    BluePattern::Off
+                } else if safe_mode::is_active() {
+                    BluePattern::Blink
+                } else if red_active {
+                    BluePattern::Solid
+                } else if blinking {
+                    BluePattern::Breathe
+                } else {
+                    BluePattern::Off
+                };
+                breather.step(pattern, Mono::now().ticks())
+            };
+
+            #[cfg(not(feature = "led-pwm"))]
             let delay = ctx.shared.blue_led.lock(|led| {
-                if ctx.shared.is_red_led_active.lock(|active| *active) {
+                if usb_suspend::is_suspended() {
+                    led.off();
+                    LED_SUSPEND_POLL
+                } else if safe_mode::is_active() {
+                    let result = if led.state() {
+                        led.set_high()
+                    } else {
+                        led.set_low()
+                    };
+                    if let Err(e) = result {
+                        handle_error(e.into());
+                    }
+                    SAFE_MODE_BLINK_MS
+                } else if red_active {
                     if let Err(e) = led.set_high() {
                         handle_error(e.into());
                     }
                     LED_CHECK_INTERVAL
-                } else if ctx.shared.is_blue_led_blinking.lock(|blinking| *blinking) {
+                } else if blinking {
                     toggle_led(led)
                 } else {
                     if let Err(e) = led.set_high() {
@@ -348,18 +1277,1459 @@ mod app {
         }
     }
 
-    /// Error code visualization task
+    /// User button task
     ///
-    /// # Display Protocol
-    /// - Short blink: Digit separator
-    /// - Long blink: Error code digit (quantity = digit value)
-    /// - 500ms pause between codes
-    #[task(shared = [red_led, is_red_led_active], priority = 5)]
-    async fn task_display_error_codes(mut ctx: task_display_error_codes::Context) {
-        let mut buffer = [0u8; 100];
+    /// # Sequence
+    /// 1. Waits `BUTTON_DEBOUNCE_MS` and drops the edge if the button is up
+    /// 2. Measures the hold time, up to `BUTTON_LONG_PRESS_MS`
+    /// 3. Runs the action bound to the press type
+    /// 4. Waits for the release plus the debounce time before returning
+    #[task(shared = [button, is_blue_led_blinking, otg_fs], priority = 1)]
+    async fn button_press(mut ctx: button_press::Context) {
+        Mono::delay(BUTTON_DEBOUNCE_MS.millis()).await;
+        if !ctx.shared.button.lock(|b| b.is_pressed()) {
+            return;
+        }
 
-        loop {
-            if !has_errors() {
+        let mut held_ms = BUTTON_DEBOUNCE_MS;
+        while held_ms < BUTTON_LONG_PRESS_MS && ctx.shared.button.lock(|b| b.is_pressed()) {
+            Mono::delay(BUTTON_POLL_MS.millis()).await;
+            held_ms += BUTTON_POLL_MS;
+        }
+
+        let press = button::classify(held_ms);
+        match action_for(press) {
+            ButtonAction::None => {}
+            ButtonAction::ClearErrors => {
+                let _cleared = clear_error_queue();
+
+                #[cfg(feature = "debug")]
+                defmt::info!("Button: {} error code(s) cleared", _cleared);
+            }
+            ButtonAction::ToggleBlink => {
+                ctx.shared
+                    .is_blue_led_blinking
+                    .lock(|blinking| *blinking = !*blinking);
+            }
+            ButtonAction::DumpStats => ctx.shared.otg_fs.lock(run_stats_report),
+            ButtonAction::Benchmark => {
+                // A run in progress keeps going; the press is ignored
+                throughput_benchmark::spawn(BENCH_DEFAULT_SECONDS).ok();
+            }
+        }
+
+        while ctx.shared.button.lock(|b| b.is_pressed()) {
+            Mono::delay(BUTTON_POLL_MS.millis()).await;
+        }
+        Mono::delay(BUTTON_DEBOUNCE_MS.millis()).await;
+    }
+
+    /// UART strap window task
+    ///
+    /// # Behavior
+    /// - Waits `STRAP_WINDOW_MS` after boot
+    /// - Applies the preset selected over the UART, if any
+    /// - Switches USART6 reception to DMA
+    #[task(shared = [usart_6], priority = 1)]
+    async fn strap_window(mut ctx: strap_window::Context) {
+        Mono::delay(STRAP_WINDOW_MS.millis()).await;
+
+        if let Err(e) = ctx.shared.usart_6.lock(uart_strap::close_window) {
+            handle_error(e.into());
+        }
+    }
+
+    /// Debug console task
+    ///
+    /// # Behavior
+    /// - Polls the console CDC port every `CONSOLE_POLL_MS`
+    /// - Echoes input and executes complete lines through `task_handlers::console`
+    /// - Spawns the probe, negotiation and latency tasks on request
+    /// - Applies Morse speed and repeat settings to the red LED
+    /// - Reports and saves the flash settings
+    /// - Starts the orderly shutdown for `reboot` and `bootloader`
+    /// - Prints a banner whenever a terminal opens the port
+    #[task(
+        shared = [otg_fs, red_led, flash, snapshot_log, usart_6, usart_3],
+        priority = 1
+    )]
+    async fn debug_console(mut ctx: debug_console::Context) {
+        /// Sends a reply, waiting for the host to drain the console endpoint
+        async fn send(
+            usb: &mut impl rtic::Mutex<T = peripherals::otg_fs::OtgFsController<'static>>,
+            mut data: &[u8],
+        ) {
+            let mut stalled_ms = 0;
+            while !data.is_empty() && stalled_ms < CONSOLE_POLL_MS {
+                match usb.lock(|usb| usb.console_write(data)) {
+                    Ok(0) => {
+                        Mono::delay(1.millis()).await;
+                        stalled_ms += 1;
+                    }
+                    Ok(written) => {
+                        data = &data[written..];
+                        stalled_ms = 0;
+                    }
+                    Err(e) => {
+                        handle_error(e.into());
+                        return;
+                    }
+                }
+            }
+        }
+
+        let mut editor = LineEditor::new();
+        let mut packet = [0u8; CDC_MAX_PACKET_SIZE];
+        let mut connected = false;
+
+        loop {
+            Mono::delay(CONSOLE_POLL_MS.millis()).await;
+
+            let (now_connected, read, idle) = ctx.shared.otg_fs.lock(|usb| {
+                (usb.console_connected(), usb.console_read(&mut packet), usb.is_idle())
+            });
+            // The OTG FS handler checks in while there is bus traffic; an
+            // idle bus raises no interrupt, so nothing is stuck
+            if idle {
+                iwdg::check_in(CheckIn::USB);
+            }
+            if now_connected && !connected {
+                send(&mut ctx.shared.otg_fs, console::BANNER.as_bytes()).await;
+                send(&mut ctx.shared.otg_fs, console::PROMPT.as_bytes()).await;
+            }
+            connected = now_connected;
+
+            #[cfg(feature = "usb-log")]
+            if connected {
+                let mut frames = [0u8; CDC_MAX_PACKET_SIZE];
+                let pending = task_handlers::usb_log::take(&mut frames);
+                if pending > 0 {
+                    send(&mut ctx.shared.otg_fs, &frames[..pending]).await;
+                }
+            }
+
+            #[cfg(feature = "adc")]
+            if connected {
+                let mut records = [0u8; CDC_MAX_PACKET_SIZE];
+                let pending = adc_stream::take(&mut records);
+                if pending > 0 {
+                    send(&mut ctx.shared.otg_fs, &records[..pending]).await;
+                }
+            }
+
+            let count = match read {
+                Ok(count) => count,
+                Err(e) => {
+                    handle_error(e.into());
+                    continue;
+                }
+            };
+
+            let mut echo = console::Echo::new();
+            for &byte in &packet[..count] {
+                let line = editor.feed(byte, &mut echo);
+                send(&mut ctx.shared.otg_fs, &echo).await;
+                let Some(line) = line else {
+                    continue;
+                };
+                let profile = ProfileGuard::start(Slot::Console);
+
+                let mut reply: heapless::String<{ console::REPLY_LEN }> = heapless::String::new();
+                let action = match console::parse(&line) {
+                    Ok(command) => console::execute(command, &mut reply),
+                    Err(message) => write!(reply, "{}\r\n", message).map(|_| Action::None),
+                };
+
+                let result = match action {
+                    Ok(Action::None) => Ok(()),
+                    Ok(Action::Probe) => {
+                        console::write_spawn_result(&mut reply, probe_target::spawn().is_ok())
+                    }
+                    Ok(Action::Negotiate(role)) => {
+                        console::write_spawn_result(&mut reply, negotiate_baud::spawn(role).is_ok())
+                    }
+                    Ok(Action::AutoBaud) => {
+                        console::write_spawn_result(&mut reply, auto_baud_detect::spawn().is_ok())
+                    }
+                    #[cfg(feature = "modbus")]
+                    Ok(Action::Modbus(request)) => console::write_spawn_result(
+                        &mut reply,
+                        modbus_transaction::spawn(request).is_ok(),
+                    ),
+                    Ok(Action::Latency(samples)) => console::write_spawn_result(
+                        &mut reply,
+                        latency_measurement::spawn(samples).is_ok(),
+                    ),
+                    Ok(Action::Bench(seconds)) => console::write_spawn_result(
+                        &mut reply,
+                        throughput_benchmark::spawn(seconds).is_ok(),
+                    ),
+                    Ok(Action::Chunk(size)) => {
+                        let applied = ctx.shared.otg_fs.lock(|usb| match size {
+                            Some(size) => usb.set_chunk_size(size),
+                            None => usb.chunk_size(),
+                        });
+                        write!(reply, "chunk size {} bytes\r\n", applied)
+                    }
+                    Ok(Action::Morse(config)) => {
+                        let applied = ctx.shared.red_led.lock(|red_led| {
+                            if let Some(config) = config {
+                                red_led.set_morse_config(config);
+                            }
+                            red_led.morse_config()
+                        });
+                        write!(reply, "{}\r\n", applied)
+                    }
+                    Ok(Action::Settings) => ctx.shared.flash.lock(|flash| {
+                        settings::write_report(flash, &mut console::CrLf(&mut reply))
+                    }),
+                    Ok(Action::Snapshots) => {
+                        (&mut ctx.shared.snapshot_log, &mut ctx.shared.flash).lock(|log, flash| {
+                            log.dump(flash, &mut console::CrLf(&mut reply))
+                        })
+                    }
+                    #[cfg(feature = "i2c")]
+                    Ok(Action::I2cScan) => {
+                        console::write_spawn_result(&mut reply, i2c_scan::spawn().is_ok())
+                    }
+                    #[cfg(feature = "can")]
+                    Ok(Action::SlcanOff) => {
+                        // A dispatcher already running closes the channel as well
+                        slcan_dispatch::spawn().ok();
+                        reply.write_str("ok\r\n")
+                    }
+                    #[cfg(feature = "spi")]
+                    Ok(Action::SpiTest) => {
+                        console::write_spawn_result(&mut reply, spi_self_test::spawn().is_ok())
+                    }
+                    #[cfg(feature = "dac")]
+                    Ok(Action::DacOutput(channel, waveform)) => console::write_spawn_result(
+                        &mut reply,
+                        dac_output::spawn(channel, waveform).is_ok(),
+                    ),
+                    Ok(Action::Reboot(restart)) => match orderly_shutdown::spawn(restart) {
+                        Ok(()) if restart == Restart::Bootloader => {
+                            reply.write_str("rebooting into the bootloader\r\n")
+                        }
+                        Ok(()) => reply.write_str("rebooting\r\n"),
+                        Err(_) => reply.write_str("reboot already pending\r\n"),
+                    },
+                    Ok(Action::Save) => {
+                        let current = Settings::current(
+                            ctx.shared.usart_6.lock(|usart| usart.baud_rate()),
+                            ctx.shared.usart_3.lock(|usart| usart.baud_rate()),
+                        );
+                        match ctx.shared.flash.lock(|flash| settings::save(flash, &current)) {
+                            Ok(0) => reply.write_str("unchanged\r\n"),
+                            Ok(records) => write!(reply, "saved, {} record(s)\r\n", records),
+                            Err(e) => {
+                                handle_error(e.into());
+                                reply.write_str("flash error\r\n")
+                            }
+                        }
+                    }
+                    Err(e) => Err(e),
+                };
+                if result.is_err() {
+                    // Reply did not fit; send what there is
+                    reply.push_str("...\r\n").ok();
+                }
+                reply.push_str(console::PROMPT).ok();
+                drop(profile);
+
+                send(&mut ctx.shared.otg_fs, reply.as_bytes()).await;
+            }
+        }
+    }
+
+    /// Periodic job scheduler task
+    ///
+    /// # Behavior
+    /// - Runs every job registered in `task_handlers::periodic` from one task
+    /// - Sleeps until the next job is due
+    /// - Jobs gated by a disabled subsystem are skipped
+    /// - Flash sector erase (snapshot rotation) runs here, at the lowest priority
+    /// - Watches for an error storm after boot and enters safe mode
+    /// - Drops the core clock while VBUS is absent and restores it on attach,
+    ///   with both UARTs locked
+    #[task(
+        shared = [otg_fs, flash, snapshot_log, usart_6, usart_3],
+        local = [modem_lines, vbus, clock_health, activity_leds],
+        priority = 1
+    )]
+    async fn periodic_jobs(mut ctx: periodic_jobs::Context) {
+        let mut scheduler = PeriodicScheduler::new();
+        let mut last_tick = Mono::now().ticks();
+        let mut uptime_ms: u64 = 0;
+        let mut boot_guard = BootGuard::new(last_tick);
+        register_jobs(&mut scheduler, last_tick);
+
+        loop {
+            let now = Mono::now().ticks();
+            uptime_ms += now.wrapping_sub(last_tick) as u64;
+            last_tick = now;
+            let profile = ProfileGuard::start(Slot::Periodic);
+
+            for job in scheduler.poll(now) {
+                match job {
+                    PeriodicJob::ErrorNotify => ctx.shared.otg_fs.lock(run_error_notify),
+                    PeriodicJob::SafeModeGuard => {
+                        let guard = &mut boot_guard;
+                        let usart_3 = &mut ctx.shared.usart_3;
+                        let (result, tx_running) = ctx.shared.usart_6.lock(|usart| {
+                            usart_3.lock(|usart_3| {
+                                let result = run_safe_mode_guard(guard, usart, usart_3, now);
+                                (result, usart.is_dma_tx_running() || usart_3.is_dma_tx_running())
+                            })
+                        });
+                        if let Err(e) = result {
+                            handle_error(e);
+                        }
+                        // The DMA handlers check in while bytes move; with no
+                        // transfer running there is nothing to get stuck
+                        if !tx_running {
+                            iwdg::check_in(CheckIn::USART);
+                        }
+                    }
+                    PeriodicJob::StatsReport => ctx.shared.otg_fs.lock(run_stats_report),
+                    PeriodicJob::ActivityLeds => run_activity_leds(ctx.local.activity_leds),
+                    #[cfg(feature = "usb-msc")]
+                    PeriodicJob::LogVolume => run_log_volume(),
+                    PeriodicJob::BudgetCheck => {
+                        if let Err(e) = run_budget_check() {
+                            handle_error(e);
+                        }
+                    }
+                    #[cfg(feature = "adc")]
+                    PeriodicJob::HealthMonitor => {
+                        if let Err(e) = run_health_monitor() {
+                            handle_error(e);
+                        }
+                    }
+                    PeriodicJob::ClockHealth => {
+                        if let Err(e) = run_clock_health(ctx.local.clock_health, now, uptime_ms) {
+                            handle_error(e);
+                        }
+                    }
+                    PeriodicJob::ModemStatus => {
+                        let lines = &*ctx.local.modem_lines;
+                        ctx.shared.otg_fs.lock(|usb| run_modem_status(lines, usb));
+                    }
+                    PeriodicJob::VbusSense => {
+                        ctx.local.vbus.poll();
+                        if let Some(target) = power::vbus_clock_target() {
+                            let usart_3 = &mut ctx.shared.usart_3;
+                            if let Err(e) = ctx.shared.usart_6.lock(|usart| {
+                                usart_3.lock(|usart_3| run_clock_switch(target, usart, usart_3))
+                            }) {
+                                handle_error(e);
+                            }
+                        }
+                    }
+                    PeriodicJob::StatsSnapshot => {
+                        let uptime_s = (uptime_ms / 1_000) as u32;
+                        if let Err(e) = (&mut ctx.shared.snapshot_log, &mut ctx.shared.flash)
+                            .lock(|log, flash| run_stats_snapshot(log, flash, uptime_s))
+                        {
+                            handle_error(e);
+                        }
+                    }
+                }
+            }
+
+            drop(profile);
+
+            let sleep_ms = scheduler.time_to_next(Mono::now().ticks()).max(1);
+            Mono::delay(sleep_ms.millis()).await;
+        }
+    }
+
+    /// On-target integration test runner
+    ///
+    /// # Sequence
+    /// 1. Pure logic: ring buffer, SPSC queue, Morse encoder, error store
+    /// 2. USART6 DMA loopback through the PG14-PG9 jumper
+    /// 3. USB echo of a probe by the host harness
+    /// 4. Summary frame
+    #[cfg(feature = "hil-test")]
+    #[task(
+        shared = [otg_fs, usart_6, rx_consumer, tx_producer, tx_consumer, tx_staging],
+        priority = 1
+    )]
+    async fn hil_runner(mut ctx: hil_runner::Context) {
+        use crate::hil_test::*;
+
+        /// Reports one outcome over RTT and the CDC port
+        fn report(
+            usb: &mut impl rtic::Mutex<T = peripherals::otg_fs::OtgFsController<'static>>,
+            summary: &mut Summary,
+            name: &str,
+            outcome: Outcome,
+        ) {
+            summary.record(&outcome);
+            defmt::info!("HIL {=str}: {:?}", name, defmt::Debug2Format(&outcome));
+
+            let frame = format_result(name, &outcome);
+            usb.lock(|usb| {
+                if usb.is_configured() {
+                    usb.write(frame.as_bytes()).ok();
+                }
+            });
+        }
+
+        Mono::delay(HIL_START_DELAY_MS.millis()).await;
+        set_capture(true);
+        let mut summary = Summary::default();
+
+        report(
+            &mut ctx.shared.otg_fs,
+            &mut summary,
+            "ring_buffer",
+            test_ring_buffer(),
+        );
+        report(
+            &mut ctx.shared.otg_fs,
+            &mut summary,
+            "spsc_queue",
+            test_spsc_queue(),
+        );
+        report(&mut ctx.shared.otg_fs, &mut summary, "morse", test_morse());
+        report(
+            &mut ctx.shared.otg_fs,
+            &mut summary,
+            "error_queue",
+            test_error_queue(),
+        );
+
+        // DMA loopback, through the USART6 jumper
+        uart_route::select(UartPort::Usart6);
+        ctx.shared.rx_consumer.lock(|rx| rx.clear());
+        ctx.shared.tx_staging.lock(|staging| staging.clear());
+        ctx.shared.tx_consumer.lock(|tx| tx.clear());
+        let queued = ctx
+            .shared
+            .tx_producer
+            .lock(|tx| tx.push(LOOPBACK_PATTERN).is_ok());
+        let sent = queued
+            && handle_dma_tx(
+                &mut ctx.shared.usart_6,
+                &mut ctx.shared.tx_consumer,
+                &mut ctx.shared.tx_staging,
+            )
+            .is_ok_and(|sent| sent > 0);
+        let outcome = if sent {
+            Mono::delay(HIL_LOOPBACK_TIMEOUT_MS.millis()).await;
+            ctx.shared.rx_consumer.lock(check_loopback)
+        } else {
+            Outcome::Fail("DMA TX start")
+        };
+        report(
+            &mut ctx.shared.otg_fs,
+            &mut summary,
+            "dma_loopback",
+            outcome,
+        );
+
+        // USB echo
+        ctx.shared.tx_consumer.lock(|tx| tx.clear());
+        let probed = ctx
+            .shared
+            .otg_fs
+            .lock(|usb| usb.is_configured() && usb.write(USB_ECHO_PROBE).is_ok());
+        let outcome = if probed {
+            Mono::delay(HIL_USB_ECHO_TIMEOUT_MS.millis()).await;
+            ctx.shared.tx_consumer.lock(check_usb_echo)
+        } else {
+            Outcome::Skip("USB not configured")
+        };
+        report(&mut ctx.shared.otg_fs, &mut summary, "usb_echo", outcome);
+
+        set_capture(false);
+
+        let frame = summary.format();
+        defmt::info!(
+            "HIL done: {} passed, {} failed, {} skipped",
+            summary.passed,
+            summary.failed,
+            summary.skipped
+        );
+        ctx.shared.otg_fs.lock(|usb| {
+            if usb.is_configured() {
+                usb.write(frame.as_bytes()).ok();
+            }
+        });
+    }
+
+    /// Downstream target identification probe
+    ///
+    /// # Behavior
+    /// - Executes the operations requested by the `Prober`
+    /// - Feeds received bytes to the prober every `POLL_MS`
+    /// - Bridged data is held in the ring buffers until probing finishes
+    /// - The result is available through `target_probe::last_result`
+    /// - Routes the data port back to USART6, the probed target's UART
+    #[task(shared = [usart_6, rx_consumer], priority = 1)]
+    async fn probe_target(mut ctx: probe_target::Context) {
+        const POLL_MS: u32 = 10;
+
+        uart_route::select(UartPort::Usart6);
+
+        let (restore_baud, restore_frame) = ctx
+            .shared
+            .usart_6
+            .lock(|usart| (usart.baud_rate(), usart.uart_config()));
+        let mut prober = Prober::new(restore_baud, restore_frame);
+        let mut ops = prober.start();
+
+        'probe: loop {
+            ctx.shared.rx_consumer.lock(|rx| rx.clear());
+
+            for op in ops.iter() {
+                match *op {
+                    target_probe::Op::Configure { baud, frame } => {
+                        let result = ctx.shared.usart_6.lock(|usart| {
+                            usart.reconfigure(frame)?;
+                            usart.set_baud_rate(baud)
+                        });
+                        if let Err(e) = result {
+                            handle_error(e.into());
+                        }
+                    }
+                    target_probe::Op::Send(bytes) => {
+                        if let Err(e) = ctx
+                            .shared
+                            .usart_6
+                            .lock(|usart| transmit_direct(usart, bytes))
+                        {
+                            handle_error(e.into());
+                        }
+
+                        // Let the request leave the shift register before listening
+                        for _ in 0..POLL_MS {
+                            Mono::delay(1.millis()).await;
+                            if ctx.shared.usart_6.lock(|u| u.is_transmission_complete()) {
+                                break;
+                            }
+                        }
+                    }
+                    target_probe::Op::Delay(ms) => Mono::delay(ms.millis()).await,
+                    target_probe::Op::Finish(_) => break 'probe,
+                }
+            }
+
+            // Wait for a known response or the step timeout
+            let mut waited = 0;
+            ops = loop {
+                Mono::delay(POLL_MS.millis()).await;
+                waited += POLL_MS;
+
+                let finished = ctx.shared.rx_consumer.lock(|rx| {
+                    let mut byte = [0u8; 1];
+                    while rx.pop(&mut byte) == 1 {
+                        if let Some(ops) = prober.on_byte(byte[0]) {
+                            return Some(ops);
+                        }
+                    }
+                    None
+                });
+
+                if let Some(ops) = finished {
+                    break ops;
+                }
+                if waited >= prober.timeout_ms() {
+                    break prober.on_timeout();
+                }
+            };
+        }
+    }
+
+    /// Bridge-to-bridge baud rate negotiation
+    ///
+    /// # Behavior
+    /// - Executes the operations requested by the `Negotiator`
+    /// - Draws a fresh nonce for the frames, or uses the device nonce if the
+    ///   RNG fails
+    /// - Polls the RX buffer for negotiation frames every `POLL_MS`
+    /// - Bridged data is held in the ring buffers until negotiation finishes
+    /// - Routes the data port back to USART6, the link being negotiated
+    ///
+    /// # Parameters
+    /// - `role`: Initiator proposes rates, responder answers
+    #[task(shared = [usart_6, rx_consumer], priority = 1)]
+    async fn negotiate_baud(mut ctx: negotiate_baud::Context, role: Role) {
+        const POLL_MS: u32 = 10;
+
+        uart_route::select(UartPort::Usart6);
+
+        let session = nonce::next().unwrap_or_else(|e| {
+            handle_error(e.into());
+            nonce::device()
+        });
+        let mut negotiator = Negotiator::new(role, session);
+        let mut assembler = FrameAssembler::default();
+        let mut ops = negotiator.start();
+
+        'negotiation: loop {
+            for op in ops.iter() {
+                match *op {
+                    Op::Send(frame) => {
+                        let bytes = frame.encode();
+                        if let Err(e) = ctx
+                            .shared
+                            .usart_6
+                            .lock(|usart| transmit_direct(usart, &bytes))
+                        {
+                            handle_error(e.into());
+                        }
+
+                        // Let the frame leave the shift register before any switch
+                        for _ in 0..POLL_MS {
+                            Mono::delay(1.millis()).await;
+                            if ctx.shared.usart_6.lock(|u| u.is_transmission_complete()) {
+                                break;
+                            }
+                        }
+                    }
+                    Op::SwitchBaud(baud) => {
+                        if let Err(e) = ctx.shared.usart_6.lock(|u| u.set_baud_rate(baud)) {
+                            handle_error(e.into());
+                        }
+                    }
+                    Op::Delay(ms) => Mono::delay(ms.millis()).await,
+                    Op::Finish(_) => break 'negotiation,
+                }
+            }
+
+            // Wait for the next frame or the step timeout
+            let mut waited = 0;
+            ops = loop {
+                Mono::delay(POLL_MS.millis()).await;
+                waited += POLL_MS;
+
+                let frame = ctx.shared.rx_consumer.lock(|rx| {
+                    let mut byte = [0u8; 1];
+                    while rx.pop(&mut byte) == 1 {
+                        if let Some(frame) = assembler.feed(byte[0]) {
+                            return Some(frame);
+                        }
+                    }
+                    None
+                });
+
+                if let Some(frame) = frame {
+                    break negotiator.on_frame(frame);
+                }
+                if waited >= negotiator.timeout_ms() {
+                    break negotiator.on_timeout();
+                }
+            };
+        }
+    }
+
+    /// Automatic baud rate detection on USART6
+    ///
+    /// # Behavior
+    /// - Routes the data port to USART6, whose RX the TIM3 jumper watches
+    /// - Hands the RX timeout timer to the edge capture; UART RX is dropped
+    ///   until the rate is known
+    /// - Switches USART6 to the detected rate, restores the RX timeout and
+    ///   reports the outcome on the USB log; `autobaud` prints it
+    /// - Keeps the old rate after `AUTOBAUD_TIMEOUT_MS` without RX traffic
+    /// - Does nothing while the target probe, the negotiation or a benchmark
+    ///   owns the UART
+    #[task(shared = [usart_6], priority = 1)]
+    async fn auto_baud_detect(mut ctx: auto_baud_detect::Context) {
+        const POLL_MS: u32 = 10;
+
+        // The probe, the negotiation or a benchmark owns the UART
+        if !uart_route::can_switch() {
+            return;
+        }
+        uart_route::select(UartPort::Usart6);
+        if let Err(e) = ctx.shared.usart_6.lock(|usart| usart.start_baud_detect()) {
+            handle_error(e.into());
+            return;
+        }
+        auto_baud::begin();
+
+        let mut waited = 0;
+        let outcome = loop {
+            Mono::delay(POLL_MS.millis()).await;
+            waited += POLL_MS;
+
+            if let Some(measured) = auto_baud::take_measurement() {
+                break auto_baud::Outcome::from_measurement(measured);
+            }
+            if waited >= AUTOBAUD_TIMEOUT_MS {
+                break auto_baud::Outcome::Timeout;
+            }
+        };
+
+        if let Err(e) = ctx
+            .shared
+            .usart_6
+            .lock(|usart| usart.finish_baud_detect(outcome.baud()))
+        {
+            handle_error(e.into());
+        }
+        auto_baud::finish(outcome);
+        usb_log!(info, "Auto-baud: {}", outcome);
+    }
+
+    /// Modbus RTU transaction on USART6
+    ///
+    /// # Behavior
+    /// - Routes the data port to USART6 and sets the RX timeout to the
+    ///   Modbus frame gap; bridged data is held in the ring buffers
+    /// - Sends the request, then collects the response until the TIM3
+    ///   interrupt posts the line silence or the expected length is in
+    /// - Gives up after `MODBUS_RESPONSE_TIMEOUT_MS`
+    /// - Restores the RX timeout and reports the result as JSON on the USB
+    ///   log; `modbus` prints it
+    /// - Does nothing while the target probe, the negotiation or a benchmark
+    ///   owns the UART
+    #[cfg(feature = "modbus")]
+    #[task(shared = [usart_6, rx_consumer], priority = 1)]
+    async fn modbus_transaction(mut ctx: modbus_transaction::Context, request: ModbusRequest) {
+        // The probe, the negotiation or a benchmark owns the UART
+        if !uart_route::can_switch() {
+            return;
+        }
+        uart_route::select(UartPort::Usart6);
+        modbus::begin();
+
+        // Without the timer (no jumper) the response ends at its length
+        let restore = ctx.shared.usart_6.lock(|usart| {
+            let restore = usart.rx_timeout_bit_times();
+            if restore.is_some() {
+                usart.set_rx_timeout(silence_bit_times(usart.baud_rate())).ok();
+            }
+            restore
+        });
+
+        ctx.shared.rx_consumer.lock(|rx| rx.clear());
+        let frame = request.encode();
+        let sent = ctx
+            .shared
+            .usart_6
+            .lock(|usart| transmit_direct(usart, &frame));
+
+        let result = match sent {
+            Err(e) => {
+                handle_error(e.into());
+                Err(ModbusError::TransmitFailed)
+            }
+            Ok(()) => {
+                let mut received = Adu::new();
+                let mut waited = 0;
+                loop {
+                    Mono::delay(1.millis()).await;
+                    waited += 1;
+
+                    // Silence first: bytes before it are already buffered
+                    let silent = modbus::take_silence();
+                    ctx.shared.rx_consumer.lock(|rx| {
+                        let mut byte = [0u8; 1];
+                        while received.len() < received.capacity() && rx.pop(&mut byte) == 1 {
+                            received.push(byte[0]).ok();
+                        }
+                    });
+
+                    if (silent && !received.is_empty()) || is_complete(&request, &received) {
+                        break decode_response(&request, &received);
+                    }
+                    if waited >= MODBUS_RESPONSE_TIMEOUT_MS {
+                        break Err(if received.is_empty() {
+                            ModbusError::Timeout
+                        } else {
+                            ModbusError::Malformed
+                        });
+                    }
+                }
+            }
+        };
+
+        if let Some(bit_times) = restore {
+            if let Err(e) = ctx.shared.usart_6.lock(|usart| usart.set_rx_timeout(bit_times)) {
+                handle_error(e.into());
+            }
+        }
+        ctx.shared.rx_consumer.lock(|rx| rx.clear());
+        let transaction = modbus::finish(request, result);
+        usb_log!(info, "Modbus: {}", transaction);
+    }
+
+    /// Interrupt latency measurement
+    ///
+    /// # Behavior
+    /// - Pends the EXTI3 probe `samples` times, 1 ms apart
+    /// - Reports min/avg/max latency over RTT
+    ///
+    /// # Parameters
+    /// - `samples`: Number of measurements to take
+    #[task(priority = 1)]
+    async fn latency_measurement(_ctx: latency_measurement::Context, samples: u16) {
+        let mut stats = LatencyStats::default();
+
+        for _ in 0..samples {
+            latency::trigger();
+            Mono::delay(1.millis()).await;
+
+            if let Some(cycles) = latency::take_sample() {
+                stats.add(cycles);
+            }
+        }
+
+        #[cfg(feature = "debug")]
+        {
+            let mut report: heapless::String<128> = heapless::String::new();
+            if stats.write_report(&mut report).is_ok() {
+                defmt::info!("{=str}", report.as_str());
+            }
+        }
+        latency::store_report(stats);
+    }
+
+    /// Throughput benchmark
+    ///
+    /// # Behavior
+    /// - Started by `bench <seconds>` on the debug console or a button press
+    ///   bound to `bench`
+    /// - Needs TX jumpered to RX on the routed UART; the DMA handlers send
+    ///   and check a PRBS-15 pattern for `seconds` (`task_handlers::benchmark`)
+    /// - Samples the receive rate every second, then waits for the last
+    ///   transfer to come back before reporting
+    /// - The report goes to the debug console, or the debug channel; `bench`
+    ///   prints it again
+    /// - Bridged host data waits in the TX buffers and goes out afterwards
+    ///
+    /// # Parameters
+    /// - `seconds`: Length of the run
+    #[task(shared = [otg_fs, usart_6, usart_3], priority = 1)]
+    async fn throughput_benchmark(mut ctx: throughput_benchmark::Context, seconds: u32) {
+        const POLL_MS: u32 = 10;
+
+        let port = uart_route::current();
+        if let Err(reason) = benchmark::start() {
+            ctx.shared
+                .otg_fs
+                .lock(|usb| benchmark::deliver(usb, Err(reason)));
+            return;
+        }
+        let baud = match port {
+            UartPort::Usart6 => ctx.shared.usart_6.lock(|usart| usart.baud_rate()),
+            UartPort::Usart3 => ctx.shared.usart_3.lock(|usart| usart.baud_rate()),
+        };
+        ring_buffer_tx_to_usart_dma::spawn(0).ok();
+
+        let mut meter = RateMeter::new();
+        for _ in 0..seconds {
+            Mono::delay(1_000.millis()).await;
+            meter.sample();
+        }
+
+        // Let the last pattern transfer leave, then the RX flush pick it up
+        benchmark::drain();
+        for _ in 0..100 {
+            let busy = match port {
+                UartPort::Usart6 => ctx.shared.usart_6.lock(|usart| usart.is_tx_busy()),
+                UartPort::Usart3 => ctx.shared.usart_3.lock(|usart| usart.is_tx_busy()),
+            };
+            if !busy {
+                break;
+            }
+            Mono::delay(POLL_MS.millis()).await;
+        }
+        Mono::delay((2 * RX_IDLE_POLL_MS + POLL_MS).millis()).await;
+
+        let report = benchmark::finish(port, baud, seconds, &meter);
+        ctx.shared
+            .otg_fs
+            .lock(|usb| benchmark::deliver(usb, Ok(&report)));
+
+        // Host data held during the run
+        ring_buffer_tx_to_usart_dma::spawn(0).ok();
+    }
+
+    /// Status screen task
+    ///
+    /// # Behavior
+    /// - Every `DISPLAY_REFRESH_MS`, samples the USB state and the routed UART
+    ///   and redraws the changed lines of the status page
+    /// - Skipped while the `display` subsystem is disabled
+    /// - Ends at once if the display did not come up
+    #[cfg(feature = "display")]
+    #[task(shared = [otg_fs, usart_6, usart_3], local = [display], priority = 1)]
+    async fn display_status(mut ctx: display_status::Context) {
+        let Some(display) = ctx.local.display.as_mut() else {
+            return;
+        };
+        let mut screen = StatusScreen::new();
+
+        loop {
+            if task_registry::is_enabled(Subsystem::DISPLAY_REFRESH) {
+                let profile = ProfileGuard::start(Slot::Display);
+                let port = uart_route::current();
+                let status = Status {
+                    usb_configured: ctx.shared.otg_fs.lock(|usb| usb.is_configured()),
+                    usb_suspended: usb_suspend::is_suspended(),
+                    port,
+                    baud_rate: match port {
+                        UartPort::Usart6 => ctx.shared.usart_6.lock(|usart| usart.baud_rate()),
+                        UartPort::Usart3 => ctx.shared.usart_3.lock(|usart| usart.baud_rate()),
+                    },
+                };
+                screen.render(display, &status);
+                drop(profile);
+            }
+
+            Mono::delay(DISPLAY_REFRESH_MS.millis()).await;
+        }
+    }
+
+    /// Touch input task
+    ///
+    /// # Behavior
+    /// - Started by the touch interrupt; further interrupts while it runs are
+    ///   dropped, the task follows the contact itself
+    /// - Dispatches a tap at the first contact point, then polls every
+    ///   `TOUCH_POLL_MS` until release and dispatches a hold once the contact
+    ///   lasted `TOUCH_HOLD_MS`
+    /// - Runs the matching `TouchAction` on the routed UART
+    #[cfg(feature = "touch")]
+    #[task(shared = [usart_6, usart_3, i2c_1], local = [touch], priority = 1)]
+    async fn touch_input(mut ctx: touch_input::Context) {
+        /// Steps one UART to the next touch baud rate
+        fn cycle_baud<U: BridgeUart>(usart: &mut impl rtic::Mutex<T = U>) {
+            let result = usart.lock(|usart| {
+                let baud = input::next_baud_rate(usart.baud_rate());
+                usart.set_baud_rate(baud)
+            });
+            if let Err(e) = result {
+                handle_error(e.into());
+            }
+        }
+
+        let Some(touch) = ctx.local.touch.as_mut() else {
+            return;
+        };
+        let (x, y) = match ctx.shared.i2c_1.lock(|bus| touch.read(bus)) {
+            Ok(Some(point)) => (point.x, point.y),
+            Ok(None) => return,
+            Err(e) => {
+                handle_error(e.into());
+                return;
+            }
+        };
+
+        let mut event = Some(InputEvent::Tap { x, y });
+        let mut held_ms = 0;
+        loop {
+            match event.take().and_then(input::dispatch) {
+                Some(TouchAction::ClearErrors) => {
+                    let _cleared = clear_error_queue();
+
+                    #[cfg(feature = "debug")]
+                    defmt::info!("Touch: {} error code(s) cleared", _cleared);
+                }
+                Some(TouchAction::CycleBaud) => match uart_route::current() {
+                    UartPort::Usart6 => cycle_baud(&mut ctx.shared.usart_6),
+                    UartPort::Usart3 => cycle_baud(&mut ctx.shared.usart_3),
+                },
+                Some(TouchAction::Reboot) => {
+                    orderly_shutdown::spawn(Restart::Application).ok();
+                }
+                None => {}
+            }
+
+            Mono::delay(TOUCH_POLL_MS.millis()).await;
+            match ctx.shared.i2c_1.lock(|bus| touch.read(bus)) {
+                Ok(Some(point)) if point.event != touch::TouchEvent::Up => {}
+                Ok(_) => return,
+                Err(e) => {
+                    handle_error(e.into());
+                    return;
+                }
+            }
+
+            if held_ms < TOUCH_HOLD_MS && held_ms + TOUCH_POLL_MS >= TOUCH_HOLD_MS {
+                event = Some(InputEvent::Hold { x, y });
+            }
+            held_ms += TOUCH_POLL_MS;
+        }
+    }
+
+    /// SD card log task
+    ///
+    /// # Behavior
+    /// - Every `SD_LOG_FLUSH_MS`, mounts a newly inserted card and appends the
+    ///   queued log lines to its log file
+    /// - Queues a traffic counter line every `SD_LOG_TRAFFIC_MS`
+    /// - A failed mount or write is reported once per card insertion
+    #[cfg(feature = "sd-log")]
+    #[task(local = [sd_card], priority = 1)]
+    async fn sd_logger(ctx: sd_logger::Context) {
+        let mut writer = SdLogWriter::new();
+        let mut traffic_ms = 0;
+
+        loop {
+            Mono::delay(SD_LOG_FLUSH_MS.millis()).await;
+
+            traffic_ms += SD_LOG_FLUSH_MS;
+            if traffic_ms >= SD_LOG_TRAFFIC_MS {
+                traffic_ms = 0;
+                sd_log::record_traffic();
+            }
+
+            if let Err(e) = writer.service(ctx.local.sd_card) {
+                handle_error(e.into());
+            }
+        }
+    }
+
+    /// I2C bus scan task
+    ///
+    /// # Behavior
+    /// - Started by `i2c scan` on the debug console
+    /// - Probes every 7-bit address on I2C1; `i2c` prints the result
+    /// - A bus failure ends the scan and is reported
+    #[cfg(feature = "i2c")]
+    #[task(shared = [i2c_1], priority = 1)]
+    async fn i2c_scan(mut ctx: i2c_scan::Context) {
+        match ctx.shared.i2c_1.lock(|bus| bus.scan()) {
+            Ok(_scan) => {
+                #[cfg(feature = "debug")]
+                defmt::info!("I2C scan: {} device(s)", _scan.count());
+            }
+            Err(e) => handle_error(e.into()),
+        }
+    }
+
+    /// USB-to-I2C request dispatcher
+    ///
+    /// # Behavior
+    /// - Spawned by the USB interrupt once it queued a request
+    /// - Runs the queued I2C transactions in order on I2C1, at priority 1
+    ///   since a transaction busy-waits on the bus
+    /// - Each response frame goes to the RX ring buffer and on to the host;
+    ///   a response that does not fit is counted as dropped
+    #[cfg(feature = "i2c-bridge")]
+    #[task(shared = [i2c_1, rx_producer], priority = 1)]
+    async fn i2c_bridge_dispatch(mut ctx: i2c_bridge_dispatch::Context) {
+        let mut frame = [0u8; i2c_bridge::RESPONSE_FRAME_LEN];
+        while let Some(len) = ctx.shared.i2c_1.lock(|bus| i2c_bridge::service(bus, &mut frame)) {
+            if ctx.shared.rx_producer.lock(|rx| rx.push(&frame[..len])).is_err() {
+                statistics::add_dropped(len);
+            }
+            ring_buffer_rx_to_serial::spawn().ok();
+        }
+    }
+
+    /// SLCAN command dispatcher
+    ///
+    /// # Behavior
+    /// - Spawned by the USB interrupt once it queued a command, and by the
+    ///   console when SLCAN mode is switched off
+    /// - Runs the queued commands in order on CAN1; mode changes busy-wait
+    ///   for the controller, so this runs below the data path
+    /// - Answers go to the RX ring buffer and on to the host, only while the
+    ///   mode is on
+    #[cfg(feature = "can")]
+    #[task(shared = [can_1, rx_producer], priority = 2)]
+    async fn slcan_dispatch(mut ctx: slcan_dispatch::Context) {
+        while let Some(answer) = ctx.shared.can_1.lock(slcan::service) {
+            if !slcan::is_enabled() {
+                continue;
+            }
+            if ctx.shared.rx_producer.lock(|rx| rx.push(&answer)).is_err() {
+                statistics::add_dropped(answer.len());
+            }
+            ring_buffer_rx_to_serial::spawn().ok();
+        }
+    }
+
+    /// CAN1 FIFO 0 interrupt handler
+    ///
+    /// # Behavior
+    /// - Drains the receive FIFO; in SLCAN mode each frame goes to the host
+    ///   as one line, otherwise it is dropped
+    /// - A line that does not fit in the RX ring buffer is dropped and
+    ///   reported as a data overrun by the next `F` command
+    #[cfg(feature = "can")]
+    #[task(binds = CAN1_RX0, shared = [can_1, rx_producer], priority = 3)]
+    fn can1_rx0(mut ctx: can1_rx0::Context) {
+        let mut forwarded = false;
+        while let Some(frame) = ctx.shared.can_1.lock(|can| can.receive()) {
+            let Some(line) = slcan::encode_received(&frame, Mono::now().ticks()) else {
+                continue;
+            };
+            if ctx.shared.rx_producer.lock(|rx| rx.push(&line)).is_err() {
+                statistics::add_dropped(line.len());
+                slcan::note_overrun();
+            }
+            forwarded = true;
+        }
+        if forwarded {
+            ring_buffer_rx_to_serial::spawn().ok();
+        }
+    }
+
+    /// SPI loopback test task
+    ///
+    /// # Behavior
+    /// - Started by `spi test` on the debug console
+    /// - Needs MOSI (PB15) jumpered to MISO (PB14); `spi` prints the result
+    /// - A mismatch only means the jumper is missing and is not recorded as
+    ///   an error; bus failures are
+    #[cfg(feature = "spi")]
+    #[task(shared = [spi_2], priority = 1)]
+    async fn spi_self_test(mut ctx: spi_self_test::Context) {
+        match ctx.shared.spi_2.lock(|spi| spi.loopback_test()) {
+            Ok(()) | Err(SpiError::LoopbackMismatch) => {}
+            Err(e) => handle_error(e.into()),
+        }
+    }
+
+    /// ADC sampler task
+    ///
+    /// # Behavior
+    /// - Scans the channels selected on the console every sampling period
+    ///   (`ADC_SAMPLE_MS` until changed), plus the internal sensors for the
+    ///   health monitor
+    /// - Each sample is kept for the `adc` report and queued for the stream
+    /// - A failed scan is reported and retried at the next period
+    #[cfg(feature = "adc")]
+    #[task(local = [adc_1], priority = 1)]
+    async fn adc_sampler(ctx: adc_sampler::Context) {
+        let adc = ctx.local.adc_1;
+
+        loop {
+            Mono::delay(adc_stream::period_ms().millis()).await;
+
+            let channels = adc_stream::scan_channels();
+            if adc.channels() != channels {
+                if let Err(e) = adc.set_channels(channels) {
+                    handle_error(e.into());
+                    continue;
+                }
+            }
+
+            match adc.scan(Mono::now().ticks()) {
+                Ok(sample) => adc_stream::record(&sample),
+                Err(e) => handle_error(e.into()),
+            }
+        }
+    }
+
+    /// DAC output task
+    ///
+    /// # Behavior
+    /// - Switches one DAC channel to the signal set on the console or with
+    ///   `AT+DAC=`; the other channel keeps running
+    /// - Both callers check the signal, so a rejection here is reported as
+    ///   an error
+    #[cfg(feature = "dac")]
+    #[task(local = [dac], priority = 1)]
+    async fn dac_output(ctx: dac_output::Context, channel: DacChannel, waveform: Waveform) {
+        if let Err(e) = ctx.local.dac.set_output(channel, waveform) {
+            handle_error(e.into());
+        }
+    }
+
+    /// Watchdog task
+    ///
+    /// # Behavior
+    /// - Every `IWDG_CHECK_MS`, feeds the IWDG if the USB, USART and LED tasks
+    ///   all checked in during the window
+    /// - A window with missing check-ins is counted and skipped; the board
+    ///   resets once the IWDG timeout passes without a feed
+    #[task(local = [iwdg], priority = 1)]
+    async fn watchdog(ctx: watchdog::Context) {
+        loop {
+            Mono::delay(IWDG_CHECK_MS.millis()).await;
+
+            if let Err(_missing) = ctx.local.iwdg.service() {
+                #[cfg(feature = "debug")]
+                defmt::warn!("Watchdog not fed, missing check-ins: {=u8:#x}", _missing.bits());
+            }
+        }
+    }
+
+    /// Window watchdog task
+    ///
+    /// # Behavior
+    /// - Starts the WWDG, then refreshes it every `WWDG_REFRESH_MS`
+    /// - A refresh due before the window opens is recorded as
+    ///   `WatchdogEarlyRefresh` first; the refresh then resets the board
+    /// - Priority 2, above the flash erases and other blocking work at
+    ///   priority 1
+    #[task(local = [wwdg], priority = 2)]
+    async fn window_watchdog(ctx: window_watchdog::Context) {
+        let wwdg = ctx.local.wwdg;
+        wwdg.start();
+        loop {
+            // Relative to the last refresh: a late refresh must not pull the
+            // next one into the closed window
+            Mono::delay(WWDG_REFRESH_MS.millis()).await;
+
+            if let Err(e) = wwdg.check_window() {
+                handle_error(e);
+            }
+            wwdg.refresh();
+        }
+    }
+
+    /// WWDG early wakeup, one counter tick before the reset
+    ///
+    /// Records `WatchdogTimeout` in the error store and the persistent error
+    /// log; the counter is not refreshed, so the reset follows.
+    #[task(binds = WWDG, priority = 7)]
+    fn wwdg_early_wakeup(_ctx: wwdg_early_wakeup::Context) {
+        let isr = IsrContext::enter();
+        if wwdg::take_early_wakeup() {
+            handle_isr_error(&isr, DeviceError::WatchdogTimeout);
+        }
+    }
+
+    /// AT command task
+    ///
+    /// # Behavior
+    /// - Runs one line received in AT command mode on the data port
+    /// - Commands act on the UART the data port is routed to
+    /// - Lets pending UART output drain before `AT+BAUD=` or `AT+FRAME=`
+    ///   switches the line
+    /// - Saves the settings to flash for `AT+SAVE`
+    /// - Hands `AT+DAC=` signals to the DAC output task
+    /// - Replies on the data port; `AT+RESET` starts the orderly shutdown
+    #[task(shared = [usart_6, usart_3, otg_fs, flash], priority = 1)]
+    async fn at_command(mut ctx: at_command::Context, line: command::Line) {
+        /// Runs a command on one UART, draining its output first if needed
+        async fn run<U: BridgeUart>(
+            usart: &mut impl rtic::Mutex<T = U>,
+            at: AtCommand,
+            reply: &mut heapless::String<AT_REPLY_LEN>,
+        ) -> Outcome {
+            const DRAIN_POLL_MS: u32 = 20;
+
+            if matches!(at, AtCommand::SetBaud(_) | AtCommand::SetFrame(_)) {
+                for _ in 0..DRAIN_POLL_MS {
+                    if usart.lock(|u| u.is_transmission_complete()) {
+                        break;
+                    }
+                    Mono::delay(1.millis()).await;
+                }
+            }
+            usart
+                .lock(|usart| command::execute(at, usart, reply))
+                .unwrap_or(Outcome::Done)
+        }
+
+        let mut reply: heapless::String<AT_REPLY_LEN> = heapless::String::new();
+        let mut outcome = match command::parse(&line) {
+            Some(at) => match uart_route::current() {
+                UartPort::Usart6 => run(&mut ctx.shared.usart_6, at, &mut reply).await,
+                UartPort::Usart3 => run(&mut ctx.shared.usart_3, at, &mut reply).await,
+            },
+            None => {
+                reply.push_str(command::ERROR_REPLY).ok();
+                Outcome::Done
+            }
+        };
+
+        if outcome == Outcome::Save {
+            let current = Settings::current(
+                ctx.shared.usart_6.lock(|usart| usart.baud_rate()),
+                ctx.shared.usart_3.lock(|usart| usart.baud_rate()),
+            );
+            let result = match ctx.shared.flash.lock(|flash| settings::save(flash, &current)) {
+                Ok(_) => "OK\r\n",
+                Err(e) => {
+                    handle_error(e.into());
+                    command::ERROR_REPLY
+                }
+            };
+            reply.push_str(result).ok();
+            outcome = Outcome::Done;
+        }
+
+        #[cfg(feature = "dac")]
+        if let Outcome::Dac(channel, waveform) = outcome {
+            let result = match dac_output::spawn(channel, waveform) {
+                Ok(()) => "OK\r\n",
+                Err(_) => command::ERROR_REPLY,
+            };
+            reply.push_str(result).ok();
+            outcome = Outcome::Done;
+        }
+
+        if let Err(e) = ctx.shared.otg_fs.lock(|usb| usb.write(reply.as_bytes())) {
+            handle_error(e.into());
+        }
+
+        if outcome == Outcome::Reset {
+            orderly_shutdown::spawn(Restart::Application).ok();
+        }
+    }
+
+    /// Host line coding task
+    ///
+    /// # Behavior
+    /// - Applies the data bits, parity and stop bits of a SET_LINE_CODING to
+    ///   the routed UART; the rate stays under `AT+BAUD=` and console control
+    /// - Lets pending UART output drain first
+    /// - Ignored while the target probe owns the UART configuration
+    #[task(shared = [usart_6, usart_3], priority = 1)]
+    async fn apply_line_coding(mut ctx: apply_line_coding::Context, config: UartConfig) {
+        /// Reconfigures one UART once its output has drained
+        async fn apply<U: BridgeUart>(usart: &mut impl rtic::Mutex<T = U>, config: UartConfig) {
+            const DRAIN_POLL_MS: u32 = 20;
+
+            if usart.lock(|usart| usart.uart_config()) == config {
+                return;
+            }
+
+            for _ in 0..DRAIN_POLL_MS {
+                if usart.lock(|u| u.is_transmission_complete()) {
+                    break;
+                }
+                Mono::delay(1.millis()).await;
+            }
+            if let Err(e) = usart.lock(|usart| usart.reconfigure(config)) {
+                handle_error(e.into());
+            }
+        }
+
+        if target_probe::is_active() {
+            return;
+        }
+        #[cfg(feature = "modbus")]
+        if modbus::is_active() {
+            return;
+        }
+        match uart_route::current() {
+            UartPort::Usart6 => apply(&mut ctx.shared.usart_6, config).await,
+            UartPort::Usart3 => apply(&mut ctx.shared.usart_3, config).await,
+        }
+    }
+
+    /// UART break task
+    ///
+    /// # Behavior
+    /// - Holds the routed UART TX line low for a host SEND_BREAK: for the
+    ///   requested time, or until the request that ends it
+    /// - Waits up to `BREAK_DRAIN_MS` for the UART output to drain; no new
+    ///   transfer starts meanwhile
+    /// - A request posted during a break ends it and is served next
+    /// - Restarts the UART TX path once the line is released
+    #[task(shared = [usart_6, usart_3], priority = 1)]
+    async fn uart_break(mut ctx: uart_break::Context) {
+        /// Holds the break once the UART output drained
+        async fn hold<U: BridgeUart>(usart: &mut impl rtic::Mutex<T = U>) -> bool {
+            for _ in 0..BREAK_DRAIN_MS {
+                if usart.lock(|usart| usart.start_break()) {
+                    return true;
+                }
+                Mono::delay(1.millis()).await;
+            }
+            usart.lock(|usart| usart.end_break());
+            false
+        }
+
+        while let Some(request) = line_break::take() {
+            // Also releases a break left on a UART routed away since
+            ctx.shared.usart_6.lock(|usart| usart.end_break());
+            ctx.shared.usart_3.lock(|usart| usart.end_break());
+
+            let duration_ms = match request {
+                BreakRequest::Stop => continue,
+                BreakRequest::Start => None,
+                BreakRequest::Timed(ms) => Some(ms),
+            };
+            let held = match uart_route::current() {
+                UartPort::Usart6 => hold(&mut ctx.shared.usart_6).await,
+                UartPort::Usart3 => hold(&mut ctx.shared.usart_3).await,
+            };
+            if !held {
+                line_break::record_failed();
+                continue;
+            }
+            line_break::record_break();
+
+            let Some(duration_ms) = duration_ms else {
+                continue;
+            };
+            for _ in 0..duration_ms {
+                if line_break::is_pending() {
+                    break;
+                }
+                Mono::delay(1.millis()).await;
+            }
+            if !line_break::is_pending() {
+                ctx.shared.usart_6.lock(|usart| usart.end_break());
+                ctx.shared.usart_3.lock(|usart| usart.end_break());
+            }
+        }
+
+        ring_buffer_tx_to_usart_dma::spawn(0).ok();
+    }
+
+    /// Orderly shutdown before a restart, see `task_handlers::shutdown`
+    ///
+    /// # Behavior
+    /// - Waits `SHUTDOWN_REPLY_MS` so the host collects the reply to the request
+    /// - Lets the bridge drain for up to `SHUTDOWN_DRAIN_MS`: host data to the
+    ///   UART, UART data to the host
+    /// - Stops the UART DMA streams, takes both UARTs off the line and
+    ///   detaches from the USB bus for `SHUTDOWN_DETACH_MS`
+    /// - Stops the other DMA users, turns the outputs off and resets, never
+    ///   returns
+    #[task(
+        shared = [usart_6, usart_3, otg_fs, rx_consumer, tx_consumer, tx_staging],
+        priority = 1
+    )]
+    async fn orderly_shutdown(mut ctx: orderly_shutdown::Context, restart: Restart) {
+        const DRAIN_POLL_MS: u32 = 10;
+
+        Mono::delay(SHUTDOWN_REPLY_MS.millis()).await;
+        for _ in 0..SHUTDOWN_DRAIN_MS / DRAIN_POLL_MS {
+            let drained = ctx.shared.rx_consumer.lock(|rx| rx.is_empty())
+                && ctx.shared.tx_consumer.lock(|tx| tx.is_empty())
+                && ctx.shared.tx_staging.lock(|staging| staging.is_empty())
+                && ctx.shared.usart_6.lock(|usart| usart.is_transmission_complete())
+                && ctx.shared.usart_3.lock(|usart| usart.is_transmission_complete());
+            if drained {
+                break;
+            }
+            Mono::delay(DRAIN_POLL_MS.millis()).await;
+        }
+
+        let usart_3 = &mut ctx.shared.usart_3;
+        let otg_fs = &mut ctx.shared.otg_fs;
+        ctx.shared.usart_6.lock(|usart_6| {
+            usart_3.lock(|usart_3| otg_fs.lock(|usb| shutdown::stop_bridge(usart_6, usart_3, usb)))
+        });
+        Mono::delay(SHUTDOWN_DETACH_MS.millis()).await;
+
+        shutdown::finish(restart);
+    }
+
+    /// Error code visualization task
+    ///
+    /// Plays on the red LED, the buzzer or both, as selected in `signal_handler`.
+    ///
+    /// # Display Protocol
+    /// - Each error code is one Morse word: its mnemonic, or its digits,
+    ///   separated by letter gaps
+    /// - Pending codes are chained into one run with word gaps, critical first
+    /// - 500ms poll interval while no code is pending or playing
+    #[task(shared = [red_led, is_red_led_active], local = [buzzer], priority = 5)]
+    async fn task_display_error_codes(mut ctx: task_display_error_codes::Context) {
+        let mut buffer = [0u8; 100];
+
+        loop {
+            iwdg::check_in(CheckIn::LED);
+            let playing = ctx.shared.red_led.lock(|red_led| red_led.is_playing());
+            if !playing && !has_errors() {
                 ctx.shared.is_red_led_active.lock(|active| *active = false);
                 Mono::delay(500.millis()).await;
                 continue;
@@ -368,11 +2738,49 @@ mod app {
             ctx.shared.is_red_led_active.lock(|active| *active = true);
 
             let current_time = Mono::now().ticks();
-            ctx.shared.red_led.lock(|red_led| {
-                update_red_led(red_led, current_time, &mut buffer);
+            budget::measure(Task::ErrorDisplay, || {
+                ctx.shared.red_led.lock(|red_led| {
+                    let mut signal = Signal {
+                        led: red_led,
+                        buzzer: ctx.local.buzzer,
+                    };
+                    update_signal(&mut signal, current_time, &mut buffer);
+                })
             });
 
-            ctx.shared.is_red_led_active.lock(|active| *active = false);
+            Mono::delay(MORSE_UPDATE_MS.millis()).await;
+        }
+    }
+
+    /// Spawns the task that takes host data to its sink in the bridge mode
+    ///
+    /// # Parameters
+    /// - `bytes_processed`: Number of bytes just received from the host
+    fn forward_host_data(bytes_processed: usize) {
+        match bridge_mode::routes().host_data {
+            Sink::Uart => {
+                #[cfg(feature = "tx-seq-check")]
+                data_structures::tx_sequence::tag(bytes_processed);
+
+                ring_buffer_tx_to_usart_dma::spawn(bytes_processed).ok();
+            }
+            Sink::Usb => {
+                echo_data::spawn().ok();
+            }
+            Sink::Discard => {}
+        }
+    }
+
+    /// Spawns the task that takes UART data to its sink in the bridge mode
+    fn forward_uart_data() {
+        match bridge_mode::routes().uart_data {
+            Sink::Usb => {
+                ring_buffer_rx_to_serial::spawn().ok();
+            }
+            Sink::Uart => {
+                echo_data::spawn().ok();
+            }
+            Sink::Discard => {}
         }
     }
 }
@@ -380,15 +2788,43 @@ mod app {
 /// Central error handling facility
 ///
 /// # Error Handling Flow
-/// 1. Log error to debug output (if enabled)
-/// 2. Add error code to persistent queue
+/// 1. Log the precise cause to debug output (if enabled): the source error
+///    of its domain, or the device condition
+/// 2. Record error code and severity in the error store; a wrapped error
+///    keeps the code of its domain
 /// 3. Trigger error visualization task
+#[cfg(target_os = "none")]
 fn handle_error(error: DeviceError) {
     #[cfg(feature = "debug")]
-    log_error(error.description());
+    log_error(&error);
+    usb_log!(
+        error,
+        "{:?}: {} ({} {})",
+        error,
+        error.description(),
+        crate::errors::errors::domain::name(error.code()).unwrap_or("unknown"),
+        error.code()
+    );
 
-    if add_error_code(error.code()).is_err() {
+    if add_error(error.code(), error.severity()).is_err() {
         #[cfg(feature = "debug")]
-        defmt::error!("Error queue overflow - code: {}", error.code());
+        defmt::error!("Error store overflow - code: {}", error.code());
     }
 }
+
+/// Central error handling facility for hardware interrupt handlers
+///
+/// As `handle_error`, but without RTT writes: the error is logged as a
+/// deferred record of its code (`isr_log!`), printed later from `idle`.
+#[cfg(target_os = "none")]
+fn handle_isr_error(isr: &IsrContext, error: DeviceError) {
+    isr_log!(isr, error, "Device error, code", error.code());
+
+    if add_error(error.code(), error.severity()).is_err() {
+        isr_log!(isr, error, "Error store overflow - code", error.code());
+    }
+}
+
+/// Host builds only carry the library, for the test suite in `tests/`
+#[cfg(not(target_os = "none"))]
+fn main() {}
@@ -0,0 +1,608 @@
+//! # ADC
+//!
+//! ADC1 scanning a selectable set of channels, driven through the registers:
+//! - The six Arduino analog inputs, VREFINT and the die temperature sensor
+//! - One software-started scan per sample; DMA2 stream 0 moves the results,
+//!   the CPU only polls the stream flags, bounded by `ADC_TIMEOUT_US`
+//! - Readings are converted with the factory calibration values: VREFINT
+//!   gives the actual VDDA, which scales the other channels
+//! - The most recent sample is kept for reports and health checks
+//!
+//! ## Hardware Configuration
+//! - A0 PB1 (IN9), A1 PC2 (IN12), A2 PC3 (IN13), A3 PC4 (IN14), A4 PC5
+//!   (IN15), A5 PA4 (IN4), in analog mode
+//! - VREFINT on IN17, temperature sensor on IN18 (VBAT sensing stays off)
+//! - ADC clock `PCLK2` / 4 = 22.5 MHz; 144 cycles per external channel,
+//!   480 for the internal ones, whose sensors need 10 us of sampling
+//!
+//! ## Safety Considerations
+//! - Scans busy-wait for about 25 us per channel: run them from a
+//!   low-priority task
+//! - DMA2 stream 0 is reserved for ADC1
+
+use crate::config::{ADC_TIMEOUT_US, SYSCLK};
+use crate::errors::errors::AdcError;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use stm32f4xx_hal::{
+    dma::Stream0,
+    gpio::{
+        gpioa::PA4,
+        gpiob::PB1,
+        gpioc::{PC2, PC3, PC4, PC5},
+        Analog,
+    },
+    pac::{self, ADC1, DMA2},
+};
+
+/// Number of selectable channels
+pub const CHANNEL_COUNT: usize = 8;
+
+/// ADC1 enable and reset bit in RCC APB2ENR and APB2RSTR
+const RCC_ADC: u32 = 1 << 8;
+
+/// CR1 scan mode
+const CR1_SCAN: u32 = 1 << 8;
+
+/// CR2 bits: converter on, DMA requests, software start
+const CR2_ADON: u32 = 1 << 0;
+const CR2_DMA: u32 = 1 << 8;
+const CR2_SWSTART: u32 = 1 << 30;
+
+/// SR bits: end of conversion, conversion started, overrun
+const SR_EOC: u32 = 1 << 1;
+const SR_STRT: u32 = 1 << 4;
+const SR_OVR: u32 = 1 << 5;
+
+/// Common CCR: ADC clock `PCLK2` / 4, temperature sensor and VREFINT on
+const CCR_ADCPRE_DIV4: u32 = 0b01 << 16;
+const CCR_TSVREFE: u32 = 1 << 23;
+
+/// SQR1 sequence length field
+const SQR1_L_SHIFT: u32 = 20;
+
+/// Sample time codes: 144 and 480 ADC cycles
+const SMP_144: u32 = 0b110;
+const SMP_480: u32 = 0b111;
+
+/// DMA stream of the ADC1 request (channel 0)
+const DMA_STREAM: usize = 0;
+
+/// DMA SxCR bits: enable, memory increment, half-word sizes, medium priority
+const DMA_CR_EN: u32 = 1 << 0;
+const DMA_CR_MINC: u32 = 1 << 10;
+const DMA_CR_PSIZE_16: u32 = 0b01 << 11;
+const DMA_CR_MSIZE_16: u32 = 0b01 << 13;
+const DMA_CR_PL_MEDIUM: u32 = 0b01 << 16;
+
+/// Stream 0 flags in LISR and LIFCR: FIFO, direct mode, transfer error,
+/// half and full transfer
+const DMA_FEIF0: u32 = 1 << 0;
+const DMA_DMEIF0: u32 = 1 << 2;
+const DMA_TEIF0: u32 = 1 << 3;
+const DMA_HTIF0: u32 = 1 << 4;
+const DMA_TCIF0: u32 = 1 << 5;
+const DMA_FLAGS0: u32 = DMA_FEIF0 | DMA_DMEIF0 | DMA_TEIF0 | DMA_HTIF0 | DMA_TCIF0;
+
+/// Converter and temperature sensor start-up time (microseconds)
+const STARTUP_US: u32 = 10;
+
+/// Busy-wait step of timeouts (microseconds)
+const WAIT_STEP_US: u32 = 1;
+
+/// Full scale of a 12-bit conversion
+const FULL_SCALE: u32 = 4095;
+
+/// Factory calibration (RM0386, device electronic signature): VREFINT at
+/// VDDA = 3.3 V, temperature sensor at 30 and 110 degrees C
+const VREFINT_CAL: *const u16 = 0x1FFF_7A2A as *const u16;
+const TS_CAL1: *const u16 = 0x1FFF_7A2C as *const u16;
+const TS_CAL2: *const u16 = 0x1FFF_7A2E as *const u16;
+
+/// VDDA of the calibration values, also assumed without a VREFINT reading
+const CAL_VDDA_MV: u32 = 3_300;
+
+/// Temperatures of the calibration points (degrees C)
+const TS_CAL1_C: i32 = 30;
+const TS_CAL2_C: i32 = 110;
+
+/// Completed scans and failed scans
+static SCANS: AtomicU32 = AtomicU32::new(0);
+static ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// Most recent completed sample
+static LAST_SAMPLE: Mutex<RefCell<Option<AdcSample>>> = Mutex::new(RefCell::new(None));
+
+/// Analog input pins: A0 to A5
+pub type AdcPins = (
+    PB1<Analog>,
+    PC2<Analog>,
+    PC3<Analog>,
+    PC4<Analog>,
+    PC5<Analog>,
+    PA4<Analog>,
+);
+
+/// Channel of a scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdcChannel {
+    /// Arduino A0 (PB1)
+    A0,
+    /// Arduino A1 (PC2)
+    A1,
+    /// Arduino A2 (PC3)
+    A2,
+    /// Arduino A3 (PC4)
+    A3,
+    /// Arduino A4 (PC5)
+    A4,
+    /// Arduino A5 (PA4)
+    A5,
+    /// Internal 1.21 V reference, measures VDDA
+    Vrefint,
+    /// Die temperature sensor
+    Temperature,
+}
+
+impl AdcChannel {
+    /// All channels in scan order
+    pub const ALL: [AdcChannel; CHANNEL_COUNT] = [
+        AdcChannel::A0,
+        AdcChannel::A1,
+        AdcChannel::A2,
+        AdcChannel::A3,
+        AdcChannel::A4,
+        AdcChannel::A5,
+        AdcChannel::Vrefint,
+        AdcChannel::Temperature,
+    ];
+
+    /// Position in `ALL`
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// ADC input number
+    fn input(self) -> u32 {
+        match self {
+            AdcChannel::A0 => 9,
+            AdcChannel::A1 => 12,
+            AdcChannel::A2 => 13,
+            AdcChannel::A3 => 14,
+            AdcChannel::A4 => 15,
+            AdcChannel::A5 => 4,
+            AdcChannel::Vrefint => 17,
+            AdcChannel::Temperature => 18,
+        }
+    }
+
+    /// Sample time code; the internal sensors need the longest
+    fn sample_time(self) -> u32 {
+        match self {
+            AdcChannel::Vrefint | AdcChannel::Temperature => SMP_480,
+            _ => SMP_144,
+        }
+    }
+
+    /// Name used by the console and the CSV header
+    pub fn name(self) -> &'static str {
+        match self {
+            AdcChannel::A0 => "a0",
+            AdcChannel::A1 => "a1",
+            AdcChannel::A2 => "a2",
+            AdcChannel::A3 => "a3",
+            AdcChannel::A4 => "a4",
+            AdcChannel::A5 => "a5",
+            AdcChannel::Vrefint => "vref",
+            AdcChannel::Temperature => "temp",
+        }
+    }
+
+    /// Channel with the given console name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|channel| channel.name() == name)
+    }
+}
+
+/// Set of channels, bit n = `AdcChannel::ALL[n]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelSet(u8);
+
+impl ChannelSet {
+    /// No channels
+    pub const EMPTY: Self = Self(0);
+    /// Every channel
+    pub const ALL: Self = Self(u8::MAX);
+    /// VREFINT and the temperature sensor
+    pub const INTERNAL: Self = Self((1 << 6) | (1 << 7));
+
+    /// Set from its bit mask
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// Bit mask of the set
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Set with `channel` added
+    pub fn with(self, channel: AdcChannel) -> Self {
+        Self(self.0 | (1 << channel.index()))
+    }
+
+    /// Union of two sets
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Channels in both sets
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Checks whether `channel` is in the set
+    pub fn contains(self, channel: AdcChannel) -> bool {
+        self.0 & (1 << channel.index()) != 0
+    }
+
+    /// Number of channels in the set
+    pub fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Checks whether the set is empty
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Channels of the set in scan order
+    pub fn iter(self) -> impl Iterator<Item = AdcChannel> {
+        AdcChannel::ALL
+            .into_iter()
+            .filter(move |&channel| self.contains(channel))
+    }
+}
+
+/// Raw results of one scan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdcSample {
+    /// Monotonic time of the scan (milliseconds)
+    pub timestamp_ms: u32,
+    /// Channels converted
+    pub channels: ChannelSet,
+    /// 12-bit results by `AdcChannel` position, 0 for channels not converted
+    raw: [u16; CHANNEL_COUNT],
+}
+
+impl AdcSample {
+    /// 12-bit result of a channel
+    pub fn raw(&self, channel: AdcChannel) -> Option<u16> {
+        self.channels
+            .contains(channel)
+            .then_some(self.raw[channel.index()])
+    }
+
+    /// Analog supply from the VREFINT reading (millivolts)
+    pub fn vdda_mv(&self) -> Option<u32> {
+        let raw = u32::from(self.raw(AdcChannel::Vrefint)?);
+        // SAFETY: Factory calibration value in system memory, always readable
+        let cal = u32::from(unsafe { VREFINT_CAL.read_volatile() });
+        (raw != 0).then(|| CAL_VDDA_MV * cal / raw)
+    }
+
+    /// Voltage at a channel input (millivolts), scaled by the measured VDDA
+    /// when VREFINT is in the sample
+    pub fn millivolts(&self, channel: AdcChannel) -> Option<u32> {
+        let raw = u32::from(self.raw(channel)?);
+        let vdda = self.vdda_mv().unwrap_or(CAL_VDDA_MV);
+        Some(raw * vdda / FULL_SCALE)
+    }
+
+    /// Die temperature (hundredths of a degree C), from the two-point
+    /// factory calibration
+    pub fn temperature_centi(&self) -> Option<i32> {
+        let raw = u32::from(self.raw(AdcChannel::Temperature)?);
+        // The calibration values were taken at 3.3 V: rescale the reading
+        let vdda = self.vdda_mv().unwrap_or(CAL_VDDA_MV);
+        let raw = (raw * vdda / CAL_VDDA_MV) as i32;
+        // SAFETY: Factory calibration values in system memory, always readable
+        let (cal1, cal2) = unsafe {
+            (
+                i32::from(TS_CAL1.read_volatile()),
+                i32::from(TS_CAL2.read_volatile()),
+            )
+        };
+        if cal2 <= cal1 {
+            return None;
+        }
+        Some(TS_CAL1_C * 100 + (raw - cal1) * (TS_CAL2_C - TS_CAL1_C) * 100 / (cal2 - cal1))
+    }
+}
+
+/// ADC1 with DMA scans
+pub struct Adc1 {
+    adc: ADC1,
+    _pins: AdcPins,
+    _dma: Stream0<DMA2>,
+    channels: ChannelSet,
+    /// DMA destination, results in sequence order
+    buffer: [u16; CHANNEL_COUNT],
+}
+
+impl Adc1 {
+    /// Powers up ADC1 with the internal sensors on; every channel is
+    /// selected
+    ///
+    /// # Arguments
+    /// * `adc` - ADC1 peripheral
+    /// * `pins` - A0 to A5 in analog mode
+    /// * `dma` - DMA2 stream 0, reserved for the scan results
+    pub fn new(adc: ADC1, pins: AdcPins, dma: Stream0<DMA2>) -> Self {
+        // SAFETY: Only the ADC bit in the APB2 enable and reset registers is
+        // touched; the common registers serve ADC1 alone in this firmware
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            rcc.apb2enr().modify(|r, w| w.bits(r.bits() | RCC_ADC));
+            rcc.apb2rstr().modify(|r, w| w.bits(r.bits() | RCC_ADC));
+            rcc.apb2rstr().modify(|r, w| w.bits(r.bits() & !RCC_ADC));
+
+            let common = &*pac::ADC_COMMON::ptr();
+            common
+                .ccr()
+                .write(|w| w.bits(CCR_ADCPRE_DIV4 | CCR_TSVREFE));
+        }
+
+        let mut sample_times = (0u32, 0u32);
+        for channel in AdcChannel::ALL {
+            let input = channel.input();
+            if input >= 10 {
+                sample_times.0 |= channel.sample_time() << ((input - 10) * 3);
+            } else {
+                sample_times.1 |= channel.sample_time() << (input * 3);
+            }
+        }
+        // SAFETY: The converter is off; sample times and scan mode are
+        // static configuration
+        unsafe {
+            adc.smpr1().write(|w| w.bits(sample_times.0));
+            adc.smpr2().write(|w| w.bits(sample_times.1));
+            adc.cr1().write(|w| w.bits(CR1_SCAN));
+            adc.cr2().write(|w| w.bits(CR2_ADON));
+        }
+        busy_wait_us(STARTUP_US);
+
+        let mut adc = Self {
+            adc,
+            _pins: pins,
+            _dma: dma,
+            channels: ChannelSet::EMPTY,
+            buffer: [0; CHANNEL_COUNT],
+        };
+        adc.set_channels(ChannelSet::ALL).ok();
+        adc
+    }
+
+    /// Selects the channels of the next scans
+    ///
+    /// # Errors
+    /// `NoChannels` for an empty set
+    pub fn set_channels(&mut self, channels: ChannelSet) -> Result<(), AdcError> {
+        if channels.is_empty() {
+            return Err(AdcError::NoChannels);
+        }
+
+        let mut sqr = [0u32; 3];
+        for (position, channel) in channels.iter().enumerate() {
+            // SQ1-SQ6 in SQR3, SQ7-SQ12 in SQR2
+            let (register, slot) = (2 - position / 6, position % 6);
+            sqr[register] |= channel.input() << (slot * 5);
+        }
+        sqr[0] |= (channels.len() as u32 - 1) << SQR1_L_SHIFT;
+
+        // SAFETY: No scan is running; the sequence registers take effect at
+        // the next software start
+        unsafe {
+            self.adc.sqr1().write(|w| w.bits(sqr[0]));
+            self.adc.sqr2().write(|w| w.bits(sqr[1]));
+            self.adc.sqr3().write(|w| w.bits(sqr[2]));
+        }
+        self.channels = channels;
+        Ok(())
+    }
+
+    /// Channels of the scans
+    pub fn channels(&self) -> ChannelSet {
+        self.channels
+    }
+
+    /// Converts every selected channel once
+    ///
+    /// The sample is kept for `last_sample`.
+    ///
+    /// # Arguments
+    /// * `timestamp_ms` - Monotonic time stamped on the sample
+    ///
+    /// # Errors
+    /// `Timeout`, `Overrun` or `Dma` if the scan did not complete
+    pub fn scan(&mut self, timestamp_ms: u32) -> Result<AdcSample, AdcError> {
+        let result = self.run_scan();
+        if result.is_err() {
+            ERRORS.fetch_add(1, Ordering::Relaxed);
+            self.stop();
+        }
+        result?;
+
+        let mut raw = [0u16; CHANNEL_COUNT];
+        for (value, channel) in self.buffer.iter().zip(self.channels.iter()) {
+            raw[channel.index()] = *value;
+        }
+        let sample = AdcSample {
+            timestamp_ms,
+            channels: self.channels,
+            raw,
+        };
+        SCANS.fetch_add(1, Ordering::Relaxed);
+        interrupt::free(|cs| *LAST_SAMPLE.borrow(cs).borrow_mut() = Some(sample));
+        Ok(sample)
+    }
+
+    fn run_scan(&mut self) -> Result<(), AdcError> {
+        let count = self.channels.len();
+        // SAFETY: Stream 0 is reserved by `_dma`. `buffer` outlives the
+        // transfer: the stream is stopped before this function returns.
+        unsafe {
+            let dma = &*DMA2::ptr();
+            let stream = dma.st(DMA_STREAM);
+
+            stream.cr().write(|w| w.bits(0));
+            while stream.cr().read().bits() & DMA_CR_EN != 0 {}
+            dma.lifcr().write(|w| w.bits(DMA_FLAGS0));
+
+            stream
+                .par()
+                .write(|w| w.bits(self.adc.dr().as_ptr() as u32));
+            stream
+                .m0ar()
+                .write(|w| w.bits(self.buffer.as_mut_ptr() as u32));
+            stream.ndtr().write(|w| w.bits(count as u32));
+            // Channel 0, peripheral to memory, direct mode
+            stream.cr().write(|w| {
+                w.bits(DMA_CR_MINC | DMA_CR_PSIZE_16 | DMA_CR_MSIZE_16 | DMA_CR_PL_MEDIUM)
+            });
+            compiler_fence(Ordering::SeqCst);
+            stream.cr().modify(|r, w| w.bits(r.bits() | DMA_CR_EN));
+
+            // DMA requests end after the last transfer; toggling DMA re-arms them
+            self.adc.sr().write(|w| w.bits(0));
+            self.adc.cr2().write(|w| w.bits(CR2_ADON));
+            self.adc.cr2().write(|w| w.bits(CR2_ADON | CR2_DMA));
+            self.adc
+                .cr2()
+                .write(|w| w.bits(CR2_ADON | CR2_DMA | CR2_SWSTART));
+        }
+
+        let mut waited_us = 0;
+        let result = loop {
+            // SAFETY: Read-only access to the DMA2 status register
+            let flags = unsafe { (*DMA2::ptr()).lisr().read().bits() };
+            if flags & DMA_TEIF0 != 0 {
+                break Err(AdcError::Dma);
+            }
+            if flags & DMA_TCIF0 != 0 {
+                break Ok(());
+            }
+            if self.adc.sr().read().bits() & SR_OVR != 0 {
+                break Err(AdcError::Overrun);
+            }
+            if waited_us >= ADC_TIMEOUT_US {
+                break Err(AdcError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        };
+        compiler_fence(Ordering::SeqCst);
+        self.stop();
+        result
+    }
+
+    /// Stops the DMA stream and clears the converter flags
+    fn stop(&mut self) {
+        // SAFETY: Stream 0 is reserved by `_dma`; the converter stays on
+        unsafe {
+            let dma = &*DMA2::ptr();
+            dma.st(DMA_STREAM).cr().write(|w| w.bits(0));
+            dma.lifcr().write(|w| w.bits(DMA_FLAGS0));
+            self.adc.cr2().write(|w| w.bits(CR2_ADON));
+            self.adc
+                .sr()
+                .modify(|r, w| w.bits(r.bits() & !(SR_EOC | SR_STRT | SR_OVR)));
+        }
+    }
+}
+
+/// Stops the DMA stream without the driver
+///
+/// For the shutdown (`task_handlers::shutdown::quiesce`); a scan in progress
+/// is abandoned, the converter stays on. Stop entry needs no such step:
+/// `scan` stops the stream before it returns.
+pub fn force_stop() {
+    // SAFETY: Stream 0 is reserved for ADC1; only the writes of `Adc::stop`,
+    // and the caller keeps the driver from running
+    unsafe {
+        let dma = &*DMA2::ptr();
+        dma.st(DMA_STREAM).cr().write(|w| w.bits(0));
+        dma.lifcr().write(|w| w.bits(DMA_FLAGS0));
+        (*ADC1::ptr()).cr2().write(|w| w.bits(CR2_ADON));
+    }
+}
+
+fn busy_wait_us(us: u32) {
+    cortex_m::asm::delay(SYSCLK / 1_000_000 * us);
+}
+
+/// Most recent completed sample
+pub fn last_sample() -> Option<AdcSample> {
+    interrupt::free(|cs| *LAST_SAMPLE.borrow(cs).borrow())
+}
+
+/// Writes one channel reading: millivolts, VDDA for VREFINT, degrees C for
+/// the temperature sensor
+pub fn write_reading<W: Write>(
+    out: &mut W,
+    sample: &AdcSample,
+    channel: AdcChannel,
+) -> fmt::Result {
+    match channel {
+        AdcChannel::Temperature => match sample.temperature_centi() {
+            Some(centi) => write!(
+                out,
+                "{}{}.{:02}",
+                if centi < 0 { "-" } else { "" },
+                centi.unsigned_abs() / 100,
+                centi.unsigned_abs() % 100
+            ),
+            None => out.write_str("-"),
+        },
+        AdcChannel::Vrefint => match sample.vdda_mv() {
+            Some(mv) => write!(out, "{}", mv),
+            None => out.write_str("-"),
+        },
+        _ => match sample.millivolts(channel) {
+            Some(mv) => write!(out, "{}", mv),
+            None => out.write_str("-"),
+        },
+    }
+}
+
+/// Writes the scan counters and the last sample
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "ADC1: {} scans, {} errors",
+        SCANS.load(Ordering::Relaxed),
+        ERRORS.load(Ordering::Relaxed)
+    )?;
+    let Some(sample) = last_sample() else {
+        return writeln!(out, "  no sample yet");
+    };
+    writeln!(out, "  sample at {} ms:", sample.timestamp_ms)?;
+    for channel in sample.channels.iter() {
+        write!(
+            out,
+            "    {:<5} {:>4}  ",
+            channel.name(),
+            sample.raw[channel.index()]
+        )?;
+        write_reading(out, &sample, channel)?;
+        let unit = match channel {
+            AdcChannel::Temperature => " C",
+            AdcChannel::Vrefint => " mV VDDA",
+            _ => " mV",
+        };
+        writeln!(out, "{}", unit)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! # Backup SRAM
+//!
+//! 4 KB of SRAM in the backup domain that keeps its contents across system
+//! resets (and, with the backup regulator on, while only VBAT is supplied):
+//! - `enable` turns on the interface clock, backup domain write access and
+//!   the backup regulator
+//! - Word-wise volatile accessors with bounds checks
+//!
+//! ## Hardware Configuration
+//! - Mapped at 0x4002_4000 on AHB1
+//! - Contents are undefined after a power-on without VBAT; users must validate
+//!   their data (magic, checksum) before trusting it
+//!
+//! ## Safety Considerations
+//! - PWR is only touched to set CR.DBP and CSR.BRE
+//! - Regions are assigned statically; see `BACKUP_SRAM_ERROR_LOG_OFFSET` in `config`
+
+use stm32f4xx_hal::pac;
+
+/// Start of the backup SRAM
+const BKPSRAM_BASE: usize = 0x4002_4000;
+
+/// Backup SRAM size in bytes
+pub const BACKUP_SRAM_SIZE: usize = 4_096;
+
+/// Polling limit for the backup regulator ready flag
+const BRR_POLL_LIMIT: u32 = 100_000;
+
+/// Enables access to the backup SRAM
+///
+/// Safe to call more than once.
+///
+/// # Returns
+/// `true` if the backup regulator is ready, i.e. contents also survive on VBAT
+pub fn enable() -> bool {
+    // SAFETY: Only enable bits are set; RCC/PWR fields touched here are not
+    // owned by any HAL driver
+    unsafe {
+        let rcc = &*pac::RCC::ptr();
+        let pwr = &*pac::PWR::ptr();
+
+        rcc.apb1enr().modify(|_, w| w.pwren().set_bit());
+        pwr.cr().modify(|_, w| w.dbp().set_bit());
+        rcc.ahb1enr().modify(|_, w| w.bkpsramen().set_bit());
+
+        pwr.csr().modify(|_, w| w.bre().set_bit());
+        (0..BRR_POLL_LIMIT).any(|_| pwr.csr().read().brr().bit_is_set())
+    }
+}
+
+/// Address of a backup SRAM word
+fn word(offset: usize) -> *mut u32 {
+    assert!(offset % 4 == 0 && offset + 4 <= BACKUP_SRAM_SIZE);
+    (BKPSRAM_BASE + offset) as *mut u32
+}
+
+/// Reads a word
+///
+/// # Arguments
+/// * `offset` - Byte offset, word aligned and below `BACKUP_SRAM_SIZE`
+pub fn read_word(offset: usize) -> u32 {
+    // SAFETY: Aligned read inside the backup SRAM, enabled by `enable`
+    unsafe { core::ptr::read_volatile(word(offset)) }
+}
+
+/// Writes a word
+///
+/// # Arguments
+/// * `offset` - Byte offset, word aligned and below `BACKUP_SRAM_SIZE`
+/// * `value` - Value to keep across resets
+pub fn write_word(offset: usize, value: u32) {
+    // SAFETY: Aligned write inside the backup SRAM, enabled by `enable`
+    unsafe { core::ptr::write_volatile(word(offset), value) }
+}
@@ -0,0 +1,146 @@
+//! # Baud Rate Capture Timer
+//!
+//! Measures the bit time of the USART6 RX line for automatic baud rate
+//! detection (`task_handlers::auto_baud`), with the timer and jumper of the
+//! RX timeout (`rx_timeout`), which it replaces while a detection runs:
+//! - Timer runs in slave reset mode; every RX edge restarts the count, and the
+//!   capture of the same edge keeps the time since the previous one
+//! - The shortest of `AUTOBAUD_EDGES` edge intervals is one bit time
+//! - An update (overflow) marks a pause longer than the counter range; the
+//!   interval ending at the next edge is not counted
+//!
+//! The line must carry bytes with an isolated bit, e.g. `U` (0x55) or CR.
+//!
+//! ## Hardware Configuration
+//! - TIM3 channel 1 on PA6 (AF2), which must be jumpered to USART6 RX (PG9)
+//! - The prescaler fits one bit time at `AUTOBAUD_MIN_BAUD` into the 16-bit
+//!   counter, e.g. 45 MHz ticks (48 per bit at 921600 baud) from a 90 MHz
+//!   timer clock
+
+use crate::config::{AUTOBAUD_EDGES, AUTOBAUD_MAX_BAUD, AUTOBAUD_MIN_BAUD};
+use stm32f4xx_hal::{
+    gpio::{gpioa::PA6, Alternate},
+    pac::TIM3,
+    rcc::{Enable, Reset},
+};
+
+/// TIM3 SR flags, cleared by writing 0
+const SR_UIF: u32 = 1 << 0;
+const SR_CC1IF: u32 = 1 << 1;
+const SR_CC1OF: u32 = 1 << 9;
+
+/// RX edge interval meter
+pub struct BaudCapture {
+    tim: TIM3,
+    pin: PA6<Alternate<2>>,
+    timer_clock: u32,
+    /// Counter frequency
+    tick_hz: u32,
+    /// Shorter intervals are glitches: half a bit at `AUTOBAUD_MAX_BAUD`
+    min_ticks: u32,
+    /// Shortest interval so far
+    shortest: u32,
+    /// Intervals counted
+    edges: u16,
+    /// The next capture ends a measurable interval
+    armed: bool,
+}
+
+impl BaudCapture {
+    /// Configures TIM3 for edge interval capture and starts it
+    ///
+    /// # Arguments
+    /// * `tim` - TIM3 peripheral instance
+    /// * `pin` - PA6 in alternate function 2 (TIM3_CH1), jumpered to RX
+    /// * `timer_clock` - TIM3 kernel clock in Hz
+    pub fn start(tim: TIM3, pin: PA6<Alternate<2>>, timer_clock: u32) -> Self {
+        // SAFETY: TIM3 is owned exclusively by this driver
+        unsafe {
+            TIM3::enable_unchecked();
+            TIM3::reset_unchecked();
+        }
+
+        let prescaler = (timer_clock / (AUTOBAUD_MIN_BAUD * 0x1_0000)).min(u16::MAX as u32);
+        let tick_hz = timer_clock / (prescaler + 1);
+
+        // IC1 mapped on TI1 with a short digital filter
+        tim.ccmr1_input()
+            .write(|w| unsafe { w.cc1s().bits(0b01).ic1f().bits(0b0011) });
+        // Capture on both edges
+        tim.ccer()
+            .write(|w| w.cc1p().set_bit().cc1np().set_bit().cc1e().set_bit());
+        // Slave reset mode, trigger on TI1 edge detector
+        tim.smcr()
+            .write(|w| unsafe { w.sms().bits(0b100).ts().bits(0b100) });
+        // Only counter overflow raises the update flag (not resets or UG)
+        tim.cr1().write(|w| w.urs().set_bit());
+        tim.psc().write(|w| w.psc().bits(prescaler as u16));
+        tim.arr().write(|w| unsafe { w.bits(0xFFFF) });
+        tim.egr().write(|w| w.ug().set_bit());
+        tim.sr().write(|w| unsafe { w.bits(0) });
+        tim.dier().write(|w| w.cc1ie().set_bit().uie().set_bit());
+        tim.cr1().modify(|_, w| w.cen().set_bit());
+
+        #[cfg(feature = "debug")]
+        defmt::info!("Baud capture started, {} Hz ticks", tick_hz);
+
+        Self {
+            tim,
+            pin,
+            timer_clock,
+            tick_hz,
+            min_ticks: tick_hz / (2 * AUTOBAUD_MAX_BAUD),
+            shortest: u32::MAX,
+            edges: 0,
+            armed: false,
+        }
+    }
+
+    /// Handles the TIM3 interrupt
+    ///
+    /// Stops the timer interrupts once `AUTOBAUD_EDGES` intervals are in.
+    ///
+    /// # Returns
+    /// The measured rate in bits per second, once
+    pub fn on_interrupt(&mut self) -> Option<u32> {
+        let sr = self.tim.sr().read();
+
+        if sr.uif().bit_is_set() {
+            self.tim.sr().write(|w| unsafe { w.bits(!SR_UIF) });
+            self.armed = false;
+        }
+
+        if !sr.cc1if().bit_is_set() {
+            return None;
+        }
+        // An overcapture only means edges were missed, the value is still
+        // the latest interval
+        let ticks = self.tim.ccr1().read().bits() & 0xFFFF;
+        self.tim
+            .sr()
+            .write(|w| unsafe { w.bits(!(SR_CC1IF | SR_CC1OF)) });
+
+        let counted = core::mem::replace(&mut self.armed, true);
+        if !counted || ticks < self.min_ticks {
+            return None;
+        }
+        self.shortest = self.shortest.min(ticks);
+        self.edges += 1;
+        if self.edges < AUTOBAUD_EDGES {
+            return None;
+        }
+
+        self.tim.dier().write(|w| unsafe { w.bits(0) });
+        Some((self.tick_hz + self.shortest / 2) / self.shortest)
+    }
+
+    /// Stops the timer and hands it back
+    ///
+    /// # Returns
+    /// TIM3, its pin and the timer clock in Hz
+    pub fn release(self) -> (TIM3, PA6<Alternate<2>>, u32) {
+        self.tim.cr1().modify(|_, w| w.cen().clear_bit());
+        self.tim.dier().write(|w| unsafe { w.bits(0) });
+        (self.tim, self.pin, self.timer_clock)
+    }
+}
@@ -0,0 +1,59 @@
+//! # Block Device Interface
+//!
+//! Fixed-size block storage as seen by the USB mass storage function:
+//! - Blocks of `BLOCK_SIZE` bytes addressed by logical block number
+//! - Read-only devices keep the default `write_block`, which refuses
+//! - `generation` changes whenever the content changed behind the host's
+//!   back, so the host can be told to drop its cache
+
+/// Block size in bytes
+pub const BLOCK_SIZE: usize = 512;
+
+/// One block of data
+pub type Block = [u8; BLOCK_SIZE];
+
+/// Block device failures
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockError {
+    /// Block number beyond the end of the device
+    OutOfRange,
+    /// No medium, or the medium is still being prepared
+    NotReady,
+    /// The device is read-only
+    WriteProtected,
+    /// A transfer to or from the medium failed
+    Medium,
+}
+
+/// Storage addressed in blocks of `BLOCK_SIZE` bytes
+pub trait BlockDevice {
+    /// Number of blocks on the device
+    fn block_count(&self) -> u32;
+
+    /// Checks whether the medium can be accessed
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// Content generation, changed whenever the content was replaced
+    fn generation(&self) -> u32 {
+        0
+    }
+
+    /// Brings the content up to date; called when a host attaches
+    fn refresh(&mut self) {}
+
+    /// Reads one block
+    ///
+    /// # Errors
+    /// `OutOfRange` beyond `block_count`, `NotReady` without a medium
+    fn read_block(&mut self, lba: u32, block: &mut Block) -> Result<(), BlockError>;
+
+    /// Writes one block
+    ///
+    /// # Errors
+    /// `WriteProtected` unless the device supports writing
+    fn write_block(&mut self, _lba: u32, _block: &Block) -> Result<(), BlockError> {
+        Err(BlockError::WriteProtected)
+    }
+}
@@ -0,0 +1,86 @@
+//! # User Button
+//!
+//! Blue user button B1 on PA0 with an EXTI line 0 interrupt:
+//! - Interrupt on the rising edge (press) only; the handling task samples
+//!   the level after `BUTTON_DEBOUNCE_MS` and ignores glitches
+//! - Hold time classified into short and long presses by `classify`
+//!
+//! ## Hardware Configuration
+//! - PA0, active high with an external pull-down on the discovery board
+//! - EXTI line 0 is routed to port A (SYSCFG EXTICR1 reset value)
+
+use crate::config::BUTTON_LONG_PRESS_MS;
+use stm32f4xx_hal::gpio::{gpioa::PA0, Input};
+use stm32f4xx_hal::pac::{self, EXTI};
+
+/// EXTI line of the button
+const BUTTON_LINE: u32 = 1 << 0;
+
+/// SYSCFG EXTICR1 field of EXTI line 0
+const EXTICR1_LINE0_MASK: u32 = 0xF;
+
+/// Press types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Press {
+    /// Released before `BUTTON_LONG_PRESS_MS`
+    Short,
+    /// Held for at least `BUTTON_LONG_PRESS_MS`
+    Long,
+}
+
+/// Classifies a press by its hold time
+///
+/// # Arguments
+/// * `held_ms` - Time the button was held down (milliseconds)
+pub fn classify(held_ms: u32) -> Press {
+    if held_ms >= BUTTON_LONG_PRESS_MS {
+        Press::Long
+    } else {
+        Press::Short
+    }
+}
+
+/// User button with its EXTI line
+pub struct UserButton {
+    pin: PA0<Input>,
+}
+
+impl UserButton {
+    /// Routes PA0 to EXTI line 0 and unmasks its rising edge
+    ///
+    /// # Arguments
+    /// * `pin` - PA0 in floating input mode (pulled down on the board)
+    pub fn new(pin: PA0<Input>) -> Self {
+        // SAFETY: Only the line 0 bits of SYSCFG EXTICR1 and the EXTI
+        // registers are touched; line 0 is not used by any other driver
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            let syscfg = &*pac::SYSCFG::ptr();
+            let exti = &*EXTI::ptr();
+
+            rcc.apb2enr().modify(|_, w| w.syscfgen().set_bit());
+            syscfg
+                .exticr1()
+                .modify(|r, w| w.bits(r.bits() & !EXTICR1_LINE0_MASK));
+
+            exti.rtsr().modify(|r, w| w.bits(r.bits() | BUTTON_LINE));
+            exti.ftsr().modify(|r, w| w.bits(r.bits() & !BUTTON_LINE));
+            exti.pr().write(|w| w.bits(BUTTON_LINE));
+            exti.imr().modify(|r, w| w.bits(r.bits() | BUTTON_LINE));
+        }
+
+        Self { pin }
+    }
+
+    /// Checks whether the button is held down
+    pub fn is_pressed(&self) -> bool {
+        self.pin.is_high()
+    }
+
+    /// Clears the pending EXTI line 0 interrupt
+    pub fn clear_interrupt(&mut self) {
+        // SAFETY: Write-one-to-clear register; only the button line bit is set
+        let exti = unsafe { &*EXTI::ptr() };
+        exti.pr().write(|w| unsafe { w.bits(BUTTON_LINE) });
+    }
+}
@@ -0,0 +1,135 @@
+//! # PWM Buzzer
+//!
+//! Audible output for error codes, for boards installed where the red LED
+//! cannot be seen:
+//! - Square wave with 50 % duty while on, silent (0 % duty) while off
+//! - Switching only changes the compare value; the timer keeps running, so
+//!   every tone starts within one period
+//! - Tone frequency set at init from `BUZZER_FREQUENCY_HZ`, adjustable at
+//!   runtime
+//!
+//! ## Hardware Configuration
+//! - TIM14 channel 1 on PA7 (AF9), Arduino header D9
+//! - A piezo transducer can be driven directly, a magnetic buzzer needs a
+//!   transistor
+//! - Timer counts at `TICK_HZ` from the APB1 timer clock
+
+use crate::peripherals::rcc::RccConfig;
+use stm32f4xx_hal::{
+    gpio::{gpioa::PA7, Alternate},
+    pac::TIM14,
+    rcc::{Enable, Reset},
+};
+
+/// Timer count rate (Hz)
+const TICK_HZ: u32 = 1_000_000;
+
+/// Lowest accepted tone frequency (Hz)
+pub const MIN_FREQUENCY_HZ: u32 = 100;
+
+/// Highest accepted tone frequency (Hz)
+pub const MAX_FREQUENCY_HZ: u32 = 10_000;
+
+/// TIMx_CCMR1.OC1M value for PWM mode 1
+const OC_MODE_PWM1: u8 = 0b110;
+
+/// Silences the buzzer without its driver
+///
+/// For the shutdown, when the driver belongs to a task that no longer runs.
+/// Stops TIM14 and disables the channel output, so the pin goes low at once
+/// instead of at the end of the period.
+pub fn force_off() {
+    // SAFETY: Only the counter enable and the output enable are cleared; the
+    // caller keeps the driver from running, as for `led::all_off`
+    unsafe {
+        let tim = &*TIM14::ptr();
+        tim.ccer().write(|w| w.bits(0));
+        tim.cr1().modify(|_, w| w.cen().clear_bit());
+    }
+}
+
+/// Piezo buzzer on a PWM timer channel
+pub struct Buzzer {
+    tim: TIM14,
+    _pin: PA7<Alternate<9>>,
+    period: u32,
+    on: bool,
+}
+
+impl Buzzer {
+    /// Configures TIM14 channel 1 for PWM and starts it silent
+    ///
+    /// # Arguments
+    /// * `tim` - TIM14 peripheral instance
+    /// * `pin` - PA7 in alternate function 9 (TIM14_CH1)
+    /// * `clocks` - System clock configuration
+    /// * `frequency_hz` - Tone frequency, clamped to
+    ///   `MIN_FREQUENCY_HZ..=MAX_FREQUENCY_HZ`
+    pub fn new(tim: TIM14, pin: PA7<Alternate<9>>, clocks: &RccConfig, frequency_hz: u32) -> Self {
+        // SAFETY: TIM14 is owned exclusively by this driver
+        unsafe {
+            TIM14::enable_unchecked();
+            TIM14::reset_unchecked();
+        }
+
+        let prescaler = (clocks.clocks.timclk1().raw() / TICK_HZ).saturating_sub(1);
+        tim.psc().write(|w| w.psc().bits(prescaler as u16));
+        // PWM mode 1 with compare preload, so a new duty applies at the next period
+        tim.ccmr1_output()
+            .write(|w| unsafe { w.oc1m().bits(OC_MODE_PWM1) }.oc1pe().set_bit());
+        tim.ccr1().write(|w| unsafe { w.bits(0) });
+        tim.ccer().write(|w| w.cc1e().set_bit());
+        tim.cr1().write(|w| w.arpe().set_bit());
+
+        let mut buzzer = Self {
+            tim,
+            _pin: pin,
+            period: 0,
+            on: false,
+        };
+        buzzer.set_frequency(frequency_hz);
+        buzzer.tim.cr1().modify(|_, w| w.cen().set_bit());
+        buzzer
+    }
+
+    /// Changes the tone frequency
+    ///
+    /// # Arguments
+    /// * `frequency_hz` - Clamped to `MIN_FREQUENCY_HZ..=MAX_FREQUENCY_HZ`
+    pub fn set_frequency(&mut self, frequency_hz: u32) {
+        let frequency_hz = frequency_hz.clamp(MIN_FREQUENCY_HZ, MAX_FREQUENCY_HZ);
+        self.period = TICK_HZ / frequency_hz;
+
+        self.tim.arr().write(|w| unsafe { w.bits(self.period - 1) });
+        self.write_compare();
+        self.tim.egr().write(|w| w.ug().set_bit());
+    }
+
+    /// Current tone frequency (Hz)
+    pub fn frequency_hz(&self) -> u32 {
+        TICK_HZ / self.period
+    }
+
+    /// Starts the tone
+    pub fn on(&mut self) {
+        self.on = true;
+        self.write_compare();
+    }
+
+    /// Silences the tone
+    pub fn off(&mut self) {
+        self.on = false;
+        self.write_compare();
+    }
+
+    /// Checks if the tone is sounding
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+
+    fn write_compare(&mut self) {
+        let compare = if self.on { self.period / 2 } else { 0 };
+        // SAFETY: Any compare value is valid; 0 keeps the output low
+        self.tim.ccr1().write(|w| unsafe { w.bits(compare) });
+    }
+}
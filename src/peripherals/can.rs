@@ -0,0 +1,475 @@
+//! # CAN Controller
+//!
+//! CAN1 (bxCAN) driven through the registers, for the SLCAN adapter mode
+//! (`task_handlers::slcan`):
+//! - Bitrates from 10 kbit/s to 1 Mbit/s, timed from `PCLK1` with the sample
+//!   point near 87.5 %; rates `PCLK1` cannot reach exactly are refused
+//! - Normal, listen-only (silent) and internal loopback operation
+//! - One 32-bit mask filter accepting every frame into FIFO 0; a pending
+//!   message raises `CAN1_RX0`
+//! - Transmit mailboxes are served in request order, so frames leave in the
+//!   order the host sent them
+//! - Automatic bus-off recovery after 128 x 11 recessive bits
+//!
+//! ## Hardware Configuration
+//! - RX PB8, TX PB9 (AF9) to an external 3.3 V transceiver, e.g. a
+//!   SN65HVD230 on the Arduino D15/D14 pins
+//! - The pins are those of I2C1, so the `can` and `i2c` features exclude
+//!   each other
+//!
+//! ## Safety Considerations
+//! - Mode changes busy-wait for the controller, bounded by `CAN_TIMEOUT_US`
+//! - Leaving initialization needs 11 recessive bits on the bus: without a
+//!   transceiver and termination `open` times out
+
+#[cfg(feature = "i2c")]
+compile_error!("the `can` and `i2c` features both need PB8/PB9");
+
+use crate::config::{CAN_TIMEOUT_US, PCLK1, SYSCLK};
+use crate::errors::errors::CanError;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+use stm32f4xx_hal::{
+    gpio::{
+        gpiob::{PB8, PB9},
+        Alternate,
+    },
+    pac::{self, CAN1},
+};
+
+/// CAN1 enable and reset bit in RCC APB1ENR and APB1RSTR
+const RCC_CAN1: u32 = 1 << 25;
+
+/// MCR bits: initialization and sleep request, transmit FIFO priority,
+/// automatic bus-off management
+const MCR_INRQ: u32 = 1 << 0;
+const MCR_SLEEP: u32 = 1 << 1;
+const MCR_TXFP: u32 = 1 << 2;
+const MCR_ABOM: u32 = 1 << 6;
+
+/// MSR initialization and sleep acknowledge
+const MSR_INAK: u32 = 1 << 0;
+const MSR_SLAK: u32 = 1 << 1;
+
+/// TSR transmit mailbox empty flags (mailbox 0 at bit 26)
+const TSR_TME0: u32 = 1 << 26;
+
+/// RF0R bits: pending message count, overrun, release output mailbox
+const RF0R_FMP: u32 = 0b11;
+const RF0R_FOVR: u32 = 1 << 4;
+const RF0R_RFOM: u32 = 1 << 5;
+
+/// IER FIFO 0 message pending interrupt
+const IER_FMPIE0: u32 = 1 << 1;
+
+/// ESR bits: error warning, error passive, bus-off, error counters
+const ESR_EWGF: u32 = 1 << 0;
+const ESR_EPVF: u32 = 1 << 1;
+const ESR_BOFF: u32 = 1 << 2;
+const ESR_TEC_SHIFT: u32 = 16;
+const ESR_REC_SHIFT: u32 = 24;
+
+/// BTR fields: loopback, silent, segment lengths
+const BTR_LBKM: u32 = 1 << 30;
+const BTR_SILM: u32 = 1 << 31;
+const BTR_TS2_SHIFT: u32 = 20;
+const BTR_TS1_SHIFT: u32 = 16;
+
+/// Identifier register bits of the mailboxes and FIFOs
+const IR_TXRQ: u32 = 1 << 0;
+const IR_RTR: u32 = 1 << 1;
+const IR_IDE: u32 = 1 << 2;
+const IR_EXID_SHIFT: u32 = 3;
+const IR_STID_SHIFT: u32 = 21;
+
+/// FMR filter initialization mode
+const FMR_FINIT: u32 = 1 << 0;
+
+/// Busy-wait step of timeouts (microseconds)
+const WAIT_STEP_US: u32 = 10;
+
+/// Largest standard and extended identifiers
+pub const MAX_STANDARD_ID: u16 = 0x7FF;
+pub const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+/// Frame counters for the report
+static TX_FRAMES: AtomicU32 = AtomicU32::new(0);
+static RX_FRAMES: AtomicU32 = AtomicU32::new(0);
+static RX_OVERRUNS: AtomicU32 = AtomicU32::new(0);
+static TX_REFUSED: AtomicU32 = AtomicU32::new(0);
+
+/// CAN1 pins: RX, TX
+pub type Can1Pins = (PB8<Alternate<9>>, PB9<Alternate<9>>);
+
+/// Frame identifier
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CanId {
+    /// 11-bit identifier
+    Standard(u16),
+    /// 29-bit identifier
+    Extended(u32),
+}
+
+/// Classic CAN frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanFrame {
+    /// Identifier
+    pub id: CanId,
+    /// Remote transmission request: no data, `dlc` is the requested length
+    pub remote: bool,
+    /// Data length code, 0 to 8
+    pub dlc: u8,
+    /// Data bytes, valid up to `dlc` for data frames
+    pub data: [u8; 8],
+}
+
+impl CanFrame {
+    /// Data bytes of the frame (empty for remote frames)
+    pub fn payload(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..usize::from(self.dlc)]
+        }
+    }
+}
+
+/// Operating mode of an open channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CanMode {
+    /// Takes part in the bus: acknowledges and transmits
+    Normal,
+    /// Receives only, without acknowledging (silent mode)
+    ListenOnly,
+    /// Transmitted frames are received back, the TX pin stays recessive
+    Loopback,
+}
+
+/// Bus error state from the error status register
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CanStatus {
+    /// An error counter reached 96
+    pub error_warning: bool,
+    /// An error counter passed 127
+    pub error_passive: bool,
+    /// Transmit error counter passed 255
+    pub bus_off: bool,
+    /// Transmit error counter
+    pub tec: u8,
+    /// Receive error counter
+    pub rec: u8,
+}
+
+/// Bit timing of one bitrate: prescaler and segment lengths in time quanta
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct BitTiming {
+    prescaler: u32,
+    bs1: u32,
+    bs2: u32,
+}
+
+impl BitTiming {
+    /// Finds the timing with the most quanta per bit (8 to 25) that divides
+    /// `PCLK1` exactly, with the sample point near 87.5 %
+    fn for_bitrate(bitrate: u32) -> Option<Self> {
+        if bitrate == 0 {
+            return None;
+        }
+        (8..=25).rev().find_map(|quanta: u32| {
+            let clock = bitrate.checked_mul(quanta)?;
+            if PCLK1 % clock != 0 {
+                return None;
+            }
+            let prescaler = PCLK1 / clock;
+            let bs2 = (quanta + 4) / 8;
+            let bs1 = quanta - 1 - bs2;
+            ((1..=1024).contains(&prescaler) && bs1 <= 16).then_some(Self {
+                prescaler,
+                bs1,
+                bs2,
+            })
+        })
+    }
+
+    /// BTR value without mode bits; SJW stays 0, a jump width of one quantum
+    fn btr_bits(&self) -> u32 {
+        ((self.bs2 - 1) << BTR_TS2_SHIFT) | ((self.bs1 - 1) << BTR_TS1_SHIFT) | (self.prescaler - 1)
+    }
+}
+
+/// Checks whether a bitrate can be timed from `PCLK1`
+pub fn is_bitrate_supported(bitrate: u32) -> bool {
+    BitTiming::for_bitrate(bitrate).is_some()
+}
+
+/// CAN1 controller
+pub struct Can1 {
+    can: CAN1,
+    _pins: Can1Pins,
+    /// Bitrate and mode while the channel is open
+    open: Option<(u32, CanMode)>,
+}
+
+impl Can1 {
+    /// Enables CAN1, sets up the accept-all filter and leaves the
+    /// controller in initialization mode, off the bus
+    ///
+    /// # Arguments
+    /// * `can` - CAN1 peripheral
+    /// * `pins` - RX and TX in AF9
+    pub fn new(can: CAN1, pins: Can1Pins) -> Self {
+        // SAFETY: Only the CAN1 bit in the APB1 enable and reset registers
+        // is touched
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            rcc.apb1enr().modify(|r, w| w.bits(r.bits() | RCC_CAN1));
+            rcc.apb1rstr().modify(|r, w| w.bits(r.bits() | RCC_CAN1));
+            rcc.apb1rstr().modify(|r, w| w.bits(r.bits() & !RCC_CAN1));
+        }
+
+        let mut controller = Self {
+            can,
+            _pins: pins,
+            open: None,
+        };
+        controller.setup_filter();
+        // Out of reset the controller sleeps; the request only fails
+        // without a CAN clock
+        controller.enter_init().ok();
+        controller
+    }
+
+    /// Joins the bus
+    ///
+    /// # Arguments
+    /// * `bitrate` - Bus bitrate (bit/s)
+    /// * `mode` - Normal, listen-only or loopback operation
+    ///
+    /// # Errors
+    /// `Bitrate` if `PCLK1` cannot produce the rate, `Timeout` if the
+    /// controller does not see an idle bus
+    pub fn open(&mut self, bitrate: u32, mode: CanMode) -> Result<(), CanError> {
+        let timing = BitTiming::for_bitrate(bitrate).ok_or(CanError::Bitrate)?;
+        self.enter_init()?;
+
+        let mode_bits = match mode {
+            CanMode::Normal => 0,
+            CanMode::ListenOnly => BTR_SILM,
+            CanMode::Loopback => BTR_LBKM,
+        };
+        // SAFETY: BTR is writable in initialization mode only, entered above
+        unsafe {
+            self.can
+                .btr()
+                .write(|w| w.bits(timing.btr_bits() | mode_bits));
+            self.can.mcr().write(|w| w.bits(MCR_TXFP | MCR_ABOM));
+        }
+        self.wait_msr(MSR_INAK, false)?;
+
+        // Frames left over from before the channel opened are dropped
+        while self.can.rf0r().read().bits() & RF0R_FMP != 0 {
+            self.release_fifo();
+        }
+        // SAFETY: Enables the FIFO 0 message pending interrupt only
+        unsafe { self.can.ier().write(|w| w.bits(IER_FMPIE0)) };
+
+        self.open = Some((bitrate, mode));
+        #[cfg(feature = "debug")]
+        defmt::info!("CAN1 open at {} bit/s", bitrate);
+        Ok(())
+    }
+
+    /// Leaves the bus; pending transmissions are aborted
+    ///
+    /// # Errors
+    /// `Timeout` if the controller does not enter initialization mode
+    pub fn close(&mut self) -> Result<(), CanError> {
+        // SAFETY: Disables the CAN1 interrupts only
+        unsafe { self.can.ier().write(|w| w.bits(0)) };
+        self.open = None;
+        self.enter_init()
+    }
+
+    /// Bitrate and mode of the open channel
+    pub fn channel(&self) -> Option<(u32, CanMode)> {
+        self.open
+    }
+
+    /// Queues a frame in a free transmit mailbox
+    ///
+    /// # Errors
+    /// `Closed` without an open channel or in listen-only mode, `BusOff`
+    /// while the controller is off the bus, `MailboxFull` if all three
+    /// mailboxes still wait for the bus
+    pub fn transmit(&mut self, frame: &CanFrame) -> Result<(), CanError> {
+        let result = self.queue_frame(frame);
+        match result {
+            Ok(()) => TX_FRAMES.fetch_add(1, Ordering::Relaxed),
+            Err(_) => TX_REFUSED.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
+    fn queue_frame(&mut self, frame: &CanFrame) -> Result<(), CanError> {
+        match self.open {
+            None | Some((_, CanMode::ListenOnly)) => return Err(CanError::Closed),
+            Some(_) => {}
+        }
+        if self.can.esr().read().bits() & ESR_BOFF != 0 {
+            return Err(CanError::BusOff);
+        }
+
+        let tsr = self.can.tsr().read().bits();
+        let mailbox = (0..3)
+            .find(|&n| tsr & (TSR_TME0 << n) != 0)
+            .ok_or(CanError::MailboxFull)?;
+
+        let mut id_bits = match frame.id {
+            CanId::Standard(id) => u32::from(id & MAX_STANDARD_ID) << IR_STID_SHIFT,
+            CanId::Extended(id) => ((id & MAX_EXTENDED_ID) << IR_EXID_SHIFT) | IR_IDE,
+        };
+        if frame.remote {
+            id_bits |= IR_RTR;
+        }
+        let data = frame.data;
+        let tx = self.can.tx(mailbox);
+        // SAFETY: The mailbox is empty; TXRQ is set last, after its
+        // identifier, length and data
+        unsafe {
+            tx.tdtr().write(|w| w.bits(u32::from(frame.dlc.min(8))));
+            tx.tdlr()
+                .write(|w| w.bits(u32::from_le_bytes([data[0], data[1], data[2], data[3]])));
+            tx.tdhr()
+                .write(|w| w.bits(u32::from_le_bytes([data[4], data[5], data[6], data[7]])));
+            tx.tir().write(|w| w.bits(id_bits | IR_TXRQ));
+        }
+        Ok(())
+    }
+
+    /// Takes the oldest frame from receive FIFO 0
+    pub fn receive(&mut self) -> Option<CanFrame> {
+        let rf0r = self.can.rf0r().read().bits();
+        if rf0r & RF0R_FOVR != 0 {
+            RX_OVERRUNS.fetch_add(1, Ordering::Relaxed);
+            // SAFETY: FOVR is cleared by writing 1, RFOM is left alone
+            unsafe { self.can.rf0r().write(|w| w.bits(RF0R_FOVR)) };
+        }
+        if rf0r & RF0R_FMP == 0 {
+            return None;
+        }
+
+        let rx = self.can.rx(0);
+        let rir = rx.rir().read().bits();
+        let dlc = (rx.rdtr().read().bits() & 0xF).min(8) as u8;
+        let low = rx.rdlr().read().bits().to_le_bytes();
+        let high = rx.rdhr().read().bits().to_le_bytes();
+        self.release_fifo();
+
+        let id = if rir & IR_IDE != 0 {
+            CanId::Extended(rir >> IR_EXID_SHIFT)
+        } else {
+            CanId::Standard((rir >> IR_STID_SHIFT) as u16)
+        };
+        let mut data = [0u8; 8];
+        data[..4].copy_from_slice(&low);
+        data[4..].copy_from_slice(&high);
+
+        RX_FRAMES.fetch_add(1, Ordering::Relaxed);
+        Some(CanFrame {
+            id,
+            remote: rir & IR_RTR != 0,
+            dlc,
+            data,
+        })
+    }
+
+    /// Error state of the controller
+    pub fn status(&self) -> CanStatus {
+        let esr = self.can.esr().read().bits();
+        CanStatus {
+            error_warning: esr & ESR_EWGF != 0,
+            error_passive: esr & ESR_EPVF != 0,
+            bus_off: esr & ESR_BOFF != 0,
+            tec: (esr >> ESR_TEC_SHIFT) as u8,
+            rec: (esr >> ESR_REC_SHIFT) as u8,
+        }
+    }
+
+    /// Checks whether every transmit mailbox is busy
+    pub fn is_tx_full(&self) -> bool {
+        let empty = TSR_TME0 | (TSR_TME0 << 1) | (TSR_TME0 << 2);
+        self.can.tsr().read().bits() & empty == 0
+    }
+
+    /// Checks whether receive FIFO 0 holds three frames
+    pub fn is_rx_full(&self) -> bool {
+        self.can.rf0r().read().bits() & RF0R_FMP == 3
+    }
+
+    /// Requests initialization mode, leaving sleep mode
+    fn enter_init(&mut self) -> Result<(), CanError> {
+        // SAFETY: INRQ with SLEEP cleared is the documented way into
+        // initialization mode from any state
+        unsafe {
+            self.can
+                .mcr()
+                .modify(|r, w| w.bits((r.bits() | MCR_INRQ) & !MCR_SLEEP));
+        }
+        self.wait_msr(MSR_INAK, true)?;
+        self.wait_msr(MSR_SLAK, false)
+    }
+
+    /// Sets filter bank 0 to a 32-bit mask of zero, into FIFO 0
+    fn setup_filter(&mut self) {
+        // SAFETY: CAN1 owns the filter banks; they are changed in filter
+        // initialization mode only. Bank 0 becomes a 32-bit mask filter
+        // accepting every identifier.
+        unsafe {
+            self.can.fmr().modify(|r, w| w.bits(r.bits() | FMR_FINIT));
+            self.can.fa1r().modify(|r, w| w.bits(r.bits() & !1));
+            self.can.fm1r().modify(|r, w| w.bits(r.bits() & !1));
+            self.can.fs1r().modify(|r, w| w.bits(r.bits() | 1));
+            self.can.ffa1r().modify(|r, w| w.bits(r.bits() & !1));
+            self.can.fb(0).fr1().write(|w| w.bits(0));
+            self.can.fb(0).fr2().write(|w| w.bits(0));
+            self.can.fa1r().modify(|r, w| w.bits(r.bits() | 1));
+            self.can.fmr().modify(|r, w| w.bits(r.bits() & !FMR_FINIT));
+        }
+    }
+
+    /// Releases the oldest FIFO 0 entry
+    fn release_fifo(&mut self) {
+        // SAFETY: RFOM only releases the output mailbox; FOVR and FULL are
+        // cleared by writing 1 and stay as they are
+        unsafe { self.can.rf0r().write(|w| w.bits(RF0R_RFOM)) };
+    }
+
+    /// Waits for an MSR flag to reach a state
+    fn wait_msr(&self, flag: u32, set: bool) -> Result<(), CanError> {
+        let mut waited_us = 0;
+        while (self.can.msr().read().bits() & flag != 0) != set {
+            if waited_us >= CAN_TIMEOUT_US {
+                return Err(CanError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        }
+        Ok(())
+    }
+}
+
+fn busy_wait_us(us: u32) {
+    cortex_m::asm::delay(SYSCLK / 1_000_000 * us);
+}
+
+/// Writes the frame counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "CAN1: {} frames sent, {} refused, {} received, {} FIFO overruns",
+        TX_FRAMES.load(Ordering::Relaxed),
+        TX_REFUSED.load(Ordering::Relaxed),
+        RX_FRAMES.load(Ordering::Relaxed),
+        RX_OVERRUNS.load(Ordering::Relaxed)
+    )
+}
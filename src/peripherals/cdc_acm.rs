@@ -0,0 +1,468 @@
+//! # CDC-ACM Class with Modem Status Notifications
+//!
+//! USB Communication Device Class (Abstract Control Model) function used by
+//! the bridge and debug console ports. Replaces `usbd_serial::SerialPort`,
+//! which gives no access to the notification endpoint. Provides:
+//! - Buffered bulk writes with automatic zero-length packet termination
+//! - Line coding and DTR/RTS control line state from the host
+//! - `SEND_BREAK` requests, taken by the UART side (`take_break_request`)
+//! - `SERIAL_STATE` notifications (DCD, DSR, RI, break and error bits, and
+//!   CTS outside the PSTN bits)
+//!
+//! ## Hardware Configuration
+//! - One interrupt IN endpoint (notifications), one bulk IN and one bulk OUT endpoint
+//! - Bulk endpoints use `CDC_MAX_PACKET_SIZE`
+
+use crate::config::{CDC_MAX_PACKET_SIZE, DATA_PACKET_SIZE};
+use bitflags::bitflags;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::UsbError;
+use usbd_serial::USB_CLASS_CDC;
+
+/// CDC data interface class
+const USB_CLASS_CDC_DATA: u8 = 0x0A;
+/// Abstract Control Model subclass
+const CDC_SUBCLASS_ACM: u8 = 0x02;
+/// No class-specific protocol
+const CDC_PROTOCOL_NONE: u8 = 0x00;
+
+/// Class-specific interface descriptor type
+const CS_INTERFACE: u8 = 0x24;
+/// Functional descriptor subtypes
+const CDC_TYPE_HEADER: u8 = 0x00;
+const CDC_TYPE_CALL_MANAGEMENT: u8 = 0x01;
+const CDC_TYPE_ACM: u8 = 0x02;
+const CDC_TYPE_UNION: u8 = 0x06;
+
+/// Class requests
+const REQ_SEND_ENCAPSULATED_COMMAND: u8 = 0x00;
+const REQ_SET_LINE_CODING: u8 = 0x20;
+const REQ_GET_LINE_CODING: u8 = 0x21;
+const REQ_SET_CONTROL_LINE_STATE: u8 = 0x22;
+const REQ_SEND_BREAK: u8 = 0x23;
+
+/// `SEND_BREAK` wValue that holds the break until the next request
+const BREAK_UNTIL_STOPPED: u16 = 0xFFFF;
+
+/// `SERIAL_STATE` notification code
+const NOTIFY_SERIAL_STATE: u8 = 0x20;
+/// Notification endpoint packet size (`SERIAL_STATE` is 10 bytes)
+const NOTIFY_PACKET_SIZE: u16 = 16;
+/// Notification endpoint polling interval (frames)
+const NOTIFY_INTERVAL: u8 = 16;
+
+bitflags! {
+    /// `SERIAL_STATE` bits reported to the host (CDC PSTN 6.5.4)
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SerialState: u16 {
+        /// Data carrier detect (bRxCarrier)
+        const DCD = 1 << 0;
+        /// Data set ready (bTxCarrier)
+        const DSR = 1 << 1;
+        /// Break detected
+        const BREAK = 1 << 2;
+        /// Ring indicator
+        const RING = 1 << 3;
+        /// Framing error
+        const FRAMING = 1 << 4;
+        /// Parity error
+        const PARITY = 1 << 5;
+        /// Receive overrun
+        const OVERRUN = 1 << 6;
+        /// Clear to send; reserved in PSTN, so standard CDC drivers ignore
+        /// it while tools reading the raw notification can use it
+        const CTS = 1 << 7;
+    }
+}
+
+/// Line coding requested by the host
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineCoding {
+    /// Data terminal rate in bits per second
+    pub baud_rate: u32,
+    /// 0 = 1 stop bit, 1 = 1.5 stop bits, 2 = 2 stop bits
+    pub stop_bits: u8,
+    /// 0 = none, 1 = odd, 2 = even, 3 = mark, 4 = space
+    pub parity: u8,
+    /// Data bits (5, 6, 7, 8 or 16)
+    pub data_bits: u8,
+}
+
+/// Break condition requested by the host (`SEND_BREAK`, CDC PSTN 6.3.13)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakRequest {
+    /// Break for the given number of milliseconds
+    Timed(u16),
+    /// Break until the next request (wValue 0xFFFF)
+    Start,
+    /// End of a running break (wValue 0)
+    Stop,
+}
+
+impl BreakRequest {
+    /// Decodes the wValue of a `SEND_BREAK` request
+    pub const fn from_value(value: u16) -> Self {
+        match value {
+            0 => Self::Stop,
+            BREAK_UNTIL_STOPPED => Self::Start,
+            ms => Self::Timed(ms),
+        }
+    }
+}
+
+impl Default for LineCoding {
+    fn default() -> Self {
+        Self {
+            baud_rate: 9_600,
+            stop_bits: 0,
+            parity: 0,
+            data_bits: 8,
+        }
+    }
+}
+
+/// CDC-ACM function
+pub struct CdcAcm<'a, B: UsbBus> {
+    comm_if: InterfaceNumber,
+    comm_ep: EndpointIn<'a, B>,
+    data_if: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    line_coding: LineCoding,
+    line_coding_changed: bool,
+    dtr: bool,
+    rts: bool,
+    break_request: Option<BreakRequest>,
+    write_buf: [u8; DATA_PACKET_SIZE],
+    write_start: usize,
+    write_end: usize,
+    write_in_flight: bool,
+    need_zlp: bool,
+    serial_state: SerialState,
+    notify_pending: bool,
+    notify_in_flight: bool,
+}
+
+impl<'a, B: UsbBus> CdcAcm<'a, B> {
+    /// Allocates interfaces and endpoints for the function
+    ///
+    /// # Arguments
+    /// * `alloc` - USB bus allocator
+    pub fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+        Self {
+            comm_if: alloc.interface(),
+            comm_ep: alloc.interrupt(NOTIFY_PACKET_SIZE, NOTIFY_INTERVAL),
+            data_if: alloc.interface(),
+            read_ep: alloc.bulk(CDC_MAX_PACKET_SIZE as u16),
+            write_ep: alloc.bulk(CDC_MAX_PACKET_SIZE as u16),
+            line_coding: LineCoding::default(),
+            line_coding_changed: false,
+            dtr: false,
+            rts: false,
+            break_request: None,
+            write_buf: [0; DATA_PACKET_SIZE],
+            write_start: 0,
+            write_end: 0,
+            write_in_flight: false,
+            need_zlp: false,
+            serial_state: SerialState::empty(),
+            notify_pending: false,
+            notify_in_flight: false,
+        }
+    }
+
+    /// Reads one packet from the host
+    ///
+    /// # Arguments
+    /// * `data` - Destination, at least `CDC_MAX_PACKET_SIZE` bytes
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` when no packet is available
+    pub fn read(&mut self, data: &mut [u8]) -> Result<usize, UsbError> {
+        self.read_ep.read(data)
+    }
+
+    /// Queues data for transmission
+    ///
+    /// # Returns
+    /// Number of bytes accepted into the write buffer
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` when the write buffer is full
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        if self.write_start > 0 {
+            self.write_buf.copy_within(self.write_start..self.write_end, 0);
+            self.write_end -= self.write_start;
+            self.write_start = 0;
+        }
+
+        let count = data.len().min(self.write_buf.len() - self.write_end);
+        if count == 0 && !data.is_empty() {
+            return Err(UsbError::WouldBlock);
+        }
+
+        self.write_buf[self.write_end..self.write_end + count].copy_from_slice(&data[..count]);
+        self.write_end += count;
+
+        match self.send_packet() {
+            Ok(()) | Err(UsbError::WouldBlock) => Ok(count),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends buffered data, terminating the transfer
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` while data or a packet is still in flight
+    pub fn flush(&mut self) -> Result<(), UsbError> {
+        match self.send_packet() {
+            Ok(()) | Err(UsbError::WouldBlock) => {}
+            Err(e) => return Err(e),
+        }
+
+        if self.write_in_flight || self.write_start < self.write_end {
+            Err(UsbError::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Line coding last set by the host
+    pub fn line_coding(&self) -> &LineCoding {
+        &self.line_coding
+    }
+
+    /// Takes the line coding if the host changed it since the last call
+    pub fn take_line_coding(&mut self) -> Option<LineCoding> {
+        core::mem::take(&mut self.line_coding_changed).then_some(self.line_coding)
+    }
+
+    /// Takes the break request the host sent since the last call
+    ///
+    /// A newer request replaces one that was not taken yet.
+    pub fn take_break_request(&mut self) -> Option<BreakRequest> {
+        self.break_request.take()
+    }
+
+    /// Data Terminal Ready as set by the host
+    pub fn dtr(&self) -> bool {
+        self.dtr
+    }
+
+    /// Request To Send as set by the host
+    pub fn rts(&self) -> bool {
+        self.rts
+    }
+
+    /// Updates the modem status reported to the host
+    ///
+    /// A `SERIAL_STATE` notification is sent only when the state changes; if
+    /// the notification endpoint is busy it is sent when the endpoint frees up.
+    ///
+    /// # Errors
+    /// Endpoint failures other than `WouldBlock`
+    pub fn set_serial_state(&mut self, state: SerialState) -> Result<(), UsbError> {
+        if state != self.serial_state {
+            self.serial_state = state;
+            self.notify_pending = true;
+        }
+        self.send_notification()
+    }
+
+    /// Current modem status as last reported
+    pub fn serial_state(&self) -> SerialState {
+        self.serial_state
+    }
+
+    /// Writes the next packet (or pending ZLP) if the endpoint is free
+    fn send_packet(&mut self) -> Result<(), UsbError> {
+        if self.write_in_flight {
+            return Err(UsbError::WouldBlock);
+        }
+
+        let pending = self.write_end - self.write_start;
+        if pending == 0 {
+            if self.need_zlp {
+                self.write_ep.write(&[])?;
+                self.need_zlp = false;
+                self.write_in_flight = true;
+            }
+            return Ok(());
+        }
+
+        let count = pending.min(CDC_MAX_PACKET_SIZE);
+        let written = self
+            .write_ep
+            .write(&self.write_buf[self.write_start..self.write_start + count])?;
+
+        self.write_start += written;
+        self.write_in_flight = true;
+        // A full packet that ends the buffered data must be followed by a ZLP
+        self.need_zlp = written == CDC_MAX_PACKET_SIZE;
+
+        if self.write_start == self.write_end {
+            self.write_start = 0;
+            self.write_end = 0;
+        }
+        Ok(())
+    }
+
+    /// Sends a pending `SERIAL_STATE` notification if the endpoint is free
+    fn send_notification(&mut self) -> Result<(), UsbError> {
+        if !self.notify_pending || self.notify_in_flight {
+            return Ok(());
+        }
+
+        let bits = self.serial_state.bits().to_le_bytes();
+        let packet = [
+            0xA1, // Device-to-host, class, interface
+            NOTIFY_SERIAL_STATE,
+            0,
+            0,
+            u8::from(self.comm_if),
+            0,
+            2,
+            0,
+            bits[0],
+            bits[1],
+        ];
+
+        match self.comm_ep.write(&packet) {
+            Ok(_) => {
+                self.notify_pending = false;
+                self.notify_in_flight = true;
+                Ok(())
+            }
+            Err(UsbError::WouldBlock) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether a class request targets the communication interface
+    fn is_own_request(&self, req: &Request) -> bool {
+        req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == u8::from(self.comm_if) as u16
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for CdcAcm<'_, B> {
+    fn get_configuration_descriptors(&self, writer: &mut DescriptorWriter) -> usb_device::Result<()> {
+        writer.iad(
+            self.comm_if,
+            2,
+            USB_CLASS_CDC,
+            CDC_SUBCLASS_ACM,
+            CDC_PROTOCOL_NONE,
+            None,
+        )?;
+
+        writer.interface(self.comm_if, USB_CLASS_CDC, CDC_SUBCLASS_ACM, CDC_PROTOCOL_NONE)?;
+        writer.write(CS_INTERFACE, &[CDC_TYPE_HEADER, 0x10, 0x01])?;
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_CALL_MANAGEMENT, 0x00, u8::from(self.data_if)],
+        )?;
+        // Supports line coding/control line state, SERIAL_STATE notifications
+        // (D1) and SEND_BREAK (D2)
+        writer.write(CS_INTERFACE, &[CDC_TYPE_ACM, 0x06])?;
+        writer.write(
+            CS_INTERFACE,
+            &[CDC_TYPE_UNION, u8::from(self.comm_if), u8::from(self.data_if)],
+        )?;
+        writer.endpoint(&self.comm_ep)?;
+
+        writer.interface(self.data_if, USB_CLASS_CDC_DATA, 0x00, 0x00)?;
+        writer.endpoint(&self.write_ep)?;
+        writer.endpoint(&self.read_ep)?;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.line_coding = LineCoding::default();
+        self.dtr = false;
+        self.rts = false;
+        // A break in progress is ended, so the line is not held low
+        self.break_request = Some(BreakRequest::Stop);
+        self.write_start = 0;
+        self.write_end = 0;
+        self.write_in_flight = false;
+        self.need_zlp = false;
+        self.notify_pending = !self.serial_state.is_empty();
+        self.notify_in_flight = false;
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_ep.address() {
+            self.write_in_flight = false;
+            let _ = self.send_packet();
+        } else if addr == self.comm_ep.address() {
+            self.notify_in_flight = false;
+            let _ = self.send_notification();
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) {
+            return;
+        }
+
+        match req.request {
+            REQ_GET_LINE_CODING if req.length >= 7 => {
+                let baud = self.line_coding.baud_rate.to_le_bytes();
+                let coding = [
+                    baud[0],
+                    baud[1],
+                    baud[2],
+                    baud[3],
+                    self.line_coding.stop_bits,
+                    self.line_coding.parity,
+                    self.line_coding.data_bits,
+                ];
+                xfer.accept_with(&coding).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) {
+            return;
+        }
+
+        match req.request {
+            REQ_SEND_ENCAPSULATED_COMMAND => {
+                xfer.accept().ok();
+            }
+            REQ_SET_LINE_CODING if xfer.data().len() >= 7 => {
+                let data = xfer.data();
+                self.line_coding = LineCoding {
+                    baud_rate: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                    stop_bits: data[4],
+                    parity: data[5],
+                    data_bits: data[6],
+                };
+                self.line_coding_changed = true;
+                xfer.accept().ok();
+            }
+            REQ_SET_CONTROL_LINE_STATE => {
+                self.dtr = req.value & 0x0001 != 0;
+                self.rts = req.value & 0x0002 != 0;
+                xfer.accept().ok();
+            }
+            REQ_SEND_BREAK => {
+                self.break_request = Some(BreakRequest::from_value(req.value));
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! # Core Clock Scaling
+//!
+//! Runtime changes of the clock tree `RccConfig` set up, in three levels:
+//! - `Full`: the `RccConfig` clocks, SYSCLK from the PLL
+//! - `Half`: AHB prescaler /2, APB1 /2 and APB2 /1, so PCLK1, PCLK2 and the
+//!   APB1 timer clocks keep their rates; UART baud divisors, I2C timing and
+//!   the TIM3-TIM14 prescalers stay valid. Taken by the `low-power` profile
+//!   while the bridge idles
+//! - `Low`: SYSCLK and all bus clocks from the HSE, PLL and over-drive off.
+//!   Taken while VBUS is absent; the bus clocks change, so the UART baud
+//!   divisors and the RX timeout are recomputed by the caller
+//!   (`UartController::set_clock_level`)
+//!
+//! Every switch passes through `Full`. The SysTick reload follows HCLK, so
+//! `Mono` keeps its 1 ms tick, the SDRAM refresh counter follows SDCLK
+//! (HCLK / 2) and the WWDG prescaler follows PCLK1. The PLL keeps the configuration `RccConfig` wrote while it is
+//! off and locks again with it.
+//!
+//! ## Safety Considerations
+//! - Busy-waits counted in core cycles (`asm::delay`, the `timeout` poll
+//!   steps) take longer below `Full`
+//! - DWT cycle counts (`profiler`, `latency`) are in cycles of the current
+//!   core clock
+//! - The APB2 timer clock drops with PCLK2's multiplier at `Half`; no APB2
+//!   timer is in use
+//! - At `Low`, PLL48CLK is off: USB, the RNG (which falls back to the cycle
+//!   counter) and SDIO stop, and the APB1 timers, I2C and SPI run slower
+//! - The prescalers survive Stop mode, so the level does as well; `low_power`
+//!   restarts only the HSE at `Low`
+
+use crate::config::{HSE, PCLK1, PCLK2, SYSCLK};
+use crate::errors::errors::DeviceError;
+use crate::peripherals::rcc::RccConfig;
+use crate::peripherals::{low_power, sdram, wwdg};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+/// RCC CFGR prescaler fields: HPRE [7:4], PPRE1 [12:10], PPRE2 [15:13]
+const CFGR_HPRE_MASK: u32 = 0xF << 4;
+const CFGR_PPRE1_MASK: u32 = 0b111 << 10;
+const CFGR_PPRE2_MASK: u32 = 0b111 << 13;
+
+/// HPRE /1 and /2
+const HPRE_DIV1: u32 = 0;
+const HPRE_DIV2: u32 = 0b1000 << 4;
+
+/// PPRE1 /1, /2 and /4, PPRE2 /1 and /2
+const PPRE1_DIV1: u32 = 0;
+const PPRE1_DIV2: u32 = 0b100 << 10;
+const PPRE1_DIV4: u32 = 0b101 << 10;
+const PPRE2_DIV1: u32 = 0;
+const PPRE2_DIV2: u32 = 0b100 << 13;
+
+// The full-clock prescalers above are the ones `RccConfig` derives
+const _: () = assert!(SYSCLK / PCLK1 == 4 && SYSCLK / PCLK2 == 2);
+
+/// SysTick ticks per second of `Mono`
+const MONO_TICK_HZ: u32 = 1_000;
+
+/// Level in effect, as `ClockLevel as u8`
+static LEVEL: AtomicU8 = AtomicU8::new(ClockLevel::Full as u8);
+
+static SWITCHES: AtomicU32 = AtomicU32::new(0);
+
+/// Core clock levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockLevel {
+    /// HCLK = SYSCLK from the PLL
+    Full = 0,
+    /// HCLK = SYSCLK / 2, peripheral clocks unchanged
+    Half = 1,
+    /// SYSCLK and all bus clocks = HSE, PLL off
+    Low = 2,
+}
+
+impl ClockLevel {
+    /// All levels, by value
+    pub const ALL: [ClockLevel; 3] = [Self::Full, Self::Half, Self::Low];
+
+    /// Name used in reports
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Half => "half",
+            Self::Low => "low",
+        }
+    }
+
+    /// HCLK at this level (Hz)
+    pub const fn hclk(self) -> u32 {
+        match self {
+            Self::Full => SYSCLK,
+            Self::Half => SYSCLK / 2,
+            Self::Low => HSE,
+        }
+    }
+
+    /// PCLK1 at this level (Hz)
+    pub const fn pclk1(self) -> u32 {
+        match self {
+            Self::Full | Self::Half => PCLK1,
+            Self::Low => HSE,
+        }
+    }
+
+    /// PCLK2 at this level (Hz)
+    pub const fn pclk2(self) -> u32 {
+        match self {
+            Self::Full | Self::Half => PCLK2,
+            Self::Low => HSE,
+        }
+    }
+
+    /// APB1 timer clock at this level (Hz); twice PCLK1 unless APB1 runs
+    /// undivided
+    pub const fn timclk1(self) -> u32 {
+        match self {
+            Self::Full | Self::Half => PCLK1 * 2,
+            Self::Low => HSE,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(bits)).copied()
+    }
+}
+
+/// Level in effect
+pub fn level() -> ClockLevel {
+    ClockLevel::from_bits(LEVEL.load(Ordering::Relaxed)).unwrap_or(ClockLevel::Full)
+}
+
+/// Current HCLK (Hz)
+pub fn hclk() -> u32 {
+    level().hclk()
+}
+
+/// Switches the core clock level
+///
+/// Runs in a critical section, so no handler sees the prescalers and the
+/// SysTick reload out of step. Leaving `Low` waits for the PLL to lock.
+///
+/// # Returns
+/// `true` if the level changed
+///
+/// # Errors
+/// `DeviceError::ClockSwitch` if the PLL did not lock; the clocks stay at
+/// `Low`
+pub fn set_level(target: ClockLevel) -> Result<bool, DeviceError> {
+    cortex_m::interrupt::free(|_| {
+        let current = level();
+        if current == target {
+            return Ok(false);
+        }
+
+        // SAFETY: RCC CR, the CFGR clock fields, the SysTick reload and the
+        // FMC refresh counter are only changed here and in `low_power` after
+        // `init_peripherals`, with interrupts disabled
+        unsafe {
+            if current != ClockLevel::Full {
+                leave(current)?;
+            }
+            if target != ClockLevel::Full {
+                enter(target);
+            }
+            // Takes effect at the next SysTick wrap
+            (*cortex_m::peripheral::SYST::PTR)
+                .rvr
+                .write(target.hclk() / MONO_TICK_HZ - 1);
+        }
+        wwdg::follow_clock(target);
+
+        LEVEL.store(target as u8, Ordering::Relaxed);
+        SWITCHES.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "debug")]
+        defmt::info!("Core clock: {=str}", target.name());
+        Ok(true)
+    })
+}
+
+/// Goes from `Full` to `level`
+///
+/// # Safety
+/// Interrupts must be disabled and the clocks at `Full`
+unsafe fn enter(level: ClockLevel) {
+    // Refresh more often first, then slow SDCLK down
+    sdram::set_refresh_clock(level.hclk() / 2);
+    match level {
+        ClockLevel::Half => {
+            // AHB down first, so no APB clock exceeds its limit
+            set_ahb_prescaler(HPRE_DIV2);
+            set_apb_prescalers(PPRE1_DIV2, PPRE2_DIV1);
+        }
+        ClockLevel::Low => {
+            low_power::switch_to_hse();
+            set_apb_prescalers(PPRE1_DIV1, PPRE2_DIV1);
+        }
+        ClockLevel::Full => {}
+    }
+}
+
+/// Goes from `level` back to `Full`
+///
+/// # Safety
+/// Interrupts must be disabled and the clocks at `level`
+unsafe fn leave(level: ClockLevel) -> Result<(), DeviceError> {
+    // APB clocks down first, so none exceeds its limit
+    set_apb_prescalers(PPRE1_DIV4, PPRE2_DIV2);
+    set_ahb_prescaler(HPRE_DIV1);
+    if level == ClockLevel::Low && !low_power::start_pll() {
+        set_apb_prescalers(PPRE1_DIV1, PPRE2_DIV1);
+        return Err(DeviceError::ClockSwitch);
+    }
+    sdram::set_refresh_clock(ClockLevel::Full.hclk() / 2);
+    Ok(())
+}
+
+/// Writes the AHB prescaler
+fn set_ahb_prescaler(hpre: u32) {
+    RccConfig::modify_cfgr(CFGR_HPRE_MASK, hpre);
+}
+
+/// Writes both APB prescalers
+fn set_apb_prescalers(ppre1: u32, ppre2: u32) {
+    RccConfig::modify_cfgr(CFGR_PPRE1_MASK | CFGR_PPRE2_MASK, ppre1 | ppre2);
+}
+
+/// Writes the clock level and the number of switches
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Core clock: {} (HCLK {} MHz), {} switches",
+        level().name(),
+        hclk() / 1_000_000,
+        SWITCHES.load(Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,156 @@
+//! # CRC32 Unit
+//!
+//! Streaming wrapper around the STM32 CRC calculation unit:
+//! - CRC-32/MPEG-2: polynomial 0x04C11DB7, initial value 0xFFFFFFFF, no
+//!   reflection, no final XOR; check value of "123456789" is 0x0376E6E7
+//! - `reset`, `feed` and `result` on a `Crc32` stream, `checksum` for a
+//!   complete buffer
+//! - Bytes are fed as big-endian words; up to 3 trailing bytes are folded in
+//!   by software when the result is read, so any length gives the byte-wise
+//!   CRC
+//!
+//! ## Hardware Configuration
+//! - Clock enabled by `enable` (AHB1ENR.CRCEN), called from the board setup
+//!   and before verifying a bootloader image
+//!
+//! ## Safety Considerations
+//! - The unit has a single data register and no way to reload a state, so
+//!   only one stream may use it at a time; `Crc32::acquire` hands out the
+//!   unit, and a context that finds it taken computes in software
+//! - Results are identical either way
+
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::pac;
+
+pub use crate::protocol::crc32::{update, INIT};
+
+/// CRC CR.RESET
+const CR_RESET: u32 = 1 << 0;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static IN_USE: AtomicBool = AtomicBool::new(false);
+
+static HW_STREAMS: AtomicU32 = AtomicU32::new(0);
+static SW_STREAMS: AtomicU32 = AtomicU32::new(0);
+
+/// Enables the CRC unit clock
+///
+/// Safe to call more than once.
+pub fn enable() {
+    // SAFETY: Only the CRCEN bit is set
+    unsafe {
+        (*pac::RCC::ptr())
+            .ahb1enr()
+            .modify(|_, w| w.crcen().set_bit());
+    }
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Computes the checksum of a complete buffer
+///
+/// Uses the CRC unit if it is free, software otherwise.
+pub fn checksum(data: &[u8]) -> u32 {
+    match Crc32::acquire() {
+        Some(mut crc) => {
+            crc.feed(data);
+            crc.result()
+        }
+        None => {
+            SW_STREAMS.fetch_add(1, Ordering::Relaxed);
+            update(INIT, data)
+        }
+    }
+}
+
+/// Exclusive checksum stream on the CRC unit
+pub struct Crc32 {
+    regs: &'static pac::crc::RegisterBlock,
+    tail: [u8; 4],
+    tail_len: usize,
+}
+
+impl Crc32 {
+    /// Takes the CRC unit and starts a new checksum
+    ///
+    /// # Returns
+    /// `None` if the unit is not enabled or another stream holds it
+    pub fn acquire() -> Option<Self> {
+        if !ENABLED.load(Ordering::Acquire) || IN_USE.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        HW_STREAMS.fetch_add(1, Ordering::Relaxed);
+
+        // SAFETY: `IN_USE` makes this the only user of the CRC registers
+        let regs = unsafe { &*pac::CRC::ptr() };
+        let mut crc = Self {
+            regs,
+            tail: [0; 4],
+            tail_len: 0,
+        };
+        crc.reset();
+        Some(crc)
+    }
+
+    /// Restarts the checksum from `INIT`
+    pub fn reset(&mut self) {
+        // SAFETY: RESET is the only defined bit of CR
+        self.regs.cr().write(|w| unsafe { w.bits(CR_RESET) });
+        self.tail_len = 0;
+    }
+
+    /// Feeds the next bytes
+    pub fn feed(&mut self, mut data: &[u8]) {
+        // Complete a word left over from the previous call
+        if self.tail_len > 0 {
+            let take = (4 - self.tail_len).min(data.len());
+            self.tail[self.tail_len..self.tail_len + take].copy_from_slice(&data[..take]);
+            self.tail_len += take;
+            data = &data[take..];
+            if self.tail_len < 4 {
+                return;
+            }
+            self.write_word(self.tail);
+            self.tail_len = 0;
+        }
+
+        let mut words = data.chunks_exact(4);
+        for word in &mut words {
+            self.write_word([word[0], word[1], word[2], word[3]]);
+        }
+
+        let rest = words.remainder();
+        self.tail[..rest.len()].copy_from_slice(rest);
+        self.tail_len = rest.len();
+    }
+
+    /// Checksum of all bytes fed since the last reset
+    ///
+    /// The stream can be fed further afterwards.
+    pub fn result(&self) -> u32 {
+        update(self.regs.dr().read().bits(), &self.tail[..self.tail_len])
+    }
+
+    fn write_word(&mut self, word: [u8; 4]) {
+        // SAFETY: Any value is a valid data word
+        self.regs
+            .dr()
+            .write(|w| unsafe { w.bits(u32::from_be_bytes(word)) });
+    }
+}
+
+impl Drop for Crc32 {
+    fn drop(&mut self) {
+        IN_USE.store(false, Ordering::Release);
+    }
+}
+
+/// Writes how many checksums ran on the unit and in software
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "CRC32: {} on the unit, {} in software",
+        HW_STREAMS.load(Ordering::Relaxed),
+        SW_STREAMS.load(Ordering::Relaxed)
+    )
+}
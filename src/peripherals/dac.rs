@@ -0,0 +1,555 @@
+//! # DAC
+//!
+//! Both DAC channels driven through the registers, as a bench signal source:
+//! - A constant level, or a sine or sawtooth from a 64-point table
+//! - Waveforms are clocked out of flash by DMA1 on the update events of a
+//!   timer, TIM6 for channel 1 and TIM5 for channel 2, so each channel runs
+//!   at its own frequency without the CPU once started
+//! - Output buffer on, range 0 to VREF+ (VDDA, 3.3 V on this board)
+//! - The waveform of each channel is published for reports
+//!
+//! ## Hardware Configuration
+//! - Channel 1 on PA4 (Arduino A5), channel 2 on PA5, in analog mode; with
+//!   the `adc` feature PA4 stays an ADC input as well, A5 reads channel 1 back
+//! - DMA1 stream 5 (channel 1) and stream 6 (channel 2), request channel 7,
+//!   circular
+//! - Timer clock 2 x `PCLK1` = 90 MHz, divided down to 64 updates per period
+//!
+//! ## Safety Considerations
+//! - DMA1 streams 5 and 6, TIM5 and TIM6 are reserved for the DAC
+//! - A DMA underrun stops a channel's requests until its output is set
+//!   again; the `dac` report shows it
+
+use crate::config::{DAC_MAX_FREQUENCY_HZ, DAC_MIN_FREQUENCY_HZ, PCLK1};
+use crate::errors::errors::DacError;
+use core::cell::Cell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{compiler_fence, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use stm32f4xx_hal::{
+    dma::{Stream5, Stream6},
+    gpio::{
+        gpioa::{PA4, PA5},
+        Analog,
+    },
+    pac::{self, DAC, DMA1, TIM5, TIM6},
+};
+
+/// Points per waveform period
+pub const TABLE_LEN: usize = 64;
+
+/// RCC APB1ENR and APB1RSTR bits: TIM5, TIM6, DAC
+const RCC_TIM5: u32 = 1 << 3;
+const RCC_TIM6: u32 = 1 << 4;
+const RCC_DAC: u32 = 1 << 29;
+
+/// DAC CR bits of channel 1; channel 2 uses the same bits shifted by 16
+const CR_EN: u32 = 1 << 0;
+const CR_TEN: u32 = 1 << 2;
+const CR_TSEL_SHIFT: u32 = 3;
+const CR_DMAEN: u32 = 1 << 12;
+const CR_CHANNEL_MASK: u32 = 0xFFFF;
+
+/// DAC trigger selections: TIM6 TRGO (channel 1) and TIM5 TRGO (channel 2)
+const TSEL_TIM6: u32 = 0b000;
+const TSEL_TIM5: u32 = 0b011;
+
+/// DAC SR DMA underrun flag of channel 1, shifted by 16 for channel 2
+const SR_DMAUDR: u32 = 1 << 13;
+
+/// Timer CR1 counter enable, CR2 update event as TRGO, EGR update generation
+const TIM_CR1_CEN: u32 = 1 << 0;
+const TIM_CR2_MMS_UPDATE: u32 = 0b010 << 4;
+const TIM_EGR_UG: u32 = 1 << 0;
+
+/// DMA SxCR bits: enable, request channel 7, memory to peripheral,
+/// circular, memory increment, half-word sizes, medium priority
+const DMA_CR_EN: u32 = 1 << 0;
+const DMA_CR_CHSEL_7: u32 = 7 << 25;
+const DMA_CR_DIR_M2P: u32 = 0b01 << 6;
+const DMA_CR_CIRC: u32 = 1 << 8;
+const DMA_CR_MINC: u32 = 1 << 10;
+const DMA_CR_PSIZE_16: u32 = 0b01 << 11;
+const DMA_CR_MSIZE_16: u32 = 0b01 << 13;
+const DMA_CR_PL_MEDIUM: u32 = 0b01 << 16;
+
+/// Stream 5 flags in HISR and HIFCR: FIFO, direct mode, transfer error,
+/// half and full transfer; stream 6 uses the same bits shifted by 10
+const DMA_FLAGS5: u32 = (1 << 6) | (1 << 8) | (1 << 9) | (1 << 10) | (1 << 11);
+
+/// Timer input clock: APB1 timers run at twice `PCLK1` (APB1 prescaler 4)
+const TIMER_CLOCK_HZ: u32 = 2 * PCLK1;
+
+/// Timer counters and prescalers are 16 bits wide (TIM5 is used the same way)
+const TIMER_RANGE: u32 = 1 << 16;
+
+/// Full scale of a 12-bit code
+const FULL_SCALE: u32 = 4095;
+
+/// VREF+ of the board (millivolts), the output for `FULL_SCALE`
+pub const VREF_MV: u32 = 3_300;
+
+/// One sine period around mid-scale
+static SINE: [u16; TABLE_LEN] = [
+    2048, 2248, 2447, 2642, 2831, 3013, 3185, 3346, 3495, 3630, 3750, 3853, 3939, 4007, 4056, 4085,
+    4095, 4085, 4056, 4007, 3939, 3853, 3750, 3630, 3495, 3346, 3185, 3013, 2831, 2642, 2447, 2248,
+    2048, 1847, 1648, 1453, 1264, 1082, 910, 749, 600, 465, 345, 242, 156, 88, 39, 10, 0, 10, 39,
+    88, 156, 242, 345, 465, 600, 749, 910, 1082, 1264, 1453, 1648, 1847,
+];
+
+/// One rising ramp from 0 to full scale
+static SAWTOOTH: [u16; TABLE_LEN] = sawtooth();
+
+const fn sawtooth() -> [u16; TABLE_LEN] {
+    let mut table = [0u16; TABLE_LEN];
+    let mut i = 0;
+    while i < TABLE_LEN {
+        table[i] = (i as u32 * FULL_SCALE / (TABLE_LEN as u32 - 1)) as u16;
+        i += 1;
+    }
+    table
+}
+
+/// Waveform of each channel, for reports
+static OUTPUTS: Mutex<Cell<[Waveform; 2]>> = Mutex::new(Cell::new([Waveform::Off; 2]));
+
+/// Output pins: channel 1 (`None` while the ADC owns PA4, already analog)
+/// and channel 2
+pub type DacPins = (Option<PA4<Analog>>, PA5<Analog>);
+
+/// DAC output channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DacChannel {
+    /// Channel 1 (PA4)
+    One,
+    /// Channel 2 (PA5)
+    Two,
+}
+
+impl DacChannel {
+    /// Both channels
+    pub const ALL: [DacChannel; 2] = [DacChannel::One, DacChannel::Two];
+
+    /// Channel with the given number, 1 or 2
+    pub fn from_number(number: u8) -> Option<Self> {
+        match number {
+            1 => Some(DacChannel::One),
+            2 => Some(DacChannel::Two),
+            _ => None,
+        }
+    }
+
+    /// Channel number, 1 or 2
+    pub fn number(self) -> u8 {
+        match self {
+            DacChannel::One => 1,
+            DacChannel::Two => 2,
+        }
+    }
+
+    /// Output pin name
+    pub fn pin(self) -> &'static str {
+        match self {
+            DacChannel::One => "PA4",
+            DacChannel::Two => "PA5",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Shift of the channel's bits in the DAC CR and SR registers
+    fn shift(self) -> u32 {
+        16 * self as u32
+    }
+}
+
+/// Signal of one channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    /// Output disabled, the pin floats in analog mode
+    Off,
+    /// Constant level (millivolts)
+    Level(u32),
+    /// Sine across the full range (Hz)
+    Sine(u32),
+    /// Rising sawtooth across the full range (Hz)
+    Sawtooth(u32),
+}
+
+impl Waveform {
+    /// Builds a waveform from its name and value, as typed on the console or
+    /// the AT channel (`off`, `dc <mV>`, `sine <Hz>`, `saw <Hz>`)
+    ///
+    /// # Returns
+    /// `None` for an unknown name, a missing or extra value, or a value
+    /// `check` rejects
+    pub fn parse(kind: &str, value: Option<&str>) -> Option<Self> {
+        if kind.eq_ignore_ascii_case("off") {
+            return value.is_none().then_some(Waveform::Off);
+        }
+        let value = value?.parse().ok()?;
+        let waveform = if kind.eq_ignore_ascii_case("dc") {
+            Waveform::Level(value)
+        } else if kind.eq_ignore_ascii_case("sine") {
+            Waveform::Sine(value)
+        } else if kind.eq_ignore_ascii_case("saw") {
+            Waveform::Sawtooth(value)
+        } else {
+            return None;
+        };
+        waveform.check().is_ok().then_some(waveform)
+    }
+
+    /// Checks the level or frequency
+    ///
+    /// # Errors
+    /// `Level` above `VREF_MV`, `Frequency` outside `DAC_MIN_FREQUENCY_HZ`
+    /// to `DAC_MAX_FREQUENCY_HZ`
+    pub fn check(&self) -> Result<(), DacError> {
+        match *self {
+            Waveform::Off => Ok(()),
+            Waveform::Level(mv) if mv > VREF_MV => Err(DacError::Level),
+            Waveform::Level(_) => Ok(()),
+            Waveform::Sine(hz) | Waveform::Sawtooth(hz) => {
+                if (DAC_MIN_FREQUENCY_HZ..=DAC_MAX_FREQUENCY_HZ).contains(&hz) {
+                    Ok(())
+                } else {
+                    Err(DacError::Frequency)
+                }
+            }
+        }
+    }
+
+    /// Name as parsed by `parse`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Waveform::Off => "off",
+            Waveform::Level(_) => "dc",
+            Waveform::Sine(_) => "sine",
+            Waveform::Sawtooth(_) => "saw",
+        }
+    }
+
+    /// Level in millivolts or frequency in Hz
+    pub fn value(&self) -> Option<u32> {
+        match *self {
+            Waveform::Off => None,
+            Waveform::Level(value) | Waveform::Sine(value) | Waveform::Sawtooth(value) => {
+                Some(value)
+            }
+        }
+    }
+
+    fn table(&self) -> Option<&'static [u16; TABLE_LEN]> {
+        match self {
+            Waveform::Sine(_) => Some(&SINE),
+            Waveform::Sawtooth(_) => Some(&SAWTOOTH),
+            _ => None,
+        }
+    }
+}
+
+/// Both DAC channels with their trigger timers
+pub struct Dac {
+    dac: DAC,
+    tim6: TIM6,
+    tim5: TIM5,
+    _pins: DacPins,
+    _dma: (Stream5<DMA1>, Stream6<DMA1>),
+}
+
+impl Dac {
+    /// Powers up the DAC and its timers with both outputs off
+    ///
+    /// # Arguments
+    /// * `dac` - DAC peripheral
+    /// * `timers` - TIM6 and TIM5, the channel 1 and channel 2 triggers
+    /// * `pins` - Output pins in analog mode
+    /// * `dma` - DMA1 streams 5 and 6, reserved for the waveforms
+    pub fn new(
+        dac: DAC,
+        timers: (TIM6, TIM5),
+        pins: DacPins,
+        dma: (Stream5<DMA1>, Stream6<DMA1>),
+    ) -> Self {
+        let bits = RCC_TIM5 | RCC_TIM6 | RCC_DAC;
+        // SAFETY: Only the DAC, TIM5 and TIM6 bits in the APB1 enable and
+        // reset registers are touched
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            rcc.apb1enr().modify(|r, w| w.bits(r.bits() | bits));
+            rcc.apb1rstr().modify(|r, w| w.bits(r.bits() | bits));
+            rcc.apb1rstr().modify(|r, w| w.bits(r.bits() & !bits));
+        }
+
+        Self {
+            dac,
+            tim6: timers.0,
+            tim5: timers.1,
+            _pins: pins,
+            _dma: dma,
+        }
+    }
+
+    /// Switches a channel to a new waveform
+    ///
+    /// The previous output of the channel stops first; the other channel
+    /// keeps running.
+    ///
+    /// # Errors
+    /// `Level` or `Frequency` if `waveform` fails `Waveform::check`; the
+    /// channel is left unchanged
+    pub fn set_output(&mut self, channel: DacChannel, waveform: Waveform) -> Result<(), DacError> {
+        waveform.check()?;
+        self.stop(channel);
+
+        match waveform {
+            Waveform::Off => {}
+            Waveform::Level(mv) => {
+                let code = mv * FULL_SCALE / VREF_MV;
+                // SAFETY: The channel is stopped; without a trigger the
+                // holding register is output one APB1 cycle after the write
+                unsafe {
+                    match channel {
+                        DacChannel::One => self.dac.dhr12r1().write(|w| w.bits(code)),
+                        DacChannel::Two => self.dac.dhr12r2().write(|w| w.bits(code)),
+                    };
+                    self.dac
+                        .cr()
+                        .modify(|r, w| w.bits(r.bits() | (CR_EN << channel.shift())));
+                }
+            }
+            Waveform::Sine(hz) | Waveform::Sawtooth(hz) => {
+                if let Some(table) = waveform.table() {
+                    self.start_waveform(channel, table, hz * TABLE_LEN as u32);
+                }
+            }
+        }
+
+        interrupt::free(|cs| {
+            let outputs = OUTPUTS.borrow(cs);
+            let mut current = outputs.get();
+            current[channel.index()] = waveform;
+            outputs.set(current);
+        });
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "DAC{}: {} {}",
+            channel.number(),
+            waveform.name(),
+            waveform.value().unwrap_or(0)
+        );
+        Ok(())
+    }
+
+    /// Starts the table transfer, the triggered channel and its timer
+    fn start_waveform(
+        &mut self,
+        channel: DacChannel,
+        table: &'static [u16; TABLE_LEN],
+        sample_hz: u32,
+    ) {
+        let (stream, tsel, holding) = match channel {
+            DacChannel::One => (5, TSEL_TIM6, self.dac.dhr12r1().as_ptr() as u32),
+            DacChannel::Two => (6, TSEL_TIM5, self.dac.dhr12r2().as_ptr() as u32),
+        };
+        // SAFETY: The stream is reserved by `_dma` and stopped; the table is
+        // a static in flash, readable by the DMA1 memory port
+        unsafe {
+            let dma = &*DMA1::ptr();
+            let stream = dma.st(stream);
+            stream.par().write(|w| w.bits(holding));
+            stream.m0ar().write(|w| w.bits(table.as_ptr() as u32));
+            stream.ndtr().write(|w| w.bits(TABLE_LEN as u32));
+            stream.cr().write(|w| {
+                w.bits(
+                    DMA_CR_CHSEL_7
+                        | DMA_CR_DIR_M2P
+                        | DMA_CR_CIRC
+                        | DMA_CR_MINC
+                        | DMA_CR_PSIZE_16
+                        | DMA_CR_MSIZE_16
+                        | DMA_CR_PL_MEDIUM,
+                )
+            });
+            compiler_fence(Ordering::SeqCst);
+            stream.cr().modify(|r, w| w.bits(r.bits() | DMA_CR_EN));
+
+            let bits = CR_EN | CR_TEN | (tsel << CR_TSEL_SHIFT) | CR_DMAEN;
+            self.dac
+                .cr()
+                .modify(|r, w| w.bits(r.bits() | (bits << channel.shift())));
+        }
+
+        let (prescaler, reload) = timer_dividers(sample_hz);
+        // SAFETY: The timer is reserved for this channel and stopped; the
+        // update generation loads the dividers before counting starts
+        unsafe {
+            match channel {
+                DacChannel::One => {
+                    self.tim6.psc().write(|w| w.bits(prescaler));
+                    self.tim6.arr().write(|w| w.bits(reload));
+                    self.tim6.cr2().write(|w| w.bits(TIM_CR2_MMS_UPDATE));
+                    self.tim6.egr().write(|w| w.bits(TIM_EGR_UG));
+                    self.tim6.cr1().write(|w| w.bits(TIM_CR1_CEN));
+                }
+                DacChannel::Two => {
+                    self.tim5.psc().write(|w| w.bits(prescaler));
+                    self.tim5.arr().write(|w| w.bits(reload));
+                    self.tim5.cr2().write(|w| w.bits(TIM_CR2_MMS_UPDATE));
+                    self.tim5.egr().write(|w| w.bits(TIM_EGR_UG));
+                    self.tim5.cr1().write(|w| w.bits(TIM_CR1_CEN));
+                }
+            }
+        }
+    }
+
+    /// Stops a channel's timer, DMA stream and output, and clears its
+    /// underrun flag
+    fn stop(&mut self, channel: DacChannel) {
+        let shift = channel.shift();
+        // SAFETY: Only the channel's own timer, stream, CR half and SR flag
+        // are touched
+        unsafe {
+            match channel {
+                DacChannel::One => self.tim6.cr1().write(|w| w.bits(0)),
+                DacChannel::Two => self.tim5.cr1().write(|w| w.bits(0)),
+            };
+            self.dac
+                .cr()
+                .modify(|r, w| w.bits(r.bits() & !(CR_CHANNEL_MASK << shift)));
+
+            let dma = &*DMA1::ptr();
+            let (stream, flags) = match channel {
+                DacChannel::One => (5, DMA_FLAGS5),
+                DacChannel::Two => (6, DMA_FLAGS5 << 10),
+            };
+            let stream = dma.st(stream);
+            stream.cr().write(|w| w.bits(0));
+            while stream.cr().read().bits() & DMA_CR_EN != 0 {}
+            dma.hifcr().write(|w| w.bits(flags));
+
+            self.dac.sr().write(|w| w.bits(SR_DMAUDR << shift));
+        }
+    }
+}
+
+/// Prescaler and auto-reload values for `sample_hz` update events
+///
+/// The prescaler is the smallest that keeps the reload within 16 bits,
+/// leaving the finest frequency steps.
+fn timer_dividers(sample_hz: u32) -> (u32, u32) {
+    let ticks = ((TIMER_CLOCK_HZ + sample_hz / 2) / sample_hz).max(1);
+    let prescaler = (ticks - 1) / TIMER_RANGE;
+    let reload = (ticks / (prescaler + 1)).max(1) - 1;
+    (prescaler, reload)
+}
+
+/// Frequency produced for a requested one (millihertz), after rounding to
+/// the timer dividers
+pub fn actual_millihertz(hz: u32) -> u32 {
+    let (prescaler, reload) = timer_dividers(hz * TABLE_LEN as u32);
+    let ticks = u64::from(prescaler + 1) * u64::from(reload + 1) * TABLE_LEN as u64;
+    (u64::from(TIMER_CLOCK_HZ) * 1000 / ticks) as u32
+}
+
+/// Turns both channels off without the driver
+///
+/// For the shutdown (`task_handlers::shutdown::quiesce`): stops the timers,
+/// the DMA streams and the outputs, as `set_output` with `Waveform::Off`
+/// does. A channel stays off until set again.
+pub fn force_off() {
+    // SAFETY: TIM5, TIM6 and DMA1 streams 5 and 6 are reserved for the DAC;
+    // the caller keeps the driver from running
+    unsafe {
+        (*TIM6::ptr()).cr1().write(|w| w.bits(0));
+        (*TIM5::ptr()).cr1().write(|w| w.bits(0));
+        (*DAC::ptr()).cr().write(|w| w.bits(0));
+
+        let dma = &*DMA1::ptr();
+        for stream in [5, 6] {
+            dma.st(stream).cr().write(|w| w.bits(0));
+        }
+        dma.hifcr().write(|w| w.bits(DMA_FLAGS5 | (DMA_FLAGS5 << 10)));
+    }
+    interrupt::free(|cs| OUTPUTS.borrow(cs).set([Waveform::Off; 2]));
+}
+
+/// Holds the waveforms for a Stop period
+///
+/// Stops the sample timers only: the outputs keep the last sample, the DMA
+/// streams their position and `output` the waveforms, so `resume` picks up
+/// where they stopped. Call with interrupts disabled, so the driver does not
+/// start a timer in between.
+pub fn pause() {
+    // SAFETY: TIM5 and TIM6 are reserved for the DAC; only the counter
+    // enable is cleared
+    unsafe {
+        (*TIM6::ptr())
+            .cr1()
+            .modify(|r, w| w.bits(r.bits() & !TIM_CR1_CEN));
+        (*TIM5::ptr())
+            .cr1()
+            .modify(|r, w| w.bits(r.bits() & !TIM_CR1_CEN));
+    }
+}
+
+/// Restarts the waveforms held by `pause`
+///
+/// Channels set to `Off` or a level in the meantime stay as they are.
+pub fn resume() {
+    for channel in DacChannel::ALL {
+        if !matches!(output(channel), Waveform::Sine(_) | Waveform::Sawtooth(_)) {
+            continue;
+        }
+        // SAFETY: TIM5 and TIM6 are reserved for the DAC; the timer of a
+        // running waveform is only started again
+        unsafe {
+            match channel {
+                DacChannel::One => (*TIM6::ptr())
+                    .cr1()
+                    .modify(|r, w| w.bits(r.bits() | TIM_CR1_CEN)),
+                DacChannel::Two => (*TIM5::ptr())
+                    .cr1()
+                    .modify(|r, w| w.bits(r.bits() | TIM_CR1_CEN)),
+            }
+        }
+    }
+}
+
+/// Current waveform of a channel
+pub fn output(channel: DacChannel) -> Waveform {
+    interrupt::free(|cs| OUTPUTS.borrow(cs).get())[channel.index()]
+}
+
+/// Writes the waveform of each channel
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    // SAFETY: Read-only access to the DAC status register
+    let status = unsafe { (*DAC::ptr()).sr().read().bits() };
+    for channel in DacChannel::ALL {
+        write!(out, "DAC{} ({}): ", channel.number(), channel.pin())?;
+        match output(channel) {
+            Waveform::Off => write!(out, "off")?,
+            Waveform::Level(mv) => write!(out, "dc {} mV", mv)?,
+            waveform @ (Waveform::Sine(hz) | Waveform::Sawtooth(hz)) => {
+                let actual = actual_millihertz(hz);
+                write!(
+                    out,
+                    "{} {} Hz ({}.{:03} Hz)",
+                    waveform.name(),
+                    hz,
+                    actual / 1000,
+                    actual % 1000
+                )?;
+            }
+        }
+        if status & (SR_DMAUDR << channel.shift()) != 0 {
+            write!(out, ", DMA underrun: set it again")?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
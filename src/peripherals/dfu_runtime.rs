@@ -0,0 +1,250 @@
+//! # USB DFU Runtime Interface
+//!
+//! Device Firmware Upgrade class in run-time mode (DFU 1.1, section 4.1), added
+//! next to the CDC functions so update tools find the device without a button
+//! press or jumper:
+//! - `DFU_DETACH` from the host (e.g. `dfu-util -e`) requests a reboot into
+//!   the bootloader; `bitWillDetach` tells the host the device leaves the bus
+//!   on its own
+//! - `DFU_GETSTATUS`/`DFU_GETSTATE` report `appIDLE`, or `appDETACH` once a
+//!   detach is pending
+//! - The reboot goes through a controlled reset: a request word is left in an
+//!   RTC backup register, and the very first thing `init` does is jump to the
+//!   bootloader at `DFU_BOOTLOADER_ADDRESS` if the word is present
+//!
+//! ## Safety Considerations
+//! - The jump happens before any clock or peripheral setup, so the bootloader
+//!   sees the MCU in its reset state
+//! - The request word is cleared before jumping; a bootloader that returns or
+//!   resets lands in the application again
+//! - A custom bootloader in flash is checked first: its last word must hold
+//!   the CRC32 of the rest of the `DFU_BOOTLOADER_LEN` image. On a mismatch
+//!   the application keeps running and reports `DeviceError::ImageCorrupt`
+
+use crate::config::{
+    DFU_BOOTLOADER_ADDRESS, DFU_BOOTLOADER_LEN, DFU_DETACH_TIMEOUT_MS, DFU_TRANSFER_SIZE,
+};
+use crate::errors::errors::DeviceError;
+use crate::peripherals::crc;
+use crate::peripherals::rtc::{self, BKP_BOOT_REQUEST};
+use stm32f4xx_hal::pac;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+
+/// Application specific class
+const USB_CLASS_APPLICATION_SPECIFIC: u8 = 0xFE;
+/// Device Firmware Upgrade subclass
+const DFU_SUBCLASS: u8 = 0x01;
+/// Run-time protocol
+const DFU_PROTOCOL_RUNTIME: u8 = 0x01;
+
+/// DFU functional descriptor type
+const DFU_FUNCTIONAL: u8 = 0x21;
+/// bitWillDetach | bitCanUpload | bitCanDnload
+const DFU_ATTRIBUTES: u8 = 0x08 | 0x02 | 0x01;
+/// DFU 1.1a
+const DFU_VERSION: u16 = 0x011A;
+
+/// Class requests
+const REQ_DETACH: u8 = 0x00;
+const REQ_GETSTATUS: u8 = 0x03;
+const REQ_GETSTATE: u8 = 0x05;
+
+/// Run-time states
+const STATE_APP_IDLE: u8 = 0;
+const STATE_APP_DETACH: u8 = 1;
+
+/// Start of the STM32F469 system memory (ROM bootloader)
+const SYSTEM_MEMORY: u32 = 0x1FFF_0000;
+
+/// SYSCFG MEMRMP.MEM_MODE value mapping system memory at 0x0000_0000
+const MEM_MODE_SYSTEM_FLASH: u8 = 0b01;
+
+/// Backup register value requesting the bootloader ("DFU!")
+const BOOT_REQUEST_MAGIC: u32 = 0x4446_5521;
+
+/// DFU run-time function
+pub struct DfuRuntime {
+    iface: InterfaceNumber,
+    detach_pending: bool,
+    detach_taken: bool,
+}
+
+impl DfuRuntime {
+    /// Allocates the DFU interface (no endpoints, control pipe only)
+    ///
+    /// # Arguments
+    /// * `alloc` - USB bus allocator
+    pub fn new<B: UsbBus>(alloc: &UsbBusAllocator<B>) -> Self {
+        Self {
+            iface: alloc.interface(),
+            detach_pending: false,
+            detach_taken: false,
+        }
+    }
+
+    /// Interface number, e.g. for the MS OS descriptors
+    pub fn interface(&self) -> InterfaceNumber {
+        self.iface
+    }
+
+    /// Takes a detach request received from the host
+    ///
+    /// # Returns
+    /// `true` once per `DFU_DETACH`
+    pub fn take_detach(&mut self) -> bool {
+        let requested = self.detach_pending && !self.detach_taken;
+        self.detach_taken |= requested;
+        requested
+    }
+
+    /// Whether a request targets this interface
+    fn is_own_request(&self, req: &Request) -> bool {
+        req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == u8::from(self.iface) as u16
+    }
+
+    fn state(&self) -> u8 {
+        if self.detach_pending {
+            STATE_APP_DETACH
+        } else {
+            STATE_APP_IDLE
+        }
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for DfuRuntime {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface(
+            self.iface,
+            USB_CLASS_APPLICATION_SPECIFIC,
+            DFU_SUBCLASS,
+            DFU_PROTOCOL_RUNTIME,
+        )?;
+
+        let timeout = (DFU_DETACH_TIMEOUT_MS as u16).to_le_bytes();
+        let transfer_size = DFU_TRANSFER_SIZE.to_le_bytes();
+        let version = DFU_VERSION.to_le_bytes();
+        writer.write(
+            DFU_FUNCTIONAL,
+            &[
+                DFU_ATTRIBUTES,
+                timeout[0],
+                timeout[1],
+                transfer_size[0],
+                transfer_size[1],
+                version[0],
+                version[1],
+            ],
+        )
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) {
+            return;
+        }
+
+        match req.request {
+            REQ_GETSTATUS => {
+                // bStatus OK, bwPollTimeout 0, bState, iString none
+                xfer.accept_with(&[0, 0, 0, 0, self.state(), 0]).ok();
+            }
+            REQ_GETSTATE => {
+                xfer.accept_with(&[self.state()]).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) {
+            return;
+        }
+
+        match req.request {
+            REQ_DETACH => {
+                self.detach_pending = true;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
+
+/// Resets the MCU into the bootloader
+///
+/// The last step of `shutdown::finish`, after the host has seen the reply
+/// or the `DFU_DETACH` status stage and the device has left the bus.
+pub fn reboot_to_bootloader() -> ! {
+    #[cfg(feature = "debug")]
+    defmt::warn!("DFU detach: rebooting into the bootloader");
+
+    cortex_m::interrupt::disable();
+    rtc::write_backup_register(BKP_BOOT_REQUEST, BOOT_REQUEST_MAGIC);
+    cortex_m::peripheral::SCB::sys_reset()
+}
+
+/// Checks the CRC32 trailer of a bootloader image in flash
+///
+/// # Returns
+/// `true` if the last word of the `DFU_BOOTLOADER_LEN` bytes at
+/// `DFU_BOOTLOADER_ADDRESS` matches the CRC32 of the bytes before it
+fn bootloader_image_valid() -> bool {
+    let body_len = DFU_BOOTLOADER_LEN - 4;
+
+    // SAFETY: The image lies in memory-mapped flash, which is always readable
+    let (body, stored) = unsafe {
+        (
+            core::slice::from_raw_parts(DFU_BOOTLOADER_ADDRESS as *const u8, body_len),
+            core::ptr::read_volatile((DFU_BOOTLOADER_ADDRESS as usize + body_len) as *const u32),
+        )
+    };
+
+    crc::enable();
+    crc::checksum(body) == stored
+}
+
+/// Jumps to the bootloader if the previous run requested it
+///
+/// Must be the first call in `init`, before clocks and peripherals are set up.
+/// Returns normally when no request is pending.
+///
+/// # Errors
+/// Returns `DeviceError::ImageCorrupt` if a bootloader in flash fails its
+/// CRC32 check; the request is dropped and the application starts
+pub fn enter_bootloader_if_requested() -> Result<(), DeviceError> {
+    if rtc::read_backup_register(BKP_BOOT_REQUEST) != BOOT_REQUEST_MAGIC {
+        return Ok(());
+    }
+    rtc::write_backup_register(BKP_BOOT_REQUEST, 0);
+
+    // The ROM bootloader carries no checksum
+    if DFU_BOOTLOADER_ADDRESS != SYSTEM_MEMORY && !bootloader_image_valid() {
+        return Err(DeviceError::ImageCorrupt);
+    }
+
+    // SAFETY: Runs right after reset with interrupts disabled; the bootloader
+    // takes over the stack pointer and never returns
+    unsafe {
+        if DFU_BOOTLOADER_ADDRESS == SYSTEM_MEMORY {
+            let rcc = &*pac::RCC::ptr();
+            let syscfg = &*pac::SYSCFG::ptr();
+            rcc.apb2enr().modify(|_, w| w.syscfgen().set_bit());
+            syscfg
+                .memrmp()
+                .modify(|_, w| w.mem_mode().bits(MEM_MODE_SYSTEM_FLASH));
+        }
+
+        cortex_m::asm::bootload(DFU_BOOTLOADER_ADDRESS as *const u32)
+    }
+}
@@ -0,0 +1,301 @@
+//! # LTDC/DSI Display
+//!
+//! Drives the Discovery board's 800x480 OTM8009A panel over the MIPI DSI
+//! host, fed by the LTDC from a framebuffer in the external SDRAM:
+//! - LTDC layer 1 scans out an RGB565 framebuffer taken with `sdram_singleton!`
+//! - DSI host in video burst mode on two lanes, 500 Mbit/s per lane
+//! - OTM8009A set up for landscape through the `otm8009a` crate
+//! - Text output in a fixed grid of `COLUMNS` x `ROWS` cells, scaled 5x7 glyphs
+//!
+//! ## Hardware Configuration
+//! - LCD reset: PH7, active low
+//! - Pixel clock `DISPLAY_PIXEL_CLOCK_KHZ` from PLLSAI, set up by the LTDC driver
+//! - DSI PLL: 8 MHz HSE / 2 * 2 * 125 = 1 GHz VCO, 62.5 MHz lane byte clock
+//!
+//! ## Safety Considerations
+//! - Needs a tested SDRAM: without the framebuffer the display stays off
+//! - The LTDC reads the framebuffer continuously; drawing is not synchronized
+//!   with the scan-out, so a redrawn line may tear for one frame
+
+mod font;
+
+use crate::config::{DISPLAY_PIXEL_CLOCK_KHZ, HSE};
+use crate::errors::errors::DisplayError;
+use crate::sdram_singleton;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+use otm8009a::{ColorMap, FrameRate, Mode, Otm8009A, Otm8009AConfig};
+use stm32f4xx_hal::{
+    dsi::{
+        ColorCoding, DsiChannel, DsiCmdModeTransmissionKind, DsiConfig, DsiHost, DsiInterrupts,
+        DsiMode, DsiPhyTimers, DsiPllConfig, DsiVideoMode, LaneCount,
+    },
+    gpio::{gpioh::PH7, Output},
+    ltdc::{DisplayConfig, DisplayController, Layer, PixelFormat},
+    pac::{DMA2D, DSI, LTDC, TIM7},
+    prelude::*,
+    rcc::Clocks,
+};
+
+/// Active area width in pixels (landscape)
+pub const WIDTH: usize = 800;
+
+/// Active area height in pixels (landscape)
+pub const HEIGHT: usize = 480;
+
+/// Glyph magnification
+const SCALE: usize = 3;
+
+/// Text cell width in pixels: glyph plus one column of spacing
+pub const CELL_WIDTH: usize = (GLYPH_WIDTH + 1) * SCALE;
+
+/// Text cell height in pixels: glyph plus three rows of spacing
+pub const CELL_HEIGHT: usize = (GLYPH_HEIGHT + 3) * SCALE;
+
+/// Text columns per line
+pub const COLUMNS: usize = WIDTH / CELL_WIDTH;
+
+/// Text lines per screen
+pub const ROWS: usize = HEIGHT / CELL_HEIGHT;
+
+/// OTM8009A timings in landscape, from the panel datasheet
+const PANEL_CONFIG: DisplayConfig = DisplayConfig {
+    active_width: WIDTH as _,
+    active_height: HEIGHT as _,
+    h_back_porch: 34,
+    h_front_porch: 34,
+    v_back_porch: 15,
+    v_front_porch: 16,
+    h_sync: 2,
+    v_sync: 1,
+    frame_rate: 60,
+    h_sync_pol: true,
+    v_sync_pol: true,
+    no_data_enable_pol: false,
+    pixel_clock_pol: true,
+};
+
+/// LCD reset pulse and recovery time (milliseconds)
+const RESET_PULSE_MS: u32 = 20;
+const RESET_RECOVERY_MS: u32 = 10;
+
+/// Set once the panel is up
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// RGB565 colors used by the status screen
+pub mod color {
+    /// Builds an RGB565 value from 8-bit components
+    pub const fn rgb(r: u8, g: u8, b: u8) -> u16 {
+        ((r as u16 & 0xF8) << 8) | ((g as u16 & 0xFC) << 3) | (b as u16 >> 3)
+    }
+
+    pub const BLACK: u16 = rgb(0, 0, 0);
+    pub const WHITE: u16 = rgb(255, 255, 255);
+    pub const GREY: u16 = rgb(128, 128, 128);
+    pub const GREEN: u16 = rgb(0, 200, 0);
+    pub const YELLOW: u16 = rgb(255, 200, 0);
+    pub const RED: u16 = rgb(255, 40, 40);
+    pub const BLUE: u16 = rgb(0, 60, 160);
+}
+
+/// LTDC/DSI display with an SDRAM framebuffer
+pub struct Display {
+    ltdc: DisplayController<u16>,
+    _dsi: DsiHost,
+    _reset: PH7<Output>,
+}
+
+impl Display {
+    /// Resets the panel and brings up the LTDC, the DSI host and the OTM8009A
+    ///
+    /// # Arguments
+    /// * `ltdc` - LCD-TFT controller
+    /// * `dma2d` - Chrom-ART accelerator, owned by the LTDC driver
+    /// * `dsi` - DSI host
+    /// * `reset` - LCD reset output (PH7)
+    /// * `timer` - Timer used for the reset and panel setup delays
+    /// * `clocks` - Frozen clock configuration
+    ///
+    /// # Errors
+    /// - `NoFramebuffer` if the SDRAM is not ready
+    /// - `DsiHost` if the DSI PLL or regulator does not lock
+    /// - `Panel` if the OTM8009A setup commands fail
+    pub fn new(
+        ltdc: LTDC,
+        dma2d: DMA2D,
+        dsi: DSI,
+        mut reset: PH7<Output>,
+        timer: TIM7,
+        clocks: &Clocks,
+    ) -> Result<Self, DisplayError> {
+        let framebuffer =
+            sdram_singleton!(: [u16; WIDTH * HEIGHT]).ok_or(DisplayError::NoFramebuffer)?;
+
+        let mut delay = timer.delay_us(clocks);
+        reset.set_low();
+        delay.delay_ms(RESET_PULSE_MS);
+        reset.set_high();
+        delay.delay_ms(RESET_RECOVERY_MS);
+
+        // The LTDC provides the pixel clock the DSI host needs, so it goes first.
+        // It outputs 24-bit color whatever the layer format.
+        let mut controller = DisplayController::<u16>::new(
+            ltdc,
+            dma2d,
+            None,
+            PixelFormat::RGB565,
+            PANEL_CONFIG,
+            Some(HSE.Hz()),
+        );
+        controller.config_layer(Layer::L1, framebuffer, PixelFormat::RGB565);
+        controller.enable_layer(Layer::L1);
+        controller.reload();
+
+        // SAFETY: 8 MHz / 2 (IDF) * 2 * 125 (NDIV) = 1 GHz VCO, /1 (ODF): 500 Mbit/s per lane
+        let pll_config = unsafe { DsiPllConfig::manual(125, 2, 0, 4) };
+        let dsi_config = DsiConfig {
+            mode: DsiMode::Video {
+                mode: DsiVideoMode::Burst,
+            },
+            lane_count: LaneCount::DoubleLane,
+            channel: DsiChannel::Ch0,
+            hse_freq: HSE.Hz(),
+            ltdc_freq: DISPLAY_PIXEL_CLOCK_KHZ.kHz(),
+            interrupts: DsiInterrupts::None,
+            color_coding_host: ColorCoding::TwentyFourBits,
+            color_coding_wrapper: ColorCoding::TwentyFourBits,
+            lp_size: 4,
+            vlp_size: 4,
+        };
+        let mut dsi = DsiHost::init(pll_config, PANEL_CONFIG, dsi_config, dsi, clocks)
+            .map_err(|_| DisplayError::DsiHost)?;
+        dsi.configure_phy_timers(DsiPhyTimers {
+            dataline_hs2lp: 35,
+            dataline_lp2hs: 35,
+            clock_hs2lp: 35,
+            clock_lp2hs: 35,
+            dataline_max_read_time: 0,
+            stop_wait_time: 10,
+        });
+
+        // Panel setup in low-power mode, video in high speed afterwards
+        dsi.set_command_mode_transmission_kind(DsiCmdModeTransmissionKind::AllInLowPower);
+        dsi.start();
+        dsi.enable_bus_turn_around();
+
+        let panel_config = Otm8009AConfig {
+            frame_rate: FrameRate::_60Hz,
+            mode: Mode::Landscape,
+            color_map: ColorMap::Rgb,
+            cols: WIDTH as u16,
+            rows: HEIGHT as u16,
+        };
+        Otm8009A::new()
+            .init(&mut dsi, panel_config, &mut delay)
+            .map_err(|_| DisplayError::Panel)?;
+
+        dsi.set_command_mode_transmission_kind(DsiCmdModeTransmissionKind::AllInHighSpeed);
+        dsi.force_rx_low_power(true);
+        dsi.refresh();
+
+        ACTIVE.store(true, Ordering::Relaxed);
+
+        #[cfg(feature = "debug")]
+        defmt::info!("Display up: {}x{} OTM8009A", WIDTH, HEIGHT);
+
+        Ok(Self {
+            ltdc: controller,
+            _dsi: dsi,
+            _reset: reset,
+        })
+    }
+
+    /// Fills a rectangle, clipped to the screen
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u16) {
+        for py in y..(y + height).min(HEIGHT) {
+            for px in x..(x + width).min(WIDTH) {
+                self.ltdc.draw_pixel(Layer::L1, px, py, color);
+            }
+        }
+    }
+
+    /// Fills the whole screen
+    pub fn clear(&mut self, color: u16) {
+        self.fill_rect(0, 0, WIDTH, HEIGHT, color);
+    }
+
+    /// Draws one character cell with its top left corner at `(x0, y0)`
+    fn draw_char(&mut self, x0: usize, y0: usize, c: char, fg: u16, bg: u16) {
+        let columns = glyph(c);
+
+        for cx in 0..CELL_WIDTH / SCALE {
+            let bits = columns.get(cx).copied().unwrap_or(0);
+            for cy in 0..CELL_HEIGHT / SCALE {
+                let color = if cy < GLYPH_HEIGHT && bits & (1 << cy) != 0 {
+                    fg
+                } else {
+                    bg
+                };
+                self.fill_rect(x0 + cx * SCALE, y0 + cy * SCALE, SCALE, SCALE, color);
+            }
+        }
+    }
+
+    /// Writes a text line, padding the rest of the row with the background
+    ///
+    /// # Arguments
+    /// * `row` - Text row, `0..ROWS`; other rows are ignored
+    /// * `text` - Line text, cut at `COLUMNS` characters
+    /// * `fg` - Glyph color
+    /// * `bg` - Background color
+    pub fn draw_line(&mut self, row: usize, text: &str, fg: u16, bg: u16) {
+        if row >= ROWS {
+            return;
+        }
+        let mut chars = text.chars();
+        for column in 0..COLUMNS {
+            let c = chars.next().unwrap_or(' ');
+            self.draw_char(column * CELL_WIDTH, row * CELL_HEIGHT, c, fg, bg);
+        }
+    }
+
+    /// Writes text at a pixel position, outside the line grid
+    ///
+    /// # Arguments
+    /// * `x`, `y` - Top left corner of the first character cell
+    /// * `text` - Text, cut at the right edge of the screen
+    /// * `fg` - Glyph color
+    /// * `bg` - Background color of the character cells
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, fg: u16, bg: u16) {
+        for (i, c) in text.chars().enumerate() {
+            let x0 = x + i * CELL_WIDTH;
+            if x0 + CELL_WIDTH > WIDTH {
+                break;
+            }
+            self.draw_char(x0, y, c, fg, bg);
+        }
+    }
+}
+
+/// Checks whether the display was brought up
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// Writes the display state
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    if is_active() {
+        writeln!(
+            out,
+            "Display: {}x{} OTM8009A, {}x{} text, {} KB framebuffer",
+            WIDTH,
+            HEIGHT,
+            COLUMNS,
+            ROWS,
+            WIDTH * HEIGHT * 2 / 1024
+        )
+    } else {
+        writeln!(out, "Display: off")
+    }
+}
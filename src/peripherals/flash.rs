@@ -0,0 +1,111 @@
+//! # Internal Flash Controller
+//!
+//! Thin wrapper over the HAL flash driver for storing data in spare sectors:
+//! - Offset-based reads through the memory-mapped flash
+//! - Sector erase and byte programming with error mapping
+//! - Bounds checks against the device flash size
+//! - Erase and program failures retried under `FLASH_RETRY`
+//!
+//! ## Safety Considerations
+//! - Only sectors in bank 2 (12-23) should be used for data; erasing them does not
+//!   stall code execution from bank 1
+//! - Programming requires erased (0xFF) destination bytes
+
+use crate::errors::errors::FlashError;
+use crate::utils::retry::{Backoff, RetryPolicy};
+use stm32f4xx_hal::{
+    flash::{FlashExt, LockedFlash},
+    pac,
+};
+
+/// Flash erase/program recovery: two retries, 1 ms apart.
+/// Only controller failures are retried, never bounds or not-erased errors.
+pub static FLASH_RETRY: RetryPolicy = RetryPolicy::new("flash", 3, Backoff::Fixed(1), 0);
+
+/// Checks whether a flash error may go away on another attempt
+fn is_transient(error: &FlashError) -> bool {
+    matches!(error, FlashError::EraseError | FlashError::ProgramError)
+}
+
+/// Internal flash controller
+pub struct FlashController {
+    flash: LockedFlash,
+}
+
+impl FlashController {
+    /// Wraps the FLASH peripheral
+    ///
+    /// # Arguments
+    /// * `flash` - FLASH peripheral instance
+    pub fn new(flash: pac::FLASH) -> Self {
+        Self {
+            flash: LockedFlash::new(flash),
+        }
+    }
+
+    /// Returns a read-only view of flash contents
+    ///
+    /// # Arguments
+    /// * `offset` - Byte offset from the flash base address
+    /// * `length` - Number of bytes to read
+    ///
+    /// # Errors
+    /// Returns `FlashError::OutOfBounds` if the range exceeds flash size
+    pub fn read(&self, offset: usize, length: usize) -> Result<&[u8], FlashError> {
+        let end = offset.checked_add(length).ok_or(FlashError::OutOfBounds)?;
+        self.flash
+            .read()
+            .get(offset..end)
+            .ok_or(FlashError::OutOfBounds)
+    }
+
+    /// Erases a complete sector
+    ///
+    /// # Arguments
+    /// * `sector` - Sector number (0-23 on dual-bank devices)
+    ///
+    /// # Errors
+    /// Returns `FlashError::EraseError` if the operation fails
+    pub fn erase_sector(&mut self, sector: u8) -> Result<(), FlashError> {
+        #[cfg(feature = "debug")]
+        defmt::info!("Erasing flash sector {}", sector);
+
+        FLASH_RETRY.run(
+            || {
+                self.flash
+                    .unlocked()
+                    .erase(sector)
+                    .map_err(|_| FlashError::EraseError)
+            },
+            is_transient,
+        )
+    }
+
+    /// Programs bytes at the given offset
+    ///
+    /// # Arguments
+    /// * `offset` - Byte offset from the flash base address
+    /// * `data` - Bytes to write; destination must be erased
+    ///
+    /// # Errors
+    /// Returns:
+    /// - `FlashError::OutOfBounds` if the range exceeds flash size
+    /// - `FlashError::NotErased` if the destination holds data
+    /// - `FlashError::ProgramError` if programming fails
+    pub fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        FLASH_RETRY.run(
+            || {
+                // Checked on every attempt: a partial write must not be retried
+                if !self.read(offset, data.len())?.iter().all(|&b| b == 0xFF) {
+                    return Err(FlashError::NotErased);
+                }
+
+                self.flash
+                    .unlocked()
+                    .program(offset, data.iter())
+                    .map_err(|_| FlashError::ProgramError)
+            },
+            is_transient,
+        )
+    }
+}
@@ -0,0 +1,507 @@
+//! # I2C Master
+//!
+//! Polled I2C master on I2C1 or I2C2, driven through the registers instead of
+//! the HAL driver, whose flag waits have no time limit:
+//! - Standard (100 kHz) and fast (400 kHz) mode from the `PCLK1` clock
+//! - Every flag wait is bounded by `I2C_TIMEOUT_US`; a transfer that timed out
+//!   or hit a bus error resets the peripheral, so the next one starts clean
+//! - NACK, arbitration loss, bus errors and a stuck bus map to `I2cError`
+//! - `scan` probes every 7-bit address and reports the devices that answer
+//!
+//! ## Hardware Configuration
+//! - I2C1: SCL PB8, SDA PB9 (AF4, open drain, pull-ups on the board), shared
+//!   by the touch controller, the audio codec and the Arduino connector
+//! - I2C2 works the same way, but its pins on this board are taken by USART3
+//!   and the SDRAM, so the firmware does not set it up
+//!
+//! ## Safety Considerations
+//! - Transfers busy-wait, about 25 us per byte at 400 kHz: use the bus from
+//!   low-priority tasks only
+//! - A slave holding SDA low survives the peripheral reset; it needs a power
+//!   cycle or clock pulses on SCL, which this driver does not generate
+
+use crate::config::{I2C_TIMEOUT_US, PCLK1, SYSCLK};
+use crate::errors::errors::I2cError;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::ops::Deref;
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use stm32f4xx_hal::{
+    gpio::{
+        gpiob::{PB8, PB9},
+        Alternate, OpenDrain,
+    },
+    pac::{self, i2c1, I2C1, I2C2},
+};
+
+/// CR1 bits
+const CR1_PE: u32 = 1 << 0;
+const CR1_START: u32 = 1 << 8;
+const CR1_STOP: u32 = 1 << 9;
+const CR1_ACK: u32 = 1 << 10;
+const CR1_POS: u32 = 1 << 11;
+const CR1_SWRST: u32 = 1 << 15;
+
+/// CCR fast mode select
+const CCR_FS: u32 = 1 << 15;
+
+/// SR1 bits
+const SR1_SB: u32 = 1 << 0;
+const SR1_ADDR: u32 = 1 << 1;
+const SR1_BTF: u32 = 1 << 2;
+const SR1_RXNE: u32 = 1 << 6;
+const SR1_TXE: u32 = 1 << 7;
+const SR1_BERR: u32 = 1 << 8;
+const SR1_ARLO: u32 = 1 << 9;
+const SR1_AF: u32 = 1 << 10;
+
+/// SR2 bus busy
+const SR2_BUSY: u32 = 1 << 1;
+
+/// Busy-wait step of timeouts (microseconds)
+const WAIT_STEP_US: u32 = 1;
+
+/// Address range probed by `scan`; the addresses outside are reserved
+const SCAN_FIRST: u8 = 0x08;
+const SCAN_LAST: u8 = 0x77;
+
+/// Completed transfers, failed transfers (NACKs excluded) and peripheral resets
+static TRANSFERS: AtomicU32 = AtomicU32::new(0);
+static ERRORS: AtomicU32 = AtomicU32::new(0);
+static RECOVERIES: AtomicU32 = AtomicU32::new(0);
+
+/// Result of the most recent completed scan
+static LAST_SCAN: Mutex<RefCell<Option<ScanResult>>> = Mutex::new(RefCell::new(None));
+
+/// I2C1 pins
+pub type I2c1Pins = (PB8<Alternate<4, OpenDrain>>, PB9<Alternate<4, OpenDrain>>);
+
+/// I2C1 master on the board's shared bus
+pub type I2c1Bus = I2cMaster<I2C1, I2c1Pins>;
+
+/// I2C peripheral usable by `I2cMaster`
+pub trait Instance: Deref<Target = i2c1::RegisterBlock> {
+    /// Peripheral name for reports
+    const NAME: &'static str;
+    /// Enable and reset bit in RCC APB1ENR and APB1RSTR
+    const RCC_BIT: u32;
+}
+
+impl Instance for I2C1 {
+    const NAME: &'static str = "I2C1";
+    const RCC_BIT: u32 = 1 << 21;
+}
+
+impl Instance for I2C2 {
+    const NAME: &'static str = "I2C2";
+    const RCC_BIT: u32 = 1 << 22;
+}
+
+/// Bus clock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum I2cSpeed {
+    /// 100 kHz
+    Standard,
+    /// 400 kHz, 2:1 duty cycle
+    Fast,
+}
+
+/// Polled I2C master with bounded waits
+pub struct I2cMaster<I: Instance, P> {
+    i2c: I,
+    _pins: P,
+    speed: I2cSpeed,
+}
+
+impl<I: Instance, P> I2cMaster<I, P> {
+    /// Enables, resets and configures the peripheral
+    ///
+    /// # Arguments
+    /// * `i2c` - I2C peripheral
+    /// * `pins` - SCL and SDA, already in their open-drain alternate function
+    /// * `speed` - Bus clock
+    pub fn new(i2c: I, pins: P, speed: I2cSpeed) -> Self {
+        // SAFETY: Only the bit of this peripheral in the APB1 enable and
+        // reset registers is touched
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            rcc.apb1enr().modify(|r, w| w.bits(r.bits() | I::RCC_BIT));
+            rcc.apb1rstr().modify(|r, w| w.bits(r.bits() | I::RCC_BIT));
+            rcc.apb1rstr().modify(|r, w| w.bits(r.bits() & !I::RCC_BIT));
+        }
+
+        let mut master = Self {
+            i2c,
+            _pins: pins,
+            speed,
+        };
+        master.configure();
+        master
+    }
+
+    /// Writes bytes to a device
+    ///
+    /// # Errors
+    /// `Nack` if the device does not acknowledge its address or a byte,
+    /// `Timeout`, `Busy`, `ArbitrationLost` or `Bus` on a bus failure
+    pub fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), I2cError> {
+        self.transfer(|bus| {
+            bus.start(address, false)?;
+            bus.send(bytes)?;
+            bus.stop()
+        })
+    }
+
+    /// Reads bytes from a device
+    ///
+    /// # Errors
+    /// As for `write`
+    pub fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), I2cError> {
+        self.transfer(|bus| {
+            bus.start(address, true)?;
+            bus.receive(buffer)
+        })
+    }
+
+    /// Writes bytes, then reads after a repeated start, e.g. a register read
+    ///
+    /// # Errors
+    /// As for `write`
+    pub fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), I2cError> {
+        self.transfer(|bus| {
+            bus.start(address, false)?;
+            bus.send(bytes)?;
+            bus.start(address, true)?;
+            bus.receive(buffer)
+        })
+    }
+
+    /// Checks whether a device acknowledges its address
+    ///
+    /// # Errors
+    /// A bus failure; a missing device is `Ok(false)`
+    pub fn probe(&mut self, address: u8) -> Result<bool, I2cError> {
+        match self.write(address, &[]) {
+            Ok(()) => Ok(true),
+            Err(I2cError::Nack) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Probes every non-reserved 7-bit address
+    ///
+    /// The result is kept for `write_report`.
+    ///
+    /// # Errors
+    /// The first bus failure; the scan stops there
+    pub fn scan(&mut self) -> Result<ScanResult, I2cError> {
+        let mut result = ScanResult {
+            bus: I::NAME,
+            found: 0,
+        };
+        for address in SCAN_FIRST..=SCAN_LAST {
+            if self.probe(address)? {
+                result.found |= 1 << address;
+            }
+        }
+        interrupt::free(|cs| *LAST_SCAN.borrow(cs).borrow_mut() = Some(result));
+        Ok(result)
+    }
+
+    /// Sets the clock registers and enables the peripheral
+    fn configure(&mut self) {
+        let freq_mhz = PCLK1 / 1_000_000;
+        let (ccr, trise) = match self.speed {
+            // 1000 ns maximum rise time
+            I2cSpeed::Standard => (PCLK1 / (2 * 100_000), freq_mhz + 1),
+            // 300 ns maximum rise time; rounded up to stay below 400 kHz
+            I2cSpeed::Fast => (
+                CCR_FS | PCLK1.div_ceil(3 * 400_000),
+                freq_mhz * 300 / 1_000 + 1,
+            ),
+        };
+
+        // SAFETY: Register values from RM0386 for the configured APB1 clock
+        unsafe {
+            self.i2c.cr1().write(|w| w.bits(0));
+            self.i2c.cr2().write(|w| w.bits(freq_mhz));
+            self.i2c.ccr().write(|w| w.bits(ccr));
+            self.i2c.trise().write(|w| w.bits(trise));
+            self.i2c.cr1().write(|w| w.bits(CR1_PE));
+        }
+    }
+
+    /// Runs one transaction and recovers the peripheral after a failure
+    fn transfer(
+        &mut self,
+        transaction: impl FnOnce(&mut Self) -> Result<(), I2cError>,
+    ) -> Result<(), I2cError> {
+        let result = self.wait_idle().and_then(|_| transaction(self));
+        match result {
+            Ok(()) => {
+                TRANSFERS.fetch_add(1, Ordering::Relaxed);
+            }
+            // The STOP is already on its way
+            Err(I2cError::Nack) => {
+                if self.wait_stop().is_err() {
+                    self.recover();
+                }
+            }
+            Err(_e) => {
+                ERRORS.fetch_add(1, Ordering::Relaxed);
+                self.recover();
+
+                #[cfg(feature = "debug")]
+                defmt::warn!("{}: {}", I::NAME, _e.description());
+            }
+        }
+        result
+    }
+
+    /// Generates a (repeated) START and sends the address
+    ///
+    /// Leaves ADDR set: `send` and `receive` clear it at the right moment.
+    fn start(&mut self, address: u8, read: bool) -> Result<(), I2cError> {
+        self.modify_cr1(0, CR1_START | CR1_ACK);
+        self.wait_flag(SR1_SB)?;
+        // SAFETY: DR takes the address byte after SB
+        unsafe {
+            self.i2c
+                .dr()
+                .write(|w| w.bits((u32::from(address) << 1) | u32::from(read)));
+        }
+        self.wait_flag(SR1_ADDR)
+    }
+
+    /// Sends bytes after the address phase of a write
+    fn send(&mut self, bytes: &[u8]) -> Result<(), I2cError> {
+        self.clear_addr();
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        for &byte in bytes {
+            self.wait_flag(SR1_TXE)?;
+            // SAFETY: DR takes the next byte once TXE is set
+            unsafe { self.i2c.dr().write(|w| w.bits(u32::from(byte))) };
+        }
+        self.wait_flag(SR1_BTF)
+    }
+
+    /// Receives bytes after the address phase of a read and ends with STOP
+    ///
+    /// Follows the RM0386 sequences, which hold the last bytes in DR and the
+    /// shift register (BTF) before NACK and STOP are set, so a preempted task
+    /// cannot acknowledge one byte too many.
+    fn receive(&mut self, buffer: &mut [u8]) -> Result<(), I2cError> {
+        match buffer {
+            [] => {
+                self.clear_addr();
+                self.stop()
+            }
+            [only] => {
+                self.modify_cr1(CR1_ACK, 0);
+                // STOP must follow the ADDR clear before the byte completes
+                interrupt::free(|_| {
+                    self.clear_addr();
+                    self.modify_cr1(0, CR1_STOP);
+                });
+                self.wait_flag(SR1_RXNE)?;
+                *only = self.read_dr();
+                self.wait_stop()
+            }
+            [first, second] => {
+                // POS: the NACK applies to the byte after the current one
+                self.modify_cr1(0, CR1_POS);
+                self.clear_addr();
+                self.modify_cr1(CR1_ACK, 0);
+                let result = self.wait_flag(SR1_BTF).map(|_| {
+                    self.modify_cr1(0, CR1_STOP);
+                    *first = self.read_dr();
+                    *second = self.read_dr();
+                });
+                self.modify_cr1(CR1_POS, 0);
+                result?;
+                self.wait_stop()
+            }
+            [head @ .., third_last, second_last, last] => {
+                self.clear_addr();
+                for byte in head.iter_mut() {
+                    self.wait_flag(SR1_RXNE)?;
+                    *byte = self.read_dr();
+                }
+                // Byte N-2 in DR, N-1 in the shift register
+                self.wait_flag(SR1_BTF)?;
+                self.modify_cr1(CR1_ACK, 0);
+                *third_last = self.read_dr();
+                // Byte N-1 in DR, N (not acknowledged) in the shift register
+                self.wait_flag(SR1_BTF)?;
+                self.modify_cr1(0, CR1_STOP);
+                *second_last = self.read_dr();
+                *last = self.read_dr();
+                self.wait_stop()
+            }
+        }
+    }
+
+    /// Clears then sets CR1 bits
+    fn modify_cr1(&self, clear: u32, set: u32) {
+        // SAFETY: Callers only pass the START, STOP, ACK and POS control bits
+        unsafe {
+            self.i2c
+                .cr1()
+                .modify(|r, w| w.bits((r.bits() & !clear) | set));
+        }
+    }
+
+    /// Reads one received byte
+    fn read_dr(&self) -> u8 {
+        self.i2c.dr().read().bits() as u8
+    }
+
+    /// Generates a STOP and waits until it went out
+    fn stop(&mut self) -> Result<(), I2cError> {
+        self.modify_cr1(0, CR1_STOP);
+        self.wait_stop()
+    }
+
+    /// Clears ADDR by reading SR1 then SR2
+    fn clear_addr(&self) {
+        let _ = self.i2c.sr1().read();
+        let _ = self.i2c.sr2().read();
+    }
+
+    /// Waits for an SR1 flag, failing on NACK and bus errors
+    fn wait_flag(&mut self, flag: u32) -> Result<(), I2cError> {
+        let mut waited_us = 0;
+        loop {
+            let sr1 = self.i2c.sr1().read().bits();
+            if sr1 & (SR1_AF | SR1_ARLO | SR1_BERR) != 0 {
+                // SAFETY: The error flags are cleared by writing 0, other
+                // SR1 bits ignore writes
+                unsafe { self.i2c.sr1().write(|w| w.bits(0)) };
+                return Err(if sr1 & SR1_AF != 0 {
+                    // Release the bus; the caller waits for the STOP
+                    self.modify_cr1(0, CR1_STOP);
+                    I2cError::Nack
+                } else if sr1 & SR1_ARLO != 0 {
+                    I2cError::ArbitrationLost
+                } else {
+                    I2cError::Bus
+                });
+            }
+            if sr1 & flag != 0 {
+                return Ok(());
+            }
+            if waited_us >= I2C_TIMEOUT_US {
+                return Err(I2cError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        }
+    }
+
+    /// Waits until a requested STOP went out
+    fn wait_stop(&self) -> Result<(), I2cError> {
+        self.wait_until(
+            |i2c| i2c.cr1().read().bits() & CR1_STOP == 0,
+            I2cError::Timeout,
+        )
+    }
+
+    /// Waits until no other master or stuck slave occupies the bus
+    fn wait_idle(&self) -> Result<(), I2cError> {
+        self.wait_until(
+            |i2c| i2c.sr2().read().bits() & SR2_BUSY == 0,
+            I2cError::Busy,
+        )
+    }
+
+    /// Polls `done` until it holds, failing with `error` after the timeout
+    fn wait_until(&self, done: impl Fn(&I) -> bool, error: I2cError) -> Result<(), I2cError> {
+        let mut waited_us = 0;
+        while !done(&self.i2c) {
+            if waited_us >= I2C_TIMEOUT_US {
+                return Err(error);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        }
+        Ok(())
+    }
+
+    /// Resets the peripheral and configures it again
+    fn recover(&mut self) {
+        // SAFETY: SWRST resets the peripheral state machine and registers
+        unsafe {
+            self.i2c.cr1().write(|w| w.bits(CR1_SWRST));
+            self.i2c.cr1().write(|w| w.bits(0));
+        }
+        self.configure();
+        RECOVERIES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Addresses that answered a `scan`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScanResult {
+    bus: &'static str,
+    /// Bit n set if address n acknowledged
+    found: u128,
+}
+
+impl ScanResult {
+    /// Checks whether a device answered at `address`
+    pub fn contains(&self, address: u8) -> bool {
+        address < 128 && self.found & (1 << address) != 0
+    }
+
+    /// Number of devices found
+    pub fn count(&self) -> u32 {
+        self.found.count_ones()
+    }
+
+    /// Addresses that answered, ascending
+    pub fn addresses(&self) -> impl Iterator<Item = u8> + '_ {
+        (SCAN_FIRST..=SCAN_LAST).filter(|&address| self.contains(address))
+    }
+
+    /// Writes the bus name and the addresses found
+    pub fn write_report<W: Write>(&self, out: &mut W) -> fmt::Result {
+        write!(out, "{}: {} device(s)", self.bus, self.count())?;
+        for address in self.addresses() {
+            write!(out, " {:#04x}", address)?;
+        }
+        writeln!(out)
+    }
+}
+
+fn busy_wait_us(us: u32) {
+    cortex_m::asm::delay(SYSCLK / 1_000_000 * us);
+}
+
+/// Result of the most recent completed scan
+pub fn last_scan() -> Option<ScanResult> {
+    interrupt::free(|cs| *LAST_SCAN.borrow(cs).borrow())
+}
+
+/// Writes the transfer counters and the last scan result
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "I2C: {} transfers, {} errors, {} resets, timeout {} us",
+        TRANSFERS.load(Ordering::Relaxed),
+        ERRORS.load(Ordering::Relaxed),
+        RECOVERIES.load(Ordering::Relaxed),
+        I2C_TIMEOUT_US
+    )?;
+    match last_scan() {
+        Some(scan) => scan.write_report(out),
+        None => writeln!(out, "No scan yet, run `i2c scan`"),
+    }
+}
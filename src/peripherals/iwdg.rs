@@ -0,0 +1,180 @@
+//! # Independent Watchdog (IWDG)
+//!
+//! Resets the board when a task hangs instead of leaving the bridge silently
+//! dead:
+//! - The USB, USART and LED paths report liveness with `check_in`, which
+//!   sets their bit in an atomic mask. USB and USART check in where the work
+//!   happens, in the OTG FS handler and the UART DMA completion handlers;
+//!   their periodic tasks only check in for an idle bus or line, so a stuck
+//!   stream or a dead USB stack stops feeding the watchdog
+//! - The watchdog task calls `Iwdg::service` every `IWDG_CHECK_MS`; the IWDG is
+//!   only reloaded when every bit was set since the previous call
+//! - Missing check-ins leave the counter running, so a task that stays silent
+//!   for `IWDG_TIMEOUT_MS` resets the MCU
+//!
+//! ## Hardware Configuration
+//! - Clocked by the LSI (nominal 32 kHz, 17-47 kHz over temperature and parts);
+//!   the timeout is approximate
+//! - Once started, the IWDG cannot be stopped until the next reset
+//! - With the `debug` feature the counter is frozen while the core is halted
+//!
+//! ## Safety Considerations
+//! - `IWDG_TIMEOUT_MS` must cover the longest blocking operation at the
+//!   watchdog task's priority, such as a flash sector erase
+//! - The IWDG keeps counting in Stop mode, where no task can check in; the
+//!   Stop mode loop reloads it with `reload_in_stop` after every wakeup
+
+use crate::config::IWDG_TIMEOUT_MS;
+use bitflags::bitflags;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use stm32f4xx_hal::pac::{self, IWDG};
+
+/// Nominal LSI frequency (Hz)
+const LSI_HZ: u32 = 32_000;
+
+/// Key register values
+const KEY_RELOAD: u32 = 0xAAAA;
+const KEY_UNLOCK: u32 = 0x5555;
+const KEY_START: u32 = 0xCCCC;
+
+/// Largest reload value (12 bits)
+const RELOAD_MAX: u32 = 0x0FFF;
+
+/// Largest prescaler setting (divide by 256)
+const PRESCALER_MAX: u8 = 6;
+
+bitflags! {
+    /// Tasks that must report liveness before the watchdog is fed
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct CheckIn: u8 {
+        const USB   = 1 << 0; // OTG FS handler, console loop while idle
+        const USART = 1 << 1; // UART DMA handlers, periodic guard while idle
+        const LED   = 1 << 2; // Red LED error display
+    }
+}
+
+/// Check-ins received since the last service
+static CHECK_INS: AtomicU8 = AtomicU8::new(0);
+
+/// Service windows that ended without every check-in
+static MISSED_WINDOWS: AtomicU32 = AtomicU32::new(0);
+
+/// Set when the IWDG caused the reset before this boot
+static WATCHDOG_RESET: AtomicBool = AtomicBool::new(false);
+
+/// Reports liveness of a task
+///
+/// Cheap enough to call from every loop iteration or interrupt.
+pub fn check_in(task: CheckIn) {
+    CHECK_INS.fetch_or(task.bits(), Ordering::Relaxed);
+}
+
+/// Checks whether the IWDG caused the last reset
+pub fn caused_last_reset() -> bool {
+    WATCHDOG_RESET.load(Ordering::Relaxed)
+}
+
+/// Independent watchdog driver
+pub struct Iwdg {
+    iwdg: IWDG,
+}
+
+impl Iwdg {
+    /// Starts the watchdog
+    ///
+    /// Records and clears the reset flags first, so `caused_last_reset`
+    /// reports whether this boot follows a watchdog reset.
+    ///
+    /// # Arguments
+    /// * `iwdg` - IWDG peripheral instance
+    pub fn start(iwdg: IWDG) -> Self {
+        // SAFETY: RCC_CSR reset flags and DBGMCU are not used by any other driver
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            WATCHDOG_RESET.store(rcc.csr().read().iwdgrstf().bit_is_set(), Ordering::Relaxed);
+            rcc.csr().modify(|_, w| w.rmvf().set_bit());
+
+            #[cfg(feature = "debug")]
+            (*pac::DBGMCU::ptr())
+                .apb1_fz()
+                .modify(|_, w| w.dbg_iwdg_stop().set_bit());
+        }
+
+        let (prescaler, reload) = Self::divider(IWDG_TIMEOUT_MS);
+
+        iwdg.kr().write(|w| unsafe { w.bits(KEY_START) });
+        iwdg.kr().write(|w| unsafe { w.bits(KEY_UNLOCK) });
+        iwdg.pr().write(|w| unsafe { w.bits(prescaler as u32) });
+        iwdg.rlr().write(|w| unsafe { w.bits(reload) });
+        // New values take effect once the LSI domain has taken them over
+        while iwdg.sr().read().bits() != 0 {}
+        iwdg.kr().write(|w| unsafe { w.bits(KEY_RELOAD) });
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "IWDG started: ~{} ms, last reset by watchdog: {}",
+            IWDG_TIMEOUT_MS,
+            caused_last_reset()
+        );
+
+        Self { iwdg }
+    }
+
+    /// Smallest prescaler that fits the timeout into the reload register
+    ///
+    /// # Returns
+    /// `(prescaler setting, reload value)`; timeouts beyond the hardware range
+    /// are clamped to the longest one (~32 s)
+    fn divider(timeout_ms: u32) -> (u8, u32) {
+        for prescaler in 0..=PRESCALER_MAX {
+            let ticks = timeout_ms * (LSI_HZ / 1_000) / (4 << prescaler);
+            if ticks <= RELOAD_MAX + 1 {
+                return (prescaler, ticks.saturating_sub(1));
+            }
+        }
+        (PRESCALER_MAX, RELOAD_MAX)
+    }
+
+    /// Reloads the counter unconditionally
+    pub fn feed(&mut self) {
+        self.iwdg.kr().write(|w| unsafe { w.bits(KEY_RELOAD) });
+    }
+
+    /// Feeds the watchdog if every task checked in since the last call
+    ///
+    /// # Errors
+    /// Returns the tasks that did not check in; the counter keeps running
+    pub fn service(&mut self) -> Result<(), CheckIn> {
+        let seen = CheckIn::from_bits_truncate(CHECK_INS.swap(0, Ordering::Relaxed));
+        let missing = CheckIn::all().difference(seen);
+
+        if missing.is_empty() {
+            self.feed();
+            Ok(())
+        } else {
+            MISSED_WINDOWS.fetch_add(1, Ordering::Relaxed);
+            Err(missing)
+        }
+    }
+}
+
+/// Reloads the counter outside the check-in scheme
+///
+/// Only for the USB suspend Stop mode loop, which wakes up at least twice
+/// per `IWDG_TIMEOUT_MS` while no task runs.
+pub fn reload_in_stop() {
+    // SAFETY: KR is write-only; the reload key has no other effect
+    unsafe { (*IWDG::ptr()).kr().write(|w| w.bits(KEY_RELOAD)) };
+}
+
+/// Writes the watchdog timeout, missed windows and reset cause
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Watchdog: ~{} ms, missed windows {}, last reset by watchdog: {}",
+        IWDG_TIMEOUT_MS,
+        MISSED_WINDOWS.load(Ordering::Relaxed),
+        if caused_last_reset() { "yes" } else { "no" }
+    )
+}
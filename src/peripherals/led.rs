@@ -0,0 +1,159 @@
+//! # Discovery Board LEDs
+//!
+//! Generic controller for the four user LEDs of the STM32F469I-DISCO:
+//! - `Led<PIN>` works with any push-pull output pin and implements `GpioPin`
+//! - State tracking without reading the pin back
+//! - Aliases for LD1-LD4; all of them are active low
+//!
+//! | LED | Color  | Pin | Use                              |
+//! |-----|--------|-----|----------------------------------|
+//! | LD1 | Green  | PG6 | USB activity                     |
+//! | LD2 | Orange | PD4 | UART activity                    |
+//! | LD3 | Red    | PD5 | Error codes (see `red_led`)      |
+//! | LD4 | Blue   | PK3 | System status                    |
+
+use crate::errors::errors::LedError;
+use crate::peripherals::traits::GpioPin;
+use core::fmt;
+use stm32f4xx_hal::gpio::{gpiod::PD4, gpiog::PG6, gpiok::PK3, Output, Pin, PushPull};
+use stm32f4xx_hal::pac;
+
+/// Green LED LD1 (PG6)
+pub type GreenLed = Led<PG6<Output<PushPull>>>;
+
+/// Orange LED LD2 (PD4)
+pub type OrangeLed = Led<PD4<Output<PushPull>>>;
+
+/// Blue LED LD4 (PK3)
+pub type BlueLed = Led<PK3<Output<PushPull>>>;
+
+/// Turns all four LEDs off without their drivers
+///
+/// For the shutdown, when the drivers belong to tasks that no longer run;
+/// their tracked state is not updated. Call with interrupts disabled, so no
+/// driver turns an LED on again.
+pub fn all_off() {
+    // SAFETY: BSRR writes are atomic and only set the LED pins, which turns
+    // the active-low LEDs off
+    unsafe {
+        (*pac::GPIOG::ptr()).bsrr().write(|w| w.bits(1 << 6));
+        (*pac::GPIOD::ptr())
+            .bsrr()
+            .write(|w| w.bits((1 << 4) | (1 << 5)));
+        (*pac::GPIOK::ptr()).bsrr().write(|w| w.bits(1 << 3));
+    }
+}
+
+/// Output pin able to drive an LED
+pub trait LedPin {
+    /// Drives the pin high
+    fn drive_high(&mut self);
+    /// Drives the pin low
+    fn drive_low(&mut self);
+    /// Checks the output latch
+    fn is_driven_high(&self) -> bool;
+}
+
+impl<const P: char, const N: u8> LedPin for Pin<P, N, Output<PushPull>> {
+    fn drive_high(&mut self) {
+        self.set_high();
+    }
+
+    fn drive_low(&mut self) {
+        self.set_low();
+    }
+
+    fn is_driven_high(&self) -> bool {
+        self.is_set_high()
+    }
+}
+
+/// Active-low LED controller with state tracking
+#[derive(Debug)]
+pub struct Led<PIN> {
+    pin: PIN,
+    state: bool,
+}
+
+impl<PIN: LedPin> Led<PIN> {
+    /// Creates the controller with the LED initially ON
+    ///
+    /// # Arguments
+    /// * `pin` - Pin in push-pull output mode
+    pub fn init_on(pin: PIN) -> Self {
+        let mut led = Led { pin, state: false };
+        led.on();
+        led
+    }
+
+    /// Creates the controller with the LED initially OFF
+    ///
+    /// # Arguments
+    /// * `pin` - Pin in push-pull output mode
+    pub fn init_off(pin: PIN) -> Self {
+        let mut led = Led { pin, state: true };
+        led.off();
+        led
+    }
+
+    /// Turns the LED on
+    pub fn on(&mut self) {
+        self.pin.drive_low();
+        self.state = true;
+    }
+
+    /// Turns the LED off
+    pub fn off(&mut self) {
+        self.pin.drive_high();
+        self.state = false;
+    }
+
+    /// Turns the LED on or off
+    pub fn set(&mut self, on: bool) {
+        if on {
+            self.on();
+        } else {
+            self.off();
+        }
+    }
+
+    /// Gets current LED state (`true` = ON)
+    pub fn state(&self) -> bool {
+        self.state
+    }
+}
+
+/// GPIO Pin trait implementation
+impl<PIN: LedPin> GpioPin for Led<PIN> {
+    type Error = LedError;
+
+    /// Sets LED to high state (OFF)
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.off();
+        Ok(())
+    }
+
+    /// Sets LED to low state (ON)
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.on();
+        Ok(())
+    }
+
+    /// Checks if LED is in high state
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.pin.is_driven_high())
+    }
+
+    /// Toggles LED state
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        self.set(!self.state);
+        Ok(())
+    }
+}
+
+/// Debug display implementation
+impl<PIN> fmt::Display for Led<PIN> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LED: {}", if self.state { "ON" } else { "OFF" })
+    }
+}
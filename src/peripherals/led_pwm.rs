@@ -0,0 +1,207 @@
+//! # Status LED PWM
+//!
+//! Brightness control for the blue and red LEDs, so they can breathe and
+//! fade instead of only switching. The Discovery board wires them to pins
+//! without a timer output (PK3, PD5), so TIM4 generates the PWM in its
+//! interrupt:
+//! - The update event starts each period and turns on every channel with a
+//!   non-zero level
+//! - Compare channel 1 (blue) and 2 (red) end the on-time
+//! - 8-bit levels on a square-law curve, so fades look even to the eye
+//! - `PWM_HZ` period, flicker-free, at most three interrupts per period
+//!
+//! Levels are set lock-free with `set_level` from any context and apply from
+//! the next period. A channel at level 0 is released: the interrupt drives it
+//! off once and then leaves the pin to its `Led` driver.
+//!
+//! ## Hardware Configuration
+//! - TIM4 (no pins), counting at `TICK_HZ` from the APB1 timer clock
+//! - Pins driven through BSRR, atomic with respect to the `Led` drivers
+//!
+//! Built with the `led-pwm` feature.
+
+use crate::peripherals::rcc::RccConfig;
+use core::sync::atomic::{AtomicU8, Ordering};
+use stm32f4xx_hal::{
+    pac::{self, TIM4},
+    rcc::{Enable, Reset},
+};
+
+/// PWM frequency (Hz)
+pub const PWM_HZ: u32 = 200;
+
+/// Highest brightness level
+pub const MAX_LEVEL: u8 = u8::MAX;
+
+/// Timer count rate (Hz)
+const TICK_HZ: u32 = 1_000_000;
+
+/// Timer ticks per PWM period
+const PERIOD_TICKS: u32 = TICK_HZ / PWM_HZ;
+
+/// Blue LED pin (PK3)
+const BLUE_LED_PIN: u32 = 3;
+
+/// Red LED pin (PD5)
+const RED_LED_PIN: u32 = 5;
+
+/// TIMx_SR / TIMx_DIER bits
+const UIF: u32 = 1 << 0;
+const CC1IF: u32 = 1 << 1;
+const CC2IF: u32 = 1 << 2;
+
+/// LEDs with a PWM channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PwmChannel {
+    /// Blue LED LD4 (PK3), compare channel 1
+    Blue,
+    /// Red LED LD3 (PD5), compare channel 2
+    Red,
+}
+
+impl PwmChannel {
+    const ALL: [PwmChannel; 2] = [PwmChannel::Blue, PwmChannel::Red];
+
+    const fn index(self) -> usize {
+        match self {
+            PwmChannel::Blue => 0,
+            PwmChannel::Red => 1,
+        }
+    }
+
+    /// Compare interrupt flag / enable bit of the channel
+    const fn compare_flag(self) -> u32 {
+        match self {
+            PwmChannel::Blue => CC1IF,
+            PwmChannel::Red => CC2IF,
+        }
+    }
+
+    /// Drives the LED pin; the LEDs are active low, the pin is reset to light them
+    fn drive(self, on: bool) {
+        let bsrr = |pin: u32| if on { 1 << (pin + 16) } else { 1 << pin };
+
+        // SAFETY: BSRR writes are atomic and only touch the LED pin
+        unsafe {
+            match self {
+                PwmChannel::Blue => (*pac::GPIOK::ptr())
+                    .bsrr()
+                    .write(|w| w.bits(bsrr(BLUE_LED_PIN))),
+                PwmChannel::Red => (*pac::GPIOD::ptr())
+                    .bsrr()
+                    .write(|w| w.bits(bsrr(RED_LED_PIN))),
+            }
+        }
+    }
+}
+
+/// Requested level per channel
+static LEVELS: [AtomicU8; 2] = [AtomicU8::new(0), AtomicU8::new(0)];
+
+/// Sets the brightness of an LED
+///
+/// # Arguments
+/// * `channel` - LED to dim
+/// * `level` - 0 releases the LED, `MAX_LEVEL` is fully on
+pub fn set_level(channel: PwmChannel, level: u8) {
+    LEVELS[channel.index()].store(level, Ordering::Relaxed);
+}
+
+/// Requested brightness of an LED
+pub fn level(channel: PwmChannel) -> u8 {
+    LEVELS[channel.index()].load(Ordering::Relaxed)
+}
+
+/// On-time of a level in timer ticks, on a square-law curve
+fn on_ticks(level: u8) -> u32 {
+    let level = level as u32;
+    let max = MAX_LEVEL as u32;
+    level * level * PERIOD_TICKS / (max * max)
+}
+
+/// Interrupt-driven PWM generator on TIM4
+pub struct LedPwm {
+    tim: TIM4,
+    active: [bool; 2],
+}
+
+impl LedPwm {
+    /// Starts TIM4 with the update interrupt enabled and all LEDs released
+    ///
+    /// # Arguments
+    /// * `tim` - TIM4 peripheral instance
+    /// * `clocks` - System clock configuration
+    pub fn new(tim: TIM4, clocks: &RccConfig) -> Self {
+        // SAFETY: TIM4 is owned exclusively by this driver
+        unsafe {
+            TIM4::enable_unchecked();
+            TIM4::reset_unchecked();
+        }
+
+        let prescaler = (clocks.clocks.timclk1().raw() / TICK_HZ).saturating_sub(1);
+        tim.psc().write(|w| w.psc().bits(prescaler as u16));
+        tim.arr().write(|w| unsafe { w.bits(PERIOD_TICKS - 1) });
+        tim.egr().write(|w| w.ug().set_bit());
+        tim.sr().write(|w| unsafe { w.bits(0) });
+        tim.dier().write(|w| unsafe { w.bits(UIF) });
+        tim.cr1().write(|w| w.cen().set_bit());
+
+        Self {
+            tim,
+            active: [false; 2],
+        }
+    }
+
+    /// Handles the TIM4 interrupt
+    pub fn on_interrupt(&mut self) {
+        let dier = self.tim.dier().read().bits();
+        let pending = self.tim.sr().read().bits() & dier;
+        // Flags are cleared by writing 0, other bits are left alone
+        self.tim
+            .sr()
+            .write(|w| unsafe { w.bits(!pending & 0xFFFF) });
+
+        // A compare flag still pending with the update belongs to the ending period
+        for channel in PwmChannel::ALL {
+            if pending & channel.compare_flag() != 0 {
+                channel.drive(false);
+            }
+        }
+        if pending & UIF != 0 {
+            self.start_period();
+        }
+    }
+
+    /// Turns on the active channels and arms their compare interrupts
+    fn start_period(&mut self) {
+        let mut dier = UIF;
+        for channel in PwmChannel::ALL {
+            let ticks = on_ticks(level(channel));
+            let index = channel.index();
+
+            if ticks == 0 {
+                if self.active[index] {
+                    channel.drive(false);
+                    self.active[index] = false;
+                }
+                continue;
+            }
+
+            channel.drive(true);
+            self.active[index] = true;
+            if ticks < PERIOD_TICKS {
+                self.write_compare(channel, ticks);
+                dier |= channel.compare_flag();
+            }
+        }
+        self.tim.dier().write(|w| unsafe { w.bits(dier) });
+    }
+
+    fn write_compare(&mut self, channel: PwmChannel, ticks: u32) {
+        // SAFETY: Any compare value below the period is valid
+        match channel {
+            PwmChannel::Blue => self.tim.ccr1().write(|w| unsafe { w.bits(ticks) }),
+            PwmChannel::Red => self.tim.ccr2().write(|w| unsafe { w.bits(ticks) }),
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! # Stop Mode
+//!
+//! Low-power entry used while the USB bus is suspended:
+//! - Stop mode with the regulator in low-power mode (PWR CR.LPDS); all 1.2 V
+//!   domain clocks stop, SRAM and registers are kept
+//! - Wakeup through EXTI: USB resume (line 18), RTC wakeup timer (line 22),
+//!   UART start bit on PG9 (line 9) and the user button (line 0)
+//! - The core restarts on the HSI; `stop` brings back the HSE, the PLL and
+//!   the over-drive before any interrupt handler runs, or only the HSE at
+//!   the low-frequency level of `clock_scaling`
+//!
+//! ## Hardware Configuration
+//! - EXTI line 9 is routed to port G (SYSCFG EXTICR3) and only unmasked
+//!   just before Stop; its handler masks it again, so UART traffic does not
+//!   interrupt while running
+//! - The OTG FS PHY clock is gated (PCGCCTL) while stopped
+//!
+//! ## Safety Considerations
+//! - SysTick stops as well: monotonic time stands still while stopped
+//! - The UART byte that wakes the MCU is lost, the USART is unclocked until
+//!   the PLL runs again
+//! - With the `debug` feature, DBGMCU keeps the debug link alive in Stop
+
+use stm32f4xx_hal::pac;
+
+use crate::config::SYSCLK;
+use crate::peripherals::clock_scaling::{self, ClockLevel};
+use crate::peripherals::rtc;
+
+/// Highest system clock without the over-drive (Hz)
+const MAX_SYSCLK_WITHOUT_OVERDRIVE: u32 = 168_000_000;
+
+/// EXTI lines used as wakeup sources
+const EXTI_UART_LINE: u32 = 1 << 9;
+const EXTI_USB_WAKEUP_LINE: u32 = 1 << 18;
+
+/// SYSCFG EXTICR3 field of EXTI line 9 and its port G setting
+const EXTICR3_LINE9_MASK: u32 = 0xF << 4;
+const EXTICR3_LINE9_PORT_G: u32 = 0x6 << 4;
+
+/// RCC CR bits
+const CR_HSEON: u32 = 1 << 16;
+const CR_HSERDY: u32 = 1 << 17;
+const CR_PLLON: u32 = 1 << 24;
+const CR_PLLRDY: u32 = 1 << 25;
+
+/// RCC CFGR system clock switch: HSE or PLL
+const CFGR_SW_MASK: u32 = 0b11;
+const CFGR_SW_HSE: u32 = 0b01;
+const CFGR_SW_PLL: u32 = 0b10;
+const CFGR_SWS_HSE: u32 = 0b01 << 2;
+const CFGR_SWS_PLL: u32 = 0b10 << 2;
+const CFGR_SWS_MASK: u32 = 0b11 << 2;
+
+/// PWR CR/CSR bits
+const PWR_CR_LPDS: u32 = 1 << 0;
+const PWR_CR_PDDS: u32 = 1 << 1;
+const PWR_CR_ODEN: u32 = 1 << 16;
+const PWR_CR_ODSWEN: u32 = 1 << 17;
+const PWR_CSR_ODRDY: u32 = 1 << 16;
+const PWR_CSR_ODSWRDY: u32 = 1 << 17;
+
+/// OTG FS PCGCCTL bits: stop PHY clock, gate HCLK
+const PCGCCTL_STPPCLK: u32 = 1 << 0;
+const PCGCCTL_GATEHCLK: u32 = 1 << 1;
+
+/// SCB SCR.SLEEPDEEP
+const SCR_SLEEPDEEP: u32 = 1 << 2;
+
+/// Polling limit for clock ready flags (cycles of a tight loop)
+const FLAG_POLL_LIMIT: u32 = 100_000;
+
+/// Wakeup sources with an interrupt handler of their own
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WakeSource {
+    /// OTG_FS_WKUP: resume signalling on the bus
+    Usb,
+    /// EXTI9_5: start bit on the UART RX line
+    Uart,
+    /// RTC_WKUP: end of the Stop period
+    Rtc,
+}
+
+/// Enters Stop mode until the next wakeup event
+///
+/// Runs with interrupts disabled, so a wakeup that arrives between the
+/// `proceed` check and the WFI still ends Stop immediately; its handler runs
+/// once the clocks are restored.
+///
+/// # Arguments
+/// * `period_ms` - Longest Stop period (RTC wakeup timer)
+/// * `proceed` - Checked with interrupts disabled; Stop is skipped if `false`
+///
+/// # Returns
+/// `true` if the MCU was stopped, `false` if skipped or the RTC wakeup timer
+/// is unavailable
+pub fn stop(period_ms: u32, proceed: impl FnOnce() -> bool) -> bool {
+    cortex_m::interrupt::free(|_| {
+        if !proceed() || !rtc::arm_wakeup(period_ms) {
+            return false;
+        }
+
+        // SAFETY: EXTI lines 9/18, EXTICR3 line 9, PCGCCTL, PWR CR.LPDS/PDDS
+        // and SCB SCR.SLEEPDEEP are only written here; RCC is restored to the
+        // configuration set up by `RccConfig`
+        unsafe {
+            let exti = &*pac::EXTI::ptr();
+            let syscfg = &*pac::SYSCFG::ptr();
+            let pwr = &*pac::PWR::ptr();
+            let pcgcctl = (*pac::OTG_FS_PWRCLK::ptr()).fs_pcgcctl();
+            let scb = &*cortex_m::peripheral::SCB::PTR;
+
+            syscfg
+                .exticr3()
+                .modify(|r, w| w.bits((r.bits() & !EXTICR3_LINE9_MASK) | EXTICR3_LINE9_PORT_G));
+            exti.ftsr().modify(|r, w| w.bits(r.bits() | EXTI_UART_LINE));
+            exti.rtsr()
+                .modify(|r, w| w.bits((r.bits() & !EXTI_UART_LINE) | EXTI_USB_WAKEUP_LINE));
+            exti.pr()
+                .write(|w| w.bits(EXTI_UART_LINE | EXTI_USB_WAKEUP_LINE));
+            exti.imr()
+                .modify(|r, w| w.bits(r.bits() | EXTI_UART_LINE | EXTI_USB_WAKEUP_LINE));
+
+            #[cfg(feature = "debug")]
+            (*pac::DBGMCU::ptr())
+                .cr()
+                .modify(|_, w| w.dbg_stop().set_bit());
+
+            pcgcctl.modify(|r, w| w.bits(r.bits() | PCGCCTL_STPPCLK | PCGCCTL_GATEHCLK));
+            pwr.cr()
+                .modify(|r, w| w.bits((r.bits() & !PWR_CR_PDDS) | PWR_CR_LPDS));
+            scb.scr.modify(|r| r | SCR_SLEEPDEEP);
+
+            cortex_m::asm::dsb();
+            cortex_m::asm::wfi();
+
+            scb.scr.modify(|r| r & !SCR_SLEEPDEEP);
+            restore_clocks();
+            pcgcctl.modify(|r, w| w.bits(r.bits() & !(PCGCCTL_STPPCLK | PCGCCTL_GATEHCLK)));
+        }
+
+        rtc::disarm_wakeup();
+        true
+    })
+}
+
+/// Clears a wakeup interrupt
+///
+/// The UART line is masked again until the next Stop entry.
+pub fn clear_wakeup(source: WakeSource) {
+    match source {
+        WakeSource::Rtc => rtc::disarm_wakeup(),
+        WakeSource::Uart => {
+            // SAFETY: Only EXTI line 9 bits are written, see `stop`
+            unsafe {
+                let exti = &*pac::EXTI::ptr();
+                exti.imr().modify(|r, w| w.bits(r.bits() & !EXTI_UART_LINE));
+                exti.pr().write(|w| w.bits(EXTI_UART_LINE));
+            }
+        }
+        WakeSource::Usb => {
+            // SAFETY: Write-one-to-clear register, only line 18 is set
+            unsafe {
+                (*pac::EXTI::ptr())
+                    .pr()
+                    .write(|w| w.bits(EXTI_USB_WAKEUP_LINE))
+            };
+        }
+    }
+}
+
+/// Checks whether the UART start bit line woke the MCU
+///
+/// EXTI9_5 is shared with other lines (touch controller on line 5), so the
+/// handler only treats line 9 as a wakeup while it is unmasked and pending.
+pub fn uart_wakeup_pending() -> bool {
+    // SAFETY: Read-only access to the EXTI mask and pending registers
+    let exti = unsafe { &*pac::EXTI::ptr() };
+    exti.imr().read().bits() & exti.pr().read().bits() & EXTI_UART_LINE != 0
+}
+
+/// Brings back the system clock after Stop
+///
+/// The PLL settings, prescalers and flash wait states survive Stop; only the
+/// oscillators, the over-drive and the clock switch need to be redone.
+///
+/// # Safety
+/// Interrupts must be disabled; the core runs on the HSI meanwhile
+unsafe fn restore_clocks() {
+    let rcc = &*pac::RCC::ptr();
+
+    rcc.cr().modify(|r, w| w.bits(r.bits() | CR_HSEON));
+    wait_for(|| rcc.cr().read().bits() & CR_HSERDY != 0);
+
+    if clock_scaling::level() == ClockLevel::Low {
+        switch_to_hse();
+    } else {
+        start_pll();
+    }
+}
+
+/// Starts the PLL and the over-drive and runs the system clock from the PLL
+///
+/// Used after Stop and by `clock_scaling` when it leaves the low-frequency
+/// level. The PLL keeps the configuration written by `RccConfig`.
+///
+/// # Returns
+/// `false` if the PLL or the over-drive did not come up; the system clock
+/// then stays where it was
+///
+/// # Safety
+/// Interrupts must be disabled and the HSE running; the prescalers must
+/// already suit `SYSCLK`
+pub unsafe fn start_pll() -> bool {
+    let rcc = &*pac::RCC::ptr();
+    let pwr = &*pac::PWR::ptr();
+
+    rcc.cr().modify(|r, w| w.bits(r.bits() | CR_PLLON));
+    if !wait_for(|| rcc.cr().read().bits() & CR_PLLRDY != 0) {
+        return false;
+    }
+
+    // Stop mode and the low-frequency level turn the over-drive off
+    if SYSCLK > MAX_SYSCLK_WITHOUT_OVERDRIVE {
+        pwr.cr().modify(|r, w| w.bits(r.bits() | PWR_CR_ODEN));
+        wait_for(|| pwr.csr().read().bits() & PWR_CSR_ODRDY != 0);
+        pwr.cr().modify(|r, w| w.bits(r.bits() | PWR_CR_ODSWEN));
+        if !wait_for(|| pwr.csr().read().bits() & PWR_CSR_ODSWRDY != 0) {
+            return false;
+        }
+    }
+
+    rcc.cfgr()
+        .modify(|r, w| w.bits((r.bits() & !CFGR_SW_MASK) | CFGR_SW_PLL));
+    wait_for(|| rcc.cfgr().read().bits() & CFGR_SWS_MASK == CFGR_SWS_PLL)
+}
+
+/// Runs the system clock from the HSE, then stops the PLL and the over-drive
+///
+/// # Safety
+/// Interrupts must be disabled and the HSE running; nothing may depend on
+/// PLL48CLK (USB, RNG, SDIO) until `start_pll`
+pub unsafe fn switch_to_hse() {
+    let rcc = &*pac::RCC::ptr();
+    let pwr = &*pac::PWR::ptr();
+
+    rcc.cfgr()
+        .modify(|r, w| w.bits((r.bits() & !CFGR_SW_MASK) | CFGR_SW_HSE));
+    wait_for(|| rcc.cfgr().read().bits() & CFGR_SWS_MASK == CFGR_SWS_HSE);
+
+    pwr.cr().modify(|r, w| w.bits(r.bits() & !PWR_CR_ODSWEN));
+    pwr.cr().modify(|r, w| w.bits(r.bits() & !PWR_CR_ODEN));
+    rcc.cr().modify(|r, w| w.bits(r.bits() & !CR_PLLON));
+}
+
+/// Busy-waits for a flag with a bounded number of polls
+fn wait_for(mut condition: impl FnMut() -> bool) -> bool {
+    (0..FLAG_POLL_LIMIT).any(|_| condition())
+}
@@ -0,0 +1,193 @@
+//! # Clock Outputs (MCO)
+//!
+//! Puts internal clocks on the MCO pins for checks with a scope or a
+//! frequency counter:
+//! - MCO1 on PA8: HSI, LSE, HSE or the main PLL
+//! - MCO2 on PC9: SYSCLK, HSE or the main PLL; PC9 is SDIO D1, so MCO2 is
+//!   not available with `sd-log`
+//! - Each clock divided by 1 to 5; settings above the pins' 100 MHz limit
+//!   are refused
+//! - `MCO_OUTPUT` puts the HSE on MCO1 and SYSCLK / 4 on MCO2 from boot, the
+//!   console `mco` command changes them at runtime
+//!
+//! ## Hardware Configuration
+//! - Alternate function 0, very high speed; a pin that is off is analog
+//! - The source and divider only change while the pin is off, as the
+//!   reference manual asks
+//!
+//! ## Safety Considerations
+//! - PLL and SYSCLK follow `clock_scaling`: at `Low` the PLL output stops
+//!   and SYSCLK is the HSE
+//! - A fast clock on a header pin radiates; keep the outputs off in normal use
+
+use crate::config::{HSE, MCO_OUTPUT, SYSCLK};
+use crate::peripherals::clock_scaling::{self, ClockLevel};
+use crate::peripherals::rcc::{McoPin, McoSource, RccConfig, MCO_MAX_DIVIDER};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use stm32f4xx_hal::gpio::{gpioa::PA8, gpioc::PC9, Analog};
+use stm32f4xx_hal::pac;
+
+/// Highest frequency the pins drive at very high speed (Hz)
+const MAX_OUTPUT_HZ: u32 = 100_000_000;
+
+/// HSI frequency (Hz)
+const HSI_HZ: u32 = 16_000_000;
+
+/// LSE frequency (Hz)
+const LSE_HZ: u32 = 32_768;
+
+/// GPIO MODER values
+const MODE_ALTERNATE: u32 = 0b10;
+const MODE_ANALOG: u32 = 0b11;
+
+/// Outputs switched on by `MCO_OUTPUT`
+const BOOT_OUTPUTS: [(McoPin, McoSource, u8); 2] = [
+    (McoPin::Mco1, McoSource::Hse, 1),
+    (McoPin::Mco2, McoSource::Sysclk, 4),
+];
+
+/// Output pins: MCO1 and MCO2 (`None` while the SD card owns PC9)
+pub type McoPins = (PA8<Analog>, Option<PC9<Analog>>);
+
+/// Output state per pin, by `McoPin::ALL` index
+static ENABLED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+/// Checks whether a pin is available in this build
+pub const fn is_available(pin: McoPin) -> bool {
+    match pin {
+        McoPin::Mco1 => true,
+        McoPin::Mco2 => cfg!(not(feature = "sd-log")),
+    }
+}
+
+/// Checks whether a pin puts out its clock
+pub fn is_enabled(pin: McoPin) -> bool {
+    ENABLED[pin as usize].load(Ordering::Relaxed)
+}
+
+/// Takes over the MCO pins
+///
+/// The pins are taken so no other driver can claim them; they must already
+/// be on alternate function 0 at very high speed, then analog. With
+/// `MCO_OUTPUT`, the boot outputs start here.
+///
+/// # Arguments
+/// * `_pins` - PA8 and, without `sd-log`, PC9
+pub fn init(_pins: McoPins) {
+    if MCO_OUTPUT {
+        for (pin, source, divider) in BOOT_OUTPUTS {
+            if is_available(pin) {
+                set(pin, Some((source, divider))).ok();
+            }
+        }
+    }
+}
+
+/// Switches a clock output
+///
+/// # Arguments
+/// * `pin` - Clock output
+/// * `setting` - Clock and divider, `None` to switch the output off
+///
+/// # Errors
+/// Returns a short message if the pin is not available, cannot put out the
+/// clock, or the result would exceed `MAX_OUTPUT_HZ`
+pub fn set(pin: McoPin, setting: Option<(McoSource, u8)>) -> Result<(), &'static str> {
+    if !is_available(pin) {
+        return Err("MCO2 shares PC9 with the SD card");
+    }
+    let Some((source, divider)) = setting else {
+        set_pin_mode(pin, MODE_ANALOG);
+        ENABLED[pin as usize].store(false, Ordering::Relaxed);
+        return Ok(());
+    };
+    if source.bits(pin).is_none() {
+        return Err("MCO1 takes hsi, lse, hse or pll, MCO2 sysclk, hse or pll");
+    }
+    if !(1..=MCO_MAX_DIVIDER).contains(&divider) {
+        return Err("divider must be 1 to 5");
+    }
+    if nominal_hz(source) / u32::from(divider) > MAX_OUTPUT_HZ {
+        return Err("above 100 MHz, use a larger divider");
+    }
+
+    set_pin_mode(pin, MODE_ANALOG);
+    // Source and divider are checked above
+    RccConfig::set_mco(pin, source, divider);
+    set_pin_mode(pin, MODE_ALTERNATE);
+    ENABLED[pin as usize].store(true, Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("{=str}: {=str} / {=u8}", pin.name(), source.name(), divider);
+    Ok(())
+}
+
+/// Frequency of a source with the `RccConfig` clocks (Hz)
+fn nominal_hz(source: McoSource) -> u32 {
+    match source {
+        McoSource::Hsi => HSI_HZ,
+        McoSource::Lse => LSE_HZ,
+        McoSource::Hse => HSE,
+        McoSource::Pll | McoSource::Sysclk => SYSCLK,
+    }
+}
+
+/// Frequency of a source at the current clock level (Hz)
+fn current_hz(source: McoSource) -> u32 {
+    match (source, clock_scaling::level()) {
+        (McoSource::Pll, ClockLevel::Low) => 0,
+        (McoSource::Sysclk, ClockLevel::Low) => HSE,
+        _ => nominal_hz(source),
+    }
+}
+
+/// Switches a pin between its alternate function and analog
+fn set_pin_mode(pin: McoPin, mode: u32) {
+    let shift = match pin {
+        McoPin::Mco1 => 2 * 8,
+        McoPin::Mco2 => 2 * 9,
+    };
+    let update = |bits: u32| (bits & !(0b11 << shift)) | (mode << shift);
+    cortex_m::interrupt::free(|_| {
+        // SAFETY: `init` took the pins, so no other driver writes their
+        // MODER fields; the critical section keeps the read-modify-write
+        // from racing other pins of the port
+        unsafe {
+            match pin {
+                McoPin::Mco1 => {
+                    (*pac::GPIOA::ptr())
+                        .moder()
+                        .modify(|r, w| w.bits(update(r.bits())));
+                }
+                McoPin::Mco2 => {
+                    (*pac::GPIOC::ptr())
+                        .moder()
+                        .modify(|r, w| w.bits(update(r.bits())));
+                }
+            }
+        }
+    });
+}
+
+/// Writes the state of both outputs
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    for pin in McoPin::ALL {
+        write!(out, "{}: ", pin.name())?;
+        if !is_available(pin) {
+            writeln!(out, "unavailable (PC9 is SDIO D1)")?;
+        } else if is_enabled(pin) {
+            let (source, divider) = RccConfig::mco(pin);
+            writeln!(
+                out,
+                "{} / {}, {} Hz",
+                source.name(),
+                divider,
+                current_hz(source) / u32::from(divider)
+            )?;
+        } else {
+            writeln!(out, "off")?;
+        }
+    }
+    Ok(())
+}
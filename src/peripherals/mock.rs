@@ -0,0 +1,296 @@
+//! # Mock Links
+//!
+//! In-memory stand-ins for the bridged UART and the USB device, for the host
+//! test suite:
+//! - `MockSerial` implements `SerialLink`: bytes queued with `receive` come
+//!   out of the DMA RX stream, transmitted ones collect in `sent`
+//! - `MockUsb` implements `UsbLink`: packets queued with `host_send` are read
+//!   by the device, written ones collect in `received`
+//! - `Shared` hands either to the handlers the way an RTIC resource proxy
+//!   does
+//!
+//! Faults are injected through the public fields: receive errors, DMA stream
+//! errors, failing restarts, partial and failing USB writes. Only built for
+//! hosts.
+
+use crate::config::{DATA_PACKET_SIZE, DEFAULT_CHUNK_SIZE, DMA_BUFFER_LEN};
+use crate::errors::errors::{UsartError, UsbError};
+use crate::peripherals::traits::{LineError, SerialLink, UsartFlag, UsbLink};
+use heapless::{Deque, Vec};
+use rtic_core::Mutex;
+
+/// Bytes a mock holds per direction
+pub const MOCK_CAPACITY: usize = 1024;
+
+/// Host packets a `MockUsb` holds before the device reads them
+pub const MOCK_PACKETS: usize = 8;
+
+/// Resource proxy stand-in: locking hands out the value
+#[derive(Debug, Default)]
+pub struct Shared<T>(pub T);
+
+impl<T> Mutex for Shared<T> {
+    type T = T;
+
+    fn lock<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.0)
+    }
+}
+
+/// UART with its DMA streams
+///
+/// Transfers complete at once unless `hold_tx` is set; then the stream
+/// stays busy until `complete_tx`. A DMA stream error lasts until the
+/// stream is restarted.
+#[derive(Debug)]
+pub struct MockSerial {
+    rx: Deque<u8, MOCK_CAPACITY>,
+    tx_buffer: [u8; DMA_BUFFER_LEN],
+    tx_busy: bool,
+    /// Transmitted bytes, oldest first
+    pub sent: Vec<u8, MOCK_CAPACITY>,
+    /// Keeps transfers running until `complete_tx`
+    pub hold_tx: bool,
+    /// Receive errors reported by the next `take_line_errors`
+    pub line_errors: LineError,
+    /// Error of the DMA RX stream
+    pub rx_dma_error: bool,
+    /// Error of the DMA TX stream
+    pub tx_dma_error: bool,
+    /// Makes stream restarts fail
+    pub fail_restart: bool,
+    /// Restarts of the DMA RX stream
+    pub rx_restarts: u32,
+    /// Restarts of the DMA TX stream
+    pub tx_restarts: u32,
+    /// Calls of `clear_errors`
+    pub errors_cleared: u32,
+}
+
+impl Default for MockSerial {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockSerial {
+    /// Creates an idle, error-free UART
+    pub fn new() -> Self {
+        Self {
+            rx: Deque::new(),
+            tx_buffer: [0; DMA_BUFFER_LEN],
+            tx_busy: false,
+            sent: Vec::new(),
+            hold_tx: false,
+            line_errors: LineError::empty(),
+            rx_dma_error: false,
+            tx_dma_error: false,
+            fail_restart: false,
+            rx_restarts: 0,
+            tx_restarts: 0,
+            errors_cleared: 0,
+        }
+    }
+
+    /// Queues bytes arriving on the line
+    ///
+    /// # Returns
+    /// Number of bytes queued; the rest is lost as in a DMA overrun
+    pub fn receive(&mut self, data: &[u8]) -> usize {
+        data.iter()
+            .take_while(|&&byte| self.rx.push_back(byte).is_ok())
+            .count()
+    }
+
+    /// Finishes a transfer kept running by `hold_tx`
+    pub fn complete_tx(&mut self) {
+        self.tx_busy = false;
+    }
+
+    fn transmit(&mut self, len: usize) -> Result<(), UsartError> {
+        if self.tx_busy {
+            return Err(UsartError::TransferError);
+        }
+        self.sent
+            .extend_from_slice(&self.tx_buffer[..len])
+            .map_err(|_| UsartError::BufferOverflow)?;
+        self.tx_busy = self.hold_tx;
+        Ok(())
+    }
+}
+
+impl SerialLink for MockSerial {
+    fn is_tx_busy(&self) -> bool {
+        self.tx_busy
+    }
+
+    unsafe fn transmit_external(&mut self, data: &[u8]) -> Result<(), UsartError> {
+        if data.len() > DMA_BUFFER_LEN {
+            return Err(UsartError::BufferOverflow);
+        }
+        if self.tx_busy {
+            return Err(UsartError::TransferError);
+        }
+        self.tx_buffer[..data.len()].copy_from_slice(data);
+        self.transmit(data.len())
+    }
+
+    fn get_tx_buffer_slice(&mut self, length: usize) -> Option<&mut [u8]> {
+        self.tx_buffer.get_mut(..length)
+    }
+
+    fn write_dma(&mut self, len: usize) -> Result<(), UsartError> {
+        if len > DMA_BUFFER_LEN {
+            return Err(UsartError::BufferOverflow);
+        }
+        self.transmit(len)
+    }
+
+    fn read_dma_rx(&mut self, out: &mut [u8]) -> Result<usize, UsartError> {
+        let mut count = 0;
+        for slot in out.iter_mut() {
+            match self.rx.pop_front() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn skip_rx(&mut self) -> Result<(), UsartError> {
+        self.rx.clear();
+        Ok(())
+    }
+
+    fn unread_rx_len(&mut self) -> Result<usize, UsartError> {
+        Ok(self.rx.len())
+    }
+
+    fn clear_dma_tx_complete_flag(&mut self) {}
+
+    fn clear_dma_rx_complete_flag(&mut self) {}
+
+    fn clear_usart_flags(&self, _flags: UsartFlag) {}
+
+    fn take_line_errors(&mut self) -> LineError {
+        core::mem::replace(&mut self.line_errors, LineError::empty())
+    }
+
+    fn clear_errors(&mut self) {
+        self.errors_cleared += 1;
+    }
+
+    fn check_dma_rx_error(&mut self) -> Result<bool, UsartError> {
+        Ok(self.rx_dma_error)
+    }
+
+    fn check_dma_tx_error(&mut self) -> Result<bool, UsartError> {
+        Ok(self.tx_dma_error)
+    }
+
+    fn restart_dma_rx(&mut self) -> Result<(), UsartError> {
+        if self.fail_restart {
+            return Err(UsartError::DmaError);
+        }
+        self.rx_dma_error = false;
+        self.rx_restarts += 1;
+        Ok(())
+    }
+
+    fn restart_dma_tx(&mut self) -> Result<(), UsartError> {
+        if self.fail_restart {
+            return Err(UsartError::DmaError);
+        }
+        self.tx_dma_error = false;
+        self.tx_busy = false;
+        self.tx_restarts += 1;
+        Ok(())
+    }
+}
+
+/// USB device with its CDC data port
+///
+/// Packets longer than the read buffer are cut, as an endpoint buffer
+/// would.
+#[derive(Debug)]
+pub struct MockUsb {
+    packets: Deque<Vec<u8, DATA_PACKET_SIZE>, MOCK_PACKETS>,
+    /// Bytes written to the host, oldest first
+    pub received: Vec<u8, MOCK_CAPACITY>,
+    /// Enumerated and configured by the host
+    pub configured: bool,
+    /// Bytes moved to the host per write
+    pub chunk_size: usize,
+    /// Bytes the endpoint takes per write; less than the data is a partial write
+    pub write_limit: usize,
+    /// Makes reads fail
+    pub fail_read: bool,
+    /// Makes writes fail
+    pub fail_write: bool,
+}
+
+impl Default for MockUsb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockUsb {
+    /// Creates a configured device taking whole writes
+    pub fn new() -> Self {
+        Self {
+            packets: Deque::new(),
+            received: Vec::new(),
+            configured: true,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            write_limit: DATA_PACKET_SIZE,
+            fail_read: false,
+            fail_write: false,
+        }
+    }
+
+    /// Queues one packet from the host
+    ///
+    /// # Returns
+    /// `false` if the packet is too long or the queue is full
+    pub fn host_send(&mut self, data: &[u8]) -> bool {
+        Vec::from_slice(data)
+            .ok()
+            .is_some_and(|packet| self.packets.push_back(packet).is_ok())
+    }
+}
+
+impl UsbLink for MockUsb {
+    fn is_configured(&self) -> bool {
+        self.configured
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn read_into(&mut self, buffer: &mut [u8]) -> Result<usize, UsbError> {
+        if self.fail_read {
+            return Err(UsbError::ReadError);
+        }
+        let Some(packet) = self.packets.pop_front() else {
+            return Ok(0);
+        };
+        let len = packet.len().min(buffer.len());
+        buffer[..len].copy_from_slice(&packet[..len]);
+        Ok(len)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        if self.fail_write {
+            return Err(UsbError::WriteError);
+        }
+        let room = self.received.capacity() - self.received.len();
+        let len = data.len().min(self.write_limit).min(room);
+        self.received
+            .extend_from_slice(&data[..len])
+            .map_err(|_| UsbError::BufferOverflow)?;
+        Ok(len)
+    }
+}
@@ -1,7 +1,91 @@
-pub mod blue_led;
+#[cfg(all(target_os = "none", feature = "adc"))]
+pub mod adc;
+#[cfg(target_os = "none")]
+pub mod backup_sram;
+#[cfg(target_os = "none")]
+pub mod baud_capture;
+#[cfg(target_os = "none")]
+pub mod block_device;
+#[cfg(target_os = "none")]
+pub mod button;
+#[cfg(target_os = "none")]
+pub mod buzzer;
+#[cfg(all(target_os = "none", feature = "can"))]
+pub mod can;
+#[cfg(target_os = "none")]
+pub mod cdc_acm;
+#[cfg(target_os = "none")]
+pub mod clock_scaling;
+#[cfg(target_os = "none")]
+pub mod crc;
+#[cfg(all(target_os = "none", feature = "dac"))]
+pub mod dac;
+#[cfg(target_os = "none")]
+pub mod dfu_runtime;
+#[cfg(all(target_os = "none", feature = "display"))]
+pub mod display;
+#[cfg(target_os = "none")]
+pub mod flash;
+#[cfg(all(target_os = "none", feature = "i2c"))]
+pub mod i2c;
+#[cfg(target_os = "none")]
+pub mod iwdg;
+#[cfg(target_os = "none")]
+pub mod led;
+#[cfg(all(target_os = "none", feature = "led-pwm"))]
+pub mod led_pwm;
+#[cfg(target_os = "none")]
+pub mod low_power;
+#[cfg(target_os = "none")]
+pub mod mco;
+#[cfg(not(target_os = "none"))]
+pub mod mock;
+#[cfg(target_os = "none")]
+pub mod modem_lines;
+#[cfg(target_os = "none")]
+pub mod ms_os;
+#[cfg(target_os = "none")]
 pub mod otg_fs;
+#[cfg(target_os = "none")]
+pub mod pin_parking;
+#[cfg(target_os = "none")]
+pub mod pvd;
+#[cfg(target_os = "none")]
+pub mod qspi;
+#[cfg(target_os = "none")]
 pub mod rcc;
+#[cfg(target_os = "none")]
+pub mod rtc;
+#[cfg(target_os = "none")]
 pub mod red_led;
+#[cfg(target_os = "none")]
+pub mod rng;
+#[cfg(target_os = "none")]
+pub mod rs485;
+#[cfg(target_os = "none")]
+pub mod rx_timeout;
+#[cfg(all(target_os = "none", feature = "sd-log"))]
+pub mod sdcard;
+#[cfg(target_os = "none")]
+pub mod sdram;
+#[cfg(all(target_os = "none", feature = "spi"))]
+pub mod spi;
+#[cfg(target_os = "none")]
 pub mod stm32f469_init;
+#[cfg(all(target_os = "none", feature = "touch"))]
+pub mod touch;
 pub mod traits;
-pub mod usart_6;
+#[cfg(target_os = "none")]
+pub mod uart;
+#[cfg(all(target_os = "none", feature = "usb-hid"))]
+pub mod usb_hid;
+#[cfg(all(target_os = "none", feature = "usb-msc"))]
+pub mod usb_msc;
+#[cfg(all(target_os = "none", feature = "usb-vendor"))]
+pub mod usb_vendor;
+#[cfg(target_os = "none")]
+pub mod vbus;
+#[cfg(target_os = "none")]
+pub mod verify;
+#[cfg(target_os = "none")]
+pub mod wwdg;
@@ -0,0 +1,174 @@
+//! # Modem Lines
+//!
+//! Samples the external handshake inputs that are mirrored to the USB host as
+//! CDC `SERIAL_STATE` bits:
+//! - DSR on PG13 (Arduino D2)
+//! - DCD on PG12 (Arduino D4)
+//! - RI on PG11 (Arduino D7)
+//! - CTS on PA3, reported in a bit CDC leaves reserved (`SerialState::CTS`)
+//!
+//! and drives the host-controlled lines of the data port as outputs, e.g.
+//! for the auto-reset circuit of an ESP32 or the BOOT0/NRST pins of another
+//! STM32:
+//! - DTR on PA1
+//! - RTS on PA2
+//!
+//! ## Hardware Configuration
+//! - Inputs use internal pull-ups and are active low, matching TTL-level
+//!   RS-232 transceivers; unconnected lines read as inactive
+//! - Outputs are push-pull and active low by default, like the modem
+//!   outputs of USB-serial converter chips; each can be switched to active
+//!   high (`set_active_high`, console `lines`, kept by `save`)
+
+use crate::config::DEFAULT_MODEM_ACTIVE_HIGH;
+use crate::peripherals::cdc_acm::SerialState;
+use bitflags::bitflags;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU16, AtomicU8, Ordering};
+use stm32f4xx_hal::gpio::{
+    gpioa::{PA1, PA2, PA3},
+    gpiog::{PG11, PG12, PG13},
+    Input, Output, PushPull,
+};
+
+bitflags! {
+    /// Host-controlled output lines
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ControlLines: u8 {
+        const DTR = 1 << 0; // PA1
+        const RTS = 1 << 1; // PA2
+    }
+}
+
+/// Output lines driven high while asserted
+static ACTIVE_HIGH: AtomicU8 = AtomicU8::new(DEFAULT_MODEM_ACTIVE_HIGH);
+
+/// Lines last asserted by the host
+static ASSERTED: AtomicU8 = AtomicU8::new(0);
+
+/// Inputs at the last sample, as `SerialState` bits
+static SAMPLED: AtomicU16 = AtomicU16::new(0);
+
+/// Output lines driven high while asserted
+pub fn active_high() -> ControlLines {
+    ControlLines::from_bits_truncate(ACTIVE_HIGH.load(Ordering::Relaxed))
+}
+
+/// Selects the output lines driven high while asserted
+///
+/// Takes effect the next time the USB interrupt drives the outputs.
+pub fn set_active_high(lines: ControlLines) {
+    ACTIVE_HIGH.store(lines.bits(), Ordering::Relaxed);
+}
+
+/// Polarity of one output line, `high` or `low`
+pub fn polarity_name(active_high: ControlLines, line: ControlLines) -> &'static str {
+    if active_high.contains(line) {
+        "high"
+    } else {
+        "low"
+    }
+}
+
+/// Handshake input pins
+pub struct ModemLines {
+    dsr: PG13<Input>,
+    dcd: PG12<Input>,
+    ri: PG11<Input>,
+    cts: PA3<Input>,
+}
+
+impl ModemLines {
+    /// Creates the input set
+    ///
+    /// # Arguments
+    /// * `dsr` - Data Set Ready input (pulled up)
+    /// * `dcd` - Data Carrier Detect input (pulled up)
+    /// * `ri` - Ring Indicator input (pulled up)
+    /// * `cts` - Clear To Send input (pulled up)
+    pub fn new(dsr: PG13<Input>, dcd: PG12<Input>, ri: PG11<Input>, cts: PA3<Input>) -> Self {
+        Self { dsr, dcd, ri, cts }
+    }
+
+    /// Samples the inputs
+    ///
+    /// # Returns
+    /// `SerialState` with DSR, DCD, RING and CTS set for asserted (low) lines
+    pub fn read(&self) -> SerialState {
+        let mut state = SerialState::empty();
+        state.set(SerialState::DSR, self.dsr.is_low());
+        state.set(SerialState::DCD, self.dcd.is_low());
+        state.set(SerialState::RING, self.ri.is_low());
+        state.set(SerialState::CTS, self.cts.is_low());
+        SAMPLED.store(state.bits(), Ordering::Relaxed);
+        state
+    }
+}
+
+/// Host-controlled output pins
+pub struct ControlOutputs {
+    dtr: PA1<Output<PushPull>>,
+    rts: PA2<Output<PushPull>>,
+}
+
+impl ControlOutputs {
+    /// Creates the output set with both lines deasserted
+    ///
+    /// # Arguments
+    /// * `dtr` - Data Terminal Ready output
+    /// * `rts` - Request To Send output
+    pub fn new(dtr: PA1<Output<PushPull>>, rts: PA2<Output<PushPull>>) -> Self {
+        let mut outputs = Self { dtr, rts };
+        outputs.drive((false, false));
+        outputs
+    }
+
+    /// Drives the outputs to the host's line states
+    ///
+    /// # Arguments
+    /// * `(dtr, rts)` - Line states set by the host, `true` for asserted
+    pub fn drive(&mut self, (dtr, rts): (bool, bool)) {
+        let mut asserted = ControlLines::empty();
+        asserted.set(ControlLines::DTR, dtr);
+        asserted.set(ControlLines::RTS, rts);
+
+        let high = active_high();
+        let level = |line| asserted.contains(line) == high.contains(line);
+        self.dtr.set_state(level(ControlLines::DTR).into());
+        self.rts.set_state(level(ControlLines::RTS).into());
+        ASSERTED.store(asserted.bits(), Ordering::Relaxed);
+    }
+}
+
+/// Writes the output line states and polarities and the sampled inputs
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let asserted = ControlLines::from_bits_truncate(ASSERTED.load(Ordering::Relaxed));
+    let high = active_high();
+    write!(out, "Modem lines:")?;
+    for (name, line) in ControlLines::all().iter_names() {
+        write!(
+            out,
+            " {}={} (active-{})",
+            name,
+            if asserted.contains(line) { "on" } else { "off" },
+            polarity_name(high, line)
+        )?;
+    }
+
+    let sampled = SerialState::from_bits_truncate(SAMPLED.load(Ordering::Relaxed));
+    write!(out, ", inputs:")?;
+    for (name, line) in [
+        ("DSR", SerialState::DSR),
+        ("DCD", SerialState::DCD),
+        ("RI", SerialState::RING),
+        ("CTS", SerialState::CTS),
+    ] {
+        write!(
+            out,
+            " {}={}",
+            name,
+            if sampled.contains(line) { "on" } else { "off" }
+        )?;
+    }
+    writeln!(out)
+}
@@ -0,0 +1,225 @@
+//! # Microsoft OS 2.0 Descriptors
+//!
+//! Lets Windows 8.1 and later bind WinUSB to our non-CDC interfaces without
+//! an INF file or a driver tool:
+//! - The BOS descriptor carries a Microsoft OS 2.0 platform capability that
+//!   names the vendor request code and the length of the descriptor set
+//! - Windows fetches the descriptor set with that vendor request
+//!   (`wIndex = 7`); it holds one function subset per listed interface with
+//!   the `WINUSB` compatible ID and a `DeviceInterfaceGUIDs` registry property
+//!   that user space (libusb, WinUSB API) opens the interface by
+//! - The CDC-ACM ports are not listed; Windows keeps binding its own
+//!   `usbser` driver to them
+//!
+//! The descriptor set is built once, when the interfaces are allocated, and
+//! answered from a static buffer, so it may exceed the control buffer.
+//!
+//! ## Hardware Configuration
+//! - No interface or endpoint of its own; answers on the control pipe
+//! - Needs a USB 2.1 device descriptor, set in `OtgFsController::new`, or
+//!   the host never asks for the BOS descriptor
+
+use crate::config::MS_OS_VENDOR_CODE;
+use crate::errors::errors::UsbError;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+
+/// Platform device capability
+const CAPABILITY_PLATFORM: u8 = 0x05;
+
+/// MS OS 2.0 platform capability UUID {D8DD60DF-4589-4CC7-9CD2-659D9E648A9F}
+const MS_OS_20_UUID: [u8; 16] = [
+    0xDF, 0x60, 0xDD, 0xD8, 0x89, 0x45, 0xC7, 0x4C, 0x9C, 0xD2, 0x65, 0x9D, 0x9E, 0x64, 0x8A, 0x9F,
+];
+
+/// Windows 8.1, the first version reading MS OS 2.0 descriptors
+const WINDOWS_VERSION: u32 = 0x0603_0000;
+
+/// `wIndex` of the descriptor set request
+const MS_OS_20_DESCRIPTOR_INDEX: u16 = 0x07;
+
+/// Descriptor types within the set
+const SET_HEADER: u16 = 0x00;
+const SUBSET_HEADER_CONFIGURATION: u16 = 0x01;
+const SUBSET_HEADER_FUNCTION: u16 = 0x02;
+const FEATURE_COMPATIBLE_ID: u16 = 0x03;
+const FEATURE_REG_PROPERTY: u16 = 0x04;
+
+/// REG_MULTI_SZ registry value type
+const REG_MULTI_SZ: u16 = 7;
+
+/// Registry value name of the interface GUIDs
+const GUIDS_PROPERTY_NAME: &str = "DeviceInterfaceGUIDs";
+
+/// Header and subset lengths
+const SET_HEADER_LEN: usize = 10;
+const CONFIGURATION_SUBSET_LEN: usize = 8;
+const FUNCTION_SUBSET_LEN: usize = 8;
+const COMPATIBLE_ID_LEN: usize = 20;
+
+/// GUID text length with braces, e.g. `{80CA1CBE-...}`
+const GUID_LEN: usize = 38;
+
+/// Registry property: header, name and value as UTF-16 with their NULs,
+/// the value a REG_MULTI_SZ with one string
+const REG_PROPERTY_LEN: usize = 10 + 2 * (GUIDS_PROPERTY_NAME.len() + 1) + 2 * (GUID_LEN + 2);
+
+/// One WinUSB function: subset header, compatible ID and GUID property
+const FUNCTION_LEN: usize = FUNCTION_SUBSET_LEN + COMPATIBLE_ID_LEN + REG_PROPERTY_LEN;
+
+/// Functions the descriptor set can list
+pub const MAX_FUNCTIONS: usize = 2;
+
+/// Largest descriptor set
+const SET_MAX_LEN: usize = SET_HEADER_LEN + CONFIGURATION_SUBSET_LEN + MAX_FUNCTIONS * FUNCTION_LEN;
+
+/// Interface that gets the WinUSB driver
+#[derive(Debug, Clone, Copy)]
+pub struct WinUsbFunction {
+    /// First interface of the function
+    pub interface: InterfaceNumber,
+    /// Interface GUID with braces, 38 characters
+    pub guid: &'static str,
+}
+
+/// MS OS 2.0 descriptor responder
+pub struct MsOsDescriptors {
+    set: &'static [u8],
+}
+
+impl MsOsDescriptors {
+    /// Builds the descriptor set for the given functions
+    ///
+    /// # Arguments
+    /// * `functions` - Interfaces to bind WinUSB to, at most `MAX_FUNCTIONS`
+    ///
+    /// # Errors
+    /// `UsbError::NotInitialized` if called twice, or with too many
+    /// functions or a malformed GUID
+    pub fn new(functions: &[WinUsbFunction]) -> Result<Self, UsbError> {
+        if functions.len() > MAX_FUNCTIONS
+            || functions
+                .iter()
+                .any(|function| function.guid.len() != GUID_LEN)
+        {
+            return Err(UsbError::NotInitialized);
+        }
+        let buffer: &'static mut [u8; SET_MAX_LEN] =
+            cortex_m::singleton!(: [u8; SET_MAX_LEN] = [0; SET_MAX_LEN])
+                .ok_or(UsbError::NotInitialized)?;
+
+        let len = encode_set(buffer, functions);
+        Ok(Self {
+            set: &buffer[..len],
+        })
+    }
+
+    /// Whether a request asks for the descriptor set
+    fn is_set_request(req: &Request) -> bool {
+        req.request_type == RequestType::Vendor
+            && req.recipient == Recipient::Device
+            && req.request == MS_OS_VENDOR_CODE
+            && req.index == MS_OS_20_DESCRIPTOR_INDEX
+    }
+}
+
+/// Little-endian writer over the set buffer
+struct SetWriter<'b> {
+    buf: &'b mut [u8],
+    pos: usize,
+}
+
+impl SetWriter<'_> {
+    fn u8(&mut self, value: u8) {
+        self.buf[self.pos] = value;
+        self.pos += 1;
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, data: &[u8]) {
+        self.buf[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+    }
+
+    /// ASCII text as UTF-16LE with a terminating NUL
+    fn utf16(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.u16(u16::from(byte));
+        }
+        self.u16(0);
+    }
+}
+
+/// Encodes the descriptor set
+///
+/// # Returns
+/// Length of the set in bytes
+fn encode_set(buf: &mut [u8; SET_MAX_LEN], functions: &[WinUsbFunction]) -> usize {
+    let total = SET_HEADER_LEN + CONFIGURATION_SUBSET_LEN + functions.len() * FUNCTION_LEN;
+    let mut w = SetWriter { buf, pos: 0 };
+
+    w.u16(SET_HEADER_LEN as u16);
+    w.u16(SET_HEADER);
+    w.u32(WINDOWS_VERSION);
+    w.u16(total as u16);
+
+    // Index 0, not the bConfigurationValue of 1, as the specification asks
+    w.u16(CONFIGURATION_SUBSET_LEN as u16);
+    w.u16(SUBSET_HEADER_CONFIGURATION);
+    w.u8(0);
+    w.u8(0);
+    w.u16((total - SET_HEADER_LEN) as u16);
+
+    for function in functions {
+        w.u16(FUNCTION_SUBSET_LEN as u16);
+        w.u16(SUBSET_HEADER_FUNCTION);
+        w.u8(u8::from(function.interface));
+        w.u8(0);
+        w.u16(FUNCTION_LEN as u16);
+
+        w.u16(COMPATIBLE_ID_LEN as u16);
+        w.u16(FEATURE_COMPATIBLE_ID);
+        w.bytes(b"WINUSB\0\0");
+        w.bytes(&[0; 8]);
+
+        w.u16(REG_PROPERTY_LEN as u16);
+        w.u16(FEATURE_REG_PROPERTY);
+        w.u16(REG_MULTI_SZ);
+        w.u16((2 * (GUIDS_PROPERTY_NAME.len() + 1)) as u16);
+        w.utf16(GUIDS_PROPERTY_NAME);
+        w.u16((2 * (GUID_LEN + 2)) as u16);
+        w.utf16(function.guid);
+        // Second NUL ends the REG_MULTI_SZ list
+        w.u16(0);
+    }
+    w.pos
+}
+
+impl<B: UsbBus> UsbClass<B> for MsOsDescriptors {
+    fn get_bos_descriptors(&self, writer: &mut BosWriter) -> usb_device::Result<()> {
+        let version = WINDOWS_VERSION.to_le_bytes();
+        let total = (self.set.len() as u16).to_le_bytes();
+
+        let mut data = [0u8; 25];
+        // data[0] is bReserved
+        data[1..17].copy_from_slice(&MS_OS_20_UUID);
+        data[17..21].copy_from_slice(&version);
+        data[21..23].copy_from_slice(&total);
+        data[23] = MS_OS_VENDOR_CODE;
+        // data[24] is bAltEnumCode: no alternate enumeration
+        writer.capability(CAPABILITY_PLATFORM, &data)
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        if Self::is_set_request(xfer.request()) {
+            xfer.accept_with_static(self.set).ok();
+        }
+    }
+}
@@ -2,16 +2,42 @@
 //!
 //! This module provides USB device functionality using the OTG FS peripheral
 //! on STM32F4 microcontrollers. Key features include:
-//! - USB Serial Communication Device Class (CDC) implementation
+//! - Composite device with two CDC-ACM functions: the UART bridge port and
+//!   an interactive debug console port
+//! - `usb-hid` feature: a HID keyboard takes the place of the bridge port;
+//!   both implement `BridgeFunction`, so the data path is the same
+//! - `usb-vendor` feature: a vendor-specific interface with two bulk
+//!   endpoints and message framing takes the place of the bridge port, for
+//!   libusb/WinUSB hosts; Windows binds WinUSB to it like the DFU interface
+//! - DFU run-time interface for rebooting into the bootloader from the host
+//! - Microsoft OS 2.0 descriptors, so Windows binds WinUSB to the DFU
+//!   interface without an INF file
+//! - `usb-msc` feature: a read-only mass storage function serving the log
+//!   volume
+//! - Modem status (DSR/DCD/RI) reporting through CDC `SERIAL_STATE`
+//! - CDC `SEND_BREAK` requests (`take_break_request`)
+//! - Bus suspend/resume tracking (`take_power_event`)
+//! - Remote wakeup: the configuration descriptor advertises it, and
+//!   `set_remote_wakeup` drives resume signalling once the host enabled it
+//! - Vendor/product IDs and descriptor strings supplied at init (`UsbIdentity`),
+//!   so they can come from the saved settings
 //! - Dual buffer management for RX/TX operations
 //! - Atomic state tracking for USB initialization
 //! - Error handling for USB communication faults
+//! - `UsbLink` (`traits`): the data path of the bridge port, for the
+//!   handlers in `task_handlers::otg_fs`
+//! - `embedded_io` blocking reads and writes on the bridge port, for
+//!   third-party drivers; they poll the device while they wait
 //!
 //! ## Hardware Configuration
 //! - Uses PA11 (DM) and PA12 (DP) pins in alternate function mode 10
 //! - Requires OTG FS global, device, and power/clock registers
 //! - Buffer sizes configured in `config` module
+//! - Two CDC functions use 4 of the 6 OTG FS IN endpoints (notify + bulk each);
+//!   the HID keyboard, the vendor bulk interface and the mass storage
+//!   function need one IN endpoint each
 
+use core::ops::Range;
 use core::sync::atomic::{AtomicBool, Ordering};
 use stm32f4xx_hal::pac::{OTG_FS_DEVICE, OTG_FS_GLOBAL, OTG_FS_PWRCLK};
 use stm32f4xx_hal::{
@@ -22,15 +48,34 @@ use stm32f4xx_hal::{
     otg_fs::{UsbBusType, USB},
 };
 use usb_device::{
-    class_prelude::UsbBusAllocator,
-    device::{StringDescriptors, UsbDeviceBuilder, UsbVidPid},
+    class_prelude::{UsbBus, UsbBusAllocator, UsbClass},
+    device::{StringDescriptors, UsbDeviceBuilder, UsbRev, UsbVidPid},
     prelude::*,
 };
-use usbd_serial::{SerialPort, USB_CLASS_CDC};
 
-use crate::config::{DATA_PACKET_SIZE, OTG_FS_BUFFER_LEN};
+use crate::config::{
+    CDC_MAX_PACKET_SIZE, DATA_PACKET_SIZE, DEFAULT_CHUNK_SIZE, DFU_INTERFACE_GUID,
+    OTG_FS_BUFFER_LEN,
+};
+#[cfg(feature = "usb-vendor")]
+use crate::config::VENDOR_INTERFACE_GUID;
+use crate::data_structures::log_queue::IsrContext;
+use crate::isr_log;
 use crate::errors::errors::UsbError;
+use crate::peripherals::cdc_acm::{BreakRequest, CdcAcm, LineCoding, SerialState};
+use crate::peripherals::dfu_runtime::DfuRuntime;
+use crate::peripherals::ms_os::{MsOsDescriptors, WinUsbFunction};
 use crate::peripherals::rcc::RccConfig;
+use crate::peripherals::traits::UsbLink;
+#[cfg(feature = "usb-hid")]
+use crate::peripherals::usb_hid::HidKeyboard;
+#[cfg(feature = "usb-msc")]
+use crate::peripherals::usb_msc::MassStorage;
+#[cfg(feature = "usb-vendor")]
+use crate::peripherals::usb_vendor::VendorBulk;
+#[cfg(feature = "usb-msc")]
+use crate::utils::log_volume::LogVolume;
+use crate::utils::meminfo;
 
 /// Shared USB bus allocator (singleton pattern)
 static mut USB_BUS: Option<UsbBusAllocator<UsbBusType>> = None;
@@ -38,12 +83,189 @@ static mut USB_BUS: Option<UsbBusAllocator<UsbBusType>> = None;
 /// Atomic state tracking for USB initialization
 static USB_BUS_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
+/// OTG FS DCTL remote wakeup signalling and soft disconnect bits
+const DCTL_RWUSIG: u32 = 1 << 0;
+const DCTL_SDIS: u32 = 1 << 1;
+
+/// Function carrying the bridged data
+#[cfg(not(any(feature = "usb-hid", feature = "usb-vendor")))]
+pub type BridgeClass<'a> = CdcAcm<'a, UsbBusType>;
+
+/// Function carrying the bridged data
+#[cfg(feature = "usb-hid")]
+pub type BridgeClass<'a> = HidKeyboard<'a, UsbBusType>;
+
+/// Function carrying the bridged data
+#[cfg(feature = "usb-vendor")]
+pub type BridgeClass<'a> = VendorBulk<'a, UsbBusType>;
+
+/// Mass storage function serving the log volume
+#[cfg(feature = "usb-msc")]
+pub type LogStorage<'a> = MassStorage<'a, UsbBusType, LogVolume>;
+
+/// USB function that carries the bridged data
+///
+/// The controller only uses this interface, so the bridge port can be a
+/// CDC-ACM serial port, a HID keyboard or a vendor bulk interface. Modem signalling defaults to
+/// nothing for functions without it.
+pub trait BridgeFunction<B: UsbBus>: UsbClass<B> {
+    /// Reads one packet from the host
+    ///
+    /// # Errors
+    /// `WouldBlock` when no packet is available
+    fn read(&mut self, data: &mut [u8]) -> usb_device::Result<usize>;
+
+    /// Queues data for the host
+    ///
+    /// # Errors
+    /// `WouldBlock` when nothing could be queued
+    fn write(&mut self, data: &[u8]) -> usb_device::Result<usize>;
+
+    /// Pushes queued data to the host
+    ///
+    /// # Errors
+    /// `WouldBlock` while data is still in flight
+    fn flush(&mut self) -> usb_device::Result<()>;
+
+    /// Reports modem status lines to the host
+    fn set_serial_state(&mut self, _state: SerialState) -> usb_device::Result<()> {
+        Ok(())
+    }
+
+    /// Host-controlled `(DTR, RTS)` lines
+    fn control_lines(&self) -> (bool, bool) {
+        (false, false)
+    }
+
+    /// Takes the line coding if the host changed it
+    fn take_line_coding(&mut self) -> Option<LineCoding> {
+        None
+    }
+
+    /// Takes the break the host requested
+    fn take_break_request(&mut self) -> Option<BreakRequest> {
+        None
+    }
+}
+
+impl<B: UsbBus> BridgeFunction<B> for CdcAcm<'_, B> {
+    fn read(&mut self, data: &mut [u8]) -> usb_device::Result<usize> {
+        CdcAcm::read(self, data)
+    }
+
+    fn write(&mut self, data: &[u8]) -> usb_device::Result<usize> {
+        CdcAcm::write(self, data)
+    }
+
+    fn flush(&mut self) -> usb_device::Result<()> {
+        CdcAcm::flush(self)
+    }
+
+    fn set_serial_state(&mut self, state: SerialState) -> usb_device::Result<()> {
+        CdcAcm::set_serial_state(self, state)
+    }
+
+    fn control_lines(&self) -> (bool, bool) {
+        (self.dtr(), self.rts())
+    }
+
+    fn take_line_coding(&mut self) -> Option<LineCoding> {
+        CdcAcm::take_line_coding(self)
+    }
+
+    fn take_break_request(&mut self) -> Option<BreakRequest> {
+        CdcAcm::take_break_request(self)
+    }
+}
+
+#[cfg(feature = "usb-hid")]
+impl<B: UsbBus> BridgeFunction<B> for HidKeyboard<'_, B> {
+    fn read(&mut self, _data: &mut [u8]) -> usb_device::Result<usize> {
+        // A keyboard has no host-to-device data
+        Err(usb_device::UsbError::WouldBlock)
+    }
+
+    fn write(&mut self, data: &[u8]) -> usb_device::Result<usize> {
+        HidKeyboard::write(self, data)
+    }
+
+    fn flush(&mut self) -> usb_device::Result<()> {
+        HidKeyboard::flush(self)
+    }
+}
+
+#[cfg(feature = "usb-vendor")]
+impl<B: UsbBus> BridgeFunction<B> for VendorBulk<'_, B> {
+    fn read(&mut self, data: &mut [u8]) -> usb_device::Result<usize> {
+        VendorBulk::read(self, data)
+    }
+
+    fn write(&mut self, data: &[u8]) -> usb_device::Result<usize> {
+        VendorBulk::write(self, data)
+    }
+
+    fn flush(&mut self) -> usb_device::Result<()> {
+        VendorBulk::flush(self)
+    }
+
+    fn set_serial_state(&mut self, state: SerialState) -> usb_device::Result<()> {
+        VendorBulk::set_serial_state(self, state)
+    }
+
+    fn control_lines(&self) -> (bool, bool) {
+        (self.dtr(), self.rts())
+    }
+
+    fn take_line_coding(&mut self) -> Option<LineCoding> {
+        VendorBulk::take_line_coding(self)
+    }
+}
+
+/// Bus power state changes reported by `poll`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerEvent {
+    /// The host suspended the bus (3 ms idle)
+    Suspend,
+    /// The bus left suspend (host resume or reset)
+    Resume,
+}
+
+/// IDs and strings the device enumerates with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsbIdentity<'s> {
+    /// Vendor ID
+    pub vid: u16,
+    /// Product ID
+    pub pid: u16,
+    /// Manufacturer string descriptor
+    pub manufacturer: &'s str,
+    /// Product string descriptor
+    pub product: &'s str,
+    /// Serial number string descriptor
+    pub serial_number: &'s str,
+}
+
 /// USB OTG FS controller with serial CDC support
 pub struct OtgFsController<'a> {
     pub(crate) usb_device: Option<UsbDevice<'a, UsbBusType>>,
-    pub(crate) serial: Option<SerialPort<'a, UsbBusType>>,
+    /// Bridge port (interfaces 0-1, interface 0 as HID keyboard or vendor bulk)
+    pub(crate) serial: Option<BridgeClass<'a>>,
+    /// Debug console port (interfaces 2-3)
+    pub(crate) console: Option<CdcAcm<'a, UsbBusType>>,
+    /// DFU run-time function (interface 4)
+    pub(crate) dfu: Option<DfuRuntime>,
+    /// MS OS 2.0 descriptors (no interface)
+    pub(crate) ms_os: Option<MsOsDescriptors>,
+    /// Log volume mass storage (interface after DFU)
+    #[cfg(feature = "usb-msc")]
+    pub(crate) msc: Option<LogStorage<'a>>,
     rx_buffer: [u8; DATA_PACKET_SIZE],
+    /// Bytes of `rx_buffer` not yet taken by an `embedded_io` read
+    rx_pending: Range<usize>,
     tx_buffer: [u8; DATA_PACKET_SIZE],
+    chunk_size: usize,
+    suspended: bool,
+    power_event: Option<PowerEvent>,
 }
 
 impl<'a> OtgFsController<'a> {
@@ -55,6 +277,7 @@ impl<'a> OtgFsController<'a> {
     /// * `otg_fs_pwrclk` - OTG FS power/clock registers
     /// * `dm_pin` - USB D- pin (PA11)
     /// * `dp_pin` - USB D+ pin (PA12)
+    /// * `identity` - IDs and string descriptors; the strings must outlive the USB device
     /// * `clocks` - Clock configuration
     ///
     /// # Errors
@@ -70,6 +293,7 @@ impl<'a> OtgFsController<'a> {
         otg_fs_pwrclk: OTG_FS_PWRCLK,
         dm_pin: PA11<Alternate<10>>,
         dp_pin: PA12<Alternate<10>>,
+        identity: UsbIdentity<'static>,
         clocks: &'a RccConfig,
     ) -> Result<Self, UsbError> {
         if USB_BUS_INITIALIZED.load(Ordering::SeqCst) {
@@ -87,25 +311,50 @@ impl<'a> OtgFsController<'a> {
         let usb_ep_memory: &'static mut [u32; OTG_FS_BUFFER_LEN] =
             cortex_m::singleton!(: [u32; OTG_FS_BUFFER_LEN] = [0; OTG_FS_BUFFER_LEN])
                 .ok_or(UsbError::NotInitialized)?;
+        meminfo::register("usb ep memory", OTG_FS_BUFFER_LEN * 4);
+        meminfo::register("usb packet bufs", DATA_PACKET_SIZE * 2);
 
-        let (usb_device, serial) = unsafe {
+        #[cfg(feature = "usb-msc")]
+        let msc;
+        let (usb_device, serial, console, dfu, ms_os) = unsafe {
             // Инициализация USB шины
             USB_BUS = Some(UsbBusType::new(usb, usb_ep_memory));
 
             #[allow(static_mut_refs)]
             let bus_ref = USB_BUS.as_ref().unwrap();
 
-            let serial = SerialPort::new(bus_ref);
-            let usb_device = UsbDeviceBuilder::new(bus_ref, UsbVidPid(0x16c0, 0x27dd))
-                .device_class(USB_CLASS_CDC)
+            // The bridge is allocated first so it stays the host's first port
+            let serial = BridgeClass::new(bus_ref);
+            let console = CdcAcm::new(bus_ref);
+            let dfu = DfuRuntime::new(bus_ref);
+            let ms_os = MsOsDescriptors::new(&[
+                WinUsbFunction {
+                    interface: dfu.interface(),
+                    guid: DFU_INTERFACE_GUID,
+                },
+                #[cfg(feature = "usb-vendor")]
+                WinUsbFunction {
+                    interface: serial.interface(),
+                    guid: VENDOR_INTERFACE_GUID,
+                },
+            ])?;
+            #[cfg(feature = "usb-msc")]
+            {
+                msc = LogStorage::new(bus_ref, LogVolume::new());
+            }
+            let usb_device = UsbDeviceBuilder::new(bus_ref, UsbVidPid(identity.vid, identity.pid))
+                .composite_with_iads()
+                // 2.1 makes Windows read the BOS descriptor
+                .usb_rev(UsbRev::Usb210)
+                .supports_remote_wakeup(true)
                 .strings(&[StringDescriptors::default()
-                    .manufacturer("xvi.xv.xii.ix.xxii.ix.xiv")
-                    .product("USB-Serial Bridge")
-                    .serial_number("007")])
+                    .manufacturer(identity.manufacturer)
+                    .product(identity.product)
+                    .serial_number(identity.serial_number)])
                 .unwrap()
                 .build();
 
-            (Some(usb_device), Some(serial))
+            (Some(usb_device), Some(serial), Some(console), Some(dfu), Some(ms_os))
         };
 
         USB_BUS_INITIALIZED.store(true, Ordering::SeqCst);
@@ -113,8 +362,17 @@ impl<'a> OtgFsController<'a> {
         Ok(Self {
             usb_device,
             serial,
+            console,
+            dfu,
+            ms_os,
+            #[cfg(feature = "usb-msc")]
+            msc: Some(msc),
             rx_buffer: [0; DATA_PACKET_SIZE],
+            rx_pending: 0..0,
             tx_buffer: [0; DATA_PACKET_SIZE],
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            suspended: false,
+            power_event: None,
         })
     }
 
@@ -127,22 +385,27 @@ impl<'a> OtgFsController<'a> {
     pub fn read(&mut self) -> Result<Option<(&[u8], usize)>, UsbError> {
         let serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
 
-        match serial.read(&mut self.rx_buffer) {
-            Ok(count) if count > 0 => Ok(Some((&self.rx_buffer[..count], count))),
-            Ok(_) => Ok(None),
-            Err(usb_device::UsbError::WouldBlock) => {
-                #[cfg(feature = "debug")]
-                defmt::trace!("USB read would block");
-                Ok(None)
-            }
-            Err(_) => {
-                #[cfg(feature = "debug")]
-                defmt::error!("USB read error");
-                Err(UsbError::ReadError)
-            }
+        match map_read(serial.read(&mut self.rx_buffer))? {
+            0 => Ok(None),
+            count => Ok(Some((&self.rx_buffer[..count], count))),
         }
     }
 
+    /// Reads one received packet straight into caller memory
+    ///
+    /// Skips the internal RX buffer, e.g. to fill a DMA TX buffer directly.
+    ///
+    /// # Arguments
+    /// * `buffer` - Destination, at least `CDC_MAX_PACKET_SIZE` bytes to
+    ///   take a full packet
+    ///
+    /// # Returns
+    /// Number of bytes read; 0 when no data is available
+    pub fn read_into(&mut self, buffer: &mut [u8]) -> Result<usize, UsbError> {
+        let serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
+        map_read(serial.read(buffer))
+    }
+
     /// Writes data to USB interface
     ///
     /// # Arguments
@@ -161,26 +424,177 @@ impl<'a> OtgFsController<'a> {
 
         self.tx_buffer[..data.len()].copy_from_slice(data);
 
-        serial
+        let written = serial
             .write(&self.tx_buffer[..data.len()])
-            .map_err(|_| UsbError::WriteError)
+            .map_err(|_| UsbError::WriteError)?;
+
+        // A transfer ending on a packet boundary needs a ZLP before the host
+        // completes the read; flushing lets the class queue it right away
+        if written > 0 && written % CDC_MAX_PACKET_SIZE == 0 {
+            self.flush()?;
+        }
+
+        Ok(written)
+    }
+
+    /// Pushes buffered data to the host, terminating the transfer
+    ///
+    /// # Errors
+    /// Returns `UsbError::WriteError` on endpoint failure; data still in flight
+    /// (`WouldBlock`) is not an error
+    pub fn flush(&mut self) -> Result<(), UsbError> {
+        let serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
+
+        match serial.flush() {
+            Ok(()) | Err(usb_device::UsbError::WouldBlock) => Ok(()),
+            Err(_) => Err(UsbError::WriteError),
+        }
+    }
+
+    /// Sets the number of bytes moved to USB per write
+    ///
+    /// # Arguments
+    /// * `size` - Requested chunk size, clamped to `1..=DATA_PACKET_SIZE`
+    ///
+    /// # Returns
+    /// The chunk size actually applied
+    pub fn set_chunk_size(&mut self, size: usize) -> usize {
+        self.chunk_size = size.clamp(1, DATA_PACKET_SIZE);
+
+        #[cfg(feature = "debug")]
+        defmt::info!("USB chunk size set to {}", self.chunk_size);
+
+        self.chunk_size
+    }
+
+    /// Current chunk size in bytes
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Reports modem status lines to the host
+    ///
+    /// # Arguments
+    /// * `state` - Current DSR/DCD/RI (and error) bits
+    ///
+    /// # Errors
+    /// Returns `UsbError::WriteError` on notification endpoint failure
+    pub fn set_serial_state(&mut self, state: SerialState) -> Result<(), UsbError> {
+        let serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
+        serial.set_serial_state(state).map_err(|_| UsbError::WriteError)
+    }
+
+    /// Host-controlled DTR and RTS lines
+    ///
+    /// # Returns
+    /// `(dtr, rts)`, both `false` before the host opens the port
+    pub fn control_lines(&self) -> (bool, bool) {
+        self.serial
+            .as_ref()
+            .map_or((false, false), |serial| serial.control_lines())
+    }
+
+    /// Takes the data port line coding if the host changed it
+    ///
+    /// # Returns
+    /// `Some` once per SET_LINE_CODING request
+    pub fn take_line_coding(&mut self) -> Option<LineCoding> {
+        self.serial.as_mut().and_then(|serial| serial.take_line_coding())
+    }
+
+    /// Takes the data port break request if the host sent one
+    ///
+    /// # Returns
+    /// `Some` once per SEND_BREAK request (and after a bus reset)
+    pub fn take_break_request(&mut self) -> Option<BreakRequest> {
+        self.serial
+            .as_mut()
+            .and_then(|serial| serial.take_break_request())
+    }
+
+    /// Reads one packet from the debug console port
+    ///
+    /// # Arguments
+    /// * `data` - Destination, at least `CDC_MAX_PACKET_SIZE` bytes
+    ///
+    /// # Returns
+    /// Number of bytes read, 0 when no packet is available
+    ///
+    /// # Errors
+    /// Returns `UsbError::ReadError` on endpoint failure
+    pub fn console_read(&mut self, data: &mut [u8]) -> Result<usize, UsbError> {
+        let console = self.console.as_mut().ok_or(UsbError::NotInitialized)?;
+
+        match console.read(data) {
+            Ok(count) => Ok(count),
+            Err(usb_device::UsbError::WouldBlock) => Ok(0),
+            Err(_) => Err(UsbError::ReadError),
+        }
+    }
+
+    /// Queues data on the debug console port
+    ///
+    /// # Arguments
+    /// * `data` - Bytes to send
+    ///
+    /// # Returns
+    /// Number of bytes accepted, 0 while the console write buffer is full
+    ///
+    /// # Errors
+    /// Returns `UsbError::WriteError` on endpoint failure
+    pub fn console_write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        let console = self.console.as_mut().ok_or(UsbError::NotInitialized)?;
+
+        match console.write(data) {
+            Ok(count) => Ok(count),
+            Err(usb_device::UsbError::WouldBlock) => Ok(0),
+            Err(_) => Err(UsbError::WriteError),
+        }
+    }
+
+    /// Checks whether a terminal has the debug console port open (DTR set)
+    pub fn console_connected(&self) -> bool {
+        self.is_configured() && self.console.as_ref().map_or(false, |console| console.dtr())
     }
 
     /// Polls USB device state and handles events
     ///
+    /// Entering or leaving the suspend state queues a `PowerEvent`.
+    ///
     /// # Returns
     /// `true` if device is active and polled successfully
     pub fn poll(&mut self) -> bool {
         if let Some(usb_dev) = &mut self.usb_device {
-            if let Some(serial) = &mut self.serial {
-                usb_dev.poll(&mut [serial]);
-
-                #[cfg(feature = "debug")]
-                match usb_dev.state() {
-                    UsbDeviceState::Configured => defmt::debug!("USB configured"),
-                    UsbDeviceState::Addressed => defmt::trace!("USB addressed"),
-                    UsbDeviceState::Default => defmt::trace!("USB default state"),
-                    UsbDeviceState::Suspend => defmt::warn!("USB suspended"),
+            if let (Some(serial), Some(console), Some(dfu), Some(ms_os)) =
+                (&mut self.serial, &mut self.console, &mut self.dfu, &mut self.ms_os)
+            {
+                #[cfg(not(feature = "usb-msc"))]
+                usb_dev.poll(&mut [serial, console, dfu, ms_os]);
+                #[cfg(feature = "usb-msc")]
+                match &mut self.msc {
+                    Some(msc) => usb_dev.poll(&mut [serial, console, dfu, ms_os, msc]),
+                    None => usb_dev.poll(&mut [serial, console, dfu, ms_os]),
+                };
+
+                // Called from the OTG_FS handler, or from a blocking
+                // `embedded_io` transfer keeping the handler locked out
+                let isr = IsrContext::enter();
+                let state = usb_dev.state();
+                match state {
+                    UsbDeviceState::Configured => isr_log!(isr, debug, "USB configured"),
+                    UsbDeviceState::Addressed => isr_log!(isr, trace, "USB addressed"),
+                    UsbDeviceState::Default => isr_log!(isr, trace, "USB default state"),
+                    UsbDeviceState::Suspend => isr_log!(isr, warn, "USB suspended"),
+                }
+
+                let suspended = state == UsbDeviceState::Suspend;
+                if suspended != self.suspended {
+                    self.suspended = suspended;
+                    self.power_event = Some(if suspended {
+                        PowerEvent::Suspend
+                    } else {
+                        PowerEvent::Resume
+                    });
                 }
 
                 return true;
@@ -189,6 +603,84 @@ impl<'a> OtgFsController<'a> {
         false
     }
 
+    /// Takes the last bus power state change
+    ///
+    /// # Returns
+    /// `Some` once per change; a suspend and resume between two calls
+    /// collapse into the later event
+    pub fn take_power_event(&mut self) -> Option<PowerEvent> {
+        self.power_event.take()
+    }
+
+    /// Checks whether the host has suspended the bus
+    pub fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    /// Checks whether the device may wake the host now
+    ///
+    /// # Returns
+    /// `true` while the bus is suspended and the host enabled remote wakeup
+    /// (SET_FEATURE DEVICE_REMOTE_WAKEUP)
+    pub fn can_wake_host(&self) -> bool {
+        self.suspended
+            && self
+                .usb_device
+                .as_ref()
+                .map_or(false, |dev| dev.remote_wakeup_enabled())
+    }
+
+    /// Starts or ends remote wakeup signalling (DCTL.RWUSIG)
+    ///
+    /// The usb-device bus API has no remote wakeup call, so the bit is set
+    /// directly. The caller keeps it set for 1 to 15 ms (USB 2.0 7.1.7.7).
+    ///
+    /// # Returns
+    /// `false` if starting was refused because `can_wake_host` is `false`
+    pub fn set_remote_wakeup(&mut self, active: bool) -> bool {
+        if active && !self.can_wake_host() {
+            return false;
+        }
+
+        // SAFETY: Read-modify-write of DCTL.RWUSIG only; the OTG driver does
+        // not touch it
+        unsafe {
+            (*OTG_FS_DEVICE::ptr()).dctl().modify(|r, w| {
+                if active {
+                    w.bits(r.bits() | DCTL_RWUSIG)
+                } else {
+                    w.bits(r.bits() & !DCTL_RWUSIG)
+                }
+            });
+        }
+        true
+    }
+
+    /// Takes the device off the bus (DCTL.SDIS)
+    ///
+    /// Releases the D+ pull-up, so the host sees a disconnect; only a reset
+    /// brings the device back. The OTG FS interrupt is masked first, so no
+    /// handler polls a bus that is gone.
+    pub fn detach(&mut self) {
+        cortex_m::peripheral::NVIC::mask(stm32f4xx_hal::pac::Interrupt::OTG_FS);
+
+        // SAFETY: Read-modify-write of DCTL.SDIS only; the OTG driver does
+        // not touch it and its interrupt is masked
+        unsafe {
+            (*OTG_FS_DEVICE::ptr())
+                .dctl()
+                .modify(|r, w| w.bits(r.bits() | DCTL_SDIS));
+        }
+    }
+
+    /// Takes a pending DFU detach request from the host
+    ///
+    /// # Returns
+    /// `true` once per request; the caller schedules the bootloader reboot
+    pub fn take_dfu_detach(&mut self) -> bool {
+        self.dfu.as_mut().map_or(false, |dfu| dfu.take_detach())
+    }
+
     /// Checks if USB device is in configured state
     pub fn is_configured(&self) -> bool {
         self.usb_device
@@ -196,6 +688,23 @@ impl<'a> OtgFsController<'a> {
             .map_or(false, |dev| dev.state() == UsbDeviceState::Configured)
     }
 
+    /// Checks whether the stack has no bus event left to handle
+    ///
+    /// An idle bus raises no OTG FS interrupt, so the handler does not run;
+    /// an unmasked event still pending (GINTSTS & GINTMSK) means the
+    /// handler has not served it yet.
+    pub fn is_idle(&self) -> bool {
+        if self.usb_device.is_none() {
+            return true;
+        }
+        // SAFETY: Reads only; the status bits are cleared by the OTG driver
+        let pending = unsafe {
+            let global = &*OTG_FS_GLOBAL::ptr();
+            global.fs_gintsts().read().bits() & global.fs_gintmsk().read().bits()
+        };
+        pending == 0
+    }
+
     /// Activates USB controller and enables interrupts
     ///
     /// # Errors
@@ -203,6 +712,9 @@ impl<'a> OtgFsController<'a> {
     pub fn start(&mut self) -> Result<(), UsbError> {
         let usb_dev = self.usb_device.as_mut().ok_or(UsbError::NotInitialized)?;
         let _serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
+        let _console = self.console.as_mut().ok_or(UsbError::NotInitialized)?;
+        #[cfg(feature = "usb-msc")]
+        let _msc = self.msc.as_mut().ok_or(UsbError::NotInitialized)?;
 
         // SAFETY: Single interrupt unmask operation
         unsafe {
@@ -227,6 +739,118 @@ impl<'a> OtgFsController<'a> {
     }
 }
 
+impl UsbLink for OtgFsController<'_> {
+    fn is_configured(&self) -> bool {
+        self.is_configured()
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.chunk_size()
+    }
+
+    fn read_into(&mut self, buffer: &mut [u8]) -> Result<usize, UsbError> {
+        self.read_into(buffer)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        self.write(data)
+    }
+}
+
+impl OtgFsController<'_> {
+    // Reads the next packet into `rx_buffer` once the previous one is taken
+    fn fill_rx_pending(&mut self) -> Result<bool, UsbError> {
+        if self.rx_pending.is_empty() {
+            let serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
+            let count = map_read(serial.read(&mut self.rx_buffer))?;
+            self.rx_pending = 0..count;
+        }
+        Ok(!self.rx_pending.is_empty())
+    }
+}
+
+/// Blocking byte streams on the bridge port, for drivers layered on the
+/// controller; the bridge handlers must not use the port meanwhile
+impl embedded_io::ErrorType for OtgFsController<'_> {
+    type Error = UsbError;
+}
+
+impl embedded_io::Read for OtgFsController<'_> {
+    /// Polls the device until the host sends data, then copies what `buf`
+    /// takes; the rest of the packet stays for the next read
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, UsbError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while !self.fill_rx_pending()? {
+            self.poll();
+        }
+
+        let Range { start, end } = self.rx_pending;
+        let count = buf.len().min(end - start);
+        buf[..count].copy_from_slice(&self.rx_buffer[start..start + count]);
+        self.rx_pending.start += count;
+        Ok(count)
+    }
+}
+
+impl embedded_io::ReadReady for OtgFsController<'_> {
+    fn read_ready(&mut self) -> Result<bool, UsbError> {
+        self.fill_rx_pending()
+    }
+}
+
+impl embedded_io::Write for OtgFsController<'_> {
+    /// Polls the device until the class takes at least one byte, at most
+    /// `DATA_PACKET_SIZE` per call
+    ///
+    /// # Errors
+    /// `UsbError::NotInitialized` while the host has not configured the
+    /// device, instead of waiting for it
+    fn write(&mut self, buf: &[u8]) -> Result<usize, UsbError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let data = &buf[..buf.len().min(DATA_PACKET_SIZE)];
+        loop {
+            if !self.is_configured() {
+                return Err(UsbError::NotInitialized);
+            }
+            let serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
+            match serial.write(data) {
+                Ok(0) | Err(usb_device::UsbError::WouldBlock) => {
+                    self.poll();
+                }
+                Ok(written) => {
+                    // ZLP rule of `write`
+                    if written % CDC_MAX_PACKET_SIZE == 0 {
+                        self.flush()?;
+                    }
+                    return Ok(written);
+                }
+                Err(_) => return Err(UsbError::WriteError),
+            }
+        }
+    }
+
+    /// Polls the device until the host has taken all queued data
+    fn flush(&mut self) -> Result<(), UsbError> {
+        loop {
+            if !self.is_configured() {
+                return Err(UsbError::NotInitialized);
+            }
+            let serial = self.serial.as_mut().ok_or(UsbError::NotInitialized)?;
+            match serial.flush() {
+                Ok(()) => return Ok(()),
+                Err(usb_device::UsbError::WouldBlock) => {
+                    self.poll();
+                }
+                Err(_) => return Err(UsbError::WriteError),
+            }
+        }
+    }
+}
+
 /// Cleanup implementation
 impl<'a> Drop for OtgFsController<'a> {
     fn drop(&mut self) {
@@ -243,3 +867,20 @@ impl<'a> Drop for OtgFsController<'a> {
         self.tx_buffer.fill(0);
     }
 }
+
+// Maps a data port read result; WouldBlock means no packet (0 bytes)
+fn map_read(result: usb_device::Result<usize>) -> Result<usize, UsbError> {
+    match result {
+        Ok(count) => Ok(count),
+        Err(usb_device::UsbError::WouldBlock) => {
+            #[cfg(feature = "debug")]
+            defmt::trace!("USB read would block");
+            Ok(0)
+        }
+        Err(_) => {
+            #[cfg(feature = "debug")]
+            defmt::error!("USB read error");
+            Err(UsbError::ReadError)
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! # Unused Pin Parking
+//!
+//! Puts every GPIO that no driver claims into analog mode without pulls:
+//! - Disables the digital input Schmitt trigger, removing leakage from floating pins
+//! - Reduces EMI and power consumption per low-power best practice
+//! - Board-specific exceptions come from `config::PIN_PARK_EXCLUDE`
+//! - The resulting map is kept for the SYSINFO report
+//!
+//! ## Safety Considerations
+//! - Must run at the end of init: the HAL resets a port when splitting it
+//! - Drivers adding pins must list them in `CLAIMED_PINS`, or export a pin
+//!   table merged in `park_unused_pins` (SDRAM)
+//! - Register access is limited to MODER/PUPDR of unclaimed pins and RCC GPIO clock enables
+
+use crate::config::PIN_PARK_EXCLUDE;
+use crate::peripherals::sdram;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::{self, Mutex};
+use stm32f4xx_hal::pac;
+
+/// Number of GPIO ports (A..K)
+pub const PORT_COUNT: usize = 11;
+
+/// GPIOA base address (RM0386, memory map)
+const GPIO_BASE: usize = 0x4002_0000;
+
+/// Address distance between consecutive GPIO ports
+const GPIO_STRIDE: usize = 0x400;
+
+/// MODER register offset
+const MODER_OFFSET: usize = 0x00;
+
+/// PUPDR register offset
+const PUPDR_OFFSET: usize = 0x0C;
+
+/// Pins configured by the drivers in `stm32f469_init`
+const CLAIMED_PINS: &[(char, u8)] = &[
+    ('A', 0),  // User button
+    ('A', 1),  // Modem DTR output
+    ('A', 2),  // Modem RTS output
+    ('A', 3),  // Modem CTS input
+    ('A', 4),  // ADC A5 or DAC channel 1
+    ('A', 5),  // DAC channel 2
+    ('A', 6),  // TIM3_CH1 RX timeout input
+    ('A', 7),  // TIM14_CH1 buzzer
+    ('A', 8),  // MCO1 clock output
+    ('A', 9),  // USB VBUS sensing
+    ('A', 11), // USB DM
+    ('A', 12), // USB DP
+    ('B', 1),  // ADC A0
+    ('B', 6),  // QSPI NCS
+    ('B', 8),  // I2C1 SCL or CAN1 RX
+    ('B', 9),  // I2C1 SDA or CAN1 TX
+    ('B', 10), // USART3 TX
+    ('B', 11), // USART3 RX
+    ('B', 14), // SPI2 MISO
+    ('B', 15), // SPI2 MOSI
+    ('C', 2),  // ADC A1
+    ('C', 3),  // ADC A2
+    ('C', 4),  // ADC A3
+    ('C', 5),  // ADC A4
+    ('C', 8),  // SDIO D0
+    ('C', 9),  // SDIO D1 or MCO2 clock output
+    ('C', 10), // SDIO D2
+    ('C', 11), // SDIO D3
+    ('C', 12), // SDIO CK
+    ('D', 2),  // SDIO CMD
+    ('D', 3),  // SPI2 SCK
+    ('D', 4),  // Orange LED
+    ('D', 5),  // Red LED
+    ('F', 6),  // QSPI IO3
+    ('F', 7),  // QSPI IO2
+    ('F', 8),  // QSPI IO0
+    ('F', 9),  // QSPI IO1
+    ('F', 10), // QSPI CLK
+    ('G', 2),  // SD card detect
+    ('G', 6),  // Green LED
+    ('G', 9),  // USART6 RX
+    ('G', 10), // SPI2 CS1 or RS-485 DE
+    ('G', 11), // Modem RI input
+    ('G', 12), // Modem DCD input
+    ('G', 13), // Modem DSR input
+    ('G', 14), // USART6 TX
+    ('H', 6),  // SPI2 CS0
+    ('H', 7),  // LCD reset
+    ('J', 5),  // Touch INT
+    ('K', 3),  // Blue LED
+];
+
+/// Parked pin masks per port, recorded by `park_unused_pins`
+static PARKED: Mutex<RefCell<[u16; PORT_COUNT]>> = Mutex::new(RefCell::new([0; PORT_COUNT]));
+
+/// Builds per-port pin masks from a (port, pin) list
+const fn masks(pins: &[(char, u8)]) -> [u16; PORT_COUNT] {
+    let mut masks = [0u16; PORT_COUNT];
+    let mut i = 0;
+    while i < pins.len() {
+        let (port, pin) = pins[i];
+        let index = (port as u8 - b'A') as usize;
+        masks[index] |= 1 << pin;
+        i += 1;
+    }
+    masks
+}
+
+/// Parks all unclaimed, non-excluded pins in analog mode
+///
+/// Port clocks that were off before parking are switched off again; the pin
+/// configuration is retained while the clock is gated.
+pub fn park_unused_pins() {
+    let fmc = masks(sdram::PINS);
+    let mut claimed = masks(CLAIMED_PINS);
+    for (claimed, fmc) in claimed.iter_mut().zip(fmc) {
+        *claimed |= fmc;
+    }
+    let excluded = masks(PIN_PARK_EXCLUDE);
+    let mut parked = [0u16; PORT_COUNT];
+
+    // SAFETY: Runs once at the end of init with interrupts not yet unmasked.
+    // Only unclaimed pins and the GPIO clock enable bits are modified.
+    unsafe {
+        let rcc = &*pac::RCC::ptr();
+        let clocks_before = rcc.ahb1enr().read().bits();
+        let port_bits = (1 << PORT_COUNT) - 1;
+        rcc.ahb1enr().modify(|r, w| w.bits(r.bits() | port_bits));
+        // Clock enable needs two peripheral cycles before the first access
+        cortex_m::asm::dsb();
+
+        for (port, parked) in parked.iter_mut().enumerate() {
+            let mask = !(claimed[port] | excluded[port]);
+            let base = GPIO_BASE + port * GPIO_STRIDE;
+            let moder = (base + MODER_OFFSET) as *mut u32;
+            let pupdr = (base + PUPDR_OFFSET) as *mut u32;
+
+            let mut mode_bits = 0u32;
+            for pin in 0..16 {
+                if mask & (1 << pin) != 0 {
+                    mode_bits |= 0b11 << (pin * 2);
+                }
+            }
+
+            pupdr.write_volatile(pupdr.read_volatile() & !mode_bits);
+            moder.write_volatile(moder.read_volatile() | mode_bits);
+            *parked = mask;
+        }
+
+        let restore = clocks_before | !port_bits;
+        rcc.ahb1enr().modify(|r, w| w.bits(r.bits() & restore));
+    }
+
+    interrupt::free(|cs| *PARKED.borrow(cs).borrow_mut() = parked);
+
+    #[cfg(feature = "debug")]
+    defmt::info!(
+        "Parked {} unused pins",
+        parked.iter().map(|m| m.count_ones()).sum::<u32>()
+    );
+}
+
+/// Parked pin masks per port (bit n = pin n parked)
+pub fn parked_pins() -> [u16; PORT_COUNT] {
+    interrupt::free(|cs| *PARKED.borrow(cs).borrow())
+}
+
+/// Writes the parked pin map
+///
+/// # Arguments
+/// * `out` - Text sink
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let parked = parked_pins();
+    let total: u32 = parked.iter().map(|m| m.count_ones()).sum();
+
+    writeln!(out, "Parked pins ({} analog):", total)?;
+    for (port, mask) in parked.iter().enumerate() {
+        writeln!(out, "  P{}: {:#06x}", (b'A' + port as u8) as char, mask)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! # Supply Voltage Monitoring (PVD)
+//!
+//! Warns about a sagging VDD before the brown-out reset pulls the MCU down,
+//! as on a marginal USB port or a long cable:
+//! - The programmable voltage detector compares VDD with `PVD_THRESHOLD_MV`
+//!   and signals both crossings on EXTI line 16
+//! - A dip below the threshold raises `DeviceError::SupplyDip`, so it shows
+//!   on the red LED, the USB log and in the persistent error log, which
+//!   survives the brown-out reset that may follow
+//! - The return above the threshold is logged and counted
+//! - `init` records whether a brown-out reset started this boot
+//!
+//! ## Hardware Configuration
+//! - Thresholds of 2.0 to 2.9 V (PWR_CR PLS), about 100 mV hysteresis
+//! - The brown-out reset level is set in the option bytes (BOR_LEV) and only
+//!   reported here; with the BOR off, the reset comes at ~1.8 V (PDR)
+//! - The detector keeps running in Stop mode, where a dip wakes the MCU
+//!
+//! ## Safety Considerations
+//! - A threshold at or below the BOR level gives no warning before the reset
+
+use crate::config::{PVD_ENABLED, PVD_THRESHOLD_MV};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::pac::{self, EXTI};
+
+/// PVD thresholds by PLS setting (millivolts, falling VDD)
+const LEVELS_MV: [u16; 8] = [2_000, 2_100, 2_300, 2_500, 2_600, 2_700, 2_800, 2_900];
+
+/// Brown-out reset levels by BOR_LEV setting, `None` with the BOR off
+const BOR_LEVELS_MV: [Option<u16>; 4] = [Some(2_700), Some(2_400), Some(2_100), None];
+
+/// EXTI line of the PVD output
+const PVD_LINE: u32 = 1 << 16;
+
+/// PWR_CR: PVD enable and level field, PWR_CSR: PVD output
+const PWR_CR_PVDE: u32 = 1 << 4;
+const PWR_CR_PLS_SHIFT: u32 = 5;
+const PWR_CR_PLS_MASK: u32 = 0b111 << PWR_CR_PLS_SHIFT;
+const PWR_CSR_PVDO: u32 = 1 << 2;
+
+/// FLASH_OPTCR BOR_LEV field
+const OPTCR_BOR_LEV_SHIFT: u32 = 2;
+const OPTCR_BOR_LEV_MASK: u32 = 0b11;
+
+// The threshold must have a PLS setting at or below it
+const _: () = assert!(PVD_THRESHOLD_MV >= LEVELS_MV[0]);
+
+/// Set while VDD is below the threshold
+static BELOW: AtomicBool = AtomicBool::new(false);
+
+/// Set when a brown-out reset started this boot
+static BROWN_OUT_RESET: AtomicBool = AtomicBool::new(false);
+
+static DIPS: AtomicU32 = AtomicU32::new(0);
+
+/// Supply threshold crossings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupplyEvent {
+    /// VDD fell below the threshold
+    Dip,
+    /// VDD is back above the threshold
+    Recovered,
+}
+
+/// PLS setting of `PVD_THRESHOLD_MV`: the highest level at or below it
+const fn level_setting() -> usize {
+    let mut setting = LEVELS_MV.len() - 1;
+    while LEVELS_MV[setting] > PVD_THRESHOLD_MV {
+        setting -= 1;
+    }
+    setting
+}
+
+/// Threshold in effect (millivolts)
+pub const fn threshold_mv() -> u16 {
+    LEVELS_MV[level_setting()]
+}
+
+/// Checks whether VDD is below the threshold
+pub fn is_below() -> bool {
+    BELOW.load(Ordering::Relaxed)
+}
+
+/// Checks whether a brown-out reset started this boot
+pub fn caused_last_reset() -> bool {
+    BROWN_OUT_RESET.load(Ordering::Relaxed)
+}
+
+/// Brown-out reset level from the option bytes (millivolts), `None` if off
+pub fn bor_level_mv() -> Option<u16> {
+    // SAFETY: Read-only access to the option control register
+    let optcr = unsafe { (*pac::FLASH::ptr()).optcr().read().bits() };
+    BOR_LEVELS_MV[((optcr >> OPTCR_BOR_LEV_SHIFT) & OPTCR_BOR_LEV_MASK) as usize]
+}
+
+/// Records the reset cause and starts the detector
+///
+/// Must run before `Iwdg::start`, which clears the reset flags. With
+/// `PVD_ENABLED`, EXTI line 16 is unmasked on both edges; a supply already
+/// below the threshold pends the line, so the dip is reported as soon as
+/// interrupts are enabled.
+pub fn init() {
+    // SAFETY: PWR_CR PVDE/PLS, EXTI line 16 and the RCC_CSR reset flags are
+    // not used by any other driver; PWR_CR is read-modify-written before
+    // interrupts are enabled
+    unsafe {
+        let rcc = &*pac::RCC::ptr();
+        let csr = rcc.csr().read();
+        // BORRSTF is also set by a power-on reset
+        BROWN_OUT_RESET.store(
+            csr.borrstf().bit_is_set() && csr.porrstf().bit_is_clear(),
+            Ordering::Relaxed,
+        );
+
+        if !PVD_ENABLED {
+            return;
+        }
+        rcc.apb1enr().modify(|_, w| w.pwren().set_bit());
+        let pwr = &*pac::PWR::ptr();
+        pwr.cr().modify(|r, w| {
+            w.bits(
+                (r.bits() & !PWR_CR_PLS_MASK)
+                    | ((level_setting() as u32) << PWR_CR_PLS_SHIFT)
+                    | PWR_CR_PVDE,
+            )
+        });
+
+        let exti = &*EXTI::ptr();
+        exti.rtsr().modify(|r, w| w.bits(r.bits() | PVD_LINE));
+        exti.ftsr().modify(|r, w| w.bits(r.bits() | PVD_LINE));
+        exti.pr().write(|w| w.bits(PVD_LINE));
+        exti.imr().modify(|r, w| w.bits(r.bits() | PVD_LINE));
+        if pwr.csr().read().bits() & PWR_CSR_PVDO != 0 {
+            exti.swier().modify(|r, w| w.bits(r.bits() | PVD_LINE));
+        }
+    }
+
+    #[cfg(feature = "debug")]
+    defmt::info!(
+        "PVD: {=u16} mV, last reset by brown-out: {}",
+        threshold_mv(),
+        caused_last_reset()
+    );
+}
+
+/// Takes a pending PVD interrupt
+///
+/// For the PVD interrupt handler. Repeated edges on the same side of the
+/// threshold are ignored.
+///
+/// # Returns
+/// The crossing, `None` if the state did not change
+pub fn take_event() -> Option<SupplyEvent> {
+    // SAFETY: Write-one-to-clear register, only line 16 is set; PWR_CSR is
+    // only read
+    let below = unsafe {
+        (*EXTI::ptr()).pr().write(|w| w.bits(PVD_LINE));
+        (*pac::PWR::ptr()).csr().read().bits() & PWR_CSR_PVDO != 0
+    };
+    if BELOW.swap(below, Ordering::Relaxed) == below {
+        return None;
+    }
+    if below {
+        DIPS.fetch_add(1, Ordering::Relaxed);
+        Some(SupplyEvent::Dip)
+    } else {
+        Some(SupplyEvent::Recovered)
+    }
+}
+
+/// Writes the threshold, the supply state, the BOR level and the reset cause
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    if PVD_ENABLED {
+        write!(
+            out,
+            "Supply: {} {} mV, {} dips",
+            if is_below() { "below" } else { "above" },
+            threshold_mv(),
+            DIPS.load(Ordering::Relaxed)
+        )?;
+    } else {
+        write!(out, "Supply: PVD off")?;
+    }
+    match bor_level_mv() {
+        Some(bor_mv) => write!(out, ", BOR {} mV", bor_mv)?,
+        None => write!(out, ", BOR off")?,
+    }
+    writeln!(
+        out,
+        ", last reset by brown-out: {}",
+        if caused_last_reset() { "yes" } else { "no" }
+    )
+}
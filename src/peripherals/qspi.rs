@@ -0,0 +1,643 @@
+//! # QSPI NOR Flash
+//!
+//! Driver for the 16 MB Micron N25Q128A on the Discovery board's QUADSPI
+//! interface, the base for logging, configuration storage and firmware
+//! staging:
+//! - Identification and software reset of the part at start-up
+//! - Indirect-mode reads (quad output fast read), page programming (quad
+//!   input fast program) and 4 KB subsector, 64 KB sector and bulk erase
+//! - Writes are split at page boundaries, so any range can be programmed
+//! - Erase and program completion through the QUADSPI automatic status
+//!   polling, with the flag status register checked for failures
+//! - Memory-mapped mode: the whole part reads at `MAPPED_BASE` like internal
+//!   flash (XIP, DMA sources); indirect commands are refused while mapped
+//! - The detected part and operation counters are kept for the `qspi` report
+//!
+//! ## Hardware Configuration
+//! - CLK PF10 (AF9), NCS PB6 (AF10), IO0 PF8 (AF10), IO1 PF9 (AF10),
+//!   IO2 PF7 (AF9), IO3 PF6 (AF9)
+//! - Kernel clock HCLK divided by `QSPI_PRESCALER + 1`
+//!
+//! ## Safety Considerations
+//! - Programming requires erased (0xFF) destination bytes; programmed bits
+//!   are not checked
+//! - Erase and program calls block until the part is ready, a sector erase
+//!   for up to `SECTOR_ERASE_TIMEOUT_MS`
+
+use crate::config::{QSPI_PRESCALER, SYSCLK};
+use crate::errors::errors::QspiError;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::{
+    gpio::{
+        gpiob::PB6,
+        gpiof::{PF10, PF6, PF7, PF8, PF9},
+        Alternate,
+    },
+    pac::QUADSPI,
+    rcc::{Enable, Reset},
+};
+
+/// Address of the memory-mapped flash
+pub const MAPPED_BASE: usize = 0x9000_0000;
+
+/// Flash size in bytes (16 MB)
+pub const FLASH_SIZE: u32 = 16 * 1024 * 1024;
+
+/// Program page size
+pub const PAGE_SIZE: u32 = 256;
+
+/// Smallest erasable unit
+pub const SUBSECTOR_SIZE: u32 = 4 * 1024;
+
+/// Erase unit of `EraseSize::Sector`
+pub const SECTOR_SIZE: u32 = 64 * 1024;
+
+/// JEDEC ID: Micron, N25Q serial NOR at 3 V, 128 Mbit
+const JEDEC_ID: [u8; 3] = [0x20, 0xBA, 0x18];
+
+/// Commands
+const CMD_RESET_ENABLE: u8 = 0x66;
+const CMD_RESET_MEMORY: u8 = 0x99;
+const CMD_READ_ID: u8 = 0x9F;
+const CMD_WRITE_ENABLE: u8 = 0x06;
+const CMD_READ_STATUS: u8 = 0x05;
+const CMD_READ_FLAG_STATUS: u8 = 0x70;
+const CMD_CLEAR_FLAG_STATUS: u8 = 0x50;
+const CMD_QUAD_OUTPUT_FAST_READ: u8 = 0x6B;
+const CMD_QUAD_INPUT_FAST_PROGRAM: u8 = 0x32;
+const CMD_SUBSECTOR_ERASE: u8 = 0x20;
+const CMD_SECTOR_ERASE: u8 = 0xD8;
+const CMD_BULK_ERASE: u8 = 0xC7;
+
+/// Dummy cycles of the quad output fast read (volatile configuration default)
+const READ_DUMMY_CYCLES: u32 = 8;
+
+/// Status register: write in progress, write enable latch
+const STATUS_WIP: u8 = 1 << 0;
+const STATUS_WEL: u8 = 1 << 1;
+
+/// Flag status register: erase failure, program failure, protection error
+const FLAG_ERASE_ERROR: u8 = 1 << 5;
+const FLAG_PROGRAM_ERROR: u8 = 1 << 4;
+const FLAG_PROTECTION_ERROR: u8 = 1 << 1;
+
+/// Worst-case operation times (N25Q128A datasheet, table 39)
+const PAGE_PROGRAM_TIMEOUT_MS: u32 = 5;
+const SUBSECTOR_ERASE_TIMEOUT_MS: u32 = 800;
+const SECTOR_ERASE_TIMEOUT_MS: u32 = 3_000;
+const BULK_ERASE_TIMEOUT_MS: u32 = 250_000;
+/// Software reset recovery time
+const RESET_TIMEOUT_MS: u32 = 1;
+
+/// QUADSPI CR bits
+const CR_EN: u32 = 1 << 0;
+const CR_ABORT: u32 = 1 << 1;
+const CR_SSHIFT: u32 = 1 << 4;
+const CR_APMS: u32 = 1 << 22;
+const CR_PRESCALER_SHIFT: u32 = 24;
+
+/// QUADSPI DCR: FSIZE (2^(FSIZE+1) bytes), CSHT (cycles - 1 of NCS high)
+const DCR_FSIZE: u32 = 23 << 16;
+const DCR_CSHT: u32 = 4 << 8;
+
+/// QUADSPI SR / FCR bits
+const SR_TCF: u32 = 1 << 1;
+const SR_FTF: u32 = 1 << 2;
+const SR_SMF: u32 = 1 << 3;
+const SR_BUSY: u32 = 1 << 5;
+const SR_FLEVEL_SHIFT: u32 = 8;
+const FCR_ALL: u32 = 0b1_1011;
+
+/// Line modes of the CCR phases
+const MODE_NONE: u32 = 0b00;
+const MODE_SINGLE: u32 = 0b01;
+const MODE_QUAD: u32 = 0b11;
+
+/// CCR functional modes
+const FMODE_INDIRECT_WRITE: u32 = 0b00;
+const FMODE_INDIRECT_READ: u32 = 0b01;
+const FMODE_AUTO_POLLING: u32 = 0b10;
+const FMODE_MEMORY_MAPPED: u32 = 0b11;
+
+/// 24-bit address size
+const ADSIZE_24: u32 = 0b10;
+
+/// Automatic polling interval (kernel clock cycles)
+const POLL_INTERVAL: u32 = 0x10;
+
+/// Busy-wait step of timeouts (microseconds)
+const WAIT_STEP_US: u32 = 10;
+
+/// Operation counters for the report
+static DETECTED: AtomicBool = AtomicBool::new(false);
+static BYTES_READ: AtomicU32 = AtomicU32::new(0);
+static BYTES_PROGRAMMED: AtomicU32 = AtomicU32::new(0);
+static ERASES: AtomicU32 = AtomicU32::new(0);
+static FAILURES: AtomicU32 = AtomicU32::new(0);
+static MAPPED: AtomicBool = AtomicBool::new(false);
+
+/// QUADSPI pins in their alternate functions
+pub type QspiPins = (
+    PF10<Alternate<9>>,
+    PB6<Alternate<10>>,
+    PF8<Alternate<10>>,
+    PF9<Alternate<10>>,
+    PF7<Alternate<9>>,
+    PF6<Alternate<9>>,
+);
+
+/// Erase granularity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EraseSize {
+    /// 4 KB subsector
+    Subsector,
+    /// 64 KB sector
+    Sector,
+}
+
+impl EraseSize {
+    const fn bytes(self) -> u32 {
+        match self {
+            EraseSize::Subsector => SUBSECTOR_SIZE,
+            EraseSize::Sector => SECTOR_SIZE,
+        }
+    }
+}
+
+/// One QUADSPI command: instruction, optional address, dummy cycles and data
+#[derive(Clone, Copy)]
+struct Command {
+    instruction: u8,
+    address: Option<u32>,
+    dummy_cycles: u32,
+    data_mode: u32,
+}
+
+impl Command {
+    const fn new(instruction: u8) -> Self {
+        Self {
+            instruction,
+            address: None,
+            dummy_cycles: 0,
+            data_mode: MODE_NONE,
+        }
+    }
+
+    fn address(mut self, address: u32) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    fn data(mut self, mode: u32) -> Self {
+        self.data_mode = mode;
+        self
+    }
+
+    fn dummy(mut self, cycles: u32) -> Self {
+        self.dummy_cycles = cycles;
+        self
+    }
+
+    /// CCR value for a functional mode
+    fn ccr(&self, fmode: u32) -> u32 {
+        let admode = if self.address.is_some() {
+            MODE_SINGLE
+        } else {
+            MODE_NONE
+        };
+        (fmode << 26)
+            | (self.data_mode << 24)
+            | (self.dummy_cycles << 18)
+            | (ADSIZE_24 << 12)
+            | (admode << 10)
+            | (MODE_SINGLE << 8)
+            | self.instruction as u32
+    }
+}
+
+/// N25Q128A driver on the QUADSPI interface
+pub struct QspiFlash {
+    qspi: QUADSPI,
+    _pins: QspiPins,
+    mapped: bool,
+}
+
+impl QspiFlash {
+    /// Enables QUADSPI, resets the flash and checks its JEDEC ID
+    ///
+    /// # Arguments
+    /// * `qspi` - QUADSPI peripheral instance
+    /// * `pins` - CLK, NCS and IO0-IO3 in their alternate functions
+    ///
+    /// # Errors
+    /// Returns `QspiError::WrongId` if no N25Q128A answers, or
+    /// `QspiError::Timeout` if the interface hangs
+    pub fn new(qspi: QUADSPI, pins: QspiPins) -> Result<Self, QspiError> {
+        // SAFETY: QUADSPI is owned exclusively by this driver
+        unsafe {
+            QUADSPI::enable_unchecked();
+            QUADSPI::reset_unchecked();
+        }
+
+        // SAFETY: Configuration before the interface is enabled
+        unsafe {
+            qspi.dcr().write(|w| w.bits(DCR_FSIZE | DCR_CSHT));
+            qspi.cr()
+                .write(|w| w.bits((QSPI_PRESCALER << CR_PRESCALER_SHIFT) | CR_SSHIFT | CR_EN));
+        }
+
+        let mut flash = Self {
+            qspi,
+            _pins: pins,
+            mapped: false,
+        };
+
+        flash.command(Command::new(CMD_RESET_ENABLE))?;
+        flash.command(Command::new(CMD_RESET_MEMORY))?;
+        busy_wait_us(RESET_TIMEOUT_MS * 1_000);
+
+        let mut id = [0u8; 3];
+        flash.read_command(Command::new(CMD_READ_ID).data(MODE_SINGLE), &mut id)?;
+        if id != JEDEC_ID {
+            #[cfg(feature = "debug")]
+            defmt::error!("QSPI flash ID {:x} not recognized", id);
+            return Err(QspiError::WrongId);
+        }
+
+        DETECTED.store(true, Ordering::Relaxed);
+
+        #[cfg(feature = "debug")]
+        defmt::info!("QSPI flash N25Q128A detected");
+
+        Ok(flash)
+    }
+
+    /// Reads bytes in indirect mode
+    ///
+    /// # Arguments
+    /// * `address` - Flash byte address
+    /// * `buffer` - Destination, filled completely
+    ///
+    /// # Errors
+    /// Returns `QspiError::OutOfBounds` past the end of the flash, or
+    /// `QspiError::MemoryMapped` while memory-mapped
+    pub fn read(&mut self, address: u32, buffer: &mut [u8]) -> Result<(), QspiError> {
+        check_range(address, buffer.len())?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+
+        let command = Command::new(CMD_QUAD_OUTPUT_FAST_READ)
+            .address(address)
+            .dummy(READ_DUMMY_CYCLES)
+            .data(MODE_QUAD);
+        self.read_command(command, buffer)?;
+        BYTES_READ.fetch_add(buffer.len() as u32, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Programs bytes, split at page boundaries
+    ///
+    /// # Arguments
+    /// * `address` - Flash byte address
+    /// * `data` - Bytes to write; destination must be erased
+    ///
+    /// # Errors
+    /// Returns:
+    /// - `QspiError::OutOfBounds` past the end of the flash
+    /// - `QspiError::ProgramError` if the flash reports a failure
+    /// - `QspiError::Timeout` if a page does not complete in time
+    pub fn program(&mut self, address: u32, data: &[u8]) -> Result<(), QspiError> {
+        check_range(address, data.len())?;
+
+        let mut address = address;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let page_left = (PAGE_SIZE - address % PAGE_SIZE) as usize;
+            let (chunk, tail) = rest.split_at(page_left.min(rest.len()));
+
+            self.write_enable()?;
+            let command = Command::new(CMD_QUAD_INPUT_FAST_PROGRAM)
+                .address(address)
+                .data(MODE_QUAD);
+            self.write_command(command, chunk)?;
+            self.wait_ready(PAGE_PROGRAM_TIMEOUT_MS)?;
+
+            BYTES_PROGRAMMED.fetch_add(chunk.len() as u32, Ordering::Relaxed);
+            address += chunk.len() as u32;
+            rest = tail;
+        }
+        Ok(())
+    }
+
+    /// Erases the subsector or sector containing `address`
+    ///
+    /// # Errors
+    /// Returns:
+    /// - `QspiError::OutOfBounds` past the end of the flash
+    /// - `QspiError::EraseError` if the flash reports a failure
+    /// - `QspiError::Timeout` if the erase does not complete in time
+    pub fn erase(&mut self, address: u32, size: EraseSize) -> Result<(), QspiError> {
+        check_range(address, 1)?;
+
+        #[cfg(feature = "debug")]
+        defmt::info!("Erasing QSPI {} bytes at {:#x}", size.bytes(), address);
+
+        let (instruction, timeout_ms) = match size {
+            EraseSize::Subsector => (CMD_SUBSECTOR_ERASE, SUBSECTOR_ERASE_TIMEOUT_MS),
+            EraseSize::Sector => (CMD_SECTOR_ERASE, SECTOR_ERASE_TIMEOUT_MS),
+        };
+        let aligned = address - address % size.bytes();
+
+        self.write_enable()?;
+        self.command(Command::new(instruction).address(aligned))?;
+        self.wait_ready(timeout_ms)?;
+        ERASES.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Erases the whole flash
+    ///
+    /// Takes minutes on a full part; the caller must keep the watchdog fed
+    /// or check it in beforehand.
+    ///
+    /// # Errors
+    /// Same as `erase`
+    pub fn erase_all(&mut self) -> Result<(), QspiError> {
+        #[cfg(feature = "debug")]
+        defmt::warn!("Erasing the whole QSPI flash");
+
+        self.write_enable()?;
+        self.command(Command::new(CMD_BULK_ERASE))?;
+        self.wait_ready(BULK_ERASE_TIMEOUT_MS)?;
+        ERASES.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Switches to memory-mapped mode
+    ///
+    /// # Returns
+    /// The flash contents as a slice at `MAPPED_BASE`
+    ///
+    /// # Errors
+    /// Returns `QspiError::Timeout` if a pending command does not finish
+    pub fn memory_map(&mut self) -> Result<&'static [u8], QspiError> {
+        if !self.mapped {
+            self.wait_idle()?;
+            let command = Command::new(CMD_QUAD_OUTPUT_FAST_READ)
+                .address(0)
+                .dummy(READ_DUMMY_CYCLES)
+                .data(MODE_QUAD);
+            // SAFETY: The interface is idle; writing CCR starts the mode
+            unsafe {
+                self.qspi
+                    .ccr()
+                    .write(|w| w.bits(command.ccr(FMODE_MEMORY_MAPPED)));
+            }
+            self.mapped = true;
+            MAPPED.store(true, Ordering::Relaxed);
+        }
+
+        // SAFETY: The range is the QUADSPI memory-mapped bank, readable for
+        // as long as the mode stays on. `unmap` takes `&mut self`, so callers
+        // must not keep the slice across it
+        Ok(unsafe { core::slice::from_raw_parts(MAPPED_BASE as *const u8, FLASH_SIZE as usize) })
+    }
+
+    /// Leaves memory-mapped mode, making indirect commands available again
+    ///
+    /// # Errors
+    /// Returns `QspiError::Timeout` if the abort does not complete
+    pub fn unmap(&mut self) -> Result<(), QspiError> {
+        if self.mapped {
+            self.abort()?;
+            self.mapped = false;
+            MAPPED.store(false, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Checks whether the flash is memory-mapped
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    /// Sets the write enable latch and checks it
+    fn write_enable(&mut self) -> Result<(), QspiError> {
+        self.command(Command::new(CMD_WRITE_ENABLE))?;
+
+        let mut status = [0u8];
+        self.read_command(Command::new(CMD_READ_STATUS).data(MODE_SINGLE), &mut status)?;
+        if status[0] & STATUS_WEL == 0 {
+            return Err(QspiError::WriteProtected);
+        }
+        Ok(())
+    }
+
+    /// Waits for the end of a program or erase and checks its outcome
+    ///
+    /// The QUADSPI polls the status register until WIP clears; the flag
+    /// status register is read and cleared afterwards.
+    fn wait_ready(&mut self, timeout_ms: u32) -> Result<(), QspiError> {
+        let command = Command::new(CMD_READ_STATUS).data(MODE_SINGLE);
+
+        // SAFETY: Auto-polling setup of an idle interface; the match on
+        // WIP = 0 stops polling (APMS)
+        unsafe {
+            self.qspi.psmkr().write(|w| w.bits(STATUS_WIP as u32));
+            self.qspi.psmar().write(|w| w.bits(0));
+            self.qspi.pir().write(|w| w.bits(POLL_INTERVAL));
+            self.qspi.cr().modify(|r, w| w.bits(r.bits() | CR_APMS));
+            self.qspi.dlr().write(|w| w.bits(0));
+            self.qspi
+                .ccr()
+                .write(|w| w.bits(command.ccr(FMODE_AUTO_POLLING)));
+        }
+
+        let matched = self.wait_flag(SR_SMF, timeout_ms * 1_000);
+        // SAFETY: Write-1-to-clear flag register
+        unsafe { self.qspi.fcr().write(|w| w.bits(FCR_ALL)) };
+        if matched.is_err() {
+            // Leave polling so the interface can take commands again
+            self.abort()?;
+            return self.fail(QspiError::Timeout);
+        }
+
+        let mut flags = [0u8];
+        self.read_command(
+            Command::new(CMD_READ_FLAG_STATUS).data(MODE_SINGLE),
+            &mut flags,
+        )?;
+        if flags[0] & (FLAG_ERASE_ERROR | FLAG_PROGRAM_ERROR | FLAG_PROTECTION_ERROR) == 0 {
+            return Ok(());
+        }
+
+        self.command(Command::new(CMD_CLEAR_FLAG_STATUS))?;
+        if flags[0] & FLAG_ERASE_ERROR != 0 {
+            self.fail(QspiError::EraseError)
+        } else {
+            self.fail(QspiError::ProgramError)
+        }
+    }
+
+    /// Runs a command without data
+    fn command(&mut self, command: Command) -> Result<(), QspiError> {
+        self.start(command, FMODE_INDIRECT_WRITE, 0)?;
+        self.finish()
+    }
+
+    /// Runs a command and reads its data phase into `buffer`
+    fn read_command(&mut self, command: Command, buffer: &mut [u8]) -> Result<(), QspiError> {
+        self.start(command, FMODE_INDIRECT_READ, buffer.len())?;
+
+        let dr = self.qspi.dr().as_ptr() as *const u8;
+        for byte in buffer.iter_mut() {
+            self.wait_fifo(|sr| (sr >> SR_FLEVEL_SHIFT) & 0x3F > 0 || sr & SR_TCF != 0)?;
+            // SAFETY: Byte read of the data register pops one FIFO byte
+            *byte = unsafe { dr.read_volatile() };
+        }
+        self.finish()
+    }
+
+    /// Runs a command and writes `data` as its data phase
+    fn write_command(&mut self, command: Command, data: &[u8]) -> Result<(), QspiError> {
+        self.start(command, FMODE_INDIRECT_WRITE, data.len())?;
+
+        let dr = self.qspi.dr().as_ptr() as *mut u8;
+        for &byte in data {
+            self.wait_fifo(|sr| sr & SR_FTF != 0)?;
+            // SAFETY: Byte write of the data register pushes one FIFO byte
+            unsafe { dr.write_volatile(byte) };
+        }
+        self.finish()
+    }
+
+    /// Programs DLR, CCR and AR; the command starts with the last write
+    fn start(&mut self, command: Command, fmode: u32, len: usize) -> Result<(), QspiError> {
+        if self.mapped {
+            return Err(QspiError::MemoryMapped);
+        }
+        self.wait_idle()?;
+
+        // SAFETY: The interface is idle. Without an address phase the CCR
+        // write starts the command, otherwise the AR write does
+        unsafe {
+            self.qspi.fcr().write(|w| w.bits(FCR_ALL));
+            self.qspi.cr().modify(|r, w| w.bits(r.bits() & !CR_APMS));
+            if len > 0 {
+                self.qspi.dlr().write(|w| w.bits(len as u32 - 1));
+            }
+            self.qspi.ccr().write(|w| w.bits(command.ccr(fmode)));
+            if let Some(address) = command.address {
+                self.qspi.ar().write(|w| w.bits(address));
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for transfer completion and clears it
+    fn finish(&mut self) -> Result<(), QspiError> {
+        let result = self.wait_flag(SR_TCF, PAGE_PROGRAM_TIMEOUT_MS * 1_000);
+        // SAFETY: Write-1-to-clear flag register
+        unsafe { self.qspi.fcr().write(|w| w.bits(FCR_ALL)) };
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.abort()?;
+                self.fail(e)
+            }
+        }
+    }
+
+    /// Aborts the running command or mode and waits for the interface
+    fn abort(&mut self) -> Result<(), QspiError> {
+        // SAFETY: ABORT is self-clearing and only stops QUADSPI activity
+        unsafe { self.qspi.cr().modify(|r, w| w.bits(r.bits() | CR_ABORT)) };
+        self.wait_idle()
+    }
+
+    fn wait_idle(&self) -> Result<(), QspiError> {
+        let mut waited_us = 0;
+        while self.qspi.sr().read().bits() & SR_BUSY != 0 {
+            if waited_us >= PAGE_PROGRAM_TIMEOUT_MS * 1_000 {
+                return Err(QspiError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        }
+        Ok(())
+    }
+
+    fn wait_flag(&self, flag: u32, timeout_us: u32) -> Result<(), QspiError> {
+        let mut waited_us = 0;
+        while self.qspi.sr().read().bits() & flag == 0 {
+            if waited_us >= timeout_us {
+                return Err(QspiError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        }
+        Ok(())
+    }
+
+    /// Waits until the FIFO condition holds; FIFO moves are quick, so the
+    /// timeout is a plain spin count
+    fn wait_fifo(&self, ready: impl Fn(u32) -> bool) -> Result<(), QspiError> {
+        for _ in 0..SYSCLK / 1_000 {
+            if ready(self.qspi.sr().read().bits()) {
+                return Ok(());
+            }
+        }
+        Err(QspiError::Timeout)
+    }
+
+    /// Counts a failed operation
+    fn fail(&self, error: QspiError) -> Result<(), QspiError> {
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "debug")]
+        defmt::error!("QSPI flash: {}", error.description());
+
+        Err(error)
+    }
+}
+
+/// Checks that `len` bytes at `address` lie within the flash
+fn check_range(address: u32, len: usize) -> Result<(), QspiError> {
+    let end = address as u64 + len as u64;
+    if end > FLASH_SIZE as u64 {
+        return Err(QspiError::OutOfBounds);
+    }
+    Ok(())
+}
+
+/// Spins for about `us` microseconds
+fn busy_wait_us(us: u32) {
+    cortex_m::asm::delay(SYSCLK / 1_000_000 * us);
+}
+
+/// Writes the detected part and the operation counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    if !DETECTED.load(Ordering::Relaxed) {
+        return writeln!(out, "QSPI flash: not detected");
+    }
+    writeln!(
+        out,
+        "QSPI flash: N25Q128A, {} KB{}",
+        FLASH_SIZE / 1024,
+        if MAPPED.load(Ordering::Relaxed) {
+            ", memory-mapped"
+        } else {
+            ""
+        }
+    )?;
+    writeln!(
+        out,
+        "  {} bytes read, {} programmed, {} erases, {} failures",
+        BYTES_READ.load(Ordering::Relaxed),
+        BYTES_PROGRAMMED.load(Ordering::Relaxed),
+        ERASES.load(Ordering::Relaxed),
+        FAILURES.load(Ordering::Relaxed)
+    )
+}
@@ -5,9 +5,13 @@
 //! - HSE (High Speed External) clock support
 //! - SYSCLK, PCLK1, and PCLK2 frequency configuration
 //! - PLL48CLK requirement for USB functionality
+//! - Register access after `freeze` for the runtime clock users:
+//!   `clock_scaling` (prescalers) and `mco` (clock outputs)
 //!
 //! ## Safety Considerations
-//! - Clock configuration should be performed once at system startup
+//! - Clock configuration should be performed once at system startup;
+//!   `clock_scaling` changes the prescalers and the SYSCLK source later on
+//!   and relies on the PLL configuration written here
 //! - Incorrect clock settings may cause unstable operation
 
 use stm32f4xx_hal::{
@@ -16,6 +20,113 @@ use stm32f4xx_hal::{
     rcc::{Clocks, RccExt},
 };
 
+/// RCC CFGR clock output fields: MCO1 [22:21], MCO1PRE [26:24],
+/// MCO2PRE [29:27], MCO2 [31:30]
+const CFGR_MCO1_SHIFT: u32 = 21;
+const CFGR_MCO1PRE_SHIFT: u32 = 24;
+const CFGR_MCO2PRE_SHIFT: u32 = 27;
+const CFGR_MCO2_SHIFT: u32 = 30;
+
+/// MCOxPRE values: 0xx = no division, 1xx = division by xx + 2
+const MCO_PRE_DIVIDED: u32 = 0b100;
+
+/// Highest MCO prescaler division
+pub const MCO_MAX_DIVIDER: u8 = 5;
+
+/// Microcontroller clock output pins
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McoPin {
+    /// MCO1 on PA8
+    Mco1,
+    /// MCO2 on PC9
+    Mco2,
+}
+
+impl McoPin {
+    /// Both outputs, in register order
+    pub const ALL: [McoPin; 2] = [Self::Mco1, Self::Mco2];
+
+    /// Name used in reports
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Mco1 => "MCO1",
+            Self::Mco2 => "MCO2",
+        }
+    }
+
+    const fn source_shift(self) -> u32 {
+        match self {
+            Self::Mco1 => CFGR_MCO1_SHIFT,
+            Self::Mco2 => CFGR_MCO2_SHIFT,
+        }
+    }
+
+    const fn prescaler_shift(self) -> u32 {
+        match self {
+            Self::Mco1 => CFGR_MCO1PRE_SHIFT,
+            Self::Mco2 => CFGR_MCO2PRE_SHIFT,
+        }
+    }
+}
+
+/// Clocks an MCO pin can put out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McoSource {
+    /// Internal 16 MHz RC oscillator (MCO1)
+    Hsi,
+    /// 32.768 kHz crystal (MCO1)
+    Lse,
+    /// External crystal (both)
+    Hse,
+    /// Main PLL output (both)
+    Pll,
+    /// System clock (MCO2)
+    Sysclk,
+}
+
+impl McoSource {
+    /// All sources
+    pub const ALL: [McoSource; 5] = [Self::Hsi, Self::Lse, Self::Hse, Self::Pll, Self::Sysclk];
+
+    /// Name used by the console
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Hsi => "hsi",
+            Self::Lse => "lse",
+            Self::Hse => "hse",
+            Self::Pll => "pll",
+            Self::Sysclk => "sysclk",
+        }
+    }
+
+    /// Looks up a source by name, in any case
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|source| source.name().eq_ignore_ascii_case(name))
+    }
+
+    /// MCOx field value of this source on `pin`, `None` if the pin cannot
+    /// put it out
+    pub const fn bits(self, pin: McoPin) -> Option<u32> {
+        match (pin, self) {
+            (McoPin::Mco1, Self::Hsi) => Some(0b00),
+            (McoPin::Mco1, Self::Lse) => Some(0b01),
+            (McoPin::Mco2, Self::Sysclk) => Some(0b00),
+            (_, Self::Hse) => Some(0b10),
+            (_, Self::Pll) => Some(0b11),
+            _ => None,
+        }
+    }
+
+    fn from_bits(pin: McoPin, bits: u32) -> Self {
+        Self::ALL
+            .into_iter()
+            .find(|source| source.bits(pin) == Some(bits))
+            .unwrap_or(Self::Hse)
+    }
+}
+
 /// Clock configuration structure
 pub struct RccConfig {
     /// Final clock configuration results
@@ -60,4 +171,77 @@ impl RccConfig {
 
         RccConfig { clocks }
     }
+
+    /// Reads RCC CFGR
+    ///
+    /// Valid any time after `new`; the clocks may since have been changed by
+    /// `clock_scaling`.
+    pub fn cfgr() -> u32 {
+        // SAFETY: Read-only access to a register the HAL no longer owns
+        // after `freeze`
+        unsafe { (*pac::RCC::ptr()).cfgr().read().bits() }
+    }
+
+    /// Replaces the `mask` bits of RCC CFGR with `bits`
+    ///
+    /// Runs in a critical section, so concurrent changes of other fields are
+    /// not lost. Only for use after `new`: the HAL writes CFGR in `freeze`.
+    ///
+    /// # Arguments
+    /// * `mask` - Fields to change
+    /// * `bits` - New field values, within `mask`
+    pub fn modify_cfgr(mask: u32, bits: u32) {
+        cortex_m::interrupt::free(|_| {
+            // SAFETY: The critical section makes the read-modify-write
+            // atomic; callers change only fields the HAL set up in `freeze`
+            unsafe {
+                (*pac::RCC::ptr())
+                    .cfgr()
+                    .modify(|r, w| w.bits((r.bits() & !mask) | (bits & mask)));
+            }
+        });
+    }
+
+    /// Selects the clock and the divider of an MCO pin
+    ///
+    /// The reference manual asks for the change while the pin is not in
+    /// its alternate function; `mco` switches it off around the call.
+    ///
+    /// # Arguments
+    /// * `pin` - Clock output
+    /// * `source` - Clock to put out
+    /// * `divider` - Division, 1 to `MCO_MAX_DIVIDER`
+    ///
+    /// # Returns
+    /// `false` if the pin cannot put out `source` or the divider is out of
+    /// range; nothing is changed then
+    pub fn set_mco(pin: McoPin, source: McoSource, divider: u8) -> bool {
+        let Some(source_bits) = source.bits(pin) else {
+            return false;
+        };
+        let prescaler = match divider {
+            1 => 0,
+            2..=MCO_MAX_DIVIDER => MCO_PRE_DIVIDED | u32::from(divider - 2),
+            _ => return false,
+        };
+
+        Self::modify_cfgr(
+            (0b11 << pin.source_shift()) | (0b111 << pin.prescaler_shift()),
+            (source_bits << pin.source_shift()) | (prescaler << pin.prescaler_shift()),
+        );
+        true
+    }
+
+    /// Reads back the clock and the divider of an MCO pin
+    pub fn mco(pin: McoPin) -> (McoSource, u8) {
+        let cfgr = Self::cfgr();
+        let source = McoSource::from_bits(pin, (cfgr >> pin.source_shift()) & 0b11);
+        let prescaler = (cfgr >> pin.prescaler_shift()) & 0b111;
+        let divider = if prescaler & MCO_PRE_DIVIDED != 0 {
+            (prescaler & 0b11) as u8 + 2
+        } else {
+            1
+        };
+        (source, divider)
+    }
 }
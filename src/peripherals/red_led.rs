@@ -1,13 +1,23 @@
 //! # Red LED Controller with Morse Code Support
 //!
 //! This module provides:
-//! - Basic LED control
+//! - Basic LED control through the generic `Led`
 //! - Morse code signaling capabilities
 //! - State machine for code transmission
-//! - Timing management
+//! - Timing management: runtime `MorseConfig` for speed and repeat policy
+//! - Dimmed to `LED_PWM_RED_LEVEL` with the `led-pwm` feature
 
-use crate::config::MAX_MORSE_LENGTH;
-use crate::utils::morse::number_to_morse;
+use crate::config::{
+    MAX_MORSE_LENGTH, MORSE_DOT_MAX_MS, MORSE_DOT_MIN_MS, MORSE_DOT_MS, MORSE_REPEATS,
+};
+use crate::peripherals::led::Led;
+use crate::utils::morse::error_code_to_morse;
+#[cfg(feature = "led-pwm")]
+use crate::{
+    config::LED_PWM_RED_LEVEL,
+    peripherals::led_pwm::{self, PwmChannel},
+};
+use core::fmt;
 use stm32f4xx_hal::gpio::{gpiod::PD5, Output, PushPull};
 
 /// Morse code transmission states
@@ -21,14 +31,108 @@ pub enum MorseState {
     Pause,
 }
 
+/// How often an error code is played before it leaves the error store
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepeatPolicy {
+    /// Played in this many runs, at least one
+    Times(u8),
+    /// Played in every run until the errors are cleared
+    UntilCleared,
+}
+
+impl RepeatPolicy {
+    /// Checks whether a code played `plays` times may be consumed
+    pub fn is_done(self, plays: u8) -> bool {
+        match self {
+            RepeatPolicy::Times(times) => plays >= times.max(1),
+            RepeatPolicy::UntilCleared => false,
+        }
+    }
+}
+
+/// Morse speed and repeat settings
+///
+/// All durations derive from the dot with the standard ratios: dash and
+/// letter gap three dots, word gap seven dots, symbol gap one dot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MorseConfig {
+    dot_ms: u32,
+    repeat: RepeatPolicy,
+}
+
+impl MorseConfig {
+    /// Creates a configuration
+    ///
+    /// # Returns
+    /// `None` if `dot_ms` is outside `MORSE_DOT_MIN_MS..=MORSE_DOT_MAX_MS`
+    /// or `repeat` is `Times(0)`
+    pub fn new(dot_ms: u32, repeat: RepeatPolicy) -> Option<Self> {
+        let valid_dot = (MORSE_DOT_MIN_MS..=MORSE_DOT_MAX_MS).contains(&dot_ms);
+        if !valid_dot || repeat == RepeatPolicy::Times(0) {
+            return None;
+        }
+        Some(Self { dot_ms, repeat })
+    }
+
+    /// Duration of a dot (milliseconds)
+    pub fn dot_ms(&self) -> u32 {
+        self.dot_ms
+    }
+
+    /// Duration of a dash (milliseconds)
+    pub fn dash_ms(&self) -> u32 {
+        self.dot_ms * 3
+    }
+
+    /// Pause between the symbols of a letter (milliseconds)
+    pub fn symbol_pause_ms(&self) -> u32 {
+        self.dot_ms
+    }
+
+    /// Pause between letters (milliseconds)
+    pub fn letter_pause_ms(&self) -> u32 {
+        self.dot_ms * 3
+    }
+
+    /// Pause between words (milliseconds)
+    pub fn word_pause_ms(&self) -> u32 {
+        self.dot_ms * 7
+    }
+
+    /// Repeat policy for played codes
+    pub fn repeat(&self) -> RepeatPolicy {
+        self.repeat
+    }
+}
+
+impl Default for MorseConfig {
+    fn default() -> Self {
+        Self {
+            dot_ms: MORSE_DOT_MS,
+            repeat: RepeatPolicy::Times(MORSE_REPEATS),
+        }
+    }
+}
+
+impl fmt::Display for MorseConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Morse: {} ms dot, ", self.dot_ms)?;
+        match self.repeat {
+            RepeatPolicy::Times(times) => write!(f, "each code played {}x", times),
+            RepeatPolicy::UntilCleared => write!(f, "codes repeated until cleared"),
+        }
+    }
+}
+
 /// Red LED controller with Morse code capabilities
 pub struct RedLed {
-    pin: PD5<Output<PushPull>>,
+    led: Led<PD5<Output<PushPull>>>,
     pub(crate) morse_sequence: Option<[u8; MAX_MORSE_LENGTH]>,
     pub(crate) morse_length: usize,
     pub(crate) morse_index: usize,
     pub(crate) morse_state: MorseState,
     pub(crate) last_toggle: u32,
+    pub(crate) morse_config: MorseConfig,
 }
 
 impl RedLed {
@@ -36,22 +140,34 @@ impl RedLed {
     ///
     /// # Arguments
     /// * `pin` - PD5 pin in push-pull output mode
-    pub fn init_off(mut pin: PD5<Output<PushPull>>) -> Self {
-        pin.set_high();
+    pub fn init_off(pin: PD5<Output<PushPull>>) -> Self {
         RedLed {
-            pin,
+            led: Led::init_off(pin),
             morse_sequence: None,
             morse_length: 0,
             morse_index: 0,
             morse_state: MorseState::Idle,
             last_toggle: 0,
+            morse_config: MorseConfig::default(),
         }
     }
 
+    /// Current Morse speed and repeat settings
+    pub fn morse_config(&self) -> MorseConfig {
+        self.morse_config
+    }
+
+    /// Replaces the Morse settings
+    ///
+    /// A sequence being played continues at the new speed.
+    pub fn set_morse_config(&mut self, config: MorseConfig) {
+        self.morse_config = config;
+    }
+
     /// Starts new Morse code sequence
     ///
     /// # Arguments
-    /// * `code` - Numeric code to convert to Morse
+    /// * `code` - Error code to convert to Morse (mnemonic or digits)
     /// * `buffer` - Temporary conversion buffer
     ///
     /// # Errors
@@ -63,14 +179,25 @@ impl RedLed {
         code: u16,
         buffer: &mut [u8],
     ) -> Result<(), &'static str> {
-        let length = number_to_morse(code, buffer).map_err(|_| "Conversion failed")?;
+        let length = error_code_to_morse(code, buffer).map_err(|_| "Conversion failed")?;
+        self.load_morse_sequence(&buffer[..length])
+    }
 
+    /// Loads a prepared Morse sequence for playback
+    ///
+    /// # Arguments
+    /// * `symbols` - Morse symbols (`.`, `-`, ` ` letter gap, `/` word gap)
+    ///
+    /// # Errors
+    /// Returns error if the sequence exceeds MAX_MORSE_LENGTH
+    pub fn load_morse_sequence(&mut self, symbols: &[u8]) -> Result<(), &'static str> {
+        let length = symbols.len();
         if length > MAX_MORSE_LENGTH {
             return Err("Sequence too long");
         }
 
         let mut sequence = [0u8; MAX_MORSE_LENGTH];
-        sequence[..length].copy_from_slice(&buffer[..length]);
+        sequence[..length].copy_from_slice(symbols);
 
         self.morse_sequence = Some(sequence);
         self.morse_length = length;
@@ -84,6 +211,11 @@ impl RedLed {
         Ok(())
     }
 
+    /// Checks if a Morse sequence is being played
+    pub fn is_playing(&self) -> bool {
+        self.morse_sequence.is_some()
+    }
+
     /// Resets Morse code transmission state
     pub fn reset_morse_state(&mut self) {
         self.morse_sequence = None;
@@ -110,25 +242,25 @@ impl RedLed {
 
     /// Sets LED to OFF state
     pub fn set_high(&mut self) {
-        self.pin.set_high();
+        #[cfg(feature = "led-pwm")]
+        led_pwm::set_level(PwmChannel::Red, 0);
+        self.led.off();
     }
 
     /// Sets LED to ON state
     pub fn set_low(&mut self) {
-        self.pin.set_low();
+        #[cfg(feature = "led-pwm")]
+        led_pwm::set_level(PwmChannel::Red, LED_PWM_RED_LEVEL);
+        self.led.on();
     }
 
     /// Checks if LED is currently ON
     pub fn is_on(&self) -> bool {
-        self.pin.is_set_low()
+        self.led.state()
     }
 
     /// Toggles LED state
     pub fn toggle(&mut self) {
-        if self.is_on() {
-            self.set_high();
-        } else {
-            self.set_low();
-        }
+        self.led.set(!self.led.state());
     }
-}
\ No newline at end of file
+}
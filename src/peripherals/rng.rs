@@ -0,0 +1,179 @@
+//! # Random Number Generator
+//!
+//! Wrapper around the true random number generator (TRNG):
+//! - 32-bit numbers from the analog noise source, read with `next_u32`
+//! - Seed errors (faulty noise source) and clock errors (RNG clock too slow)
+//!   are checked on every read and returned as `RngError`; a seed error
+//!   restarts the generator as the reference manual prescribes
+//! - Continuous test (FIPS PUB 140-2): the first number after a start is
+//!   only kept for comparison, and a number equal to its predecessor is
+//!   rejected
+//!
+//! ## Hardware Configuration
+//! - Clocked by PLL48CLK, shared with USB; enabled by `enable` from the
+//!   board setup
+//! - A new number is ready 40 RNG clock cycles after the previous one
+//!
+//! ## Safety Considerations
+//! - The data register hands out each number once, so only one context may
+//!   read at a time; a context that finds the generator taken gets
+//!   `RngError::Busy` and uses its fallback, as with the CRC unit
+
+use crate::config::{RNG_TIMEOUT_US, SYSCLK};
+use crate::errors::errors::RngError;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::pac;
+
+/// RNG enable bit in RCC AHB2ENR
+const RCC_RNGEN: u32 = 1 << 6;
+
+/// CR generator enable
+const CR_RNGEN: u32 = 1 << 2;
+
+/// SR bits: data ready, clock and seed error states, clock and seed error
+/// interrupt flags (cleared by writing 0)
+const SR_DRDY: u32 = 1 << 0;
+const SR_CECS: u32 = 1 << 1;
+const SR_SECS: u32 = 1 << 2;
+const SR_CEIS: u32 = 1 << 5;
+const SR_SEIS: u32 = 1 << 6;
+
+/// Busy-wait step of the ready wait (microseconds)
+const WAIT_STEP_US: u32 = 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// Previous number, and whether one was read since the last start
+static LAST: AtomicU32 = AtomicU32::new(0);
+static PRIMED: AtomicBool = AtomicBool::new(false);
+
+static NUMBERS: AtomicU32 = AtomicU32::new(0);
+static SEED_ERRORS: AtomicU32 = AtomicU32::new(0);
+static CLOCK_ERRORS: AtomicU32 = AtomicU32::new(0);
+static REPEATS: AtomicU32 = AtomicU32::new(0);
+static TIMEOUTS: AtomicU32 = AtomicU32::new(0);
+
+/// Enables the generator clock, starts it and reads the first number
+///
+/// Needs PLL48CLK, which the clock setup provides for USB.
+///
+/// # Errors
+/// The error of the first read: the generator stays enabled and may
+/// recover, later reads report again
+pub fn enable() -> Result<(), RngError> {
+    // SAFETY: Only the RNGEN bit of AHB2ENR is set
+    unsafe {
+        (*pac::RCC::ptr())
+            .ahb2enr()
+            .modify(|r, w| w.bits(r.bits() | RCC_RNGEN));
+    }
+    // SAFETY: Nothing reads the generator before `ENABLED` is set
+    restart(unsafe { &*pac::RNG::ptr() });
+    ENABLED.store(true, Ordering::Release);
+    next_u32().map(|_| ())
+}
+
+/// Switches the generator off and on; the next number is a new first one
+fn restart(rng: &pac::rng::RegisterBlock) {
+    // SAFETY: RNGEN is the only bit of CR used; the interrupt stays off
+    unsafe {
+        rng.cr().write(|w| w.bits(0));
+        rng.cr().write(|w| w.bits(CR_RNGEN));
+    }
+    PRIMED.store(false, Ordering::Relaxed);
+}
+
+/// Reads the next random number
+///
+/// # Errors
+/// - `Busy` if the generator is not enabled or another context reads it
+/// - `Seed` or `Clock` if the generator reports a fault
+/// - `Repeated` if the continuous test fails
+/// - `Timeout` if no number is ready within `RNG_TIMEOUT_US`
+pub fn next_u32() -> Result<u32, RngError> {
+    if !ENABLED.load(Ordering::Acquire) || IN_USE.swap(true, Ordering::Acquire) {
+        return Err(RngError::Busy);
+    }
+    // SAFETY: `IN_USE` makes this the only user of the RNG registers
+    let result = read(unsafe { &*pac::RNG::ptr() });
+    IN_USE.store(false, Ordering::Release);
+
+    let counter = match result {
+        Ok(_) => &NUMBERS,
+        Err(RngError::Seed) => &SEED_ERRORS,
+        Err(RngError::Clock) => &CLOCK_ERRORS,
+        Err(RngError::Repeated) => &REPEATS,
+        Err(RngError::Timeout) => &TIMEOUTS,
+        Err(RngError::Busy) => return result,
+    };
+    counter.fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    if let Err(e) = result {
+        defmt::warn!("RNG: {}", e.description());
+    }
+    result
+}
+
+fn read(rng: &pac::rng::RegisterBlock) -> Result<u32, RngError> {
+    loop {
+        let mut waited_us = 0;
+        let status = loop {
+            let status = rng.sr().read().bits();
+            if status & (SR_DRDY | SR_CECS | SR_SECS | SR_CEIS | SR_SEIS) != 0 {
+                break status;
+            }
+            if waited_us >= RNG_TIMEOUT_US {
+                return Err(RngError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        };
+
+        if status & (SR_SECS | SR_SEIS) != 0 {
+            // RM0386: clear SEIS, then restart the generator; the number
+            // being generated is discarded
+            // SAFETY: Writing 1 leaves the other flags unchanged
+            unsafe { rng.sr().write(|w| w.bits(!SR_SEIS)) };
+            restart(rng);
+            return Err(RngError::Seed);
+        }
+        if status & (SR_CECS | SR_CEIS) != 0 {
+            // The numbers already generated are still good, but the
+            // generator needs a faster clock to go on
+            // SAFETY: Writing 1 leaves the other flags unchanged
+            unsafe { rng.sr().write(|w| w.bits(!SR_CEIS)) };
+            return Err(RngError::Clock);
+        }
+
+        let value = rng.dr().read().bits();
+        let previous = LAST.swap(value, Ordering::Relaxed);
+        if !PRIMED.swap(true, Ordering::Relaxed) {
+            // First number after a start: only kept for the comparison
+            continue;
+        }
+        if value == previous {
+            return Err(RngError::Repeated);
+        }
+        return Ok(value);
+    }
+}
+
+fn busy_wait_us(us: u32) {
+    cortex_m::asm::delay(SYSCLK / 1_000_000 * us);
+}
+
+/// Writes the number and error counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "RNG: {} numbers, {} seed errors, {} clock errors, {} repeats, {} timeouts",
+        NUMBERS.load(Ordering::Relaxed),
+        SEED_ERRORS.load(Ordering::Relaxed),
+        CLOCK_ERRORS.load(Ordering::Relaxed),
+        REPEATS.load(Ordering::Relaxed),
+        TIMEOUTS.load(Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,170 @@
+//! # RS-485 Driver Enable
+//!
+//! Half-duplex operation of a UART on an RS-485 bus through an external
+//! transceiver (MAX485 or similar):
+//! - The driver enable (DE) output is asserted before a TX DMA transfer
+//!   starts and released from the transmission-complete interrupt, once the
+//!   stop bit of the last frame is on the bus
+//! - Turnaround delays in bit times before the first start bit and after
+//!   the last stop bit (`Rs485Config`), capped at `RS485_MAX_DELAY_US`
+//! - A break holds the driver enabled as well
+//!
+//! The receiver sees the bus whenever the driver is off; with RE tied to
+//! DE, the bridge does not hear its own transmissions.
+//!
+//! ## Hardware Configuration
+//! - `rs485` feature: DE on PG10 (Arduino D8), which SPI2 uses as CS1
+//!   otherwise, driving USART6 (PG14/PG9) onto the bus
+//! - Any push-pull output works as DE (`DriverEnable::new`)
+
+#[cfg(all(feature = "rs485", feature = "spi"))]
+compile_error!("the `rs485` and `spi` features both need PG10");
+
+use crate::config::{
+    RS485_DE_ACTIVE_HIGH, RS485_MAX_DELAY_US, RS485_POST_DELAY_BITS, RS485_PRE_DELAY_BITS, SYSCLK,
+};
+use core::cell::Cell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::interrupt::Mutex;
+use stm32f4xx_hal::gpio::{ErasedPin, Output};
+
+/// Driver enable output pin
+pub type DriverEnablePin = ErasedPin<Output>;
+
+/// Polarity and turnaround timing of the driver enable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rs485Config {
+    /// DE is driven high while transmitting
+    pub active_high: bool,
+    /// Delay from asserting DE to the first start bit, in bit times
+    pub pre_delay_bits: u16,
+    /// Delay from the last stop bit to releasing DE, in bit times
+    pub post_delay_bits: u16,
+}
+
+impl Rs485Config {
+    /// `config` defaults
+    pub const DEFAULT: Self = Self {
+        active_high: RS485_DE_ACTIVE_HIGH,
+        pre_delay_bits: RS485_PRE_DELAY_BITS,
+        post_delay_bits: RS485_POST_DELAY_BITS,
+    };
+}
+
+impl Default for Rs485Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Configuration of the attached driver enable, `None` without one
+static CONFIG: Mutex<Cell<Option<Rs485Config>>> = Mutex::new(Cell::new(None));
+
+/// Times the driver was enabled
+static TURNAROUNDS: AtomicU32 = AtomicU32::new(0);
+
+/// Driver enable output of an RS-485 transceiver
+pub struct DriverEnable {
+    pin: DriverEnablePin,
+    config: Rs485Config,
+    asserted: bool,
+}
+
+impl DriverEnable {
+    /// Creates the output with the driver off
+    ///
+    /// # Arguments
+    /// * `pin` - Push-pull output wired to the transceiver's DE input
+    /// * `config` - Polarity and turnaround delays
+    pub fn new(pin: DriverEnablePin, config: Rs485Config) -> Self {
+        let mut de = Self {
+            pin,
+            config,
+            asserted: false,
+        };
+        de.drive(false);
+        cortex_m::interrupt::free(|cs| CONFIG.borrow(cs).set(Some(config)));
+        de
+    }
+
+    /// Polarity and turnaround delays in use
+    pub fn config(&self) -> Rs485Config {
+        self.config
+    }
+
+    /// Changes polarity and turnaround delays
+    ///
+    /// The driver is off afterwards; call between transfers.
+    pub fn set_config(&mut self, config: Rs485Config) {
+        self.config = config;
+        self.drive(false);
+        self.asserted = false;
+        cortex_m::interrupt::free(|cs| CONFIG.borrow(cs).set(Some(config)));
+    }
+
+    /// Checks whether the driver is enabled
+    pub fn is_asserted(&self) -> bool {
+        self.asserted
+    }
+
+    /// Enables the driver and waits the pre-transmit delay
+    ///
+    /// No delay if the driver is still enabled from the previous transfer.
+    ///
+    /// # Arguments
+    /// * `baud_rate` - Current UART rate, for the delay in bit times
+    pub fn assert(&mut self, baud_rate: u32) {
+        if self.asserted {
+            return;
+        }
+        self.drive(true);
+        self.asserted = true;
+        TURNAROUNDS.fetch_add(1, Ordering::Relaxed);
+        wait_bits(self.config.pre_delay_bits, baud_rate);
+    }
+
+    /// Waits the post-transmit delay and disables the driver
+    ///
+    /// # Arguments
+    /// * `baud_rate` - Current UART rate, for the delay in bit times
+    pub fn release(&mut self, baud_rate: u32) {
+        if !self.asserted {
+            return;
+        }
+        wait_bits(self.config.post_delay_bits, baud_rate);
+        self.drive(false);
+        self.asserted = false;
+    }
+
+    fn drive(&mut self, enabled: bool) {
+        let high = enabled == self.config.active_high;
+        self.pin.set_state(high.into());
+    }
+}
+
+/// Busy-waits `bits` bit times, at most `RS485_MAX_DELAY_US`
+fn wait_bits(bits: u16, baud_rate: u32) {
+    if bits == 0 || baud_rate == 0 {
+        return;
+    }
+    let max_cycles = u64::from(SYSCLK / 1_000_000) * u64::from(RS485_MAX_DELAY_US);
+    let cycles = (u64::from(bits) * u64::from(SYSCLK) / u64::from(baud_rate)).min(max_cycles);
+    cortex_m::asm::delay(cycles as u32);
+}
+
+/// Writes the driver enable configuration and how often it was enabled
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let config = cortex_m::interrupt::free(|cs| CONFIG.borrow(cs).get());
+    match config {
+        Some(config) => writeln!(
+            out,
+            "RS-485: DE active-{}, turnaround {}/{} bit times, {} transmissions",
+            if config.active_high { "high" } else { "low" },
+            config.pre_delay_bits,
+            config.post_delay_bits,
+            TURNAROUNDS.load(Ordering::Relaxed)
+        ),
+        None => writeln!(out, "RS-485: off (full duplex)"),
+    }
+}
@@ -0,0 +1,526 @@
+//! # Real-Time Clock
+//!
+//! Brings up the real-time clock, preferably on the 32.768 kHz LSE crystal,
+//! as an independent time base and calendar:
+//! - LSE start-up is non-blocking; readiness is polled later, so a board
+//!   without the crystal neither hangs nor delays boot
+//! - Without the LSE, the RTC falls back to the internal LSI (about 32 kHz,
+//!   a few percent off); the calendar runs, drift estimation does not
+//! - LSE prescalers give a 4096 Hz sub-second counter (244 µs resolution)
+//! - An RTC already running (warm reset) is reused untouched, including the
+//!   calendar and the clock source
+//! - Calendar time (`now`, `set_time`), years 2000 to 2099; it starts at
+//!   2000-01-01 00:00:00 after a backup domain reset
+//! - Backup registers carry small values across resets (e.g. boot requests)
+//! - The wakeup timer ends Stop mode periods (`arm_wakeup`, EXTI line 22)
+//!
+//! ## Hardware Configuration
+//! - LSE crystal on PC14/PC15 (X2 on the discovery board)
+//!
+//! ## Safety Considerations
+//! - Raw register access: the HAL `Rtc` waits for LSERDY without a timeout
+//! - PWR is only touched to set CR.DBP (backup domain write access)
+//! - BDCR/RTC are not used by any other module
+//! - The wakeup timer functions only touch the WUT bits, never used by `Rtc`
+//! - Calendar reads and writes run in a critical section, so the shadow
+//!   register lock of one reader is never released by another
+
+use crate::utils::datetime::EPOCH_YEAR;
+use core::fmt;
+use stm32f4xx_hal::pac::{self, RTC};
+
+pub use crate::utils::datetime::DateTime;
+
+/// Asynchronous prescaler: 32768 Hz / (7 + 1) = 4096 Hz
+const PREDIV_A: u32 = 7;
+
+/// Synchronous prescaler: 4096 Hz / (4095 + 1) = 1 Hz
+const PREDIV_S: u32 = 4_095;
+
+/// LSI prescalers: 32000 Hz / (127 + 1) / (249 + 1) = 1 Hz
+const PREDIV_A_LSI: u32 = 127;
+const PREDIV_S_LSI: u32 = 249;
+
+/// Nominal LSI frequency (Hz)
+const LSI_HZ: u32 = 32_000;
+
+/// LSE frequency (Hz)
+const LSE_HZ: u32 = 32_768;
+
+/// Sub-second ticks per second
+pub const RTC_TICKS_PER_SECOND: u32 = PREDIV_S + 1;
+
+/// Seconds per calendar day (time-of-day wrap)
+const SECONDS_PER_DAY: u32 = 86_400;
+
+/// RCC BDCR bits
+const BDCR_LSEON: u32 = 1 << 0;
+const BDCR_LSERDY: u32 = 1 << 1;
+const BDCR_RTCSEL_MASK: u32 = 0b11 << 8;
+const BDCR_RTCSEL_LSE: u32 = 0b01 << 8;
+const BDCR_RTCSEL_LSI: u32 = 0b10 << 8;
+const BDCR_RTCEN: u32 = 1 << 15;
+
+/// RCC CSR bits
+const CSR_LSION: u32 = 1 << 0;
+const CSR_LSIRDY: u32 = 1 << 1;
+
+/// RTC ISR bits
+const ISR_RSF: u32 = 1 << 5;
+const ISR_INITF: u32 = 1 << 6;
+const ISR_INIT: u32 = 1 << 7;
+
+/// RTC CR bits
+const CR_WUCKSEL_MASK: u32 = 0b111;
+const CR_WUTE: u32 = 1 << 10;
+const CR_WUTIE: u32 = 1 << 14;
+
+/// RTC ISR wakeup timer bits
+const ISR_WUTWF: u32 = 1 << 2;
+const ISR_WUTF: u32 = 1 << 10;
+
+/// Wakeup timer clock divider: RTCCLK / 16 (CR.WUCKSEL = 0)
+const WAKEUP_TIMER_DIV: u32 = 16;
+
+/// EXTI line of the RTC wakeup event
+const EXTI_WAKEUP_LINE: u32 = 1 << 22;
+
+/// Polling limit for RTC flag waits (cycles of a tight loop)
+const FLAG_POLL_LIMIT: u32 = 100_000;
+
+/// Offset of BKP0R in the RTC register block
+const BKP_OFFSET: usize = 0x50;
+
+/// Number of RTC backup registers
+pub const BACKUP_REGISTER_COUNT: usize = 20;
+
+/// Backup register holding a reboot request (see `dfu_runtime`)
+pub const BKP_BOOT_REQUEST: usize = 0;
+
+/// RTC start-up state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RtcState {
+    /// LSE oscillator started, not yet stable
+    Starting,
+    /// RTC running, see `clock_source`
+    Running,
+    /// Neither the LSE nor the LSI fallback started
+    Absent,
+}
+
+/// RTC clock sources
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RtcClock {
+    /// 32.768 kHz crystal
+    Lse,
+    /// Internal RC oscillator, about 32 kHz
+    Lsi,
+}
+
+impl RtcClock {
+    /// Name for reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lse => "LSE",
+            Self::Lsi => "LSI",
+        }
+    }
+
+    fn hz(self) -> u32 {
+        match self {
+            Self::Lse => LSE_HZ,
+            Self::Lsi => LSI_HZ,
+        }
+    }
+}
+
+/// RTC driver
+pub struct Rtc {
+    rtc: RTC,
+    state: RtcState,
+}
+
+impl Rtc {
+    /// Starts the LSE oscillator without waiting for it
+    ///
+    /// # Arguments
+    /// * `rtc` - RTC peripheral
+    pub fn start(rtc: RTC) -> Self {
+        // SAFETY: Backup domain access is only needed by this driver; BDCR is
+        // not touched by the HAL after clock configuration
+        let state = unsafe {
+            let rcc = &*pac::RCC::ptr();
+            let pwr = &*pac::PWR::ptr();
+
+            rcc.apb1enr().modify(|_, w| w.pwren().set_bit());
+            pwr.cr().modify(|_, w| w.dbp().set_bit());
+
+            if is_running() {
+                RtcState::Running
+            } else {
+                rcc.bdcr().modify(|r, w| w.bits(r.bits() | BDCR_LSEON));
+                RtcState::Starting
+            }
+        };
+
+        Self { rtc, state }
+    }
+
+    /// Advances start-up once the LSE is stable
+    ///
+    /// # Arguments
+    /// * `give_up` - Declare the LSE absent if it is still not ready
+    ///
+    /// # Returns
+    /// The current state
+    pub fn poll_startup(&mut self, give_up: bool) -> RtcState {
+        if self.state != RtcState::Starting {
+            return self.state;
+        }
+
+        // SAFETY: See `start`
+        let rcc = unsafe { &*pac::RCC::ptr() };
+        if rcc.bdcr().read().bits() & BDCR_LSERDY == 0 {
+            if give_up {
+                rcc.bdcr().modify(|r, w| unsafe { w.bits(r.bits() & !BDCR_LSEON) });
+                self.state = self.start_on_lsi();
+            }
+            return self.state;
+        }
+
+        // RTCSEL can only be written once after a backup domain reset
+        rcc.bdcr().modify(|r, w| unsafe {
+            w.bits((r.bits() & !BDCR_RTCSEL_MASK) | BDCR_RTCSEL_LSE | BDCR_RTCEN)
+        });
+
+        self.state = if self.program_prescalers(PREDIV_A, PREDIV_S) {
+            RtcState::Running
+        } else {
+            RtcState::Absent
+        };
+
+        #[cfg(feature = "debug")]
+        defmt::info!("RTC start-up finished: running = {}", self.state == RtcState::Running);
+
+        self.state
+    }
+
+    /// Current start-up state
+    pub fn state(&self) -> RtcState {
+        self.state
+    }
+
+    /// Reads a monotonic-within-a-day tick count
+    ///
+    /// # Returns
+    /// Sub-second ticks since midnight (`RTC_TICKS_PER_SECOND` per second),
+    /// or `None` unless the RTC runs from the LSE. Wraps once per day.
+    pub fn day_ticks(&self) -> Option<u32> {
+        if self.state != RtcState::Running || clock_source() != Some(RtcClock::Lse) {
+            return None;
+        }
+
+        // Reading SSR locks TR/DR shadows until DR is read
+        let ssr = self.rtc.ssr().read().bits() & 0xFFFF;
+        let tr = self.rtc.tr().read().bits();
+        let _ = self.rtc.dr().read().bits();
+
+        let seconds =
+            from_bcd(tr, 0, 0x7) + from_bcd(tr, 8, 0x7) * 60 + from_bcd(tr, 16, 0x3) * 3_600;
+
+        Some(seconds % SECONDS_PER_DAY * RTC_TICKS_PER_SECOND + (PREDIV_S - ssr.min(PREDIV_S)))
+    }
+
+    /// Ticks in one day, the wrap period of `day_ticks`
+    pub const fn ticks_per_day() -> u32 {
+        SECONDS_PER_DAY * RTC_TICKS_PER_SECOND
+    }
+
+    /// Runs the RTC from the LSI after the LSE failed to start
+    fn start_on_lsi(&mut self) -> RtcState {
+        // SAFETY: See `start`; LSION is also set by the IWDG and never cleared
+        let rcc = unsafe { &*pac::RCC::ptr() };
+        rcc.csr().modify(|r, w| unsafe { w.bits(r.bits() | CSR_LSION) });
+        if !wait_for(|| rcc.csr().read().bits() & CSR_LSIRDY != 0) {
+            #[cfg(feature = "debug")]
+            defmt::warn!("LSE and LSI not ready - RTC unavailable");
+            return RtcState::Absent;
+        }
+
+        rcc.bdcr().modify(|r, w| unsafe {
+            w.bits((r.bits() & !BDCR_RTCSEL_MASK) | BDCR_RTCSEL_LSI | BDCR_RTCEN)
+        });
+
+        #[cfg(feature = "debug")]
+        defmt::warn!("LSE not ready - RTC running from the LSI");
+
+        if self.program_prescalers(PREDIV_A_LSI, PREDIV_S_LSI) {
+            RtcState::Running
+        } else {
+            RtcState::Absent
+        }
+    }
+
+    /// Programs the prescalers in initialization mode
+    fn program_prescalers(&mut self, prediv_a: u32, prediv_s: u32) -> bool {
+        with_init_mode(&self.rtc, |rtc| {
+            rtc.prer()
+                .write(|w| unsafe { w.bits((prediv_a << 16) | prediv_s) });
+        })
+    }
+}
+
+/// Runs `write` with the RTC in initialization mode
+///
+/// # Returns
+/// `false` if init mode was not entered (`write` is skipped) or the shadow
+/// registers did not resynchronize
+fn with_init_mode(
+    rtc: &pac::rtc::RegisterBlock,
+    write: impl FnOnce(&pac::rtc::RegisterBlock),
+) -> bool {
+    // Unlock the write protection
+    rtc.wpr().write(|w| unsafe { w.bits(0xCA) });
+    rtc.wpr().write(|w| unsafe { w.bits(0x53) });
+
+    rtc.isr().modify(|r, w| unsafe { w.bits(r.bits() | ISR_INIT) });
+    let entered = wait_for(|| rtc.isr().read().bits() & ISR_INITF != 0);
+
+    if entered {
+        write(rtc);
+    }
+
+    // Leave init mode and clear RSF so the next read sees synchronized shadows
+    rtc.isr()
+        .modify(|r, w| unsafe { w.bits(r.bits() & !(ISR_INIT | ISR_RSF)) });
+    rtc.wpr().write(|w| unsafe { w.bits(0xFF) });
+
+    entered && wait_for(|| rtc.isr().read().bits() & ISR_RSF != 0)
+}
+
+/// Decodes a BCD field of `tens_bits` tens and 4 unit bits at `shift`
+fn from_bcd(value: u32, shift: u32, tens_bits: u32) -> u32 {
+    ((value >> (shift + 4)) & tens_bits) * 10 + ((value >> shift) & 0xF)
+}
+
+/// Encodes a value below 100 as two BCD digits
+fn to_bcd(value: u32) -> u32 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Reads the calendar
+///
+/// Usable without the `Rtc` driver, from any context.
+///
+/// # Returns
+/// `None` if the RTC is not running
+pub fn now() -> Option<DateTime> {
+    if !is_running() {
+        return None;
+    }
+
+    // SAFETY: Read-only access; reading TR locks DR until DR is read, and the
+    // critical section keeps other readers out in between
+    let (tr, dr) = cortex_m::interrupt::free(|_| unsafe {
+        let rtc = &*pac::RTC::ptr();
+        let tr = rtc.tr().read().bits();
+        (tr, rtc.dr().read().bits())
+    });
+
+    Some(DateTime {
+        year: EPOCH_YEAR + from_bcd(dr, 16, 0xF) as u16,
+        month: from_bcd(dr, 8, 0x1) as u8,
+        day: from_bcd(dr, 0, 0x3) as u8,
+        hour: from_bcd(tr, 16, 0x3) as u8,
+        minute: from_bcd(tr, 8, 0x7) as u8,
+        second: from_bcd(tr, 0, 0x7) as u8,
+    })
+}
+
+/// Sets the calendar
+///
+/// # Returns
+/// `false` if the RTC is not running or did not accept the new time
+pub fn set_time(time: &DateTime) -> bool {
+    if !is_running() {
+        return false;
+    }
+
+    let tr = (to_bcd(time.hour as u32) << 16)
+        | (to_bcd(time.minute as u32) << 8)
+        | to_bcd(time.second as u32);
+    let dr = (to_bcd((time.year - EPOCH_YEAR) as u32) << 16)
+        | ((time.weekday() as u32) << 13)
+        | (to_bcd(time.month as u32) << 8)
+        | to_bcd(time.day as u32);
+
+    // SAFETY: TR/DR are only written here, in init mode; see the module
+    // safety notes
+    let written = cortex_m::interrupt::free(|_| {
+        let rtc = unsafe { &*pac::RTC::ptr() };
+        with_init_mode(rtc, |rtc| {
+            rtc.tr().write(|w| unsafe { w.bits(tr) });
+            rtc.dr().write(|w| unsafe { w.bits(dr) });
+        })
+    });
+
+    #[cfg(feature = "debug")]
+    if written {
+        defmt::info!(
+            "RTC set to {}-{}-{} {}:{}:{}",
+            time.year,
+            time.month,
+            time.day,
+            time.hour,
+            time.minute,
+            time.second
+        );
+    }
+
+    written
+}
+
+/// Address of a backup register
+fn backup_register(index: usize) -> *mut u32 {
+    assert!(index < BACKUP_REGISTER_COUNT);
+    (pac::RTC::ptr() as usize + BKP_OFFSET + 4 * index) as *mut u32
+}
+
+/// Reads a backup register
+///
+/// Backup registers keep their value across system resets as long as VDD or
+/// VBAT is present; they read as 0 after a backup domain reset.
+///
+/// # Arguments
+/// * `index` - Register number, below `BACKUP_REGISTER_COUNT`
+pub fn read_backup_register(index: usize) -> u32 {
+    // SAFETY: Aligned MMIO read of a valid backup register; the RTC APB
+    // interface is clocked from reset
+    unsafe { core::ptr::read_volatile(backup_register(index)) }
+}
+
+/// Writes a backup register
+///
+/// Enables backup domain write access (PWR CR.DBP) first, so it works before
+/// the RTC driver is started, e.g. from a reset path.
+///
+/// # Arguments
+/// * `index` - Register number, below `BACKUP_REGISTER_COUNT`
+/// * `value` - Value to keep across resets
+pub fn write_backup_register(index: usize, value: u32) {
+    // SAFETY: Same PWR/DBP setup as `Rtc::start`, then an aligned MMIO write
+    // of a valid backup register
+    unsafe {
+        let rcc = &*pac::RCC::ptr();
+        let pwr = &*pac::PWR::ptr();
+        rcc.apb1enr().modify(|_, w| w.pwren().set_bit());
+        pwr.cr().modify(|_, w| w.dbp().set_bit());
+
+        core::ptr::write_volatile(backup_register(index), value);
+    }
+}
+
+/// Clock source of the running RTC
+///
+/// Usable without the `Rtc` driver, e.g. from `idle`.
+///
+/// # Returns
+/// `None` if the RTC is not running from a stable clock
+pub fn clock_source() -> Option<RtcClock> {
+    // SAFETY: Read-only access to BDCR and CSR
+    let (bdcr, csr) = unsafe {
+        let rcc = &*pac::RCC::ptr();
+        (rcc.bdcr().read().bits(), rcc.csr().read().bits())
+    };
+    if bdcr & BDCR_RTCEN == 0 {
+        return None;
+    }
+
+    match bdcr & BDCR_RTCSEL_MASK {
+        BDCR_RTCSEL_LSE if bdcr & BDCR_LSERDY != 0 => Some(RtcClock::Lse),
+        BDCR_RTCSEL_LSI if csr & CSR_LSIRDY != 0 => Some(RtcClock::Lsi),
+        _ => None,
+    }
+}
+
+/// Checks whether the RTC runs from a stable clock
+///
+/// Usable without the `Rtc` driver, e.g. from `idle`.
+pub fn is_running() -> bool {
+    clock_source().is_some()
+}
+
+/// Writes the calendar time and the clock source
+pub fn write_report<W: fmt::Write>(out: &mut W) -> fmt::Result {
+    match (now(), clock_source()) {
+        (Some(time), Some(clock)) => writeln!(out, "RTC: {} ({})", time, clock.name()),
+        _ => writeln!(out, "RTC: not running"),
+    }
+}
+
+/// Starts the wakeup timer for one period
+///
+/// The timer raises the RTC_WKUP interrupt through EXTI line 22 (rising
+/// edge), which also ends Stop mode.
+///
+/// # Arguments
+/// * `period_ms` - Time to the wakeup, at most 32 s
+///
+/// # Returns
+/// `false` if the RTC is not running or the timer did not become writable
+pub fn arm_wakeup(period_ms: u32) -> bool {
+    let Some(clock) = clock_source() else {
+        return false;
+    };
+
+    let ticks = (period_ms * (clock.hz() / WAKEUP_TIMER_DIV) / 1_000).clamp(1, 0x1_0000) - 1;
+
+    // SAFETY: Only the wakeup timer bits of the RTC and EXTI line 22 are
+    // written; see the module safety notes
+    unsafe {
+        let rtc = &*pac::RTC::ptr();
+        let exti = &*pac::EXTI::ptr();
+
+        rtc.wpr().write(|w| w.bits(0xCA));
+        rtc.wpr().write(|w| w.bits(0x53));
+
+        rtc.cr().modify(|r, w| w.bits(r.bits() & !(CR_WUTE | CR_WUTIE)));
+        let writable = wait_for(|| rtc.isr().read().bits() & ISR_WUTWF != 0);
+        if writable {
+            rtc.wutr().write(|w| w.bits(ticks));
+            rtc.cr().modify(|r, w| w.bits((r.bits() & !CR_WUCKSEL_MASK) | CR_WUTE | CR_WUTIE));
+        }
+        rtc.isr().modify(|r, w| w.bits(r.bits() & !ISR_WUTF));
+        rtc.wpr().write(|w| w.bits(0xFF));
+
+        exti.rtsr().modify(|r, w| w.bits(r.bits() | EXTI_WAKEUP_LINE));
+        exti.pr().write(|w| w.bits(EXTI_WAKEUP_LINE));
+        exti.imr().modify(|r, w| w.bits(r.bits() | EXTI_WAKEUP_LINE));
+
+        writable
+    }
+}
+
+/// Stops the wakeup timer and clears its pending flags
+///
+/// Called from the RTC_WKUP handler and after leaving Stop mode.
+pub fn disarm_wakeup() {
+    // SAFETY: See `arm_wakeup`
+    unsafe {
+        let rtc = &*pac::RTC::ptr();
+        let exti = &*pac::EXTI::ptr();
+
+        rtc.wpr().write(|w| w.bits(0xCA));
+        rtc.wpr().write(|w| w.bits(0x53));
+        rtc.cr().modify(|r, w| w.bits(r.bits() & !(CR_WUTE | CR_WUTIE)));
+        rtc.isr().modify(|r, w| w.bits(r.bits() & !ISR_WUTF));
+        rtc.wpr().write(|w| w.bits(0xFF));
+
+        exti.pr().write(|w| w.bits(EXTI_WAKEUP_LINE));
+    }
+}
+
+/// Busy-waits for a flag with a bounded number of polls
+fn wait_for(mut condition: impl FnMut() -> bool) -> bool {
+    (0..FLAG_POLL_LIMIT).any(|_| condition())
+}
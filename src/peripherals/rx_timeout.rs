@@ -0,0 +1,161 @@
+//! # USART RX Timeout Timer
+//!
+//! Programmable receiver timeout measured in bit times, built from a general
+//! purpose timer slaved to the RX line (the USART6 on this part has no RTOF):
+//! - Timer runs in slave reset mode; every RX edge restarts the count
+//! - Update (overflow) after `bit_times` of silence signals the timeout
+//! - Capture interrupt re-arms the timeout on the first edge of the next burst,
+//!   so an idle line costs no interrupts
+//!
+//! ## Hardware Configuration
+//! - TIM3 channel 1 on PA6 (AF2), which must be jumpered to USART6 RX (PG9)
+//! - One timer tick equals one bit time at the current baud rate
+//! - During automatic baud rate detection the timer is handed to
+//!   `baud_capture` (`into_capture`) and taken back afterwards
+
+use crate::peripherals::baud_capture::BaudCapture;
+use crate::peripherals::rcc::RccConfig;
+use stm32f4xx_hal::{
+    gpio::{gpioa::PA6, Alternate},
+    pac::TIM3,
+    rcc::{Enable, Reset},
+};
+
+/// Events reported by the timeout timer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RxTimeoutEvent {
+    /// First edge of a new burst, timeout armed
+    Activity,
+    /// Line idle for the configured number of bit times
+    Timeout,
+}
+
+/// RX line silence detector
+pub struct RxTimeout {
+    tim: TIM3,
+    _pin: PA6<Alternate<2>>,
+    timer_clock: u32,
+    bit_times: u16,
+}
+
+impl RxTimeout {
+    /// Configures TIM3 in slave reset mode on the RX edge detector
+    ///
+    /// # Arguments
+    /// * `tim` - TIM3 peripheral instance
+    /// * `pin` - PA6 in alternate function 2 (TIM3_CH1), jumpered to RX
+    /// * `clocks` - System clock configuration
+    pub fn new(tim: TIM3, pin: PA6<Alternate<2>>, clocks: &RccConfig) -> Self {
+        Self::setup(tim, pin, clocks.clocks.timclk1().raw())
+    }
+
+    /// Takes the timer back from a baud rate capture
+    ///
+    /// The timer is stopped; `configure` starts it again.
+    pub fn from_capture(capture: BaudCapture) -> Self {
+        let (tim, pin, timer_clock) = capture.release();
+        Self::setup(tim, pin, timer_clock)
+    }
+
+    /// Hands the timer to a baud rate capture, which starts right away
+    pub fn into_capture(self) -> BaudCapture {
+        BaudCapture::start(self.tim, self._pin, self.timer_clock)
+    }
+
+    fn setup(tim: TIM3, pin: PA6<Alternate<2>>, timer_clock: u32) -> Self {
+        // SAFETY: TIM3 is owned exclusively by this driver
+        unsafe {
+            TIM3::enable_unchecked();
+            TIM3::reset_unchecked();
+        }
+
+        // IC1 mapped on TI1 with a short digital filter
+        tim.ccmr1_input()
+            .write(|w| unsafe { w.cc1s().bits(0b01).ic1f().bits(0b0011) });
+        // Capture on both edges
+        tim.ccer()
+            .write(|w| w.cc1p().set_bit().cc1np().set_bit().cc1e().set_bit());
+        // Slave reset mode, trigger on TI1 edge detector
+        tim.smcr()
+            .write(|w| unsafe { w.sms().bits(0b100).ts().bits(0b100) });
+        // Only counter overflow raises the update flag (not resets or UG)
+        tim.cr1().write(|w| w.urs().set_bit());
+
+        Self {
+            tim,
+            _pin: pin,
+            timer_clock,
+            bit_times: 0,
+        }
+    }
+
+    /// Takes over a new APB1 timer clock (Hz)
+    ///
+    /// Applies with the next `configure`.
+    pub fn set_timer_clock(&mut self, timer_clock: u32) {
+        self.timer_clock = timer_clock;
+    }
+
+    /// Programs the timeout and starts the timer
+    ///
+    /// # Arguments
+    /// * `baud_rate` - Current USART baud rate, defines one tick
+    /// * `bit_times` - Silence duration that triggers a timeout
+    pub fn configure(&mut self, baud_rate: u32, bit_times: u16) {
+        let prescaler = (self.timer_clock / baud_rate).saturating_sub(1).min(u16::MAX as u32);
+
+        self.tim.cr1().modify(|_, w| w.cen().clear_bit());
+        self.tim.psc().write(|w| w.psc().bits(prescaler as u16));
+        self.tim.arr().write(|w| unsafe { w.bits(bit_times as u32) });
+        self.tim.egr().write(|w| w.ug().set_bit());
+        self.tim.sr().write(|w| unsafe { w.bits(0) });
+
+        // Wait for the first edge before counting
+        self.tim
+            .dier()
+            .write(|w| w.cc1ie().set_bit().uie().clear_bit());
+        self.tim.cr1().modify(|_, w| w.cen().set_bit());
+        self.bit_times = bit_times;
+
+        #[cfg(feature = "debug")]
+        defmt::info!("RX timeout set to {} bit times", bit_times);
+    }
+
+    /// Stops the timer and masks its interrupts
+    pub fn disable(&mut self) {
+        self.tim.cr1().modify(|_, w| w.cen().clear_bit());
+        self.tim.dier().write(|w| unsafe { w.bits(0) });
+        self.bit_times = 0;
+    }
+
+    /// Currently configured timeout in bit times (0 when disabled)
+    pub fn bit_times(&self) -> u16 {
+        self.bit_times
+    }
+
+    /// Handles the TIM3 interrupt and reports what happened
+    ///
+    /// Toggles between the two interrupt sources so each burst produces exactly
+    /// one `Activity` and one `Timeout` event.
+    pub fn on_interrupt(&mut self) -> Option<RxTimeoutEvent> {
+        let sr = self.tim.sr().read();
+
+        if sr.uif().bit_is_set() {
+            self.tim.sr().write(|w| unsafe { w.bits(0) });
+            self.tim
+                .dier()
+                .write(|w| w.cc1ie().set_bit().uie().clear_bit());
+            return Some(RxTimeoutEvent::Timeout);
+        }
+
+        if sr.cc1if().bit_is_set() {
+            self.tim.sr().write(|w| unsafe { w.bits(0) });
+            self.tim
+                .dier()
+                .write(|w| w.cc1ie().clear_bit().uie().set_bit());
+            return Some(RxTimeoutEvent::Activity);
+        }
+
+        None
+    }
+}
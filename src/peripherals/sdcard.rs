@@ -0,0 +1,141 @@
+//! # SD Card
+//!
+//! microSD card on the SDIO interface, holding the bridge log file:
+//! - Identification at 400 kHz, then a 4-bit bus at 24 MHz (HAL `Sdio`)
+//! - Card detect switch checked before every mount
+//! - The card as a `BlockDevice` of 512-byte blocks
+//! - `LOG_FILE_NAME` opened for appending on the first FAT16/FAT32 volume
+//!   through the in-crate `fat` layer
+//!
+//! ## Hardware Configuration
+//! - SDIO: CK PC12, CMD PD2, D0-D3 PC8-PC11 (AF12), pull-ups on CMD and data
+//! - Card detect: PG2, low while a card is inserted
+//!
+//! ## Safety Considerations
+//! - Block transfers are polled and take up to a few milliseconds per block:
+//!   only use the card from a low-priority task
+//! - A card pulled between two flushes loses the data written since the last
+//!   flush; the file system itself stays consistent
+
+mod fat;
+
+pub use fat::LogFile;
+
+use crate::errors::errors::SdCardError;
+use crate::peripherals::block_device::{Block, BlockDevice, BlockError, BLOCK_SIZE};
+use crate::peripherals::rtc::DateTime;
+use fat::Volume;
+use stm32f4xx_hal::{
+    gpio::{
+        gpioc::{PC10, PC11, PC12, PC8, PC9},
+        gpiod::PD2,
+        gpiog::PG2,
+        Alternate, Input,
+    },
+    pac::SDIO,
+    rcc::Clocks,
+    sdio::{self, ClockFreq, Sdio},
+};
+
+/// Log file in the root directory, 8.3 directory form of `BRIDGE.LOG`
+pub const LOG_FILE_NAME: &[u8; 11] = b"BRIDGE  LOG";
+
+/// SDIO pins: CK, CMD, D0, D1, D2, D3
+pub type SdioPins = (
+    PC12<Alternate<12>>,
+    PD2<Alternate<12>>,
+    PC8<Alternate<12>>,
+    PC9<Alternate<12>>,
+    PC10<Alternate<12>>,
+    PC11<Alternate<12>>,
+);
+
+/// microSD card slot
+pub struct SdCard {
+    sdio: Sdio<sdio::SdCard>,
+    detect: PG2<Input>,
+}
+
+impl SdCard {
+    /// Sets up the SDIO interface; the card is identified by `mount`
+    ///
+    /// # Arguments
+    /// * `sdio` - SDIO peripheral
+    /// * `pins` - Bus pins, with pull-ups on CMD and D0-D3
+    /// * `detect` - Card detect input (PG2), with pull-up
+    /// * `clocks` - Frozen clock configuration
+    pub fn new(sdio: SDIO, pins: SdioPins, detect: PG2<Input>, clocks: &Clocks) -> Self {
+        Self {
+            sdio: Sdio::new(sdio, pins, clocks),
+            detect,
+        }
+    }
+
+    /// Checks the card detect switch
+    pub fn is_inserted(&self) -> bool {
+        self.detect.is_low()
+    }
+
+    /// Identifies the card and opens the log file for appending
+    ///
+    /// # Arguments
+    /// * `now` - Creation time, if the log file has to be created
+    ///
+    /// # Errors
+    /// - `NoCard` if the slot is empty
+    /// - `Init` if the card does not complete identification
+    /// - `NoFilesystem` or `DirectoryFull` from the FAT layer
+    /// - `Io` if a block transfer fails
+    pub fn mount(&mut self, now: Option<DateTime>) -> Result<LogFile, SdCardError> {
+        if !self.is_inserted() {
+            return Err(SdCardError::NoCard);
+        }
+        self.sdio
+            .init(ClockFreq::F24Mhz)
+            .map_err(|_| SdCardError::Init)?;
+
+        let mut scratch = [0u8; BLOCK_SIZE];
+        let volume = Volume::mount(self, &mut scratch)?;
+        let file = volume.open_append(self, LOG_FILE_NAME, now)?;
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "SD card: {} blocks, log file at {} bytes",
+            self.block_count(),
+            file.size()
+        );
+
+        Ok(file)
+    }
+}
+
+impl BlockDevice for SdCard {
+    fn block_count(&self) -> u32 {
+        self.sdio
+            .card()
+            .map(|card| card.csd.block_count() as u32)
+            .unwrap_or(0)
+    }
+
+    fn is_ready(&self) -> bool {
+        self.is_inserted() && self.sdio.card().is_ok()
+    }
+
+    fn read_block(&mut self, lba: u32, block: &mut Block) -> Result<(), BlockError> {
+        if !self.is_ready() {
+            return Err(BlockError::NotReady);
+        }
+        self.sdio
+            .read_block(lba, block)
+            .map_err(|_| BlockError::Medium)
+    }
+
+    fn write_block(&mut self, lba: u32, block: &Block) -> Result<(), BlockError> {
+        if !self.is_ready() {
+            return Err(BlockError::NotReady);
+        }
+        self.sdio
+            .write_block(lba, block)
+            .map_err(|_| BlockError::Medium)
+    }
+}
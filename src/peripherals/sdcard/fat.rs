@@ -0,0 +1,549 @@
+//! FAT16/FAT32 append-only log file
+//!
+//! Just enough of the file system for the SD card log, in the manner of
+//! `embedded-sdmmc` but without its directory and file handle tables:
+//! - Mounts the first partition of an MBR, or a volume without partition table
+//! - Opens one file in the root directory, creating it when missing
+//! - Appends through a one-sector buffer, allocating clusters on demand
+//! - `flush` writes the partial sector and the directory entry, so the file
+//!   on a card pulled afterwards ends at the last flush
+//!
+//! FAT12 volumes (below 16 MB) and long file names are not supported. The
+//! FAT32 FSInfo free cluster hints are left alone; hosts treat them as advisory.
+
+use crate::errors::errors::SdCardError;
+use crate::peripherals::block_device::{Block, BlockDevice, BLOCK_SIZE};
+use crate::peripherals::rtc::DateTime;
+
+/// Boot sector and MBR signature at offset 510
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// Offset of the first MBR partition entry
+const PARTITION_ENTRY: usize = 0x1BE;
+
+/// MBR partition types of FAT16 and FAT32 volumes
+const FAT_PARTITION_TYPES: [u8; 5] = [0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+/// Bytes per directory entry
+const DIR_ENTRY_LEN: usize = 32;
+
+/// First name byte of a deleted entry, and of the entry ending the directory
+const ENTRY_DELETED: u8 = 0xE5;
+const ENTRY_END: u8 = 0x00;
+
+/// Directory entry attributes
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+
+/// Cluster counts from which a volume is FAT16, FAT32 (Microsoft FAT specification)
+const FAT16_MIN_CLUSTERS: u32 = 4_085;
+const FAT32_MIN_CLUSTERS: u32 = 65_525;
+
+/// FAT32 entries use the low 28 bits
+const FAT32_MASK: u32 = 0x0FFF_FFFF;
+
+/// Number of the first data cluster
+const FIRST_CLUSTER: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FatType {
+    Fat16,
+    Fat32,
+}
+
+/// Geometry of a mounted volume
+#[derive(Debug, Clone, Copy)]
+pub struct Volume {
+    fat_type: FatType,
+    /// First sector of the first FAT
+    fat_start: u32,
+    /// Sectors per FAT copy
+    fat_sectors: u32,
+    fat_count: u32,
+    sectors_per_cluster: u32,
+    /// FAT16: fixed root directory sectors
+    root_start: u32,
+    root_sectors: u32,
+    /// FAT32: first cluster of the root directory
+    root_cluster: u32,
+    /// Sector of cluster 2
+    data_start: u32,
+    /// One past the highest cluster number
+    cluster_end: u32,
+}
+
+impl Volume {
+    /// Reads the boot sector of the first FAT16/FAT32 volume
+    ///
+    /// # Arguments
+    /// * `device` - Card, ready for block transfers
+    /// * `scratch` - Sector buffer
+    ///
+    /// # Errors
+    /// `NoFilesystem` without a supported volume, `Io` if a read fails
+    pub fn mount<D: BlockDevice>(device: &mut D, scratch: &mut Block) -> Result<Self, SdCardError> {
+        read(device, 0, scratch)?;
+        if scratch[510..] != BOOT_SIGNATURE {
+            return Err(SdCardError::NoFilesystem);
+        }
+
+        let start = if is_boot_sector(scratch) {
+            0
+        } else {
+            let entry = &scratch[PARTITION_ENTRY..PARTITION_ENTRY + 16];
+            if !FAT_PARTITION_TYPES.contains(&entry[4]) {
+                return Err(SdCardError::NoFilesystem);
+            }
+            let start = le32(&entry[8..12]);
+            read(device, start, scratch)?;
+            if !is_boot_sector(scratch) {
+                return Err(SdCardError::NoFilesystem);
+            }
+            start
+        };
+
+        let sectors_per_cluster = u32::from(scratch[13]);
+        let reserved = u32::from(le16(&scratch[14..16]));
+        let fat_count = u32::from(scratch[16]);
+        let root_entries = u32::from(le16(&scratch[17..19]));
+        let total = match le16(&scratch[19..21]) {
+            0 => le32(&scratch[32..36]),
+            n => u32::from(n),
+        };
+        let fat_sectors = match le16(&scratch[22..24]) {
+            0 => le32(&scratch[36..40]),
+            n => u32::from(n),
+        };
+        if fat_count == 0 || fat_sectors == 0 {
+            return Err(SdCardError::NoFilesystem);
+        }
+
+        let fat_start = start + reserved;
+        let root_start = fat_start + fat_count * fat_sectors;
+        let root_sectors = (root_entries * DIR_ENTRY_LEN as u32).div_ceil(BLOCK_SIZE as u32);
+        let data_start = root_start + root_sectors;
+        let clusters = total.saturating_sub(data_start - start) / sectors_per_cluster;
+        let fat_type = if clusters >= FAT32_MIN_CLUSTERS {
+            FatType::Fat32
+        } else if clusters >= FAT16_MIN_CLUSTERS {
+            FatType::Fat16
+        } else {
+            return Err(SdCardError::NoFilesystem);
+        };
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "SD card: {} clusters of {} sectors at sector {}",
+            clusters,
+            sectors_per_cluster,
+            start
+        );
+
+        let root_cluster = match fat_type {
+            FatType::Fat16 => 0,
+            FatType::Fat32 => le32(&scratch[44..48]) & FAT32_MASK,
+        };
+        let cluster_end = clusters + FIRST_CLUSTER;
+        if fat_type == FatType::Fat32 && !(FIRST_CLUSTER..cluster_end).contains(&root_cluster) {
+            return Err(SdCardError::NoFilesystem);
+        }
+
+        Ok(Self {
+            fat_type,
+            fat_start,
+            fat_sectors,
+            fat_count,
+            sectors_per_cluster,
+            root_start,
+            root_sectors,
+            root_cluster,
+            data_start,
+            cluster_end,
+        })
+    }
+
+    /// Opens a root directory file for appending, creating it when missing
+    ///
+    /// # Arguments
+    /// * `device` - Card holding the volume
+    /// * `name` - 8.3 name in directory form, e.g. `b"BRIDGE  LOG"`
+    /// * `now` - Creation time of a new file
+    ///
+    /// # Errors
+    /// `DirectoryFull` if a new file finds no free entry, `Io` if a transfer fails
+    pub fn open_append<D: BlockDevice>(
+        self,
+        device: &mut D,
+        name: &[u8; 11],
+        now: Option<DateTime>,
+    ) -> Result<LogFile, SdCardError> {
+        let mut file = LogFile {
+            volume: self,
+            dir_sector: 0,
+            dir_offset: 0,
+            first_cluster: 0,
+            cluster: 0,
+            size: 0,
+            flushed_size: 0,
+            dirty: false,
+            buffer: [0; BLOCK_SIZE],
+            scratch: [0; BLOCK_SIZE],
+        };
+
+        let (sector, offset, found) = self.find_entry(device, &mut file.scratch, name)?;
+        file.dir_sector = sector;
+        file.dir_offset = offset;
+        read(device, sector, &mut file.scratch)?;
+        let entry = &mut file.scratch[offset..offset + DIR_ENTRY_LEN];
+
+        if !found {
+            entry.fill(0);
+            entry[0..11].copy_from_slice(name);
+            entry[11] = ATTR_ARCHIVE;
+            let (date, time) = now.map(|now| now.to_fat()).unwrap_or_default();
+            entry[14..16].copy_from_slice(&time.to_le_bytes());
+            entry[16..18].copy_from_slice(&date.to_le_bytes());
+            entry[18..20].copy_from_slice(&date.to_le_bytes());
+            entry[22..24].copy_from_slice(&time.to_le_bytes());
+            entry[24..26].copy_from_slice(&date.to_le_bytes());
+            write(device, sector, &file.scratch)?;
+            return Ok(file);
+        }
+
+        file.first_cluster =
+            (u32::from(le16(&entry[20..22])) << 16) | u32::from(le16(&entry[26..28]));
+        file.size = le32(&entry[28..32]);
+        file.flushed_size = file.size;
+        if file.size == 0 || !self.is_data_cluster(file.first_cluster) {
+            // Nothing to append to; the first write allocates afresh
+            file.first_cluster = 0;
+            file.size = 0;
+            return Ok(file);
+        }
+
+        // Walk to the cluster holding the last byte
+        file.cluster = file.first_cluster;
+        for walked in 1..file.size.div_ceil(self.cluster_bytes()) {
+            match self.next_cluster(device, &mut file.scratch, file.cluster)? {
+                Some(next) => file.cluster = next,
+                None => {
+                    // Chain shorter than the size: the file ends with the chain
+                    file.size = walked * self.cluster_bytes();
+                    break;
+                }
+            }
+        }
+        if file.size % BLOCK_SIZE as u32 != 0 {
+            let sector = file.end_sector();
+            read(device, sector, &mut file.buffer)?;
+        }
+        Ok(file)
+    }
+
+    /// Bytes per cluster
+    fn cluster_bytes(&self) -> u32 {
+        self.sectors_per_cluster * BLOCK_SIZE as u32
+    }
+
+    /// First sector of a data cluster
+    fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start + (cluster - FIRST_CLUSTER) * self.sectors_per_cluster
+    }
+
+    fn is_data_cluster(&self, cluster: u32) -> bool {
+        (FIRST_CLUSTER..self.cluster_end).contains(&cluster)
+    }
+
+    /// Sector of the first FAT and byte offset holding a cluster's entry
+    fn fat_location(&self, cluster: u32) -> (u32, usize) {
+        let entry_len = match self.fat_type {
+            FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        };
+        let offset = cluster * entry_len;
+        (
+            self.fat_start + offset / BLOCK_SIZE as u32,
+            (offset % BLOCK_SIZE as u32) as usize,
+        )
+    }
+
+    /// Reads a FAT entry out of a loaded FAT sector
+    fn entry(&self, sector: &Block, at: usize) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => u32::from(le16(&sector[at..at + 2])),
+            FatType::Fat32 => le32(&sector[at..at + 4]) & FAT32_MASK,
+        }
+    }
+
+    /// End-of-chain marker
+    fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => FAT32_MASK,
+        }
+    }
+
+    /// Follows the chain one step
+    ///
+    /// # Returns
+    /// `None` at the end of the chain
+    fn next_cluster<D: BlockDevice>(
+        &self,
+        device: &mut D,
+        scratch: &mut Block,
+        cluster: u32,
+    ) -> Result<Option<u32>, SdCardError> {
+        let (sector, at) = self.fat_location(cluster);
+        read(device, sector, scratch)?;
+        let next = self.entry(scratch, at);
+        Ok(self.is_data_cluster(next).then_some(next))
+    }
+
+    /// Sets a FAT entry in every FAT copy
+    fn set_entry<D: BlockDevice>(
+        &self,
+        device: &mut D,
+        scratch: &mut Block,
+        cluster: u32,
+        value: u32,
+    ) -> Result<(), SdCardError> {
+        let (sector, at) = self.fat_location(cluster);
+        read(device, sector, scratch)?;
+        match self.fat_type {
+            FatType::Fat16 => scratch[at..at + 2].copy_from_slice(&(value as u16).to_le_bytes()),
+            FatType::Fat32 => {
+                // The top four bits are reserved and kept
+                let value = (le32(&scratch[at..at + 4]) & !FAT32_MASK) | (value & FAT32_MASK);
+                scratch[at..at + 4].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+        for copy in 0..self.fat_count {
+            write(device, sector + copy * self.fat_sectors, scratch)?;
+        }
+        Ok(())
+    }
+
+    /// Finds a free cluster, searching upwards from `hint` and wrapping around
+    ///
+    /// # Errors
+    /// `DiskFull` if no cluster is free
+    fn find_free<D: BlockDevice>(
+        &self,
+        device: &mut D,
+        scratch: &mut Block,
+        hint: u32,
+    ) -> Result<u32, SdCardError> {
+        let hint = hint.clamp(FIRST_CLUSTER, self.cluster_end);
+        let mut loaded = None;
+        for cluster in (hint..self.cluster_end).chain(FIRST_CLUSTER..hint) {
+            let (sector, at) = self.fat_location(cluster);
+            if loaded != Some(sector) {
+                read(device, sector, scratch)?;
+                loaded = Some(sector);
+            }
+            if self.entry(scratch, at) == 0 {
+                return Ok(cluster);
+            }
+        }
+        Err(SdCardError::DiskFull)
+    }
+
+    /// Looks up a file in the root directory
+    ///
+    /// # Returns
+    /// Sector and byte offset of its entry and `true`, or of the first free
+    /// entry and `false` if the file does not exist
+    fn find_entry<D: BlockDevice>(
+        &self,
+        device: &mut D,
+        scratch: &mut Block,
+        name: &[u8; 11],
+    ) -> Result<(u32, usize, bool), SdCardError> {
+        let mut free = None;
+        let mut cluster = self.root_cluster;
+        let mut index = 0;
+
+        loop {
+            let sector = match self.fat_type {
+                FatType::Fat16 if index < self.root_sectors => self.root_start + index,
+                FatType::Fat16 => break,
+                FatType::Fat32 => {
+                    let in_cluster = index % self.sectors_per_cluster;
+                    if index > 0 && in_cluster == 0 {
+                        match self.next_cluster(device, scratch, cluster)? {
+                            Some(next) => cluster = next,
+                            None => break,
+                        }
+                    }
+                    self.cluster_sector(cluster) + in_cluster
+                }
+            };
+            read(device, sector, scratch)?;
+
+            for offset in (0..BLOCK_SIZE).step_by(DIR_ENTRY_LEN) {
+                let entry = &scratch[offset..offset + DIR_ENTRY_LEN];
+                match entry[0] {
+                    ENTRY_END => {
+                        let (sector, offset) = free.unwrap_or((sector, offset));
+                        return Ok((sector, offset, false));
+                    }
+                    ENTRY_DELETED => {
+                        free.get_or_insert((sector, offset));
+                    }
+                    // Long name entries carry the volume ID bit as well
+                    _ if entry[11] & (ATTR_VOLUME_ID | ATTR_DIRECTORY) != 0 => {}
+                    _ if entry[0..11] == name[..] => return Ok((sector, offset, true)),
+                    _ => {}
+                }
+            }
+            index += 1;
+        }
+
+        // The root directory is not grown
+        free.map(|(sector, offset)| (sector, offset, false))
+            .ok_or(SdCardError::DirectoryFull)
+    }
+}
+
+/// File opened for appending
+pub struct LogFile {
+    volume: Volume,
+    /// Location of the directory entry
+    dir_sector: u32,
+    dir_offset: usize,
+    /// 0 while the file is empty
+    first_cluster: u32,
+    /// Cluster holding the last byte
+    cluster: u32,
+    size: u32,
+    /// Size recorded in the directory entry
+    flushed_size: u32,
+    /// `buffer` holds bytes not yet written to the card
+    dirty: bool,
+    /// Last, partial sector of the file
+    buffer: Block,
+    /// Sector buffer for FAT and directory updates
+    scratch: Block,
+}
+
+impl LogFile {
+    /// File size in bytes, including buffered data
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Appends data; full sectors go to the card at once
+    ///
+    /// # Errors
+    /// `DiskFull` if no cluster is left or the file reached 4 GB, `Io` if a
+    /// transfer fails
+    pub fn write<D: BlockDevice>(
+        &mut self,
+        device: &mut D,
+        mut data: &[u8],
+    ) -> Result<(), SdCardError> {
+        while !data.is_empty() {
+            let at = self.size as usize % BLOCK_SIZE;
+            if at == 0 {
+                if self.size % self.volume.cluster_bytes() == 0 {
+                    self.extend(device)?;
+                }
+                self.buffer.fill(0);
+            }
+
+            let count = data.len().min(BLOCK_SIZE - at);
+            self.size = self
+                .size
+                .checked_add(count as u32)
+                .ok_or(SdCardError::DiskFull)?;
+            self.buffer[at..at + count].copy_from_slice(&data[..count]);
+            self.dirty = true;
+            data = &data[count..];
+
+            if self.size as usize % BLOCK_SIZE == 0 {
+                write(device, self.end_sector(), &self.buffer)?;
+                self.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered sector and updates the directory entry
+    ///
+    /// # Arguments
+    /// * `device` - Card holding the file
+    /// * `now` - Modification time; the entry keeps its time for `None`
+    pub fn flush<D: BlockDevice>(
+        &mut self,
+        device: &mut D,
+        now: Option<DateTime>,
+    ) -> Result<(), SdCardError> {
+        if self.dirty {
+            write(device, self.end_sector(), &self.buffer)?;
+            self.dirty = false;
+        }
+        if self.size == self.flushed_size {
+            return Ok(());
+        }
+
+        read(device, self.dir_sector, &mut self.scratch)?;
+        let entry = &mut self.scratch[self.dir_offset..self.dir_offset + DIR_ENTRY_LEN];
+        entry[20..22].copy_from_slice(&((self.first_cluster >> 16) as u16).to_le_bytes());
+        entry[26..28].copy_from_slice(&(self.first_cluster as u16).to_le_bytes());
+        entry[28..32].copy_from_slice(&self.size.to_le_bytes());
+        if let Some(now) = now {
+            let (date, time) = now.to_fat();
+            entry[18..20].copy_from_slice(&date.to_le_bytes());
+            entry[22..24].copy_from_slice(&time.to_le_bytes());
+            entry[24..26].copy_from_slice(&date.to_le_bytes());
+        }
+        write(device, self.dir_sector, &self.scratch)?;
+        self.flushed_size = self.size;
+        Ok(())
+    }
+
+    /// Allocates the next cluster and links it to the chain
+    fn extend<D: BlockDevice>(&mut self, device: &mut D) -> Result<(), SdCardError> {
+        let volume = self.volume;
+        let cluster = volume.find_free(device, &mut self.scratch, self.cluster + 1)?;
+        volume.set_entry(device, &mut self.scratch, cluster, volume.end_of_chain())?;
+        if self.first_cluster == 0 {
+            self.first_cluster = cluster;
+        } else {
+            volume.set_entry(device, &mut self.scratch, self.cluster, cluster)?;
+        }
+        self.cluster = cluster;
+        Ok(())
+    }
+
+    /// Sector holding the last byte of the file
+    fn end_sector(&self) -> u32 {
+        let sector_in_cluster =
+            (self.size - 1) / BLOCK_SIZE as u32 % self.volume.sectors_per_cluster;
+        self.volume.cluster_sector(self.cluster) + sector_in_cluster
+    }
+}
+
+/// Checks for a FAT boot sector: a jump instruction and 512-byte sectors
+fn is_boot_sector(sector: &Block) -> bool {
+    matches!(sector[0], 0xEB | 0xE9)
+        && usize::from(le16(&sector[11..13])) == BLOCK_SIZE
+        && sector[13].is_power_of_two()
+}
+
+fn read<D: BlockDevice>(device: &mut D, lba: u32, block: &mut Block) -> Result<(), SdCardError> {
+    device.read_block(lba, block).map_err(|_| SdCardError::Io)
+}
+
+fn write<D: BlockDevice>(device: &mut D, lba: u32, block: &Block) -> Result<(), SdCardError> {
+    device.write_block(lba, block).map_err(|_| SdCardError::Io)
+}
+
+fn le16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
@@ -0,0 +1,448 @@
+//! # External SDRAM
+//!
+//! Bring-up of the Discovery board's 16 MB SDRAM (Micron MT48LC4M32B2,
+//! 4 banks x 4096 rows x 256 columns x 32 bits) on FMC SDRAM bank 1:
+//! - FMC pins, controller timing and the JEDEC power-up sequence
+//! - Quick memory test at start-up: data bus walking ones and an address
+//!   bus test on every power-of-two offset
+//! - Mapped at `SDRAM_BASE`; the `.sdram` linker section (see `memory.x`)
+//!   holds large buffers, handed out by `sdram_singleton!` once the memory
+//!   passed its test
+//!
+//! ## Hardware Configuration
+//! - All FMC signals on AF12: D0-D31, A0-A11, BA0/BA1, NBL0-3, SDCLK, SDCKE0,
+//!   SDNE0, SDNWE, SDNRAS, SDNCAS (see `PINS`)
+//! - SDCLK = HCLK / 2 (90 MHz), CAS latency 3, read burst enabled
+//! - Refresh every 64 ms / 4096 rows, counted in SDCLK cycles; the count
+//!   follows HCLK changes of `clock_scaling` (`set_refresh_clock`)
+//!
+//! ## Safety Considerations
+//! - `init` configures the pins through the GPIO registers; it must run
+//!   after every HAL port split, which resets the port
+//! - The pins must be listed as claimed in `pin_parking`
+//! - The `.sdram` section is not loaded or zeroed by the runtime; nothing in
+//!   it may be touched before `init` succeeded
+
+use crate::config::SYSCLK;
+use crate::errors::errors::SdramError;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::pac;
+
+/// Address of SDRAM bank 1
+pub const SDRAM_BASE: usize = 0xC000_0000;
+
+/// SDRAM size in bytes (16 MB)
+pub const SDRAM_SIZE: usize = 16 * 1024 * 1024;
+
+/// FMC signals: (port, pin), all on alternate function 12
+pub const PINS: &[(char, u8)] = &[
+    ('C', 0),  // SDNWE
+    ('D', 0),  // D2
+    ('D', 1),  // D3
+    ('D', 8),  // D13
+    ('D', 9),  // D14
+    ('D', 10), // D15
+    ('D', 14), // D0
+    ('D', 15), // D1
+    ('E', 0),  // NBL0
+    ('E', 1),  // NBL1
+    ('E', 7),  // D4
+    ('E', 8),  // D5
+    ('E', 9),  // D6
+    ('E', 10), // D7
+    ('E', 11), // D8
+    ('E', 12), // D9
+    ('E', 13), // D10
+    ('E', 14), // D11
+    ('E', 15), // D12
+    ('F', 0),  // A0
+    ('F', 1),  // A1
+    ('F', 2),  // A2
+    ('F', 3),  // A3
+    ('F', 4),  // A4
+    ('F', 5),  // A5
+    ('F', 11), // SDNRAS
+    ('F', 12), // A6
+    ('F', 13), // A7
+    ('F', 14), // A8
+    ('F', 15), // A9
+    ('G', 0),  // A10
+    ('G', 1),  // A11
+    ('G', 4),  // BA0
+    ('G', 5),  // BA1
+    ('G', 8),  // SDCLK
+    ('G', 15), // SDNCAS
+    ('H', 2),  // SDCKE0
+    ('H', 3),  // SDNE0
+    ('H', 8),  // D16
+    ('H', 9),  // D17
+    ('H', 10), // D18
+    ('H', 11), // D19
+    ('H', 12), // D20
+    ('H', 13), // D21
+    ('H', 14), // D22
+    ('H', 15), // D23
+    ('I', 0),  // D24
+    ('I', 1),  // D25
+    ('I', 2),  // D26
+    ('I', 3),  // D27
+    ('I', 4),  // NBL2
+    ('I', 5),  // NBL3
+    ('I', 6),  // D28
+    ('I', 7),  // D29
+    ('I', 9),  // D30
+    ('I', 10), // D31
+];
+
+/// FMC alternate function number
+const FMC_AF: u32 = 12;
+
+/// GPIOA base address and port distance (RM0386, memory map)
+const GPIO_BASE: usize = 0x4002_0000;
+const GPIO_STRIDE: usize = 0x400;
+
+/// GPIO register offsets
+const MODER_OFFSET: usize = 0x00;
+const OSPEEDR_OFFSET: usize = 0x08;
+const PUPDR_OFFSET: usize = 0x0C;
+const AFRL_OFFSET: usize = 0x20;
+const AFRH_OFFSET: usize = 0x24;
+
+/// FMC SDRAM register offsets from the FMC base
+const SDCR1_OFFSET: usize = 0x140;
+const SDTR1_OFFSET: usize = 0x148;
+const SDCMR_OFFSET: usize = 0x150;
+const SDRTR_OFFSET: usize = 0x154;
+const SDSR_OFFSET: usize = 0x158;
+
+/// SDCR1: 8 column bits, 12 row bits, 32-bit bus, 4 internal banks, CAS
+/// latency 3, SDCLK = HCLK / 2, read burst
+const SDCR1_VALUE: u32 =
+    (0b01 << 2) | (0b10 << 4) | (1 << 6) | (0b11 << 7) | (0b10 << 10) | (1 << 12);
+
+/// SDTR1 in SDCLK cycles minus one: TMRD 2, TXSR 7, TRAS 4, TRC 7, TWR 2,
+/// TRP 2, TRCD 2
+const SDTR1_VALUE: u32 = 1 | (6 << 4) | (3 << 8) | (6 << 12) | (1 << 16) | (1 << 20) | (1 << 24);
+
+/// SDCMR command modes, sent to bank 1 (CTB1)
+const CMD_CLOCK_ENABLE: u32 = 1;
+const CMD_PRECHARGE_ALL: u32 = 2;
+const CMD_AUTO_REFRESH: u32 = 3;
+const CMD_LOAD_MODE: u32 = 4;
+const SDCMR_CTB1: u32 = 1 << 4;
+const SDCMR_NRFS_SHIFT: u32 = 5;
+const SDCMR_MRD_SHIFT: u32 = 9;
+
+/// Auto-refresh cycles issued at power-up
+const POWER_UP_REFRESHES: u32 = 8;
+
+/// Mode register: burst length 1, sequential, CAS latency 3, single write burst
+const MODE_REGISTER: u32 = 0x0230;
+
+/// SDSR: controller busy, refresh error
+const SDSR_BUSY: u32 = 1 << 5;
+const SDSR_RE: u32 = 1 << 0;
+
+/// SDRAM clock
+const SDCLK: u32 = SYSCLK / 2;
+
+/// SDRTR refresh count for an SDRAM clock: one row every 64 ms / 4096 rows,
+/// with the recommended 20-cycle margin
+const fn refresh_count(sdclk: u32) -> u32 {
+    (sdclk / 1_000) * 64 / 4096 - 20
+}
+
+/// Clock-enable to first command delay required by the part
+const POWER_UP_DELAY_US: u32 = 100;
+
+/// Polling limit for the controller busy flag
+const BUSY_POLL_LIMIT: u32 = 100_000;
+
+static READY: AtomicBool = AtomicBool::new(false);
+static FAILED_TESTS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" {
+    static mut __ssdram: u32;
+    static mut __esdram: u32;
+}
+
+/// Brings up the SDRAM and tests it
+///
+/// # Arguments
+/// * `_fmc` - FMC peripheral, owned by the SDRAM from here on
+///
+/// # Errors
+/// Returns:
+/// - `SdramError::Timeout` if the controller stays busy
+/// - `SdramError::DataBus` or `SdramError::AddressBus` if the memory test fails
+pub fn init(_fmc: pac::FMC) -> Result<(), SdramError> {
+    configure_pins();
+
+    // SAFETY: Only the FMC enable bit is set; no HAL driver owns AHB3ENR
+    unsafe {
+        let rcc = &*pac::RCC::ptr();
+        rcc.ahb3enr().modify(|_, w| w.fmcen().set_bit());
+    }
+    cortex_m::asm::dsb();
+
+    // SAFETY: FMC registers of the owned peripheral, bank 1 SDRAM only
+    unsafe {
+        reg(SDCR1_OFFSET).write_volatile(SDCR1_VALUE);
+        reg(SDTR1_OFFSET).write_volatile(SDTR1_VALUE);
+
+        command(CMD_CLOCK_ENABLE)?;
+        cortex_m::asm::delay(SYSCLK / 1_000_000 * POWER_UP_DELAY_US);
+        command(CMD_PRECHARGE_ALL)?;
+        command(CMD_AUTO_REFRESH | ((POWER_UP_REFRESHES - 1) << SDCMR_NRFS_SHIFT))?;
+        command(CMD_LOAD_MODE | (MODE_REGISTER << SDCMR_MRD_SHIFT))?;
+
+        reg(SDRTR_OFFSET).write_volatile(refresh_count(SDCLK) << 1);
+    }
+
+    if let Err(e) = test_memory() {
+        FAILED_TESTS.fetch_add(1, Ordering::Relaxed);
+        return Err(e);
+    }
+    READY.store(true, Ordering::Release);
+
+    #[cfg(feature = "debug")]
+    defmt::info!(
+        "SDRAM ready: {} KB, {} bytes placed",
+        SDRAM_SIZE / 1024,
+        placed_len()
+    );
+
+    Ok(())
+}
+
+/// Address of an FMC register
+fn reg(offset: usize) -> *mut u32 {
+    (pac::FMC::ptr() as usize + offset) as *mut u32
+}
+
+/// Sends a command to bank 1 once the controller is idle
+///
+/// # Safety
+/// The FMC must be owned by the caller
+unsafe fn command(mode: u32) -> Result<(), SdramError> {
+    wait_idle()?;
+    reg(SDCMR_OFFSET).write_volatile(mode | SDCMR_CTB1);
+    wait_idle()
+}
+
+/// Waits for the controller busy flag to clear
+///
+/// # Safety
+/// The FMC must be owned by the caller
+unsafe fn wait_idle() -> Result<(), SdramError> {
+    if (0..BUSY_POLL_LIMIT).any(|_| reg(SDSR_OFFSET).read_volatile() & SDSR_BUSY == 0) {
+        Ok(())
+    } else {
+        Err(SdramError::Timeout)
+    }
+}
+
+/// Puts every pin in `PINS` on the FMC: alternate function 12, very high
+/// speed, no pulls
+fn configure_pins() {
+    // SAFETY: Runs once during init after all port splits; only FMC pins and
+    // the GPIO clock enable bits are modified
+    unsafe {
+        let rcc = &*pac::RCC::ptr();
+        let ports = PINS
+            .iter()
+            .fold(0u32, |mask, &(port, _)| mask | (1 << (port as u8 - b'A')));
+        rcc.ahb1enr().modify(|r, w| w.bits(r.bits() | ports));
+        // Clock enable needs two peripheral cycles before the first access
+        cortex_m::asm::dsb();
+
+        for &(port, pin) in PINS {
+            let gpio = GPIO_BASE + (port as u8 - b'A') as usize * GPIO_STRIDE;
+            let pin = pin as u32;
+            let modify = |offset: usize, width: u32, shift: u32, value: u32| {
+                let register = (gpio + offset) as *mut u32;
+                let mask = ((1 << width) - 1) << shift;
+                register.write_volatile((register.read_volatile() & !mask) | (value << shift));
+            };
+
+            let (afr, afr_shift) = if pin < 8 {
+                (AFRL_OFFSET, pin * 4)
+            } else {
+                (AFRH_OFFSET, (pin - 8) * 4)
+            };
+            modify(afr, 4, afr_shift, FMC_AF);
+            modify(OSPEEDR_OFFSET, 2, pin * 2, 0b11);
+            modify(PUPDR_OFFSET, 2, pin * 2, 0b00);
+            modify(MODER_OFFSET, 2, pin * 2, 0b10);
+        }
+    }
+}
+
+/// Quick test of the data and address lines
+///
+/// Runs before anything lives in the SDRAM, so its contents may be
+/// overwritten freely.
+fn test_memory() -> Result<(), SdramError> {
+    let words = SDRAM_BASE as *mut u32;
+
+    // SAFETY: All accesses are aligned and inside the initialized SDRAM
+    unsafe {
+        // Data bus: walking ones on the first word
+        for bit in 0..32 {
+            let pattern = 1u32 << bit;
+            words.write_volatile(pattern);
+            if words.read_volatile() != pattern {
+                return Err(SdramError::DataBus);
+            }
+        }
+
+        // Address bus: each power-of-two word offset must hit its own cell
+        const PATTERN: u32 = 0xAAAA_AAAA;
+        const ANTIPATTERN: u32 = 0x5555_5555;
+        let offsets = || {
+            core::iter::successors(Some(1usize), |&o| Some(o << 1))
+                .take_while(|&o| o < SDRAM_SIZE / 4)
+        };
+
+        words.write_volatile(PATTERN);
+        for offset in offsets() {
+            words.add(offset).write_volatile(PATTERN);
+        }
+        words.write_volatile(ANTIPATTERN);
+        if offsets().any(|offset| words.add(offset).read_volatile() != PATTERN) {
+            return Err(SdramError::AddressBus);
+        }
+        words.write_volatile(PATTERN);
+
+        for offset in offsets() {
+            words.add(offset).write_volatile(ANTIPATTERN);
+            let aliased = words.read_volatile() != PATTERN
+                || offsets()
+                    .filter(|&other| other != offset)
+                    .any(|other| words.add(other).read_volatile() == ANTIPATTERN);
+            if aliased {
+                return Err(SdramError::AddressBus);
+            }
+            words.add(offset).write_volatile(PATTERN);
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether the SDRAM passed its test and may be used
+pub fn is_ready() -> bool {
+    READY.load(Ordering::Acquire)
+}
+
+/// Adapts the refresh counter to a new SDRAM clock
+///
+/// Called by `clock_scaling` around HCLK changes: before slowing the clock
+/// down and after speeding it up, so rows are never refreshed too rarely.
+///
+/// # Arguments
+/// * `sdclk` - SDRAM clock (HCLK / 2) in Hz
+pub fn set_refresh_clock(sdclk: u32) {
+    if !is_ready() {
+        return;
+    }
+    // SAFETY: SDRTR only holds the refresh count and the error interrupt
+    // enable, which stays off; the controller takes a new count at any time
+    unsafe { reg(SDRTR_OFFSET).write_volatile(refresh_count(sdclk) << 1) };
+}
+
+/// Bytes of the `.sdram` section taken by placed buffers
+pub fn placed_len() -> usize {
+    // SAFETY: Only the addresses of the linker symbols are used
+    unsafe { core::ptr::addr_of!(__esdram) as usize - core::ptr::addr_of!(__ssdram) as usize }
+}
+
+/// Element types of SDRAM buffers: plain integers, valid when zeroed
+pub trait Word: Copy + sealed::Sealed {}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+macro_rules! impl_word {
+    ($($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for $ty {}
+            impl Word for $ty {}
+        )*
+    };
+}
+
+impl_word!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// Zeroes a buffer in the `.sdram` section and hands it out
+///
+/// Used by `sdram_singleton!`.
+///
+/// # Safety
+/// - `slot` must be a `.sdram` static, handed out at most once
+/// - The SDRAM must be ready (`is_ready`)
+#[doc(hidden)]
+pub unsafe fn take_zeroed<T: Word, const N: usize>(
+    slot: *mut core::mem::MaybeUninit<[T; N]>,
+) -> &'static mut [T; N] {
+    slot.write_bytes(0, 1);
+    (*slot).assume_init_mut()
+}
+
+/// Places a zeroed array in the external SDRAM, once
+///
+/// Like `cortex_m::singleton!`, but the buffer lives in the `.sdram` linker
+/// section and only integer arrays are supported.
+///
+/// # Returns
+/// `Some(&'static mut [T; N])` on the first call after the SDRAM passed its
+/// test, `None` otherwise; callers fall back to a smaller buffer in SRAM
+///
+/// # Example
+/// ```rust
+/// let log: Option<&'static mut [u8; 1 << 20]> = sdram_singleton!(: [u8; 1 << 20]);
+/// ```
+#[macro_export]
+macro_rules! sdram_singleton {
+    (: [$elem:ty; $len:expr]) => {{
+        #[link_section = ".sdram"]
+        static mut BUFFER: core::mem::MaybeUninit<[$elem; $len]> = core::mem::MaybeUninit::uninit();
+        static TAKEN: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+        if $crate::peripherals::sdram::is_ready()
+            && !TAKEN.swap(true, core::sync::atomic::Ordering::AcqRel)
+        {
+            // SAFETY: The SDRAM is ready and `TAKEN` guards the only reference
+            Some(unsafe {
+                let slot = core::ptr::addr_of_mut!(BUFFER);
+                $crate::peripherals::sdram::take_zeroed::<$elem, { $len }>(slot)
+            })
+        } else {
+            None
+        }
+    }};
+}
+
+/// Writes the SDRAM state and section usage
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    if !is_ready() {
+        let reason = if FAILED_TESTS.load(Ordering::Relaxed) > 0 {
+            "memory test failed"
+        } else {
+            "not initialized"
+        };
+        return writeln!(out, "SDRAM: {}", reason);
+    }
+
+    // SAFETY: Read-only access to the status register
+    let refresh_error = unsafe { reg(SDSR_OFFSET).read_volatile() & SDSR_RE != 0 };
+    writeln!(
+        out,
+        "SDRAM: {} KB at {:#010x}, {} bytes placed{}",
+        SDRAM_SIZE / 1024,
+        SDRAM_BASE,
+        placed_len(),
+        if refresh_error { ", refresh error" } else { "" }
+    )
+}
@@ -0,0 +1,532 @@
+//! # SPI Master
+//!
+//! SPI2 master for bridging USB to SPI devices next to the UART bridge:
+//! - Software chip selects, each with its own mode and clock; the bus is
+//!   reconfigured when the selected device needs different settings
+//! - Blocking full-duplex transfers and DMA writes (DMA1 stream 4)
+//! - Every flag and DMA wait has a time limit scaled to the transfer length
+//! - `loopback_test` checks the bus with MOSI jumpered to MISO
+//!
+//! ## Hardware Configuration
+//! - SCK PD3, MISO PB14, MOSI PB15 (AF5), the Arduino D13/D12/D11 pins
+//! - Chip selects: CS0 PH6 (Arduino D10), CS1 PG10, active low
+//! - Clock `PCLK1` divided by a power of two, 22.5 MHz at most
+//!
+//! ## Safety Considerations
+//! - The SPI2 RX DMA request only exists on DMA1 stream 3, which carries
+//!   USART3 TX, so DMA transfers are write-only: received bytes are dropped
+//! - Transfers busy-wait: use the bus from low-priority tasks only
+
+use crate::config::{PCLK1, SPI_DEFAULT_FREQUENCY_HZ, SPI_TIMEOUT_US, SYSCLK};
+use crate::errors::errors::SpiError;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{compiler_fence, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use stm32f4xx_hal::{
+    dma::Stream4,
+    gpio::{
+        gpiob::{PB14, PB15},
+        gpiod::PD3,
+        Alternate, ErasedPin, Output,
+    },
+    pac::{DMA1, SPI2},
+    rcc::{Enable, Reset},
+};
+
+/// Number of chip select lines
+pub const CHIP_SELECTS: usize = 2;
+
+/// CR1 bits
+const CR1_CPHA: u32 = 1 << 0;
+const CR1_CPOL: u32 = 1 << 1;
+const CR1_MSTR: u32 = 1 << 2;
+const CR1_BR_SHIFT: u32 = 3;
+const CR1_SPE: u32 = 1 << 6;
+const CR1_SSI: u32 = 1 << 8;
+const CR1_SSM: u32 = 1 << 9;
+
+/// CR2 TX DMA request enable
+const CR2_TXDMAEN: u32 = 1 << 1;
+
+/// SR bits
+const SR_RXNE: u32 = 1 << 0;
+const SR_TXE: u32 = 1 << 1;
+const SR_MODF: u32 = 1 << 5;
+const SR_OVR: u32 = 1 << 6;
+const SR_BSY: u32 = 1 << 7;
+
+/// DMA stream of the SPI2 TX request (channel 0)
+const DMA_STREAM: usize = 4;
+
+/// DMA SxCR bits: enable, memory increment, memory-to-peripheral, high priority
+const DMA_CR_EN: u32 = 1 << 0;
+const DMA_CR_DIR_M2P: u32 = 0b01 << 6;
+const DMA_CR_MINC: u32 = 1 << 10;
+const DMA_CR_PL_HIGH: u32 = 0b10 << 16;
+
+/// Stream 4 flags in HISR and HIFCR: FIFO, direct mode, transfer error,
+/// half and full transfer
+const DMA_FEIF4: u32 = 1 << 0;
+const DMA_DMEIF4: u32 = 1 << 2;
+const DMA_TEIF4: u32 = 1 << 3;
+const DMA_HTIF4: u32 = 1 << 4;
+const DMA_TCIF4: u32 = 1 << 5;
+const DMA_FLAGS4: u32 = DMA_FEIF4 | DMA_DMEIF4 | DMA_TEIF4 | DMA_HTIF4 | DMA_TCIF4;
+
+/// Largest DMA transfer (NDTR is 16 bits)
+const DMA_MAX_LEN: usize = 0xFFFF;
+
+/// Busy-wait step of timeouts (microseconds)
+const WAIT_STEP_US: u32 = 1;
+
+/// Bytes sent by `loopback_test`
+const LOOPBACK_LEN: usize = 64;
+
+/// Transfer counters for the report
+static TRANSFERS: AtomicU32 = AtomicU32::new(0);
+static BYTES: AtomicU32 = AtomicU32::new(0);
+static DMA_TRANSFERS: AtomicU32 = AtomicU32::new(0);
+static ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// Result of the most recent loopback test
+static LAST_TEST: Mutex<RefCell<Option<Result<(), SpiError>>>> = Mutex::new(RefCell::new(None));
+
+/// SPI2 pins: SCK, MISO, MOSI
+pub type Spi2Pins = (PD3<Alternate<5>>, PB14<Alternate<5>>, PB15<Alternate<5>>);
+
+/// Chip select output, high while idle
+pub type ChipSelectPin = ErasedPin<Output>;
+
+/// Clock polarity and phase
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpiMode {
+    /// Idle low, sample on the rising edge
+    Mode0,
+    /// Idle low, sample on the falling edge
+    Mode1,
+    /// Idle high, sample on the falling edge
+    Mode2,
+    /// Idle high, sample on the rising edge
+    Mode3,
+}
+
+impl SpiMode {
+    /// CPOL and CPHA bits of CR1
+    fn cr1_bits(self) -> u32 {
+        match self {
+            SpiMode::Mode0 => 0,
+            SpiMode::Mode1 => CR1_CPHA,
+            SpiMode::Mode2 => CR1_CPOL,
+            SpiMode::Mode3 => CR1_CPOL | CR1_CPHA,
+        }
+    }
+}
+
+/// Bus settings of one device
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpiConfig {
+    /// Clock polarity and phase
+    pub mode: SpiMode,
+    /// Highest clock the device accepts (Hz)
+    pub frequency_hz: u32,
+}
+
+impl Default for SpiConfig {
+    fn default() -> Self {
+        Self {
+            mode: SpiMode::Mode0,
+            frequency_hz: SPI_DEFAULT_FREQUENCY_HZ,
+        }
+    }
+}
+
+impl SpiConfig {
+    /// BR field: the smallest divider `2 << BR` that stays at or below the
+    /// requested clock; 256 for very low requests
+    fn baud_divider_bits(&self) -> u32 {
+        let mut br = 0;
+        while br < 7 && PCLK1 / (2 << br) > self.frequency_hz {
+            br += 1;
+        }
+        br
+    }
+
+    /// Clock actually generated (Hz)
+    pub fn actual_hz(&self) -> u32 {
+        PCLK1 / (2 << self.baud_divider_bits())
+    }
+
+    /// CR1 value with the peripheral enabled
+    fn cr1_bits(&self) -> u32 {
+        CR1_MSTR
+            | CR1_SSM
+            | CR1_SSI
+            | CR1_SPE
+            | self.mode.cr1_bits()
+            | (self.baud_divider_bits() << CR1_BR_SHIFT)
+    }
+
+    /// Time the bus needs for `bytes` at this clock, plus `SPI_TIMEOUT_US`
+    fn timeout_us(&self, bytes: usize) -> u32 {
+        let bits = (bytes as u32).saturating_mul(8);
+        bits / (self.actual_hz() / 1_000_000).max(1) + SPI_TIMEOUT_US
+    }
+}
+
+/// SPI2 master with software chip selects
+pub struct Spi2Master {
+    spi: SPI2,
+    _pins: Spi2Pins,
+    chip_selects: [ChipSelectPin; CHIP_SELECTS],
+    _dma: Stream4<DMA1>,
+    configs: [SpiConfig; CHIP_SELECTS],
+    /// Settings currently in CR1
+    active: Option<SpiConfig>,
+}
+
+impl Spi2Master {
+    /// Enables SPI2; every chip select starts with `SpiConfig::default()`
+    ///
+    /// # Arguments
+    /// * `spi` - SPI2 peripheral
+    /// * `pins` - SCK, MISO and MOSI in AF5
+    /// * `chip_selects` - Chip select outputs, already high
+    /// * `dma` - DMA1 stream 4, reserved for TX transfers
+    pub fn new(
+        spi: SPI2,
+        pins: Spi2Pins,
+        chip_selects: [ChipSelectPin; CHIP_SELECTS],
+        dma: Stream4<DMA1>,
+    ) -> Self {
+        // SAFETY: SPI2 is owned by this driver, nothing else uses its
+        // enable and reset bits
+        unsafe {
+            SPI2::enable_unchecked();
+            SPI2::reset_unchecked();
+        }
+
+        let mut master = Self {
+            spi,
+            _pins: pins,
+            chip_selects,
+            _dma: dma,
+            configs: [SpiConfig::default(); CHIP_SELECTS],
+            active: None,
+        };
+        for cs in master.chip_selects.iter_mut() {
+            cs.set_high();
+        }
+        master.apply(SpiConfig::default());
+        master
+    }
+
+    /// Sets the bus settings used with a chip select
+    ///
+    /// # Errors
+    /// `InvalidChipSelect` if `cs` is out of range
+    pub fn set_config(&mut self, cs: usize, config: SpiConfig) -> Result<(), SpiError> {
+        let slot = self
+            .configs
+            .get_mut(cs)
+            .ok_or(SpiError::InvalidChipSelect)?;
+        *slot = config;
+        Ok(())
+    }
+
+    /// Bus settings of a chip select
+    pub fn config(&self, cs: usize) -> Option<SpiConfig> {
+        self.configs.get(cs).copied()
+    }
+
+    /// Exchanges bytes with a device; `data` is sent and replaced by the
+    /// bytes received
+    ///
+    /// # Errors
+    /// `InvalidChipSelect`, or `Timeout`, `ModeFault` or `Overrun` on a bus
+    /// failure
+    pub fn transfer(&mut self, cs: usize, data: &mut [u8]) -> Result<(), SpiError> {
+        self.with_device(cs, data.len(), |bus, timeout_us| {
+            for byte in data.iter_mut() {
+                *byte = bus.exchange(*byte, timeout_us)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Sends bytes to a device, dropping the bytes received
+    ///
+    /// # Errors
+    /// As for `transfer`
+    pub fn write(&mut self, cs: usize, data: &[u8]) -> Result<(), SpiError> {
+        self.with_device(cs, data.len(), |bus, timeout_us| {
+            for &byte in data {
+                bus.exchange(byte, timeout_us)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Sends bytes to a device through DMA, dropping the bytes received
+    ///
+    /// Returns once the last byte left the shift register; the CPU only
+    /// polls the stream flags meanwhile.
+    ///
+    /// # Errors
+    /// `Dma` on a DMA transfer error, otherwise as for `transfer`
+    pub fn write_dma(&mut self, cs: usize, data: &[u8]) -> Result<(), SpiError> {
+        let result = self.with_device(cs, data.len(), |bus, timeout_us| {
+            data.chunks(DMA_MAX_LEN)
+                .try_for_each(|chunk| bus.dma_chunk(chunk, timeout_us))
+        });
+        if result.is_ok() {
+            DMA_TRANSFERS.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Checks the bus with MOSI jumpered to MISO and no device selected
+    ///
+    /// Sends a counting pattern with blocking transfers and compares the
+    /// echo, then sends it again through DMA. The result is kept for
+    /// `write_report`.
+    ///
+    /// # Errors
+    /// `LoopbackMismatch` if the echo differs (no jumper), otherwise as for
+    /// `write_dma`
+    pub fn loopback_test(&mut self) -> Result<(), SpiError> {
+        let result = self.run_loopback();
+        interrupt::free(|cs| *LAST_TEST.borrow(cs).borrow_mut() = Some(result));
+        result
+    }
+
+    fn run_loopback(&mut self) -> Result<(), SpiError> {
+        let mut pattern = [0u8; LOOPBACK_LEN];
+        for (i, byte) in pattern.iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(37) ^ 0xA5;
+        }
+        let config = SpiConfig::default();
+        let timeout_us = config.timeout_us(LOOPBACK_LEN);
+        self.apply(config);
+
+        let mut result =
+            pattern
+                .iter()
+                .try_for_each(|&byte| match self.exchange(byte, timeout_us) {
+                    Ok(echo) if echo == byte => Ok(()),
+                    Ok(_) => Err(SpiError::LoopbackMismatch),
+                    Err(e) => Err(e),
+                });
+        if result.is_ok() {
+            result = self.dma_chunk(&pattern, timeout_us);
+        }
+        if result.is_err() {
+            self.recover();
+        }
+        result
+    }
+
+    /// Selects a device, runs a transfer on it and deselects it
+    fn with_device(
+        &mut self,
+        cs: usize,
+        len: usize,
+        transfer: impl FnOnce(&mut Self, u32) -> Result<(), SpiError>,
+    ) -> Result<(), SpiError> {
+        let config = self.config(cs).ok_or(SpiError::InvalidChipSelect)?;
+        if self.active != Some(config) {
+            self.apply(config);
+        }
+        let timeout_us = config.timeout_us(len);
+
+        self.chip_selects[cs].set_low();
+        let result = transfer(self, timeout_us).and_then(|_| self.wait_idle(timeout_us));
+        self.chip_selects[cs].set_high();
+
+        match result {
+            Ok(()) => {
+                TRANSFERS.fetch_add(1, Ordering::Relaxed);
+                BYTES.fetch_add(len as u32, Ordering::Relaxed);
+            }
+            Err(_e) => {
+                self.recover();
+
+                #[cfg(feature = "debug")]
+                defmt::warn!("SPI2 CS{}: {}", cs, _e.description());
+            }
+        }
+        result
+    }
+
+    /// Writes CR1 for a device, with the peripheral briefly disabled
+    fn apply(&mut self, config: SpiConfig) {
+        // SAFETY: SPE is cleared before the mode and divider change, as
+        // RM0386 requires; the bus is idle between transfers
+        unsafe {
+            self.spi.cr1().write(|w| w.bits(0));
+            self.spi.cr2().write(|w| w.bits(0));
+            self.spi.cr1().write(|w| w.bits(config.cr1_bits()));
+        }
+        self.active = Some(config);
+    }
+
+    /// Sends one byte and returns the byte received with it
+    fn exchange(&mut self, byte: u8, timeout_us: u32) -> Result<u8, SpiError> {
+        self.wait_status(SR_TXE, timeout_us)?;
+        // SAFETY: DR takes the next byte once TXE is set
+        unsafe { self.spi.dr().write(|w| w.bits(u32::from(byte))) };
+        self.wait_status(SR_RXNE, timeout_us)?;
+        Ok(self.spi.dr().read().bits() as u8)
+    }
+
+    /// Sends up to `DMA_MAX_LEN` bytes through DMA1 stream 4
+    fn dma_chunk(&mut self, chunk: &[u8], timeout_us: u32) -> Result<(), SpiError> {
+        // SAFETY: Stream 4 is reserved by `_dma`. `chunk` outlives the
+        // transfer: the stream is disabled before this function returns.
+        let result = unsafe {
+            let dma = &*DMA1::ptr();
+            let stream = dma.st(DMA_STREAM);
+
+            stream.cr().write(|w| w.bits(0));
+            while stream.cr().read().bits() & DMA_CR_EN != 0 {}
+            dma.hifcr().write(|w| w.bits(DMA_FLAGS4));
+
+            stream
+                .par()
+                .write(|w| w.bits(self.spi.dr().as_ptr() as u32));
+            stream.m0ar().write(|w| w.bits(chunk.as_ptr() as u32));
+            stream.ndtr().write(|w| w.bits(chunk.len() as u32));
+            // Channel 0, byte transfers, direct mode
+            stream
+                .cr()
+                .write(|w| w.bits(DMA_CR_DIR_M2P | DMA_CR_MINC | DMA_CR_PL_HIGH));
+            compiler_fence(Ordering::SeqCst);
+            stream.cr().modify(|r, w| w.bits(r.bits() | DMA_CR_EN));
+            self.spi.cr2().modify(|r, w| w.bits(r.bits() | CR2_TXDMAEN));
+
+            let mut waited_us = 0;
+            let result = loop {
+                let flags = dma.hisr().read().bits();
+                if flags & DMA_TEIF4 != 0 {
+                    break Err(SpiError::Dma);
+                }
+                if flags & DMA_TCIF4 != 0 {
+                    break Ok(());
+                }
+                if waited_us >= timeout_us {
+                    break Err(SpiError::Timeout);
+                }
+                busy_wait_us(WAIT_STEP_US);
+                waited_us += WAIT_STEP_US;
+            };
+
+            stream.cr().write(|w| w.bits(0));
+            dma.hifcr().write(|w| w.bits(DMA_FLAGS4));
+            result
+        };
+        compiler_fence(Ordering::SeqCst);
+
+        let result = result.and_then(|_| self.wait_idle(timeout_us));
+        // SAFETY: Clears the TX DMA request only
+        unsafe {
+            self.spi
+                .cr2()
+                .modify(|r, w| w.bits(r.bits() & !CR2_TXDMAEN));
+        }
+        // The unread bytes overran the receiver: DR then SR clears OVR
+        let _ = self.spi.dr().read();
+        let _ = self.spi.sr().read();
+        result
+    }
+
+    /// Waits for a status flag, failing on a mode fault or overrun
+    fn wait_status(&self, flag: u32, timeout_us: u32) -> Result<(), SpiError> {
+        let mut waited_us = 0;
+        loop {
+            let sr = self.spi.sr().read().bits();
+            if sr & SR_MODF != 0 {
+                return Err(SpiError::ModeFault);
+            }
+            if sr & SR_OVR != 0 {
+                return Err(SpiError::Overrun);
+            }
+            if sr & flag != 0 {
+                return Ok(());
+            }
+            if waited_us >= timeout_us {
+                return Err(SpiError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        }
+    }
+
+    /// Waits until the last byte left the shift register
+    fn wait_idle(&self, timeout_us: u32) -> Result<(), SpiError> {
+        self.wait_status(SR_TXE, timeout_us)?;
+        let mut waited_us = 0;
+        while self.spi.sr().read().bits() & SR_BSY != 0 {
+            if waited_us >= timeout_us {
+                return Err(SpiError::Timeout);
+            }
+            busy_wait_us(WAIT_STEP_US);
+            waited_us += WAIT_STEP_US;
+        }
+        Ok(())
+    }
+
+    /// Clears OVR and MODF and writes CR1 again
+    fn recover(&mut self) {
+        ERRORS.fetch_add(1, Ordering::Relaxed);
+        // OVR: read DR then SR; MODF: read SR then write CR1 (in `apply`)
+        let _ = self.spi.dr().read();
+        let _ = self.spi.sr().read();
+        let config = self.active.unwrap_or_default();
+        self.apply(config);
+    }
+}
+
+/// Stops a DMA write without the driver
+///
+/// For the shutdown (`task_handlers::shutdown::quiesce`); the bytes not yet
+/// sent are dropped. Stop entry needs no such step: `write_dma` stops the
+/// stream before it returns.
+pub fn force_stop() {
+    // SAFETY: Stream 4 is reserved for SPI2 TX; only its enable and flags
+    // and the TX DMA request are cleared, and the caller keeps the driver
+    // from running
+    unsafe {
+        let dma = &*DMA1::ptr();
+        dma.st(DMA_STREAM).cr().write(|w| w.bits(0));
+        dma.hifcr().write(|w| w.bits(DMA_FLAGS4));
+        (*SPI2::ptr())
+            .cr2()
+            .modify(|r, w| w.bits(r.bits() & !CR2_TXDMAEN));
+    }
+}
+
+fn busy_wait_us(us: u32) {
+    cortex_m::asm::delay(SYSCLK / 1_000_000 * us);
+}
+
+/// Result of the most recent loopback test
+pub fn last_test() -> Option<Result<(), SpiError>> {
+    interrupt::free(|cs| *LAST_TEST.borrow(cs).borrow())
+}
+
+/// Writes the transfer counters and the last loopback result
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "SPI2: {} transfers ({} DMA), {} bytes, {} errors",
+        TRANSFERS.load(Ordering::Relaxed),
+        DMA_TRANSFERS.load(Ordering::Relaxed),
+        BYTES.load(Ordering::Relaxed),
+        ERRORS.load(Ordering::Relaxed)
+    )?;
+    match last_test() {
+        Some(Ok(())) => writeln!(out, "  loopback test passed"),
+        Some(Err(e)) => writeln!(out, "  loopback test failed: {}", e.description()),
+        None => writeln!(out, "  no loopback test yet, run `spi test`"),
+    }
+}
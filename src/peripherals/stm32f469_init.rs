@@ -3,37 +3,162 @@
 //! This module handles the initialization of critical peripherals for the STM32F469 microcontroller.
 //! It configures:
 //! - Clock tree through RCC
-//! - GPIO pins for LEDs and communication interfaces
-//! - USART6 for serial communication
+//! - GPIO pins for the four user LEDs, the user button and communication interfaces
+//! - PWM buzzer for audible error codes
+//! - USART6 and USART3 for serial communication (one bridged at a time)
 //! - USB OTG FS for USB device functionality
+//! - Modem status inputs mirrored to the USB host
+//! - Internal flash access for persistent data, and the saved settings
+//! - QSPI NOR flash (optional: boot continues without it)
+//! - External SDRAM on the FMC, memory-tested (optional as well)
+//! - LTDC/DSI display with its framebuffer in the SDRAM (`display` feature)
+//! - I2C1 master bus (`i2c` feature)
+//! - FT6206 touch controller on I2C1 (`touch` feature)
+//! - CAN1 controller on the I2C1 pins (`can` feature)
+//! - SPI2 master with two chip selects (`spi` feature)
+//! - ADC1 on the Arduino analog inputs and internal sensors (`adc` feature)
+//! - Both DAC channels with their trigger timers (`dac` feature)
+//! - microSD card slot on the SDIO interface (`sd-log` feature)
+//! - CRC unit for packet and image checksums
+//! - Random number generator for protocol nonces and retry jitter
+//! - RTC on the LSE crystal (start-up completes in the background)
+//! - Unused GPIOs parked in analog mode
+//! - Read-back verification of critical register configuration
 //! - Interrupt configuration for peripherals
+//! - Independent watchdog, started last
 //!
 //! ## Safety Considerations
 //! - Must be called only once during system startup
 //! - Direct hardware access requires proper sequencing
 //! - Interrupt masks should match actual peripheral usage
 
-use crate::config::{HSE, PCLK1, PCLK2, SYSCLK};
-use crate::errors::errors::InitError;
-use crate::peripherals::blue_led::BlueLed;
-use crate::peripherals::otg_fs::OtgFsController;
+use crate::config::{
+    BUZZER_FREQUENCY_HZ, DMA_BUFFER_LEN, HSE, PCLK1, PCLK2, RX_TIMEOUT_BIT_TIMES, SYSCLK,
+    USART3_BAUD_RATE, USART6_BAUD_RATE,
+};
+#[cfg(feature = "display")]
+use crate::errors::errors::DisplayError;
+#[cfg(feature = "touch")]
+use crate::errors::errors::TouchError;
+use crate::errors::errors::{InitError, SdramError};
+#[cfg(feature = "adc")]
+use crate::peripherals::adc::Adc1;
+use crate::peripherals::button::UserButton;
+use crate::peripherals::buzzer::Buzzer;
+#[cfg(feature = "can")]
+use crate::peripherals::can::Can1;
+use crate::peripherals::crc;
+#[cfg(feature = "dac")]
+use crate::peripherals::dac::Dac;
+#[cfg(feature = "display")]
+use crate::peripherals::display::Display;
+use crate::peripherals::flash::FlashController;
+#[cfg(feature = "i2c")]
+use crate::peripherals::i2c::{I2c1Bus, I2cMaster, I2cSpeed};
+use crate::peripherals::iwdg::Iwdg;
+use crate::peripherals::led::{BlueLed, GreenLed, OrangeLed};
+#[cfg(feature = "led-pwm")]
+use crate::peripherals::led_pwm::LedPwm;
+use crate::peripherals::mco;
+use crate::peripherals::modem_lines::{ControlOutputs, ModemLines};
+use crate::peripherals::otg_fs::{OtgFsController, UsbIdentity};
+use crate::peripherals::pin_parking::park_unused_pins;
+use crate::peripherals::pvd;
+use crate::peripherals::qspi::QspiFlash;
 use crate::peripherals::rcc::RccConfig;
 use crate::peripherals::red_led::RedLed;
-use crate::peripherals::usart_6::Usart6Controller;
+use crate::peripherals::rng;
+#[cfg(feature = "rs485")]
+use crate::peripherals::rs485::{DriverEnable, Rs485Config};
+use crate::peripherals::rtc::Rtc;
+use crate::peripherals::rx_timeout::RxTimeout;
+#[cfg(feature = "sd-log")]
+use crate::peripherals::sdcard::SdCard;
+use crate::peripherals::sdram;
+#[cfg(feature = "spi")]
+use crate::peripherals::spi::Spi2Master;
+#[cfg(feature = "touch")]
+use crate::peripherals::touch::Touch;
+use crate::peripherals::uart::{Usart3Controller, Usart6Controller, DMA_RX_LEN};
+use crate::peripherals::vbus::VbusSense;
+use crate::peripherals::wwdg::Wwdg;
+use crate::peripherals::verify::verify_configuration;
+use crate::task_handlers::settings::{self, SerialNumber, UsbDescriptors};
+use crate::utils::meminfo;
 use cortex_m::singleton;
+use stm32f4xx_hal::dma::StreamsTuple;
+#[cfg(feature = "spi")]
+use stm32f4xx_hal::gpio::PinState;
+use stm32f4xx_hal::gpio::Speed;
 use stm32f4xx_hal::pac::Interrupt;
 use stm32f4xx_hal::{pac, prelude::*};
 
 /// Container for initialized hardware peripherals
 pub struct InitializedPeripherals {
-    /// Blue LED controller (PK3)
-    pub blue_led: BlueLed,
+    /// Green LED controller (PG6)
+    pub green_led: GreenLed,
+    /// Orange LED controller (PD4)
+    pub orange_led: OrangeLed,
     /// Red LED controller (PD5)
     pub red_led: RedLed,
+    /// PWM buzzer (PA7, TIM14_CH1)
+    pub buzzer: Buzzer,
+    /// Blue LED controller (PK3)
+    pub blue_led: BlueLed,
+    /// Blue/red LED brightness control (TIM4)
+    #[cfg(feature = "led-pwm")]
+    pub led_pwm: LedPwm,
+    /// User button (PA0, EXTI line 0)
+    pub button: UserButton,
     /// USART6 controller with DMA capabilities
     pub usart_6: Usart6Controller,
+    /// USART3 controller with DMA capabilities (second bridge UART)
+    pub usart_3: Usart3Controller,
     /// USB OTG FS device controller
     pub otg_fs: OtgFsController<'static>,
+    /// Internal flash controller for data sectors
+    pub flash: FlashController,
+    /// N25Q128A QSPI flash, `None` if it did not answer
+    pub qspi: Option<QspiFlash>,
+    /// SDRAM bring-up and memory test result; `sdram_singleton!` buffers
+    /// are only available after a success
+    pub sdram: Result<(), SdramError>,
+    /// Status display, needs the SDRAM for its framebuffer
+    #[cfg(feature = "display")]
+    pub display: Result<Display, DisplayError>,
+    /// I2C1 master, shared by the touch controller and the console scan
+    #[cfg(feature = "i2c")]
+    pub i2c_1: I2c1Bus,
+    /// Touch controller of the display
+    #[cfg(feature = "touch")]
+    pub touch: Result<Touch, TouchError>,
+    /// CAN1 controller, off the bus until the SLCAN host opens the channel
+    #[cfg(feature = "can")]
+    pub can_1: Can1,
+    /// SPI2 master on the Arduino header
+    #[cfg(feature = "spi")]
+    pub spi_2: Spi2Master,
+    /// ADC1, scanning every channel until the sampler selects others
+    #[cfg(feature = "adc")]
+    pub adc_1: Adc1,
+    /// DAC channels 1 and 2, both outputs off
+    #[cfg(feature = "dac")]
+    pub dac: Dac,
+    /// microSD card slot; the card is mounted by the log writer
+    #[cfg(feature = "sd-log")]
+    pub sd_card: SdCard,
+    /// DSR/DCD/RI/CTS inputs reported to the USB host
+    pub modem_lines: ModemLines,
+    /// DTR/RTS outputs set by the USB host
+    pub control_outputs: ControlOutputs,
+    /// USB bus voltage input (PA9)
+    pub vbus: VbusSense,
+    /// Real-time clock on the LSE
+    pub rtc: Rtc,
+    /// Independent watchdog, already running
+    pub iwdg: Iwdg,
+    /// Window watchdog, configured; started by its refresh task
+    pub wwdg: Wwdg,
 }
 
 /// Initializes all critical system peripherals
@@ -44,8 +169,9 @@ pub struct InitializedPeripherals {
 /// # Errors
 /// Returns `InitError` if:
 /// - Clock configuration fails
-/// - USART6 initialization fails
+/// - USART6 or USART3 initialization fails
 /// - USB initialization fails
+/// - A configuration register does not read back as expected
 ///
 /// # Safety
 /// - Must maintain exclusive access to hardware resources
@@ -55,14 +181,58 @@ pub fn init_peripherals(device: pac::Peripherals) -> Result<InitializedPeriphera
     let pac::Peripherals {
         RCC,
         GPIOA,
+        GPIOB,
+        #[cfg(any(feature = "sd-log", feature = "adc"))]
+        GPIOC,
         GPIOD,
+        GPIOF,
         GPIOK,
         GPIOG,
+        #[cfg(any(feature = "display", feature = "spi"))]
+        GPIOH,
+        #[cfg(feature = "touch")]
+        GPIOJ,
         USART6,
+        USART3,
+        DMA1,
         DMA2,
         OTG_FS_DEVICE,
         OTG_FS_GLOBAL,
         OTG_FS_PWRCLK,
+        FLASH,
+        QUADSPI,
+        FMC,
+        #[cfg(feature = "display")]
+        LTDC,
+        #[cfg(feature = "display")]
+        DMA2D,
+        #[cfg(feature = "display")]
+        DSI,
+        #[cfg(feature = "display")]
+        TIM7,
+        #[cfg(feature = "i2c")]
+        I2C1,
+        #[cfg(feature = "can")]
+        CAN1,
+        #[cfg(feature = "spi")]
+        SPI2,
+        #[cfg(feature = "adc")]
+        ADC1,
+        #[cfg(feature = "dac")]
+        DAC,
+        #[cfg(feature = "dac")]
+        TIM5,
+        #[cfg(feature = "dac")]
+        TIM6,
+        #[cfg(feature = "sd-log")]
+        SDIO,
+        TIM3,
+        TIM14,
+        #[cfg(feature = "led-pwm")]
+        TIM4,
+        RTC,
+        IWDG,
+        WWDG,
         ..
     } = device;
 
@@ -72,6 +242,20 @@ pub fn init_peripherals(device: pac::Peripherals) -> Result<InitializedPeriphera
     )
     .ok_or(InitError::RccError)?;
 
+    // ===================== CRC Unit =====================
+    crc::enable();
+
+    // ===================== Random Number Generator =====================
+    // Runs from PLL48CLK, configured with the clocks above. If the first read
+    // fails, nonces and retry jitter use their fallbacks until it recovers
+    rng::enable().ok();
+
+    // ===================== Flash Storage =====================
+    // Settings records are CRC-checked, so this follows the CRC unit
+    let flash = FlashController::new(FLASH);
+    let settings = settings::load(&flash);
+    settings.apply();
+
     // ===================== LED Initialization =====================
     // Blue LED (PK3) - System status indicator
     let gpiok = GPIOK.split();
@@ -81,29 +265,280 @@ pub fn init_peripherals(device: pac::Peripherals) -> Result<InitializedPeriphera
     let gpiod = GPIOD.split();
     let red_led = RedLed::init_off(gpiod.pd5.into_push_pull_output());
 
-    // ===================== USART6 Configuration =====================
+    // Orange LED (PD4) - UART activity
+    let orange_led = OrangeLed::init_off(gpiod.pd4.into_push_pull_output());
+
+    // Green LED (PG6) - USB activity
     let gpiog = GPIOG.split();
-    let usart6 = Usart6Controller::init(
+    let green_led = GreenLed::init_off(gpiog.pg6.into_push_pull_output());
+
+    // ===================== USART6 Configuration =====================
+    let dma2 = StreamsTuple::new(DMA2);
+    let usart6_buffers = (
+        singleton!(: [u8; DMA_BUFFER_LEN] = [0; DMA_BUFFER_LEN]).ok_or(InitError::UsartError)?,
+        singleton!(: [u8; DMA_RX_LEN] = [0; DMA_RX_LEN]).ok_or(InitError::UsartError)?,
+    );
+    meminfo::register("usart6 dma tx", DMA_BUFFER_LEN);
+    meminfo::register("usart6 dma rx", DMA_RX_LEN);
+    let mut usart6 = Usart6Controller::init(
         USART6,
-        DMA2,
-        gpiog.pg14.into_alternate::<8>(), // TX pin
-        gpiog.pg9.into_alternate::<8>(),  // RX pin
+        (dma2.6, dma2.1),
+        (
+            gpiog.pg14.into_alternate::<8>(), // TX pin
+            gpiog.pg9.into_alternate::<8>(),  // RX pin
+        ),
+        usart6_buffers,
+        USART6_BAUD_RATE,
         rcc_config,
     )
     .map_err(|_| InitError::UsartError)?;
+    // A saved rate the UART cannot produce leaves the default in place
+    if settings.usart6_baud != USART6_BAUD_RATE {
+        usart6.set_baud_rate(settings.usart6_baud).ok();
+    }
+    // RS-485 transceiver driver enable on PG10 (Arduino D8), off until TX
+    #[cfg(feature = "rs485")]
+    usart6.attach_rs485(DriverEnable::new(
+        gpiog.pg10.into_push_pull_output().erase(),
+        Rs485Config::DEFAULT,
+    ));
 
-    // ===================== USB OTG FS Configuration =====================
+    // ===================== USART3 Configuration =====================
+    let dma1 = StreamsTuple::new(DMA1);
+    let usart3_buffers = (
+        singleton!(: [u8; DMA_BUFFER_LEN] = [0; DMA_BUFFER_LEN]).ok_or(InitError::UsartError)?,
+        singleton!(: [u8; DMA_RX_LEN] = [0; DMA_RX_LEN]).ok_or(InitError::UsartError)?,
+    );
+    meminfo::register("usart3 dma tx", DMA_BUFFER_LEN);
+    meminfo::register("usart3 dma rx", DMA_RX_LEN);
+    let gpiob = GPIOB.split();
+    let mut usart3 = Usart3Controller::init(
+        USART3,
+        (dma1.3, dma1.1),
+        (
+            gpiob.pb10.into_alternate::<7>(), // TX pin
+            gpiob.pb11.into_alternate::<7>(), // RX pin
+        ),
+        usart3_buffers,
+        USART3_BAUD_RATE,
+        rcc_config,
+    )
+    .map_err(|_| InitError::UsartError)?;
+    if settings.usart3_baud != USART3_BAUD_RATE {
+        usart3.set_baud_rate(settings.usart3_baud).ok();
+    }
+    // No strap window on USART3: circular reception starts right away
+    usart3.start_dma_rx().map_err(|_| InitError::UsartError)?;
+
+    // ===================== Modem Lines =====================
     let gpioa = GPIOA.split();
+    let modem_lines = ModemLines::new(
+        gpiog.pg13.into_pull_up_input(), // DSR
+        gpiog.pg12.into_pull_up_input(), // DCD
+        gpiog.pg11.into_pull_up_input(), // RI
+        gpioa.pa3.into_pull_up_input(),  // CTS
+    );
+    // Deasserted per the polarity loaded with the settings
+    let control_outputs = ControlOutputs::new(
+        gpioa.pa1.into_push_pull_output(), // DTR
+        gpioa.pa2.into_push_pull_output(), // RTS
+    );
+
+    // ===================== USB OTG FS Configuration =====================
+    // The string descriptors must outlive the USB device
+    let serial_number: &'static SerialNumber =
+        singleton!(: SerialNumber = settings.serial_number).ok_or(InitError::UsbError)?;
+    let descriptors: &'static UsbDescriptors =
+        singleton!(: UsbDescriptors = settings.usb).ok_or(InitError::UsbError)?;
     let otg_fs = OtgFsController::new(
         OTG_FS_GLOBAL,
         OTG_FS_DEVICE,
         OTG_FS_PWRCLK,
         gpioa.pa11.into_alternate::<10>(), // DM pin
         gpioa.pa12.into_alternate::<10>(), // DP pin
+        UsbIdentity {
+            vid: descriptors.vid,
+            pid: descriptors.pid,
+            manufacturer: descriptors.manufacturer.as_str(),
+            product: descriptors.product.as_str(),
+            serial_number: serial_number.as_str(),
+        },
         rcc_config,
     )
     .map_err(|_| InitError::UsbError)?;
 
+    // ===================== VBUS Sensing =====================
+    let vbus = VbusSense::new(gpioa.pa9.into_pull_down_input());
+
+    // ===================== User Button =====================
+    let button = UserButton::new(gpioa.pa0.into_floating_input());
+
+    // ===================== USART6 RX Timeout =====================
+    // TIM3_CH1 (PA6) is jumpered to USART6 RX (PG9)
+    let rx_timeout = RxTimeout::new(TIM3, gpioa.pa6.into_alternate::<2>(), rcc_config);
+    usart6.attach_rx_timeout(rx_timeout, RX_TIMEOUT_BIT_TIMES);
+
+    // ===================== Buzzer =====================
+    // TIM14_CH1 (PA7), Arduino header D9
+    let buzzer = Buzzer::new(
+        TIM14,
+        gpioa.pa7.into_alternate::<9>(),
+        rcc_config,
+        BUZZER_FREQUENCY_HZ,
+    );
+
+    // ===================== LED PWM =====================
+    // TIM4 interrupt only, the blue and red LED pins have no timer output
+    #[cfg(feature = "led-pwm")]
+    let led_pwm = LedPwm::new(TIM4, rcc_config);
+
+    // ===================== QSPI Flash =====================
+    let gpiof = GPIOF.split();
+    // 90 MHz flash clock: very high speed output drivers
+    let qspi_pins = (
+        gpiof.pf10.into_alternate::<9>().speed(Speed::VeryHigh), // CLK
+        gpiob.pb6.into_alternate::<10>().speed(Speed::VeryHigh), // NCS
+        gpiof.pf8.into_alternate::<10>().speed(Speed::VeryHigh), // IO0
+        gpiof.pf9.into_alternate::<10>().speed(Speed::VeryHigh), // IO1
+        gpiof.pf7.into_alternate::<9>().speed(Speed::VeryHigh),  // IO2
+        gpiof.pf6.into_alternate::<9>().speed(Speed::VeryHigh),  // IO3
+    );
+    // Nothing depends on the QSPI flash yet, so a missing part is not fatal
+    let qspi = QspiFlash::new(QUADSPI, qspi_pins).ok();
+
+    // ===================== RTC =====================
+    let rtc = Rtc::start(RTC);
+
+    // ===================== Port C =====================
+    // Port C also carries an SDRAM pin, so it is split before the SDRAM set-up
+    let gpioc = GPIOC.split();
+
+    // ===================== SD Card =====================
+    #[cfg(feature = "sd-log")]
+    let sd_card = {
+        let pins = (
+            gpioc.pc12.into_alternate::<12>(),                        // CK
+            gpiod.pd2.into_alternate::<12>().internal_pull_up(true),  // CMD
+            gpioc.pc8.into_alternate::<12>().internal_pull_up(true),  // D0
+            gpioc.pc9.into_alternate::<12>().internal_pull_up(true),  // D1
+            gpioc.pc10.into_alternate::<12>().internal_pull_up(true), // D2
+            gpioc.pc11.into_alternate::<12>().internal_pull_up(true), // D3
+        );
+        SdCard::new(SDIO, pins, gpiog.pg2.into_pull_up_input(), &rcc_config.clocks)
+    };
+
+    // ===================== Clock Outputs =====================
+    // MCO1 on PA8, MCO2 on PC9 (SDIO D1 with the `sd-log` feature); AF0 and
+    // very high speed are set once, the pins stay analog until switched on
+    #[cfg(not(feature = "sd-log"))]
+    let mco2 = Some(gpioc.pc9.into_alternate::<0>().speed(Speed::VeryHigh).into_analog());
+    #[cfg(feature = "sd-log")]
+    let mco2 = None;
+    let mco1 = gpioa.pa8.into_alternate::<0>().speed(Speed::VeryHigh).into_analog();
+    mco::init((mco1, mco2));
+
+    // ===================== Port H =====================
+    // Port H also carries SDRAM pins, so it is split before the SDRAM set-up
+    #[cfg(any(feature = "display", feature = "spi"))]
+    let gpioh = GPIOH.split();
+
+    // ===================== LCD Reset =====================
+    #[cfg(feature = "display")]
+    let lcd_reset = gpioh.ph7.into_push_pull_output();
+
+    // ===================== SDRAM =====================
+    // Must follow all port splits: its pins are set up through the GPIO registers
+    let sdram = sdram::init(FMC);
+
+    // ===================== Display =====================
+    // The framebuffer is in the SDRAM; without it the display stays off
+    #[cfg(feature = "display")]
+    let display = Display::new(LTDC, DMA2D, DSI, lcd_reset, TIM7, &rcc_config.clocks);
+
+    // ===================== I2C1 =====================
+    // PB8/PB9 open drain, pull-ups on the board
+    #[cfg(feature = "i2c")]
+    #[allow(unused_mut)]
+    let mut i2c_1 = I2cMaster::new(
+        I2C1,
+        (
+            gpiob.pb8.into_alternate_open_drain::<4>(), // SCL
+            gpiob.pb9.into_alternate_open_drain::<4>(), // SDA
+        ),
+        I2cSpeed::Fast,
+    );
+
+    // ===================== Touch Controller =====================
+    // On I2C1, INT on PJ5 (EXTI line 5)
+    #[cfg(feature = "touch")]
+    let touch = Touch::new(&mut i2c_1, GPIOJ.split().pj5.into_pull_up_input());
+
+    // ===================== CAN1 =====================
+    // PB8 RX, PB9 TX to an external transceiver, instead of I2C1
+    #[cfg(feature = "can")]
+    let can_1 = Can1::new(
+        CAN1,
+        (
+            gpiob.pb8.into_alternate::<9>(), // RX
+            gpiob.pb9.into_alternate::<9>(), // TX
+        ),
+    );
+
+    // ===================== SPI2 =====================
+    // Arduino D13/D12/D11, chip selects on D10 (PH6) and PG10; TX DMA on DMA1 stream 4
+    #[cfg(feature = "spi")]
+    let spi_2 = Spi2Master::new(
+        SPI2,
+        (
+            gpiod.pd3.into_alternate::<5>().speed(Speed::High),  // SCK
+            gpiob.pb14.into_alternate::<5>(),                    // MISO
+            gpiob.pb15.into_alternate::<5>().speed(Speed::High), // MOSI
+        ),
+        [
+            gpioh.ph6.into_push_pull_output_in_state(PinState::High).erase(),  // CS0
+            gpiog.pg10.into_push_pull_output_in_state(PinState::High).erase(), // CS1
+        ],
+        dma1.4,
+    );
+
+    // ===================== ADC1 =====================
+    // Arduino A0-A5 in analog mode; results collected by DMA2 stream 0
+    #[cfg(feature = "adc")]
+    let adc_1 = Adc1::new(
+        ADC1,
+        (
+            gpiob.pb1.into_analog(), // A0
+            gpioc.pc2.into_analog(), // A1
+            gpioc.pc3.into_analog(), // A2
+            gpioc.pc4.into_analog(), // A3
+            gpioc.pc5.into_analog(), // A4
+            gpioa.pa4.into_analog(), // A5
+        ),
+        dma2.0,
+    );
+
+    // ===================== DAC =====================
+    // Channel 1 on PA4 (ADC A5 with the `adc` feature), channel 2 on PA5;
+    // waveforms on DMA1 streams 5 and 6
+    #[cfg(all(feature = "dac", not(feature = "adc")))]
+    let dac_out1 = Some(gpioa.pa4.into_analog());
+    #[cfg(all(feature = "dac", feature = "adc"))]
+    let dac_out1 = None;
+    #[cfg(feature = "dac")]
+    let dac = Dac::new(
+        DAC,
+        (TIM6, TIM5),
+        (dac_out1, gpioa.pa5.into_analog()),
+        (dma1.5, dma1.6),
+    );
+
+    // ===================== Unused Pin Parking =====================
+    // Must follow all port splits: the HAL resets a port when splitting it
+    park_unused_pins();
+
+    // ===================== Configuration Verification =====================
+    verify_configuration()?;
+
     // ===================== Interrupt Configuration =====================
     // SAFETY: Single unmask operations during initialization
     unsafe {
@@ -111,12 +546,67 @@ pub fn init_peripherals(device: pac::Peripherals) -> Result<InitializedPeriphera
         cortex_m::peripheral::NVIC::unmask(Interrupt::USART6);
         cortex_m::peripheral::NVIC::unmask(Interrupt::DMA2_STREAM1);
         cortex_m::peripheral::NVIC::unmask(Interrupt::DMA2_STREAM6);
+        cortex_m::peripheral::NVIC::unmask(Interrupt::USART3);
+        cortex_m::peripheral::NVIC::unmask(Interrupt::DMA1_STREAM1);
+        cortex_m::peripheral::NVIC::unmask(Interrupt::DMA1_STREAM3);
+        cortex_m::peripheral::NVIC::unmask(Interrupt::TIM3);
+        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI0);
+        #[cfg(feature = "led-pwm")]
+        cortex_m::peripheral::NVIC::unmask(Interrupt::TIM4);
+        #[cfg(feature = "touch")]
+        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI9_5);
+        #[cfg(feature = "can")]
+        cortex_m::peripheral::NVIC::unmask(Interrupt::CAN1_RX0);
     }
 
+    // ===================== Window Watchdog =====================
+    // Reads its reset flag before the IWDG clears them all
+    let wwdg = Wwdg::new(WWDG);
+
+    // ===================== Supply Voltage Detector =====================
+    // Reads the brown-out reset flag, as above
+    pvd::init();
+
+    // ===================== Independent Watchdog =====================
+    // Started last so slow initialization cannot trip it
+    let iwdg = Iwdg::start(IWDG);
+
     Ok(InitializedPeripherals {
-        blue_led,
+        green_led,
+        orange_led,
         red_led,
+        buzzer,
+        blue_led,
+        #[cfg(feature = "led-pwm")]
+        led_pwm,
+        button,
         usart_6: usart6,
+        usart_3: usart3,
         otg_fs,
+        flash,
+        qspi,
+        sdram,
+        #[cfg(feature = "display")]
+        display,
+        #[cfg(feature = "i2c")]
+        i2c_1,
+        #[cfg(feature = "touch")]
+        touch,
+        #[cfg(feature = "can")]
+        can_1,
+        #[cfg(feature = "spi")]
+        spi_2,
+        #[cfg(feature = "adc")]
+        adc_1,
+        #[cfg(feature = "dac")]
+        dac,
+        #[cfg(feature = "sd-log")]
+        sd_card,
+        modem_lines,
+        control_outputs,
+        vbus,
+        rtc,
+        iwdg,
+        wwdg,
     })
 }
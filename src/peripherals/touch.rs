@@ -0,0 +1,216 @@
+//! # FT6206 Capacitive Touch
+//!
+//! Driver for the touch controller of the Discovery board's display:
+//! - Identification and interrupt (trigger) mode set-up at start-up
+//! - Reads the first touch point, mapped to landscape screen coordinates
+//! - EXTI line 5 on the falling edge of the controller's INT output
+//! - Read and bus error counters are kept for the `touch` report
+//!
+//! ## Hardware Configuration
+//! - I2C1 through the shared `I2c1Bus`, 400 kHz
+//! - INT: PJ5, active low, routed to EXTI line 5 (SYSCFG EXTICR2)
+//! - The panel's native orientation is portrait, so X and Y are swapped
+//!
+//! ## Safety Considerations
+//! - EXTI9_5 is shared with the UART wakeup line; the handler must check
+//!   `take_interrupt` instead of assuming a touch
+//! - Reads block on the I2C bus for about 0.2 ms; call from a low-priority task
+
+use crate::errors::errors::TouchError;
+use crate::peripherals::display::{HEIGHT, WIDTH};
+use crate::peripherals::i2c::I2c1Bus;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::{
+    gpio::{gpioj::PJ5, Input},
+    pac::{self, EXTI},
+};
+
+/// 7-bit I2C address of the controller on the Discovery board
+const ADDRESS: u8 = 0x2A;
+
+/// Registers
+const REG_TD_STATUS: u8 = 0x02;
+const REG_TH_GROUP: u8 = 0x80;
+const REG_G_MODE: u8 = 0xA4;
+const REG_FOCALTECH_ID: u8 = 0xA8;
+
+/// FocalTech vendor ID
+const FOCALTECH_ID: u8 = 0x11;
+
+/// G_MODE: INT pulses low for every report while touched
+const G_MODE_TRIGGER: u8 = 0x01;
+
+/// Touch detection threshold; lower is more sensitive
+const TOUCH_THRESHOLD: u8 = 0x16;
+
+/// TD_STATUS: number of touch points
+const TD_STATUS_MASK: u8 = 0x0F;
+
+/// Event flag field of P1_XH
+const EVENT_SHIFT: u8 = 6;
+
+/// EXTI line of the INT pin
+const TOUCH_LINE: u32 = 1 << 5;
+
+/// SYSCFG EXTICR2 field of EXTI line 5 and its port J setting
+const EXTICR2_LINE5_MASK: u32 = 0xF << 4;
+const EXTICR2_LINE5_PORT_J: u32 = 0x9 << 4;
+
+/// Set once the controller answered
+static DETECTED: AtomicBool = AtomicBool::new(false);
+
+/// Touch points read since boot
+static READS: AtomicU32 = AtomicU32::new(0);
+
+/// Failed I2C transfers since boot
+static BUS_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// Contact phase reported with a touch point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchEvent {
+    /// First report of a new contact
+    Down,
+    /// Finger lifted
+    Up,
+    /// Ongoing contact
+    Contact,
+}
+
+/// One touch point in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchPoint {
+    /// Column, `0..WIDTH`
+    pub x: u16,
+    /// Row, `0..HEIGHT`
+    pub y: u16,
+    /// Contact phase
+    pub event: TouchEvent,
+}
+
+/// FT6206 touch controller on I2C1
+pub struct Touch {
+    _int: PJ5<Input>,
+}
+
+impl Touch {
+    /// Identifies the controller, selects interrupt mode and unmasks EXTI line 5
+    ///
+    /// # Arguments
+    /// * `bus` - I2C1 bus
+    /// * `int` - Controller INT output (PJ5)
+    ///
+    /// # Errors
+    /// - `NotFound` if the controller does not answer with the FocalTech ID
+    /// - `Bus` if the set-up writes fail
+    pub fn new(bus: &mut I2c1Bus, int: PJ5<Input>) -> Result<Self, TouchError> {
+        let mut id = [0u8; 1];
+        bus.write_read(ADDRESS, &[REG_FOCALTECH_ID], &mut id)
+            .map_err(|_| TouchError::NotFound)?;
+        if id[0] != FOCALTECH_ID {
+            return Err(TouchError::NotFound);
+        }
+
+        write_register(bus, REG_TH_GROUP, TOUCH_THRESHOLD)?;
+        write_register(bus, REG_G_MODE, G_MODE_TRIGGER)?;
+        DETECTED.store(true, Ordering::Relaxed);
+
+        // SAFETY: Only the line 5 bits of SYSCFG EXTICR2 and the EXTI
+        // registers are touched; line 5 is not used by any other driver
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            let syscfg = &*pac::SYSCFG::ptr();
+            let exti = &*EXTI::ptr();
+
+            rcc.apb2enr().modify(|_, w| w.syscfgen().set_bit());
+            syscfg
+                .exticr2()
+                .modify(|r, w| w.bits((r.bits() & !EXTICR2_LINE5_MASK) | EXTICR2_LINE5_PORT_J));
+
+            exti.ftsr().modify(|r, w| w.bits(r.bits() | TOUCH_LINE));
+            exti.rtsr().modify(|r, w| w.bits(r.bits() & !TOUCH_LINE));
+            exti.pr().write(|w| w.bits(TOUCH_LINE));
+            exti.imr().modify(|r, w| w.bits(r.bits() | TOUCH_LINE));
+        }
+
+        #[cfg(feature = "debug")]
+        defmt::info!("FT6206 touch controller detected");
+
+        Ok(Self { _int: int })
+    }
+
+    /// Reads the first touch point
+    ///
+    /// # Arguments
+    /// * `bus` - I2C1 bus
+    ///
+    /// # Returns
+    /// `None` while nothing touches the panel
+    pub fn read(&mut self, bus: &mut I2c1Bus) -> Result<Option<TouchPoint>, TouchError> {
+        // TD_STATUS followed by P1_XH, P1_XL, P1_YH and P1_YL
+        let mut data = [0u8; 5];
+        bus.write_read(ADDRESS, &[REG_TD_STATUS], &mut data)
+            .map_err(|_| {
+                BUS_ERRORS.fetch_add(1, Ordering::Relaxed);
+                TouchError::Bus
+            })?;
+
+        if data[0] & TD_STATUS_MASK == 0 {
+            return Ok(None);
+        }
+        READS.fetch_add(1, Ordering::Relaxed);
+
+        let event = match data[1] >> EVENT_SHIFT {
+            0 => TouchEvent::Down,
+            1 => TouchEvent::Up,
+            _ => TouchEvent::Contact,
+        };
+        let raw_x = (u16::from(data[1] & 0x0F) << 8) | u16::from(data[2]);
+        let raw_y = (u16::from(data[3] & 0x0F) << 8) | u16::from(data[4]);
+
+        // Portrait panel turned for landscape: the long axis is the panel's Y
+        Ok(Some(TouchPoint {
+            x: raw_y.min(WIDTH as u16 - 1),
+            y: (HEIGHT as u16 - 1).saturating_sub(raw_x),
+            event,
+        }))
+    }
+}
+
+/// Writes one controller register
+fn write_register(bus: &mut I2c1Bus, register: u8, value: u8) -> Result<(), TouchError> {
+    bus.write(ADDRESS, &[register, value]).map_err(|_| {
+        BUS_ERRORS.fetch_add(1, Ordering::Relaxed);
+        TouchError::Bus
+    })
+}
+
+/// Clears a pending touch interrupt
+///
+/// # Returns
+/// `true` if EXTI line 5 was pending, i.e. the controller signalled a touch
+pub fn take_interrupt() -> bool {
+    // SAFETY: Write-one-to-clear register, only line 5 is set
+    unsafe {
+        let exti = &*EXTI::ptr();
+        if exti.pr().read().bits() & TOUCH_LINE == 0 {
+            return false;
+        }
+        exti.pr().write(|w| w.bits(TOUCH_LINE));
+    }
+    true
+}
+
+/// Writes the controller state and counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    if !DETECTED.load(Ordering::Relaxed) {
+        return writeln!(out, "Touch: not detected");
+    }
+    writeln!(
+        out,
+        "Touch: FT6206 at {:#04x}, {} reads, {} bus errors",
+        ADDRESS,
+        READS.load(Ordering::Relaxed),
+        BUS_ERRORS.load(Ordering::Relaxed)
+    )
+}
@@ -1,3 +1,6 @@
+use crate::errors::errors::{UsartError, UsbError};
+use bitflags::bitflags;
+
 /// Trait для работы с GPIO пинами
 pub trait GpioPin {
     /// Тип ошибки при работе с пином
@@ -20,3 +23,114 @@ pub trait GpioPin {
     /// Переключает состояние пина
     fn toggle(&mut self) -> Result<(), Self::Error>;
 }
+
+bitflags! {
+    /// USART status flags for interrupt handling
+    pub struct UsartFlag: u32 {
+        const RXNE = 1 << 5;  // Receive Data Register Not Empty
+        const TXE  = 1 << 7;  // Transmit Data Register Empty
+        const TC   = 1 << 6;  // Transmission Complete
+        const IDLE = 1 << 4;  // Idle line detected
+    }
+}
+
+bitflags! {
+    /// USART receive errors, at their status register positions
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LineError: u32 {
+        const PE  = 1 << 0;  // Parity error
+        const FE  = 1 << 1;  // Framing error
+        const NE  = 1 << 2;  // Noise detected
+        const ORE = 1 << 3;  // Overrun
+    }
+}
+
+impl LineError {
+    /// Error reported for the set
+    ///
+    /// Line problems go before overrun, which often follows them: framing,
+    /// then parity, then noise.
+    pub fn classify(self) -> Option<UsartError> {
+        if self.contains(LineError::FE) {
+            Some(UsartError::Framing)
+        } else if self.contains(LineError::PE) {
+            Some(UsartError::Parity)
+        } else if self.contains(LineError::NE) {
+            Some(UsartError::Noise)
+        } else if self.contains(LineError::ORE) {
+            Some(UsartError::Overrun)
+        } else {
+            None
+        }
+    }
+}
+
+/// Data path of a bridged UART: DMA transfers, error checks and recovery
+///
+/// What the DMA handlers (`dma2`, `data_path`) need of a UART, implemented
+/// by every `UartController` and, in host builds, by `mock::MockSerial`.
+/// `uart::BridgeUart` adds the line settings on top.
+pub trait SerialLink {
+    /// Checks whether the transmitter is taken by a DMA transfer or a break
+    fn is_tx_busy(&self) -> bool;
+    /// Transmits caller memory without copying it into the TX buffer
+    ///
+    /// # Safety
+    /// `data` must stay valid and unchanged until the transfer completed
+    /// (`is_tx_busy` is false again)
+    unsafe fn transmit_external(&mut self, data: &[u8]) -> Result<(), UsartError>;
+    /// Gets mutable slice of TX buffer
+    fn get_tx_buffer_slice(&mut self, length: usize) -> Option<&mut [u8]>;
+    /// Initiates DMA write transfer of the first `len` bytes of the TX buffer
+    fn write_dma(&mut self, len: usize) -> Result<(), UsartError>;
+    /// Copies the bytes received since the previous call into `out`
+    ///
+    /// # Returns
+    /// Number of bytes copied, at most `out.len()`
+    fn read_dma_rx(&mut self, out: &mut [u8]) -> Result<usize, UsartError>;
+    /// Drops the bytes received since the previous read
+    fn skip_rx(&mut self) -> Result<(), UsartError>;
+    /// Number of received bytes not yet read
+    fn unread_rx_len(&mut self) -> Result<usize, UsartError>;
+    /// Clears DMA TX complete flag
+    fn clear_dma_tx_complete_flag(&mut self);
+    /// Clears DMA RX half-transfer and transfer complete flags
+    fn clear_dma_rx_complete_flag(&mut self);
+    /// Clears specified USART flags using proper clear sequences
+    fn clear_usart_flags(&self, flags: UsartFlag);
+    /// Takes the receive errors seen since the previous call
+    fn take_line_errors(&mut self) -> LineError;
+    /// Clears all DMA error flags
+    fn clear_errors(&mut self);
+    /// Checks for DMA RX transfer errors
+    fn check_dma_rx_error(&mut self) -> Result<bool, UsartError>;
+    /// Checks for DMA TX transfer errors
+    fn check_dma_tx_error(&mut self) -> Result<bool, UsartError>;
+    /// Restarts DMA reception with error recovery
+    fn restart_dma_rx(&mut self) -> Result<(), UsartError>;
+    /// Restarts DMA transmission with error recovery
+    fn restart_dma_tx(&mut self) -> Result<(), UsartError>;
+}
+
+/// Data path of the USB bridge port
+///
+/// What the USB handlers (`otg_fs`, `data_path`) need of the device,
+/// implemented by `OtgFsController` and, in host builds, by
+/// `mock::MockUsb`.
+pub trait UsbLink {
+    /// Checks whether the host configured the device
+    fn is_configured(&self) -> bool;
+    /// Bytes moved to the host per write
+    fn chunk_size(&self) -> usize;
+    /// Reads one packet from the host into `buffer`
+    ///
+    /// # Returns
+    /// Number of bytes read; 0 when no data is available
+    fn read_into(&mut self, buffer: &mut [u8]) -> Result<usize, UsbError>;
+    /// Writes data to the host
+    ///
+    /// # Returns
+    /// Number of bytes taken, fewer than `data.len()` while the endpoint is
+    /// busy
+    fn write(&mut self, data: &[u8]) -> Result<usize, UsbError>;
+}
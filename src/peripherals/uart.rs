@@ -0,0 +1,1417 @@
+//! # UART Controller Implementation
+//!
+//! This module provides DMA-driven UART communication handling for the bridged
+//! USARTs on STM32F469 microcontrollers. `UartController` is generic over the
+//! USART instance and its DMA streams; `Usart6Controller` and
+//! `Usart3Controller` are the two instances of this board. Key features include:
+//! - Full-duplex DMA transfers with configurable buffers
+//! - TX from the controller's own buffer (framed packets, benchmark pattern,
+//!   link-control writes) or, zero-copy, from caller memory: the `TxPingPong`
+//!   staging buffers and the read grants of the TX queue (`spsc`), so
+//!   bridged data is never copied into the controller's buffer
+//! - Gap-free circular RX: double-buffer DMA with half/complete interrupts,
+//!   new bytes located from the NDTR counter and the current target bit
+//! - Error detection and recovery mechanisms; waits on the hardware are
+//!   bounded (`utils::timeout`) and end in `UsartError::Timeout`
+//! - Hardware flag management for USART status
+//! - Receive error classification (`LineError`): overrun, framing, noise and
+//!   parity errors are latched on every status read and taken as one set
+//! - Thread-safe buffer access patterns
+//! - Optional programmable RX timeout in bit times
+//! - Automatic baud rate detection: the RX timeout timer measures the RX
+//!   edge intervals (`baud_capture`) until the rate is known
+//! - Runtime frame format (`UartConfig`: data bits, parity, stop bits)
+//! - Optional RS-485 half-duplex mode: a driver enable output (`rs485`) is
+//!   asserted before each TX transfer and released from the
+//!   transmission-complete interrupt (`poll_rs485`)
+//! - Suspend/resume for safe mode
+//! - Bus clock changes of `clock_scaling`: BRR and the RX timeout prescaler
+//!   are recomputed for the unchanged baud rate (`set_clock_level`)
+//! - Break conditions: the TX pin is taken over as a GPIO output held low,
+//!   for as long as the caller keeps the break
+//! - `SerialLink` (`traits`): the data-path operations, so the DMA handlers
+//!   serve either instance; `BridgeUart` adds the line settings
+//! - `embedded_io` blocking reads and writes and the `embedded_hal_nb`
+//!   serial traits, for third-party drivers on top of either instance
+//!
+//! ## Hardware Configuration
+//! - USART6: PG14 (TX) and PG9 (RX) in alternate function mode 8, DMA2
+//!   streams 6 (TX) and 1 (RX), channel 5
+//! - USART3: PB10 (TX) and PB11 (RX) in alternate function mode 7, DMA1
+//!   streams 3 (TX) and 1 (RX), channel 4
+//! - A break switches the TX pin to a GPIO output low; the alternate
+//!   function selection stays
+//! - RX never stops between transfers
+//! - Baud rates configured in `config` module
+//!
+//! ## Safety Considerations
+//! - Register access outside the HAL is confined to the `regs` submodule
+//! - DMA buffers are allocated once by the caller (singleton pattern)
+//! - Atomic flag checks for transfer status
+//! - Automatic error recovery for DMA faults
+
+use core::sync::atomic::{compiler_fence, Ordering};
+use stm32f4xx_hal::{
+    dma::{
+        traits::{Channel, DMASet, PeriAddress, Stream, StreamISR},
+        ChannelX, CurrentBuffer, DmaFlag, MemoryToPeripheral, PeripheralToMemory, Stream1,
+        Stream3, Stream6, Transfer,
+    },
+    gpio::PushPull,
+    pac::{usart1::RegisterBlock, Interrupt, DMA1, DMA2, GPIOB, GPIOG, USART3, USART6},
+    prelude::*,
+    serial::{self, Config, Rx, Serial, Tx},
+};
+
+use crate::config::{DMA_BUFFER_LEN, UART_STOP_TIMEOUT_MS};
+use crate::data_structures::typedefs::{DmaRxTransfer, DmaTxTransfer};
+use crate::dma_cfg;
+use crate::dma_rx_cfg;
+use crate::errors::errors::UsartError;
+use crate::peripherals::baud_capture::BaudCapture;
+use crate::peripherals::clock_scaling::ClockLevel;
+use crate::peripherals::rcc::RccConfig;
+use crate::peripherals::rs485::{DriverEnable, Rs485Config};
+use crate::peripherals::rx_timeout::{RxTimeout, RxTimeoutEvent};
+use crate::utils::timeout::busy_wait_until;
+use embedded_hal_nb::nb;
+
+mod regs;
+mod uart_config;
+
+use regs::{TxPinRegs, UsartRegs};
+pub use crate::peripherals::traits::{LineError, SerialLink, UsartFlag};
+pub use uart_config::{DataBits, Parity, StopBits, UartConfig};
+
+/// Size of the circular RX area (both DMA halves)
+pub const DMA_RX_LEN: usize = 2 * DMA_BUFFER_LEN;
+
+/// USART instances the controller can drive
+///
+/// Only USARTs with the `usart1` register block layout qualify; `regs`
+/// aliases that block.
+pub trait UartInstance: serial::Instance {
+    /// Register block
+    const REGS: *const RegisterBlock;
+    /// USART interrupt
+    const IRQ: Interrupt;
+    /// DMA TX and RX stream interrupts
+    const DMA_IRQS: [Interrupt; 2];
+    /// GPIO port register block and pin number of the TX pin
+    const TX_PIN: (*const u32, u8);
+    /// Name used in logs
+    const NAME: &'static str;
+    /// Clocked by PCLK2 (APB2) rather than PCLK1 (APB1)
+    const ON_APB2: bool;
+}
+
+impl UartInstance for USART6 {
+    const REGS: *const RegisterBlock = USART6::ptr();
+    const IRQ: Interrupt = Interrupt::USART6;
+    const DMA_IRQS: [Interrupt; 2] = [Interrupt::DMA2_STREAM6, Interrupt::DMA2_STREAM1];
+    const TX_PIN: (*const u32, u8) = (GPIOG::ptr() as *const u32, 14);
+    const NAME: &'static str = "USART6";
+    const ON_APB2: bool = true;
+}
+
+impl UartInstance for USART3 {
+    const REGS: *const RegisterBlock = USART3::ptr();
+    const IRQ: Interrupt = Interrupt::USART3;
+    const DMA_IRQS: [Interrupt; 2] = [Interrupt::DMA1_STREAM3, Interrupt::DMA1_STREAM1];
+    const TX_PIN: (*const u32, u8) = (GPIOB::ptr() as *const u32, 10);
+    const NAME: &'static str = "USART3";
+    const ON_APB2: bool = false;
+}
+
+/// Line settings of a bridged UART, on top of its data path
+///
+/// Implemented by every `UartController`, so the AT channel and the console
+/// serve whichever UART the bridge is routed to. The methods mirror the
+/// inherent ones of the same name.
+pub trait BridgeUart: SerialLink {
+    /// Current baud rate in bits per second
+    fn baud_rate(&self) -> u32;
+    /// Changes the baud rate at runtime
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), UsartError>;
+    /// Current frame format
+    fn uart_config(&self) -> UartConfig;
+    /// Changes data bits, parity and stop bits at runtime
+    fn reconfigure(&mut self, config: UartConfig) -> Result<(), UsartError>;
+    /// Checks if transmission is complete
+    fn is_transmission_complete(&self) -> bool;
+    /// Starts a break condition on the TX line
+    fn start_break(&mut self) -> bool;
+    /// Ends a break condition
+    fn end_break(&mut self);
+}
+
+/// USART6 on DMA2 streams 6 (TX) and 1 (RX)
+pub type Usart6Controller = UartController<USART6, Stream6<DMA2>, Stream1<DMA2>, 5>;
+
+/// USART3 on DMA1 streams 3 (TX) and 1 (RX)
+pub type Usart3Controller = UartController<USART3, Stream3<DMA1>, Stream1<DMA1>, 4>;
+
+/// Main controller for one USART with DMA capabilities
+///
+/// # Type Parameters
+/// * `USART` - USART peripheral
+/// * `TXS`, `RXS` - DMA streams for TX and RX
+/// * `CH` - DMA channel shared by both streams
+pub struct UartController<USART, TXS, RXS, const CH: u8>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    dma_tx: Option<DmaTxTransfer<USART, TXS, CH>>,
+    dma_rx: Option<DmaRxTransfer<USART, RXS, CH>>,
+    regs: UsartRegs,
+    tx_pin: TxPinRegs,
+    /// A break holds the transmitter
+    break_held: bool,
+    tx_buffer: &'static mut [u8],
+    /// Read-only view of both RX halves, written by the DMA
+    rx_buffer: &'static [u8],
+    /// Next unread byte in `rx_buffer`
+    rx_read_pos: usize,
+    rx_timeout: Option<RxTimeout>,
+    /// Holds the RX timeout timer during a baud rate detection
+    baud_capture: Option<BaudCapture>,
+    /// RX timeout restored after the detection, in bit times
+    paused_rx_timeout: u16,
+    /// RS-485 transceiver driver enable, `None` for full duplex
+    rs485: Option<DriverEnable>,
+    baud_rate: u32,
+    uart_config: UartConfig,
+    pclk: u32,
+}
+
+impl<USART, TXS, RXS, const CH: u8> UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    /// Initializes the USART with DMA configuration
+    ///
+    /// # Arguments
+    /// * `usart` - USART peripheral instance
+    /// * `streams` - DMA TX and RX streams
+    /// * `pins` - Configured TX and RX pins
+    /// * `buffers` - DMA TX buffer and circular RX area, allocated once
+    /// * `baud_rate` - Initial baud rate (8N1)
+    /// * `clocks` - System clock configuration
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if serial port initialization fails
+    ///
+    /// # Safety
+    /// - Must be called only once per USART during system initialization
+    /// - Requires exclusive access to both DMA streams
+    pub fn init(
+        usart: USART,
+        (tx_stream, rx_stream): (TXS, RXS),
+        pins: (
+            impl Into<USART::Tx<PushPull>>,
+            impl Into<USART::Rx<PushPull>>,
+        ),
+        (tx_buffer, rx_ring): (
+            &'static mut [u8; DMA_BUFFER_LEN],
+            &'static mut [u8; DMA_RX_LEN],
+        ),
+        baud_rate: u32,
+        clocks: &RccConfig,
+    ) -> Result<Self, UsartError> {
+        let serial = Serial::new(
+            usart,
+            pins,
+            Config {
+                baudrate: baud_rate.bps(),
+                wordlength: stm32f4xx_hal::serial::config::WordLength::DataBits8,
+                parity: stm32f4xx_hal::serial::config::Parity::ParityNone,
+                stopbits: stm32f4xx_hal::serial::config::StopBits::STOP1,
+                dma: stm32f4xx_hal::serial::config::DmaConfig::TxRx,
+                ..Default::default()
+            },
+            &clocks.clocks,
+        )
+        .map_err(|_| UsartError::NotInitialized)?;
+
+        let (tx, mut rx) = serial.split();
+        let tx_buffer: &'static mut [u8] = tx_buffer;
+        let rx_ring: &'static mut [u8] = rx_ring;
+
+        // SAFETY: Buffer pointers remain valid for 'static lifetime
+        let tx_buffer_dma = unsafe { &mut *(tx_buffer as *mut [u8]) };
+        // SAFETY: The controller only reads bytes the DMA has already written
+        // (behind the NDTR position), never the byte being transferred
+        let rx_buffer = unsafe { core::slice::from_raw_parts(rx_ring.as_ptr(), DMA_RX_LEN) };
+        let (rx_first, rx_second) = rx_ring.split_at_mut(DMA_BUFFER_LEN);
+
+        rx.listen_idle();
+
+        // SAFETY: Only handle for this USART, created after HAL configuration
+        let regs = unsafe { UsartRegs::new(USART::REGS) };
+        regs.disable_tx_interrupts();
+        // SAFETY: The pin was just handed to the USART by `Serial::new`
+        let tx_pin = unsafe { TxPinRegs::new(USART::TX_PIN.0, USART::TX_PIN.1) };
+
+        let mut dma_tx =
+            Transfer::init_memory_to_peripheral(tx_stream, tx, tx_buffer_dma, None, dma_cfg!());
+        // The second buffer selects double-buffer mode: the stream alternates
+        // between both halves without ever being restarted
+        let dma_rx = Transfer::init_peripheral_to_memory(
+            rx_stream,
+            rx,
+            rx_first,
+            Some(rx_second),
+            dma_rx_cfg!(),
+        );
+
+        dma_tx.start(|_tx| {});
+
+        #[cfg(feature = "debug")]
+        defmt::info!("{=str} initialized successfully", USART::NAME);
+
+        Ok(Self {
+            dma_tx: Some(dma_tx),
+            dma_rx: Some(dma_rx),
+            regs,
+            tx_pin,
+            break_held: false,
+            tx_buffer,
+            rx_buffer,
+            rx_read_pos: 0,
+            rx_timeout: None,
+            baud_capture: None,
+            paused_rx_timeout: 0,
+            rs485: None,
+            baud_rate,
+            uart_config: UartConfig::EIGHT_N1,
+            pclk: if USART::ON_APB2 {
+                clocks.clocks.pclk2().raw()
+            } else {
+                clocks.clocks.pclk1().raw()
+            },
+        })
+    }
+
+    /// Attaches the RX timeout timer and programs its initial timeout
+    ///
+    /// # Arguments
+    /// * `timer` - Timer slaved to the RX line
+    /// * `bit_times` - Initial timeout in bit times
+    pub fn attach_rx_timeout(&mut self, mut timer: RxTimeout, bit_times: u16) {
+        timer.configure(self.baud_rate, bit_times);
+        self.rx_timeout = Some(timer);
+    }
+
+    /// Sets the receiver timeout
+    ///
+    /// # Arguments
+    /// * `bit_times` - Line silence, in bit times, after which buffered RX data
+    ///   is flushed (e.g. 35 for the Modbus RTU 3.5 character gap); 0 disables it
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if no timeout timer is attached
+    pub fn set_rx_timeout(&mut self, bit_times: u16) -> Result<(), UsartError> {
+        let timer = self.rx_timeout.as_mut().ok_or(UsartError::NotInitialized)?;
+
+        if bit_times == 0 {
+            timer.disable();
+        } else {
+            timer.configure(self.baud_rate, bit_times);
+        }
+        Ok(())
+    }
+
+    /// Receiver timeout in bit times, 0 while disabled
+    ///
+    /// # Returns
+    /// `None` if no timeout timer is attached (or it measures the baud rate)
+    pub fn rx_timeout_bit_times(&self) -> Option<u16> {
+        self.rx_timeout.as_ref().map(RxTimeout::bit_times)
+    }
+
+    /// Handles the RX timeout timer interrupt
+    ///
+    /// # Returns
+    /// - `Some(RxTimeoutEvent)` if the timer raised an event
+    /// - `None` if no timer is attached or no flag was set
+    pub fn poll_rx_timeout(&mut self) -> Option<RxTimeoutEvent> {
+        self.rx_timeout.as_mut()?.on_interrupt()
+    }
+
+    /// Starts measuring the baud rate of the RX line
+    ///
+    /// The RX timeout timer is taken over for the measurement; the timeout
+    /// is off until `finish_baud_detect`.
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if no timeout timer is attached
+    /// or a detection is already running
+    pub fn start_baud_detect(&mut self) -> Result<(), UsartError> {
+        let timer = self.rx_timeout.take().ok_or(UsartError::NotInitialized)?;
+        self.paused_rx_timeout = timer.bit_times();
+        self.baud_capture = Some(timer.into_capture());
+        Ok(())
+    }
+
+    /// Handles the timer interrupt during a baud rate detection
+    ///
+    /// # Returns
+    /// - `Some(rate)` once the measurement is complete, in bits per second
+    /// - `None` if no detection runs or it needs more edges
+    pub fn poll_baud_capture(&mut self) -> Option<u32> {
+        self.baud_capture.as_mut()?.on_interrupt()
+    }
+
+    /// Ends a baud rate detection
+    ///
+    /// Restores the RX timeout and drops the bytes received meanwhile, which
+    /// were sampled at the old rate.
+    ///
+    /// # Arguments
+    /// * `baud_rate` - Detected rate to switch to; `None` keeps the old one
+    ///
+    /// # Errors
+    /// - `UsartError::NotInitialized` if no detection runs
+    /// - As `set_baud_rate` and `skip_rx`
+    pub fn finish_baud_detect(&mut self, baud_rate: Option<u32>) -> Result<(), UsartError> {
+        let capture = self.baud_capture.take().ok_or(UsartError::NotInitialized)?;
+        let mut timer = RxTimeout::from_capture(capture);
+        if self.paused_rx_timeout > 0 {
+            timer.configure(self.baud_rate, self.paused_rx_timeout);
+        }
+        self.rx_timeout = Some(timer);
+
+        if let Some(baud_rate) = baud_rate {
+            self.set_baud_rate(baud_rate)?;
+        }
+        self.skip_rx()
+    }
+
+    /// Switches to RS-485 half-duplex operation
+    ///
+    /// # Arguments
+    /// * `de` - Driver enable output of the transceiver
+    pub fn attach_rs485(&mut self, de: DriverEnable) {
+        self.rs485 = Some(de);
+    }
+
+    /// Polarity and turnaround delays of the driver enable
+    ///
+    /// # Returns
+    /// `None` in full-duplex operation
+    pub fn rs485_config(&self) -> Option<Rs485Config> {
+        self.rs485.as_ref().map(DriverEnable::config)
+    }
+
+    /// Changes polarity and turnaround delays of the driver enable
+    ///
+    /// # Errors
+    /// - `UsartError::NotInitialized` if no driver enable is attached
+    /// - `UsartError::TransferError` while a transfer or break holds the driver
+    pub fn set_rs485_config(&mut self, config: Rs485Config) -> Result<(), UsartError> {
+        if self.is_tx_busy() {
+            return Err(UsartError::TransferError);
+        }
+        let de = self.rs485.as_mut().ok_or(UsartError::NotInitialized)?;
+        de.set_config(config);
+        self.regs.set_tc_interrupt(false);
+        Ok(())
+    }
+
+    /// Handles the transmission complete interrupt in RS-485 mode
+    ///
+    /// Releases the driver once the TX stream is idle and the last stop bit
+    /// is out. A completion flagged while the stream still runs (DMA late
+    /// with the next byte) is cleared and waited for again.
+    ///
+    /// # Returns
+    /// `true` if the driver was released
+    pub fn poll_rs485(&mut self) -> bool {
+        let held = self.break_held || self.is_dma_tx_running();
+        let Some(de) = self.rs485.as_mut() else {
+            return false;
+        };
+        if !de.is_asserted() || !self.regs.is_transmission_complete() {
+            return false;
+        }
+        if held {
+            self.regs.clear_tc();
+            return false;
+        }
+        self.regs.set_tc_interrupt(false);
+        de.release(self.baud_rate);
+        true
+    }
+
+    /// Current baud rate in bits per second
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    /// Changes the baud rate at runtime
+    ///
+    /// The USART is briefly disabled while BRR is rewritten; any byte in flight
+    /// is lost, so callers should wait for `is_transmission_complete()` first.
+    /// The RX timeout timer is rescaled to keep its value in bit times.
+    ///
+    /// # Arguments
+    /// * `baud_rate` - New rate in bits per second
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if the rate cannot be generated
+    /// from the peripheral clock (oversampling by 16)
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), UsartError> {
+        if baud_rate == 0 || baud_rate > self.pclk / 16 {
+            return Err(UsartError::NotInitialized);
+        }
+
+        let brr = (self.pclk + baud_rate / 2) / baud_rate;
+        self.regs.set_brr(brr);
+        self.baud_rate = baud_rate;
+
+        if let Some(timer) = self.rx_timeout.as_mut() {
+            let bit_times = timer.bit_times();
+            if bit_times > 0 {
+                timer.configure(baud_rate, bit_times);
+            }
+        }
+
+        #[cfg(feature = "debug")]
+        defmt::info!("{=str} baud rate set to {}", USART::NAME, baud_rate);
+        Ok(())
+    }
+
+    /// Checks whether the current baud rate can be generated at a clock level
+    pub fn fits_clock_level(&self, level: ClockLevel) -> bool {
+        self.baud_rate <= Self::bus_clock(level) / 16
+    }
+
+    /// Takes over the bus clocks of a `clock_scaling` level
+    ///
+    /// Called right after the switch, with the USART interrupts masked: BRR
+    /// and the RX timeout prescaler are recomputed for the unchanged baud
+    /// rate. As with `set_baud_rate`, a byte in flight is lost.
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if the baud rate cannot be
+    /// generated at `level` (see `fits_clock_level`); nothing is changed
+    pub fn set_clock_level(&mut self, level: ClockLevel) -> Result<(), UsartError> {
+        if !self.fits_clock_level(level) {
+            return Err(UsartError::NotInitialized);
+        }
+        self.pclk = Self::bus_clock(level);
+        if let Some(timer) = self.rx_timeout.as_mut() {
+            timer.set_timer_clock(level.timclk1());
+        }
+        self.set_baud_rate(self.baud_rate)
+    }
+
+    /// Peripheral clock of this USART at a clock level (Hz)
+    fn bus_clock(level: ClockLevel) -> u32 {
+        if USART::ON_APB2 {
+            level.pclk2()
+        } else {
+            level.pclk1()
+        }
+    }
+
+    /// Current frame format
+    pub fn uart_config(&self) -> UartConfig {
+        self.uart_config
+    }
+
+    /// Changes data bits, parity and stop bits at runtime
+    ///
+    /// Used for the CDC line coding, the AT channel and targets such as the
+    /// STM32 ROM bootloader that require even parity. As with
+    /// `set_baud_rate`, a byte in flight is lost.
+    ///
+    /// # Arguments
+    /// * `config` - New frame format (8N1 after init)
+    ///
+    /// # Errors
+    /// Returns `UsartError::InvalidConfig` if the USART cannot frame `config`
+    pub fn reconfigure(&mut self, config: UartConfig) -> Result<(), UsartError> {
+        let bits = config.frame_bits()?;
+        self.regs
+            .set_frame(bits.nine_bits, bits.parity, bits.odd, bits.stop);
+        self.uart_config = config;
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "{=str} frame format {}",
+            USART::NAME,
+            defmt::Display2Format(&config)
+        );
+        Ok(())
+    }
+
+    /// Starts DMA transmission
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA TX not configured
+    pub fn start_dma_tx(&mut self) -> Result<(), UsartError> {
+        self.dma_tx
+            .as_mut()
+            .ok_or(UsartError::NotInitialized)?
+            .start(|_| ());
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("DMA TX started");
+        Ok(())
+    }
+
+    /// Starts circular DMA reception
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn start_dma_rx(&mut self) -> Result<(), UsartError> {
+        self.dma_rx
+            .as_mut()
+            .ok_or(UsartError::NotInitialized)?
+            .start(|_| ());
+        self.rx_read_pos = 0;
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("DMA RX started");
+        Ok(())
+    }
+
+    /// Restarts DMA reception with error recovery
+    ///
+    /// Only needed after a transfer error stopped the stream; in normal
+    /// operation circular reception never stops. Bytes not yet read from the
+    /// current half are dropped.
+    ///
+    /// # Flow
+    /// 1. Disable the stream and clear previous transfer errors
+    /// 2. Reload the transfer count of the current half
+    /// 3. Re-enable the stream and resynchronize the read position
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn restart_dma_rx(&mut self) -> Result<(), UsartError> {
+        let dma = self.dma_rx.as_mut().ok_or(UsartError::NotInitialized)?;
+        // SAFETY: Stream register access through the HAL; the stream is
+        // disabled before its transfer count is rewritten
+        unsafe {
+            dma.stream().disable();
+            dma.clear_transfer_error();
+            dma.stream().set_number_of_transfers(DMA_BUFFER_LEN as u16);
+        }
+        dma.start(|_| {});
+        self.rx_read_pos = self.dma_rx_position()?;
+        Ok(())
+    }
+
+    /// Restarts DMA transmission with error recovery
+    ///
+    /// # Flow
+    /// 1. Clear previous transfer errors
+    /// 2. Reinitialize DMA transfer
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA TX not configured
+    pub fn restart_dma_tx(&mut self) -> Result<(), UsartError> {
+        let dma = self.dma_tx.as_mut().ok_or(UsartError::NotInitialized)?;
+        dma.clear_transfer_error();
+        dma.start(|_| {});
+        Ok(())
+    }
+
+    /// Enables or disables the per-byte RX interrupt
+    ///
+    /// Used while DMA reception is not running (UART strap window).
+    pub fn set_rx_interrupt(&mut self, enabled: bool) {
+        self.regs.set_rx_interrupt(enabled);
+    }
+
+    /// Reads one received byte outside DMA operation
+    ///
+    /// Reading DR also clears the IDLE and overrun flags.
+    ///
+    /// # Returns
+    /// `Some(byte)` if RXNE was set, `None` otherwise
+    pub fn read_byte(&mut self) -> Option<u8> {
+        let has_data = self.regs.is_rx_not_empty();
+        let data = self.regs.read_dr() as u8 & self.uart_config.data_mask();
+        has_data.then_some(data)
+    }
+
+    /// Takes the USART off the line
+    ///
+    /// Disables the peripheral and masks its USART and DMA interrupts so a
+    /// faulty external connection cannot keep raising errors.
+    pub fn suspend(&mut self) {
+        cortex_m::peripheral::NVIC::mask(USART::IRQ);
+        for irq in USART::DMA_IRQS {
+            cortex_m::peripheral::NVIC::mask(irq);
+        }
+        self.regs.set_enabled(false);
+
+        #[cfg(feature = "debug")]
+        defmt::warn!("{=str} suspended", USART::NAME);
+    }
+
+    /// Brings the USART back after `suspend`
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn resume(&mut self) -> Result<(), UsartError> {
+        self.clear_errors();
+        self.regs.set_enabled(true);
+        self.restart_dma_rx()?;
+
+        // SAFETY: Re-enables the handlers masked by `suspend`
+        unsafe {
+            cortex_m::peripheral::NVIC::unmask(USART::IRQ);
+            for irq in USART::DMA_IRQS {
+                cortex_m::peripheral::NVIC::unmask(irq);
+            }
+        }
+
+        #[cfg(feature = "debug")]
+        defmt::info!("{=str} resumed", USART::NAME);
+        Ok(())
+    }
+
+    /// Initiates DMA write transfer of the first `len` bytes of the TX buffer
+    ///
+    /// # Example
+    /// ```rust
+    /// usart.get_tx_buffer_slice(4).unwrap().copy_from_slice(b"ping");
+    /// usart.write_dma(4)?;
+    /// ```
+    ///
+    /// # Errors
+    /// - `UsartError::NotInitialized` if DMA TX not configured
+    /// - `UsartError::TransferError` while the previous transfer is running
+    pub fn write_dma(&mut self, len: usize) -> Result<(), UsartError> {
+        let len = len.min(self.tx_buffer.len());
+        let address = self.tx_buffer.as_ptr() as u32;
+        self.start_tx(address, len)?;
+
+        #[cfg(feature = "debug")]
+        defmt::trace!("DMA write started");
+        Ok(())
+    }
+
+    /// Transmits caller memory without copying it into the TX buffer
+    ///
+    /// Bridged data goes this way, straight from a staging buffer or a read
+    /// grant of the TX queue; the caller frees the memory once the stream is
+    /// idle again.
+    ///
+    /// # Safety
+    /// `data` must stay valid and unmodified until `is_tx_busy()` returns
+    /// `false`; the DMA reads it in the background.
+    ///
+    /// # Errors
+    /// Same as `write_dma`
+    pub unsafe fn transmit_external(&mut self, data: &[u8]) -> Result<(), UsartError> {
+        if data.len() > u16::MAX as usize {
+            return Err(UsartError::BufferOverflow);
+        }
+        self.start_tx(data.as_ptr() as u32, data.len())
+    }
+
+    /// Checks whether the transmitter is taken
+    ///
+    /// `true` while a DMA TX transfer runs or a break holds the line.
+    pub fn is_tx_busy(&self) -> bool {
+        self.break_held || self.is_dma_tx_running()
+    }
+
+    /// Checks whether a DMA TX transfer runs
+    ///
+    /// Unlike `is_tx_busy`, a held break does not count.
+    pub fn is_dma_tx_running(&self) -> bool {
+        self.dma_tx.as_ref().is_some_and(|dma| !dma.is_idle())
+    }
+
+    /// Starts a break condition on the TX line
+    ///
+    /// The transmitter is held for the break right away, so no new transfer
+    /// starts; the line only goes low once the running transfer and the
+    /// frame in the shift register are out. Call again until it returns
+    /// `true`, or `end_break` to give up.
+    ///
+    /// # Returns
+    /// `true` once the TX pin is held low
+    pub fn start_break(&mut self) -> bool {
+        self.break_held = true;
+        if self.is_dma_tx_running() || !self.regs.is_transmission_complete() {
+            return false;
+        }
+        if let Some(de) = self.rs485.as_mut() {
+            de.assert(self.baud_rate);
+        }
+        self.tx_pin.hold_low();
+        true
+    }
+
+    /// Ends a break condition and returns the TX pin to the USART
+    ///
+    /// Does nothing without a break.
+    pub fn end_break(&mut self) {
+        if !core::mem::take(&mut self.break_held) {
+            return;
+        }
+        self.tx_pin.release();
+        if let Some(de) = self.rs485.as_mut() {
+            self.regs.set_tc_interrupt(false);
+            de.release(self.baud_rate);
+        }
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("{=str} break ended", USART::NAME);
+    }
+
+    // Points the idle TX stream at `len` bytes from `address` and starts it
+    fn start_tx(&mut self, address: u32, len: usize) -> Result<(), UsartError> {
+        if self.is_tx_busy() {
+            return Err(UsartError::TransferError);
+        }
+        // Stale flags keep the stream from being re-enabled
+        self.clear_dma_tx_complete_flag();
+
+        let dma = self.dma_tx.as_mut().ok_or(UsartError::NotInitialized)?;
+        dma.clear_transfer_error();
+        // SAFETY: The stream is disabled (checked above) while its memory
+        // address and transfer count are rewritten
+        unsafe {
+            let stream = dma.stream();
+            stream.set_memory_address(address);
+            stream.set_number_of_transfers(len as u16);
+        }
+        if let Some(de) = self.rs485.as_mut() {
+            // The driver is on the bus before the first start bit; TC is
+            // still set from the previous transfer and would end it at once
+            de.assert(self.baud_rate);
+            self.regs.clear_tc();
+            self.regs.set_tc_interrupt(true);
+        }
+        // The memory writes must land before the DMA reads them
+        compiler_fence(Ordering::Release);
+        dma.start(|_| {});
+        Ok(())
+    }
+
+    /// Copies the bytes received since the previous call out of the RX ring
+    ///
+    /// The DMA keeps running; the half-transfer and transfer-complete
+    /// interrupts guarantee a call at least every `DMA_BUFFER_LEN / 2` bytes,
+    /// well before unread data could be overwritten.
+    ///
+    /// # Arguments
+    /// * `out` - Destination; bytes that do not fit stay for the next call
+    ///
+    /// # Returns
+    /// Number of bytes copied
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn read_dma_rx(&mut self, out: &mut [u8]) -> Result<usize, UsartError> {
+        let write_pos = self.dma_rx_position()?;
+        // Bytes behind the write position must be read from memory, not cached
+        compiler_fence(Ordering::Acquire);
+
+        let start = self.rx_read_pos;
+        let available = (write_pos + DMA_RX_LEN - start) % DMA_RX_LEN;
+        let count = available.min(out.len());
+
+        let first = count.min(DMA_RX_LEN - start);
+        out[..first].copy_from_slice(&self.rx_buffer[start..start + first]);
+        out[first..count].copy_from_slice(&self.rx_buffer[..count - first]);
+        self.rx_read_pos = (start + count) % DMA_RX_LEN;
+
+        // With 7 data bits the parity bit arrives in bit 7
+        let mask = self.uart_config.data_mask();
+        if mask != u8::MAX {
+            out[..count].iter_mut().for_each(|byte| *byte &= mask);
+        }
+
+        // Mostly called from the UART handlers, where RTT is not written
+        #[cfg(feature = "isr-direct-log")]
+        defmt::trace!("DMA RX read {} bytes", count);
+        Ok(count)
+    }
+
+    /// Number of received bytes not yet taken by `read_dma_rx`
+    ///
+    /// Unlike the raw NDTR (`get_dma_rx_length`), this stays unambiguous
+    /// across the two halves of the RX ring.
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn unread_rx_len(&mut self) -> Result<usize, UsartError> {
+        let write_pos = self.dma_rx_position()?;
+        Ok((write_pos + DMA_RX_LEN - self.rx_read_pos) % DMA_RX_LEN)
+    }
+
+    /// Drops the bytes received since the previous read
+    ///
+    /// Used while the bridge is routed to another UART: the read position
+    /// follows the DMA and the interrupt flags are cleared, so no stale data
+    /// is forwarded once the route returns.
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn skip_rx(&mut self) -> Result<(), UsartError> {
+        self.clear_dma_rx_complete_flag();
+        self.clear_usart_flags(UsartFlag::IDLE);
+        self.rx_read_pos = self.dma_rx_position()?;
+        Ok(())
+    }
+
+    /// Offset in the RX ring of the next byte the DMA will write
+    ///
+    /// Combines the current target (which half) with NDTR (how far into it),
+    /// re-reading if the DMA switched halves in between.
+    fn dma_rx_position(&mut self) -> Result<usize, UsartError> {
+        let dma = self.dma_rx.as_mut().ok_or(UsartError::NotInitialized)?;
+        // SAFETY: Read-only access to the stream's CR.CT and NDTR registers
+        let stream = unsafe { dma.stream() };
+
+        loop {
+            let second = !matches!(stream.current_buffer(), CurrentBuffer::FirstBuffer);
+            let remaining = stream.number_of_transfers() as usize;
+            if second == !matches!(stream.current_buffer(), CurrentBuffer::FirstBuffer) {
+                let half = if second { DMA_BUFFER_LEN } else { 0 };
+                return Ok((half + DMA_BUFFER_LEN - remaining) % DMA_RX_LEN);
+            }
+        }
+    }
+
+    /// Checks for DMA RX transfer errors
+    ///
+    /// The caller restarts the stream (`restart_dma_rx`) and logs the
+    /// error; in the UART handlers, through `isr_log!`.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if an error was detected
+    /// - `Ok(false)` if no errors present
+    /// - `Err(UsartError)` if initialization check fails
+    pub fn check_dma_rx_error(&mut self) -> Result<bool, UsartError> {
+        let has_error = self
+            .dma_rx
+            .as_ref()
+            .ok_or(UsartError::NotInitialized)?
+            .is_transfer_error();
+        Ok(has_error)
+    }
+
+    /// Checks for DMA TX transfer errors
+    ///
+    /// The caller restarts the stream (`restart_dma_tx`) and logs the
+    /// error; in the UART handlers, through `isr_log!`.
+    ///
+    /// # Returns
+    /// - `Ok(true)` if an error was detected
+    /// - `Ok(false)` if no errors present
+    /// - `Err(UsartError)` if initialization check fails
+    pub fn check_dma_tx_error(&mut self) -> Result<bool, UsartError> {
+        let has_error = self
+            .dma_tx
+            .as_ref()
+            .ok_or(UsartError::NotInitialized)?
+            .is_transfer_error();
+        Ok(has_error)
+    }
+
+    /// Checks DMA TX completion status
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA TX not configured
+    pub fn is_dma_tx_complete(&self) -> Result<bool, UsartError> {
+        self.dma_tx
+            .as_ref()
+            .ok_or(UsartError::NotInitialized)
+            .map(|dma| dma.is_transfer_complete())
+    }
+
+    /// Checks DMA RX completion status
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn is_dma_rx_complete(&self) -> Result<bool, UsartError> {
+        self.dma_rx
+            .as_ref()
+            .ok_or(UsartError::NotInitialized)
+            .map(|dma| dma.is_transfer_complete())
+    }
+
+    /// Gets mutable slice of TX buffer
+    ///
+    /// # Parameters
+    /// - `length`: Maximum bytes to return (clamped to buffer size)
+    ///
+    /// # Returns
+    /// `Some(&mut [u8])` if buffer initialized, `None` otherwise
+    pub fn get_tx_buffer_slice(&mut self, length: usize) -> Option<&mut [u8]> {
+        if self.tx_buffer.is_empty() {
+            None
+        } else {
+            let len = length.min(self.tx_buffer.len());
+            Some(&mut self.tx_buffer[..len])
+        }
+    }
+
+    /// Clears all DMA error flags
+    pub fn clear_errors(&mut self) {
+        if let Some(dma_rx) = &mut self.dma_rx {
+            dma_rx.clear_transfer_error();
+        }
+        if let Some(dma_tx) = &mut self.dma_tx {
+            dma_tx.clear_transfer_error();
+        }
+    }
+
+    /// Clears DMA TX complete flag
+    pub fn clear_dma_tx_complete_flag(&mut self) {
+        if let Some(dma_tx) = &mut self.dma_tx {
+            dma_tx.clear_flags(DmaFlag::FifoError | DmaFlag::TransferComplete);
+        }
+    }
+
+    /// Clears DMA RX half-transfer and transfer complete flags
+    pub fn clear_dma_rx_complete_flag(&mut self) {
+        if let Some(dma_rx) = &mut self.dma_rx {
+            dma_rx.clear_flags(
+                DmaFlag::FifoError | DmaFlag::HalfTransfer | DmaFlag::TransferComplete,
+            );
+        }
+    }
+
+    /// Checks if USART RX buffer is not empty
+    pub fn is_rx_not_empty(&self) -> bool {
+        self.regs.is_rx_not_empty()
+    }
+
+    /// Checks if USART TX buffer is empty
+    pub fn is_tx_empty(&self) -> bool {
+        self.regs.is_tx_empty()
+    }
+
+    /// Checks if transmission is complete
+    pub fn is_transmission_complete(&self) -> bool {
+        self.regs.is_transmission_complete()
+    }
+
+    /// Clears specified USART flags using proper clear sequences
+    ///
+    /// # Parameters
+    /// - `flags`: Combination of UsartFlag bits to clear
+    pub fn clear_usart_flags(&self, flags: UsartFlag) {
+        let sr = UsartFlag::from_bits_truncate(self.regs.status());
+
+        if flags.contains(UsartFlag::RXNE) && sr.contains(UsartFlag::RXNE) {
+            let _ = self.regs.read_dr();
+        }
+
+        // SR read above, DR read completes the sequence; with DMA reading DR,
+        // RXNE is rarely set when the line goes idle
+        if flags.contains(UsartFlag::IDLE) && sr.contains(UsartFlag::IDLE) {
+            let _ = self.regs.read_dr();
+        }
+
+        if flags.contains(UsartFlag::TXE) && sr.contains(UsartFlag::TXE) {
+            self.regs.write_dr(0);
+        }
+
+        if flags.contains(UsartFlag::TC) && sr.contains(UsartFlag::TC) {
+            self.regs.write_dr(0);
+        }
+
+        // Mostly called from the UART handlers, where RTT is not written
+        #[cfg(feature = "isr-direct-log")]
+        defmt::trace!("Cleared USART flags: {:?}", flags);
+    }
+
+    /// Takes the receive errors seen since the previous call
+    ///
+    /// Errors are latched on every status read, so the ones cleared by an
+    /// idle-line or RXNE clear sequence are still reported. Flags still set
+    /// are cleared without taking a byte from the DMA.
+    pub fn take_line_errors(&mut self) -> LineError {
+        LineError::from_bits_truncate(self.regs.take_line_errors())
+    }
+
+    /// Checks DMA RX idle state
+    ///
+    /// With circular reception the stream is only idle before it is started
+    /// or after a transfer error stopped it.
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn is_dma_rx_is_idle(&self) -> Result<bool, UsartError> {
+        self.dma_rx
+            .as_ref()
+            .ok_or(UsartError::NotInitialized)
+            .map(|dma| dma.is_idle())
+    }
+
+    /// Stops ongoing transfers and cleans up resources
+    ///
+    /// Lets a running TX transfer finish for up to `UART_STOP_TIMEOUT_MS`;
+    /// a stream still busy after that is disabled.
+    ///
+    /// # Errors
+    /// Returns `UsartError::Timeout` if the TX stream had to be disabled
+    pub fn stop_transfer(&mut self) -> Result<(), UsartError> {
+        self.clear_errors();
+        let drained = busy_wait_until(UART_STOP_TIMEOUT_MS, || !self.is_dma_tx_running());
+        if drained.is_err() {
+            if let Some(dma) = self.dma_tx.as_mut() {
+                // SAFETY: Disabling the stream only ends the wedged transfer
+                unsafe { dma.stream().disable() };
+            }
+
+            #[cfg(feature = "debug")]
+            defmt::warn!("{=str} DMA TX wedged, stream stopped", USART::NAME);
+        }
+        drained.map_err(UsartError::from)
+    }
+
+    /// Gets available data size in TX buffer
+    pub fn available_data(&mut self) -> usize {
+        self.is_dma_tx_complete()
+            .map(|complete| if complete { DMA_BUFFER_LEN } else { 0 })
+            .unwrap_or(0)
+    }
+
+    /// Gets current number of transfers configured in DMA RX stream
+    ///
+    /// # Example
+    /// ```rust
+    /// let length = usart.get_dma_rx_length()?;
+    /// defmt::info!("DMA RX transfers: {}", length);
+    /// ```
+    ///
+    /// # Errors
+    /// Returns `UsartError::NotInitialized` if DMA RX not configured
+    pub fn get_dma_rx_length(&mut self) -> Result<usize, UsartError> {
+        let dma = self.dma_rx.as_mut().ok_or(UsartError::NotInitialized)?;
+
+        // SAFETY: Direct register access wrapped in HAL methods
+        let transfers = unsafe { dma.stream().number_of_transfers() };
+
+        #[cfg(feature = "debug")]
+        defmt::trace!("DMA RX length: {}", transfers);
+
+        Ok(transfers as usize)
+    }
+}
+
+impl<USART, TXS, RXS, const CH: u8> BridgeUart for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    fn baud_rate(&self) -> u32 {
+        self.baud_rate()
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), UsartError> {
+        self.set_baud_rate(baud_rate)
+    }
+
+    fn uart_config(&self) -> UartConfig {
+        self.uart_config()
+    }
+
+    fn reconfigure(&mut self, config: UartConfig) -> Result<(), UsartError> {
+        self.reconfigure(config)
+    }
+
+    fn is_transmission_complete(&self) -> bool {
+        self.is_transmission_complete()
+    }
+
+    fn start_break(&mut self) -> bool {
+        self.start_break()
+    }
+
+    fn end_break(&mut self) {
+        self.end_break()
+    }
+}
+
+impl<USART, TXS, RXS, const CH: u8> SerialLink for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    fn is_tx_busy(&self) -> bool {
+        self.is_tx_busy()
+    }
+
+    unsafe fn transmit_external(&mut self, data: &[u8]) -> Result<(), UsartError> {
+        self.transmit_external(data)
+    }
+
+    fn get_tx_buffer_slice(&mut self, length: usize) -> Option<&mut [u8]> {
+        self.get_tx_buffer_slice(length)
+    }
+
+    fn write_dma(&mut self, len: usize) -> Result<(), UsartError> {
+        self.write_dma(len)
+    }
+
+    fn read_dma_rx(&mut self, out: &mut [u8]) -> Result<usize, UsartError> {
+        self.read_dma_rx(out)
+    }
+
+    fn skip_rx(&mut self) -> Result<(), UsartError> {
+        self.skip_rx()
+    }
+
+    fn unread_rx_len(&mut self) -> Result<usize, UsartError> {
+        self.unread_rx_len()
+    }
+
+    fn clear_dma_tx_complete_flag(&mut self) {
+        self.clear_dma_tx_complete_flag()
+    }
+
+    fn clear_dma_rx_complete_flag(&mut self) {
+        self.clear_dma_rx_complete_flag()
+    }
+
+    fn clear_usart_flags(&self, flags: UsartFlag) {
+        self.clear_usart_flags(flags)
+    }
+
+    fn take_line_errors(&mut self) -> LineError {
+        self.take_line_errors()
+    }
+
+    fn clear_errors(&mut self) {
+        self.clear_errors()
+    }
+
+    fn check_dma_rx_error(&mut self) -> Result<bool, UsartError> {
+        self.check_dma_rx_error()
+    }
+
+    fn check_dma_tx_error(&mut self) -> Result<bool, UsartError> {
+        self.check_dma_tx_error()
+    }
+
+    fn restart_dma_rx(&mut self) -> Result<(), UsartError> {
+        self.restart_dma_rx()
+    }
+
+    fn restart_dma_tx(&mut self) -> Result<(), UsartError> {
+        self.restart_dma_tx()
+    }
+}
+
+/// Blocking byte streams for drivers layered on the controller (GNSS
+/// parsers, modem crates); they share the DMA streams with the bridge, so
+/// only one of the two may use a controller
+impl<USART, TXS, RXS, const CH: u8> embedded_io::ErrorType for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    type Error = UsartError;
+}
+
+impl<USART, TXS, RXS, const CH: u8> embedded_io::Read for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    /// Blocks until data arrives, then copies what `buf` takes
+    ///
+    /// # Errors
+    /// - The class of receive errors seen since the previous read or check
+    /// - `UsartError::DmaError` if the RX stream stopped on an error
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, UsartError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(error) = self.take_line_errors().classify() {
+                return Err(error);
+            }
+            let count = self.read_dma_rx(buf)?;
+            if count > 0 {
+                return Ok(count);
+            }
+            if self.check_dma_rx_error()? {
+                return Err(UsartError::DmaError);
+            }
+        }
+    }
+}
+
+impl<USART, TXS, RXS, const CH: u8> embedded_io::ReadReady for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    fn read_ready(&mut self) -> Result<bool, UsartError> {
+        Ok(self.unread_rx_len()? > 0)
+    }
+}
+
+impl<USART, TXS, RXS, const CH: u8> embedded_io::Write for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    /// Waits for the running transfer, then starts one of up to
+    /// `DMA_BUFFER_LEN` bytes from the TX buffer
+    ///
+    /// # Errors
+    /// `UsartError::Timeout` if the transmitter stays taken for
+    /// `UART_STOP_TIMEOUT_MS`, e.g. by a break
+    fn write(&mut self, buf: &[u8]) -> Result<usize, UsartError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        busy_wait_until(UART_STOP_TIMEOUT_MS, || !self.is_tx_busy())?;
+
+        let len = buf.len().min(self.tx_buffer.len());
+        self.tx_buffer[..len].copy_from_slice(&buf[..len]);
+        self.write_dma(len)?;
+        Ok(len)
+    }
+
+    /// Waits until the last stop bit left the shift register
+    fn flush(&mut self) -> Result<(), UsartError> {
+        busy_wait_until(UART_STOP_TIMEOUT_MS, || {
+            !self.is_tx_busy() && self.is_transmission_complete()
+        })?;
+        Ok(())
+    }
+}
+
+impl<USART, TXS, RXS, const CH: u8> embedded_io::WriteReady for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    fn write_ready(&mut self) -> Result<bool, UsartError> {
+        Ok(!self.is_tx_busy())
+    }
+}
+
+/// Non-blocking byte-wise access, the `embedded_hal::serial` traits of
+/// embedded-hal 1.0
+impl<USART, TXS, RXS, const CH: u8> embedded_hal_nb::serial::ErrorType for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    type Error = UsartError;
+}
+
+impl<USART, TXS, RXS, const CH: u8> embedded_hal_nb::serial::Read<u8> for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    fn read(&mut self) -> nb::Result<u8, UsartError> {
+        if let Some(error) = self.take_line_errors().classify() {
+            return Err(nb::Error::Other(error));
+        }
+        let mut byte = [0u8];
+        match self.read_dma_rx(&mut byte)? {
+            0 => Err(nb::Error::WouldBlock),
+            _ => Ok(byte[0]),
+        }
+    }
+}
+
+impl<USART, TXS, RXS, const CH: u8> embedded_hal_nb::serial::Write<u8> for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    /// Sends the byte as a DMA transfer of its own
+    fn write(&mut self, word: u8) -> nb::Result<(), UsartError> {
+        if self.is_tx_busy() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.tx_buffer[0] = word;
+        self.write_dma(1)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), UsartError> {
+        if self.is_tx_busy() || !self.is_transmission_complete() {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(())
+    }
+}
+
+/// Automatic cleanup implementation
+impl<USART, TXS, RXS, const CH: u8> Drop for UartController<USART, TXS, RXS, CH>
+where
+    USART: UartInstance,
+    TXS: Stream + StreamISR,
+    RXS: Stream + StreamISR,
+    ChannelX<CH>: Channel,
+    Tx<USART>: PeriAddress<MemSize = u8> + DMASet<TXS, CH, MemoryToPeripheral>,
+    Rx<USART>: PeriAddress<MemSize = u8> + DMASet<RXS, CH, PeripheralToMemory>,
+{
+    fn drop(&mut self) {
+        self.clear_errors();
+        #[cfg(feature = "debug")]
+        defmt::info!("{=str} controller released", USART::NAME);
+    }
+}
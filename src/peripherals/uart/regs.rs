@@ -0,0 +1,246 @@
+//! # USART Register Access
+//!
+//! The HAL moves the USART peripheral into its `Tx`/`Rx` halves, which are in
+//! turn owned by the DMA transfer objects, so the controller keeps no safe path
+//! to the status and control registers. This module is the single audited place
+//! where the register block is aliased.
+//!
+//! ## Invariants
+//! 1. A `UsartRegs` handle is created exactly once per USART, by the controller's
+//!    `init`, after the HAL has finished configuring the peripheral.
+//! 2. The handle is neither `Clone` nor `Copy` and lives inside its controller,
+//!    so every access is serialized by the RTIC lock protecting that controller.
+//! 3. Only registers the HAL no longer touches after init are accessed: SR/DR for
+//!    flag checks and clear sequences, BRR, the CR1 bits UE/M/PCE/PS/TXEIE/TCIE/RXNEIE
+//!    and the CR2 STOP field.
+//! 4. Every SR read latches the receive error bits (PE/FE/NE/ORE): an SR read
+//!    followed by any DR read, the DMA's included, clears them in hardware, so
+//!    without the latch the idle-line clear sequence would drop them unseen.
+//! 5. The TX pin mode is only switched by `TxPinRegs`, whose handle follows
+//!    invariants 1 and 2; the MODER read-modify-write runs in a critical
+//!    section since other pins of the port share the register.
+
+use core::cell::Cell;
+use core::ptr::{read_volatile, write_volatile};
+
+use stm32f4xx_hal::pac::usart1::RegisterBlock;
+
+/// CR2 STOP field (bits 13:12)
+const CR2_STOP_SHIFT: u32 = 12;
+const CR2_STOP_MASK: u32 = 0b11 << CR2_STOP_SHIFT;
+
+/// SR receive error bits: PE, FE, NE, ORE
+const SR_ERRORS: u32 = 0b1111;
+/// SR status bits
+const SR_RXNE: u32 = 1 << 5;
+const SR_TC: u32 = 1 << 6;
+const SR_TXE: u32 = 1 << 7;
+
+/// GPIO register offsets, in 32-bit words
+const GPIO_MODER: usize = 0;
+const GPIO_BSRR: usize = 6;
+/// GPIO MODER values of the TX pin
+const MODE_OUTPUT: u32 = 0b01;
+const MODE_ALTERNATE: u32 = 0b10;
+
+/// Exclusive handle to one USART register block
+pub struct UsartRegs {
+    regs: *const RegisterBlock,
+    /// Receive error bits seen since the last `take_line_errors`
+    line_errors: Cell<u32>,
+}
+
+// SAFETY: The handle is only used through its owning controller, which RTIC
+// moves between contexts as a whole and protects with a lock (invariant 2).
+unsafe impl Send for UsartRegs {}
+
+impl UsartRegs {
+    /// Creates the handle for a USART register block
+    ///
+    /// # Safety
+    /// - `regs` must point to a USART register block (e.g. `USART6::ptr()`)
+    /// - At most one handle may exist per USART (invariant 1)
+    pub unsafe fn new(regs: *const RegisterBlock) -> Self {
+        Self {
+            regs,
+            line_errors: Cell::new(0),
+        }
+    }
+
+    #[inline]
+    fn block(&self) -> &RegisterBlock {
+        // SAFETY: Valid pointer per `new` contract; the block is memory-mapped
+        // for the whole program lifetime
+        unsafe { &*self.regs }
+    }
+
+    /// Reads SR, latching its receive error bits (invariant 4)
+    #[inline]
+    fn sr(&self) -> u32 {
+        let sr = self.block().sr().read().bits();
+        self.line_errors
+            .set(self.line_errors.get() | (sr & SR_ERRORS));
+        sr
+    }
+
+    /// Receive data register not empty
+    pub fn is_rx_not_empty(&self) -> bool {
+        self.sr() & SR_RXNE != 0
+    }
+
+    /// Transmit data register empty
+    pub fn is_tx_empty(&self) -> bool {
+        self.sr() & SR_TXE != 0
+    }
+
+    /// Transmission complete
+    pub fn is_transmission_complete(&self) -> bool {
+        self.sr() & SR_TC != 0
+    }
+
+    /// Reads the data register (completes the RXNE/error-flag clear sequence)
+    pub fn read_dr(&self) -> u16 {
+        self.block().dr().read().bits() as u16
+    }
+
+    /// Writes the data register
+    pub fn write_dr(&self, value: u16) {
+        self.block().dr().write(|w| unsafe { w.bits(value as u32) });
+    }
+
+    /// Raw status register value
+    pub fn status(&self) -> u32 {
+        self.sr()
+    }
+
+    /// Takes the receive error bits seen since the previous call
+    ///
+    /// Bits still set in SR are cleared: by a DR read while no byte waits,
+    /// otherwise by the DMA's read of the waiting byte, which completes the
+    /// sequence started by the SR read here. A byte arriving between the two
+    /// reads of the first case is lost; a full frame time makes that rare.
+    ///
+    /// # Returns
+    /// SR bit positions: PE (0), FE (1), NE (2), ORE (3)
+    pub fn take_line_errors(&self) -> u32 {
+        let sr = self.sr();
+        if sr & SR_ERRORS != 0 && sr & SR_RXNE == 0 {
+            let _ = self.read_dr();
+        }
+        self.line_errors.replace(0)
+    }
+
+    /// Masks the TXE and TC interrupts (DMA drives the transmitter)
+    pub fn disable_tx_interrupts(&self) {
+        self.block()
+            .cr1()
+            .modify(|_, w| w.txeie().clear_bit().tcie().clear_bit());
+    }
+
+    /// Enables or disables the transmission complete interrupt (CR1.TCIE)
+    pub fn set_tc_interrupt(&self, enabled: bool) {
+        self.block().cr1().modify(|_, w| w.tcie().bit(enabled));
+    }
+
+    /// Clears the transmission complete flag
+    ///
+    /// SR bits are cleared by writing 0; the 1s leave the others as they are.
+    pub fn clear_tc(&self) {
+        self.block().sr().write(|w| unsafe { w.bits(!SR_TC) });
+    }
+
+    /// Enables or disables the RXNE interrupt (CR1.RXNEIE)
+    pub fn set_rx_interrupt(&self, enabled: bool) {
+        self.block().cr1().modify(|_, w| w.rxneie().bit(enabled));
+    }
+
+    /// Enables or disables the USART (CR1.UE)
+    pub fn set_enabled(&self, enabled: bool) {
+        self.block().cr1().modify(|_, w| w.ue().bit(enabled));
+    }
+
+    /// Rewrites the baud rate divider with the USART briefly disabled
+    pub fn set_brr(&self, brr: u32) {
+        let usart = self.block();
+        usart.cr1().modify(|_, w| w.ue().clear_bit());
+        usart.brr().write(|w| unsafe { w.bits(brr) });
+        usart.cr1().modify(|_, w| w.ue().set_bit());
+    }
+
+    /// Rewrites the frame format with the USART briefly disabled
+    ///
+    /// # Arguments
+    /// * `nine_bits` - 9-bit frame (CR1.M), parity included
+    /// * `parity` - Parity control (CR1.PCE)
+    /// * `odd` - Odd instead of even parity (CR1.PS)
+    /// * `stop` - CR2.STOP field value
+    pub fn set_frame(&self, nine_bits: bool, parity: bool, odd: bool, stop: u8) {
+        let usart = self.block();
+        usart.cr1().modify(|_, w| w.ue().clear_bit());
+        usart
+            .cr1()
+            .modify(|_, w| w.m().bit(nine_bits).pce().bit(parity).ps().bit(odd));
+        usart.cr2().modify(|r, w| unsafe {
+            w.bits(
+                (r.bits() & !CR2_STOP_MASK) | (((stop as u32) << CR2_STOP_SHIFT) & CR2_STOP_MASK),
+            )
+        });
+        usart.cr1().modify(|_, w| w.ue().set_bit());
+    }
+}
+
+/// Exclusive handle to the mode of one USART TX pin
+///
+/// Takes the pin from the USART as a GPIO output driven low, the break
+/// condition, and hands it back. The alternate function selection (AFR) is
+/// left untouched.
+pub struct TxPinRegs {
+    port: *const u32,
+    pin: u8,
+}
+
+// SAFETY: Same ownership as `UsartRegs` (invariant 4)
+unsafe impl Send for TxPinRegs {}
+
+impl TxPinRegs {
+    /// Creates the handle for a TX pin
+    ///
+    /// # Safety
+    /// - `port` must point to a GPIO register block, `pin` be below 16
+    /// - The pin must be the TX pin of the USART owning the handle
+    /// - At most one handle may exist per pin (invariant 4)
+    pub unsafe fn new(port: *const u32, pin: u8) -> Self {
+        Self { port, pin }
+    }
+
+    /// Drives the pin low as a GPIO output
+    pub fn hold_low(&self) {
+        // SAFETY: BSRR is write-only and only affects this pin's bit; the
+        // output is low before the pin leaves the USART
+        unsafe {
+            write_volatile(
+                self.port.add(GPIO_BSRR) as *mut u32,
+                1 << (16 + u32::from(self.pin)),
+            );
+        }
+        self.set_mode(MODE_OUTPUT);
+    }
+
+    /// Returns the pin to the USART
+    pub fn release(&self) {
+        self.set_mode(MODE_ALTERNATE);
+    }
+
+    fn set_mode(&self, mode: u32) {
+        let shift = 2 * u32::from(self.pin);
+        cortex_m::interrupt::free(|_| {
+            // SAFETY: Valid register block per `new` contract; the critical
+            // section keeps the read-modify-write from racing other pins
+            unsafe {
+                let moder = self.port.add(GPIO_MODER) as *mut u32;
+                let value = read_volatile(moder);
+                write_volatile(moder, (value & !(0b11 << shift)) | (mode << shift));
+            }
+        });
+    }
+}
@@ -0,0 +1,201 @@
+//! # UART Frame Format
+//!
+//! Data bits, parity and stop bits of USART6, changeable at runtime from the
+//! CDC line coding or the AT command channel.
+//!
+//! The USART frames 8 or 9 bits (CR1.M) and, with parity, uses the last one
+//! as parity bit. Supported formats are therefore 7 or 8 data bits with
+//! parity and 8 data bits without; 9 data bits do not fit the byte-wide DMA.
+//!
+//! Formats are written as in terminal programs: `8N1`, `7E1`, `8O2`,
+//! `8N1.5` or `8N0.5`.
+
+use crate::errors::errors::UsartError;
+use crate::peripherals::cdc_acm::LineCoding;
+use core::fmt;
+
+/// Parity bit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// Data bits per character
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataBits {
+    Seven,
+    Eight,
+}
+
+/// Stop bits (CR2.STOP)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopBits {
+    One,
+    Half,
+    Two,
+    OneAndHalf,
+}
+
+/// Frame format of the UART link
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UartConfig {
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+/// Register encoding of a frame format
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct FrameBits {
+    /// CR1.M: 9-bit frame
+    pub nine_bits: bool,
+    /// CR1.PCE: parity enabled
+    pub parity: bool,
+    /// CR1.PS: odd parity
+    pub odd: bool,
+    /// CR2.STOP field value
+    pub stop: u8,
+}
+
+impl UartConfig {
+    /// 8 data bits, no parity, 1 stop bit
+    pub const EIGHT_N1: Self = Self::new(DataBits::Eight, Parity::None, StopBits::One);
+
+    /// 8 data bits, even parity, 1 stop bit (STM32 ROM bootloader)
+    pub const EIGHT_E1: Self = Self::new(DataBits::Eight, Parity::Even, StopBits::One);
+
+    /// Creates a frame format
+    pub const fn new(data_bits: DataBits, parity: Parity, stop_bits: StopBits) -> Self {
+        Self {
+            data_bits,
+            parity,
+            stop_bits,
+        }
+    }
+
+    /// Converts a CDC line coding
+    ///
+    /// # Returns
+    /// `None` for formats the USART cannot frame (5, 6 or 16 data bits,
+    /// mark or space parity, 7 data bits without parity)
+    pub fn from_line_coding(coding: &LineCoding) -> Option<Self> {
+        let data_bits = match coding.data_bits {
+            7 => DataBits::Seven,
+            8 => DataBits::Eight,
+            _ => return None,
+        };
+        let parity = match coding.parity {
+            0 => Parity::None,
+            1 => Parity::Odd,
+            2 => Parity::Even,
+            _ => return None,
+        };
+        let stop_bits = match coding.stop_bits {
+            0 => StopBits::One,
+            1 => StopBits::OneAndHalf,
+            2 => StopBits::Two,
+            _ => return None,
+        };
+
+        let config = Self::new(data_bits, parity, stop_bits);
+        config.frame_bits().ok().map(|_| config)
+    }
+
+    /// Parses a format such as `8N1` or `7E1.5` (case-insensitive)
+    pub fn parse(text: &str) -> Option<Self> {
+        let bytes = text.as_bytes();
+        if bytes.len() < 3 {
+            return None;
+        }
+
+        let data_bits = match bytes[0] {
+            b'7' => DataBits::Seven,
+            b'8' => DataBits::Eight,
+            _ => return None,
+        };
+        let parity = match bytes[1].to_ascii_uppercase() {
+            b'N' => Parity::None,
+            b'E' => Parity::Even,
+            b'O' => Parity::Odd,
+            _ => return None,
+        };
+        let stop_bits = match &text[2..] {
+            "1" => StopBits::One,
+            "0.5" => StopBits::Half,
+            "2" => StopBits::Two,
+            "1.5" => StopBits::OneAndHalf,
+            _ => return None,
+        };
+
+        let config = Self::new(data_bits, parity, stop_bits);
+        config.frame_bits().ok().map(|_| config)
+    }
+
+    /// Mask of the data bits in a received byte
+    ///
+    /// With 7 data bits the parity bit lands in bit 7 of the data register.
+    #[inline]
+    pub fn data_mask(&self) -> u8 {
+        match self.data_bits {
+            DataBits::Seven => 0x7F,
+            DataBits::Eight => 0xFF,
+        }
+    }
+
+    /// Register encoding
+    ///
+    /// # Errors
+    /// Returns `UsartError::InvalidConfig` for 7 data bits without parity
+    pub(super) fn frame_bits(&self) -> Result<FrameBits, UsartError> {
+        let parity = self.parity != Parity::None;
+        let nine_bits = match (self.data_bits, parity) {
+            (DataBits::Seven, true) => false,
+            (DataBits::Eight, false) => false,
+            (DataBits::Eight, true) => true,
+            (DataBits::Seven, false) => return Err(UsartError::InvalidConfig),
+        };
+        let stop = match self.stop_bits {
+            StopBits::One => 0b00,
+            StopBits::Half => 0b01,
+            StopBits::Two => 0b10,
+            StopBits::OneAndHalf => 0b11,
+        };
+
+        Ok(FrameBits {
+            nine_bits,
+            parity,
+            odd: self.parity == Parity::Odd,
+            stop,
+        })
+    }
+}
+
+impl Default for UartConfig {
+    fn default() -> Self {
+        Self::EIGHT_N1
+    }
+}
+
+/// Terminal notation, e.g. `8N1`
+impl fmt::Display for UartConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let data_bits = match self.data_bits {
+            DataBits::Seven => '7',
+            DataBits::Eight => '8',
+        };
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => "1",
+            StopBits::Half => "0.5",
+            StopBits::Two => "2",
+            StopBits::OneAndHalf => "1.5",
+        };
+        write!(f, "{}{}{}", data_bits, parity, stop_bits)
+    }
+}
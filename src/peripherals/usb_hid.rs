@@ -0,0 +1,403 @@
+//! # USB HID Keyboard Function
+//!
+//! Boot-protocol keyboard that replaces the bridge CDC port in the `usb-hid`
+//! build, for barcode-scanner-to-keyboard wedges: bytes received on the UART
+//! are typed into whatever application has the focus on the host, no driver
+//! or terminal needed.
+//! - Printable ASCII, Enter (CR, LF or CRLF), Tab, Backspace and Escape are
+//!   mapped to US layout key codes; other bytes are dropped and counted
+//! - Each character is one key press report followed by a release report,
+//!   so repeated characters are not merged by the host
+//! - Host data (LED output reports) is accepted and ignored; nothing flows
+//!   from the host to the UART in this mode
+//!
+//! ## Hardware Configuration
+//! - One interrupt IN endpoint, 8-byte reports every `HID_POLL_INTERVAL_MS`
+//! - Keystrokes are buffered up to `HID_KEY_QUEUE_LEN`
+
+use crate::config::{HID_KEY_QUEUE_LEN, HID_POLL_INTERVAL_MS};
+use core::sync::atomic::{AtomicU32, Ordering};
+use heapless::Deque;
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, Request, RequestType};
+use usb_device::UsbError;
+
+/// Human interface device class
+const USB_CLASS_HID: u8 = 0x03;
+/// Boot interface subclass
+const HID_SUBCLASS_BOOT: u8 = 0x01;
+/// Keyboard boot protocol
+const HID_PROTOCOL_KEYBOARD: u8 = 0x01;
+
+/// Class descriptor types
+const DESC_HID: u8 = 0x21;
+const DESC_REPORT: u8 = 0x22;
+
+/// Class requests
+const REQ_GET_REPORT: u8 = 0x01;
+const REQ_GET_IDLE: u8 = 0x02;
+const REQ_GET_PROTOCOL: u8 = 0x03;
+const REQ_SET_REPORT: u8 = 0x09;
+const REQ_SET_IDLE: u8 = 0x0A;
+const REQ_SET_PROTOCOL: u8 = 0x0B;
+
+/// Input report length: modifiers, reserved, six key codes
+const REPORT_LEN: usize = 8;
+
+/// Left shift bit of the modifier byte
+const MOD_LEFT_SHIFT: u8 = 0x02;
+
+/// Boot keyboard report descriptor (HID 1.11, appendix B.1)
+const REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xA1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Key Codes)
+    0x19, 0xE0, //   Usage Minimum (224)
+    0x29, 0xE7, //   Usage Maximum (231)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute): modifiers
+    0x95, 0x01, //   Report Count (1)
+    0x75, 0x08, //   Report Size (8)
+    0x81, 0x01, //   Input (Constant): reserved
+    0x95, 0x05, //   Report Count (5)
+    0x75, 0x01, //   Report Size (1)
+    0x05, 0x08, //   Usage Page (LEDs)
+    0x19, 0x01, //   Usage Minimum (1)
+    0x29, 0x05, //   Usage Maximum (5)
+    0x91, 0x02, //   Output (Data, Variable, Absolute): LEDs
+    0x95, 0x01, //   Report Count (1)
+    0x75, 0x03, //   Report Size (3)
+    0x91, 0x01, //   Output (Constant): padding
+    0x95, 0x06, //   Report Count (6)
+    0x75, 0x08, //   Report Size (8)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x65, //   Logical Maximum (101)
+    0x05, 0x07, //   Usage Page (Key Codes)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0x65, //   Usage Maximum (101)
+    0x81, 0x00, //   Input (Data, Array): key codes
+    0xC0, // End Collection
+];
+
+/// Bytes without a key mapping, since boot
+static UNMAPPED_BYTES: AtomicU32 = AtomicU32::new(0);
+
+/// One key press: modifier byte and key code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keystroke {
+    /// Modifier bits (shift)
+    pub modifiers: u8,
+    /// HID usage ID of the key
+    pub key: u8,
+}
+
+impl Keystroke {
+    const fn plain(key: u8) -> Self {
+        Self { modifiers: 0, key }
+    }
+
+    const fn shifted(key: u8) -> Self {
+        Self {
+            modifiers: MOD_LEFT_SHIFT,
+            key,
+        }
+    }
+
+    /// Maps an ASCII byte to a US layout keystroke
+    ///
+    /// # Returns
+    /// `None` for bytes no key produces
+    pub fn from_ascii(byte: u8) -> Option<Self> {
+        let stroke = match byte {
+            b'a'..=b'z' => Self::plain(0x04 + (byte - b'a')),
+            b'A'..=b'Z' => Self::shifted(0x04 + (byte - b'A')),
+            b'1'..=b'9' => Self::plain(0x1E + (byte - b'1')),
+            b'0' => Self::plain(0x27),
+            b'\r' | b'\n' => Self::plain(0x28),
+            0x1B => Self::plain(0x29),
+            0x08 | 0x7F => Self::plain(0x2A),
+            b'\t' => Self::plain(0x2B),
+            b' ' => Self::plain(0x2C),
+            b'-' => Self::plain(0x2D),
+            b'=' => Self::plain(0x2E),
+            b'[' => Self::plain(0x2F),
+            b']' => Self::plain(0x30),
+            b'\\' => Self::plain(0x31),
+            b';' => Self::plain(0x33),
+            b'\'' => Self::plain(0x34),
+            b'`' => Self::plain(0x35),
+            b',' => Self::plain(0x36),
+            b'.' => Self::plain(0x37),
+            b'/' => Self::plain(0x38),
+            b'!' => Self::shifted(0x1E),
+            b'@' => Self::shifted(0x1F),
+            b'#' => Self::shifted(0x20),
+            b'$' => Self::shifted(0x21),
+            b'%' => Self::shifted(0x22),
+            b'^' => Self::shifted(0x23),
+            b'&' => Self::shifted(0x24),
+            b'*' => Self::shifted(0x25),
+            b'(' => Self::shifted(0x26),
+            b')' => Self::shifted(0x27),
+            b'_' => Self::shifted(0x2D),
+            b'+' => Self::shifted(0x2E),
+            b'{' => Self::shifted(0x2F),
+            b'}' => Self::shifted(0x30),
+            b'|' => Self::shifted(0x31),
+            b':' => Self::shifted(0x33),
+            b'"' => Self::shifted(0x34),
+            b'~' => Self::shifted(0x35),
+            b'<' => Self::shifted(0x36),
+            b'>' => Self::shifted(0x37),
+            b'?' => Self::shifted(0x38),
+            _ => return None,
+        };
+        Some(stroke)
+    }
+
+    fn report(self) -> [u8; REPORT_LEN] {
+        [self.modifiers, 0, self.key, 0, 0, 0, 0, 0]
+    }
+}
+
+/// HID boot keyboard function
+pub struct HidKeyboard<'a, B: UsbBus> {
+    interface: InterfaceNumber,
+    write_ep: EndpointIn<'a, B>,
+    queue: Deque<Keystroke, HID_KEY_QUEUE_LEN>,
+    /// A press report is out, the release comes next
+    release_pending: bool,
+    /// The last byte was a CR, a following LF is part of the same Enter
+    after_cr: bool,
+    in_flight: bool,
+    last_report: [u8; REPORT_LEN],
+    idle_rate: u8,
+    boot_protocol: bool,
+    leds: u8,
+}
+
+impl<'a, B: UsbBus> HidKeyboard<'a, B> {
+    /// Allocates the interface and endpoint for the function
+    ///
+    /// # Arguments
+    /// * `alloc` - USB bus allocator
+    pub fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+        Self {
+            interface: alloc.interface(),
+            write_ep: alloc.interrupt(REPORT_LEN as u16, HID_POLL_INTERVAL_MS),
+            queue: Deque::new(),
+            release_pending: false,
+            after_cr: false,
+            in_flight: false,
+            last_report: [0; REPORT_LEN],
+            idle_rate: 0,
+            boot_protocol: false,
+            leds: 0,
+        }
+    }
+
+    /// Types bytes on the host
+    ///
+    /// Bytes without a key mapping are consumed and counted.
+    ///
+    /// # Returns
+    /// Number of bytes consumed
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` when the keystroke queue is full
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        let mut count = 0;
+        for &byte in data {
+            let crlf = byte == b'\n' && self.after_cr;
+            match Keystroke::from_ascii(byte) {
+                _ if crlf => {}
+                Some(stroke) => {
+                    if self.queue.push_back(stroke).is_err() {
+                        break;
+                    }
+                }
+                None => {
+                    UNMAPPED_BYTES.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            self.after_cr = byte == b'\r';
+            count += 1;
+        }
+
+        if count == 0 && !data.is_empty() {
+            return Err(UsbError::WouldBlock);
+        }
+        match self.send_report() {
+            Ok(()) | Err(UsbError::WouldBlock) => Ok(count),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks whether all queued keystrokes were typed
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` while keystrokes or a report are still pending
+    pub fn flush(&mut self) -> Result<(), UsbError> {
+        match self.send_report() {
+            Ok(()) | Err(UsbError::WouldBlock) => {}
+            Err(e) => return Err(e),
+        }
+
+        if self.in_flight || self.release_pending || !self.queue.is_empty() {
+            Err(UsbError::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Keyboard LEDs (Num, Caps, Scroll Lock...) as last set by the host
+    pub fn leds(&self) -> u8 {
+        self.leds
+    }
+
+    /// Bytes dropped for lack of a key mapping, since boot
+    pub fn unmapped_bytes() -> u32 {
+        UNMAPPED_BYTES.load(Ordering::Relaxed)
+    }
+
+    /// Sends the next press or release report if the endpoint is free
+    fn send_report(&mut self) -> Result<(), UsbError> {
+        if self.in_flight {
+            return Err(UsbError::WouldBlock);
+        }
+
+        let report = if self.release_pending {
+            [0; REPORT_LEN]
+        } else {
+            match self.queue.front() {
+                Some(stroke) => stroke.report(),
+                None => return Ok(()),
+            }
+        };
+
+        self.write_ep.write(&report)?;
+        if self.release_pending {
+            self.release_pending = false;
+        } else {
+            self.queue.pop_front();
+            self.release_pending = true;
+        }
+        self.last_report = report;
+        self.in_flight = true;
+        Ok(())
+    }
+
+    /// Whether a request targets this interface
+    fn is_own_request(&self, req: &Request) -> bool {
+        req.recipient == Recipient::Interface && req.index == u8::from(self.interface) as u16
+    }
+
+    /// HID class descriptor
+    fn hid_descriptor() -> [u8; 7] {
+        let len = (REPORT_DESCRIPTOR.len() as u16).to_le_bytes();
+        // bcdHID 1.11, no country code, one report descriptor
+        [0x11, 0x01, 0x00, 0x01, DESC_REPORT, len[0], len[1]]
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for HidKeyboard<'_, B> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface(
+            self.interface,
+            USB_CLASS_HID,
+            HID_SUBCLASS_BOOT,
+            HID_PROTOCOL_KEYBOARD,
+        )?;
+        writer.write(DESC_HID, &Self::hid_descriptor())?;
+        writer.endpoint(&self.write_ep)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.queue.clear();
+        self.release_pending = false;
+        self.after_cr = false;
+        self.in_flight = false;
+        self.last_report = [0; REPORT_LEN];
+        self.idle_rate = 0;
+        self.boot_protocol = false;
+        self.leds = 0;
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_ep.address() {
+            self.in_flight = false;
+            let _ = self.send_report();
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) {
+            return;
+        }
+
+        match (req.request_type, req.request) {
+            (RequestType::Standard, Request::GET_DESCRIPTOR) => match (req.value >> 8) as u8 {
+                DESC_REPORT => {
+                    xfer.accept_with_static(REPORT_DESCRIPTOR).ok();
+                }
+                DESC_HID => {
+                    xfer.accept_with(&Self::hid_descriptor()).ok();
+                }
+                _ => {
+                    xfer.reject().ok();
+                }
+            },
+            (RequestType::Class, REQ_GET_REPORT) => {
+                xfer.accept_with(&self.last_report).ok();
+            }
+            (RequestType::Class, REQ_GET_IDLE) => {
+                xfer.accept_with(&[self.idle_rate]).ok();
+            }
+            (RequestType::Class, REQ_GET_PROTOCOL) => {
+                // 0 = boot protocol, 1 = report protocol
+                xfer.accept_with(&[u8::from(!self.boot_protocol)]).ok();
+            }
+            (RequestType::Class, _) => {
+                xfer.reject().ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) || req.request_type != RequestType::Class {
+            return;
+        }
+
+        match req.request {
+            REQ_SET_REPORT => {
+                if let Some(&leds) = xfer.data().first() {
+                    self.leds = leds;
+                }
+                xfer.accept().ok();
+            }
+            REQ_SET_IDLE => {
+                self.idle_rate = (req.value >> 8) as u8;
+                xfer.accept().ok();
+            }
+            REQ_SET_PROTOCOL => {
+                // The report format is the boot format either way
+                self.boot_protocol = req.value == 0;
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
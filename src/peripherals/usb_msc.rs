@@ -0,0 +1,540 @@
+//! # USB Mass Storage Function
+//!
+//! Read-only USB flash drive built on a `BlockDevice`, so a host mounts the
+//! device logs without a terminal or driver:
+//! - Bulk-Only Transport (BOT 1.0) with one logical unit
+//! - SCSI transparent command set: INQUIRY, TEST UNIT READY, REQUEST SENSE,
+//!   READ CAPACITY(10), READ FORMAT CAPACITIES, READ(10), MODE SENSE(6/10)
+//!   with the write-protect bit set, and the medium commands hosts send on
+//!   mount and eject
+//! - WRITE(10) data is consumed and the command fails with DATA PROTECT
+//! - A changed device generation is reported as UNIT ATTENTION, so the host
+//!   drops its cached sectors and reads the new content
+//!
+//! Data phases shorter than the host asked for end with a stall of the IN
+//! endpoint; the status is sent once the host cleared it (BOT 6.7.2).
+//!
+//! ## Hardware Configuration
+//! - Bulk IN and OUT endpoints of `MSC_MAX_PACKET_SIZE`
+//! - One block buffer of `BLOCK_SIZE` bytes
+
+use crate::config::MSC_MAX_PACKET_SIZE;
+use crate::peripherals::block_device::{Block, BlockDevice, BlockError, BLOCK_SIZE};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+
+/// Mass storage class
+const USB_CLASS_MSC: u8 = 0x08;
+/// SCSI transparent command set subclass
+const MSC_SUBCLASS_SCSI: u8 = 0x06;
+/// Bulk-Only Transport protocol
+const MSC_PROTOCOL_BOT: u8 = 0x50;
+
+/// Class requests
+const REQ_GET_MAX_LUN: u8 = 0xFE;
+const REQ_BOT_RESET: u8 = 0xFF;
+
+/// Command block wrapper
+const CBW_SIGNATURE: u32 = 0x4342_5355;
+const CBW_LEN: usize = 31;
+/// Direction bit of the CBW flags: device to host
+const CBW_FLAG_IN: u8 = 0x80;
+
+/// Command status wrapper
+const CSW_SIGNATURE: u32 = 0x5342_5355;
+const CSW_LEN: usize = 13;
+
+/// CSW status values
+const STATUS_PASSED: u8 = 0x00;
+const STATUS_FAILED: u8 = 0x01;
+const STATUS_PHASE_ERROR: u8 = 0x02;
+
+/// SCSI operation codes
+const OP_TEST_UNIT_READY: u8 = 0x00;
+const OP_REQUEST_SENSE: u8 = 0x03;
+const OP_INQUIRY: u8 = 0x12;
+const OP_MODE_SENSE_6: u8 = 0x1A;
+const OP_START_STOP_UNIT: u8 = 0x1B;
+const OP_PREVENT_ALLOW_REMOVAL: u8 = 0x1E;
+const OP_READ_FORMAT_CAPACITIES: u8 = 0x23;
+const OP_READ_CAPACITY_10: u8 = 0x25;
+const OP_READ_10: u8 = 0x28;
+const OP_WRITE_10: u8 = 0x2A;
+const OP_VERIFY_10: u8 = 0x2F;
+const OP_MODE_SENSE_10: u8 = 0x5A;
+
+/// Standard INQUIRY data: direct access, removable, SPC-2
+const INQUIRY_DATA: [u8; 36] = *b"\x00\x80\x04\x02\x1F\x00\x00\x00STM32F4 Bridge Log Disk 1.0 ";
+
+/// Commands handled, since boot
+static COMMANDS: AtomicU32 = AtomicU32::new(0);
+/// Commands that failed, since boot
+static COMMANDS_FAILED: AtomicU32 = AtomicU32::new(0);
+/// Blocks sent to the host, since boot
+static BLOCKS_READ: AtomicU32 = AtomicU32::new(0);
+
+/// SCSI sense data of the last failed command
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sense {
+    key: u8,
+    asc: u8,
+    ascq: u8,
+}
+
+impl Sense {
+    const NONE: Self = Self::new(0x00, 0x00, 0x00);
+    const NOT_PRESENT: Self = Self::new(0x02, 0x3A, 0x00);
+    const MEDIUM_CHANGED: Self = Self::new(0x06, 0x28, 0x00);
+    const INVALID_OPCODE: Self = Self::new(0x05, 0x20, 0x00);
+    const LBA_OUT_OF_RANGE: Self = Self::new(0x05, 0x21, 0x00);
+    const WRITE_PROTECTED: Self = Self::new(0x07, 0x27, 0x00);
+    const MEDIUM_ERROR: Self = Self::new(0x03, 0x00, 0x00);
+
+    const fn new(key: u8, asc: u8, ascq: u8) -> Self {
+        Self { key, asc, ascq }
+    }
+}
+
+impl From<BlockError> for Sense {
+    fn from(e: BlockError) -> Self {
+        match e {
+            BlockError::OutOfRange => Self::LBA_OUT_OF_RANGE,
+            BlockError::NotReady => Self::NOT_PRESENT,
+            BlockError::WriteProtected => Self::WRITE_PROTECTED,
+            BlockError::Medium => Self::MEDIUM_ERROR,
+        }
+    }
+}
+
+/// Transport state
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Waiting for a command block wrapper
+    Command,
+    /// Sending the buffer, then any further blocks of a READ
+    DataIn,
+    /// Discarding host data
+    DataOut,
+    /// Status to send once the IN endpoint is not stalled
+    StatusPending,
+    /// Status sent, waiting for its completion
+    Status,
+    /// Invalid CBW: both endpoints stall until a BOT reset
+    ResetRecovery,
+}
+
+/// USB mass storage function serving a block device
+pub struct MassStorage<'a, B: UsbBus, D: BlockDevice> {
+    interface: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    device: D,
+    state: State,
+    /// Tag of the current command, echoed in its status
+    tag: u32,
+    /// Data length the host expects
+    expected: u32,
+    /// Data moved so far
+    transferred: u32,
+    /// Host data goes to the device (OUT)
+    host_to_device: bool,
+    status: u8,
+    sense: Sense,
+    /// Generation the host was last told about
+    seen_generation: u32,
+    buf: Block,
+    buf_len: usize,
+    buf_pos: usize,
+    /// Next block of a READ; blocks are loaded until `expected` is reached
+    next_lba: u32,
+    reading_blocks: bool,
+}
+
+impl<'a, B: UsbBus, D: BlockDevice> MassStorage<'a, B, D> {
+    /// Allocates the interface and endpoints for the function
+    ///
+    /// # Arguments
+    /// * `alloc` - USB bus allocator
+    /// * `device` - Medium served to the host
+    pub fn new(alloc: &'a UsbBusAllocator<B>, device: D) -> Self {
+        Self {
+            interface: alloc.interface(),
+            read_ep: alloc.bulk(MSC_MAX_PACKET_SIZE as u16),
+            write_ep: alloc.bulk(MSC_MAX_PACKET_SIZE as u16),
+            seen_generation: device.generation(),
+            device,
+            state: State::Command,
+            tag: 0,
+            expected: 0,
+            transferred: 0,
+            host_to_device: false,
+            status: STATUS_PASSED,
+            sense: Sense::NONE,
+            buf: [0; BLOCK_SIZE],
+            buf_len: 0,
+            buf_pos: 0,
+            next_lba: 0,
+            reading_blocks: false,
+        }
+    }
+
+    /// Medium served to the host
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Returns to waiting for a command
+    fn reset_transport(&mut self) {
+        self.state = State::Command;
+        self.buf_len = 0;
+        self.buf_pos = 0;
+        self.reading_blocks = false;
+    }
+
+    /// Reads and runs a command block wrapper
+    fn receive_command(&mut self) {
+        let mut cbw = [0u8; CBW_LEN];
+        let count = match self.read_ep.read(&mut cbw) {
+            Ok(count) => count,
+            Err(_) => return,
+        };
+
+        let signature = u32::from_le_bytes([cbw[0], cbw[1], cbw[2], cbw[3]]);
+        if count != CBW_LEN || signature != CBW_SIGNATURE {
+            // Not a valid CBW: the host has to do a reset recovery
+            self.write_ep.stall();
+            self.read_ep.stall();
+            self.state = State::ResetRecovery;
+            return;
+        }
+
+        self.tag = u32::from_le_bytes([cbw[4], cbw[5], cbw[6], cbw[7]]);
+        self.expected = u32::from_le_bytes([cbw[8], cbw[9], cbw[10], cbw[11]]);
+        self.host_to_device = cbw[12] & CBW_FLAG_IN == 0;
+        self.transferred = 0;
+        self.status = STATUS_PASSED;
+        self.buf_len = 0;
+        self.buf_pos = 0;
+        self.reading_blocks = false;
+
+        let cb_len = (cbw[14] & 0x1F) as usize;
+        let mut cb = [0u8; 16];
+        cb[..cb_len.min(16)].copy_from_slice(&cbw[15..15 + cb_len.min(16)]);
+
+        COMMANDS.fetch_add(1, Ordering::Relaxed);
+        self.run_command(&cb);
+    }
+
+    /// Runs one SCSI command and starts its data phase
+    fn run_command(&mut self, cb: &[u8; 16]) {
+        let opcode = cb[0];
+
+        // A replaced medium is reported once, on the first medium access
+        if !matches!(opcode, OP_INQUIRY | OP_REQUEST_SENSE) {
+            let generation = self.device.generation();
+            if generation != self.seen_generation && self.device.is_ready() {
+                self.seen_generation = generation;
+                return self.fail(Sense::MEDIUM_CHANGED);
+            }
+        }
+
+        match opcode {
+            OP_INQUIRY => self.send(&INQUIRY_DATA),
+            OP_REQUEST_SENSE => {
+                let sense = core::mem::replace(&mut self.sense, Sense::NONE);
+                let mut data = [0u8; 18];
+                data[0] = 0x70; // Current error, fixed format
+                data[2] = sense.key;
+                data[7] = 10; // Additional sense length
+                data[12] = sense.asc;
+                data[13] = sense.ascq;
+                self.send(&data);
+            }
+            OP_TEST_UNIT_READY | OP_START_STOP_UNIT | OP_PREVENT_ALLOW_REMOVAL | OP_VERIFY_10 => {
+                if self.device.is_ready() {
+                    self.send(&[]);
+                } else {
+                    self.fail(Sense::NOT_PRESENT);
+                }
+            }
+            OP_READ_CAPACITY_10 => {
+                if !self.device.is_ready() {
+                    return self.fail(Sense::NOT_PRESENT);
+                }
+                let last_lba = self.device.block_count().saturating_sub(1);
+                let mut data = [0u8; 8];
+                data[..4].copy_from_slice(&last_lba.to_be_bytes());
+                data[4..].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+                self.send(&data);
+            }
+            OP_READ_FORMAT_CAPACITIES => {
+                let mut data = [0u8; 12];
+                data[3] = 8; // Capacity list length
+                data[4..8].copy_from_slice(&self.device.block_count().to_be_bytes());
+                // Formatted media, or no media present
+                data[8] = if self.device.is_ready() { 0x02 } else { 0x03 };
+                data[9..12].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes()[1..]);
+                self.send(&data);
+            }
+            OP_MODE_SENSE_6 => self.send(&[0x03, 0x00, 0x80, 0x00]),
+            OP_MODE_SENSE_10 => self.send(&[0x00, 0x06, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00]),
+            OP_READ_10 => {
+                let lba = u32::from_be_bytes([cb[2], cb[3], cb[4], cb[5]]);
+                let blocks = u16::from_be_bytes([cb[7], cb[8]]) as u32;
+                self.start_read(lba, blocks);
+            }
+            OP_WRITE_10 => {
+                // Nothing is written; the data is still taken off the bus
+                self.sense = Sense::WRITE_PROTECTED;
+                self.status = STATUS_FAILED;
+                self.receive_discarded();
+            }
+            _ => self.fail(Sense::INVALID_OPCODE),
+        }
+    }
+
+    /// Starts a READ(10) of `blocks` blocks at `lba`
+    fn start_read(&mut self, lba: u32, blocks: u32) {
+        if !self.device.is_ready() {
+            return self.fail(Sense::NOT_PRESENT);
+        }
+        if lba.saturating_add(blocks) > self.device.block_count() {
+            return self.fail(Sense::LBA_OUT_OF_RANGE);
+        }
+        if self.host_to_device && self.expected > 0 {
+            return self.phase_error();
+        }
+
+        self.expected = self.expected.min(blocks * BLOCK_SIZE as u32);
+        self.next_lba = lba;
+        self.reading_blocks = true;
+        self.state = State::DataIn;
+        self.send_next();
+    }
+
+    /// Sends a short response, cut to the length the host asked for
+    fn send(&mut self, data: &[u8]) {
+        if self.host_to_device && self.expected > 0 {
+            return self.phase_error();
+        }
+
+        let len = data.len().min(self.expected as usize);
+        self.buf[..len].copy_from_slice(&data[..len]);
+        self.buf_len = len;
+        self.buf_pos = 0;
+        self.state = State::DataIn;
+        self.send_next();
+    }
+
+    /// Fails the command without data
+    fn fail(&mut self, sense: Sense) {
+        self.sense = sense;
+        self.status = STATUS_FAILED;
+        if self.host_to_device {
+            self.receive_discarded();
+        } else {
+            self.buf_len = 0;
+            self.buf_pos = 0;
+            self.state = State::DataIn;
+            self.send_next();
+        }
+    }
+
+    /// Ends the command with a phase error; the host recovers with a reset
+    fn phase_error(&mut self) {
+        self.status = STATUS_PHASE_ERROR;
+        if self.host_to_device {
+            self.read_ep.stall();
+        } else {
+            self.write_ep.stall();
+        }
+        self.state = State::StatusPending;
+    }
+
+    /// Takes the host data phase and drops it
+    fn receive_discarded(&mut self) {
+        if self.expected == 0 {
+            self.send_status();
+        } else {
+            self.state = State::DataOut;
+        }
+    }
+
+    /// Sends the next data packet, loading the next block when needed
+    fn send_next(&mut self) {
+        if self.buf_pos == self.buf_len && self.reading_blocks && self.transferred < self.expected {
+            match self.device.read_block(self.next_lba, &mut self.buf) {
+                Ok(()) => {
+                    self.next_lba += 1;
+                    self.buf_len = BLOCK_SIZE.min((self.expected - self.transferred) as usize);
+                    self.buf_pos = 0;
+                    BLOCKS_READ.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    // The rest of the data phase is cut off by the stall
+                    self.sense = e.into();
+                    self.status = STATUS_FAILED;
+                    self.reading_blocks = false;
+                }
+            }
+        }
+
+        if self.buf_pos == self.buf_len {
+            return self.finish_data_in();
+        }
+
+        let end = (self.buf_pos + MSC_MAX_PACKET_SIZE).min(self.buf_len);
+        if let Ok(count) = self.write_ep.write(&self.buf[self.buf_pos..end]) {
+            self.buf_pos += count;
+            self.transferred += count as u32;
+        }
+    }
+
+    /// Ends the data IN phase, stalling it if it was short
+    fn finish_data_in(&mut self) {
+        self.reading_blocks = false;
+        if self.transferred < self.expected {
+            self.write_ep.stall();
+            self.state = State::StatusPending;
+        } else {
+            self.send_status();
+        }
+    }
+
+    /// Sends the command status wrapper
+    fn send_status(&mut self) {
+        if self.status != STATUS_PASSED {
+            COMMANDS_FAILED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let residue = self.expected.saturating_sub(self.transferred);
+        let mut csw = [0u8; CSW_LEN];
+        csw[0..4].copy_from_slice(&CSW_SIGNATURE.to_le_bytes());
+        csw[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        csw[8..12].copy_from_slice(&residue.to_le_bytes());
+        csw[12] = self.status;
+
+        self.state = match self.write_ep.write(&csw) {
+            Ok(_) => State::Status,
+            // Retried from `poll`
+            Err(_) => State::StatusPending,
+        };
+    }
+
+    /// Whether a request targets this interface
+    fn is_own_request(&self, req: &usb_device::control::Request) -> bool {
+        req.recipient == Recipient::Interface
+            && req.request_type == RequestType::Class
+            && req.index == u8::from(self.interface) as u16
+    }
+}
+
+impl<B: UsbBus, D: BlockDevice> UsbClass<B> for MassStorage<'_, B, D> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface(
+            self.interface,
+            USB_CLASS_MSC,
+            MSC_SUBCLASS_SCSI,
+            MSC_PROTOCOL_BOT,
+        )?;
+        writer.endpoint(&self.write_ep)?;
+        writer.endpoint(&self.read_ep)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.reset_transport();
+        self.sense = Sense::NONE;
+        // A new host session gets current files
+        self.device.refresh();
+    }
+
+    fn poll(&mut self) {
+        if self.state == State::StatusPending && !self.write_ep.is_stalled() {
+            self.send_status();
+        }
+    }
+
+    fn endpoint_out(&mut self, addr: EndpointAddress) {
+        if addr != self.read_ep.address() {
+            return;
+        }
+
+        match self.state {
+            State::Command => self.receive_command(),
+            State::DataOut => {
+                let mut packet = [0u8; MSC_MAX_PACKET_SIZE];
+                if let Ok(count) = self.read_ep.read(&mut packet) {
+                    self.transferred += count as u32;
+                    if self.transferred >= self.expected || count < MSC_MAX_PACKET_SIZE {
+                        self.send_status();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr != self.write_ep.address() {
+            return;
+        }
+
+        match self.state {
+            State::DataIn => self.send_next(),
+            State::Status => self.state = State::Command,
+            _ => {}
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) {
+            return;
+        }
+
+        match req.request {
+            REQ_GET_MAX_LUN => {
+                // A single logical unit
+                xfer.accept_with(&[0]).ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = *xfer.request();
+        if !self.is_own_request(&req) {
+            return;
+        }
+
+        match req.request {
+            REQ_BOT_RESET => {
+                // The host clears the endpoint halts next
+                self.reset_transport();
+                xfer.accept().ok();
+            }
+            _ => {
+                xfer.reject().ok();
+            }
+        }
+    }
+}
+
+/// Writes the mass storage command counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "USB mass storage: {} commands, {} failed, {} blocks read",
+        COMMANDS.load(Ordering::Relaxed),
+        COMMANDS_FAILED.load(Ordering::Relaxed),
+        BLOCKS_READ.load(Ordering::Relaxed)
+    )
+}
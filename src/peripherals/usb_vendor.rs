@@ -0,0 +1,332 @@
+//! # USB Vendor Bulk Function
+//!
+//! Vendor-specific interface that replaces the bridge CDC port in the
+//! `usb-vendor` build, for hosts that move UART data through libusb or the
+//! WinUSB API instead of a virtual COM port: no CDC class driver, no tty
+//! layer and no control requests on the data path.
+//!
+//! Every bulk packet holds whole messages, `TYPE LEN PAYLOAD[LEN]`, so a
+//! message never spans two packets:
+//! - `0x01` DATA, both directions: UART bytes
+//! - `0x02` LINE_CODING, host to device: 7 bytes laid out like the CDC
+//!   line coding (baud rate u32 LE, stop bits, parity, data bits)
+//! - `0x03` CONTROL_LINES, host to device: 1 byte, bit 0 DTR, bit 1 RTS
+//! - `0x04` SERIAL_STATE, device to host: u16 LE, the CDC `SERIAL_STATE`
+//!   bits; sent ahead of the data when the modem status changed
+//!
+//! A packet with an unknown or truncated message is dropped from that
+//! message on and counted; the messages before it still apply.
+//!
+//! ## Hardware Configuration
+//! - One bulk IN and one bulk OUT endpoint of `CDC_MAX_PACKET_SIZE`
+//! - Windows binds WinUSB to the interface through the MS OS 2.0
+//!   descriptors (`VENDOR_INTERFACE_GUID`)
+
+#[cfg(feature = "usb-hid")]
+compile_error!("features `usb-hid` and `usb-vendor` both replace the bridge port");
+
+use crate::config::{CDC_MAX_PACKET_SIZE, DATA_PACKET_SIZE};
+use crate::peripherals::cdc_acm::{LineCoding, SerialState};
+use core::sync::atomic::{AtomicU32, Ordering};
+use usb_device::class_prelude::*;
+use usb_device::UsbError;
+
+/// Vendor-specific interface class, subclass and protocol
+const USB_CLASS_VENDOR: u8 = 0xFF;
+const VENDOR_SUBCLASS: u8 = 0x00;
+const VENDOR_PROTOCOL: u8 = 0x00;
+
+/// Message types
+const MSG_DATA: u8 = 0x01;
+const MSG_LINE_CODING: u8 = 0x02;
+const MSG_CONTROL_LINES: u8 = 0x03;
+const MSG_SERIAL_STATE: u8 = 0x04;
+
+/// Message header: type and payload length
+const HEADER_LEN: usize = 2;
+
+/// SERIAL_STATE message with its header
+const SERIAL_STATE_MSG_LEN: usize = HEADER_LEN + 2;
+
+/// Packets dropped in part for an unknown or truncated message, since boot
+static MALFORMED_PACKETS: AtomicU32 = AtomicU32::new(0);
+
+/// Vendor bulk function with message framing
+pub struct VendorBulk<'a, B: UsbBus> {
+    interface: InterfaceNumber,
+    read_ep: EndpointOut<'a, B>,
+    write_ep: EndpointIn<'a, B>,
+    line_coding: LineCoding,
+    line_coding_changed: bool,
+    dtr: bool,
+    rts: bool,
+    write_buf: [u8; DATA_PACKET_SIZE],
+    write_start: usize,
+    write_end: usize,
+    write_in_flight: bool,
+    need_zlp: bool,
+    serial_state: SerialState,
+    state_pending: bool,
+}
+
+impl<'a, B: UsbBus> VendorBulk<'a, B> {
+    /// Allocates the interface and endpoints for the function
+    ///
+    /// # Arguments
+    /// * `alloc` - USB bus allocator
+    pub fn new(alloc: &'a UsbBusAllocator<B>) -> Self {
+        Self {
+            interface: alloc.interface(),
+            read_ep: alloc.bulk(CDC_MAX_PACKET_SIZE as u16),
+            write_ep: alloc.bulk(CDC_MAX_PACKET_SIZE as u16),
+            line_coding: LineCoding::default(),
+            line_coding_changed: false,
+            dtr: false,
+            rts: false,
+            write_buf: [0; DATA_PACKET_SIZE],
+            write_start: 0,
+            write_end: 0,
+            write_in_flight: false,
+            need_zlp: false,
+            serial_state: SerialState::empty(),
+            state_pending: false,
+        }
+    }
+
+    /// Interface number, for the WinUSB binding
+    pub fn interface(&self) -> InterfaceNumber {
+        self.interface
+    }
+
+    /// Reads one packet from the host
+    ///
+    /// Applies the LINE_CODING and CONTROL_LINES messages and copies the
+    /// DATA payloads to `data`.
+    ///
+    /// # Arguments
+    /// * `data` - Destination, at least `CDC_MAX_PACKET_SIZE` bytes
+    ///
+    /// # Returns
+    /// Number of UART bytes, 0 for a packet with control messages only
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` when no packet is available
+    pub fn read(&mut self, data: &mut [u8]) -> Result<usize, UsbError> {
+        let mut packet = [0u8; CDC_MAX_PACKET_SIZE];
+        let len = self.read_ep.read(&mut packet)?;
+
+        let mut pos = 0;
+        let mut count = 0;
+        while pos < len {
+            let Some(&[kind, size]) = packet[..len].get(pos..pos + HEADER_LEN) else {
+                break;
+            };
+            let start = pos + HEADER_LEN;
+            let Some(payload) = packet[..len].get(start..start + usize::from(size)) else {
+                break;
+            };
+
+            match (kind, payload.len()) {
+                (MSG_DATA, _) => {
+                    // Bytes beyond a short destination are lost
+                    let take = payload.len().min(data.len() - count);
+                    data[count..count + take].copy_from_slice(&payload[..take]);
+                    count += take;
+                }
+                (MSG_LINE_CODING, 7) => {
+                    self.line_coding = LineCoding {
+                        baud_rate: u32::from_le_bytes([
+                            payload[0], payload[1], payload[2], payload[3],
+                        ]),
+                        stop_bits: payload[4],
+                        parity: payload[5],
+                        data_bits: payload[6],
+                    };
+                    self.line_coding_changed = true;
+                }
+                (MSG_CONTROL_LINES, 1) => {
+                    self.dtr = payload[0] & 0x01 != 0;
+                    self.rts = payload[0] & 0x02 != 0;
+                }
+                _ => break,
+            }
+            pos = start + payload.len();
+        }
+
+        if pos < len {
+            MALFORMED_PACKETS.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(count)
+    }
+
+    /// Queues UART bytes for the host
+    ///
+    /// # Returns
+    /// Number of bytes accepted into the write buffer
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` when the write buffer is full
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, UsbError> {
+        if self.write_start > 0 {
+            self.write_buf
+                .copy_within(self.write_start..self.write_end, 0);
+            self.write_end -= self.write_start;
+            self.write_start = 0;
+        }
+
+        let count = data.len().min(self.write_buf.len() - self.write_end);
+        if count == 0 && !data.is_empty() {
+            return Err(UsbError::WouldBlock);
+        }
+
+        self.write_buf[self.write_end..self.write_end + count].copy_from_slice(&data[..count]);
+        self.write_end += count;
+
+        match self.send_packet() {
+            Ok(()) | Err(UsbError::WouldBlock) => Ok(count),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends buffered data, terminating the transfer
+    ///
+    /// # Errors
+    /// `UsbError::WouldBlock` while data or a packet is still in flight
+    pub fn flush(&mut self) -> Result<(), UsbError> {
+        match self.send_packet() {
+            Ok(()) | Err(UsbError::WouldBlock) => {}
+            Err(e) => return Err(e),
+        }
+
+        if self.write_in_flight || self.state_pending || self.write_start < self.write_end {
+            Err(UsbError::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Takes the line coding if the host changed it since the last call
+    pub fn take_line_coding(&mut self) -> Option<LineCoding> {
+        core::mem::take(&mut self.line_coding_changed).then_some(self.line_coding)
+    }
+
+    /// Data Terminal Ready as set by the host
+    pub fn dtr(&self) -> bool {
+        self.dtr
+    }
+
+    /// Request To Send as set by the host
+    pub fn rts(&self) -> bool {
+        self.rts
+    }
+
+    /// Updates the modem status reported to the host
+    ///
+    /// A SERIAL_STATE message is queued only when the state changes; it goes
+    /// out in the next packet, ahead of any data.
+    ///
+    /// # Errors
+    /// Endpoint failures other than `WouldBlock`
+    pub fn set_serial_state(&mut self, state: SerialState) -> Result<(), UsbError> {
+        if state != self.serial_state {
+            self.serial_state = state;
+            self.state_pending = true;
+        }
+        match self.send_packet() {
+            Ok(()) | Err(UsbError::WouldBlock) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Packets dropped in part for an unknown or truncated message, since boot
+    pub fn malformed_packets() -> u32 {
+        MALFORMED_PACKETS.load(Ordering::Relaxed)
+    }
+
+    /// Frames and writes the next packet (or pending ZLP) if the endpoint is free
+    fn send_packet(&mut self) -> Result<(), UsbError> {
+        if self.write_in_flight {
+            return Err(UsbError::WouldBlock);
+        }
+
+        let pending = self.write_end - self.write_start;
+        if pending == 0 && !self.state_pending {
+            if self.need_zlp {
+                self.write_ep.write(&[])?;
+                self.need_zlp = false;
+                self.write_in_flight = true;
+            }
+            return Ok(());
+        }
+
+        let mut packet = [0u8; CDC_MAX_PACKET_SIZE];
+        let mut len = 0;
+        if self.state_pending {
+            let bits = self.serial_state.bits().to_le_bytes();
+            packet[..SERIAL_STATE_MSG_LEN].copy_from_slice(&[
+                MSG_SERIAL_STATE,
+                2,
+                bits[0],
+                bits[1],
+            ]);
+            len = SERIAL_STATE_MSG_LEN;
+        }
+        let count = pending.min(CDC_MAX_PACKET_SIZE - len - HEADER_LEN);
+        if count > 0 {
+            packet[len] = MSG_DATA;
+            packet[len + 1] = count as u8;
+            packet[len + HEADER_LEN..len + HEADER_LEN + count]
+                .copy_from_slice(&self.write_buf[self.write_start..self.write_start + count]);
+            len += HEADER_LEN + count;
+        }
+
+        // Packets are written whole or not at all
+        self.write_ep.write(&packet[..len])?;
+        self.state_pending = false;
+        self.write_start += count;
+        self.write_in_flight = true;
+        // A full packet that ends the buffered data must be followed by a ZLP
+        self.need_zlp = len == CDC_MAX_PACKET_SIZE;
+
+        if self.write_start == self.write_end {
+            self.write_start = 0;
+            self.write_end = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for VendorBulk<'_, B> {
+    fn get_configuration_descriptors(
+        &self,
+        writer: &mut DescriptorWriter,
+    ) -> usb_device::Result<()> {
+        writer.interface(
+            self.interface,
+            USB_CLASS_VENDOR,
+            VENDOR_SUBCLASS,
+            VENDOR_PROTOCOL,
+        )?;
+        writer.endpoint(&self.write_ep)?;
+        writer.endpoint(&self.read_ep)?;
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.line_coding = LineCoding::default();
+        self.dtr = false;
+        self.rts = false;
+        self.write_start = 0;
+        self.write_end = 0;
+        self.write_in_flight = false;
+        self.need_zlp = false;
+        self.state_pending = !self.serial_state.is_empty();
+    }
+
+    fn endpoint_in_complete(&mut self, addr: EndpointAddress) {
+        if addr == self.write_ep.address() {
+            self.write_in_flight = false;
+            let _ = self.send_packet();
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! # VBUS Sensing
+//!
+//! Watches the USB bus voltage on PA9 (OTG_FS_VBUS on the Discovery board),
+//! so the bridge knows when no host can be attached:
+//! - Sampled as a plain GPIO input by the periodic scheduler; EXTI line 9
+//!   belongs to the PG9 UART wakeup of `low_power`
+//! - A change counts once `VBUS_DEBOUNCE_POLLS` samples in a row agree,
+//!   well within the 100 ms a host waits after attach before it resets the
+//!   device
+//! - The OTG FS core keeps its own VBUS sensing off, so the pin is free
+//!
+//! ## Hardware Configuration
+//! - PA9 with the internal pull-down; an unconnected pin reads as absent
+//! - Boards without PA9 on VBUS set `VBUS_SENSING` to `false`; VBUS then
+//!   always reads as present
+
+use crate::config::{VBUS_DEBOUNCE_POLLS, VBUS_SENSING};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::gpio::{gpioa::PA9, Input};
+
+/// Debounced VBUS state
+static PRESENT: AtomicBool = AtomicBool::new(true);
+
+static CHANGES: AtomicU32 = AtomicU32::new(0);
+
+/// Checks whether VBUS is present (debounced)
+#[inline]
+pub fn is_present() -> bool {
+    PRESENT.load(Ordering::Relaxed)
+}
+
+/// VBUS input with its debouncer
+pub struct VbusSense {
+    pin: PA9<Input>,
+    /// Samples in a row that disagree with the debounced state
+    streak: u8,
+}
+
+impl VbusSense {
+    /// Takes the input and its current level as the debounced state
+    ///
+    /// # Arguments
+    /// * `pin` - PA9 as an input with pull-down
+    pub fn new(pin: PA9<Input>) -> Self {
+        let sense = Self { pin, streak: 0 };
+        PRESENT.store(sense.sample(), Ordering::Relaxed);
+        sense
+    }
+
+    /// Takes one sample
+    ///
+    /// # Returns
+    /// The new state once a change is debounced, `None` otherwise
+    pub fn poll(&mut self) -> Option<bool> {
+        let present = self.sample();
+        if present == is_present() {
+            self.streak = 0;
+            return None;
+        }
+
+        self.streak += 1;
+        if self.streak < VBUS_DEBOUNCE_POLLS {
+            return None;
+        }
+        self.streak = 0;
+        PRESENT.store(present, Ordering::Relaxed);
+        CHANGES.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(feature = "debug")]
+        defmt::info!("VBUS {=str}", if present { "present" } else { "absent" });
+        Some(present)
+    }
+
+    fn sample(&self) -> bool {
+        !VBUS_SENSING || self.pin.is_high()
+    }
+}
+
+/// Writes the VBUS state and the number of changes
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "VBUS: {}{}, {} changes",
+        if is_present() { "present" } else { "absent" },
+        if VBUS_SENSING { "" } else { " (sensing off)" },
+        CHANGES.load(Ordering::Relaxed)
+    )
+}
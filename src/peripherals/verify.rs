@@ -0,0 +1,129 @@
+//! # Configuration Read-Back Verification
+//!
+//! Reads back key configuration registers after initialization and compares
+//! them with values derived from `config`:
+//! - USART6 CR1/CR3/BRR (enables, frame format, DMA requests, baud divisor)
+//! - DMA2 stream 1/6 CR (channel, direction, increment, interrupt, priority)
+//! - OTG FS core configuration (forced device mode, full speed)
+//! - RCC CFGR (PLL as system clock, bus prescalers)
+//!
+//! Catches silent behavior changes in the HAL across upgrades. Every mismatch
+//! is logged; the first one is returned as `InitError::RegisterMismatch`.
+//!
+//! ## Safety Considerations
+//! - Registers are only read, never written
+//! - Must run after all peripherals are configured and before interrupts are unmasked
+
+use crate::config::{PCLK1, PCLK2, SYSCLK, USART6_BAUD_RATE};
+use crate::errors::errors::{InitError, RegisterMismatch};
+use stm32f4xx_hal::pac;
+
+/// DMA channel routing USART6 on streams 1 (RX) and 6 (TX)
+const USART6_DMA_CHANNEL: u32 = 5;
+
+/// DMA stream CR bits checked: CHSEL, PL, MINC, PINC, DIR, TCIE
+const DMA_CR_MASK: u32 =
+    (0b111 << 25) | (0b11 << 16) | (1 << 10) | (1 << 9) | (0b11 << 6) | (1 << 4);
+
+/// Encodes an APB prescaler divisor as PPREx bits
+const fn ppre_bits(div: u32) -> u32 {
+    match div {
+        2 => 0b100,
+        4 => 0b101,
+        8 => 0b110,
+        16 => 0b111,
+        _ => 0b000,
+    }
+}
+
+/// Expected DMA stream CR value for USART6
+const fn dma_cr_expected(dir: u32) -> u32 {
+    (USART6_DMA_CHANNEL << 25) | (0b10 << 16) | (1 << 10) | (dir << 6) | (1 << 4)
+}
+
+/// Collects mismatches while the checks run
+struct Verifier {
+    first: Option<RegisterMismatch>,
+}
+
+impl Verifier {
+    /// Compares the masked register value with the expectation
+    fn check(&mut self, register: &'static str, value: u32, mask: u32, expected: u32) {
+        let actual = value & mask;
+        if actual == expected {
+            return;
+        }
+
+        let mismatch = RegisterMismatch {
+            register,
+            mask,
+            expected,
+            actual,
+        };
+
+        #[cfg(feature = "debug")]
+        defmt::error!("Read-back mismatch: {}", mismatch);
+
+        self.first.get_or_insert(mismatch);
+    }
+}
+
+/// Verifies the critical register configuration
+///
+/// # Errors
+/// Returns `InitError::RegisterMismatch` describing the first discrepancy
+pub fn verify_configuration() -> Result<(), InitError> {
+    let mut verifier = Verifier { first: None };
+
+    // SAFETY: Read-only access to configuration registers after init
+    let (usart, dma, otg_global, otg_device, rcc) = unsafe {
+        (
+            &*pac::USART6::ptr(),
+            &*pac::DMA2::ptr(),
+            &*pac::OTG_FS_GLOBAL::ptr(),
+            &*pac::OTG_FS_DEVICE::ptr(),
+            &*pac::RCC::ptr(),
+        )
+    };
+
+    // USART6: UE, M=8 bit, PCE off, IDLEIE, TE, RE
+    let cr1_mask = (1 << 13) | (1 << 12) | (1 << 10) | (1 << 4) | (1 << 3) | (1 << 2);
+    let cr1_expected = (1 << 13) | (1 << 4) | (1 << 3) | (1 << 2);
+    verifier.check("USART6.CR1", usart.cr1().read().bits(), cr1_mask, cr1_expected);
+
+    // USART6: DMAT, DMAR
+    let cr3_mask = (1 << 7) | (1 << 6);
+    verifier.check("USART6.CR3", usart.cr3().read().bits(), cr3_mask, cr3_mask);
+
+    // USART6: 16x oversampling divisor, rounded to nearest
+    let brr_expected = (PCLK2 + USART6_BAUD_RATE / 2) / USART6_BAUD_RATE;
+    verifier.check("USART6.BRR", usart.brr().read().bits(), 0xFFFF, brr_expected);
+
+    // DMA2: stream 1 peripheral-to-memory, stream 6 memory-to-peripheral
+    let s1_cr = dma.st(1).cr().read().bits();
+    verifier.check("DMA2.S1CR", s1_cr, DMA_CR_MASK, dma_cr_expected(0b00));
+    let s6_cr = dma.st(6).cr().read().bits();
+    verifier.check("DMA2.S6CR", s6_cr, DMA_CR_MASK, dma_cr_expected(0b01));
+
+    // OTG FS: forced device mode
+    let fdmod = 1 << 30;
+    verifier.check("OTG_FS.GUSBCFG", otg_global.gusbcfg().read().bits(), fdmod, fdmod);
+
+    // OTG FS: device speed = full speed (internal PHY)
+    verifier.check("OTG_FS.DCFG", otg_device.dcfg().read().bits(), 0b11, 0b11);
+
+    // RCC: PLL selected as SYSCLK, AHB undivided, APB prescalers from config
+    let cfgr_mask = (0b11 << 2) | (0b1111 << 4) | (0b111 << 10) | (0b111 << 13);
+    let cfgr_expected =
+        (0b10 << 2) | (ppre_bits(SYSCLK / PCLK1) << 10) | (ppre_bits(SYSCLK / PCLK2) << 13);
+    verifier.check("RCC.CFGR", rcc.cfgr().read().bits(), cfgr_mask, cfgr_expected);
+
+    match verifier.first {
+        Some(mismatch) => Err(InitError::RegisterMismatch(mismatch)),
+        None => {
+            #[cfg(feature = "debug")]
+            defmt::info!("Register read-back verification passed");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! # Window Watchdog (WWDG)
+//!
+//! Catches the faults the IWDG cannot: a refresh that comes too early, as
+//! from a loop that runs away or a monotonic that runs fast against PCLK1:
+//! - The refresh task starts the WWDG and refreshes it every
+//!   `WWDG_REFRESH_MS`; a refresh is only accepted once `WINDOW_OPEN_TICKS`
+//!   of the 64 counter ticks have passed
+//! - `check_window` reports a refresh that would come before the window
+//!   opens, so the caller records `WatchdogEarlyRefresh` before the refresh
+//!   resets the MCU
+//! - The early wakeup interrupt fires one tick before a missed refresh
+//!   resets the MCU; its handler records `WatchdogTimeout`
+//! - Both errors reach the persistent error log, so the cause survives the
+//!   reset; `caused_last_reset` tells a WWDG reset apart at the next boot
+//!
+//! ## Hardware Configuration
+//! - Clocked by PCLK1 / 4096 / 2^WDGTB; the prescaler is the smallest one
+//!   whose timeout exceeds the refresh period by a quarter, and follows the
+//!   core clock level (`follow_clock`)
+//! - Once started, the WWDG cannot be stopped until the next reset
+//! - The counter and SysTick both stop in Stop mode, so Stop periods need no
+//!   refresh
+//! - With the `debug` feature the counter is frozen while the core is halted
+//!
+//! ## Safety Considerations
+//! - Anything that keeps priority 2 out longer than the timeout minus the
+//!   refresh period (~14 ms at the full clock), such as a long critical
+//!   section, resets the MCU
+//! - A clock level switch keeps the tick count between two refreshes inside
+//!   the window: the tick period changes from 728 us to 1024 us or back
+
+use crate::config::{PCLK1, WWDG_REFRESH_MS};
+use crate::errors::errors::DeviceError;
+use crate::peripherals::clock_scaling::{self, ClockLevel};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::pac::{self, WWDG};
+
+/// Counter value loaded by a refresh (T[6:0])
+const COUNTER_MAX: u32 = 0x7F;
+
+/// Counter ticks from a refresh to the reset (T6 clears below 0x40)
+const TIMEOUT_TICKS: u32 = 64;
+
+/// Counter ticks from a refresh until the next refresh is accepted
+const WINDOW_OPEN_TICKS: u32 = 24;
+
+/// Window value (W[6:0]); a refresh above it resets the MCU
+const WINDOW: u32 = COUNTER_MAX - WINDOW_OPEN_TICKS;
+
+/// Largest prescaler setting (divide by 8)
+const PRESCALER_MAX: u32 = 3;
+
+/// PCLK1 cycles per counter tick at prescaler setting 0
+const TICK_CYCLES: u64 = 4096;
+
+/// CR: activation bit, CFR: prescaler shift and early wakeup interrupt enable
+const CR_WDGA: u32 = 1 << 7;
+const CFR_WDGTB_SHIFT: u32 = 7;
+const CFR_EWI: u32 = 1 << 9;
+
+/// SR: early wakeup interrupt flag
+const SR_EWIF: u32 = 1 << 0;
+
+// The refresh period must fit the longest timeout at the full clock
+const _: () = assert!(prescaler(PCLK1) <= PRESCALER_MAX);
+
+/// Set once the WWDG is running
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+static REFRESHES: AtomicU32 = AtomicU32::new(0);
+
+/// Set when the WWDG caused the reset before this boot
+static WATCHDOG_RESET: AtomicBool = AtomicBool::new(false);
+
+/// Checks whether the WWDG caused the last reset
+pub fn caused_last_reset() -> bool {
+    WATCHDOG_RESET.load(Ordering::Relaxed)
+}
+
+/// Checks whether the WWDG is running
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Relaxed)
+}
+
+/// Time from a refresh to the reset at a PCLK1 and prescaler (microseconds)
+const fn timeout_us(pclk1: u32, prescaler: u32) -> u32 {
+    ((TICK_CYCLES << prescaler) * TIMEOUT_TICKS as u64 * 1_000_000 / pclk1 as u64) as u32
+}
+
+/// Smallest prescaler whose timeout exceeds the refresh period by a quarter
+///
+/// # Returns
+/// The prescaler setting, above `PRESCALER_MAX` if none fits
+const fn prescaler(pclk1: u32) -> u32 {
+    let mut prescaler = 0;
+    while prescaler <= PRESCALER_MAX
+        && timeout_us(pclk1, prescaler) <= WWDG_REFRESH_MS * 1_000 * 5 / 4
+    {
+        prescaler += 1;
+    }
+    prescaler
+}
+
+/// CFR value for a PCLK1
+fn cfr_bits(pclk1: u32) -> u32 {
+    (prescaler(pclk1).min(PRESCALER_MAX) << CFR_WDGTB_SHIFT) | CFR_EWI | WINDOW
+}
+
+/// Window watchdog driver
+pub struct Wwdg {
+    wwdg: WWDG,
+}
+
+impl Wwdg {
+    /// Configures the watchdog without starting it
+    ///
+    /// Records the WWDG reset flag; must run before `Iwdg::start`, which
+    /// clears the reset flags.
+    ///
+    /// # Arguments
+    /// * `wwdg` - WWDG peripheral instance
+    pub fn new(wwdg: WWDG) -> Self {
+        // SAFETY: RCC APB1ENR.WWDGEN is only set here; the RCC_CSR reset
+        // flags and DBGMCU are not written by any other driver at this point
+        unsafe {
+            let rcc = &*pac::RCC::ptr();
+            rcc.apb1enr().modify(|_, w| w.wwdgen().set_bit());
+            WATCHDOG_RESET.store(rcc.csr().read().wwdgrstf().bit_is_set(), Ordering::Relaxed);
+
+            #[cfg(feature = "debug")]
+            (*pac::DBGMCU::ptr())
+                .apb1_fz()
+                .modify(|_, w| w.dbg_wwdg_stop().set_bit());
+        }
+
+        wwdg.cfr()
+            .write(|w| unsafe { w.bits(cfr_bits(clock_scaling::level().pclk1())) });
+        Self { wwdg }
+    }
+
+    /// Starts the watchdog with a full counter
+    pub fn start(&mut self) {
+        self.wwdg
+            .cr()
+            .write(|w| unsafe { w.bits(CR_WDGA | COUNTER_MAX) });
+        RUNNING.store(true, Ordering::Relaxed);
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "WWDG started: refresh {} ms, last reset by window watchdog: {}",
+            WWDG_REFRESH_MS,
+            caused_last_reset()
+        );
+    }
+
+    /// Checks whether a refresh now would be accepted
+    ///
+    /// # Errors
+    /// `DeviceError::WatchdogEarlyRefresh` if the window is still closed; a
+    /// refresh then resets the MCU
+    pub fn check_window(&self) -> Result<(), DeviceError> {
+        if self.wwdg.cr().read().bits() & COUNTER_MAX > WINDOW {
+            Err(DeviceError::WatchdogEarlyRefresh)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reloads the counter
+    ///
+    /// Resets the MCU if the window is still closed, see `check_window`.
+    pub fn refresh(&mut self) {
+        self.wwdg
+            .cr()
+            .write(|w| unsafe { w.bits(CR_WDGA | COUNTER_MAX) });
+        REFRESHES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Takes a pending early wakeup interrupt
+///
+/// For the WWDG interrupt handler. The flag is cleared but the counter is
+/// not refreshed, so the reset still follows one tick later.
+///
+/// # Returns
+/// `true` if the early wakeup flag was set
+pub fn take_early_wakeup() -> bool {
+    // SAFETY: SR is write-zero-to-clear; EWIF is its only bit
+    let wwdg = unsafe { &*WWDG::ptr() };
+    if wwdg.sr().read().bits() & SR_EWIF == 0 {
+        return false;
+    }
+    wwdg.sr().write(|w| unsafe { w.bits(0) });
+    true
+}
+
+/// Sets the prescaler for the PCLK1 of a clock level
+///
+/// Called by `clock_scaling::set_level` with interrupts disabled, so no
+/// refresh sees the old prescaler at the new clock.
+pub fn follow_clock(level: ClockLevel) {
+    // SAFETY: CFR is only written here and in `Wwdg::new`, which runs
+    // before any clock switch
+    unsafe {
+        (*WWDG::ptr())
+            .cfr()
+            .write(|w| w.bits(cfr_bits(level.pclk1())))
+    };
+}
+
+/// Writes the refresh period, the window at the current clock and the reset
+/// cause
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let pclk1 = clock_scaling::level().pclk1();
+    let timeout = timeout_us(pclk1, prescaler(pclk1).min(PRESCALER_MAX));
+    writeln!(
+        out,
+        "Window watchdog: {}, refresh {} ms, window {}-{} us, {} refreshes, last reset by window watchdog: {}",
+        if is_running() { "running" } else { "off" },
+        WWDG_REFRESH_MS,
+        timeout / TIMEOUT_TICKS * WINDOW_OPEN_TICKS,
+        timeout,
+        REFRESHES.load(Ordering::Relaxed),
+        if caused_last_reset() { "yes" } else { "no" }
+    )
+}
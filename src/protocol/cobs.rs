@@ -0,0 +1,105 @@
+//! # COBS
+//!
+//! Consistent Overhead Byte Stuffing: removes all zero bytes from a buffer so
+//! that 0x00 can delimit frames on a byte stream:
+//! - At most one byte of overhead per 254 input bytes, plus one
+//! - The delimiter is not part of the encoding; the framer appends it
+//! - Decoding rejects embedded zeros and truncated blocks
+
+use crate::errors::errors::ProtocolError;
+
+/// Longest code block: 254 data bytes
+const MAX_CODE: u8 = 0xFF;
+
+/// Worst-case encoded length for `len` input bytes
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / (MAX_CODE as usize - 1) + 1
+}
+
+/// Encodes a buffer
+///
+/// # Arguments
+/// * `src` - Raw bytes
+/// * `dst` - Destination; `max_encoded_len(src.len())` bytes always suffice
+///
+/// # Returns
+/// Encoded length; the output contains no zero bytes
+pub fn encode(src: &[u8], dst: &mut [u8]) -> Result<usize, ProtocolError> {
+    let mut code_index = 0;
+    let mut out = 1;
+    let mut code: u8 = 1;
+
+    for &byte in src {
+        if byte == 0 {
+            put(dst, code_index, code)?;
+            code_index = out;
+            out += 1;
+            code = 1;
+            continue;
+        }
+
+        put(dst, out, byte)?;
+        out += 1;
+        code += 1;
+
+        if code == MAX_CODE {
+            put(dst, code_index, code)?;
+            code_index = out;
+            out += 1;
+            code = 1;
+        }
+    }
+
+    put(dst, code_index, code)?;
+    Ok(out)
+}
+
+// Bounds-checked store into the output buffer
+fn put(dst: &mut [u8], index: usize, byte: u8) -> Result<(), ProtocolError> {
+    *dst.get_mut(index).ok_or(ProtocolError::BufferTooSmall)? = byte;
+    Ok(())
+}
+
+/// Decodes one frame
+///
+/// # Arguments
+/// * `src` - Encoded bytes without the delimiter
+/// * `dst` - Destination for the raw bytes
+///
+/// # Returns
+/// Decoded length
+///
+/// # Errors
+/// - `Malformed` for a zero byte or a block running past the end
+/// - `BufferTooSmall` if `dst` cannot hold the result
+pub fn decode(src: &[u8], dst: &mut [u8]) -> Result<usize, ProtocolError> {
+    let mut read = 0;
+    let mut write = 0;
+
+    while read < src.len() {
+        let code = src[read];
+        if code == 0 {
+            return Err(ProtocolError::Malformed);
+        }
+        read += 1;
+
+        let run = code as usize - 1;
+        let block = src.get(read..read + run).ok_or(ProtocolError::Malformed)?;
+        if block.contains(&0) {
+            return Err(ProtocolError::Malformed);
+        }
+        dst.get_mut(write..write + run)
+            .ok_or(ProtocolError::BufferTooSmall)?
+            .copy_from_slice(block);
+        read += run;
+        write += run;
+
+        // Every block but a full one and the last stands for a zero byte
+        if code != MAX_CODE && read < src.len() {
+            put(dst, write, 0)?;
+            write += 1;
+        }
+    }
+
+    Ok(write)
+}
@@ -0,0 +1,39 @@
+//! # Software CRC32
+//!
+//! CRC-32/MPEG-2 as computed by the CRC unit (`peripherals::crc`):
+//! polynomial 0x04C11DB7, initial value 0xFFFFFFFF, no reflection, no final
+//! XOR; check value of "123456789" is 0x0376E6E7.
+//!
+//! The unit folds trailing bytes in with `update` and falls back to it while
+//! another stream holds the registers. On the host, where there is no unit,
+//! the framer checksums with `checksum` directly.
+
+/// Initial register value
+pub const INIT: u32 = 0xFFFF_FFFF;
+
+/// Generator polynomial
+const POLY: u32 = 0x04C1_1DB7;
+
+/// Continues a checksum over more data
+///
+/// # Arguments
+/// * `crc` - Checksum of the preceding data, or `INIT`
+/// * `data` - Next bytes
+pub fn update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ POLY
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Computes the checksum of a complete buffer
+pub fn checksum(data: &[u8]) -> u32 {
+    update(INIT, data)
+}
@@ -0,0 +1,247 @@
+//! # Framed UART Link
+//!
+//! Optional packet mode for the UART side of the bridge. Instead of a raw
+//! byte stream, every packet travels as one frame:
+//!
+//! ```text
+//! COBS(payload | CRC32 big-endian) | 0x00
+//! ```
+//!
+//! - TX: each chunk from the host becomes one frame (`encode_packet`)
+//! - RX: bytes are collected up to the delimiter; frames with a bad CRC or
+//!   encoding are counted and dropped, resynchronizing on the next 0x00
+//! - Valid payloads go to the dispatch callback when one is registered,
+//!   otherwise to the RX ring buffer and on to the USB host
+//!
+//! The CRC32 (CRC-32/MPEG-2) is computed by the CRC unit (`peripherals::crc`),
+//! or in software (`crc32`) in host builds.
+//!
+//! The mode is off by default (raw bridge), on with the `framed-uart`
+//! feature, and switchable at runtime with the console `framing` command.
+
+use super::cobs;
+#[cfg(not(target_os = "none"))]
+use super::crc32 as crc;
+use crate::config::FRAME_MAX_PAYLOAD_LEN;
+use crate::errors::errors::ProtocolError;
+#[cfg(target_os = "none")]
+use crate::peripherals::crc;
+use core::cell::{Cell, RefCell};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Vec;
+
+/// Frame delimiter
+pub const DELIMITER: u8 = 0x00;
+
+/// CRC32 trailer length
+const CRC_LEN: usize = 4;
+
+/// Longest encoded frame, delimiter excluded
+pub const MAX_FRAME_LEN: usize = cobs::max_encoded_len(FRAME_MAX_PAYLOAD_LEN + CRC_LEN);
+
+/// Receiver of decoded packet payloads
+pub type PacketHandler = fn(&[u8]);
+
+static ENABLED: AtomicBool = AtomicBool::new(cfg!(feature = "framed-uart"));
+static DISPATCH: Mutex<Cell<Option<PacketHandler>>> = Mutex::new(Cell::new(None));
+static DECODER: Mutex<RefCell<FrameDecoder>> = Mutex::new(RefCell::new(FrameDecoder::new()));
+
+static FRAMES_RX: AtomicU32 = AtomicU32::new(0);
+static FRAMES_TX: AtomicU32 = AtomicU32::new(0);
+static CRC_ERRORS: AtomicU32 = AtomicU32::new(0);
+static MALFORMED: AtomicU32 = AtomicU32::new(0);
+
+/// Stream decoder collecting one frame at a time
+#[derive(Debug)]
+pub struct FrameDecoder {
+    frame: Vec<u8, MAX_FRAME_LEN>,
+    discarding: bool,
+}
+
+impl FrameDecoder {
+    /// Creates a decoder waiting for the first frame
+    pub const fn new() -> Self {
+        Self {
+            frame: Vec::new(),
+            discarding: false,
+        }
+    }
+
+    /// Drops a partially received frame
+    pub fn reset(&mut self) {
+        self.frame.clear();
+        self.discarding = false;
+    }
+
+    /// Feeds one received byte
+    ///
+    /// # Arguments
+    /// * `byte` - Received byte
+    /// * `out` - Receives the payload when the byte completes a frame
+    ///
+    /// # Returns
+    /// `Some` with the payload length or the frame error at a delimiter,
+    /// `None` otherwise; empty frames are skipped
+    pub fn push(&mut self, byte: u8, out: &mut [u8]) -> Option<Result<usize, ProtocolError>> {
+        if byte != DELIMITER {
+            if !self.discarding && self.frame.push(byte).is_err() {
+                self.discarding = true;
+            }
+            return None;
+        }
+
+        let result = if self.discarding {
+            Some(Err(ProtocolError::FrameTooLong))
+        } else if self.frame.is_empty() {
+            None
+        } else {
+            Some(decode_frame(&self.frame, out))
+        };
+        self.reset();
+        result
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// COBS decoding and CRC check of one frame
+fn decode_frame(frame: &[u8], out: &mut [u8]) -> Result<usize, ProtocolError> {
+    let mut decoded = [0u8; FRAME_MAX_PAYLOAD_LEN + CRC_LEN];
+    let len = cobs::decode(frame, &mut decoded).map_err(|e| match e {
+        ProtocolError::BufferTooSmall => ProtocolError::FrameTooLong,
+        e => e,
+    })?;
+    if len < CRC_LEN {
+        return Err(ProtocolError::Malformed);
+    }
+
+    let (payload, crc) = decoded[..len].split_at(len - CRC_LEN);
+    if crc::checksum(payload) != u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]) {
+        return Err(ProtocolError::CrcMismatch);
+    }
+
+    out.get_mut(..payload.len())
+        .ok_or(ProtocolError::BufferTooSmall)?
+        .copy_from_slice(payload);
+    Ok(payload.len())
+}
+
+/// Checks whether the UART link is framed
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Switches the UART link between framed and raw mode
+///
+/// A partially received frame is dropped.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    interrupt::free(|cs| DECODER.borrow(cs).borrow_mut().reset());
+
+    #[cfg(feature = "debug")]
+    defmt::info!("UART framing {=bool}", enabled);
+}
+
+/// Registers the receiver of decoded packets
+///
+/// With `None`, payloads are forwarded to the USB host.
+pub fn set_dispatch(handler: Option<PacketHandler>) {
+    interrupt::free(|cs| DISPATCH.borrow(cs).set(handler));
+}
+
+/// Hands a payload to the registered callback
+///
+/// # Returns
+/// `false` if no callback is registered and the caller keeps the payload
+pub fn dispatch(payload: &[u8]) -> bool {
+    match interrupt::free(|cs| DISPATCH.borrow(cs).get()) {
+        Some(handler) => {
+            handler(payload);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Feeds received bytes until a frame completes
+///
+/// # Arguments
+/// * `data` - Bytes from the UART
+/// * `out` - Receives the payload of a completed frame
+///
+/// # Returns
+/// Number of bytes consumed, and the outcome of the frame completed by the
+/// last of them, if any. Call again with the rest of `data`.
+pub fn receive(
+    data: &[u8],
+    out: &mut [u8; FRAME_MAX_PAYLOAD_LEN],
+) -> (usize, Option<Result<usize, ProtocolError>>) {
+    interrupt::free(|cs| {
+        let mut decoder = DECODER.borrow(cs).borrow_mut();
+        for (i, &byte) in data.iter().enumerate() {
+            if let Some(result) = decoder.push(byte, out) {
+                count_frame(&result);
+                return (i + 1, Some(result));
+            }
+        }
+        (data.len(), None)
+    })
+}
+
+fn count_frame(result: &Result<usize, ProtocolError>) {
+    match result {
+        Ok(_) => FRAMES_RX.fetch_add(1, Ordering::Relaxed),
+        Err(ProtocolError::CrcMismatch) => CRC_ERRORS.fetch_add(1, Ordering::Relaxed),
+        Err(_) => MALFORMED.fetch_add(1, Ordering::Relaxed),
+    };
+
+    #[cfg(feature = "debug")]
+    if let Err(e) = result {
+        defmt::warn!("Framed RX: {}", e);
+    }
+}
+
+/// Encodes one packet into a frame
+///
+/// # Arguments
+/// * `payload` - At most `FRAME_MAX_PAYLOAD_LEN` bytes
+/// * `dst` - Destination for the frame, delimiter included
+///
+/// # Returns
+/// Frame length
+pub fn encode_packet(payload: &[u8], dst: &mut [u8]) -> Result<usize, ProtocolError> {
+    if payload.len() > FRAME_MAX_PAYLOAD_LEN {
+        return Err(ProtocolError::FrameTooLong);
+    }
+
+    let mut raw = [0u8; FRAME_MAX_PAYLOAD_LEN + CRC_LEN];
+    let len = payload.len();
+    raw[..len].copy_from_slice(payload);
+    raw[len..len + CRC_LEN].copy_from_slice(&crc::checksum(payload).to_be_bytes());
+
+    let encoded = cobs::encode(&raw[..len + CRC_LEN], dst)?;
+    *dst.get_mut(encoded).ok_or(ProtocolError::BufferTooSmall)? = DELIMITER;
+
+    FRAMES_TX.fetch_add(1, Ordering::Relaxed);
+    Ok(encoded + 1)
+}
+
+/// Writes the framing mode and counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Framing: {}, frames rx/tx {}/{}, crc errors {}, malformed {}",
+        if is_enabled() { "on" } else { "off" },
+        FRAMES_RX.load(Ordering::Relaxed),
+        FRAMES_TX.load(Ordering::Relaxed),
+        CRC_ERRORS.load(Ordering::Relaxed),
+        MALFORMED.load(Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,15 @@
+pub mod cobs;
+pub mod crc32;
+pub mod framer;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+#[cfg(feature = "nmea")]
+pub mod nmea;
+#[cfg(target_os = "none")]
+pub mod nonce;
+#[cfg(all(target_os = "none", feature = "can"))]
+pub mod slcan;
+#[cfg(target_os = "none")]
+pub mod transform;
+#[cfg(target_os = "none")]
+pub mod xon_xoff;
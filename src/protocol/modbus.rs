@@ -0,0 +1,356 @@
+//! # Modbus RTU Framing
+//!
+//! Request encoding and response decoding for a Modbus RTU master:
+//! - ADU: slave address, function code, data, CRC16 (polynomial 0xA001,
+//!   initial value 0xFFFF, low byte first)
+//! - Frames are delimited by 3.5 character times of line silence; above
+//!   19200 baud the fixed 1.75 ms of the specification applies
+//!   (`silence_bit_times`)
+//! - A response is complete at its expected length or at the silence,
+//!   whichever comes first (`is_complete`)
+//!
+//! | Function | Code | Request data            | Response data          |
+//! |----------|------|-------------------------|------------------------|
+//! | Read coils              | 0x01 | address, count | byte count, bits |
+//! | Read discrete inputs    | 0x02 | address, count | byte count, bits |
+//! | Read holding registers  | 0x03 | address, count | byte count, registers |
+//! | Read input registers    | 0x04 | address, count | byte count, registers |
+//! | Write single coil       | 0x05 | address, 0xFF00/0x0000 | echo |
+//! | Write single register   | 0x06 | address, value | echo |
+//! | Write multiple registers| 0x10 | address, count, byte count, registers | address, count |
+//!
+//! Broadcasts (slave 0) are not supported: every request expects a response.
+
+use crate::config::{MODBUS_MAX_READ_VALUES, MODBUS_MAX_WRITE_VALUES};
+use crate::errors::errors::ModbusError;
+use heapless::Vec;
+
+/// Longest RTU frame
+pub const MAX_ADU_LEN: usize = 256;
+
+/// Highest unicast slave address
+pub const MAX_SLAVE: u8 = 247;
+
+/// Exception responses set this bit in the function code
+const EXCEPTION_FLAG: u8 = 0x80;
+
+/// Length of an exception response: slave, function, code, CRC
+const EXCEPTION_LEN: usize = 5;
+
+/// Character time used for the 3.5 character gap: start, 8 data, parity
+/// or second stop, stop bit
+const CHARACTER_BITS: u32 = 11;
+
+/// Fixed inter-frame gap above 19200 baud (microseconds)
+const FIXED_GAP_US: u32 = 1_750;
+
+/// One RTU frame
+pub type Adu = Vec<u8, MAX_ADU_LEN>;
+
+/// Registers or coils returned by a read
+pub type Values = Vec<u16, MODBUS_MAX_READ_VALUES>;
+
+/// Supported function codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    ReadCoils = 0x01,
+    ReadDiscreteInputs = 0x02,
+    ReadHoldingRegisters = 0x03,
+    ReadInputRegisters = 0x04,
+    WriteSingleCoil = 0x05,
+    WriteSingleRegister = 0x06,
+    WriteMultipleRegisters = 0x10,
+}
+
+impl Function {
+    /// Function code on the wire
+    pub const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// Looks up a supported function code
+    pub const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x01 => Some(Self::ReadCoils),
+            0x02 => Some(Self::ReadDiscreteInputs),
+            0x03 => Some(Self::ReadHoldingRegisters),
+            0x04 => Some(Self::ReadInputRegisters),
+            0x05 => Some(Self::WriteSingleCoil),
+            0x06 => Some(Self::WriteSingleRegister),
+            0x10 => Some(Self::WriteMultipleRegisters),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the response carries values
+    pub const fn is_read(self) -> bool {
+        matches!(
+            self,
+            Self::ReadCoils
+                | Self::ReadDiscreteInputs
+                | Self::ReadHoldingRegisters
+                | Self::ReadInputRegisters
+        )
+    }
+
+    /// Checks whether the response carries single bits
+    const fn reads_bits(self) -> bool {
+        matches!(self, Self::ReadCoils | Self::ReadDiscreteInputs)
+    }
+}
+
+/// One master request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Request {
+    pub slave: u8,
+    pub function: Function,
+    /// First register or coil
+    pub address: u16,
+    /// Registers or coils read or written
+    pub count: u16,
+    /// Values written, the first `count` are used
+    pub values: [u16; MODBUS_MAX_WRITE_VALUES],
+}
+
+impl Request {
+    /// Creates a read request
+    ///
+    /// # Errors
+    /// Returns `ModbusError::InvalidRequest` for a write function, a slave
+    /// outside 1..=247, or a count outside 1..=`MODBUS_MAX_READ_VALUES` or
+    /// past the end of the address space
+    pub fn read(
+        slave: u8,
+        function: Function,
+        address: u16,
+        count: u16,
+    ) -> Result<Self, ModbusError> {
+        let request = Self {
+            slave,
+            function,
+            address,
+            count,
+            values: [0; MODBUS_MAX_WRITE_VALUES],
+        };
+        if !function.is_read() || usize::from(count) > MODBUS_MAX_READ_VALUES {
+            return Err(ModbusError::InvalidRequest);
+        }
+        request.validate()
+    }
+
+    /// Creates a write request
+    ///
+    /// One value writes a single register (0x06), or a coil (0x05, any
+    /// non-zero value switches it on); more values write multiple
+    /// registers (0x10).
+    ///
+    /// # Errors
+    /// Returns `ModbusError::InvalidRequest` for a slave outside 1..=247, no
+    /// values, more than `MODBUS_MAX_WRITE_VALUES`, or more than one coil
+    pub fn write(slave: u8, coil: bool, address: u16, values: &[u16]) -> Result<Self, ModbusError> {
+        let function = match (coil, values.len()) {
+            (true, 1) => Function::WriteSingleCoil,
+            (false, 1) => Function::WriteSingleRegister,
+            (false, 2..=MODBUS_MAX_WRITE_VALUES) => Function::WriteMultipleRegisters,
+            _ => return Err(ModbusError::InvalidRequest),
+        };
+        let mut request = Self {
+            slave,
+            function,
+            address,
+            count: values.len() as u16,
+            values: [0; MODBUS_MAX_WRITE_VALUES],
+        };
+        request.values[..values.len()].copy_from_slice(values);
+        request.validate()
+    }
+
+    fn validate(self) -> Result<Self, ModbusError> {
+        let in_range = u32::from(self.address) + u32::from(self.count) <= 0x1_0000;
+        if self.slave == 0 || self.slave > MAX_SLAVE || self.count == 0 || !in_range {
+            return Err(ModbusError::InvalidRequest);
+        }
+        Ok(self)
+    }
+
+    /// Values written
+    pub fn written(&self) -> &[u16] {
+        if self.function.is_read() {
+            &[]
+        } else {
+            &self.values[..usize::from(self.count)]
+        }
+    }
+
+    /// Encodes the request frame, CRC included
+    pub fn encode(&self) -> Adu {
+        let mut data = Adu::new();
+        match self.function {
+            Function::WriteSingleCoil => {
+                let state = if self.values[0] != 0 { 0xFF00 } else { 0x0000 };
+                push_words(&mut data, &[self.address, state]);
+            }
+            Function::WriteSingleRegister => {
+                push_words(&mut data, &[self.address, self.values[0]]);
+            }
+            Function::WriteMultipleRegisters => {
+                push_words(&mut data, &[self.address, self.count]);
+                data.push((self.count * 2) as u8).ok();
+                push_words(&mut data, self.written());
+            }
+            _ => push_words(&mut data, &[self.address, self.count]),
+        }
+        frame(self.slave, self.function.code(), &data)
+    }
+
+    /// Length of the regular response frame, CRC included
+    pub fn response_len(&self) -> usize {
+        let count = usize::from(self.count);
+        match self.function {
+            Function::ReadCoils | Function::ReadDiscreteInputs => 3 + count.div_ceil(8) + 2,
+            Function::ReadHoldingRegisters | Function::ReadInputRegisters => 3 + 2 * count + 2,
+            _ => 8,
+        }
+    }
+}
+
+/// Decoded response
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Registers, or coils and inputs as 0/1, of a read
+    Values(Values),
+    /// The write was acknowledged
+    Written,
+    /// The slave rejected the request with this exception code
+    Exception(u8),
+}
+
+/// Name of a standard exception code
+pub fn exception_name(code: u8) -> &'static str {
+    match code {
+        0x01 => "illegal function",
+        0x02 => "illegal data address",
+        0x03 => "illegal data value",
+        0x04 => "slave device failure",
+        0x05 => "acknowledge",
+        0x06 => "slave device busy",
+        0x08 => "memory parity error",
+        0x0A => "gateway path unavailable",
+        0x0B => "gateway target failed to respond",
+        _ => "unknown exception",
+    }
+}
+
+/// Checks whether the bytes received so far form the whole response
+pub fn is_complete(request: &Request, received: &[u8]) -> bool {
+    match received.get(1) {
+        Some(&function) if function == request.function.code() | EXCEPTION_FLAG => {
+            received.len() >= EXCEPTION_LEN
+        }
+        Some(_) => received.len() >= request.response_len(),
+        None => false,
+    }
+}
+
+/// Decodes the response to `request`
+///
+/// # Errors
+/// - `ModbusError::CrcMismatch` if the CRC does not match
+/// - `ModbusError::UnexpectedReply` for another slave or function
+/// - `ModbusError::Malformed` for a length that does not fit the request
+pub fn decode_response(request: &Request, frame: &[u8]) -> Result<Response, ModbusError> {
+    if frame.len() < EXCEPTION_LEN {
+        return Err(ModbusError::Malformed);
+    }
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    if crc16(body).to_le_bytes() != crc {
+        return Err(ModbusError::CrcMismatch);
+    }
+    let (slave, function, data) = (body[0], body[1], &body[2..]);
+    if slave != request.slave {
+        return Err(ModbusError::UnexpectedReply);
+    }
+    if function == request.function.code() | EXCEPTION_FLAG {
+        return Ok(Response::Exception(data[0]));
+    }
+    if function != request.function.code() {
+        return Err(ModbusError::UnexpectedReply);
+    }
+    if frame.len() != request.response_len() {
+        return Err(ModbusError::Malformed);
+    }
+
+    if !request.function.is_read() {
+        // Writes echo the address, then the value or the count
+        let echoed = u16::from_be_bytes([data[0], data[1]]);
+        return if echoed == request.address {
+            Ok(Response::Written)
+        } else {
+            Err(ModbusError::UnexpectedReply)
+        };
+    }
+
+    let (&byte_count, payload) = data.split_first().ok_or(ModbusError::Malformed)?;
+    if usize::from(byte_count) != payload.len() {
+        return Err(ModbusError::Malformed);
+    }
+    let mut values = Values::new();
+    let count = usize::from(request.count);
+    if request.function.reads_bits() {
+        for bit in 0..count {
+            let set = payload[bit / 8] & (1 << (bit % 8)) != 0;
+            values.push(u16::from(set)).ok();
+        }
+    } else {
+        for pair in payload.chunks_exact(2).take(count) {
+            values.push(u16::from_be_bytes([pair[0], pair[1]])).ok();
+        }
+    }
+    Ok(Response::Values(values))
+}
+
+/// Line silence that ends a frame, in bit times
+///
+/// 3.5 character times up to 19200 baud, 1.75 ms above.
+pub fn silence_bit_times(baud_rate: u32) -> u16 {
+    let bits = if baud_rate <= 19_200 {
+        (CHARACTER_BITS * 7).div_ceil(2)
+    } else {
+        (u64::from(baud_rate) * u64::from(FIXED_GAP_US)).div_ceil(1_000_000) as u32
+    };
+    bits.min(u32::from(u16::MAX)) as u16
+}
+
+/// Modbus CRC16 of `data`
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// Appends big-endian words
+///
+/// Never overflows: the longest request has 7 + 2 * `MODBUS_MAX_WRITE_VALUES`
+/// + 2 bytes.
+fn push_words(data: &mut Adu, words: &[u16]) {
+    for word in words {
+        data.extend_from_slice(&word.to_be_bytes()).ok();
+    }
+}
+
+/// Builds a frame from its address, function code and data
+fn frame(slave: u8, function: u8, data: &[u8]) -> Adu {
+    let mut adu = Adu::new();
+    adu.push(slave).ok();
+    adu.push(function).ok();
+    adu.extend_from_slice(data).ok();
+    let crc = crc16(&adu);
+    adu.extend_from_slice(&crc.to_le_bytes()).ok();
+    adu
+}
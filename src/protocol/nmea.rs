@@ -0,0 +1,353 @@
+//! # NMEA 0183 Sentences
+//!
+//! Sentence assembly and decoding for GNSS receivers:
+//! - A sentence runs from `$` to CR or LF, at most 82 characters; a `$`
+//!   inside a sentence starts it over
+//! - The checksum after `*` is the XOR of the characters between `$` and
+//!   `*`, as two hex digits; sentences without one are rejected
+//! - GGA (fix quality, satellites, altitude) and RMC (validity, date) are
+//!   decoded along with their time and position; other sentences are only
+//!   checked
+//! - Any talker ID is accepted (GP, GL, GA, GN, ...)
+//!
+//! Positions are fixed point in 1e-7 degrees and altitudes in decimetres,
+//! no floating point involved.
+
+use crate::errors::errors::NmeaError;
+use core::fmt;
+use heapless::Vec;
+
+/// Longest sentence, `$` and CR LF included
+pub const MAX_SENTENCE_LEN: usize = 82;
+
+/// Characters between `$` and CR LF
+const BODY_LEN: usize = MAX_SENTENCE_LEN - 3;
+
+/// Most fields of a standard sentence (GSV has 20)
+const MAX_FIELDS: usize = 24;
+
+/// Fields of one sentence, the address first
+type Fields<'a> = Vec<&'a str, MAX_FIELDS>;
+
+/// UTC time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcTime {
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl fmt::Display for UtcTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+}
+
+/// UTC date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Position in 1e-7 degrees, north and east positive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub latitude_e7: i32,
+    pub longitude_e7: i32,
+}
+
+impl fmt::Display for Position {
+    /// Writes `<latitude>,<longitude>` in decimal degrees
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_fixed(f, self.latitude_e7, 7)?;
+        f.write_str(",")?;
+        write_fixed(f, self.longitude_e7, 7)
+    }
+}
+
+/// Decoded sentence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sentence {
+    /// Fix data
+    Gga {
+        time: Option<UtcTime>,
+        position: Option<Position>,
+        /// 0 no fix, 1 GPS, 2 DGPS, 4/5 RTK, 6 dead reckoning
+        quality: u8,
+        satellites: u8,
+        /// Above mean sea level
+        altitude_dm: Option<i32>,
+    },
+    /// Recommended minimum data
+    Rmc {
+        time: Option<UtcTime>,
+        /// Status `A`
+        valid: bool,
+        position: Option<Position>,
+        date: Option<Date>,
+    },
+    /// Any other sentence with a valid checksum
+    Other,
+}
+
+/// Collects received characters into sentences
+#[derive(Debug)]
+pub struct SentenceReader {
+    body: Vec<u8, BODY_LEN>,
+    receiving: bool,
+    overflow: bool,
+}
+
+impl SentenceReader {
+    /// Creates a reader waiting for a `$`
+    pub const fn new() -> Self {
+        Self {
+            body: Vec::new(),
+            receiving: false,
+            overflow: false,
+        }
+    }
+
+    /// Feeds one received character
+    ///
+    /// # Returns
+    /// The decoded sentence once its line ends, `None` before
+    pub fn push(&mut self, byte: u8) -> Option<Result<Sentence, NmeaError>> {
+        match byte {
+            b'$' => {
+                self.body.clear();
+                self.receiving = true;
+                self.overflow = false;
+                None
+            }
+            _ if !self.receiving => None,
+            b'\r' | b'\n' => {
+                self.receiving = false;
+                if self.overflow {
+                    Some(Err(NmeaError::TooLong))
+                } else {
+                    Some(parse(&self.body))
+                }
+            }
+            _ => {
+                if self.body.push(byte).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Default for SentenceReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Checks and decodes the characters between `$` and the line end
+///
+/// # Errors
+/// - `NmeaError::Checksum` if the checksum does not match
+/// - `NmeaError::Malformed` without a checksum, or for a GGA or RMC field
+///   that does not parse
+pub fn parse(body: &[u8]) -> Result<Sentence, NmeaError> {
+    let text = core::str::from_utf8(body).map_err(|_| NmeaError::Malformed)?;
+    let (data, sum) = text.rsplit_once('*').ok_or(NmeaError::Malformed)?;
+    if sum.len() != 2 || !sum.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(NmeaError::Malformed);
+    }
+    let sum = u8::from_str_radix(sum, 16).map_err(|_| NmeaError::Malformed)?;
+    if checksum(data) != sum {
+        return Err(NmeaError::Checksum);
+    }
+
+    let mut fields = Fields::new();
+    for field in data.split(',') {
+        fields.push(field).map_err(|_| NmeaError::Malformed)?;
+    }
+    // Proprietary sentences (`$P...`) have addresses of other lengths
+    match fields[0].get(2..).filter(|_| fields[0].len() == 5) {
+        Some("GGA") => parse_gga(&fields),
+        Some("RMC") => parse_rmc(&fields),
+        _ => Ok(Sentence::Other),
+    }
+}
+
+/// XOR of the characters between `$` and `*`
+pub fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |sum, byte| sum ^ byte)
+}
+
+/// `$--GGA,time,lat,N,lon,E,quality,satellites,hdop,altitude,M,...`
+fn parse_gga(fields: &Fields) -> Result<Sentence, NmeaError> {
+    let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+    Ok(Sentence::Gga {
+        time: parse_time(field(1))?,
+        position: parse_position(field(2), field(3), field(4), field(5))?,
+        quality: parse_small(field(6))?.unwrap_or(0),
+        satellites: parse_small(field(7))?.unwrap_or(0),
+        altitude_dm: parse_fixed(field(9), 1)?
+            .map(|altitude| i32::try_from(altitude).map_err(|_| NmeaError::Malformed))
+            .transpose()?,
+    })
+}
+
+/// `$--RMC,time,status,lat,N,lon,E,speed,course,date,...`
+fn parse_rmc(fields: &Fields) -> Result<Sentence, NmeaError> {
+    let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+    Ok(Sentence::Rmc {
+        time: parse_time(field(1))?,
+        valid: field(2) == "A",
+        position: parse_position(field(3), field(4), field(5), field(6))?,
+        date: parse_date(field(9))?,
+    })
+}
+
+/// `hhmmss[.ss]`, the fraction is dropped
+fn parse_time(text: &str) -> Result<Option<UtcTime>, NmeaError> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let [hour, minute, second] = parse_pairs(text.split('.').next().unwrap_or_default())?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(NmeaError::Malformed);
+    }
+    Ok(Some(UtcTime {
+        hour,
+        minute,
+        second,
+    }))
+}
+
+/// `ddmmyy`, years from 2000
+fn parse_date(text: &str) -> Result<Option<Date>, NmeaError> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let [day, month, year] = parse_pairs(text)?;
+    if !(1..=31).contains(&day) || !(1..=12).contains(&month) {
+        return Err(NmeaError::Malformed);
+    }
+    Ok(Some(Date {
+        year: 2000 + u16::from(year),
+        month,
+        day,
+    }))
+}
+
+/// Three two-digit numbers
+fn parse_pairs(text: &str) -> Result<[u8; 3], NmeaError> {
+    let digits = text.as_bytes();
+    if digits.len() != 6 || !digits.iter().all(u8::is_ascii_digit) {
+        return Err(NmeaError::Malformed);
+    }
+    let pair = |i: usize| (digits[i] - b'0') * 10 + (digits[i + 1] - b'0');
+    Ok([pair(0), pair(2), pair(4)])
+}
+
+/// Latitude `ddmm.mmmm` and longitude `dddmm.mmmm` with their hemispheres
+fn parse_position(
+    latitude: &str,
+    north_south: &str,
+    longitude: &str,
+    east_west: &str,
+) -> Result<Option<Position>, NmeaError> {
+    if latitude.is_empty() || longitude.is_empty() {
+        return Ok(None);
+    }
+    let latitude_e7 = parse_coordinate(latitude, 2, north_south, "N", "S")?;
+    let longitude_e7 = parse_coordinate(longitude, 3, east_west, "E", "W")?;
+    if latitude_e7.abs() > 900_000_000 || longitude_e7.abs() > 1_800_000_000 {
+        return Err(NmeaError::Malformed);
+    }
+    Ok(Some(Position {
+        latitude_e7,
+        longitude_e7,
+    }))
+}
+
+/// Degrees and decimal minutes to 1e-7 degrees
+fn parse_coordinate(
+    text: &str,
+    degree_digits: usize,
+    hemisphere: &str,
+    positive: &str,
+    negative: &str,
+) -> Result<i32, NmeaError> {
+    let (degrees, minutes) = text
+        .get(..degree_digits)
+        .zip(text.get(degree_digits..))
+        .ok_or(NmeaError::Malformed)?;
+    if !degrees.bytes().all(|b| b.is_ascii_digit()) || minutes.starts_with('-') {
+        return Err(NmeaError::Malformed);
+    }
+    let degrees: i64 = degrees.parse().map_err(|_| NmeaError::Malformed)?;
+    let minutes_e5 = parse_fixed(minutes, 5)?.ok_or(NmeaError::Malformed)?;
+    if minutes_e5 >= 6_000_000 {
+        return Err(NmeaError::Malformed);
+    }
+    // 1e-7 degrees per 1e-5 minutes: 100 / 60
+    let value = degrees * 10_000_000 + minutes_e5 * 100 / 60;
+    let value = i32::try_from(value).map_err(|_| NmeaError::Malformed)?;
+    match hemisphere {
+        h if h == positive => Ok(value),
+        h if h == negative => Ok(-value),
+        _ => Err(NmeaError::Malformed),
+    }
+}
+
+/// Small decimal count, e.g. the number of satellites
+fn parse_small(text: &str) -> Result<Option<u8>, NmeaError> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.parse().map(Some).map_err(|_| NmeaError::Malformed)
+}
+
+/// Decimal number scaled by 10^`decimals`; further digits are dropped
+fn parse_fixed(text: &str, decimals: u32) -> Result<Option<i64>, NmeaError> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let digits = || whole.bytes().chain(fraction.bytes());
+    if whole.is_empty() || whole.len() > 9 || !digits().all(|b| b.is_ascii_digit()) {
+        return Err(NmeaError::Malformed);
+    }
+
+    let mut value: i64 = whole.parse().map_err(|_| NmeaError::Malformed)?;
+    let mut fraction = fraction.bytes();
+    for _ in 0..decimals {
+        let digit = fraction.next().map_or(0, |b| b - b'0');
+        value = value * 10 + i64::from(digit);
+    }
+    Ok(Some(if negative { -value } else { value }))
+}
+
+/// Writes a number scaled by 10^`decimals` with all its decimals
+pub fn write_fixed<W: fmt::Write>(out: &mut W, value: i32, decimals: u32) -> fmt::Result {
+    let scale = 10u32.pow(decimals);
+    let sign = if value < 0 { "-" } else { "" };
+    let value = value.unsigned_abs();
+    write!(
+        out,
+        "{}{}.{:0width$}",
+        sign,
+        value / scale,
+        value % scale,
+        width = decimals as usize
+    )
+}
@@ -0,0 +1,39 @@
+//! # Protocol Nonces
+//!
+//! Numbers that tie protocol exchanges to one board and one exchange:
+//! - The device nonce is drawn from the RNG once per boot by `init`; if the
+//!   generator failed, it is the CRC32 of the 96-bit unique device ID, which
+//!   still tells boards apart but repeats across boots
+//! - `next` draws a fresh nonce for each exchange, so a late reply to an
+//!   earlier exchange is not taken for an answer to the current one
+//!
+//! Nonces only tell exchanges apart; they are not secrets and need not be
+//! unpredictable.
+
+use crate::errors::errors::RngError;
+use crate::peripherals::{crc, rng};
+use crate::utils::device_id;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+static DEVICE: AtomicU32 = AtomicU32::new(0);
+
+/// Draws the device nonce
+///
+/// Called once from init, after the RNG has been enabled.
+pub fn init() {
+    let nonce = rng::next_u32().unwrap_or_else(|_| crc::checksum(&device_id::read()));
+    DEVICE.store(nonce, Ordering::Relaxed);
+}
+
+/// Nonce of this board for the current boot
+pub fn device() -> u32 {
+    DEVICE.load(Ordering::Relaxed)
+}
+
+/// Draws a fresh nonce for one exchange
+///
+/// # Errors
+/// The RNG error; callers fall back to `device`
+pub fn next() -> Result<u32, RngError> {
+    rng::next_u32()
+}
@@ -0,0 +1,185 @@
+//! # SLCAN (LAWICEL) Codec
+//!
+//! ASCII serial-line CAN protocol understood by `slcand`/can-utils,
+//! SavvyCAN and most CAN tools. Every command and frame is one line ending
+//! in CR; the adapter answers CR for success and BEL for failure.
+//!
+//! | Command            | Meaning                                   | Answer         |
+//! |--------------------|-------------------------------------------|----------------|
+//! | `S0`..`S8`         | 10k, 20k, 50k, 100k, 125k, 250k, 500k, 800k, 1M bit/s | CR |
+//! | `O` / `L` / `C`    | Open, open listen-only, close the channel | CR             |
+//! | `tiiildd..`        | Standard data frame, `l` data bytes       | `z` CR         |
+//! | `Tiiiiiiiildd..`   | Extended data frame                       | `Z` CR         |
+//! | `riiil` / `Riiiiiiiil` | Standard / extended remote frame      | `z` / `Z` CR   |
+//! | `F`                | Status flags                              | `Fxx` CR       |
+//! | `V` / `N`          | Version / serial number                   | `Vhhss` / `Nxxxx` CR |
+//! | `Z0` / `Z1`        | Receive timestamps off / on               | CR             |
+//!
+//! Received frames are sent in the transmit command format, followed by a
+//! four-digit hex millisecond timestamp (0 to 59999) while timestamps are on.
+
+use crate::peripherals::can::{CanFrame, CanId, MAX_EXTENDED_ID, MAX_STANDARD_ID};
+use heapless::Vec;
+
+/// Longest line: extended frame, 8 data bytes, timestamp and CR
+pub const MAX_LINE_LEN: usize = 1 + 8 + 1 + 16 + 4 + 1;
+
+/// Line end and success answer
+pub const OK: u8 = b'\r';
+
+/// Failure answer
+pub const ERROR: u8 = 0x07;
+
+/// Bitrates of the `S0`..`S8` commands (bit/s)
+pub const BITRATES: [u32; 9] = [
+    10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+];
+
+/// Timestamps wrap after one minute
+const TIMESTAMP_PERIOD_MS: u32 = 60_000;
+
+/// Status flag bits of the `F` answer
+pub const STATUS_RX_FULL: u8 = 1 << 0;
+pub const STATUS_TX_FULL: u8 = 1 << 1;
+pub const STATUS_ERROR_WARNING: u8 = 1 << 2;
+pub const STATUS_OVERRUN: u8 = 1 << 3;
+pub const STATUS_ERROR_PASSIVE: u8 = 1 << 5;
+pub const STATUS_BUS_ERROR: u8 = 1 << 7;
+
+/// Text line in the protocol
+pub type Line = Vec<u8, MAX_LINE_LEN>;
+
+/// Decoded command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Select the bitrate for the next open (index into `BITRATES`)
+    SetBitrate(u8),
+    /// Join the bus
+    Open,
+    /// Join the bus without acknowledging or transmitting
+    ListenOnly,
+    /// Leave the bus
+    Close,
+    /// Send a frame
+    Transmit(CanFrame),
+    /// Read the status flags
+    Status,
+    /// Read the hardware and software version
+    Version,
+    /// Read the adapter serial number
+    SerialNumber,
+    /// Switch receive timestamps off or on
+    Timestamps(bool),
+}
+
+/// Decodes one command line, without its CR
+///
+/// # Returns
+/// `None` for unknown commands and malformed arguments
+pub fn parse(line: &[u8]) -> Option<Command> {
+    let (&command, args) = line.split_first()?;
+    match (command, args) {
+        (b'S', [digit @ b'0'..=b'8']) => Some(Command::SetBitrate(digit - b'0')),
+        (b'O', []) => Some(Command::Open),
+        (b'L', []) => Some(Command::ListenOnly),
+        (b'C', []) => Some(Command::Close),
+        (b'F', []) => Some(Command::Status),
+        (b'V', []) => Some(Command::Version),
+        (b'N', []) => Some(Command::SerialNumber),
+        (b'Z', [b'0']) => Some(Command::Timestamps(false)),
+        (b'Z', [b'1']) => Some(Command::Timestamps(true)),
+        (b't' | b'T' | b'r' | b'R', _) => parse_frame(command, args).map(Command::Transmit),
+        _ => None,
+    }
+}
+
+/// Decodes the identifier, length and data of a transmit command
+fn parse_frame(command: u8, args: &[u8]) -> Option<CanFrame> {
+    let extended = command.is_ascii_uppercase();
+    let remote = command.eq_ignore_ascii_case(&b'r');
+    let id_len = if extended { 8 } else { 3 };
+    if args.len() < id_len + 1 {
+        return None;
+    }
+    let (id_text, rest) = args.split_at(id_len);
+    let raw_id = parse_hex(id_text)?;
+    let id = if extended {
+        (raw_id <= MAX_EXTENDED_ID).then_some(CanId::Extended(raw_id))?
+    } else {
+        (raw_id <= u32::from(MAX_STANDARD_ID)).then_some(CanId::Standard(raw_id as u16))?
+    };
+
+    let (&dlc, data_text) = rest.split_first()?;
+    let dlc = match dlc {
+        b'0'..=b'8' => dlc - b'0',
+        _ => return None,
+    };
+    let data_len = if remote { 0 } else { usize::from(dlc) };
+    if data_text.len() != data_len * 2 {
+        return None;
+    }
+
+    let mut data = [0u8; 8];
+    for (byte, pair) in data.iter_mut().zip(data_text.chunks_exact(2)) {
+        *byte = parse_hex(pair)? as u8;
+    }
+    Some(CanFrame {
+        id,
+        remote,
+        dlc,
+        data,
+    })
+}
+
+/// Parses up to eight hex digits
+fn parse_hex(text: &[u8]) -> Option<u32> {
+    text.iter().try_fold(0u32, |value, &digit| {
+        let nibble = char::from(digit).to_digit(16)?;
+        Some((value << 4) | nibble)
+    })
+}
+
+/// Appends `digits` hex digits of `value`, most significant first
+fn push_hex(line: &mut Line, value: u32, digits: u32) {
+    for shift in (0..digits).rev() {
+        let nibble = (value >> (shift * 4)) & 0xF;
+        // Never fails: callers stay within MAX_LINE_LEN
+        line.push(b"0123456789ABCDEF"[nibble as usize]).ok();
+    }
+}
+
+/// Encodes a received frame as a line, CR included
+///
+/// # Arguments
+/// * `frame` - Received frame
+/// * `timestamp_ms` - Receive time when timestamps are on; reduced modulo
+///   one minute
+pub fn encode_frame(frame: &CanFrame, timestamp_ms: Option<u32>) -> Line {
+    let mut line = Line::new();
+    let (command, id, id_digits) = match (frame.id, frame.remote) {
+        (CanId::Standard(id), false) => (b't', u32::from(id), 3),
+        (CanId::Standard(id), true) => (b'r', u32::from(id), 3),
+        (CanId::Extended(id), false) => (b'T', id, 8),
+        (CanId::Extended(id), true) => (b'R', id, 8),
+    };
+    line.push(command).ok();
+    push_hex(&mut line, id, id_digits);
+    push_hex(&mut line, u32::from(frame.dlc), 1);
+    for &byte in frame.payload() {
+        push_hex(&mut line, u32::from(byte), 2);
+    }
+    if let Some(ms) = timestamp_ms {
+        push_hex(&mut line, ms % TIMESTAMP_PERIOD_MS, 4);
+    }
+    line.push(OK).ok();
+    line
+}
+
+/// Encodes a one-letter answer with hex digits, e.g. `F00` CR
+pub fn encode_value(prefix: u8, value: u32, digits: u32) -> Line {
+    let mut line = Line::new();
+    line.push(prefix).ok();
+    push_hex(&mut line, value, digits);
+    line.push(OK).ok();
+    line
+}
@@ -0,0 +1,403 @@
+//! # Character Transform Stage
+//!
+//! Text-mode options of the bridge, as offered by commercial USB-serial
+//! adapters, applied per direction:
+//! - Line ending translation: `cr-crlf` turns every CR into CR LF, `crlf-cr`
+//!   turns CR LF into CR; an LF without a CR in front is kept either way,
+//!   and a CR LF split across two packets is still recognized
+//! - Strip: only printable ASCII, TAB, BS, LF and CR are passed on
+//! - Local echo: the bytes sent to the UART are also sent back to the host
+//!
+//! Host data is translated in `process_usb_data`, UART data in
+//! `process_rx_buffer`. Translated host-bound bytes that a partial USB
+//! write left over wait in the outbox, ahead of the local echo, instead of
+//! going back into the RX ring buffer, so they are never translated twice.
+//!
+//! All options are off by default (raw bridge). They are set with the
+//! console `transform` command and kept by `save`. While the USB-to-I2C
+//! bridge or SLCAN mode holds the data port, their replies pass untouched.
+
+use crate::config::TRANSFORM_OUTBOX_LEN;
+use crate::utils::statistics;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Deque;
+
+/// Options in effect, as `TransformConfig::bits`
+static CONFIG: AtomicU8 = AtomicU8::new(0);
+
+/// Line ending state of each direction
+static TO_UART: Mutex<RefCell<Translator>> = Mutex::new(RefCell::new(Translator::new()));
+static TO_HOST: Mutex<RefCell<Translator>> = Mutex::new(RefCell::new(Translator::new()));
+
+/// Host-bound bytes produced outside the RX ring buffer
+static OUTBOX: Mutex<RefCell<Deque<u8, TRANSFORM_OUTBOX_LEN>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+static STRIPPED: AtomicU32 = AtomicU32::new(0);
+static ECHO_DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Line ending translation of one direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Passed through
+    Keep = 0,
+    /// CR becomes CR LF
+    CrToCrLf = 1,
+    /// CR LF becomes CR
+    CrLfToCr = 2,
+}
+
+impl LineEnding {
+    /// All translations, in bit order
+    pub const ALL: [LineEnding; 3] = [Self::Keep, Self::CrToCrLf, Self::CrLfToCr];
+
+    /// Name used by the console
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Keep => "keep",
+            Self::CrToCrLf => "cr-crlf",
+            Self::CrLfToCr => "crlf-cr",
+        }
+    }
+
+    /// Looks up a translation by name
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|ending| ending.name() == name)
+    }
+}
+
+/// Direction of the data through the bridge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Host to UART
+    ToUart,
+    /// UART to host
+    ToHost,
+}
+
+impl Direction {
+    /// Name used by the console
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ToUart => "uart",
+            Self::ToHost => "host",
+        }
+    }
+
+    /// Looks up a direction by name
+    pub fn by_name(name: &str) -> Option<Self> {
+        [Self::ToUart, Self::ToHost]
+            .into_iter()
+            .find(|direction| direction.name() == name)
+    }
+}
+
+/// Options of one direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirectionConfig {
+    /// Line ending translation
+    pub line_ending: LineEnding,
+    /// Drops non-printable characters
+    pub strip: bool,
+}
+
+impl DirectionConfig {
+    /// Nothing changed
+    pub const RAW: Self = Self {
+        line_ending: LineEnding::Keep,
+        strip: false,
+    };
+
+    /// Checks whether data passes unchanged
+    pub fn is_raw(&self) -> bool {
+        *self == Self::RAW
+    }
+
+    fn bits(self) -> u8 {
+        self.line_ending as u8 | (u8::from(self.strip) << 2)
+    }
+
+    fn from_bits(bits: u8) -> Option<Self> {
+        Some(Self {
+            line_ending: *LineEnding::ALL.get(usize::from(bits & 0x03))?,
+            strip: bits & 0x04 != 0,
+        })
+    }
+}
+
+/// Options of both directions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransformConfig {
+    /// Host to UART
+    pub to_uart: DirectionConfig,
+    /// UART to host
+    pub to_host: DirectionConfig,
+    /// Sends the bytes for the UART back to the host as well
+    pub local_echo: bool,
+}
+
+impl Default for TransformConfig {
+    fn default() -> Self {
+        Self {
+            to_uart: DirectionConfig::RAW,
+            to_host: DirectionConfig::RAW,
+            local_echo: false,
+        }
+    }
+}
+
+impl TransformConfig {
+    /// Packs the options into one byte, for the settings store
+    pub fn bits(&self) -> u8 {
+        self.to_uart.bits() | (self.to_host.bits() << 3) | (u8::from(self.local_echo) << 6)
+    }
+
+    /// Unpacks `bits`; `None` for an unknown line ending or a reserved bit
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        if bits & 0x80 != 0 {
+            return None;
+        }
+        Some(Self {
+            to_uart: DirectionConfig::from_bits(bits & 0x07)?,
+            to_host: DirectionConfig::from_bits((bits >> 3) & 0x07)?,
+            local_echo: bits & 0x40 != 0,
+        })
+    }
+
+    /// Options of one direction
+    pub fn direction_mut(&mut self, direction: Direction) -> &mut DirectionConfig {
+        match direction {
+            Direction::ToUart => &mut self.to_uart,
+            Direction::ToHost => &mut self.to_host,
+        }
+    }
+
+    /// Returns the options with one of them changed
+    pub fn with(mut self, change: Change) -> Self {
+        match change {
+            Change::LineEnding(direction, ending) => {
+                self.direction_mut(direction).line_ending = ending
+            }
+            Change::Strip(direction, strip) => self.direction_mut(direction).strip = strip,
+            Change::LocalEcho(echo) => self.local_echo = echo,
+        }
+        self
+    }
+}
+
+/// One option change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Line ending translation of a direction
+    LineEnding(Direction, LineEnding),
+    /// Strip option of a direction
+    Strip(Direction, bool),
+    /// Local echo on or off
+    LocalEcho(bool),
+}
+
+impl fmt::Display for TransformConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (direction, options) in [
+            (Direction::ToUart, self.to_uart),
+            (Direction::ToHost, self.to_host),
+        ] {
+            write!(f, "{} {}", direction.name(), options.line_ending.name())?;
+            if options.strip {
+                f.write_str(" strip")?;
+            }
+            f.write_str(", ")?;
+        }
+        write!(f, "echo {}", if self.local_echo { "on" } else { "off" })
+    }
+}
+
+/// Line ending translator and character filter of one direction
+#[derive(Debug)]
+pub struct Translator {
+    /// The last byte passed on was a CR
+    after_cr: bool,
+}
+
+impl Translator {
+    /// Creates a translator at the start of a line
+    pub const fn new() -> Self {
+        Self { after_cr: false }
+    }
+
+    /// Translates bytes into `out`
+    ///
+    /// # Arguments
+    /// * `config` - Options of the direction
+    /// * `input` - Bytes to translate
+    /// * `out` - Destination, twice as long as `input` for `cr-crlf`
+    ///
+    /// # Returns
+    /// Number of bytes written to `out`
+    pub fn apply(&mut self, config: DirectionConfig, input: &[u8], out: &mut [u8]) -> usize {
+        let mut len = 0;
+        for &byte in input {
+            if config.strip && !is_printable(byte) {
+                STRIPPED.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+            match (config.line_ending, byte) {
+                (LineEnding::CrToCrLf, b'\r') => {
+                    out[len..len + 2].copy_from_slice(b"\r\n");
+                    len += 2;
+                }
+                (LineEnding::CrToCrLf | LineEnding::CrLfToCr, b'\n') if after_cr => {}
+                _ => {
+                    out[len] = byte;
+                    len += 1;
+                }
+            }
+        }
+        len
+    }
+}
+
+impl Default for Translator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Characters kept by the strip option
+fn is_printable(byte: u8) -> bool {
+    matches!(byte, 0x20..=0x7E | b'\t' | 0x08 | b'\n' | b'\r')
+}
+
+/// Options in effect
+pub fn config() -> TransformConfig {
+    TransformConfig::from_bits(CONFIG.load(Ordering::Relaxed)).unwrap_or_default()
+}
+
+/// Applies new options
+///
+/// Both translators restart at the beginning of a line.
+pub fn set_config(config: TransformConfig) {
+    interrupt::free(|cs| {
+        *TO_UART.borrow(cs).borrow_mut() = Translator::new();
+        *TO_HOST.borrow(cs).borrow_mut() = Translator::new();
+    });
+    CONFIG.store(config.bits(), Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Transform options: {=u8:#04x}", config.bits());
+}
+
+/// Checks whether host data goes through `to_uart`
+///
+/// Zero-copy staging of host data is off while this is true.
+pub fn is_active_to_uart() -> bool {
+    let config = config();
+    !config.to_uart.is_raw() || config.local_echo
+}
+
+/// Checks whether UART data goes through `to_host`
+pub fn is_active_to_host() -> bool {
+    !config().to_host.is_raw()
+}
+
+/// Translates host data for the UART and queues its local echo
+///
+/// # Arguments
+/// * `input` - Host bytes after the AT command filter
+/// * `out` - Destination, twice as long as `input`
+///
+/// # Returns
+/// Number of bytes for the UART
+pub fn to_uart(input: &[u8], out: &mut [u8]) -> usize {
+    let config = config();
+    let len = interrupt::free(|cs| {
+        TO_UART
+            .borrow(cs)
+            .borrow_mut()
+            .apply(config.to_uart, input, out)
+    });
+
+    if config.local_echo {
+        interrupt::free(|cs| {
+            let mut outbox = OUTBOX.borrow(cs).borrow_mut();
+            for &byte in &out[..len] {
+                if outbox.push_back(byte).is_err() {
+                    ECHO_DROPPED.fetch_add(1, Ordering::Relaxed);
+                    statistics::add_dropped(1);
+                }
+            }
+        });
+    }
+    len
+}
+
+/// Translates UART data for the host
+///
+/// # Arguments
+/// * `input` - Bytes popped from the RX ring buffer
+/// * `out` - Destination, twice as long as `input`
+///
+/// # Returns
+/// Number of bytes for the host
+pub fn to_host(input: &[u8], out: &mut [u8]) -> usize {
+    let config = config().to_host;
+    interrupt::free(|cs| TO_HOST.borrow(cs).borrow_mut().apply(config, input, out))
+}
+
+/// Checks whether host-bound bytes wait in the outbox
+pub fn has_outbox() -> bool {
+    interrupt::free(|cs| !OUTBOX.borrow(cs).borrow().is_empty())
+}
+
+/// Takes host-bound bytes from the outbox
+///
+/// # Returns
+/// Number of bytes copied to `out`
+pub fn take_outbox(out: &mut [u8]) -> usize {
+    interrupt::free(|cs| {
+        let mut outbox = OUTBOX.borrow(cs).borrow_mut();
+        let mut len = 0;
+        while len < out.len() {
+            let Some(byte) = outbox.pop_front() else {
+                break;
+            };
+            out[len] = byte;
+            len += 1;
+        }
+        len
+    })
+}
+
+/// Puts translated bytes a partial USB write left over back in front of
+/// the outbox
+///
+/// # Returns
+/// Number of bytes that did not fit and were dropped
+pub fn return_to_outbox(data: &[u8]) -> usize {
+    interrupt::free(|cs| {
+        let mut outbox = OUTBOX.borrow(cs).borrow_mut();
+        let mut dropped = 0;
+        for &byte in data.iter().rev() {
+            if outbox.push_front(byte).is_err() {
+                dropped += 1;
+            }
+        }
+        dropped
+    })
+}
+
+/// Writes the options and counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Transform: {}; {} bytes stripped, {} echo bytes dropped",
+        config(),
+        STRIPPED.load(Ordering::Relaxed),
+        ECHO_DROPPED.load(Ordering::Relaxed)
+    )
+}
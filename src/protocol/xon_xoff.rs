@@ -0,0 +1,190 @@
+//! # XON/XOFF Software Flow Control
+//!
+//! In-band flow control of the UART side, for targets without RTS/CTS
+//! wiring; a mode of the transform stage next to the character options:
+//! - `honor`: XOFF (0x13) from the UART pauses the TX DMA path, XON (0x11)
+//!   resumes it; both are taken out of the RX data (`filter_rx`)
+//! - `full`: as `honor`, and the bridge sends XOFF when the RX ring buffer
+//!   reaches its high watermark and XON once it drained to the low one
+//!   (`on_rx_level`, next to RTS in `backpressure`)
+//!
+//! A control character goes out ahead of the queued TX data, as soon as the
+//! running DMA transfer ends (`take_control`). Pausing only holds new
+//! transfers back; the one in flight still completes.
+//!
+//! The mode is off by default and set with the console `transform flow`
+//! command or `AT+FLOW=`, and kept by `save`. Being in-band, it only suits
+//! text data: with it on, no 0x11 or 0x13 byte reaches the host.
+
+use crate::data_structures::ring_buffer::FillLevel;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use stm32f4xx_hal::pac::Interrupt;
+
+/// Resume transmission (DC1)
+pub const XON: u8 = 0x11;
+
+/// Pause transmission (DC3)
+pub const XOFF: u8 = 0x13;
+
+/// Mode in effect, as `FlowMode as u8`
+static MODE: AtomicU8 = AtomicU8::new(FlowMode::Off as u8);
+
+/// The UART peer sent XOFF
+static TX_PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Control character waiting for the transmitter, 0 while none
+static PENDING: AtomicU8 = AtomicU8::new(0);
+
+/// The TX path must be restarted from the USB interrupt
+static KICK: AtomicBool = AtomicBool::new(false);
+
+static XOFF_RECEIVED: AtomicU32 = AtomicU32::new(0);
+static XON_RECEIVED: AtomicU32 = AtomicU32::new(0);
+static XOFF_SENT: AtomicU32 = AtomicU32::new(0);
+static XON_SENT: AtomicU32 = AtomicU32::new(0);
+
+/// Software flow control modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowMode {
+    /// XON and XOFF are plain data
+    Off = 0,
+    /// XON/XOFF from the UART pause and resume TX
+    Honor = 1,
+    /// As `Honor`, and XON/XOFF are sent at the RX watermarks
+    Full = 2,
+}
+
+impl FlowMode {
+    /// All modes, by value
+    pub const ALL: [FlowMode; 3] = [Self::Off, Self::Honor, Self::Full];
+
+    /// Name used by the console and, upper-cased, by `AT+FLOW`
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Honor => "honor",
+            Self::Full => "full",
+        }
+    }
+
+    /// Looks up a mode by name, in any case
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Looks up a mode by value, for the settings store
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(bits)).copied()
+    }
+}
+
+/// Mode in effect
+pub fn mode() -> FlowMode {
+    FlowMode::from_bits(MODE.load(Ordering::Relaxed)).unwrap_or(FlowMode::Off)
+}
+
+/// Switches the mode
+///
+/// A pause from the UART peer ends with it, and the TX path restarts.
+pub fn set_mode(mode: FlowMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+    PENDING.store(0, Ordering::Relaxed);
+    resume();
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Software flow control: {=str}", mode.name());
+}
+
+/// Checks whether XON and XOFF are control characters
+#[inline]
+pub fn is_enabled() -> bool {
+    MODE.load(Ordering::Relaxed) != FlowMode::Off as u8
+}
+
+/// Checks whether the UART peer paused the TX path
+#[inline]
+pub fn is_tx_paused() -> bool {
+    TX_PAUSED.load(Ordering::Relaxed)
+}
+
+/// Takes XON and XOFF out of received UART data, in place
+///
+/// Called by the DMA RX path while the mode is on.
+///
+/// # Returns
+/// Number of data bytes left at the start of `data`
+pub fn filter_rx(data: &mut [u8]) -> usize {
+    let mut len = 0;
+    for i in 0..data.len() {
+        match data[i] {
+            XOFF => {
+                XOFF_RECEIVED.fetch_add(1, Ordering::Relaxed);
+                TX_PAUSED.store(true, Ordering::Relaxed);
+            }
+            XON => {
+                XON_RECEIVED.fetch_add(1, Ordering::Relaxed);
+                resume();
+            }
+            byte => {
+                data[len] = byte;
+                len += 1;
+            }
+        }
+    }
+    len
+}
+
+/// Watermark hook of the RX ring buffer, called by `backpressure`
+///
+/// In `full` mode, queues XOFF at the high and XON at the low watermark;
+/// the USB interrupt the backpressure pends then starts the transmitter.
+pub fn on_rx_level(level: FillLevel) {
+    if mode() != FlowMode::Full {
+        return;
+    }
+    let control = if level == FillLevel::High { XOFF } else { XON };
+    PENDING.store(control, Ordering::Relaxed);
+    KICK.store(true, Ordering::Relaxed);
+}
+
+/// Takes the control character to send before any data
+pub fn take_control() -> Option<u8> {
+    let control = PENDING.swap(0, Ordering::Relaxed);
+    let sent = match control {
+        XOFF => &XOFF_SENT,
+        XON => &XON_SENT,
+        _ => return None,
+    };
+    sent.fetch_add(1, Ordering::Relaxed);
+    Some(control)
+}
+
+/// Takes the request to restart the TX path (USB interrupt)
+pub fn take_kick() -> bool {
+    KICK.swap(false, Ordering::Relaxed)
+}
+
+/// Ends a pause and has the USB interrupt restart the TX path
+fn resume() {
+    if TX_PAUSED.swap(false, Ordering::Relaxed) {
+        KICK.store(true, Ordering::Relaxed);
+        cortex_m::peripheral::NVIC::pend(Interrupt::OTG_FS);
+    }
+}
+
+/// Writes the mode, the pause state and the control character counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Flow: {}, TX {}; received {} XOFF/{} XON, sent {} XOFF/{} XON",
+        mode().name(),
+        if is_tx_paused() { "paused" } else { "running" },
+        XOFF_RECEIVED.load(Ordering::Relaxed),
+        XON_RECEIVED.load(Ordering::Relaxed),
+        XOFF_SENT.load(Ordering::Relaxed),
+        XON_SENT.load(Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,47 @@
+//! # Traffic Activity LEDs
+//!
+//! Lights the green LED (LD1) while USB data moves and the orange LED (LD2)
+//! while UART data moves. Activity is derived from the `statistics` byte
+//! counters, so the data path itself never touches the LEDs:
+//! - Sampled every `ACTIVITY_LED_MS` by the periodic scheduler
+//! - An LED stays on for one sample period after the last byte
+
+use crate::peripherals::led::{GreenLed, OrangeLed};
+use crate::utils::statistics;
+
+/// USB and UART activity indicators
+pub struct ActivityLeds {
+    usb: GreenLed,
+    uart: OrangeLed,
+    last_usb_bytes: u32,
+    last_uart_bytes: u32,
+}
+
+impl ActivityLeds {
+    /// Takes over the two LEDs
+    ///
+    /// # Arguments
+    /// * `usb` - Green LED, shows USB traffic
+    /// * `uart` - Orange LED, shows UART traffic
+    pub fn new(usb: GreenLed, uart: OrangeLed) -> Self {
+        Self {
+            usb,
+            uart,
+            last_usb_bytes: 0,
+            last_uart_bytes: 0,
+        }
+    }
+
+    /// Updates both LEDs from the byte counters
+    pub fn update(&mut self) {
+        let stats = statistics::get_stats();
+        let usb_bytes = stats.usb_rx_bytes.wrapping_add(stats.usb_tx_bytes);
+        let uart_bytes = stats.uart_rx_bytes.wrapping_add(stats.uart_tx_bytes);
+
+        self.usb.set(usb_bytes != self.last_usb_bytes);
+        self.uart.set(uart_bytes != self.last_uart_bytes);
+
+        self.last_usb_bytes = usb_bytes;
+        self.last_uart_bytes = uart_bytes;
+    }
+}
@@ -0,0 +1,256 @@
+//! # ADC Streaming
+//!
+//! Sampling settings and host streaming of the ADC scans (`adc` feature):
+//! - The sampler task scans the selected channels every period and hands
+//!   each sample to `record`; VREFINT and the temperature sensor are always
+//!   scanned for the health monitor, but only streamed when selected
+//! - While streaming, samples are formatted into records and buffered in a
+//!   byte ring of `ADC_STREAM_BUFFER_LEN`; a record that does not fit is
+//!   dropped whole and counted
+//! - The console task ships the buffer on the debug console port while a
+//!   terminal has it open, like the USB log
+//! - Off by default, set with `adc stream csv|bin|off` on the console
+//!
+//! ## CSV Format
+//! A header line `ms,<channel>,...` starts the stream and follows every
+//! channel change; each sample is then one line of the monotonic time and
+//! the readings: millivolts, VDDA in millivolts for `vref`, degrees C for
+//! `temp`. Lines end in CR LF.
+//!
+//! ## Binary Format
+//! One frame per sample, framed as on the UART link (`protocol::framer`):
+//!
+//! ```text
+//! COBS('A' | ms u32 LE | channel mask u8 | raw u16 LE per channel | CRC32 big-endian) | 0x00
+//! ```
+//!
+//! Bit n of the mask is channel n of `AdcChannel::ALL`; the raw 12-bit
+//! results follow in that order. The host converts them with its own
+//! calibration or the `adc` report.
+
+use crate::config::{ADC_SAMPLE_MS, ADC_STREAM_BUFFER_LEN};
+use crate::peripherals::adc::{self, AdcSample, ChannelSet, CHANNEL_COUNT};
+use crate::peripherals::crc;
+use crate::protocol::cobs;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::{Deque, String};
+
+/// Tag byte of a binary sample payload
+const BINARY_TAG: u8 = b'A';
+
+/// Binary payload: tag, timestamp, mask and all channels
+const BINARY_PAYLOAD_LEN: usize = 1 + 4 + 1 + 2 * CHANNEL_COUNT;
+
+/// CRC32 trailer length
+const CRC_LEN: usize = 4;
+
+/// Longest binary frame, delimiter included
+const BINARY_FRAME_LEN: usize = cobs::max_encoded_len(BINARY_PAYLOAD_LEN + CRC_LEN) + 1;
+
+/// Longest CSV line: timestamp and eight readings of up to seven characters
+const CSV_LINE_LEN: usize = 96;
+
+/// Output format of the stream
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamFormat {
+    /// Samples are kept for the report only
+    Off,
+    /// Text lines
+    Csv,
+    /// Framed binary records
+    Binary,
+}
+
+/// Pending record bytes
+static BUFFER: Mutex<RefCell<Deque<u8, ADC_STREAM_BUFFER_LEN>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+static FORMAT: AtomicU8 = AtomicU8::new(StreamFormat::Off as u8);
+static CHANNELS: AtomicU8 = AtomicU8::new(ChannelSet::ALL.bits());
+static PERIOD_MS: AtomicU32 = AtomicU32::new(ADC_SAMPLE_MS);
+
+/// The next CSV record is preceded by the header line
+static HEADER_PENDING: AtomicBool = AtomicBool::new(true);
+
+static RECORDS_QUEUED: AtomicU32 = AtomicU32::new(0);
+static RECORDS_DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Current output format
+pub fn format() -> StreamFormat {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => StreamFormat::Csv,
+        2 => StreamFormat::Binary,
+        _ => StreamFormat::Off,
+    }
+}
+
+/// Selects the output format; pending records are discarded
+pub fn set_format(format: StreamFormat) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+    HEADER_PENDING.store(true, Ordering::Relaxed);
+    interrupt::free(|cs| BUFFER.borrow(cs).borrow_mut().clear());
+}
+
+/// Channels selected for the stream
+pub fn channels() -> ChannelSet {
+    ChannelSet::from_bits(CHANNELS.load(Ordering::Relaxed))
+}
+
+/// Selects the channels of the next samples
+///
+/// The caller rejects an empty set; the sampler would report it as an error.
+pub fn set_channels(channels: ChannelSet) {
+    CHANNELS.store(channels.bits(), Ordering::Relaxed);
+    HEADER_PENDING.store(true, Ordering::Relaxed);
+}
+
+/// Sampling period (milliseconds)
+pub fn period_ms() -> u32 {
+    PERIOD_MS.load(Ordering::Relaxed)
+}
+
+/// Sets the sampling period; the caller checks `ADC_MIN_SAMPLE_MS`
+pub fn set_period_ms(period_ms: u32) {
+    PERIOD_MS.store(period_ms, Ordering::Relaxed);
+}
+
+/// Channels to scan: the selected ones and the internal sensors
+pub fn scan_channels() -> ChannelSet {
+    channels().union(ChannelSet::INTERNAL)
+}
+
+/// Formats and queues the selected channels of one sample in the current
+/// format
+pub fn record(sample: &AdcSample) {
+    let selected = sample.channels.intersection(channels());
+    match format() {
+        StreamFormat::Off => {}
+        StreamFormat::Csv => {
+            let mut line: String<CSV_LINE_LEN> = String::new();
+            if HEADER_PENDING.swap(false, Ordering::Relaxed) {
+                // Never fails: the header is shorter than a data line
+                let _ = write_csv_header(&mut line, selected);
+                queue(line.as_bytes());
+                line.clear();
+            }
+            if write_csv_line(&mut line, sample, selected).is_ok() {
+                queue(line.as_bytes());
+            }
+        }
+        StreamFormat::Binary => {
+            let mut frame = [0u8; BINARY_FRAME_LEN];
+            if let Some(len) = encode_binary(sample, selected, &mut frame) {
+                queue(&frame[..len]);
+            }
+        }
+    }
+}
+
+fn write_csv_header<W: Write>(out: &mut W, channels: ChannelSet) -> fmt::Result {
+    out.write_str("ms")?;
+    for channel in channels.iter() {
+        write!(out, ",{}", channel.name())?;
+    }
+    out.write_str("\r\n")
+}
+
+fn write_csv_line<W: Write>(out: &mut W, sample: &AdcSample, selected: ChannelSet) -> fmt::Result {
+    write!(out, "{}", sample.timestamp_ms)?;
+    for channel in selected.iter() {
+        out.write_char(',')?;
+        adc::write_reading(out, sample, channel)?;
+    }
+    out.write_str("\r\n")
+}
+
+/// Encodes a sample as a binary frame
+///
+/// # Returns
+/// Frame length, delimiter included
+fn encode_binary(
+    sample: &AdcSample,
+    selected: ChannelSet,
+    dst: &mut [u8; BINARY_FRAME_LEN],
+) -> Option<usize> {
+    let mut raw = [0u8; BINARY_PAYLOAD_LEN + CRC_LEN];
+    raw[0] = BINARY_TAG;
+    raw[1..5].copy_from_slice(&sample.timestamp_ms.to_le_bytes());
+    raw[5] = selected.bits();
+    let mut len = 6;
+    for channel in selected.iter() {
+        let value = sample.raw(channel).unwrap_or(0);
+        raw[len..len + 2].copy_from_slice(&value.to_le_bytes());
+        len += 2;
+    }
+    let checksum = crc::checksum(&raw[..len]);
+    raw[len..len + CRC_LEN].copy_from_slice(&checksum.to_be_bytes());
+
+    let encoded = cobs::encode(&raw[..len + CRC_LEN], dst).ok()?;
+    *dst.get_mut(encoded)? = 0x00;
+    Some(encoded + 1)
+}
+
+/// Queues one record whole, or counts it as dropped
+fn queue(record: &[u8]) {
+    let queued = interrupt::free(|cs| {
+        let mut buffer = BUFFER.borrow(cs).borrow_mut();
+        if buffer.capacity() - buffer.len() < record.len() {
+            return false;
+        }
+        for &byte in record {
+            // Cannot fail, the free space was checked
+            let _ = buffer.push_back(byte);
+        }
+        true
+    });
+
+    if queued {
+        RECORDS_QUEUED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        RECORDS_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Moves pending record bytes into `buf`
+///
+/// # Returns
+/// Number of bytes copied; 0 if nothing is pending
+pub fn take(buf: &mut [u8]) -> usize {
+    interrupt::free(|cs| {
+        let mut buffer = BUFFER.borrow(cs).borrow_mut();
+        let mut count = 0;
+        for slot in buf.iter_mut() {
+            match buffer.pop_front() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    })
+}
+
+/// Writes the sampling settings and stream counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    write!(out, "ADC sampling every {} ms, channels", period_ms())?;
+    for channel in channels().iter() {
+        write!(out, " {}", channel.name())?;
+    }
+    writeln!(out)?;
+    let pending = interrupt::free(|cs| BUFFER.borrow(cs).borrow().len());
+    writeln!(
+        out,
+        "  stream {}, {} records queued, {} dropped, {} bytes pending",
+        match format() {
+            StreamFormat::Off => "off",
+            StreamFormat::Csv => "csv",
+            StreamFormat::Binary => "bin",
+        },
+        RECORDS_QUEUED.load(Ordering::Relaxed),
+        RECORDS_DROPPED.load(Ordering::Relaxed),
+        pending
+    )
+}
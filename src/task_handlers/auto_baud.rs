@@ -0,0 +1,134 @@
+//! # Automatic Baud Rate Detection
+//!
+//! Finds the rate of a device of unknown speed on USART6 from its own
+//! traffic (console `autobaud start`):
+//! - The `auto_baud_detect` task routes the data port to USART6 and hands the
+//!   RX timeout timer to the edge capture (`peripherals::baud_capture`)
+//! - The TIM3 interrupt posts the measured rate (`post`)
+//! - The measurement snaps to the nearest entry of `AUTOBAUD_BAUD_RATES`
+//!   within `AUTOBAUD_TOLERANCE_PCT`, which USART6 switches to
+//! - The outcome goes to the USB log and is shown by `autobaud`; `AT+BAUD?`
+//!   reports the rate in use
+//!
+//! Without RX traffic for `AUTOBAUD_TIMEOUT_MS`, or for a measurement off
+//! the table, the old rate is kept. UART RX is dropped while the rate is
+//! measured, the measured bytes included.
+
+use crate::config::{AUTOBAUD_BAUD_RATES, AUTOBAUD_TOLERANCE_PCT};
+use core::cell::Cell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::Mutex;
+
+/// Set while a detection owns the USART6 RX path
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Rate posted by the capture interrupt, 0 while none
+static MEASURED: AtomicU32 = AtomicU32::new(0);
+
+/// Outcome of the most recent detection
+static LAST: Mutex<Cell<Option<Outcome>>> = Mutex::new(Cell::new(None));
+
+/// Result of one detection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// USART6 switched to `baud`; `measured` is the raw measurement
+    Detected { baud: u32, measured: u32 },
+    /// The measured rate is no standard rate
+    Unmatched(u32),
+    /// No RX traffic within `AUTOBAUD_TIMEOUT_MS`
+    Timeout,
+}
+
+impl Outcome {
+    /// Classifies a measured rate
+    pub fn from_measurement(measured: u32) -> Self {
+        match snap(measured) {
+            Some(baud) => Outcome::Detected { baud, measured },
+            None => Outcome::Unmatched(measured),
+        }
+    }
+
+    /// Rate to switch to, if one was detected
+    pub fn baud(&self) -> Option<u32> {
+        match *self {
+            Outcome::Detected { baud, .. } => Some(baud),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Outcome::Detected { baud, measured } => {
+                write!(f, "{} baud (measured {})", baud, measured)
+            }
+            Outcome::Unmatched(measured) => {
+                write!(f, "measured {} baud, no standard rate, kept", measured)
+            }
+            Outcome::Timeout => f.write_str("no RX traffic, rate kept"),
+        }
+    }
+}
+
+/// Standard rate within `AUTOBAUD_TOLERANCE_PCT` of a measured rate
+///
+/// # Returns
+/// The nearest entry of `AUTOBAUD_BAUD_RATES`, or `None` if all are too far
+pub fn snap(measured: u32) -> Option<u32> {
+    AUTOBAUD_BAUD_RATES
+        .iter()
+        .copied()
+        .filter(|&rate| {
+            u64::from(measured.abs_diff(rate)) * 100
+                <= u64::from(rate) * u64::from(AUTOBAUD_TOLERANCE_PCT)
+        })
+        .min_by_key(|&rate| measured.abs_diff(rate))
+}
+
+/// Marks a detection as running; UART RX is dropped from now on
+pub fn begin() {
+    MEASURED.store(0, Ordering::Relaxed);
+    ACTIVE.store(true, Ordering::SeqCst);
+}
+
+/// Checks whether UART RX must be left for the detection
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Posts a measured rate (capture interrupt)
+pub fn post(measured: u32) {
+    MEASURED.store(measured, Ordering::Relaxed);
+}
+
+/// Takes the rate posted by the capture interrupt
+pub fn take_measurement() -> Option<u32> {
+    match MEASURED.swap(0, Ordering::Relaxed) {
+        0 => None,
+        measured => Some(measured),
+    }
+}
+
+/// Records the outcome and hands UART RX back to the bridge
+pub fn finish(outcome: Outcome) {
+    cortex_m::interrupt::free(|cs| LAST.borrow(cs).set(Some(outcome)));
+    ACTIVE.store(false, Ordering::SeqCst);
+}
+
+/// Outcome of the most recent detection
+pub fn last_outcome() -> Option<Outcome> {
+    cortex_m::interrupt::free(|cs| LAST.borrow(cs).get())
+}
+
+/// Writes the detection state and the last outcome
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    if is_active() {
+        return writeln!(out, "Auto-baud: measuring on USART6");
+    }
+    match last_outcome() {
+        Some(outcome) => writeln!(out, "Auto-baud: {}", outcome),
+        None => writeln!(out, "Auto-baud: not run"),
+    }
+}
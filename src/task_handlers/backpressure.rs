@@ -0,0 +1,84 @@
+//! # Backpressure
+//!
+//! Flow control of the bridge data path from the fill levels of the ring
+//! buffers, instead of dropping data into `BufferOverflow`:
+//! - TX ring (host to UART) at `TX_HIGH_WATERMARK`: the USB handler leaves
+//!   host packets unread, so the OUT endpoint NAKs the host until the ring
+//!   drained to `TX_LOW_WATERMARK`
+//! - RX ring (UART to host) at `RX_HIGH_WATERMARK`: the RTS output (PA2) is
+//!   deasserted, whatever the host set, until the ring drained to
+//!   `RX_LOW_WATERMARK`; in the `full` XON/XOFF mode, XOFF and XON are sent
+//!   as well (`xon_xoff`)
+//!
+//! The ring buffers report the crossings through their watermark callbacks,
+//! `on_tx_level` and `on_rx_level`. Both pend the USB interrupt, which reads
+//! the held packet and drives the modem outputs.
+
+use crate::config::{RX_HIGH_WATERMARK, RX_LOW_WATERMARK, TX_HIGH_WATERMARK, TX_LOW_WATERMARK};
+use crate::data_structures::ring_buffer::{FillLevel, Watermarks};
+use crate::protocol::xon_xoff;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use stm32f4xx_hal::pac::Interrupt;
+
+/// Watermarks of the TX ring buffer
+pub const TX_WATERMARKS: Watermarks =
+    Watermarks::new(TX_HIGH_WATERMARK, TX_LOW_WATERMARK, on_tx_level);
+
+/// Watermarks of the RX ring buffer
+pub const RX_WATERMARKS: Watermarks =
+    Watermarks::new(RX_HIGH_WATERMARK, RX_LOW_WATERMARK, on_rx_level);
+
+static TX_HELD: AtomicBool = AtomicBool::new(false);
+static RX_HELD: AtomicBool = AtomicBool::new(false);
+
+static TX_HOLDS: AtomicU32 = AtomicU32::new(0);
+static RX_HOLDS: AtomicU32 = AtomicU32::new(0);
+
+/// Watermark callback of the TX ring buffer
+pub fn on_tx_level(level: FillLevel) {
+    let held = level == FillLevel::High;
+    TX_HELD.store(held, Ordering::Relaxed);
+    if held {
+        TX_HOLDS.fetch_add(1, Ordering::Relaxed);
+    }
+    // Reads the packet left in the endpoint
+    cortex_m::peripheral::NVIC::pend(Interrupt::OTG_FS);
+}
+
+/// Watermark callback of the RX ring buffer
+pub fn on_rx_level(level: FillLevel) {
+    let held = level == FillLevel::High;
+    RX_HELD.store(held, Ordering::Relaxed);
+    if held {
+        RX_HOLDS.fetch_add(1, Ordering::Relaxed);
+    }
+    xon_xoff::on_rx_level(level);
+    // Drives RTS, and sends XON/XOFF
+    cortex_m::peripheral::NVIC::pend(Interrupt::OTG_FS);
+}
+
+/// Checks whether host packets are left unread
+#[inline]
+pub fn is_tx_held() -> bool {
+    TX_HELD.load(Ordering::Relaxed)
+}
+
+/// Checks whether RTS is held deasserted
+#[inline]
+pub fn is_rx_held() -> bool {
+    RX_HELD.load(Ordering::Relaxed)
+}
+
+/// Writes the hold states and how often each side was held
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let state = |held| if held { "held" } else { "open" };
+    writeln!(
+        out,
+        "Backpressure: USB {} ({} holds), RTS {} ({} holds)",
+        state(is_tx_held()),
+        TX_HOLDS.load(Ordering::Relaxed),
+        state(is_rx_held()),
+        RX_HOLDS.load(Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,324 @@
+//! # Bridge-to-Bridge Baud Rate Negotiation
+//!
+//! Lets two boards running this firmware, wired UART-to-UART, agree on the
+//! fastest baud rate that works on their link:
+//! - Both ends start at the safe rate (`USART6_BAUD_RATE`)
+//! - The initiator proposes rates from `NEGOTIATION_BAUD_RATES`, fastest first
+//! - Both switch, exchange a test frame, and confirm; any timeout falls back to
+//!   the safe rate and the next candidate
+//!
+//! ## Frame Format
+//! `SYNC(0xA5) TYPE BAUD[4, LE] NONCE[4, LE] CHECK` where CHECK is the XOR of
+//! TYPE, BAUD and NONCE.
+//!
+//! NONCE is drawn by the initiator for each negotiation (`protocol::nonce`);
+//! the responder adopts it from PROPOSE and echoes it. Frames with another
+//! nonce are ignored, so a late reply from an earlier attempt or a third
+//! board on the line does not advance the handshake.
+//!
+//! ## Sequence
+//! ```text
+//! Initiator              Responder
+//!   PROPOSE(b) ------->
+//!              <------- ACCEPT(b)      both switch to b
+//!   TEST(b)    ------->
+//!              <------- TEST_ECHO(b)
+//!   CONFIRM(b) ------->                done
+//! ```
+
+use crate::config::{
+    NEGOTIATION_BAUD_RATES, NEGOTIATION_LISTEN_TIMEOUT_MS, NEGOTIATION_STEP_TIMEOUT_MS,
+    USART6_BAUD_RATE,
+};
+use core::sync::atomic::{AtomicBool, Ordering};
+use heapless::Vec;
+
+/// Encoded frame length
+pub const FRAME_LEN: usize = 11;
+
+/// Frame start marker
+const SYNC: u8 = 0xA5;
+
+/// Settling time after a baud switch before sending (milliseconds)
+pub const SWITCH_SETTLE_MS: u32 = 20;
+
+/// Set while a negotiation owns the UART data path
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Checks whether RX data must be left for the negotiator
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Side of the handshake this board plays
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    /// Proposes rates
+    Initiator,
+    /// Waits for proposals
+    Responder,
+}
+
+/// Negotiation frame types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Propose = 1,
+    Accept = 2,
+    Test = 3,
+    TestEcho = 4,
+    Confirm = 5,
+}
+
+/// Negotiation frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub baud: u32,
+    pub nonce: u32,
+}
+
+impl Frame {
+    /// Serializes the frame
+    pub fn encode(&self) -> [u8; FRAME_LEN] {
+        let baud = self.baud.to_le_bytes();
+        let nonce = self.nonce.to_le_bytes();
+        let kind = self.kind as u8;
+        let check = baud.iter().chain(&nonce).fold(kind, |acc, b| acc ^ b);
+        [
+            SYNC, kind, baud[0], baud[1], baud[2], baud[3], nonce[0], nonce[1], nonce[2], nonce[3],
+            check,
+        ]
+    }
+
+    /// Parses a complete frame
+    fn decode(bytes: &[u8; FRAME_LEN]) -> Option<Self> {
+        let check = bytes[1..10].iter().fold(0, |acc, b| acc ^ b);
+        if bytes[0] != SYNC || check != bytes[10] {
+            return None;
+        }
+
+        let kind = match bytes[1] {
+            1 => FrameKind::Propose,
+            2 => FrameKind::Accept,
+            3 => FrameKind::Test,
+            4 => FrameKind::TestEcho,
+            5 => FrameKind::Confirm,
+            _ => return None,
+        };
+        let baud = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
+        let nonce = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+        Some(Self { kind, baud, nonce })
+    }
+}
+
+/// Byte-wise frame assembler that resynchronizes on SYNC
+#[derive(Default)]
+pub struct FrameAssembler {
+    buffer: [u8; FRAME_LEN],
+    length: usize,
+}
+
+impl FrameAssembler {
+    /// Feeds one received byte
+    ///
+    /// # Returns
+    /// `Some(Frame)` when a valid frame has been completed
+    pub fn feed(&mut self, byte: u8) -> Option<Frame> {
+        if self.length == 0 && byte != SYNC {
+            return None;
+        }
+
+        self.buffer[self.length] = byte;
+        self.length += 1;
+
+        if self.length < FRAME_LEN {
+            return None;
+        }
+
+        self.length = 0;
+        Frame::decode(&self.buffer)
+    }
+}
+
+/// Single operation requested from the driving task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// Transmit a frame and wait until it has left the shift register
+    Send(Frame),
+    /// Reprogram the UART baud rate
+    SwitchBaud(u32),
+    /// Pause before the next operation (milliseconds)
+    Delay(u32),
+    /// Negotiation over; the UART runs at this rate
+    Finish(u32),
+}
+
+/// Operations to execute before waiting for the next frame
+pub type Ops = Vec<Op, 4>;
+
+/// Internal protocol state
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    AwaitAccept,
+    AwaitTestEcho,
+    Listening,
+    AwaitTest,
+    AwaitConfirm,
+    Done,
+}
+
+/// Negotiation state machine
+pub struct Negotiator {
+    role: Role,
+    state: State,
+    candidate: usize,
+    listened_ms: u32,
+    nonce: u32,
+}
+
+impl Negotiator {
+    /// Creates a negotiator and claims the UART data path
+    ///
+    /// # Arguments
+    /// * `role` - Side of the handshake
+    /// * `nonce` - Nonce of this negotiation; a responder replaces it with
+    ///   the one of the proposal it answers
+    pub fn new(role: Role, nonce: u32) -> Self {
+        ACTIVE.store(true, Ordering::SeqCst);
+        Self {
+            role,
+            state: State::Done,
+            candidate: 0,
+            listened_ms: 0,
+            nonce,
+        }
+    }
+
+    /// Timeout for the current wait (milliseconds)
+    pub fn timeout_ms(&self) -> u32 {
+        NEGOTIATION_STEP_TIMEOUT_MS
+    }
+
+    /// Produces the opening operations
+    pub fn start(&mut self) -> Ops {
+        let mut ops = Ops::new();
+        ops.push(Op::SwitchBaud(USART6_BAUD_RATE)).ok();
+
+        match self.role {
+            Role::Initiator => {
+                self.candidate = 0;
+                self.propose(&mut ops);
+            }
+            Role::Responder => self.state = State::Listening,
+        }
+        ops
+    }
+
+    /// Advances the state machine on a received frame
+    pub fn on_frame(&mut self, frame: Frame) -> Ops {
+        let mut ops = Ops::new();
+        let baud = frame.baud;
+        let ours = frame.nonce == self.nonce;
+
+        match (self.state, frame.kind) {
+            // Initiator
+            (State::AwaitAccept, FrameKind::Accept) if ours && baud == self.current() => {
+                ops.push(Op::SwitchBaud(baud)).ok();
+                ops.push(Op::Delay(SWITCH_SETTLE_MS)).ok();
+                ops.push(Op::Send(self.frame(FrameKind::Test, baud))).ok();
+                self.state = State::AwaitTestEcho;
+            }
+            (State::AwaitTestEcho, FrameKind::TestEcho) if ours && baud == self.current() => {
+                ops.push(Op::Send(self.frame(FrameKind::Confirm, baud)))
+                    .ok();
+                self.finish(&mut ops, baud);
+            }
+            // Responder
+            (State::Listening, FrameKind::Propose) if NEGOTIATION_BAUD_RATES.contains(&baud) => {
+                self.nonce = frame.nonce;
+                ops.push(Op::Send(self.frame(FrameKind::Accept, baud))).ok();
+                ops.push(Op::SwitchBaud(baud)).ok();
+                self.state = State::AwaitTest;
+            }
+            (State::AwaitTest, FrameKind::Test) if ours => {
+                ops.push(Op::Send(self.frame(FrameKind::TestEcho, baud)))
+                    .ok();
+                self.state = State::AwaitConfirm;
+            }
+            (State::AwaitConfirm, FrameKind::Confirm) if ours => self.finish(&mut ops, baud),
+            _ => {
+                #[cfg(feature = "debug")]
+                defmt::trace!("Negotiation: unexpected frame {}", frame.kind as u8);
+            }
+        }
+        ops
+    }
+
+    /// Advances the state machine when no frame arrived in time
+    pub fn on_timeout(&mut self) -> Ops {
+        let mut ops = Ops::new();
+
+        match self.state {
+            State::AwaitAccept | State::AwaitTestEcho => {
+                ops.push(Op::SwitchBaud(USART6_BAUD_RATE)).ok();
+                self.candidate += 1;
+                if self.candidate < NEGOTIATION_BAUD_RATES.len() {
+                    self.propose(&mut ops);
+                } else {
+                    self.finish(&mut ops, USART6_BAUD_RATE);
+                }
+            }
+            State::AwaitTest | State::AwaitConfirm => {
+                ops.push(Op::SwitchBaud(USART6_BAUD_RATE)).ok();
+                self.state = State::Listening;
+            }
+            State::Listening => {
+                self.listened_ms += self.timeout_ms();
+                if self.listened_ms >= NEGOTIATION_LISTEN_TIMEOUT_MS {
+                    self.finish(&mut ops, USART6_BAUD_RATE);
+                }
+            }
+            State::Done => {}
+        }
+        ops
+    }
+
+    /// Candidate currently being tried
+    fn current(&self) -> u32 {
+        NEGOTIATION_BAUD_RATES[self.candidate]
+    }
+
+    /// Frame of this negotiation
+    fn frame(&self, kind: FrameKind, baud: u32) -> Frame {
+        Frame {
+            kind,
+            baud,
+            nonce: self.nonce,
+        }
+    }
+
+    fn propose(&mut self, ops: &mut Ops) {
+        let baud = self.current();
+        ops.push(Op::Send(self.frame(FrameKind::Propose, baud)))
+            .ok();
+        self.state = State::AwaitAccept;
+
+        #[cfg(feature = "debug")]
+        defmt::info!("Negotiation: proposing {} baud", baud);
+    }
+
+    fn finish(&mut self, ops: &mut Ops, baud: u32) {
+        ops.push(Op::Finish(baud)).ok();
+        self.state = State::Done;
+        ACTIVE.store(false, Ordering::SeqCst);
+
+        #[cfg(feature = "debug")]
+        defmt::info!("Negotiation finished at {} baud", baud);
+    }
+}
+
+impl Drop for Negotiator {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
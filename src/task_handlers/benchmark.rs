@@ -0,0 +1,366 @@
+//! # Throughput Benchmark
+//!
+//! Self-test of the UART data path with a loopback jumper (TX wired to RX,
+//! and RTS to CTS when hardware flow control is on):
+//! - The DMA TX handler sends a PRBS-15 pattern (x^15 + x^14 + 1, ITU-T
+//!   O.150) instead of host data, back to back for the whole run
+//! - The DMA RX handlers check the received bytes against the pattern
+//!   instead of passing them to the host
+//! - The benchmark task samples the byte counters every second and reports
+//!   the rates, pattern errors and lost bytes at the end
+//!
+//! The checker is self-synchronizing: it predicts each bit from the 15 bits
+//! received before it, so it locks on after two bytes and again after a lost
+//! byte. A flipped bit on the line is counted three times, once itself and
+//! once at each feedback tap.
+//!
+//! Host data is held in the staging and TX ring buffers while a run is in
+//! progress, and the route cannot be switched.
+
+use crate::bridge::UartPort;
+use crate::peripherals::otg_fs::OtgFsController;
+use crate::task_handlers::console::CrLf;
+use crate::task_handlers::bridge_mode::{self, BridgeMode};
+use crate::task_handlers::{baud_negotiation, safe_mode, target_probe};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU16, AtomicU32, AtomicU8, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::String;
+
+/// Capacity of the report sent at the end of a run
+const REPORT_LEN: usize = 192;
+
+/// Generator register after a start; any non-zero value works
+const SEED: u16 = 0x7FFF;
+
+/// PRBS-15 register width mask
+const MASK: u16 = 0x7FFF;
+
+/// Bits the checker needs before it can predict one
+const LOCK_BITS: u8 = 15;
+
+/// Run phases
+const IDLE: u8 = 0;
+const RUNNING: u8 = 1;
+const DRAINING: u8 = 2;
+
+static PHASE: AtomicU8 = AtomicU8::new(IDLE);
+
+/// Generator and checker registers between DMA transfers
+static GENERATOR: AtomicU16 = AtomicU16::new(SEED);
+static CHECK_HISTORY: AtomicU16 = AtomicU16::new(0);
+static CHECK_LOCK: AtomicU8 = AtomicU8::new(0);
+
+/// Counters of the current run
+static TX_BYTES: AtomicU32 = AtomicU32::new(0);
+static RX_BYTES: AtomicU32 = AtomicU32::new(0);
+static PATTERN_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// Result of the most recent run
+static LAST_REPORT: Mutex<RefCell<Option<BenchReport>>> = Mutex::new(RefCell::new(None));
+
+/// PRBS-15 generator, bits sent least significant first like the UART
+#[derive(Debug, Clone, Copy)]
+pub struct Prbs15 {
+    state: u16,
+}
+
+impl Prbs15 {
+    /// Creates a generator at the seed
+    pub const fn new() -> Self {
+        Self { state: SEED }
+    }
+
+    fn next_bit(&mut self) -> u8 {
+        let bit = ((self.state >> 14) ^ (self.state >> 13)) & 1;
+        self.state = ((self.state << 1) | bit) & MASK;
+        bit as u8
+    }
+
+    /// Fills a buffer with the next bytes of the pattern
+    pub fn fill(&mut self, buffer: &mut [u8]) {
+        for byte in buffer.iter_mut() {
+            *byte = (0..8).fold(0, |acc, i| acc | (self.next_bit() << i));
+        }
+    }
+}
+
+impl Default for Prbs15 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Self-synchronizing PRBS-15 checker
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrbsChecker {
+    /// Last 15 received bits, the newest in bit 0
+    history: u16,
+    /// Bits received before the checker locked on
+    lock: u8,
+}
+
+impl PrbsChecker {
+    /// Creates a checker that has not locked on yet
+    pub const fn new() -> Self {
+        Self {
+            history: 0,
+            lock: 0,
+        }
+    }
+
+    /// Checks received bytes against the pattern
+    ///
+    /// # Returns
+    /// Number of bits that differ from the prediction
+    pub fn check(&mut self, data: &[u8]) -> u32 {
+        let mut errors = 0;
+        for &byte in data {
+            for i in 0..8 {
+                let bit = u16::from(byte >> i) & 1;
+                let expected = ((self.history >> 14) ^ (self.history >> 13)) & 1;
+                self.history = ((self.history << 1) | bit) & MASK;
+                if self.lock < LOCK_BITS {
+                    self.lock += 1;
+                } else if bit != expected {
+                    errors += 1;
+                }
+            }
+        }
+        errors
+    }
+}
+
+/// Per-second receive rate extremes of a run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateMeter {
+    last_rx: u32,
+    min: Option<u32>,
+    max: u32,
+}
+
+impl RateMeter {
+    /// Creates a meter with no samples
+    pub const fn new() -> Self {
+        Self {
+            last_rx: 0,
+            min: None,
+            max: 0,
+        }
+    }
+
+    /// Takes the one-second sample; call once per second of the run
+    pub fn sample(&mut self) {
+        let rx = RX_BYTES.load(Ordering::Relaxed);
+        let rate = rx.wrapping_sub(self.last_rx);
+        self.last_rx = rx;
+        self.min = Some(self.min.map_or(rate, |min| min.min(rate)));
+        self.max = self.max.max(rate);
+    }
+}
+
+/// Outcome of one benchmark run
+#[derive(Debug, Clone, Copy)]
+pub struct BenchReport {
+    /// UART under test
+    pub port: UartPort,
+    /// Its baud rate
+    pub baud: u32,
+    /// Run length in seconds
+    pub seconds: u32,
+    /// Pattern bytes handed to the DMA
+    pub tx_bytes: u32,
+    /// Bytes received during the run and the drain
+    pub rx_bytes: u32,
+    /// Received bits that differ from the pattern
+    pub pattern_errors: u32,
+    /// Slowest second (bytes/s)
+    pub min_rate: u32,
+    /// Fastest second (bytes/s)
+    pub max_rate: u32,
+}
+
+impl BenchReport {
+    /// Writes the rates, errors and lost bytes on one line
+    pub fn write_report<W: Write>(&self, out: &mut W) -> fmt::Result {
+        let seconds = self.seconds.max(1);
+        writeln!(
+            out,
+            "Benchmark {} at {} baud, {} s: TX {} B/s, RX {} B/s (min {}, max {}), \
+             {} pattern errors in {} bits, {} bytes lost",
+            self.port.name(),
+            self.baud,
+            self.seconds,
+            self.tx_bytes / seconds,
+            self.rx_bytes / seconds,
+            self.min_rate,
+            self.max_rate,
+            self.pattern_errors,
+            u64::from(self.rx_bytes) * 8,
+            self.tx_bytes.saturating_sub(self.rx_bytes)
+        )
+    }
+}
+
+/// Checks whether the DMA TX handler sends the pattern
+#[inline]
+pub fn is_running() -> bool {
+    PHASE.load(Ordering::Relaxed) == RUNNING
+}
+
+/// Checks whether a run owns the UART data path (running or draining)
+#[inline]
+pub fn is_active() -> bool {
+    PHASE.load(Ordering::Relaxed) != IDLE
+}
+
+/// Starts a run on the routed UART
+///
+/// Resets the generator, the checker and the counters.
+///
+/// # Errors
+/// A short reason if the data path is taken or the UART is off
+pub fn start() -> Result<(), &'static str> {
+    if target_probe::is_active() || baud_negotiation::is_active() {
+        return Err("busy, probe or negotiation running");
+    }
+    if safe_mode::is_active() {
+        return Err("UART off in safe mode");
+    }
+    if bridge_mode::current() != BridgeMode::Bridge {
+        return Err("data port in an echo mode");
+    }
+
+    PHASE
+        .compare_exchange(IDLE, RUNNING, Ordering::SeqCst, Ordering::SeqCst)
+        .map_err(|_| "benchmark already running")?;
+
+    // Nothing is sent before the caller kicks the TX path
+    GENERATOR.store(SEED, Ordering::Relaxed);
+    CHECK_HISTORY.store(0, Ordering::Relaxed);
+    CHECK_LOCK.store(0, Ordering::Relaxed);
+    TX_BYTES.store(0, Ordering::Relaxed);
+    RX_BYTES.store(0, Ordering::Relaxed);
+    PATTERN_ERRORS.store(0, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stops sending the pattern; bytes still on the way are checked
+pub fn drain() {
+    PHASE.store(DRAINING, Ordering::SeqCst);
+}
+
+/// Ends the run and keeps its report
+///
+/// # Arguments
+/// * `port` - UART under test
+/// * `baud` - Its baud rate
+/// * `seconds` - Run length
+/// * `meter` - Per-second samples of the run
+pub fn finish(port: UartPort, baud: u32, seconds: u32, meter: &RateMeter) -> BenchReport {
+    let report = BenchReport {
+        port,
+        baud,
+        seconds,
+        tx_bytes: TX_BYTES.load(Ordering::Relaxed),
+        rx_bytes: RX_BYTES.load(Ordering::Relaxed),
+        pattern_errors: PATTERN_ERRORS.load(Ordering::Relaxed),
+        min_rate: meter.min.unwrap_or(0),
+        max_rate: meter.max,
+    };
+    interrupt::free(|cs| *LAST_REPORT.borrow(cs).borrow_mut() = Some(report));
+    PHASE.store(IDLE, Ordering::SeqCst);
+
+    #[cfg(feature = "debug")]
+    defmt::info!(
+        "Benchmark: {} bytes sent, {} received, {} pattern errors",
+        report.tx_bytes,
+        report.rx_bytes,
+        report.pattern_errors
+    );
+    report
+}
+
+/// Fills a DMA TX buffer with the next pattern bytes
+///
+/// Called by the DMA TX handler while `is_running`.
+pub fn fill_tx(buffer: &mut [u8]) {
+    let mut generator = Prbs15 {
+        state: GENERATOR.load(Ordering::Relaxed),
+    };
+    generator.fill(buffer);
+    GENERATOR.store(generator.state, Ordering::Relaxed);
+    TX_BYTES.fetch_add(buffer.len() as u32, Ordering::Relaxed);
+}
+
+/// Checks bytes received from the DMA RX buffer
+///
+/// Called by the DMA RX handlers while `is_active`; the bytes are not
+/// passed on to the host.
+pub fn check_rx(data: &[u8]) {
+    let mut checker = PrbsChecker {
+        history: CHECK_HISTORY.load(Ordering::Relaxed),
+        lock: CHECK_LOCK.load(Ordering::Relaxed),
+    };
+    let errors = checker.check(data);
+    CHECK_HISTORY.store(checker.history, Ordering::Relaxed);
+    CHECK_LOCK.store(checker.lock, Ordering::Relaxed);
+    RX_BYTES.fetch_add(data.len() as u32, Ordering::Relaxed);
+    PATTERN_ERRORS.fetch_add(errors, Ordering::Relaxed);
+}
+
+/// Returns the report of the most recent run
+pub fn last_report() -> Option<BenchReport> {
+    interrupt::free(|cs| *LAST_REPORT.borrow(cs).borrow())
+}
+
+/// Writes the state of a run in progress, or the last report
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    if is_active() {
+        return writeln!(
+            out,
+            "Benchmark running: {} bytes sent, {} received, {} pattern errors",
+            TX_BYTES.load(Ordering::Relaxed),
+            RX_BYTES.load(Ordering::Relaxed),
+            PATTERN_ERRORS.load(Ordering::Relaxed)
+        );
+    }
+    match last_report() {
+        Some(report) => report.write_report(out),
+        None => writeln!(out, "Benchmark: no run yet"),
+    }
+}
+
+/// Sends the outcome of a run to the host
+///
+/// Goes to the debug console while a terminal has it open, and to the
+/// debug channel otherwise (with `debug`), like the statistics report.
+///
+/// # Arguments
+/// * `outcome` - The report, or the reason the run did not start
+pub fn deliver(usb: &mut OtgFsController<'static>, outcome: Result<&BenchReport, &str>) {
+    let mut text: String<REPORT_LEN> = String::new();
+
+    if usb.console_connected() {
+        if write_outcome(&mut CrLf(&mut text), outcome).is_err()
+            || usb.console_write(text.as_bytes()).is_err()
+        {
+            #[cfg(feature = "debug")]
+            defmt::warn!("Benchmark report not delivered");
+        }
+        return;
+    }
+
+    #[cfg(feature = "debug")]
+    if write_outcome(&mut text, outcome).is_ok() {
+        defmt::info!("{=str}", text.trim_end());
+    }
+}
+
+fn write_outcome<W: Write>(out: &mut W, outcome: Result<&BenchReport, &str>) -> fmt::Result {
+    match outcome {
+        Ok(report) => report.write_report(out),
+        Err(reason) => writeln!(out, "Benchmark not started: {}", reason),
+    }
+}
@@ -1,14 +1,35 @@
 //! # Blue LED Control Utilities
 //!
 //! Provides timing constants and state management for blue LED operations.
+//!
+//! With the `led-pwm` feature the LED is dimmed instead of switched, and
+//! `Breather` renders the status patterns: slow breathing while idle, fast
+//! breathing during bridge traffic, solid while an error code plays.
 
-use crate::peripherals::blue_led::BlueLed;
+use crate::peripherals::led::BlueLed;
 use crate::peripherals::traits::GpioPin;
+#[cfg(feature = "led-pwm")]
+use crate::{
+    config::SAFE_MODE_BLINK_MS,
+    peripherals::led_pwm::{self, PwmChannel, MAX_LEVEL},
+    utils::statistics,
+};
 
 /// LED timing constants (milliseconds)
 pub const LED_ON_DURATION: u32 = 4_000; // Active state duration
 pub const LED_OFF_DURATION: u32 = 1_000; // Inactive state duration
 pub const LED_CHECK_INTERVAL: u32 = 60_000; // Status check interval
+pub const LED_SUSPEND_POLL: u32 = 250; // Resume check interval while USB is suspended
+
+/// Breathing timing constants (milliseconds)
+#[cfg(feature = "led-pwm")]
+pub const BREATHE_SLOW_MS: u32 = 4_000; // Full fade in and out while idle
+#[cfg(feature = "led-pwm")]
+pub const BREATHE_FAST_MS: u32 = 800; // Full fade in and out during traffic
+#[cfg(feature = "led-pwm")]
+pub const BREATHE_STEP_MS: u32 = 20; // Brightness update interval
+#[cfg(feature = "led-pwm")]
+pub const TRAFFIC_HOLD_MS: u32 = 1_000; // Fast breathing after the last traffic
 
 /// LED operational states
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -70,3 +91,89 @@ pub fn toggle_led(led: &mut BlueLed) -> u32 {
         }
     }
 }
+
+/// Blue LED patterns in PWM mode
+#[cfg(feature = "led-pwm")]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum BluePattern {
+    /// Released, LED off
+    Off,
+    /// Full brightness
+    Solid,
+    /// Full on/off at `SAFE_MODE_BLINK_MS`
+    Blink,
+    /// Slow breathing, fast while the bridge carries traffic
+    Breathe,
+}
+
+/// Renders `BluePattern`s on the blue LED PWM channel
+#[cfg(feature = "led-pwm")]
+#[derive(Debug, Default)]
+pub struct Breather {
+    last_bytes: u32,
+    fast_until: u32,
+}
+
+#[cfg(feature = "led-pwm")]
+impl Breather {
+    /// Creates a breather in the idle state
+    pub const fn new() -> Self {
+        Self {
+            last_bytes: 0,
+            fast_until: 0,
+        }
+    }
+
+    /// Sets the blue LED brightness for `pattern`
+    ///
+    /// # Arguments
+    /// * `pattern` - Pattern to render
+    /// * `now_ms` - Current time (milliseconds)
+    ///
+    /// # Returns
+    /// Delay until the next step in milliseconds
+    pub fn step(&mut self, pattern: BluePattern, now_ms: u32) -> u32 {
+        let (level, delay) = match pattern {
+            BluePattern::Off => (0, LED_SUSPEND_POLL),
+            BluePattern::Solid => (MAX_LEVEL, LED_SUSPEND_POLL),
+            BluePattern::Blink => {
+                let on = (now_ms / SAFE_MODE_BLINK_MS) % 2 == 0;
+                (if on { MAX_LEVEL } else { 0 }, SAFE_MODE_BLINK_MS)
+            }
+            BluePattern::Breathe => {
+                let period = if self.traffic_seen(now_ms) {
+                    BREATHE_FAST_MS
+                } else {
+                    BREATHE_SLOW_MS
+                };
+                (breathe_level(now_ms, period), BREATHE_STEP_MS)
+            }
+        };
+        led_pwm::set_level(PwmChannel::Blue, level);
+        delay
+    }
+
+    /// Checks for bridge traffic since the previous step, held for `TRAFFIC_HOLD_MS`
+    fn traffic_seen(&mut self, now_ms: u32) -> bool {
+        let stats = statistics::get_stats();
+        let bytes = stats.uart_rx_bytes.wrapping_add(stats.usb_rx_bytes);
+        if bytes != self.last_bytes {
+            self.last_bytes = bytes;
+            self.fast_until = now_ms.wrapping_add(TRAFFIC_HOLD_MS);
+        }
+        (self.fast_until.wrapping_sub(now_ms) as i32) > 0
+    }
+}
+
+/// Brightness on a triangle wave: dark at the start of each period, full at half
+#[cfg(feature = "led-pwm")]
+fn breathe_level(now_ms: u32, period_ms: u32) -> u8 {
+    let half = period_ms / 2;
+    let phase = now_ms % period_ms;
+    let ramp = if phase < half {
+        phase
+    } else {
+        period_ms - phase
+    };
+    (ramp.min(half) * MAX_LEVEL as u32 / half) as u8
+}
@@ -0,0 +1,216 @@
+//! # Bridge Mode
+//!
+//! Runtime-selectable data paths of the bridge, for testing either side on
+//! its own:
+//! - `bridge`: host data goes to the routed UART, UART data to the host
+//! - `uart-echo`: UART data is sent back out of the same UART; host data is
+//!   discarded after the AT command filter
+//! - `usb-echo`: host data is sent back to the host; UART data is skipped
+//!
+//! Each mode is one entry of a routing table that names the sink of the
+//! host data and of the UART data. The USB and UART handlers spawn the task
+//! of that sink instead of a fixed one; looped-back data is moved between
+//! the ring buffers by the `echo_data` task.
+//!
+//! The mode starts as `bridge` after every reset and is changed with
+//! `AT+MODE=` on the data port or `mode` on the debug console. While the
+//! USB-to-I2C bridge or SLCAN mode holds the data port, the bridge routes
+//! apply. Data already buffered follows the routes of the new mode.
+
+use crate::config::DATA_PACKET_SIZE;
+use crate::data_structures::spsc::{Consumer, Producer};
+#[cfg(feature = "modbus")]
+use crate::task_handlers::modbus;
+use crate::task_handlers::{auto_baud, baud_negotiation, benchmark, dma2, target_probe};
+use crate::utils::statistics;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use rtic::Mutex;
+
+/// Selected mode, stored as its index into `ROUTES`
+static MODE: AtomicU8 = AtomicU8::new(BridgeMode::Bridge as u8);
+
+static ECHOED_BYTES: AtomicU32 = AtomicU32::new(0);
+
+/// Data path configurations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BridgeMode {
+    /// USB and UART bridged to each other
+    Bridge = 0,
+    /// UART data echoed to the UART
+    UartEcho = 1,
+    /// USB data echoed to the host
+    UsbEcho = 2,
+}
+
+/// Destination of the data received on one side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    /// Transmitted on the routed UART
+    Uart,
+    /// Sent to the host on the data port
+    Usb,
+    /// Dropped
+    Discard,
+}
+
+/// Routing table entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Routes {
+    /// Sink of the data read from the host
+    pub host_data: Sink,
+    /// Sink of the data received on the routed UART
+    pub uart_data: Sink,
+}
+
+impl Routes {
+    /// Checks whether the UART TX path drains the TX ring buffer
+    pub fn feeds_uart(&self) -> bool {
+        self.host_data == Sink::Uart || self.uart_data == Sink::Uart
+    }
+
+    /// Checks whether the USB path drains the RX ring buffer
+    pub fn feeds_usb(&self) -> bool {
+        self.host_data == Sink::Usb || self.uart_data == Sink::Usb
+    }
+}
+
+/// Routing table, indexed by `BridgeMode`
+const ROUTES: [Routes; 3] = [
+    Routes {
+        host_data: Sink::Uart,
+        uart_data: Sink::Usb,
+    },
+    Routes {
+        host_data: Sink::Discard,
+        uart_data: Sink::Uart,
+    },
+    Routes {
+        host_data: Sink::Usb,
+        uart_data: Sink::Discard,
+    },
+];
+
+impl BridgeMode {
+    /// All modes, in `ROUTES` order
+    pub const ALL: [BridgeMode; 3] = [Self::Bridge, Self::UartEcho, Self::UsbEcho];
+
+    /// Name used by the AT command and the console
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Bridge => "bridge",
+            Self::UartEcho => "uart-echo",
+            Self::UsbEcho => "usb-echo",
+        }
+    }
+
+    /// Looks up a mode by name, ignoring case
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Routing table entry of the mode
+    pub const fn routes(self) -> Routes {
+        ROUTES[self as usize]
+    }
+}
+
+/// Currently selected mode
+#[inline]
+pub fn current() -> BridgeMode {
+    BridgeMode::ALL
+        .get(usize::from(MODE.load(Ordering::Relaxed)))
+        .copied()
+        .unwrap_or(BridgeMode::Bridge)
+}
+
+/// Routes in effect for the data path
+///
+/// Those of `bridge` while a bridge mode holds the data port.
+#[inline]
+pub fn routes() -> Routes {
+    if dma2::data_port_diverted() {
+        return BridgeMode::Bridge.routes();
+    }
+    current().routes()
+}
+
+/// Checks whether a host request may change the mode
+///
+/// `false` while the target probe, the baud negotiation, a baud rate
+/// detection, a Modbus transaction or a benchmark owns the UART data path.
+pub fn can_switch() -> bool {
+    #[cfg(feature = "modbus")]
+    if modbus::is_active() {
+        return false;
+    }
+    !target_probe::is_active()
+        && !baud_negotiation::is_active()
+        && !auto_baud::is_active()
+        && !benchmark::is_active()
+}
+
+/// Selects a mode
+///
+/// # Returns
+/// `true` if the mode changed
+pub fn select(mode: BridgeMode) -> bool {
+    let previous = MODE.swap(mode as u8, Ordering::Relaxed);
+    if previous == mode as u8 {
+        return false;
+    }
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Bridge mode: {=str}", mode.name());
+    true
+}
+
+/// Moves buffered data from one ring buffer to the other
+///
+/// Locks the buffers one at a time, copying through a stack buffer in
+/// between. Stops when the source is empty or the destination full.
+///
+/// # Arguments
+/// * `from` - Consumer half of the ring buffer holding the data to echo
+/// * `to` - Producer half of the ring buffer of the sending side
+///
+/// # Returns
+/// Number of bytes moved
+pub fn loop_back<const N: usize, const M: usize>(
+    from: &mut impl Mutex<T = Consumer<'static, N>>,
+    to: &mut impl Mutex<T = Producer<'static, M>>,
+) -> usize {
+    let mut chunk = [0u8; DATA_PACKET_SIZE];
+    let mut moved = 0;
+
+    loop {
+        let space = to.lock(|to| to.available_space()).min(chunk.len());
+        if space == 0 {
+            break;
+        }
+        let count = from.lock(|from| from.pop(&mut chunk[..space]));
+        if count == 0 {
+            break;
+        }
+        if to.lock(|to| to.push(&chunk[..count])).is_err() {
+            statistics::add_dropped(count);
+            break;
+        }
+        moved += count;
+    }
+
+    ECHOED_BYTES.fetch_add(moved as u32, Ordering::Relaxed);
+    moved
+}
+
+/// Writes the mode and the number of echoed bytes
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Bridge mode: {}, echoed {} bytes",
+        current().name(),
+        ECHOED_BYTES.load(Ordering::Relaxed)
+    )
+}
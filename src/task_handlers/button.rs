@@ -0,0 +1,119 @@
+//! # User Button Actions
+//!
+//! Maps short and long presses of the user button to actions:
+//! - `clear-errors`: empty the error store
+//! - `blink`: toggle the blue LED status blinking
+//! - `stats`: dump the bridge statistics (console or debug channel)
+//! - `bench`: run a throughput benchmark of `BENCH_DEFAULT_SECONDS`, the
+//!   report goes where the statistics go
+//! - `none`: ignore the press
+//!
+//! The mapping can be changed at runtime with the console `button` command.
+//! Actions that need RTIC resources are executed by the button task.
+
+use crate::peripherals::button::Press;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Number of actions
+const ACTION_COUNT: usize = 5;
+
+/// Action run on a short press until changed
+const DEFAULT_SHORT_ACTION: ButtonAction = ButtonAction::DumpStats;
+
+/// Action run on a long press until changed
+const DEFAULT_LONG_ACTION: ButtonAction = ButtonAction::ClearErrors;
+
+/// Action bound to a short press
+static SHORT_ACTION: AtomicU8 = AtomicU8::new(DEFAULT_SHORT_ACTION as u8);
+
+/// Action bound to a long press
+static LONG_ACTION: AtomicU8 = AtomicU8::new(DEFAULT_LONG_ACTION as u8);
+
+/// Actions a press can trigger
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ButtonAction {
+    /// Ignore the press
+    None,
+    /// Empty the error store
+    ClearErrors,
+    /// Toggle the blue LED status blinking
+    ToggleBlink,
+    /// Dump the bridge statistics
+    DumpStats,
+    /// Run a throughput benchmark
+    Benchmark,
+}
+
+impl ButtonAction {
+    /// All actions, indexed by their discriminant
+    pub const ALL: [ButtonAction; ACTION_COUNT] = [
+        Self::None,
+        Self::ClearErrors,
+        Self::ToggleBlink,
+        Self::DumpStats,
+        Self::Benchmark,
+    ];
+
+    /// Name used by the console
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::ClearErrors => "clear-errors",
+            Self::ToggleBlink => "blink",
+            Self::DumpStats => "stats",
+            Self::Benchmark => "bench",
+        }
+    }
+
+    /// Looks up an action by its console name
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+fn slot(press: Press) -> &'static AtomicU8 {
+    match press {
+        Press::Short => &SHORT_ACTION,
+        Press::Long => &LONG_ACTION,
+    }
+}
+
+/// Returns the action bound to a press type
+pub fn action_for(press: Press) -> ButtonAction {
+    let index = slot(press).load(Ordering::Relaxed) as usize;
+    ButtonAction::ALL
+        .get(index)
+        .copied()
+        .unwrap_or(ButtonAction::None)
+}
+
+/// Binds an action to a press type
+pub fn set_action(press: Press, action: ButtonAction) {
+    slot(press).store(action as u8, Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!(
+        "Button: {=str} press bound to {=str}",
+        press_name(press),
+        action.name()
+    );
+}
+
+/// Name of a press type used by the console
+pub fn press_name(press: Press) -> &'static str {
+    match press {
+        Press::Short => "short",
+        Press::Long => "long",
+    }
+}
+
+/// Writes the current press-to-action mapping
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Button: short={} long={}",
+        action_for(Press::Short).name(),
+        action_for(Press::Long).name()
+    )
+}
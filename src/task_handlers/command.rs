@@ -0,0 +1,536 @@
+//! # AT Command Channel
+//!
+//! In-band control of the bridge over the data CDC port, modelled on Hayes
+//! modems, for hosts that cannot open the debug console:
+//! - `+++` after `AT_GUARD_MS` of silence switches to command mode; the
+//!   escape characters are not forwarded to the UART
+//! - In command mode, host input is collected into CR-terminated lines and
+//!   nothing reaches the UART; UART data keeps flowing to the host
+//! - Each line is run by the `at_command` task, replies go to the same port
+//!
+//! | Command        | Reply                                            |
+//! |----------------|--------------------------------------------------|
+//! | `AT`           | `OK`                                             |
+//! | `ATO`          | `OK`, back to data mode                          |
+//! | `AT+BAUD?`     | `+BAUD: <rate>`                                  |
+//! | `AT+BAUD=<n>`  | `OK` after the UART switched to `n` baud         |
+//! | `AT+FRAME?`    | `+FRAME: <format>`, e.g. `+FRAME: 8N1`           |
+//! | `AT+FRAME=<f>` | `OK` after the UART switched to format `f`       |
+//! | `AT+UART?`     | `+UART: <n>`, the routed USART number            |
+//! | `AT+UART=<n>`  | `OK` after the data port moved to USART `n`      |
+//! | `AT+MODE?`     | `+MODE: <mode>`, e.g. `+MODE: BRIDGE`            |
+//! | `AT+MODE=<m>`  | `OK` once mode `m` routes the data: `BRIDGE`,    |
+//! |                | `UART-ECHO` or `USB-ECHO` (see `bridge_mode`)    |
+//! | `AT+STATS?`    | `+STATS: <uart rx>,<uart tx>,<usb rx>,<usb tx>,` |
+//! |                | `<dropped>,<usb errors>,<overrun>,<framing>,`    |
+//! |                | `<noise>,<parity>`                               |
+//! | `AT+FLOW?`     | `+FLOW: <mode>,<paused>`, XON/XOFF mode `OFF`,   |
+//! |                | `HONOR` or `FULL` and 1 while TX is paused       |
+//! | `AT+FLOW=<m>`  | `OK` once XON/XOFF mode `m` is on (`xon_xoff`)   |
+//! | `AT+POWER?`    | `+POWER: <profile>,<clock>`, e.g.                |
+//! |                | `+POWER: LOW-POWER,HALF`                         |
+//! | `AT+POWER=<p>` | `OK` once profile `p` is on: `PERFORMANCE`,      |
+//! |                | `BALANCED` or `LOW-POWER` (see `power`)          |
+//! | `AT+SERIAL?`   | `+SERIAL: <text>`, the USB serial number         |
+//! | `AT+SERIAL=<s>`| `OK`; reported from the next enumeration on      |
+//! | `AT+SAVE`      | `OK` once the settings are stored in flash       |
+//! | `AT+RESET`     | `OK`, then an orderly shutdown and reset         |
+//! | `AT+DAC?`      | `+DAC: <n>,<signal>` per channel (`dac` feature) |
+//! | `AT+DAC=<args>`| `OK` once the DAC task takes the signal; args    |
+//! |                | `<n>,OFF`, `<n>,DC,<mV>`, `<n>,SINE,<Hz>` or     |
+//! |                | `<n>,SAW,<Hz>`                                   |
+//! | `AT+GPS?`      | `+GPS: <fix>,<date>,<time>,<lat>,<lon>,<alt>,`   |
+//! |                | `<satellites>` from NMEA (`nmea` feature)        |
+//! | `AT+GPS=<m>`   | `OK`; `PASS` forwards the NMEA data, `QUERY`     |
+//! |                | keeps it for `AT+GPS?` (see `gnss`)              |
+//!
+//! Commands are case-insensitive, serial numbers are not; anything else is
+//! answered with `ERROR`. `AT+SAVE` stores the baud rates, route, framing,
+//! error signal outputs, flow control mode, power profile and serial number
+//! (see `settings`).
+//! A `+` after the guard time is held back until the next byte shows whether
+//! it starts an escape, and is then forwarded together with that byte.
+
+use crate::bridge::UartPort;
+use crate::config::{AT_GUARD_MS, AT_LINE_LEN};
+use crate::peripherals::clock_scaling;
+#[cfg(feature = "dac")]
+use crate::peripherals::dac::{self, DacChannel, Waveform};
+use crate::peripherals::uart::{BridgeUart, UartConfig};
+use crate::protocol::xon_xoff::{self, FlowMode};
+use crate::task_handlers::bridge_mode::{self, BridgeMode};
+#[cfg(feature = "nmea")]
+use crate::task_handlers::gnss;
+use crate::task_handlers::power::{self, PowerProfile};
+use crate::task_handlers::settings::{self, SerialNumber};
+use crate::task_handlers::uart_route;
+use crate::utils::statistics;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::{String, Vec};
+use rtic_monotonics::systick::prelude::*;
+
+/// Escape character, repeated `ESCAPE_LEN` times
+const ESCAPE_CHAR: u8 = b'+';
+
+/// Length of the escape sequence
+///
+/// Data given to `filter` must start at least `ESCAPE_LEN - 1` bytes into
+/// its buffer, headroom for escape characters held back from the previous
+/// packet.
+pub const ESCAPE_LEN: usize = 3;
+
+/// Reply to an unknown or failed command
+pub const ERROR_REPLY: &str = "ERROR\r\n";
+
+/// One complete command line
+pub type Line = String<AT_LINE_LEN>;
+
+/// State of the data port shared by the USB handler and the command task
+static CHANNEL: Mutex<RefCell<CommandChannel>> = Mutex::new(RefCell::new(CommandChannel::new()));
+
+/// Data port modes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Host data is bridged to the UART
+    Data,
+    /// Host input is parsed as commands
+    Command,
+}
+
+/// Parsed AT command
+#[derive(Debug, Clone, PartialEq)]
+pub enum AtCommand {
+    /// `AT`
+    Attention,
+    /// `ATO`
+    Online,
+    /// `AT+BAUD?`
+    BaudQuery,
+    /// `AT+BAUD=<n>`
+    SetBaud(u32),
+    /// `AT+FRAME?`
+    FrameQuery,
+    /// `AT+FRAME=<format>`
+    SetFrame(UartConfig),
+    /// `AT+UART?`
+    UartQuery,
+    /// `AT+UART=<n>`
+    SetUart(UartPort),
+    /// `AT+MODE?`
+    ModeQuery,
+    /// `AT+MODE=<mode>`
+    SetMode(BridgeMode),
+    /// `AT+STATS?`
+    Stats,
+    /// `AT+FLOW?`
+    FlowQuery,
+    /// `AT+FLOW=<mode>`
+    SetFlow(FlowMode),
+    /// `AT+POWER?`
+    PowerQuery,
+    /// `AT+POWER=<profile>`
+    SetPower(PowerProfile),
+    /// `AT+SERIAL?`
+    SerialQuery,
+    /// `AT+SERIAL=<text>`
+    SetSerial(SerialNumber),
+    /// `AT+SAVE`
+    Save,
+    /// `AT+RESET`
+    Reset,
+    /// `AT+DAC?`
+    #[cfg(feature = "dac")]
+    DacQuery,
+    /// `AT+DAC=<channel>,<signal>`
+    #[cfg(feature = "dac")]
+    SetDac(DacChannel, Waveform),
+    /// `AT+GPS?`
+    #[cfg(feature = "nmea")]
+    GpsQuery,
+    /// `AT+GPS=PASS|QUERY`, `true` for passthrough
+    #[cfg(feature = "nmea")]
+    SetGps(bool),
+}
+
+/// Work left to the command task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// Nothing
+    Done,
+    /// Save the settings, then reply with the result
+    Save,
+    /// Reset the system once the reply is sent
+    Reset,
+    /// Hand the signal to the DAC task, then reply with the result
+    #[cfg(feature = "dac")]
+    Dac(DacChannel, Waveform),
+}
+
+/// Escape detector and command line collector
+#[derive(Debug)]
+pub struct CommandChannel {
+    mode: Mode,
+    held: u8,
+    last_data_ms: u32,
+    line: Vec<u8, AT_LINE_LEN>,
+    overflow: bool,
+    pending: Option<Line>,
+}
+
+impl CommandChannel {
+    /// Creates a channel in data mode
+    pub const fn new() -> Self {
+        Self {
+            mode: Mode::Data,
+            held: 0,
+            last_data_ms: 0,
+            line: Vec::new(),
+            overflow: false,
+            pending: None,
+        }
+    }
+
+    /// Current mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Feeds bytes received from the host, in place
+    ///
+    /// The bytes to bridge are written back to the start of `buffer`. Output
+    /// never overtakes input, as at most `ESCAPE_LEN - 1` held characters
+    /// are added in front.
+    ///
+    /// # Arguments
+    /// * `buffer` - Holds the received bytes at `start..`
+    /// * `start` - Offset of the received bytes, at least `ESCAPE_LEN - 1`
+    /// * `now_ms` - Current time (milliseconds)
+    ///
+    /// # Returns
+    /// Number of bytes to bridge at the start of `buffer`
+    pub fn filter(&mut self, buffer: &mut [u8], start: usize, now_ms: u32) -> usize {
+        let mut forwarded = 0;
+        for i in start..buffer.len() {
+            let byte = buffer[i];
+            match self.mode {
+                Mode::Data => {
+                    forwarded += self.data_byte(byte, now_ms, &mut buffer[forwarded..=i]);
+                }
+                Mode::Command => self.command_byte(byte),
+            }
+        }
+        forwarded
+    }
+
+    /// Takes the next complete command line
+    pub fn take_line(&mut self) -> Option<Line> {
+        self.pending.take()
+    }
+
+    /// Switches back to data mode; the guard time restarts
+    pub fn return_online(&mut self, now_ms: u32) {
+        self.mode = Mode::Data;
+        self.last_data_ms = now_ms;
+    }
+
+    fn data_byte(&mut self, byte: u8, now_ms: u32, out: &mut [u8]) -> usize {
+        let guarded = self.held > 0 || now_ms.wrapping_sub(self.last_data_ms) >= AT_GUARD_MS;
+        if byte == ESCAPE_CHAR && guarded {
+            self.held += 1;
+            if self.held as usize == ESCAPE_LEN {
+                self.held = 0;
+                self.mode = Mode::Command;
+                self.line.clear();
+                self.overflow = false;
+
+                #[cfg(feature = "debug")]
+                defmt::info!("AT: command mode");
+            }
+            return 0;
+        }
+
+        // Not an escape after all: release the held characters
+        let held = core::mem::take(&mut self.held) as usize;
+        out[..held].fill(ESCAPE_CHAR);
+        out[held] = byte;
+        self.last_data_ms = now_ms;
+        held + 1
+    }
+
+    fn command_byte(&mut self, byte: u8) {
+        match byte {
+            b'\r' | b'\n' => {
+                let overflow = core::mem::take(&mut self.overflow);
+                let line = core::mem::take(&mut self.line);
+                if overflow || line.is_empty() || self.pending.is_some() {
+                    return;
+                }
+                // Only printable ASCII is ever stored
+                self.pending = core::str::from_utf8(&line)
+                    .ok()
+                    .and_then(|s| Line::try_from(s).ok());
+            }
+            0x08 | 0x7F => {
+                self.line.pop();
+            }
+            0x20..=0x7E => {
+                if self.line.push(byte).is_err() {
+                    self.overflow = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for CommandChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Feeds host data through the data port channel, in place
+///
+/// Called by the USB handler for every received packet, read into
+/// `buffer[start..]` with `start` at least `ESCAPE_LEN - 1`.
+///
+/// # Returns
+/// Number of bytes at the start of `buffer` to bridge to the UART
+pub fn filter(buffer: &mut [u8], start: usize) -> usize {
+    let now_ms = crate::Mono::now().ticks();
+    interrupt::free(|cs| {
+        CHANNEL
+            .borrow(cs)
+            .borrow_mut()
+            .filter(buffer, start, now_ms)
+    })
+}
+
+/// Takes the next command line received in command mode
+pub fn take_line() -> Option<Line> {
+    interrupt::free(|cs| CHANNEL.borrow(cs).borrow_mut().take_line())
+}
+
+/// Parses one command line
+///
+/// # Returns
+/// `None` for anything that is not a known command
+pub fn parse(line: &str) -> Option<AtCommand> {
+    let upper: Line = line
+        .trim()
+        .chars()
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    match upper.as_str() {
+        "AT" => Some(AtCommand::Attention),
+        "ATO" => Some(AtCommand::Online),
+        "AT+BAUD?" => Some(AtCommand::BaudQuery),
+        "AT+FRAME?" => Some(AtCommand::FrameQuery),
+        "AT+UART?" => Some(AtCommand::UartQuery),
+        "AT+MODE?" => Some(AtCommand::ModeQuery),
+        "AT+STATS?" => Some(AtCommand::Stats),
+        "AT+FLOW?" => Some(AtCommand::FlowQuery),
+        "AT+POWER?" => Some(AtCommand::PowerQuery),
+        "AT+SERIAL?" => Some(AtCommand::SerialQuery),
+        "AT+SAVE" => Some(AtCommand::Save),
+        "AT+RESET" => Some(AtCommand::Reset),
+        #[cfg(feature = "dac")]
+        "AT+DAC?" => Some(AtCommand::DacQuery),
+        #[cfg(feature = "nmea")]
+        "AT+GPS?" => Some(AtCommand::GpsQuery),
+        #[cfg(feature = "nmea")]
+        "AT+GPS=PASS" => Some(AtCommand::SetGps(true)),
+        #[cfg(feature = "nmea")]
+        "AT+GPS=QUERY" => Some(AtCommand::SetGps(false)),
+        other => {
+            #[cfg(feature = "dac")]
+            if let Some(args) = other.strip_prefix("AT+DAC=") {
+                return parse_dac(args);
+            }
+            if let Some(upper_serial) = other.strip_prefix("AT+SERIAL=") {
+                // Same byte length as the upper-cased line, in its original case
+                let line = line.trim();
+                let serial = &line[line.len() - upper_serial.len()..];
+                SerialNumber::try_from(serial)
+                    .ok()
+                    .filter(|_| settings::is_valid_serial_number(serial))
+                    .map(AtCommand::SetSerial)
+            } else if let Some(rate) = other.strip_prefix("AT+BAUD=") {
+                rate.parse().ok().map(AtCommand::SetBaud)
+            } else if let Some(number) = other.strip_prefix("AT+UART=") {
+                number
+                    .parse()
+                    .ok()
+                    .and_then(UartPort::from_number)
+                    .map(AtCommand::SetUart)
+            } else if let Some(name) = other.strip_prefix("AT+MODE=") {
+                BridgeMode::by_name(name).map(AtCommand::SetMode)
+            } else if let Some(name) = other.strip_prefix("AT+FLOW=") {
+                FlowMode::by_name(name).map(AtCommand::SetFlow)
+            } else if let Some(name) = other.strip_prefix("AT+POWER=") {
+                PowerProfile::by_name(name).map(AtCommand::SetPower)
+            } else {
+                other
+                    .strip_prefix("AT+FRAME=")
+                    .and_then(UartConfig::parse)
+                    .map(AtCommand::SetFrame)
+            }
+        }
+    }
+}
+
+/// Parses the arguments of `AT+DAC=`: channel, signal name and value
+#[cfg(feature = "dac")]
+fn parse_dac(args: &str) -> Option<AtCommand> {
+    let mut args = args.split(',');
+    let channel = args
+        .next()?
+        .parse()
+        .ok()
+        .and_then(DacChannel::from_number)?;
+    let waveform = Waveform::parse(args.next()?, args.next())?;
+    args.next()
+        .is_none()
+        .then_some(AtCommand::SetDac(channel, waveform))
+}
+
+/// Executes a command
+///
+/// `SetBaud` and `SetFrame` switch immediately; the caller lets pending
+/// UART output drain first. `SetUart` only moves the route, the UART
+/// settings of the new port are kept. `Save` writes no reply: it needs the
+/// flash, so the caller saves and replies. `SetDac` likewise leaves the
+/// reply to the caller, which hands the signal to the DAC task.
+///
+/// # Arguments
+/// * `command` - Parsed command
+/// * `usart` - Controller of the bridged UART
+/// * `out` - Reply text sink
+pub fn execute<W: Write>(
+    command: AtCommand,
+    usart: &mut impl BridgeUart,
+    out: &mut W,
+) -> Result<Outcome, fmt::Error> {
+    match command {
+        AtCommand::Attention => out.write_str("OK\r\n")?,
+        AtCommand::Online => {
+            let now_ms = crate::Mono::now().ticks();
+            interrupt::free(|cs| CHANNEL.borrow(cs).borrow_mut().return_online(now_ms));
+            out.write_str("OK\r\n")?;
+        }
+        AtCommand::BaudQuery => write!(out, "+BAUD: {}\r\n", usart.baud_rate())?,
+        AtCommand::SetBaud(baud) => match usart.set_baud_rate(baud) {
+            Ok(()) => out.write_str("OK\r\n")?,
+            Err(_) => out.write_str(ERROR_REPLY)?,
+        },
+        AtCommand::FrameQuery => write!(out, "+FRAME: {}\r\n", usart.uart_config())?,
+        AtCommand::SetFrame(config) => match usart.reconfigure(config) {
+            Ok(()) => out.write_str("OK\r\n")?,
+            Err(_) => out.write_str(ERROR_REPLY)?,
+        },
+        AtCommand::UartQuery => write!(out, "+UART: {}\r\n", uart_route::current().number())?,
+        AtCommand::SetUart(port) => {
+            if uart_route::can_switch() {
+                uart_route::select(port);
+                out.write_str("OK\r\n")?;
+            } else {
+                out.write_str(ERROR_REPLY)?;
+            }
+        }
+        AtCommand::ModeQuery => {
+            out.write_str("+MODE: ")?;
+            for c in bridge_mode::current().name().chars() {
+                out.write_char(c.to_ascii_uppercase())?;
+            }
+            out.write_str("\r\n")?;
+        }
+        AtCommand::SetMode(mode) => {
+            if bridge_mode::can_switch() {
+                bridge_mode::select(mode);
+                out.write_str("OK\r\n")?;
+            } else {
+                out.write_str(ERROR_REPLY)?;
+            }
+        }
+        AtCommand::Stats => {
+            let stats = statistics::get_stats();
+            write!(
+                out,
+                "+STATS: {},{},{},{},{},{},{},{},{},{}\r\n",
+                stats.uart_rx_bytes,
+                stats.uart_tx_bytes,
+                stats.usb_rx_bytes,
+                stats.usb_tx_bytes,
+                stats.dropped_bytes,
+                stats.usb_errors,
+                stats.overrun_errors,
+                stats.framing_errors,
+                stats.noise_errors,
+                stats.parity_errors
+            )?;
+        }
+        AtCommand::FlowQuery => {
+            out.write_str("+FLOW: ")?;
+            for c in xon_xoff::mode().name().chars() {
+                out.write_char(c.to_ascii_uppercase())?;
+            }
+            write!(out, ",{}\r\n", u8::from(xon_xoff::is_tx_paused()))?;
+        }
+        AtCommand::SetFlow(mode) => {
+            xon_xoff::set_mode(mode);
+            out.write_str("OK\r\n")?;
+        }
+        AtCommand::PowerQuery => {
+            out.write_str("+POWER: ")?;
+            for c in power::profile().name().chars() {
+                out.write_char(c.to_ascii_uppercase())?;
+            }
+            out.write_char(',')?;
+            for c in clock_scaling::level().name().chars() {
+                out.write_char(c.to_ascii_uppercase())?;
+            }
+            out.write_str("\r\n")?;
+        }
+        AtCommand::SetPower(profile) => {
+            power::set_profile(profile);
+            out.write_str("OK\r\n")?;
+        }
+        AtCommand::SerialQuery => write!(out, "+SERIAL: {}\r\n", settings::serial_number())?,
+        AtCommand::SetSerial(serial) => {
+            settings::set_serial_number(&serial);
+            out.write_str("OK\r\n")?;
+        }
+        AtCommand::Save => return Ok(Outcome::Save),
+        AtCommand::Reset => {
+            out.write_str("OK\r\n")?;
+            return Ok(Outcome::Reset);
+        }
+        #[cfg(feature = "dac")]
+        AtCommand::DacQuery => {
+            for channel in DacChannel::ALL {
+                let waveform = dac::output(channel);
+                write!(out, "+DAC: {},", channel.number())?;
+                for c in waveform.name().chars() {
+                    out.write_char(c.to_ascii_uppercase())?;
+                }
+                if let Some(value) = waveform.value() {
+                    write!(out, ",{}", value)?;
+                }
+                out.write_str("\r\n")?;
+            }
+        }
+        #[cfg(feature = "dac")]
+        AtCommand::SetDac(channel, waveform) => return Ok(Outcome::Dac(channel, waveform)),
+        #[cfg(feature = "nmea")]
+        AtCommand::GpsQuery => gnss::write_at_reply(out)?,
+        #[cfg(feature = "nmea")]
+        AtCommand::SetGps(passthrough) => {
+            gnss::set_passthrough(passthrough);
+            out.write_str("OK\r\n")?;
+        }
+    }
+    Ok(Outcome::Done)
+}
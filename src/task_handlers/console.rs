@@ -0,0 +1,1250 @@
+//! # Debug Console
+//!
+//! Interactive command console on the second CDC-ACM port, next to the UART
+//! bridge port. Any terminal program can open it while bridged traffic keeps
+//! flowing on the first port:
+//! - Line editing with echo and backspace, CR, LF or CRLF line ends
+//! - Reports: `sysinfo`, `stats`, `errors`, `errlog` (persistent log), `latency`,
+//!   `profile` (CPU load per handler), `qspi` (external flash), `sdram`,
+//!   `bench` (last throughput benchmark)
+//! - RTC calendar: `time` shows it, `time <YYYY-MM-DDThh:mm:ss>` sets it
+//! - Control: subsystem enable/disable, error notifications, safe mode retry,
+//!   user button actions, UART packet framing, UART routing, bridge and
+//!   echo modes, line ending translation and local echo, DTR/RTS output
+//!   polarity, error signal outputs, power profile, clock outputs, USB
+//!   serial number, USB IDs and descriptor strings
+//! - Settings: `settings` shows the ones in flash, `save` stores the current ones
+//! - Snapshots: `snapshots` dumps the newest statistics snapshots from flash
+//! - Restart: `reboot` resets after an orderly shutdown, `bootloader` does
+//!   the same into the DFU bootloader
+//! - Operations that need RTIC resources or tasks (`probe`, `negotiate`,
+//!   `autobaud start`, `latency <n>`, `bench <s>`, `chunk`, `morse`,
+//!   `settings`, `snapshots`, `save`, `reboot`, `bootloader`) are
+//!   returned as an `Action` for the console task
+//!
+//! Error notification frames are only sent here, and wait while the console
+//! is closed, so they never mix into the bridged data. USB log frames with
+//! the `usb-log` feature are sent here while it is open (`log [on|off]`).
+//! With `usb-msc`, `msc refresh` renders the USB log volume files again.
+//! With `display`, `display` shows the
+//! state of the status screen, with `touch`, `touch` the touch controller
+//! counters, with `sd-log`, `sd` the SD card log state, with `i2c`, `i2c`
+//! the bus counters and `i2c scan` a scan of the I2C1 bus, with `i2c-bridge`,
+//! `i2c bridge on|off` the USB-to-I2C bridge mode of the data port, with
+//! `can`, `can` the CAN1 counters and `can slcan on|off` the SLCAN adapter
+//! mode of the data port, with `spi`, `spi` the SPI2 counters and
+//! `spi test` a loopback test, with `adc`, `adc` the last ADC sample
+//! and the `adc stream|rate|channels` sampling settings, and `health` the
+//! die temperature and supply readings with their limits (`health temp|vdd
+//! <min> <max>`, kept by `save`), and with `dac`, `dac` the DAC outputs and
+//! `dac 1|2 off|dc <mV>|sine <Hz>|saw <Hz>` a new signal on a channel. With
+//! `rs485`, `rs485` shows the driver enable polarity and turnaround delays,
+//! with `modbus`, `modbus` the last Modbus transaction as JSON and
+//! `modbus <request>` a new one (`task_handlers::modbus`), and with `nmea`,
+//! `gps` the GNSS fix and `gps pass|query` the NMEA passthrough mode.
+
+use crate::bridge::UartPort;
+#[cfg(feature = "adc")]
+use crate::config::ADC_MIN_SAMPLE_MS;
+use crate::config::{BENCH_MAX_SECONDS, CONSOLE_LINE_LEN};
+#[cfg(feature = "adc")]
+use crate::peripherals::adc::{self, AdcChannel, ChannelSet};
+use crate::peripherals::button::Press;
+#[cfg(feature = "can")]
+use crate::peripherals::can;
+#[cfg(feature = "dac")]
+use crate::peripherals::dac::{self, DacChannel, Waveform};
+#[cfg(feature = "display")]
+use crate::peripherals::display;
+#[cfg(feature = "i2c")]
+use crate::peripherals::i2c;
+use crate::peripherals::mco;
+use crate::peripherals::modem_lines::{self, ControlLines};
+use crate::peripherals::qspi;
+use crate::peripherals::rcc::{McoPin, McoSource};
+use crate::peripherals::red_led::{MorseConfig, RepeatPolicy};
+#[cfg(feature = "rs485")]
+use crate::peripherals::rs485;
+use crate::peripherals::rtc::{self, DateTime};
+use crate::peripherals::sdram;
+#[cfg(feature = "spi")]
+use crate::peripherals::spi;
+#[cfg(feature = "touch")]
+use crate::peripherals::touch;
+#[cfg(feature = "usb-msc")]
+use crate::peripherals::usb_msc;
+use crate::protocol::framer;
+#[cfg(feature = "modbus")]
+use crate::protocol::modbus::Request as ModbusRequest;
+use crate::protocol::transform::{self, Change, Direction, LineEnding};
+use crate::protocol::xon_xoff::{self, FlowMode};
+#[cfg(feature = "adc")]
+use crate::task_handlers::adc_stream::{self, StreamFormat};
+use crate::task_handlers::auto_baud;
+use crate::task_handlers::baud_negotiation::Role;
+use crate::task_handlers::benchmark;
+use crate::task_handlers::bridge_mode::{self, BridgeMode};
+use crate::task_handlers::button::{self, ButtonAction};
+#[cfg(feature = "nmea")]
+use crate::task_handlers::gnss;
+#[cfg(feature = "i2c-bridge")]
+use crate::task_handlers::i2c_bridge;
+#[cfg(feature = "modbus")]
+use crate::task_handlers::modbus;
+use crate::task_handlers::power::{self, PowerProfile};
+#[cfg(feature = "sd-log")]
+use crate::task_handlers::sd_log;
+use crate::task_handlers::settings::{self, SerialNumber, UsbString};
+use crate::task_handlers::shutdown::Restart;
+use crate::task_handlers::signal_handler::{self, SignalOutputs};
+#[cfg(feature = "can")]
+use crate::task_handlers::slcan;
+use crate::task_handlers::task_registry::{self, Subsystem};
+#[cfg(feature = "usb-log")]
+use crate::task_handlers::usb_log;
+use crate::task_handlers::{error_handlers, error_log, error_notify, safe_mode, uart_route};
+#[cfg(feature = "adc")]
+use crate::utils::health_monitor::{self, HealthLimits};
+#[cfg(feature = "usb-msc")]
+use crate::utils::log_volume;
+use crate::utils::sysinfo::write_sysinfo;
+use crate::utils::{latency, profiler, statistics};
+use core::fmt::{self, Write};
+use heapless::{String, Vec};
+
+/// Capacity of one command reply
+pub const REPLY_LEN: usize = 2048;
+
+/// Prompt printed after every reply
+pub const PROMPT: &str = "> ";
+
+/// Greeting printed when a terminal opens the console
+pub const BANNER: &str = "\r\nDebug console, type `help` for commands\r\n";
+
+/// Default number of samples for `latency <n>` without a valid count
+const DEFAULT_LATENCY_SAMPLES: u16 = 100;
+
+/// Command reference printed by `help`
+const HELP: &str = "\
+help                      this text\r\n\
+sysinfo                   system report\r\n\
+stats                     bridge traffic counters\r\n\
+errors                    pending error records\r\n\
+errlog [clear]            persistent error log\r\n\
+time [YYYY-MM-DDThh:mm:ss] show or set the RTC calendar\r\n\
+enable|disable <name>     toggle a subsystem\r\n\
+notify on|off             error notification frames\r\n\
+framing [on|off]          COBS packet framing on the UART\r\n\
+uart [3|6]                show or set the bridged USART\r\n\
+mode [<mode>]             show or set the data path (bridge, uart-echo, usb-echo)\r\n\
+transform [uart|host <le>] show or set line endings (keep, cr-crlf, crlf-cr)\r\n\
+transform uart|host strip on|off drop non-printable characters\r\n\
+transform echo on|off     local echo of the data sent to the UART\r\n\
+transform flow <mode>     XON/XOFF flow control (off, honor, full)\r\n\
+lines [dtr|rts high|low]  show modem lines or set an output's active level\r\n\
+retry                     leave safe mode\r\n\
+probe                     identify the downstream bootloader\r\n\
+negotiate [responder]     bridge-to-bridge baud negotiation\r\n\
+autobaud [start]          last result, or detect the USART6 rate from RX traffic\r\n\
+latency [samples]         last report, or start a measurement\r\n\
+bench [seconds]           last report, or a PRBS run with TX jumpered to RX\r\n\
+chunk [bytes]             show or set the USB chunk size\r\n\
+button [short|long <act>] show or bind button actions\r\n\
+                          (none, clear-errors, blink, stats, bench)\r\n\
+morse [<dot ms> <n>|forever] show or set Morse speed and repeats\r\n\
+signal [led|buzzer|both]  show or set the error code outputs\r\n\
+power [<profile>]         show or set the power profile\r\n\
+                          (performance, balanced, low-power)\r\n\
+mco [1|2 off|<clock> [div]] show or set a clock output (MCO1 PA8, MCO2 PC9)\r\n\
+                          (MCO1 hsi, lse, hse, pll; MCO2 sysclk, hse, pll; div 1-5)\r\n\
+profile [reset]           CPU load per handler, or start a new window\r\n\
+qspi                      QSPI flash part and counters\r\n\
+sdram                     external SDRAM state and usage\r\n\
+serial [<text>]           show or set the USB serial number (next enumeration)\r\n\
+usb [id <vid>:<pid>]      show or set the USB IDs in hex (next enumeration)\r\n\
+usb maker|product <text>  set a USB descriptor string (next enumeration)\r\n\
+settings                  settings saved in flash\r\n\
+snapshots                 newest statistics snapshots stored in flash\r\n\
+save                      save the current settings to flash\r\n\
+reboot                    drain the buffers, detach USB and reset\r\n\
+bootloader                as reboot, into the USB DFU bootloader\r\n";
+
+/// Command reference for the USB log
+#[cfg(feature = "usb-log")]
+const HELP_USB_LOG: &str = "\
+log [on|off]              show or toggle the USB log\r\n";
+
+/// Command reference for the USB log volume
+#[cfg(feature = "usb-msc")]
+const HELP_USB_MSC: &str = "\
+msc [refresh]             log volume state, or render its files again\r\n";
+
+/// Command reference for the status display
+#[cfg(feature = "display")]
+const HELP_DISPLAY: &str = "\
+display                   status display state\r\n";
+
+/// Command reference for the touch controller
+#[cfg(feature = "touch")]
+const HELP_TOUCH: &str = "\
+touch                     touch controller state and counters\r\n";
+
+/// Command reference for the I2C bus
+#[cfg(feature = "i2c")]
+const HELP_I2C: &str = "\
+i2c [scan]                I2C1 counters and last scan, or scan the bus\r\n";
+
+/// Command reference for the USB-to-I2C bridge
+#[cfg(feature = "i2c-bridge")]
+const HELP_I2C_BRIDGE: &str = "\
+i2c bridge on|off         Data port carries framed I2C requests instead of UART data\r\n";
+
+/// Command reference for the CAN bus
+#[cfg(feature = "can")]
+const HELP_CAN: &str = "\
+can                       CAN1 counters and SLCAN state\r\n\
+can slcan on|off          Data port is an SLCAN adapter instead of the UART bridge\r\n";
+
+/// Command reference for the SPI bus
+#[cfg(feature = "spi")]
+const HELP_SPI: &str = "\
+spi [test]                SPI2 counters, or a loopback test (MOSI-MISO jumper)\r\n";
+
+/// Command reference for the ADC
+#[cfg(feature = "adc")]
+const HELP_ADC: &str = "\
+adc                       last ADC sample and sampling settings\r\n\
+adc stream csv|bin|off    stream the samples on this port\r\n\
+adc rate <ms>             sampling period\r\n\
+adc channels all|<names>  channels to stream (a0-a5, vref, temp)\r\n\
+health                    die temperature and VDDA, limits and excursions\r\n\
+health temp <min> <max>   die temperature limits in C (`save` keeps them)\r\n\
+health vdd <min> <max>    VDDA limits in mV (`save` keeps them)\r\n";
+
+/// Command reference for the DAC
+#[cfg(feature = "dac")]
+const HELP_DAC: &str = "\
+dac                       DAC outputs\r\n\
+dac 1|2 off|dc <mV>       switch a channel off or to a constant level\r\n\
+dac 1|2 sine|saw <Hz>     sine or sawtooth across 0-3.3 V, 1 Hz to 10 kHz\r\n";
+
+/// Command reference for the SD card log
+#[cfg(feature = "sd-log")]
+const HELP_SD_LOG: &str = "\
+sd                        SD card log state and counters\r\n";
+
+/// Command reference for the Modbus master
+#[cfg(feature = "modbus")]
+const HELP_MODBUS: &str = "\
+modbus                    counters and the last transaction as JSON\r\n\
+modbus <slave> coils|discrete|holding|input <addr> [count]\r\n\
+modbus <slave> write <addr> <value>... | coil <addr> on|off\r\n\
+modbus {\"slave\":1,\"fn\":3,\"addr\":0,\"count\":2} the same as JSON\r\n";
+
+/// Command reference for the NMEA parser
+#[cfg(feature = "nmea")]
+const HELP_GPS: &str = "\
+gps                       GNSS fix and NMEA sentence counters\r\n\
+gps pass|query            forward NMEA data to the host, or keep it for AT+GPS?\r\n";
+
+/// Command reference for RS-485 mode
+#[cfg(feature = "rs485")]
+const HELP_RS485: &str = "\
+rs485                     driver enable polarity, turnaround and transmissions\r\n";
+
+/// One complete input line
+pub type Line = String<CONSOLE_LINE_LEN>;
+
+/// Bytes to echo back for one input byte
+pub type Echo = Vec<u8, 3>;
+
+/// Line editor for terminal input
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    line: Vec<u8, CONSOLE_LINE_LEN>,
+    overflow: bool,
+}
+
+impl LineEditor {
+    /// Creates an empty editor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds one received byte
+    ///
+    /// # Arguments
+    /// * `byte` - Input byte
+    /// * `echo` - Receives the bytes to send back to the terminal
+    ///
+    /// # Returns
+    /// `Some(line)` when the byte completes a non-empty line; overlong lines
+    /// are dropped
+    pub fn feed(&mut self, byte: u8, echo: &mut Echo) -> Option<Line> {
+        echo.clear();
+
+        match byte {
+            b'\r' | b'\n' => {
+                if self.line.is_empty() && !self.overflow {
+                    // Second half of CRLF, or an empty line
+                    return None;
+                }
+                echo.extend_from_slice(b"\r\n").ok();
+
+                let overflow = core::mem::take(&mut self.overflow);
+                let line = core::mem::take(&mut self.line);
+                if overflow {
+                    return None;
+                }
+                // Only printable ASCII is ever stored
+                core::str::from_utf8(&line)
+                    .ok()
+                    .and_then(|s| Line::try_from(s).ok())
+            }
+            0x08 | 0x7F => {
+                if self.line.pop().is_some() {
+                    echo.extend_from_slice(b"\x08 \x08").ok();
+                }
+                None
+            }
+            0x20..=0x7E => {
+                if self.line.push(byte).is_ok() {
+                    echo.push(byte).ok();
+                } else {
+                    self.overflow = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// USB descriptor change requested with `usb`
+#[derive(Debug, Clone, PartialEq)]
+pub enum UsbSetting {
+    /// Vendor and product ID
+    Ids(u16, u16),
+    /// Manufacturer string
+    Manufacturer(UsbString),
+    /// Product string
+    Product(UsbString),
+}
+
+/// Parsed console command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Sysinfo,
+    Stats,
+    Errors,
+    /// Print the persistent error log
+    ErrorLog,
+    /// Clear the persistent error log
+    ClearErrorLog,
+    /// `None` prints the RTC calendar time
+    Time(Option<DateTime>),
+    Enable(Subsystem),
+    Disable(Subsystem),
+    Notify(bool),
+    /// `None` prints the framing mode and counters
+    Framing(Option<bool>),
+    /// `None` prints the route
+    Uart(Option<UartPort>),
+    /// `None` prints the mode
+    Mode(Option<BridgeMode>),
+    /// `None` prints the options and counters
+    Transform(Option<Change>),
+    /// Switch the XON/XOFF flow control mode
+    Flow(FlowMode),
+    Retry,
+    Probe,
+    Negotiate(Role),
+    /// Print the last baud rate detection
+    AutoBaud,
+    /// Start a baud rate detection
+    StartAutoBaud,
+    /// `None` prints the last report
+    Latency(Option<u16>),
+    /// `None` prints the last report or the run in progress
+    Bench(Option<u32>),
+    /// `None` prints the current size
+    Chunk(Option<usize>),
+    /// `None` prints the current button mapping
+    Button(Option<(Press, ButtonAction)>),
+    /// `None` prints the current Morse settings
+    Morse(Option<MorseConfig>),
+    /// `None` prints the selected error code outputs
+    Signal(Option<SignalOutputs>),
+    /// `None` prints the power profile and core clock level
+    Power(Option<PowerProfile>),
+    /// `None` prints the clock outputs; otherwise an output and its clock
+    /// and divider, `None` to switch it off
+    Mco(Option<(McoPin, Option<(McoSource, u8)>)>),
+    /// `None` prints the modem lines; otherwise an output and whether it is
+    /// active high
+    Lines(Option<(ControlLines, bool)>),
+    /// Print the CPU load report
+    Profile,
+    /// Start a new profiling window
+    ResetProfile,
+    /// Print the QSPI flash state
+    Qspi,
+    /// Print the SDRAM state
+    Sdram,
+    /// `None` prints the USB serial number
+    Serial(Option<SerialNumber>),
+    /// `None` prints the USB IDs and descriptor strings
+    Usb(Option<UsbSetting>),
+    /// Print the settings saved in flash
+    Settings,
+    /// Print the newest statistics snapshots from flash
+    Snapshots,
+    /// Save the current settings to flash
+    Save,
+    /// Reset after an orderly shutdown
+    Reboot(Restart),
+    /// `None` prints the USB log state
+    #[cfg(feature = "usb-log")]
+    Log(Option<bool>),
+    /// Print the log volume and mass storage state
+    #[cfg(feature = "usb-msc")]
+    Msc,
+    /// Render the log volume files again
+    #[cfg(feature = "usb-msc")]
+    RefreshMsc,
+    /// Print the status display state
+    #[cfg(feature = "display")]
+    Display,
+    /// Print the touch controller state
+    #[cfg(feature = "touch")]
+    Touch,
+    /// Print the SD card log state
+    #[cfg(feature = "sd-log")]
+    SdLog,
+    /// Print the RS-485 driver enable state
+    #[cfg(feature = "rs485")]
+    Rs485,
+    /// Print the Modbus counters and the last transaction
+    #[cfg(feature = "modbus")]
+    Modbus,
+    /// `None` prints the GNSS fix, otherwise selects passthrough
+    #[cfg(feature = "nmea")]
+    Gps(Option<bool>),
+    /// Print the I2C counters and the last scan
+    #[cfg(feature = "i2c")]
+    I2c,
+    /// Scan the I2C1 bus
+    #[cfg(feature = "i2c")]
+    I2cScan,
+    /// Switch the USB-to-I2C bridge mode on or off
+    #[cfg(feature = "i2c-bridge")]
+    I2cBridge(bool),
+    /// Print the SPI counters and the last loopback test
+    #[cfg(feature = "spi")]
+    Spi,
+    /// Run the SPI loopback test
+    #[cfg(feature = "spi")]
+    SpiTest,
+    /// Print the CAN1 counters and the SLCAN state
+    #[cfg(feature = "can")]
+    Can,
+    /// Switch the SLCAN adapter mode on or off
+    #[cfg(feature = "can")]
+    Slcan(bool),
+    /// Print the last ADC sample and the sampling settings
+    #[cfg(feature = "adc")]
+    Adc,
+    /// Select the ADC stream format
+    #[cfg(feature = "adc")]
+    AdcStream(StreamFormat),
+    /// Set the ADC sampling period (milliseconds)
+    #[cfg(feature = "adc")]
+    AdcRate(u32),
+    /// Select the ADC channels
+    #[cfg(feature = "adc")]
+    AdcChannels(ChannelSet),
+    /// `None` prints the health readings and limits
+    #[cfg(feature = "adc")]
+    Health(Option<HealthLimits>),
+    /// Print the DAC outputs
+    #[cfg(feature = "dac")]
+    Dac,
+    /// Switch a DAC channel to a waveform
+    #[cfg(feature = "dac")]
+    DacOutput(DacChannel, Waveform),
+    /// Spawn a Modbus transaction
+    #[cfg(feature = "modbus")]
+    ModbusRequest(ModbusRequest),
+}
+
+/// Parses one command line
+///
+/// # Errors
+/// Returns a short message for unknown commands or bad arguments
+pub fn parse(line: &str) -> Result<Command, &'static str> {
+    let mut words = line.split_ascii_whitespace();
+    let command = words.next().unwrap_or("");
+    let arg = words.next();
+
+    if command == "button" {
+        return parse_button(arg, words.next());
+    }
+    if command == "morse" {
+        return parse_morse(arg, words.next());
+    }
+    if command == "transform" {
+        return parse_transform(arg, words.next(), words.next());
+    }
+    if command == "lines" {
+        return parse_lines(arg, words.next());
+    }
+    if command == "mco" {
+        return parse_mco(arg, words.next(), words.next());
+    }
+    if command == "usb" && arg.is_some() {
+        return parse_usb(line);
+    }
+    #[cfg(feature = "adc")]
+    if command == "adc" && arg.is_some() {
+        return parse_adc(arg, words);
+    }
+    #[cfg(feature = "adc")]
+    if command == "health" {
+        return parse_health(arg, words.next(), words.next());
+    }
+    #[cfg(feature = "dac")]
+    if command == "dac" && arg.is_some() {
+        return parse_dac(arg, words.next(), words.next(), words.next());
+    }
+    #[cfg(feature = "i2c-bridge")]
+    if command == "i2c" && arg == Some("bridge") {
+        return match words.next() {
+            Some("on") => Ok(Command::I2cBridge(true)),
+            Some("off") => Ok(Command::I2cBridge(false)),
+            _ => Err("i2c bridge takes on or off"),
+        };
+    }
+    #[cfg(feature = "can")]
+    if command == "can" && arg == Some("slcan") {
+        return match words.next() {
+            Some("on") => Ok(Command::Slcan(true)),
+            Some("off") => Ok(Command::Slcan(false)),
+            _ => Err("can slcan takes on or off"),
+        };
+    }
+    #[cfg(feature = "modbus")]
+    if command == "modbus" && arg.is_some() {
+        let rest = line.trim().strip_prefix("modbus").unwrap_or_default();
+        return modbus::parse(rest).map(Command::ModbusRequest);
+    }
+
+    match (command, arg) {
+        ("help" | "?", None) => Ok(Command::Help),
+        ("sysinfo", None) => Ok(Command::Sysinfo),
+        ("stats", None) => Ok(Command::Stats),
+        ("errors", None) => Ok(Command::Errors),
+        ("errlog", None) => Ok(Command::ErrorLog),
+        ("errlog", Some("clear")) => Ok(Command::ClearErrorLog),
+        ("time", None) => Ok(Command::Time(None)),
+        ("time", Some(text)) => DateTime::parse(text)
+            .map(|time| Command::Time(Some(time)))
+            .ok_or("time must be YYYY-MM-DDThh:mm:ss"),
+        ("enable", Some(name)) => subsystem(name).map(Command::Enable),
+        ("disable", Some(name)) => subsystem(name).map(Command::Disable),
+        ("notify", Some("on")) => Ok(Command::Notify(true)),
+        ("notify", Some("off")) => Ok(Command::Notify(false)),
+        ("framing", None) => Ok(Command::Framing(None)),
+        ("framing", Some("on")) => Ok(Command::Framing(Some(true))),
+        ("framing", Some("off")) => Ok(Command::Framing(Some(false))),
+        ("uart", None) => Ok(Command::Uart(None)),
+        ("uart", Some(n)) => n
+            .parse()
+            .ok()
+            .and_then(UartPort::from_number)
+            .map(|port| Command::Uart(Some(port)))
+            .ok_or("uart must be 3 or 6"),
+        ("mode", None) => Ok(Command::Mode(None)),
+        ("mode", Some(name)) => BridgeMode::by_name(name)
+            .map(|mode| Command::Mode(Some(mode)))
+            .ok_or("mode must be bridge, uart-echo or usb-echo"),
+        ("retry", None) => Ok(Command::Retry),
+        ("probe", None) => Ok(Command::Probe),
+        ("negotiate", None | Some("initiator")) => Ok(Command::Negotiate(Role::Initiator)),
+        ("negotiate", Some("responder")) => Ok(Command::Negotiate(Role::Responder)),
+        ("autobaud", None) => Ok(Command::AutoBaud),
+        ("autobaud", Some("start")) => Ok(Command::StartAutoBaud),
+        ("latency", None) => Ok(Command::Latency(None)),
+        ("latency", Some(n)) => Ok(Command::Latency(Some(
+            n.parse().unwrap_or(DEFAULT_LATENCY_SAMPLES),
+        ))),
+        ("bench", None) => Ok(Command::Bench(None)),
+        ("bench", Some(n)) => n
+            .parse()
+            .ok()
+            .filter(|seconds| (1..=BENCH_MAX_SECONDS).contains(seconds))
+            .map(|seconds| Command::Bench(Some(seconds)))
+            .ok_or("bench takes 1 to 300 seconds"),
+        ("signal", None) => Ok(Command::Signal(None)),
+        ("signal", Some(name)) => signal_handler::outputs_by_name(name)
+            .map(|outputs| Command::Signal(Some(outputs)))
+            .ok_or("signal must be led, buzzer or both"),
+        ("power", None) => Ok(Command::Power(None)),
+        ("power", Some(name)) => PowerProfile::by_name(name)
+            .map(|profile| Command::Power(Some(profile)))
+            .ok_or("power must be performance, balanced or low-power"),
+        ("profile", None) => Ok(Command::Profile),
+        ("profile", Some("reset")) => Ok(Command::ResetProfile),
+        ("qspi", None) => Ok(Command::Qspi),
+        ("sdram", None) => Ok(Command::Sdram),
+        ("serial", None) => Ok(Command::Serial(None)),
+        ("serial", Some(text)) => SerialNumber::try_from(text)
+            .ok()
+            .filter(|_| settings::is_valid_serial_number(text))
+            .map(|serial| Command::Serial(Some(serial)))
+            .ok_or("serial number too long or not printable"),
+        ("usb", None) => Ok(Command::Usb(None)),
+        ("settings", None) => Ok(Command::Settings),
+        ("snapshots", None) => Ok(Command::Snapshots),
+        ("save", None) => Ok(Command::Save),
+        ("reboot", None) => Ok(Command::Reboot(Restart::Application)),
+        ("bootloader", None) => Ok(Command::Reboot(Restart::Bootloader)),
+        #[cfg(feature = "usb-log")]
+        ("log", None) => Ok(Command::Log(None)),
+        #[cfg(feature = "usb-log")]
+        ("log", Some("on")) => Ok(Command::Log(Some(true))),
+        #[cfg(feature = "usb-log")]
+        ("log", Some("off")) => Ok(Command::Log(Some(false))),
+        #[cfg(feature = "usb-msc")]
+        ("msc", None) => Ok(Command::Msc),
+        #[cfg(feature = "usb-msc")]
+        ("msc", Some("refresh")) => Ok(Command::RefreshMsc),
+        #[cfg(feature = "display")]
+        ("display", None) => Ok(Command::Display),
+        #[cfg(feature = "touch")]
+        ("touch", None) => Ok(Command::Touch),
+        #[cfg(feature = "sd-log")]
+        ("sd", None) => Ok(Command::SdLog),
+        #[cfg(feature = "rs485")]
+        ("rs485", None) => Ok(Command::Rs485),
+        #[cfg(feature = "modbus")]
+        ("modbus", None) => Ok(Command::Modbus),
+        #[cfg(feature = "nmea")]
+        ("gps", None) => Ok(Command::Gps(None)),
+        #[cfg(feature = "nmea")]
+        ("gps", Some("pass")) => Ok(Command::Gps(Some(true))),
+        #[cfg(feature = "nmea")]
+        ("gps", Some("query")) => Ok(Command::Gps(Some(false))),
+        #[cfg(feature = "i2c")]
+        ("i2c", None) => Ok(Command::I2c),
+        #[cfg(feature = "i2c")]
+        ("i2c", Some("scan")) => Ok(Command::I2cScan),
+        #[cfg(feature = "can")]
+        ("can", None) => Ok(Command::Can),
+        #[cfg(feature = "adc")]
+        ("adc", None) => Ok(Command::Adc),
+        #[cfg(feature = "dac")]
+        ("dac", None) => Ok(Command::Dac),
+        #[cfg(feature = "spi")]
+        ("spi", None) => Ok(Command::Spi),
+        #[cfg(feature = "spi")]
+        ("spi", Some("test")) => Ok(Command::SpiTest),
+        ("chunk", None) => Ok(Command::Chunk(None)),
+        ("chunk", Some(n)) => n
+            .parse()
+            .map(|size| Command::Chunk(Some(size)))
+            .map_err(|_| "chunk size must be a number"),
+        _ => Err("unknown command, try `help`"),
+    }
+}
+
+fn parse_transform(
+    target: Option<&str>,
+    option: Option<&str>,
+    value: Option<&str>,
+) -> Result<Command, &'static str> {
+    let switch = |value| match value {
+        Some("on") => Ok(true),
+        Some("off") => Ok(false),
+        _ => Err("transform switches take on or off"),
+    };
+    let change = match (target, option) {
+        (None, _) => return Ok(Command::Transform(None)),
+        (Some("echo"), _) if value.is_none() => Change::LocalEcho(switch(option)?),
+        (Some("flow"), Some(mode)) if value.is_none() => {
+            return FlowMode::by_name(mode)
+                .map(Command::Flow)
+                .ok_or("flow must be off, honor or full");
+        }
+        (Some(target), Some("strip")) => {
+            let direction = Direction::by_name(target).ok_or("direction must be uart or host")?;
+            Change::Strip(direction, switch(value)?)
+        }
+        (Some(target), Some(ending)) if value.is_none() => {
+            let direction = Direction::by_name(target).ok_or("direction must be uart or host")?;
+            let ending = LineEnding::by_name(ending)
+                .ok_or("line ending must be keep, cr-crlf or crlf-cr")?;
+            Change::LineEnding(direction, ending)
+        }
+        _ => return Err("see `help` for the transform options"),
+    };
+    Ok(Command::Transform(Some(change)))
+}
+
+fn parse_lines(line: Option<&str>, level: Option<&str>) -> Result<Command, &'static str> {
+    let line = match line {
+        None => return Ok(Command::Lines(None)),
+        Some("dtr") => ControlLines::DTR,
+        Some("rts") => ControlLines::RTS,
+        Some(_) => return Err("line must be dtr or rts"),
+    };
+    match level {
+        Some("high") => Ok(Command::Lines(Some((line, true)))),
+        Some("low") => Ok(Command::Lines(Some((line, false)))),
+        _ => Err("active level must be high or low"),
+    }
+}
+
+fn parse_mco(
+    pin: Option<&str>,
+    source: Option<&str>,
+    divider: Option<&str>,
+) -> Result<Command, &'static str> {
+    let pin = match pin {
+        None => return Ok(Command::Mco(None)),
+        Some("1") => McoPin::Mco1,
+        Some("2") => McoPin::Mco2,
+        Some(_) => return Err("clock output must be 1 or 2"),
+    };
+    let setting = match (source, divider) {
+        (Some("off"), None) => None,
+        (Some(name), divider) => {
+            let source = McoSource::by_name(name).ok_or("unknown clock, see `help`")?;
+            let divider = match divider {
+                Some(n) => n.parse().map_err(|_| "divider must be a number")?,
+                None => 1,
+            };
+            Some((source, divider))
+        }
+        (None, _) => return Err("usage: mco [1|2 off|<clock> [div]]"),
+    };
+    Ok(Command::Mco(Some((pin, setting))))
+}
+
+fn parse_button(press: Option<&str>, action: Option<&str>) -> Result<Command, &'static str> {
+    let press = match press {
+        None => return Ok(Command::Button(None)),
+        Some("short") => Press::Short,
+        Some("long") => Press::Long,
+        Some(_) => return Err("press must be short or long"),
+    };
+    action
+        .and_then(ButtonAction::by_name)
+        .map(|action| Command::Button(Some((press, action))))
+        .ok_or("unknown action, see `help`")
+}
+
+fn parse_morse(dot_ms: Option<&str>, repeat: Option<&str>) -> Result<Command, &'static str> {
+    let Some(dot_ms) = dot_ms else {
+        return Ok(Command::Morse(None));
+    };
+    let repeat = match repeat {
+        Some("forever") => Some(RepeatPolicy::UntilCleared),
+        Some(times) => times.parse().ok().map(RepeatPolicy::Times),
+        None => None,
+    };
+    dot_ms
+        .parse()
+        .ok()
+        .zip(repeat)
+        .and_then(|(dot_ms, repeat)| MorseConfig::new(dot_ms, repeat))
+        .map(|config| Command::Morse(Some(config)))
+        .ok_or("usage: morse <dot ms> <repeats>|forever")
+}
+
+/// Parses `usb id|maker|product`; a descriptor string runs to the end of
+/// the line and may contain spaces
+fn parse_usb(line: &str) -> Result<Command, &'static str> {
+    let rest = line
+        .trim()
+        .strip_prefix("usb")
+        .unwrap_or_default()
+        .trim_start();
+    let (field, value) = rest.split_once(' ').unwrap_or((rest, ""));
+    let value = value.trim();
+    match field {
+        "id" => value
+            .split_once(':')
+            .and_then(|(vid, pid)| {
+                let vid = u16::from_str_radix(vid, 16).ok()?;
+                let pid = u16::from_str_radix(pid, 16).ok()?;
+                Some((vid, pid))
+            })
+            .filter(|&(vid, pid)| settings::is_valid_usb_id(vid, pid))
+            .map(|(vid, pid)| Command::Usb(Some(UsbSetting::Ids(vid, pid))))
+            .ok_or("usb id takes <vid>:<pid> in hex, not 0000 or FFFF"),
+        "maker" | "product" => UsbString::try_from(value)
+            .ok()
+            .filter(|_| settings::is_valid_usb_string(value))
+            .map(|text| match field {
+                "maker" => Command::Usb(Some(UsbSetting::Manufacturer(text))),
+                _ => Command::Usb(Some(UsbSetting::Product(text))),
+            })
+            .ok_or("usb string empty, too long or not printable"),
+        _ => Err("usb takes id, maker or product"),
+    }
+}
+
+#[cfg(feature = "adc")]
+fn parse_adc<'a>(
+    setting: Option<&str>,
+    mut values: impl Iterator<Item = &'a str>,
+) -> Result<Command, &'static str> {
+    match (setting, values.next()) {
+        (Some("stream"), Some("csv")) => Ok(Command::AdcStream(StreamFormat::Csv)),
+        (Some("stream"), Some("bin")) => Ok(Command::AdcStream(StreamFormat::Binary)),
+        (Some("stream"), Some("off")) => Ok(Command::AdcStream(StreamFormat::Off)),
+        (Some("rate"), Some(ms)) => ms
+            .parse()
+            .ok()
+            .filter(|&ms| ms >= ADC_MIN_SAMPLE_MS)
+            .map(Command::AdcRate)
+            .ok_or("adc rate must be a number of ms, 10 or more"),
+        (Some("channels"), Some("all")) => Ok(Command::AdcChannels(ChannelSet::ALL)),
+        (Some("channels"), Some(first)) => core::iter::once(first)
+            .chain(values)
+            .flat_map(|word| word.split(','))
+            .filter(|name| !name.is_empty())
+            .try_fold(ChannelSet::EMPTY, |set, name| {
+                AdcChannel::from_name(name).map(|channel| set.with(channel))
+            })
+            .filter(|set| !set.is_empty())
+            .map(Command::AdcChannels)
+            .ok_or("adc channels takes all or names: a0-a5, vref, temp"),
+        _ => Err("usage: adc [stream csv|bin|off | rate <ms> | channels all|<names>]"),
+    }
+}
+
+#[cfg(feature = "adc")]
+fn parse_health(
+    quantity: Option<&str>,
+    min: Option<&str>,
+    max: Option<&str>,
+) -> Result<Command, &'static str> {
+    let mut limits = health_monitor::limits();
+    match (quantity, min.zip(max)) {
+        (None, None) => return Ok(Command::Health(None)),
+        (Some("temp"), Some((min, max))) => {
+            let (Ok(min), Ok(max)) = (min.parse(), max.parse()) else {
+                return Err("health temp takes two whole degrees C");
+            };
+            limits.temp_min_c = min;
+            limits.temp_max_c = max;
+        }
+        (Some("vdd"), Some((min, max))) => {
+            let (Ok(min), Ok(max)) = (min.parse(), max.parse()) else {
+                return Err("health vdd takes two voltages in mV");
+            };
+            limits.vdd_min_mv = min;
+            limits.vdd_max_mv = max;
+        }
+        _ => return Err("usage: health [temp|vdd <min> <max>]"),
+    }
+    if !limits.is_valid() {
+        return Err("limits too close together, or min above max");
+    }
+    Ok(Command::Health(Some(limits)))
+}
+
+#[cfg(feature = "dac")]
+fn parse_dac(
+    channel: Option<&str>,
+    kind: Option<&str>,
+    value: Option<&str>,
+    extra: Option<&str>,
+) -> Result<Command, &'static str> {
+    let channel = channel
+        .and_then(|number| number.parse().ok())
+        .and_then(DacChannel::from_number)
+        .ok_or("dac channel must be 1 or 2")?;
+    kind.filter(|_| extra.is_none())
+        .and_then(|kind| Waveform::parse(kind, value))
+        .map(|waveform| Command::DacOutput(channel, waveform))
+        .ok_or("usage: dac 1|2 off|dc <mV 0-3300>|sine <Hz>|saw <Hz>, 1 Hz to 10 kHz")
+}
+
+fn subsystem(name: &str) -> Result<Subsystem, &'static str> {
+    task_registry::subsystem_by_name(name).ok_or("unknown subsystem, see `sysinfo`")
+}
+
+/// Work left to the console task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Reply is complete
+    None,
+    /// Spawn the target probe
+    Probe,
+    /// Spawn baud negotiation
+    Negotiate(Role),
+    /// Spawn a baud rate detection
+    AutoBaud,
+    /// Spawn a latency measurement
+    Latency(u16),
+    /// Spawn a throughput benchmark of the given length (seconds)
+    Bench(u32),
+    /// Read (`None`) or set the USB chunk size, then report it
+    Chunk(Option<usize>),
+    /// Read (`None`) or set the Morse settings of the red LED, then report them
+    Morse(Option<MorseConfig>),
+    /// Report the settings saved in flash
+    Settings,
+    /// Dump the newest statistics snapshots
+    Snapshots,
+    /// Save the current settings to flash
+    Save,
+    /// Spawn the shutdown task
+    Reboot(Restart),
+    /// Spawn an I2C1 bus scan
+    #[cfg(feature = "i2c")]
+    I2cScan,
+    /// Spawn the SPI2 loopback test
+    #[cfg(feature = "spi")]
+    SpiTest,
+    /// Run the SLCAN dispatcher to close the CAN channel
+    #[cfg(feature = "can")]
+    SlcanOff,
+    /// Spawn the DAC task to switch a channel
+    #[cfg(feature = "dac")]
+    DacOutput(DacChannel, Waveform),
+    /// Spawn the Modbus master for one transaction
+    #[cfg(feature = "modbus")]
+    Modbus(ModbusRequest),
+}
+
+/// Executes a command that needs no RTIC resources
+///
+/// # Arguments
+/// * `command` - Parsed command
+/// * `out` - Reply text sink
+///
+/// # Returns
+/// The remaining work for the console task
+pub fn execute<W: Write>(command: Command, out: &mut W) -> Result<Action, fmt::Error> {
+    match command {
+        Command::Help => {
+            out.write_str(HELP)?;
+            #[cfg(feature = "usb-log")]
+            out.write_str(HELP_USB_LOG)?;
+            #[cfg(feature = "usb-msc")]
+            out.write_str(HELP_USB_MSC)?;
+            #[cfg(feature = "display")]
+            out.write_str(HELP_DISPLAY)?;
+            #[cfg(feature = "touch")]
+            out.write_str(HELP_TOUCH)?;
+            #[cfg(feature = "sd-log")]
+            out.write_str(HELP_SD_LOG)?;
+            #[cfg(feature = "rs485")]
+            out.write_str(HELP_RS485)?;
+            #[cfg(feature = "modbus")]
+            out.write_str(HELP_MODBUS)?;
+            #[cfg(feature = "nmea")]
+            out.write_str(HELP_GPS)?;
+            #[cfg(feature = "i2c")]
+            out.write_str(HELP_I2C)?;
+            #[cfg(feature = "i2c-bridge")]
+            out.write_str(HELP_I2C_BRIDGE)?;
+            #[cfg(feature = "can")]
+            out.write_str(HELP_CAN)?;
+            #[cfg(feature = "spi")]
+            out.write_str(HELP_SPI)?;
+            #[cfg(feature = "adc")]
+            out.write_str(HELP_ADC)?;
+            #[cfg(feature = "dac")]
+            out.write_str(HELP_DAC)?;
+        }
+        Command::Sysinfo => write_sysinfo(&mut CrLf(out))?,
+        Command::Stats => {
+            statistics::write_report(&mut CrLf(out))?;
+            #[cfg(feature = "adc")]
+            health_monitor::write_readings(&mut CrLf(out))?;
+        }
+        Command::Errors => error_handlers::write_error_dump(&mut CrLf(out))?,
+        Command::ErrorLog => error_log::write_log(&mut CrLf(out))?,
+        Command::ClearErrorLog => {
+            error_log::clear();
+            out.write_str("ok\r\n")?;
+        }
+        Command::Time(None) => rtc::write_report(&mut CrLf(out))?,
+        Command::Time(Some(time)) => {
+            let reply = if rtc::set_time(&time) {
+                "ok\r\n"
+            } else {
+                "RTC not running\r\n"
+            };
+            out.write_str(reply)?;
+        }
+        Command::Enable(subsystem) => {
+            task_registry::enable(subsystem);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Disable(subsystem) => {
+            task_registry::disable(subsystem);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Notify(enabled) => {
+            error_notify::set_enabled(enabled);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Framing(None) => framer::write_report(&mut CrLf(out))?,
+        Command::Framing(Some(enabled)) => {
+            framer::set_enabled(enabled);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Uart(None) => uart_route::write_report(&mut CrLf(out))?,
+        Command::Uart(Some(port)) => {
+            let reply = if !uart_route::can_switch() {
+                "busy, probe, negotiation, auto-baud or benchmark running\r\n"
+            } else if uart_route::select(port) {
+                "ok\r\n"
+            } else {
+                "already routed\r\n"
+            };
+            out.write_str(reply)?;
+        }
+        Command::Transform(None) => {
+            transform::write_report(&mut CrLf(out))?;
+            xon_xoff::write_report(&mut CrLf(out))?;
+        }
+        Command::Transform(Some(change)) => {
+            transform::set_config(transform::config().with(change));
+            out.write_str("ok\r\n")?;
+        }
+        Command::Flow(mode) => {
+            xon_xoff::set_mode(mode);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Mode(None) => bridge_mode::write_report(&mut CrLf(out))?,
+        Command::Mode(Some(mode)) => {
+            let reply = if !bridge_mode::can_switch() {
+                "busy, probe, negotiation, auto-baud or benchmark running\r\n"
+            } else if bridge_mode::select(mode) {
+                "ok\r\n"
+            } else {
+                "already in that mode\r\n"
+            };
+            out.write_str(reply)?;
+        }
+        Command::Retry => {
+            let reply = if safe_mode::request_retry() {
+                "leaving safe mode\r\n"
+            } else {
+                "not in safe mode\r\n"
+            };
+            out.write_str(reply)?;
+        }
+        Command::Latency(None) => match latency::last_report() {
+            Some(stats) => stats.write_report(&mut CrLf(out))?,
+            None => out.write_str("no measurement yet\r\n")?,
+        },
+        Command::Latency(Some(samples)) => return Ok(Action::Latency(samples)),
+        Command::Bench(None) => benchmark::write_report(&mut CrLf(out))?,
+        Command::Bench(Some(seconds)) => return Ok(Action::Bench(seconds)),
+        Command::Probe => return Ok(Action::Probe),
+        Command::Negotiate(role) => return Ok(Action::Negotiate(role)),
+        Command::AutoBaud => auto_baud::write_report(&mut CrLf(out))?,
+        Command::StartAutoBaud => return Ok(Action::AutoBaud),
+        Command::Chunk(size) => return Ok(Action::Chunk(size)),
+        Command::Morse(config) => return Ok(Action::Morse(config)),
+        Command::Button(None) => button::write_report(&mut CrLf(out))?,
+        Command::Button(Some((press, action))) => {
+            button::set_action(press, action);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Signal(None) => signal_handler::write_report(&mut CrLf(out))?,
+        Command::Signal(Some(outputs)) => {
+            signal_handler::set_outputs(outputs);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Power(None) => power::write_report(&mut CrLf(out))?,
+        Command::Power(Some(profile)) => {
+            power::set_profile(profile);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Mco(None) => mco::write_report(&mut CrLf(out))?,
+        Command::Mco(Some((pin, setting))) => match mco::set(pin, setting) {
+            Ok(()) => out.write_str("ok\r\n")?,
+            Err(reason) => write!(out, "{}\r\n", reason)?,
+        },
+        Command::Lines(None) => modem_lines::write_report(&mut CrLf(out))?,
+        Command::Lines(Some((line, active_high))) => {
+            let mut lines = modem_lines::active_high();
+            lines.set(line, active_high);
+            modem_lines::set_active_high(lines);
+            out.write_str("ok\r\n")?;
+        }
+        Command::Profile => profiler::write_report(&mut CrLf(out))?,
+        Command::ResetProfile => {
+            profiler::reset();
+            out.write_str("ok\r\n")?;
+        }
+        Command::Qspi => qspi::write_report(&mut CrLf(out))?,
+        Command::Sdram => sdram::write_report(&mut CrLf(out))?,
+        Command::Serial(None) => write!(out, "serial {}\r\n", settings::serial_number())?,
+        Command::Serial(Some(serial)) => {
+            settings::set_serial_number(&serial);
+            out.write_str("ok, `save` keeps it\r\n")?;
+        }
+        Command::Usb(None) => {
+            let usb = settings::usb_descriptors();
+            write!(
+                out,
+                "usb {:04X}:{:04X}, maker \"{}\", product \"{}\"\r\n",
+                usb.vid, usb.pid, usb.manufacturer, usb.product
+            )?;
+        }
+        Command::Usb(Some(setting)) => {
+            let mut usb = settings::usb_descriptors();
+            match setting {
+                UsbSetting::Ids(vid, pid) => {
+                    usb.vid = vid;
+                    usb.pid = pid;
+                }
+                UsbSetting::Manufacturer(text) => usb.manufacturer = text,
+                UsbSetting::Product(text) => usb.product = text,
+            }
+            settings::set_usb_descriptors(&usb);
+            out.write_str("ok, `save` keeps it\r\n")?;
+        }
+        Command::Settings => return Ok(Action::Settings),
+        Command::Snapshots => return Ok(Action::Snapshots),
+        Command::Save => return Ok(Action::Save),
+        Command::Reboot(restart) => return Ok(Action::Reboot(restart)),
+        #[cfg(feature = "usb-log")]
+        Command::Log(None) => usb_log::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "usb-log")]
+        Command::Log(Some(enabled)) => {
+            usb_log::set_enabled(enabled);
+            out.write_str("ok\r\n")?;
+        }
+        #[cfg(feature = "usb-msc")]
+        Command::Msc => {
+            log_volume::write_report(&mut CrLf(out))?;
+            usb_msc::write_report(&mut CrLf(out))?;
+        }
+        #[cfg(feature = "usb-msc")]
+        Command::RefreshMsc => {
+            // The host is told about the new files on its next medium access
+            log_volume::refresh();
+            out.write_str("ok\r\n")?;
+        }
+        #[cfg(feature = "display")]
+        Command::Display => display::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "touch")]
+        Command::Touch => touch::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "sd-log")]
+        Command::SdLog => sd_log::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "rs485")]
+        Command::Rs485 => rs485::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "modbus")]
+        Command::Modbus => modbus::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "nmea")]
+        Command::Gps(None) => gnss::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "nmea")]
+        Command::Gps(Some(passthrough)) => {
+            gnss::set_passthrough(passthrough);
+            write!(out, "GNSS: {} mode\r\n", gnss::mode_name())?;
+        }
+        #[cfg(feature = "i2c")]
+        Command::I2c => {
+            i2c::write_report(&mut CrLf(out))?;
+            #[cfg(feature = "i2c-bridge")]
+            i2c_bridge::write_report(&mut CrLf(out))?;
+        }
+        #[cfg(feature = "i2c")]
+        Command::I2cScan => return Ok(Action::I2cScan),
+        #[cfg(feature = "i2c-bridge")]
+        Command::I2cBridge(enabled) => {
+            i2c_bridge::set_enabled(enabled);
+            out.write_str("ok\r\n")?;
+        }
+        #[cfg(feature = "spi")]
+        Command::Spi => spi::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "spi")]
+        Command::SpiTest => return Ok(Action::SpiTest),
+        #[cfg(feature = "can")]
+        Command::Can => {
+            can::write_report(&mut CrLf(out))?;
+            slcan::write_report(&mut CrLf(out))?;
+        }
+        #[cfg(feature = "can")]
+        Command::Slcan(true) => {
+            slcan::set_enabled(true);
+            out.write_str("ok\r\n")?;
+        }
+        #[cfg(feature = "can")]
+        Command::Slcan(false) => {
+            slcan::set_enabled(false);
+            return Ok(Action::SlcanOff);
+        }
+        #[cfg(feature = "adc")]
+        Command::Adc => {
+            adc::write_report(&mut CrLf(out))?;
+            adc_stream::write_report(&mut CrLf(out))?;
+        }
+        #[cfg(feature = "adc")]
+        Command::AdcStream(format) => {
+            adc_stream::set_format(format);
+            out.write_str("ok\r\n")?;
+        }
+        #[cfg(feature = "adc")]
+        Command::AdcRate(period_ms) => {
+            adc_stream::set_period_ms(period_ms);
+            out.write_str("ok, from the next sample\r\n")?;
+        }
+        #[cfg(feature = "adc")]
+        Command::AdcChannels(channels) => {
+            adc_stream::set_channels(channels);
+            out.write_str("ok\r\n")?;
+        }
+        #[cfg(feature = "adc")]
+        Command::Health(None) => health_monitor::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "adc")]
+        Command::Health(Some(limits)) => {
+            health_monitor::set_limits(limits);
+            out.write_str("ok, `save` keeps them\r\n")?;
+        }
+        #[cfg(feature = "dac")]
+        Command::Dac => dac::write_report(&mut CrLf(out))?,
+        #[cfg(feature = "dac")]
+        Command::DacOutput(channel, waveform) => return Ok(Action::DacOutput(channel, waveform)),
+        #[cfg(feature = "modbus")]
+        Command::ModbusRequest(request) => return Ok(Action::Modbus(request)),
+    }
+    Ok(Action::None)
+}
+
+/// Reports the outcome of spawning a task for an `Action`
+pub fn write_spawn_result<W: Write>(out: &mut W, spawned: bool) -> fmt::Result {
+    if spawned {
+        out.write_str("started\r\n")
+    } else {
+        out.write_str("busy, try again later\r\n")
+    }
+}
+
+/// Translates the `\n` line ends of the shared reports to `\r\n`
+pub struct CrLf<'a, W: Write>(pub &'a mut W);
+
+impl<W: Write> Write for CrLf<'_, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut lines = s.split('\n');
+        if let Some(first) = lines.next() {
+            self.0.write_str(first)?;
+        }
+        for line in lines {
+            self.0.write_str("\r\n")?;
+            self.0.write_str(line)?;
+        }
+        Ok(())
+    }
+}
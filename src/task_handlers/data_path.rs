@@ -0,0 +1,206 @@
+//! # Link-Level Data Path
+//!
+//! The steps of the DMA and USB handlers (`dma2`, `otg_fs`) that only move
+//! bytes between the links and the ring buffers, generic over the link
+//! traits (`peripherals::traits`):
+//! - `read_rx`: the bytes the UART received since the previous call
+//! - `store_rx`: into the RX queue, counting what does not fit as dropped
+//! - `forward_rx`: from the RX queue to the host, one chunk per call; bytes
+//!   are only peeked and released once written, so a partial write leaves
+//!   the rest queued
+//! - `write_usb`: one write to the host, with its statistics
+//! - `handle_usart_error`: receive error accounting and DMA stream restarts
+//!   under `DMA_RETRY`
+//!
+//! Framing, transforms and the bridge modes stay with the handlers. Free of
+//! register access, these steps also run in host builds, against the mock
+//! links of `peripherals::mock`.
+
+use crate::config::DMA_RESTART_BACKOFF_US;
+use crate::data_structures::log_queue::IsrContext;
+use crate::data_structures::spsc::{RxConsumer, RxProducer};
+use crate::errors::errors::{DeviceError, DmaError, UsartError};
+use crate::isr_log;
+use crate::peripherals::traits::{LineError, SerialLink, UsartFlag, UsbLink};
+use crate::task_handlers::auto_baud;
+use crate::utils::lock_stats::{self, LockSite};
+use crate::utils::retry::{busy_wait_us, random_u32, Backoff, RetryPolicy, RetryState, Verdict};
+use crate::utils::statistics;
+use rtic_core::Mutex;
+
+/// DMA stream recovery: restart in the same ISR after a random pause of a
+/// few microseconds (`DMA_RESTART_BACKOFF_US`), give up after three restarts
+/// in a row
+pub static DMA_RETRY: RetryPolicy = RetryPolicy::new("dma", 4, Backoff::Immediate, 0);
+
+/// Copies the bytes received since the previous call out of the UART
+///
+/// Clears the DMA RX complete and idle line flags in the same lock.
+///
+/// # Arguments
+/// * `usart` - UART resource
+/// * `buffer` - Destination, as large as the circular DMA RX area
+///
+/// # Returns
+/// Number of bytes copied to the start of `buffer`
+pub fn read_rx<L: SerialLink>(
+    usart: &mut impl Mutex<T = L>,
+    buffer: &mut [u8],
+) -> Result<usize, DmaError> {
+    let len = lock_stats::lock(LockSite::DmaRx, usart, |usart| {
+        usart.clear_dma_rx_complete_flag();
+        usart.clear_usart_flags(UsartFlag::IDLE);
+        usart.read_dma_rx(buffer).map_err(|_| DmaError::ReadError)
+    })?;
+    statistics::add_uart_rx(len);
+    Ok(len)
+}
+
+/// Stores received bytes in the RX queue, all or nothing
+///
+/// # Errors
+/// `DmaError::BufferOverflow` if they do not fit; they count as dropped
+pub fn store_rx(rx: &mut impl Mutex<T = RxProducer>, data: &[u8]) -> Result<(), DmaError> {
+    lock_stats::lock(LockSite::DmaRx, rx, |rx| {
+        rx.push(data).map_err(|_| {
+            statistics::add_dropped(data.len());
+            DmaError::BufferOverflow
+        })?;
+        statistics::note_rx_buffer_level(rx.len());
+        Ok(())
+    })
+}
+
+/// Sends the oldest bytes of the RX queue to the host
+///
+/// Peeks at most `chunk.len()` bytes, writes them and releases what the
+/// write took, each in its own lock.
+///
+/// # Arguments
+/// * `usb` - USB resource
+/// * `rx` - Consumer half of the receive ring buffer
+/// * `chunk` - Scratch space, as large as one write
+///
+/// # Returns
+/// Number of bytes sent; 0 if the queue is empty
+pub fn forward_rx<L: UsbLink>(
+    usb: &mut impl Mutex<T = L>,
+    rx: &mut impl Mutex<T = RxConsumer>,
+    chunk: &mut [u8],
+) -> Result<usize, DeviceError> {
+    let len = lock_stats::lock(LockSite::UsbTx, rx, |rx| rx.peek(chunk));
+    if len == 0 {
+        #[cfg(feature = "debug")]
+        defmt::trace!("RX buffer empty - nothing to transmit");
+        return Ok(0);
+    }
+
+    let written = write_usb(usb, &chunk[..len])?;
+    lock_stats::lock(LockSite::UsbTx, rx, |rx| rx.release(written));
+    Ok(written)
+}
+
+/// Writes data to the host
+///
+/// # Returns
+/// Number of bytes the endpoint took; the caller keeps the rest
+///
+/// # Errors
+/// The `UsbError` of the write, counted in the statistics
+pub fn write_usb<L: UsbLink>(
+    usb: &mut impl Mutex<T = L>,
+    data: &[u8],
+) -> Result<usize, DeviceError> {
+    #[cfg(feature = "debug")]
+    defmt::debug!("Preparing to send {} bytes", data.len());
+
+    match lock_stats::lock(LockSite::UsbTx, usb, |usb| usb.write(data)) {
+        Ok(written) => {
+            statistics::add_usb_tx(written);
+
+            #[cfg(feature = "debug")]
+            if written < data.len() {
+                defmt::warn!("Partial write: {}/{} bytes", written, data.len());
+            }
+            Ok(written)
+        }
+        Err(e) => {
+            #[cfg(feature = "debug")]
+            defmt::error!("USB write failure: {:?}", e);
+            statistics::add_usb_error();
+            Err(e.into())
+        }
+    }
+}
+
+/// Handles USART receive errors and DMA errors with recovery logic
+///
+/// Receive errors are counted per class and cleared first; DMA errors then
+/// restart their stream. The consecutive-failure count in `retry` is
+/// cleared once both streams are error-free again.
+///
+/// Runs in the UART interrupt handlers, so it logs through `isr_log!` only.
+///
+/// # Errors
+/// - The DMA error if a stream could not be restarted
+/// - Otherwise the receive error, classified by `LineError::classify`
+pub fn handle_usart_error(
+    isr: &IsrContext,
+    usart: &mut impl SerialLink,
+    retry: &mut RetryState,
+) -> Result<(), DeviceError> {
+    let mut line_errors = usart.take_line_errors();
+    if auto_baud::is_active() {
+        // Expected while the USART still samples at the old rate
+        line_errors = LineError::empty();
+    }
+    statistics::add_line_errors(line_errors);
+
+    let rx_error = usart.check_dma_rx_error().unwrap_or(false);
+    if rx_error {
+        isr_log!(isr, error, "DMA RX error detected, restarting the stream");
+        restart_stream(usart, retry, |u| u.restart_dma_rx())?;
+    }
+
+    let tx_error = usart.check_dma_tx_error().unwrap_or(false);
+    if tx_error {
+        isr_log!(isr, error, "DMA TX error detected, restarting the stream");
+        restart_stream(usart, retry, |u| u.restart_dma_tx())?;
+    }
+
+    if !rx_error && !tx_error {
+        DMA_RETRY.on_success(retry);
+    }
+
+    usart.clear_usart_flags(UsartFlag::RXNE);
+    match line_errors.classify() {
+        Some(error) => Err(error.into()),
+        None => Ok(()),
+    }
+}
+
+// Restarts a DMA stream after an error, within the `DMA_RETRY` budget
+fn restart_stream<L, F>(
+    usart: &mut L,
+    retry: &mut RetryState,
+    restart_fn: F,
+) -> Result<(), DmaError>
+where
+    L: SerialLink,
+    F: FnOnce(&mut L) -> Result<(), UsartError>,
+{
+    if DMA_RETRY.on_failure(retry) == Verdict::Exhausted {
+        return Err(DmaError::RetryLimitExceeded);
+    }
+
+    // Random pause, its window doubling per failure, so that both UARTs hit
+    // by the same disturbance do not restart in lockstep
+    let shift = u32::from(retry.failures().saturating_sub(1)).min(2);
+    let pause_us = random_u32() % (DMA_RESTART_BACKOFF_US << shift);
+    busy_wait_us(pause_us);
+
+    usart.clear_errors();
+    restart_fn(usart).map_err(|_| DmaError::InitError)?;
+    statistics::add_dma_restart();
+    Ok(())
+}
@@ -1,135 +1,350 @@
 //! # DMA2 Stream Management
 //!
-//! Handles DMA operations for USART6 communication including:
+//! Handles DMA operations for the bridged UART (USART6 or USART3) including:
 //! - Error recovery mechanisms
+//! - Receive error classification: overrun, framing, noise and parity
+//!   errors are counted in `statistics` and reported as their own
+//!   `DeviceError`, so cabling problems read apart from buffer problems
 //! - Data transfer between ring buffers and DMA
 //! - Retry logic for failed operations
+//!
+//! The handlers are generic over `SerialLink`, and the steps that only move
+//! bytes and recover streams live in `data_path`, where the host build
+//! tests them against a mock UART.
+//!
+//! The data-path handlers take RTIC resource proxies. The ring buffers are
+//! lock-free SPSC queues (`data_structures::spsc`): RX locks only the
+//! producer half of the RX queue, TX only the consumer half of the TX queue,
+//! so neither contends with the USB side. RX locks the USART and the queue
+//! one at a time, copying through a stack buffer in between.
+//! TX avoids copies instead: staged USB data and the read grants of the TX
+//! queue are transmitted in place, the queue bytes released only once the
+//! stream is idle again. Only framed packets are encoded into the
+//! controller's own DMA buffer.
+//! With UART framing on (`protocol::framer`), both directions pass through
+//! the framer: TX chunks become frames and RX frames are decoded into packets.
+//! RX of the UART the data port is not routed to (`uart_route`) is skipped.
+//! During a throughput benchmark (`benchmark`), TX sends the test pattern
+//! and RX checks it; neither touches the bridged data.
+//! All UART RX is skipped while the USB-to-I2C bridge or SLCAN mode is on,
+//! in the `usb-echo` mode (`bridge_mode`) and while a baud rate detection
+//! runs (`auto_baud`).
+//! With the `nmea` feature, USART6 RX is read by the NMEA parser (`gnss`)
+//! first, which keeps it from the RX buffer in its query mode.
+//! With XON/XOFF flow control on (`xon_xoff`), RX drops the control
+//! characters, and TX sends ours ahead of the data and holds the data while
+//! the UART peer paused it.
 
-use crate::config::{DMA_BUFFER_LEN, RING_BUFFER_LEN};
-use crate::data_structures::ring_buffer::RingBuffer;
-use crate::errors::errors::{DmaError, UsartError};
-use crate::peripherals::usart_6::{Usart6Controller, UsartFlag};
-
-/// Maximum retry attempts for DMA operations
-pub const MAX_RETRY_COUNT: u8 = 3;
-
-/// Handles USART-related DMA errors with recovery logic
-pub fn handle_usart_error(
-    usart: &mut Usart6Controller,
-    retry_count: &mut u8,
-) -> Result<(), DmaError> {
-    if usart.check_dma_rx_error().unwrap_or(false) {
-        handle_error_condition(usart, retry_count, |u| u.restart_dma_rx())?;
-    }
+use crate::bridge::UartPort;
+use crate::config::{DMA_BUFFER_LEN, FRAME_MAX_PAYLOAD_LEN};
+use crate::data_structures::spsc::{RxProducer, TxConsumer};
+use crate::data_structures::tx_pingpong::TxPingPong;
+use crate::errors::errors::DmaError;
+use crate::peripherals::uart::{SerialLink, DMA_RX_LEN};
+use crate::protocol::{framer, xon_xoff};
+use crate::task_handlers::auto_baud;
+use crate::task_handlers::benchmark;
+use crate::task_handlers::bridge_mode::{self, Sink};
+use crate::task_handlers::data_path;
+#[cfg(feature = "nmea")]
+use crate::task_handlers::gnss;
+#[cfg(feature = "i2c-bridge")]
+use crate::task_handlers::i2c_bridge;
+#[cfg(feature = "can")]
+use crate::task_handlers::slcan;
+use crate::task_handlers::uart_route;
+use crate::utils::lock_stats::{self, LockSite};
+use crate::utils::statistics;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use rtic::Mutex;
 
-    if usart.check_dma_tx_error().unwrap_or(false) {
-        handle_error_condition(usart, retry_count, |u| u.restart_dma_tx())?;
-    }
+/// Bytes of the TX queue read grant the DMA is sending, or sent last
+static TX_GRANT_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts the next DMA TX transfer if the stream is idle
+///
+/// A pending XON/XOFF character goes first, on its own. While a benchmark
+/// runs, the DMA buffer is filled with the test pattern instead. Data waits
+/// while the UART peer paused it with XOFF. Otherwise data staged in the ping-pong buffers goes first and is
+/// transmitted in place. Overflow data in the TX queue is transmitted in
+/// place as well, one read grant per transfer; in framed mode it is popped
+/// and encoded into the controller's DMA buffer as one packet of at most
+/// `FRAME_MAX_PAYLOAD_LEN` bytes.
+///
+/// The caller makes sure no UART is still transmitting: the read grant of
+/// the previous transfer is released here, whichever UART sent it.
+///
+/// # Returns
+/// Number of bytes handed to the DMA; 0 if the stream is busy (the TX
+/// complete interrupt calls again) or nothing is pending
+pub fn handle_dma_tx<U: SerialLink>(
+    usart: &mut impl Mutex<T = U>,
+    tx: &mut impl Mutex<T = TxConsumer>,
+    staging: &mut impl Mutex<T = TxPingPong>,
+) -> Result<usize, DmaError> {
+    lock_stats::span(LockSite::DmaTx, || {
+        if lock_stats::lock(LockSite::DmaTx, usart, |usart| usart.is_tx_busy()) {
+            return Ok(0);
+        }
+
+        let released = TX_GRANT_LEN.swap(0, Ordering::Relaxed);
+        if released > 0 {
+            lock_stats::lock(LockSite::DmaTx, tx, |tx| tx.release(released));
+        }
+
+        if let Some(control) = xon_xoff::take_control() {
+            lock_stats::lock(LockSite::DmaTx, usart, |usart| {
+                let buffer = usart.get_tx_buffer_slice(1).ok_or(DmaError::WriteError)?;
+                buffer[0] = control;
+                usart.write_dma(1).map_err(|_| DmaError::WriteError)
+            })?;
+            statistics::add_uart_tx(1);
+            return Ok(1);
+        }
+
+        if benchmark::is_running() {
+            let sent = lock_stats::lock(LockSite::DmaTx, usart, |usart| {
+                let buffer = usart
+                    .get_tx_buffer_slice(DMA_BUFFER_LEN)
+                    .ok_or(DmaError::WriteError)?;
+                benchmark::fill_tx(buffer);
+                usart
+                    .write_dma(DMA_BUFFER_LEN)
+                    .map_err(|_| DmaError::WriteError)?;
+                Ok(DMA_BUFFER_LEN)
+            })?;
+            statistics::add_uart_tx(sent);
+            return Ok(sent);
+        }
 
-    usart.clear_usart_flags(UsartFlag::RXNE);
-    Ok(())
+        if xon_xoff::is_tx_paused() {
+            return Ok(0);
+        }
+
+        let staged = lock_stats::lock(LockSite::DmaTx, staging, |staging| {
+            staging.release();
+            let Some(data) = staging.start() else {
+                return Ok(0);
+            };
+            let len = data.len();
+
+            // SAFETY: The buffer stays in flight, out of reach of producers,
+            // until `release` after the stream went idle; the staging buffers
+            // live in a static RTIC resource
+            let started = usart.lock(|usart| unsafe { usart.transmit_external(data) });
+            if started.is_err() {
+                staging.abort();
+                return Err(DmaError::WriteError);
+            }
+            Ok(len)
+        })?;
+        if staged > 0 {
+            statistics::add_uart_tx(staged);
+            return Ok(staged);
+        }
+
+        let sent = if framer::is_enabled() {
+            lock_stats::lock(LockSite::DmaTx, usart, |usart| {
+                let buffer = usart
+                    .get_tx_buffer_slice(DMA_BUFFER_LEN)
+                    .ok_or(DmaError::WriteError)?;
+
+                let mut packet = [0u8; FRAME_MAX_PAYLOAD_LEN];
+                let popped = tx.lock(|tx| tx.pop(&mut packet));
+                if popped == 0 {
+                    return Ok(0);
+                }
+                let len = framer::encode_packet(&packet[..popped], buffer).map_err(|_| {
+                    statistics::add_dropped(popped);
+                    DmaError::BufferOverflow
+                })?;
+
+                usart.write_dma(len).map_err(|_| DmaError::WriteError)?;
+                Ok(len)
+            })?
+        } else {
+            lock_stats::lock(LockSite::DmaTx, tx, |tx| {
+                let grant = tx.read();
+                if grant.is_empty() {
+                    return Ok(0);
+                }
+
+                // SAFETY: The granted bytes stay in the queue, out of reach of
+                // the producer, until the next call releases them after the
+                // stream went idle; the queue lives in a static `init` local
+                let started = usart.lock(|usart| unsafe { usart.transmit_external(grant) });
+                started.map_err(|_| DmaError::WriteError)?;
+                TX_GRANT_LEN.store(grant.len(), Ordering::Relaxed);
+                Ok(grant.len())
+            })?
+        };
+        statistics::add_uart_tx(sent);
+        Ok(sent)
+    })
 }
 
-/// Processes DMA TX operations
-pub fn handle_dma_tx(
-    usart: &mut Usart6Controller,
-    tx: &mut RingBuffer,
-    bytes_processed: usize,
-) -> Result<(), DmaError> {
-    let mut buffer = [0u8; DMA_BUFFER_LEN];
-    let data = prepare_tx_data(tx, bytes_processed, &mut buffer)?;
-    transfer_to_dma(usart, data)?;
-    usart.clear_dma_tx_complete_flag();
-    Ok(())
+/// Transmits a slice directly, bypassing the TX ring buffer
+///
+/// Used for link-control traffic that must not be queued behind bridged data.
+pub fn transmit_direct(usart: &mut impl SerialLink, data: &[u8]) -> Result<(), DmaError> {
+    if data.len() > DMA_BUFFER_LEN {
+        return Err(DmaError::BufferOverflow);
+    }
+    transfer_to_dma(usart, data)
 }
 
-/// Processes DMA RX operations with full error handling
-pub fn handle_dma_rx(usart: &mut Usart6Controller, rx: &mut RingBuffer) -> Result<(), DmaError> {
-    // Process received data
-    let mut buffer = [0u8; DMA_BUFFER_LEN];
-    let data = read_from_dma(usart, &mut buffer)?;
-    store_to_buffer(rx, data)?;
-    usart.clear_dma_rx_complete_flag();
-
-    Ok(())
+/// Detects partial RX data that no interrupt will flush
+///
+/// Fed with the unread DMA RX byte count at a fixed interval; data that did
+/// not grow between two polls belongs to a finished burst.
+#[derive(Debug, Default)]
+pub struct RxIdleWatch {
+    last_unread: usize,
 }
 
-// Shared error handling logic
-fn handle_error_condition<F>(
-    usart: &mut Usart6Controller,
-    retry_count: &mut u8,
-    restart_fn: F,
-) -> Result<(), DmaError>
-where
-    F: FnOnce(&mut Usart6Controller) -> Result<(), UsartError>,
-{
-    *retry_count = retry_count.saturating_add(1);
-
-    if *retry_count > MAX_RETRY_COUNT {
-        *retry_count = 0;
-        return Err(DmaError::RetryLimitExceeded);
+impl RxIdleWatch {
+    /// Creates a watch with no data observed
+    pub const fn new() -> Self {
+        Self { last_unread: 0 }
+    }
+
+    /// Checks one poll
+    ///
+    /// # Arguments
+    /// * `unread` - Bytes waiting in the DMA RX buffer (`unread_rx_len`)
+    ///
+    /// # Returns
+    /// `true` if the bytes should be flushed now
+    pub fn poll(&mut self, unread: usize) -> bool {
+        let stalled = unread > 0 && unread == self.last_unread;
+        self.last_unread = if stalled { 0 } else { unread };
+        stalled
     }
+}
 
-    usart.clear_errors();
-    restart_fn(usart).map_err(|_| DmaError::InitError)?;
-    Ok(())
+/// Processes DMA RX operations with full error handling
+///
+/// Copies the bytes received since the last call out of the circular DMA
+/// buffer under the USART lock, then stores them under the RX buffer lock.
+/// Called on DMA half/complete, USART idle line and RX timeout events.
+/// In framed mode only the payloads of complete packets are stored, and
+/// only when no dispatch callback takes them. During a benchmark the bytes
+/// are checked against the test pattern and nothing is stored. XON and XOFF
+/// are taken out with software flow control on. USART6 bytes
+/// pass the NMEA parser, and are not stored in its query mode (`nmea`
+/// feature).
+///
+/// # Returns
+/// Number of new bytes stored in the RX buffer
+pub fn handle_dma_rx<U: SerialLink>(
+    usart: &mut impl Mutex<T = U>,
+    rx: &mut impl Mutex<T = RxProducer>,
+) -> Result<usize, DmaError> {
+    lock_stats::span(LockSite::DmaRx, || {
+        let mut buffer = [0u8; DMA_RX_LEN];
+        let len = data_path::read_rx(usart, &mut buffer)?;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        if benchmark::is_active() {
+            benchmark::check_rx(&buffer[..len]);
+            return Ok(0);
+        }
+        let len = if xon_xoff::is_enabled() {
+            xon_xoff::filter_rx(&mut buffer[..len])
+        } else {
+            len
+        };
+        if len == 0 {
+            return Ok(0);
+        }
+        // Only routed data gets here: the route is the UART read
+        #[cfg(feature = "nmea")]
+        if uart_route::current() == UartPort::Usart6 && !gnss::receive(&buffer[..len]) {
+            return Ok(0);
+        }
+        if framer::is_enabled() {
+            return receive_packets(rx, &buffer[..len]);
+        }
+        data_path::store_rx(rx, &buffer[..len])?;
+        Ok(len)
+    })
 }
 
-// TX data preparation with static buffer
-fn prepare_tx_data<'a>(
-    tx: &mut RingBuffer,
-    bytes_processed: usize,
-    buffer: &'a mut [u8; DMA_BUFFER_LEN],
-) -> Result<&'a [u8], DmaError> {
-    if bytes_processed > DMA_BUFFER_LEN || tx.len() < bytes_processed {
-        return Err(DmaError::BufferUnderflow);
+/// Processes DMA RX of one bridge UART according to the route
+///
+/// Bytes from a UART the data port is not routed to, and all UART bytes in
+/// a bridge mode that discards them or during a baud rate detection, are
+/// dropped without copying (`skip_rx`); otherwise as `handle_dma_rx`.
+///
+/// # Arguments
+/// * `port` - UART behind `usart`
+///
+/// # Returns
+/// Number of new bytes stored in the RX buffer
+pub fn handle_uart_rx<U: SerialLink>(
+    port: UartPort,
+    usart: &mut impl Mutex<T = U>,
+    rx: &mut impl Mutex<T = RxProducer>,
+) -> Result<usize, DmaError> {
+    if !uart_route::is_routed(port)
+        || data_port_diverted()
+        || bridge_mode::routes().uart_data == Sink::Discard
+        || auto_baud::is_active()
+    {
+        usart
+            .lock(|usart| usart.skip_rx())
+            .map_err(|_| DmaError::ReadError)?;
+        return Ok(0);
     }
+    handle_dma_rx(usart, rx)
+}
 
-    let data = tx.pop_n::<RING_BUFFER_LEN>(bytes_processed);
-    buffer[..bytes_processed].copy_from_slice(&data);
-    Ok(&buffer[..bytes_processed])
+/// Checks whether a bridge mode took over the data port from the UARTs
+pub fn data_port_diverted() -> bool {
+    #[cfg(feature = "i2c-bridge")]
+    if i2c_bridge::is_enabled() {
+        return true;
+    }
+    #[cfg(feature = "can")]
+    if slcan::is_enabled() {
+        return true;
+    }
+    false
 }
 
 // DMA write operation
-fn transfer_to_dma(usart: &mut Usart6Controller, data: &[u8]) -> Result<(), DmaError> {
+fn transfer_to_dma(usart: &mut impl SerialLink, data: &[u8]) -> Result<(), DmaError> {
     let buffer = usart
         .get_tx_buffer_slice(data.len())
         .ok_or(DmaError::WriteError)?;
 
     buffer.copy_from_slice(data);
 
-    usart.write_dma().map_err(|_| {
+    usart.write_dma(data.len()).map_err(|_| {
         usart.clear_errors();
         DmaError::WriteError
     })
 }
 
-// DMA read operation with buffer management
-fn read_from_dma<'a>(
-    usart: &mut Usart6Controller,
-    buffer: &'a mut [u8; DMA_BUFFER_LEN],
-) -> Result<&'a [u8], DmaError> {
-    // Initiate DMA read operation
-    usart.read_dma().map_err(|_| {
-        usart.clear_errors();
-        DmaError::ReadError
-    })?;
-
-    // Clear USART flags after successful read
-    usart.clear_usart_flags(UsartFlag::RXNE);
+// Framed RX: decodes complete packets and hands each payload to the
+// dispatch callback, or to the RX buffer when none is registered
+fn receive_packets(rx: &mut impl Mutex<T = RxProducer>, data: &[u8]) -> Result<usize, DmaError> {
+    let mut packet = [0u8; FRAME_MAX_PAYLOAD_LEN];
+    let mut stored = 0;
+    let mut rest = data;
 
-    let bytes_received =
-        DMA_BUFFER_LEN - usart.get_dma_rx_length().map_err(|_| DmaError::ReadError)?;
+    while !rest.is_empty() {
+        let (consumed, frame) = framer::receive(rest, &mut packet);
+        rest = &rest[consumed..];
 
-    let data = usart
-        .get_rx_buffer_slice(bytes_received)
-        .ok_or(DmaError::ReadError)?;
-
-    buffer[..bytes_received].copy_from_slice(data);
-    Ok(&buffer[..bytes_received])
-}
-
-// Buffer storage with overflow protection
-fn store_to_buffer(rx: &mut RingBuffer, data: &[u8]) -> Result<(), DmaError> {
-    rx.push(data).map_err(|_| DmaError::BufferOverflow)
+        if let Some(Ok(len)) = frame {
+            if !framer::dispatch(&packet[..len]) {
+                data_path::store_rx(rx, &packet[..len])?;
+                stored += len;
+            }
+        }
+    }
+    Ok(stored)
 }
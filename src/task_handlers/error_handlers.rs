@@ -1,45 +1,191 @@
-use crate::data_structures::error_queue::ERROR_QUEUE;
+use crate::data_structures::error_queue::{ErrorRecord, Severity, ERROR_STORE};
+use crate::peripherals::rtc;
+#[cfg(feature = "sd-log")]
+use crate::task_handlers::sd_log;
+use crate::task_handlers::{error_log, error_notify};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
 use cortex_m::interrupt::{self};
+use stm32f4xx_hal::pac::Interrupt;
 
-/// Adds an error code to the queue.
+/// Maximum number of records listed by `write_error_dump`
+const ERROR_DUMP_MAX: usize = 32;
+
+/// Total number of errors reported since boot
+static ERRORS_TOTAL: AtomicU32 = AtomicU32::new(0);
+
+/// Most recently reported error code
+static LAST_ERROR_CODE: AtomicU32 = AtomicU32::new(0);
+
+/// Adds an error code to the store with `Severity::Error`.
+///
+/// See `add_error`.
+pub fn add_error_code(code: u16) -> Result<(), &'static str> {
+    add_error(code, Severity::Error)
+}
+
+/// Adds an error report to the store.
+///
+/// A repeat of a pending code is coalesced into its record: the count is
+/// incremented and the RTC calendar time is kept as `last_seen`. The code is
+/// also recorded for host push notifications and in the persistent error log
+/// (and the SD card log with `sd-log`), even if the store is full. A due
+/// notification pends the USB interrupt, which sends it.
 ///
 /// # Parameters:
-/// - `code`: The error code to enqueue.
+/// - `code`: The error code to record.
+/// - `severity`: How serious the error is; a record keeps the highest one.
 ///
 /// # Returns:
-/// - `Ok(())` if the error code was successfully added.
-/// - `Err("Error queue is full")` if the queue is full.
-pub fn add_error_code(code: u16) -> Result<(), &'static str> {
+/// - `Ok(())` if the report was recorded.
+/// - `Err("Error store is full")` if no record of a lower severity could be evicted.
+pub fn add_error(code: u16, severity: Severity) -> Result<(), &'static str> {
+    ERRORS_TOTAL.fetch_add(1, Ordering::Relaxed);
+    LAST_ERROR_CODE.store(code as u32, Ordering::Relaxed);
+    if error_notify::record(code) {
+        cortex_m::peripheral::NVIC::pend(Interrupt::OTG_FS);
+    }
+    error_log::record(code);
+    #[cfg(feature = "sd-log")]
+    sd_log::record_error(code, severity);
+
+    let now = rtc::now();
     interrupt::free(|cs| {
-        let mut queue = ERROR_QUEUE.borrow(cs).borrow_mut();
-        if queue.enqueue(code).is_err() {
-            Err("Error queue is full")
-        } else {
+        let mut store = ERROR_STORE.borrow(cs).borrow_mut();
+        if store.record(code, severity, now) {
             Ok(())
+        } else {
+            Err("Error store is full")
         }
     })
 }
 
-/// Retrieves the first error code from the queue.
+/// Removes the most severe error record.
 ///
 /// # Returns:
-/// - `Some(u16)` if an error code is available.
-/// - `None` if the queue is empty.
+/// - `Some(u16)` with its code if a record is pending.
+/// - `None` if the store is empty.
 pub fn get_first_error_code() -> Option<u16> {
     interrupt::free(|cs| {
-        let mut queue = ERROR_QUEUE.borrow(cs).borrow_mut();
-        queue.dequeue()
+        let mut store = ERROR_STORE.borrow(cs).borrow_mut();
+        store.take_first().map(|record| record.code)
     })
 }
 
-/// Checks if the error queue contains any errors.
+/// Copies the pending error records into a caller buffer without removing them.
+///
+/// Records are copied most severe first, and in order of first report within
+/// a severity; several consumers (LED display, host dump) can observe the
+/// same faults this way.
+///
+/// # Parameters:
+/// - `buf`: Destination for the records.
 ///
 /// # Returns:
-/// - `true` if the queue is not empty.
-/// - `false` if the queue is empty.
-pub fn has_errors() -> bool {
+/// - The number of records copied, at most `buf.len()`.
+pub fn peek_all(buf: &mut [ErrorRecord]) -> usize {
+    interrupt::free(|cs| ERROR_STORE.borrow(cs).borrow().copy_by_priority(buf))
+}
+
+/// Looks up the pending record of an error code.
+pub fn find_record(code: u16) -> Option<ErrorRecord> {
+    interrupt::free(|cs| ERROR_STORE.borrow(cs).borrow().get(code))
+}
+
+/// Consumes an observed error record.
+///
+/// Only the `count` reports seen by the caller are consumed, so repeats that
+/// arrived meanwhile stay pending and a record consumed by another observer
+/// is never removed twice.
+///
+/// # Parameters:
+/// - `record`: The record previously returned by `peek_all`.
+///
+/// # Returns:
+/// - `true` if a record of the code was found.
+/// - `false` otherwise.
+pub fn consume(record: &ErrorRecord) -> bool {
     interrupt::free(|cs| {
-        let queue = ERROR_QUEUE.borrow(cs).borrow();
-        !queue.is_empty()
+        ERROR_STORE
+            .borrow(cs)
+            .borrow_mut()
+            .consume(record.code, record.count)
     })
 }
+
+/// Counts one playback of an observed error record without consuming it.
+///
+/// Used by observers that show a record several times before consuming it.
+///
+/// # Returns:
+/// - `true` if a record of the code was found.
+/// - `false` otherwise.
+pub fn mark_played(record: &ErrorRecord) -> bool {
+    interrupt::free(|cs| ERROR_STORE.borrow(cs).borrow_mut().mark_played(record.code))
+}
+
+/// Writes the pending error records, most severe first, without consuming them.
+///
+/// Each record shows its severity tag, code and count, followed by the RTC
+/// time of the first and, for repeats, the last report:
+/// `C12x3@2024-05-01T10:00:00..2024-05-01T10:05:00`.
+///
+/// # Parameters:
+/// - `out`: Text sink (console, log buffer, ...).
+pub fn write_error_dump<W: Write>(out: &mut W) -> fmt::Result {
+    let mut records = [ErrorRecord::default(); ERROR_DUMP_MAX];
+    let count = peek_all(&mut records);
+
+    write!(out, "Errors: {} pending", pending_error_count())?;
+    for record in &records[..count] {
+        write!(
+            out,
+            " {}{}x{}",
+            record.severity.tag(),
+            record.code,
+            record.count
+        )?;
+        if let Some(first) = record.first_seen {
+            write!(out, "@{}", first)?;
+            match record.last_seen {
+                Some(last) if record.count > 1 && last != first => write!(out, "..{}", last)?,
+                _ => {}
+            }
+        }
+    }
+    writeln!(out)
+}
+
+/// Removes all pending error records.
+///
+/// # Returns:
+/// - The number of records removed.
+pub fn clear_error_queue() -> usize {
+    interrupt::free(|cs| ERROR_STORE.borrow(cs).borrow_mut().clear())
+}
+
+/// Checks if any error record is pending.
+///
+/// # Returns:
+/// - `true` if the store is not empty.
+/// - `false` if the store is empty.
+pub fn has_errors() -> bool {
+    interrupt::free(|cs| !ERROR_STORE.borrow(cs).borrow().is_empty())
+}
+
+/// Returns the number of distinct error codes currently pending.
+pub fn pending_error_count() -> usize {
+    interrupt::free(|cs| ERROR_STORE.borrow(cs).borrow().len())
+}
+
+/// Returns the total number of errors reported since boot.
+///
+/// Counts every reported error, including repeats and reports rejected by a full store.
+pub fn total_error_count() -> u32 {
+    ERRORS_TOTAL.load(Ordering::Relaxed)
+}
+
+/// Returns the most recently reported error code (0 if none).
+pub fn last_error_code() -> u16 {
+    LAST_ERROR_CODE.load(Ordering::Relaxed) as u16
+}
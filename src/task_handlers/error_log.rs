@@ -0,0 +1,176 @@
+//! # Persistent Error Log
+//!
+//! Keeps the last `ERROR_LOG_LEN` error codes in backup SRAM so they survive
+//! resets, including watchdog resets and crashes:
+//! - Every code passed to `add_error` is appended with its boot number
+//!   and the milliseconds since that boot
+//! - Circular: the oldest entry is overwritten when the log is full
+//! - A header with magic and layout version detects uninitialized SRAM; the
+//!   log is formatted on the first boot after a backup domain loss
+//! - Read and cleared from the host with the console `errlog` command
+//!
+//! ## Layout
+//! Header (4 words): magic, boot count, next slot, entry count; then one
+//! 2-word entry per slot: code | boot << 16, uptime (ms).
+
+use crate::config::{BACKUP_SRAM_ERROR_LOG_OFFSET, ERROR_LOG_LEN};
+use crate::errors::errors::domain;
+use crate::peripherals::backup_sram;
+use core::fmt::{self, Write};
+use cortex_m::interrupt;
+use rtic_monotonics::systick::prelude::*;
+
+/// Header marker ("ELG") and layout version in the low byte; version 2
+/// holds the registered codes of `errors::domain`
+const LOG_MAGIC: u32 = 0x454C_4702;
+
+/// Header word offsets
+const MAGIC: usize = BACKUP_SRAM_ERROR_LOG_OFFSET;
+const BOOT_COUNT: usize = MAGIC + 4;
+const NEXT_SLOT: usize = MAGIC + 8;
+const ENTRY_COUNT: usize = MAGIC + 12;
+
+/// First entry
+const ENTRIES: usize = MAGIC + 16;
+
+/// Entry size in bytes
+const ENTRY_LEN: usize = 8;
+
+/// One persisted error
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LoggedError {
+    /// Error code
+    pub code: u16,
+    /// Boot number the error was reported in (wraps at 65536)
+    pub boot: u16,
+    /// Milliseconds since that boot
+    pub uptime_ms: u32,
+}
+
+/// Opens the log and counts this boot
+///
+/// Must run once during init, before the first error is reported.
+pub fn init() {
+    backup_sram::enable();
+
+    interrupt::free(|_| {
+        if backup_sram::read_word(MAGIC) != LOG_MAGIC
+            || backup_sram::read_word(NEXT_SLOT) as usize >= ERROR_LOG_LEN
+            || backup_sram::read_word(ENTRY_COUNT) as usize > ERROR_LOG_LEN
+        {
+            #[cfg(feature = "debug")]
+            defmt::info!("Error log: no valid log in backup SRAM, formatting");
+
+            backup_sram::write_word(BOOT_COUNT, 0);
+            format();
+            backup_sram::write_word(MAGIC, LOG_MAGIC);
+        }
+
+        let boot = backup_sram::read_word(BOOT_COUNT).wrapping_add(1);
+        backup_sram::write_word(BOOT_COUNT, boot);
+    });
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Error log: boot {}, {} entries kept", boot_count(), len());
+}
+
+/// Appends an error code
+///
+/// # Arguments
+/// * `code` - Error code
+pub fn record(code: u16) {
+    let uptime_ms = crate::Mono::now().ticks();
+
+    interrupt::free(|_| {
+        if backup_sram::read_word(MAGIC) != LOG_MAGIC {
+            return;
+        }
+
+        let slot = backup_sram::read_word(NEXT_SLOT) as usize % ERROR_LOG_LEN;
+        let boot = backup_sram::read_word(BOOT_COUNT) as u16;
+        let offset = ENTRIES + slot * ENTRY_LEN;
+        backup_sram::write_word(offset, code as u32 | ((boot as u32) << 16));
+        backup_sram::write_word(offset + 4, uptime_ms);
+
+        backup_sram::write_word(NEXT_SLOT, ((slot + 1) % ERROR_LOG_LEN) as u32);
+        let count = (backup_sram::read_word(ENTRY_COUNT) as usize + 1).min(ERROR_LOG_LEN);
+        backup_sram::write_word(ENTRY_COUNT, count as u32);
+    });
+}
+
+/// Copies the logged errors, oldest first
+///
+/// # Arguments
+/// * `buf` - Destination for the entries
+///
+/// # Returns
+/// The number of entries copied, at most `buf.len()`
+pub fn entries(buf: &mut [LoggedError]) -> usize {
+    interrupt::free(|_| {
+        if backup_sram::read_word(MAGIC) != LOG_MAGIC {
+            return 0;
+        }
+
+        let count = backup_sram::read_word(ENTRY_COUNT) as usize;
+        let next = backup_sram::read_word(NEXT_SLOT) as usize;
+        let oldest = (next + ERROR_LOG_LEN - count) % ERROR_LOG_LEN;
+
+        let mut copied = 0;
+        for (i, slot) in buf.iter_mut().take(count).enumerate() {
+            let offset = ENTRIES + (oldest + i) % ERROR_LOG_LEN * ENTRY_LEN;
+            let head = backup_sram::read_word(offset);
+            *slot = LoggedError {
+                code: head as u16,
+                boot: (head >> 16) as u16,
+                uptime_ms: backup_sram::read_word(offset + 4),
+            };
+            copied += 1;
+        }
+        copied
+    })
+}
+
+/// Number of logged errors
+pub fn len() -> usize {
+    interrupt::free(|_| backup_sram::read_word(ENTRY_COUNT) as usize).min(ERROR_LOG_LEN)
+}
+
+/// Boots counted since the log was formatted
+pub fn boot_count() -> u32 {
+    backup_sram::read_word(BOOT_COUNT)
+}
+
+/// Removes all entries; the boot count is kept
+pub fn clear() {
+    interrupt::free(|_| format());
+}
+
+/// Resets the entry pointers
+fn format() {
+    backup_sram::write_word(NEXT_SLOT, 0);
+    backup_sram::write_word(ENTRY_COUNT, 0);
+}
+
+/// Writes all logged errors, oldest first, one per line
+pub fn write_log<W: Write>(out: &mut W) -> fmt::Result {
+    let mut logged = [LoggedError::default(); ERROR_LOG_LEN];
+    let count = entries(&mut logged);
+
+    writeln!(out, "Error log: {} entries, boot {}", count, boot_count())?;
+    for entry in &logged[..count] {
+        writeln!(
+            out,
+            "  boot {} +{} ms: {} ({})",
+            entry.boot,
+            entry.uptime_ms,
+            entry.code,
+            domain::name(entry.code).unwrap_or("unknown")
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a one-line summary of the log
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(out, "Error log: {} entries, boot {}", len(), boot_count())
+}
@@ -0,0 +1,119 @@
+//! # Error Push Notifications
+//!
+//! Pushes a short notification frame to the host whenever errors are enqueued,
+//! so dashboards learn about faults without polling:
+//! - The first error of a rate window is sent at once: `record` reports it
+//!   due and the caller pends the USB interrupt, which sends the frame
+//! - One frame per window of `NOTIFY_INTERVAL_MS`, so at most
+//!   `ERROR_NOTIFY_MAX_PER_SEC` frames per second; errors arriving inside a
+//!   window are coalesced into the frame sent when the next one opens
+//! - Off by default; enabled at runtime via `set_enabled`
+//! - Sent on the debug console port only; while it is closed the frame stays
+//!   pending, so no escape sequence lands in the bridged serial stream
+//!
+//! ## Frame Format
+//! `ESC "!ERR " <last code> " x" <count> CR LF` — the leading escape byte lets
+//! the host separate notifications from bridged serial data.
+
+use crate::config::{ERROR_NOTIFY_ENABLED, ERROR_NOTIFY_MAX_PER_SEC};
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use heapless::String;
+
+/// Interval between notification checks (milliseconds)
+pub const NOTIFY_INTERVAL_MS: u32 = 1_000 / ERROR_NOTIFY_MAX_PER_SEC;
+
+/// Maximum encoded frame length
+pub const NOTIFY_FRAME_LEN: usize = 32;
+
+/// Runtime enable flag
+static ENABLED: AtomicBool = AtomicBool::new(ERROR_NOTIFY_ENABLED);
+
+/// Number of errors recorded since the last frame
+static PENDING_COUNT: AtomicU32 = AtomicU32::new(0);
+
+/// Most recent error code recorded
+static LAST_CODE: AtomicU32 = AtomicU32::new(0);
+
+/// A frame was sent in the current rate window
+static WINDOW_USED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables push notifications
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        PENDING_COUNT.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Checks whether push notifications are enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Records a newly enqueued error for the next notification frame
+///
+/// Safe to call from any context; costs a few atomic operations.
+///
+/// # Returns
+/// - `true` if the rate window has room and the frame is due now
+/// - `false` if disabled, or if the error is coalesced until the next window
+pub fn record(code: u16) -> bool {
+    if !is_enabled() {
+        return false;
+    }
+
+    LAST_CODE.store(code as u32, Ordering::Relaxed);
+    PENDING_COUNT.fetch_add(1, Ordering::Relaxed);
+    !WINDOW_USED.load(Ordering::Relaxed)
+}
+
+/// Opens the next rate window
+///
+/// Called every `NOTIFY_INTERVAL_MS`; errors coalesced in the previous
+/// window become due.
+pub fn next_window() {
+    WINDOW_USED.store(false, Ordering::Relaxed);
+}
+
+/// Takes the notification due in the current rate window, if any
+///
+/// Uses up the window, so errors recorded after this wait for the next one.
+///
+/// # Returns
+/// - `Some((code, count))` - most recent code and number of errors it stands for
+/// - `None` if nothing is pending or the window was already used
+pub fn take_due() -> Option<(u16, u32)> {
+    if WINDOW_USED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let pending = take_pending();
+    if pending.is_some() {
+        WINDOW_USED.store(true, Ordering::Relaxed);
+    }
+    pending
+}
+
+/// Takes the coalesced notification, if any
+///
+/// # Returns
+/// - `Some((code, count))` - most recent code and number of errors it stands for
+/// - `None` if nothing was recorded since the last call
+pub fn take_pending() -> Option<(u16, u32)> {
+    match PENDING_COUNT.swap(0, Ordering::Relaxed) {
+        0 => None,
+        count => Some((LAST_CODE.load(Ordering::Relaxed) as u16, count)),
+    }
+}
+
+/// Encodes a notification frame
+///
+/// # Arguments
+/// * `code` - Most recent error code
+/// * `count` - Number of coalesced errors
+pub fn format_frame(code: u16, count: u32) -> String<NOTIFY_FRAME_LEN> {
+    let mut frame = String::new();
+    // Cannot overflow: worst case is 1 + 5 + 5 + 2 + 10 + 2 bytes
+    let _ = write!(frame, "\x1b!ERR {} x{}\r\n", code, count);
+    frame
+}
@@ -0,0 +1,221 @@
+//! # GNSS Receiver Passthrough
+//!
+//! Reads NMEA 0183 sentences of a GPS module on USART6 (`nmea` feature,
+//! see `protocol::nmea`):
+//! - Every byte received while the data port is routed to USART6 goes
+//!   through the sentence reader, from the DMA RX path (`receive`)
+//! - Valid GGA and RMC sentences update the shared fix: time, date,
+//!   position, altitude, satellites and fix quality
+//! - Passthrough mode (`NMEA_PASSTHROUGH`) forwards the data to the host
+//!   unchanged; query mode consumes it, and the host reads the fix with
+//!   `AT+GPS?` instead
+//! - `AT+GPS=PASS|QUERY` switches the mode, `gps` on the console shows the
+//!   fix and the sentence counters
+//!
+//! Sentences with a bad checksum or a field that does not parse are
+//! counted and leave the fix alone.
+
+use crate::config::NMEA_PASSTHROUGH;
+use crate::errors::errors::NmeaError;
+use crate::protocol::nmea::{self, Date, Position, Sentence, SentenceReader, UtcTime};
+use core::cell::{Cell, RefCell};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+
+/// USART6 data goes to the host as well
+static PASSTHROUGH: AtomicBool = AtomicBool::new(NMEA_PASSTHROUGH);
+
+/// Sentence assembly across DMA reads
+static READER: Mutex<RefCell<SentenceReader>> = Mutex::new(RefCell::new(SentenceReader::new()));
+
+/// Fix merged from the sentences so far
+static FIX: Mutex<Cell<Fix>> = Mutex::new(Cell::new(Fix::new()));
+
+static SENTENCES: AtomicU32 = AtomicU32::new(0);
+static CHECKSUM_ERRORS: AtomicU32 = AtomicU32::new(0);
+static MALFORMED: AtomicU32 = AtomicU32::new(0);
+
+/// Receiver state from the latest GGA and RMC sentences
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fix {
+    pub time: Option<UtcTime>,
+    pub date: Option<Date>,
+    /// `None` while the receiver has no fix
+    pub position: Option<Position>,
+    pub altitude_dm: Option<i32>,
+    pub satellites: u8,
+    /// GGA fix quality, 0 without a fix
+    pub quality: u8,
+    /// RMC status `A`
+    pub valid: bool,
+}
+
+impl Fix {
+    /// No sentence seen yet
+    pub const fn new() -> Self {
+        Self {
+            time: None,
+            date: None,
+            position: None,
+            altitude_dm: None,
+            satellites: 0,
+            quality: 0,
+            valid: false,
+        }
+    }
+
+    /// Merges a decoded sentence
+    pub fn apply(&mut self, sentence: Sentence) {
+        match sentence {
+            Sentence::Gga {
+                time,
+                position,
+                quality,
+                satellites,
+                altitude_dm,
+            } => {
+                self.time = time.or(self.time);
+                self.quality = quality;
+                self.satellites = satellites;
+                self.position = position.filter(|_| quality != 0);
+                self.altitude_dm = altitude_dm.filter(|_| quality != 0);
+            }
+            Sentence::Rmc {
+                time,
+                valid,
+                position,
+                date,
+            } => {
+                self.time = time.or(self.time);
+                self.date = date.or(self.date);
+                self.valid = valid;
+                self.position = position.filter(|_| valid);
+            }
+            Sentence::Other => {}
+        }
+    }
+
+    /// Checks whether the position is current
+    pub fn has_fix(&self) -> bool {
+        self.valid || self.quality != 0
+    }
+}
+
+impl Default for Fix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Feeds USART6 data to the sentence reader
+///
+/// Called from the DMA RX path with the bytes just read.
+///
+/// # Returns
+/// Whether the data also goes to the host
+pub fn receive(data: &[u8]) -> bool {
+    interrupt::free(|cs| {
+        let mut reader = READER.borrow(cs).borrow_mut();
+        let fix = FIX.borrow(cs);
+        for &byte in data {
+            match reader.push(byte) {
+                Some(Ok(sentence)) => {
+                    SENTENCES.fetch_add(1, Ordering::Relaxed);
+                    let mut merged = fix.get();
+                    merged.apply(sentence);
+                    fix.set(merged);
+                }
+                Some(Err(NmeaError::Checksum)) => {
+                    CHECKSUM_ERRORS.fetch_add(1, Ordering::Relaxed);
+                }
+                Some(Err(_)) => {
+                    MALFORMED.fetch_add(1, Ordering::Relaxed);
+                }
+                None => {}
+            }
+        }
+    });
+    is_passthrough()
+}
+
+/// Current fix
+pub fn fix() -> Fix {
+    interrupt::free(|cs| FIX.borrow(cs).get())
+}
+
+/// Checks whether USART6 data still goes to the host
+pub fn is_passthrough() -> bool {
+    PASSTHROUGH.load(Ordering::Relaxed)
+}
+
+/// Selects passthrough (`true`) or query mode
+pub fn set_passthrough(passthrough: bool) {
+    PASSTHROUGH.store(passthrough, Ordering::Relaxed);
+}
+
+/// Name of the mode in `AT+GPS=` and the console
+pub fn mode_name() -> &'static str {
+    if is_passthrough() {
+        "pass"
+    } else {
+        "query"
+    }
+}
+
+/// Writes the `AT+GPS?` reply
+///
+/// `+GPS: <fix>,<date>,<time>,<latitude>,<longitude>,<altitude>,<satellites>`
+/// with a fix of 0 or 1, ISO 8601 date and time, decimal degrees and metres;
+/// fields not known yet are left empty.
+pub fn write_at_reply<W: Write>(out: &mut W) -> fmt::Result {
+    let fix = fix();
+    write!(out, "+GPS: {},", u8::from(fix.has_fix()))?;
+    if let Some(date) = fix.date {
+        write!(out, "{}", date)?;
+    }
+    out.write_char(',')?;
+    if let Some(time) = fix.time {
+        write!(out, "{}", time)?;
+    }
+    out.write_char(',')?;
+    match fix.position {
+        Some(position) => write!(out, "{}", position)?,
+        None => out.write_char(',')?,
+    }
+    out.write_char(',')?;
+    if let Some(altitude) = fix.altitude_dm {
+        nmea::write_fixed(out, altitude, 1)?;
+    }
+    write!(out, ",{}\r\n", fix.satellites)
+}
+
+/// Writes the mode, the fix and the sentence counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let fix = fix();
+    writeln!(
+        out,
+        "GNSS: {} mode, {} sentences, {} checksum errors, {} malformed",
+        mode_name(),
+        SENTENCES.load(Ordering::Relaxed),
+        CHECKSUM_ERRORS.load(Ordering::Relaxed),
+        MALFORMED.load(Ordering::Relaxed)
+    )?;
+    match (fix.date, fix.time) {
+        (Some(date), Some(time)) => writeln!(out, "  time {}T{}Z", date, time)?,
+        (None, Some(time)) => writeln!(out, "  time {}Z", time)?,
+        _ => {}
+    }
+    match fix.position {
+        Some(position) => {
+            write!(out, "  fix quality {}, position {}", fix.quality, position)?;
+            if let Some(altitude) = fix.altitude_dm {
+                out.write_str(", altitude ")?;
+                nmea::write_fixed(out, altitude, 1)?;
+                out.write_str(" m")?;
+            }
+            writeln!(out, ", {} satellites", fix.satellites)
+        }
+        None => writeln!(out, "  no fix, {} satellites", fix.satellites),
+    }
+}
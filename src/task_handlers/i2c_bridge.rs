@@ -0,0 +1,216 @@
+//! # USB-to-I2C Bridge
+//!
+//! Turns the data port into an I2C host adapter (`i2c-bridge` feature):
+//! while the mode is on, host data is not forwarded to the UART but read as
+//! frames of the framed link (`protocol::framer`: COBS, CRC32, 0x00
+//! delimiter), each carrying one I2C transaction for the I2C1 master.
+//!
+//! - The USB interrupt only decodes frames and queues them
+//!   (`I2C_BRIDGE_QUEUE_LEN`); a full queue drops the request
+//! - The priority-1 dispatcher task runs the queued transactions in order
+//!   and frames one response per request back to the host
+//! - UART data is dropped while the mode is on, so responses are not mixed
+//!   with bridged bytes
+//!
+//! ## Wire Protocol
+//! ```text
+//! Request:  [address << 1 | R/W] [length] [payload ...]
+//! Response: [status] [read data ...]
+//! ```
+//! - Write (R/W = 0): sends `payload`; `length` must equal its size. An
+//!   empty write probes the address
+//! - Read (R/W = 1): sends `payload` if there is one, then reads `length`
+//!   bytes after a repeated start, e.g. a register read
+//! - `status` is a `Status` code; read data follows only on `Ok`
+
+use crate::config::{FRAME_MAX_PAYLOAD_LEN, I2C_BRIDGE_QUEUE_LEN};
+use crate::errors::errors::I2cError;
+use crate::peripherals::i2c::I2c1Bus;
+use crate::protocol::framer::{self, FrameDecoder};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::{Deque, Vec};
+
+/// Request header: address byte and length
+const HEADER_LEN: usize = 2;
+
+/// Longest read: the response carries the status byte in front
+pub const MAX_READ_LEN: usize = FRAME_MAX_PAYLOAD_LEN - 1;
+
+/// Largest response frame, delimiter included
+pub const RESPONSE_FRAME_LEN: usize = framer::MAX_FRAME_LEN + 1;
+
+/// Response status codes
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Status {
+    /// Transaction completed
+    Ok = 0x00,
+    /// Address or data byte not acknowledged
+    Nack = 0x01,
+    /// Bus wait timed out
+    Timeout = 0x02,
+    /// Another master won the bus
+    ArbitrationLost = 0x03,
+    /// Misplaced START or STOP on the bus
+    BusError = 0x04,
+    /// Bus held busy
+    Busy = 0x05,
+    /// Request shorter than its header, or a length that does not fit
+    BadRequest = 0x10,
+    /// Frame with a CRC or encoding error
+    BadFrame = 0x11,
+}
+
+impl From<I2cError> for Status {
+    fn from(error: I2cError) -> Self {
+        match error {
+            I2cError::Nack => Status::Nack,
+            I2cError::Timeout => Status::Timeout,
+            I2cError::ArbitrationLost => Status::ArbitrationLost,
+            I2cError::Bus => Status::BusError,
+            I2cError::Busy => Status::Busy,
+        }
+    }
+}
+
+/// Decoded request, or `None` for a frame that failed to decode
+type Pending = Option<Vec<u8, FRAME_MAX_PAYLOAD_LEN>>;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DECODER: Mutex<RefCell<FrameDecoder>> = Mutex::new(RefCell::new(FrameDecoder::new()));
+static QUEUE: Mutex<RefCell<Deque<Pending, I2C_BRIDGE_QUEUE_LEN>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+static REQUESTS: AtomicU32 = AtomicU32::new(0);
+static FAILED: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Checks whether host data is read as I2C requests
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Switches the data port between the UART bridge and the I2C bridge
+///
+/// Partial frames and queued requests are dropped.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    interrupt::free(|cs| {
+        DECODER.borrow(cs).borrow_mut().reset();
+        QUEUE.borrow(cs).borrow_mut().clear();
+    });
+
+    #[cfg(feature = "debug")]
+    defmt::info!("USB-I2C bridge {=bool}", enabled);
+}
+
+/// Decodes host data and queues the completed requests
+///
+/// # Arguments
+/// * `data` - Bytes received on the data port
+///
+/// # Returns
+/// Number of requests queued
+pub fn receive(data: &[u8]) -> usize {
+    let mut payload = [0u8; FRAME_MAX_PAYLOAD_LEN];
+    let mut queued = 0;
+    interrupt::free(|cs| {
+        let mut decoder = DECODER.borrow(cs).borrow_mut();
+        let mut queue = QUEUE.borrow(cs).borrow_mut();
+        for &byte in data {
+            let pending = match decoder.push(byte, &mut payload) {
+                None => continue,
+                Some(Ok(len)) => Vec::from_slice(&payload[..len]).ok(),
+                Some(Err(_)) => None,
+            };
+            if queue.push_back(pending).is_ok() {
+                queued += 1;
+            } else {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+    queued
+}
+
+/// Checks whether requests wait for the dispatcher
+pub fn has_pending() -> bool {
+    interrupt::free(|cs| !QUEUE.borrow(cs).borrow().is_empty())
+}
+
+/// Runs the oldest queued request and frames its response
+///
+/// # Arguments
+/// * `bus` - I2C1 master
+/// * `frame` - Destination of the response frame
+///
+/// # Returns
+/// Length of the response frame; `None` once the queue is empty
+pub fn service(bus: &mut I2c1Bus, frame: &mut [u8; RESPONSE_FRAME_LEN]) -> Option<usize> {
+    let pending = interrupt::free(|cs| QUEUE.borrow(cs).borrow_mut().pop_front())?;
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    let mut response = [0u8; FRAME_MAX_PAYLOAD_LEN];
+    let result = match pending {
+        Some(request) => execute(bus, &request, &mut response[1..]),
+        None => Err(Status::BadFrame),
+    };
+    let len = match result {
+        Ok(read) => {
+            response[0] = Status::Ok as u8;
+            1 + read
+        }
+        Err(status) => {
+            FAILED.fetch_add(1, Ordering::Relaxed);
+            response[0] = status as u8;
+            1
+        }
+    };
+
+    // The response never exceeds the payload limit of the frame
+    framer::encode_packet(&response[..len], frame).ok()
+}
+
+/// Runs one request on the bus
+///
+/// # Returns
+/// Number of bytes read into `read_buffer`
+fn execute(bus: &mut I2c1Bus, request: &[u8], read_buffer: &mut [u8]) -> Result<usize, Status> {
+    let (header, payload) = request
+        .split_first_chunk::<HEADER_LEN>()
+        .ok_or(Status::BadRequest)?;
+    let address = header[0] >> 1;
+    let length = usize::from(header[1]);
+
+    if header[0] & 1 == 0 {
+        if length != payload.len() {
+            return Err(Status::BadRequest);
+        }
+        bus.write(address, payload)?;
+        return Ok(0);
+    }
+
+    let buffer = read_buffer.get_mut(..length).ok_or(Status::BadRequest)?;
+    if payload.is_empty() {
+        bus.read(address, buffer)?;
+    } else {
+        bus.write_read(address, payload, buffer)?;
+    }
+    Ok(length)
+}
+
+/// Writes the bridge mode and request counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "USB-I2C bridge: {}, {} requests, {} failed, {} dropped",
+        if is_enabled() { "on" } else { "off" },
+        REQUESTS.load(Ordering::Relaxed),
+        FAILED.load(Ordering::Relaxed),
+        DROPPED.load(Ordering::Relaxed)
+    )
+}
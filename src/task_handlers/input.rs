@@ -0,0 +1,130 @@
+//! # Input Event Dispatch
+//!
+//! Turns touch input into bridge actions (`touch` feature):
+//! - The touch task reports `InputEvent`s: a tap on first contact, a hold
+//!   once the finger stayed down for `TOUCH_HOLD_MS`
+//! - `TOUCH_AREAS` maps screen rectangles to actions; the status screen
+//!   draws them as a button bar
+//! - Each area fires on one trigger only, so a hold on a tap button does
+//!   not repeat its action
+//!
+//! The actions themselves need RTIC resources and are run by the task.
+
+use crate::config::TOUCH_BAUD_RATES;
+use crate::peripherals::display::{CELL_HEIGHT, HEIGHT, WIDTH};
+
+/// Height of the button bar: two text rows
+const BAR_HEIGHT: u16 = 2 * CELL_HEIGHT as u16;
+
+/// Top edge of the button bar
+const BAR_TOP: u16 = HEIGHT as u16 - BAR_HEIGHT;
+
+/// Gap between two buttons (pixels)
+const GAP: u16 = 10;
+
+/// Width of one of the three buttons
+const BUTTON_WIDTH: u16 = (WIDTH as u16 - 2 * GAP) / 3;
+
+/// Input reported by the touch task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    /// First contact of a finger
+    Tap { x: u16, y: u16 },
+    /// Finger held down for `TOUCH_HOLD_MS` at the tap position
+    Hold { x: u16, y: u16 },
+}
+
+/// Event kind an area reacts to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    Tap,
+    Hold,
+}
+
+/// Actions bound to touch areas
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchAction {
+    /// Drop all pending error records
+    ClearErrors,
+    /// Step the routed UART to the next of `TOUCH_BAUD_RATES`
+    CycleBaud,
+    /// Reset the board
+    Reboot,
+}
+
+/// Screen rectangle bound to an action
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchArea {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+    /// Button caption
+    pub label: &'static str,
+    pub trigger: Trigger,
+    pub action: TouchAction,
+}
+
+impl TouchArea {
+    /// Checks whether a point lies inside the area
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// On-screen buttons, left to right along the bottom edge
+pub const TOUCH_AREAS: [TouchArea; 3] = [
+    TouchArea {
+        x: 0,
+        y: BAR_TOP,
+        width: BUTTON_WIDTH,
+        height: BAR_HEIGHT,
+        label: "CLEAR ERRORS",
+        trigger: Trigger::Tap,
+        action: TouchAction::ClearErrors,
+    },
+    TouchArea {
+        x: BUTTON_WIDTH + GAP,
+        y: BAR_TOP,
+        width: BUTTON_WIDTH,
+        height: BAR_HEIGHT,
+        label: "NEXT BAUD",
+        trigger: Trigger::Tap,
+        action: TouchAction::CycleBaud,
+    },
+    TouchArea {
+        x: 2 * (BUTTON_WIDTH + GAP),
+        y: BAR_TOP,
+        width: BUTTON_WIDTH,
+        height: BAR_HEIGHT,
+        label: "REBOOT (HOLD)",
+        trigger: Trigger::Hold,
+        action: TouchAction::Reboot,
+    },
+];
+
+/// Finds the action an input event triggers
+///
+/// # Returns
+/// `None` for events outside all areas or with the other trigger
+pub fn dispatch(event: InputEvent) -> Option<TouchAction> {
+    let (trigger, x, y) = match event {
+        InputEvent::Tap { x, y } => (Trigger::Tap, x, y),
+        InputEvent::Hold { x, y } => (Trigger::Hold, x, y),
+    };
+    TOUCH_AREAS
+        .iter()
+        .find(|area| area.trigger == trigger && area.contains(x, y))
+        .map(|area| area.action)
+}
+
+/// Returns the baud rate following `current` in `TOUCH_BAUD_RATES`
+///
+/// Rates above the last entry wrap around to the first one.
+pub fn next_baud_rate(current: u32) -> u32 {
+    TOUCH_BAUD_RATES
+        .iter()
+        .copied()
+        .find(|&rate| rate > current)
+        .unwrap_or(TOUCH_BAUD_RATES[0])
+}
@@ -0,0 +1,59 @@
+//! # UART Break
+//!
+//! Break conditions on the routed UART for the CDC `SEND_BREAK` requests of
+//! the data port. The USB interrupt posts each request here and starts the
+//! `uart_break` task, which holds the TX line low through the controller:
+//! - a timed request for its duration in milliseconds
+//! - wValue 0xFFFF until the next request
+//! - wValue 0 ends a running break
+//!
+//! Only the latest request is kept; a request that arrives during a break
+//! replaces it. A USB bus reset ends any break. UART data from the host
+//! waits in the TX ring buffer while the line is held.
+
+use crate::peripherals::cdc_acm::BreakRequest;
+use core::cell::Cell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::interrupt::Mutex;
+
+/// Request not yet taken by the task
+static PENDING: Mutex<Cell<Option<BreakRequest>>> = Mutex::new(Cell::new(None));
+
+static BREAKS: AtomicU32 = AtomicU32::new(0);
+static FAILED: AtomicU32 = AtomicU32::new(0);
+
+/// Posts a host request, replacing one not taken yet
+pub fn post(request: BreakRequest) {
+    cortex_m::interrupt::free(|cs| PENDING.borrow(cs).set(Some(request)));
+}
+
+/// Takes the latest request
+pub fn take() -> Option<BreakRequest> {
+    cortex_m::interrupt::free(|cs| PENDING.borrow(cs).take())
+}
+
+/// Checks whether a request waits, ending the current break early
+pub fn is_pending() -> bool {
+    cortex_m::interrupt::free(|cs| PENDING.borrow(cs).get().is_some())
+}
+
+/// Counts a break that held the line
+pub fn record_break() {
+    BREAKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a break dropped because the UART output did not drain
+pub fn record_failed() {
+    FAILED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Writes the break counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "UART breaks: {} sent, {} dropped",
+        BREAKS.load(Ordering::Relaxed),
+        FAILED.load(Ordering::Relaxed)
+    )
+}
@@ -1,5 +1,71 @@
+#[cfg(target_os = "none")]
+pub mod activity_leds;
+#[cfg(all(target_os = "none", feature = "adc"))]
+pub mod adc_stream;
+pub mod auto_baud;
+#[cfg(target_os = "none")]
+pub mod backpressure;
+pub mod baud_negotiation;
+#[cfg(target_os = "none")]
+pub mod benchmark;
+#[cfg(target_os = "none")]
 pub mod blue_led;
+#[cfg(target_os = "none")]
+pub mod bridge_mode;
+#[cfg(target_os = "none")]
+pub mod button;
+#[cfg(target_os = "none")]
+pub mod command;
+#[cfg(target_os = "none")]
+pub mod console;
+pub mod data_path;
+#[cfg(target_os = "none")]
 pub mod dma2;
+#[cfg(target_os = "none")]
 pub mod error_handlers;
+#[cfg(target_os = "none")]
+pub mod error_log;
+pub mod error_notify;
+#[cfg(feature = "nmea")]
+pub mod gnss;
+#[cfg(all(target_os = "none", feature = "i2c-bridge"))]
+pub mod i2c_bridge;
+#[cfg(all(target_os = "none", feature = "touch"))]
+pub mod input;
+#[cfg(target_os = "none")]
+pub mod line_break;
+#[cfg(feature = "modbus")]
+pub mod modbus;
+#[cfg(target_os = "none")]
 pub mod otg_fs;
-pub mod red_led_handler;
+#[cfg(target_os = "none")]
+pub mod periodic;
+#[cfg(target_os = "none")]
+pub mod power;
+#[cfg(target_os = "none")]
+pub mod safe_mode;
+#[cfg(all(target_os = "none", feature = "sd-log"))]
+pub mod sd_log;
+#[cfg(target_os = "none")]
+pub mod settings;
+#[cfg(target_os = "none")]
+pub mod shutdown;
+#[cfg(target_os = "none")]
+pub mod signal_handler;
+#[cfg(all(target_os = "none", feature = "can"))]
+pub mod slcan;
+#[cfg(target_os = "none")]
+pub mod snapshot;
+#[cfg(all(target_os = "none", feature = "display"))]
+pub mod status_screen;
+#[cfg(target_os = "none")]
+pub mod target_probe;
+pub mod task_registry;
+#[cfg(target_os = "none")]
+pub mod uart_route;
+#[cfg(target_os = "none")]
+pub mod uart_strap;
+#[cfg(all(target_os = "none", feature = "usb-log"))]
+pub mod usb_log;
+#[cfg(target_os = "none")]
+pub mod usb_suspend;
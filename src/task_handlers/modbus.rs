@@ -0,0 +1,306 @@
+//! # Modbus RTU Master
+//!
+//! Polls Modbus slaves on USART6 from the debug console (`modbus` feature),
+//! with the framing of `protocol::modbus`:
+//! - `modbus <slave> coils|discrete|holding|input <addr> [count]` reads,
+//!   `modbus <slave> write <addr> <value>...` writes registers and
+//!   `modbus <slave> coil <addr> on|off` a coil
+//! - The same requests as a JSON object, e.g.
+//!   `modbus {"slave":1,"fn":3,"addr":0,"count":2}`, with the values of a
+//!   write in `"values":[...]`
+//! - The `modbus_transaction` task routes the data port to USART6, sets the
+//!   RX timeout to the frame gap and sends the request; the TIM3 interrupt
+//!   posts the silence that ends the response (`post_silence`)
+//! - The result is one JSON line, e.g.
+//!   `{"slave":1,"fn":3,"addr":0,"values":[17,4]}`, shown by `modbus` and
+//!   sent to the USB log
+//!
+//! Bridged data is held in the ring buffers while a transaction runs.
+
+use crate::config::MODBUS_MAX_WRITE_VALUES;
+use crate::errors::errors::ModbusError;
+use crate::protocol::modbus::{self, Function, Request, Response};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Vec;
+
+/// Usage of the ASCII form
+const USAGE: &str = "usage: modbus <slave> coils|discrete|holding|input <addr> [count], \
+                     modbus <slave> write <addr> <value>..., modbus <slave> coil <addr> on|off";
+
+/// Usage of the JSON form
+const USAGE_JSON: &str =
+    "usage: modbus {\"slave\":<n>,\"fn\":<code>,\"addr\":<n>,\"count\":<n>|\"values\":[...]}";
+
+/// Rejection of a request outside the Modbus limits
+const LIMITS: &str = "slave 1-247, read 1-64 values, write 1-16 registers or one coil";
+
+/// Values of a write request
+type WriteValues = Vec<u16, MODBUS_MAX_WRITE_VALUES>;
+
+/// Set while a transaction owns the UART data path
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Set by the RX timeout interrupt once the line went silent
+static SILENCE: AtomicBool = AtomicBool::new(false);
+
+/// Most recent transaction
+static LAST: Mutex<RefCell<Option<Transaction>>> = Mutex::new(RefCell::new(None));
+
+static REQUESTS: AtomicU32 = AtomicU32::new(0);
+static EXCEPTIONS: AtomicU32 = AtomicU32::new(0);
+static TIMEOUTS: AtomicU32 = AtomicU32::new(0);
+static FAILURES: AtomicU32 = AtomicU32::new(0);
+
+/// Request and its outcome
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub request: Request,
+    pub result: Result<Response, ModbusError>,
+}
+
+impl fmt::Display for Transaction {
+    /// Writes the transaction as one JSON object
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let request = &self.request;
+        write!(
+            f,
+            "{{\"slave\":{},\"fn\":{},\"addr\":{},",
+            request.slave,
+            request.function.code(),
+            request.address
+        )?;
+        match &self.result {
+            Ok(Response::Values(values)) => {
+                f.write_str("\"values\":[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_str("]}")
+            }
+            Ok(Response::Written) => write!(f, "\"written\":{}}}", request.count),
+            Ok(Response::Exception(code)) => write!(
+                f,
+                "\"exception\":{},\"text\":\"{}\"}}",
+                code,
+                modbus::exception_name(*code)
+            ),
+            Err(e) => write!(f, "\"error\":\"{}\"}}", e),
+        }
+    }
+}
+
+/// Parses the arguments of a `modbus` console request
+///
+/// # Arguments
+/// * `args` - Everything after `modbus`: a JSON object or the ASCII form
+pub fn parse(args: &str) -> Result<Request, &'static str> {
+    let args = args.trim();
+    if args.starts_with('{') {
+        parse_json(args)
+    } else {
+        parse_ascii(args)
+    }
+}
+
+fn parse_ascii(args: &str) -> Result<Request, &'static str> {
+    let mut words = args.split_ascii_whitespace();
+    let slave = words.next().and_then(parse_number).ok_or(USAGE)?;
+    let slave = to_slave(slave)?;
+    let kind = words.next().ok_or(USAGE)?;
+    let address = words.next().and_then(parse_number).ok_or(USAGE)?;
+
+    let function = match kind {
+        "coils" => Function::ReadCoils,
+        "discrete" => Function::ReadDiscreteInputs,
+        "holding" => Function::ReadHoldingRegisters,
+        "input" => Function::ReadInputRegisters,
+        "coil" => Function::WriteSingleCoil,
+        "write" => Function::WriteMultipleRegisters,
+        _ => return Err(USAGE),
+    };
+    let request = match function {
+        Function::WriteSingleCoil => {
+            let state = match (words.next(), words.next()) {
+                (Some("on" | "1"), None) => 1,
+                (Some("off" | "0"), None) => 0,
+                _ => return Err(USAGE),
+            };
+            Request::write(slave, true, address, &[state])
+        }
+        Function::WriteMultipleRegisters => {
+            let mut values = WriteValues::new();
+            for word in words {
+                let value = parse_number(word).ok_or(USAGE)?;
+                values.push(value).map_err(|_| LIMITS)?;
+            }
+            Request::write(slave, false, address, &values)
+        }
+        _ => {
+            let count = match (words.next(), words.next()) {
+                (None, _) => 1,
+                (Some(count), None) => parse_number(count).ok_or(USAGE)?,
+                _ => return Err(USAGE),
+            };
+            Request::read(slave, function, address, count)
+        }
+    };
+    request.map_err(|_| LIMITS)
+}
+
+/// Parses a flat JSON object with number and number array members
+fn parse_json(text: &str) -> Result<Request, &'static str> {
+    let body = text
+        .strip_prefix('{')
+        .and_then(|rest| rest.strip_suffix('}'))
+        .ok_or(USAGE_JSON)?;
+
+    let (mut slave, mut code, mut address, mut count) = (None, None, None, None);
+    let mut values = WriteValues::new();
+    let mut rest = body.trim();
+    while !rest.is_empty() {
+        let (key, after) = rest
+            .strip_prefix('"')
+            .and_then(|rest| rest.split_once('"'))
+            .ok_or(USAGE_JSON)?;
+        let after = after
+            .trim_start()
+            .strip_prefix(':')
+            .ok_or(USAGE_JSON)?
+            .trim_start();
+
+        let after = if let Some(array) = after.strip_prefix('[') {
+            let (items, after) = array.split_once(']').ok_or(USAGE_JSON)?;
+            if key != "values" {
+                return Err(USAGE_JSON);
+            }
+            for item in items
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+            {
+                let value = parse_number(item).ok_or(USAGE_JSON)?;
+                values.push(value).map_err(|_| LIMITS)?;
+            }
+            after
+        } else {
+            let end = after.find(',').unwrap_or(after.len());
+            let value = parse_number(after[..end].trim()).ok_or(USAGE_JSON)?;
+            match key {
+                "slave" => slave = Some(value),
+                "fn" => code = Some(value),
+                "addr" => address = Some(value),
+                "count" => count = Some(value),
+                "value" => values.push(value).map_err(|_| LIMITS)?,
+                _ => return Err(USAGE_JSON),
+            }
+            &after[end..]
+        };
+
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    let slave = to_slave(slave.ok_or(USAGE_JSON)?)?;
+    let address = address.ok_or(USAGE_JSON)?;
+    let function = code
+        .and_then(|code| u8::try_from(code).ok())
+        .and_then(Function::from_code)
+        .ok_or("fn must be 1, 2, 3, 4, 5, 6 or 16")?;
+    let request = match function {
+        Function::WriteSingleCoil => Request::write(slave, true, address, &values),
+        Function::WriteSingleRegister if values.len() == 1 => {
+            Request::write(slave, false, address, &values)
+        }
+        Function::WriteMultipleRegisters if values.len() > 1 => {
+            Request::write(slave, false, address, &values)
+        }
+        Function::WriteSingleRegister | Function::WriteMultipleRegisters => {
+            Err(ModbusError::InvalidRequest)
+        }
+        _ => Request::read(slave, function, address, count.unwrap_or(1)),
+    };
+    request.map_err(|_| LIMITS)
+}
+
+/// Parses a decimal or `0x` hex number
+fn parse_number(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn to_slave(value: u16) -> Result<u8, &'static str> {
+    u8::try_from(value).map_err(|_| LIMITS)
+}
+
+/// Marks a transaction as running; bridged data is held from now on
+pub fn begin() {
+    SILENCE.store(false, Ordering::Relaxed);
+    ACTIVE.store(true, Ordering::SeqCst);
+    REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Checks whether UART RX must be left for the transaction
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Posts the end of an RX burst (RX timeout interrupt)
+pub fn post_silence() {
+    if is_active() {
+        SILENCE.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Takes the silence posted by the RX timeout interrupt
+pub fn take_silence() -> bool {
+    SILENCE.swap(false, Ordering::Relaxed)
+}
+
+/// Records the outcome and hands the data path back to the bridge
+///
+/// # Returns
+/// The completed transaction
+pub fn finish(request: Request, result: Result<Response, ModbusError>) -> Transaction {
+    let counter = match result {
+        Ok(Response::Exception(_)) => Some(&EXCEPTIONS),
+        Err(ModbusError::Timeout) => Some(&TIMEOUTS),
+        Err(_) => Some(&FAILURES),
+        Ok(_) => None,
+    };
+    if let Some(counter) = counter {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+    let transaction = Transaction { request, result };
+    interrupt::free(|cs| *LAST.borrow(cs).borrow_mut() = Some(transaction.clone()));
+    ACTIVE.store(false, Ordering::SeqCst);
+    transaction
+}
+
+/// Writes the counters and the most recent transaction as JSON
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "Modbus: {} requests, {} exceptions, {} timeouts, {} failed{}",
+        REQUESTS.load(Ordering::Relaxed),
+        EXCEPTIONS.load(Ordering::Relaxed),
+        TIMEOUTS.load(Ordering::Relaxed),
+        FAILURES.load(Ordering::Relaxed),
+        if is_active() {
+            ", transaction running"
+        } else {
+            ""
+        }
+    )?;
+    match interrupt::free(|cs| LAST.borrow(cs).borrow().clone()) {
+        Some(transaction) => writeln!(out, "{}", transaction),
+        None => Ok(()),
+    }
+}
@@ -5,17 +5,84 @@
 //! - Bidirectional data transfer handling
 //! - Buffer management with error recovery
 //! - Partial write handling with data preservation
+//! - AT command escape filtering of host data (see `command`)
+//! - Zero-copy staging of host data in the UART DMA TX buffers
+//! - Line ending translation, stripping and local echo (see `transform`)
+//! - Backpressure: host packets stay in the endpoint, which NAKs, while the
+//!   TX ring buffer is above its high watermark (see `backpressure`)
+//!
+//! Host packets are read straight into the `TxPingPong` staging buffers
+//! while the TX ring buffer is empty; the ring buffer only takes the
+//! overflow, and all data while framing or a host-to-UART transform is on.
+//! The USB controller is locked inside the staging lock for the direct
+//! read, but never in the same critical section as a ring buffer.
+//!
+//! Staging is skipped unless the bridge mode (`bridge_mode`) sends host
+//! data to the UART; in `usb-echo` the ring buffer holds it for the echo,
+//! in `uart-echo` it is dropped after the AT command filter.
+//!
+//! In USB-to-I2C bridge mode (`i2c-bridge` feature) and SLCAN mode (`can`
+//! feature) host data bypasses the AT command filter and the UART and goes
+//! to the request decoder of the mode.
+//!
+//! The handlers are generic over `UsbLink`; the write to the host and the
+//! forwarding of untranslated RX data are steps of `data_path`, tested in
+//! host builds against a mock device.
 
 use crate::config::DATA_PACKET_SIZE;
-use crate::data_structures::ring_buffer::RingBuffer;
+use crate::data_structures::log_queue::IsrContext;
+use crate::data_structures::spsc::{RxConsumer, TxProducer};
+use crate::data_structures::tx_pingpong::TxPingPong;
 use crate::errors::errors::{DeviceError, UsbError};
-use crate::peripherals::otg_fs::OtgFsController;
+use crate::isr_log;
+use crate::peripherals::traits::UsbLink;
+use crate::protocol::{framer, transform};
+use crate::task_handlers::backpressure;
+use crate::task_handlers::bridge_mode::{self, Sink};
+use crate::task_handlers::command;
+use crate::task_handlers::data_path;
+use crate::task_handlers::dma2::data_port_diverted;
+#[cfg(feature = "i2c-bridge")]
+use crate::task_handlers::i2c_bridge;
+#[cfg(feature = "can")]
+use crate::task_handlers::slcan;
+use crate::utils::lock_stats::{self, LockSite};
+use crate::utils::retry::{Backoff, RetryPolicy};
+use crate::utils::statistics;
+use core::sync::atomic::{AtomicBool, Ordering};
+use rtic::Mutex;
+
+/// Staging space needed for one packet behind the held escape characters
+const STAGING_HEADROOM: usize = DATA_PACKET_SIZE + command::ESCAPE_LEN - 1;
+
+static TX_STAGING: AtomicBool = AtomicBool::new(true);
+
+/// Waiting for the host to (re)configure the device before forwarding RX data:
+/// backoff from 50 ms up to 1 s, about 3 s in total before the data is left buffered
+pub static USB_RECONNECT: RetryPolicy = RetryPolicy::new(
+    "usb",
+    6,
+    Backoff::Exponential {
+        base_ms: 50,
+        max_ms: 1000,
+    },
+    20,
+);
+
+/// Enables or disables zero-copy staging of host data
+///
+/// While disabled, all host data goes through the TX ring buffer.
+pub fn set_tx_staging(enabled: bool) {
+    TX_STAGING.store(enabled, Ordering::Relaxed);
+}
 
 /// Handles USB communication lifecycle
 ///
 /// # Arguments
-/// * `usb` - USB controller instance
-/// * `tx` - Transmit ring buffer
+/// * `isr` - Interrupt context of the OTG FS handler; logs are deferred
+/// * `usb` - USB controller resource
+/// * `tx` - Producer half of the transmit ring buffer
+/// * `staging` - DMA TX staging buffers
 ///
 /// # Returns
 /// - `Ok(bytes_processed)` - Number of bytes successfully processed
@@ -23,59 +90,188 @@ use crate::peripherals::otg_fs::OtgFsController;
 ///
 /// # Flow
 /// 1. Checks USB configuration status
-/// 2. Processes incoming USB data
-/// 3. Returns transfer metrics
-pub fn handle_usb(
-    usb: &mut OtgFsController<'static>,
-    tx: &mut RingBuffer,
+/// 2. Leaves the packet unread while the TX ring buffer is held
+/// 3. Processes incoming USB data
+/// 4. Returns transfer metrics
+pub fn handle_usb<L: UsbLink>(
+    isr: &IsrContext,
+    usb: &mut impl Mutex<T = L>,
+    tx: &mut impl Mutex<T = TxProducer>,
+    staging: &mut impl Mutex<T = TxPingPong>,
 ) -> Result<usize, DeviceError> {
-    if !usb.is_configured() {
-        #[cfg(feature = "debug")]
-        defmt::warn!("USB device not configured - skipping transfer");
+    if !usb.lock(|usb| usb.is_configured()) {
+        isr_log!(isr, warn, "USB device not configured - skipping transfer");
+        return Ok(0);
+    }
+
+    #[cfg(feature = "i2c-bridge")]
+    if i2c_bridge::is_enabled() {
+        return divert_usb_data(usb, i2c_bridge::receive);
+    }
+    #[cfg(feature = "can")]
+    if slcan::is_enabled() {
+        return divert_usb_data(usb, slcan::receive);
+    }
+
+    // The TX low watermark pends the USB interrupt to read it
+    if backpressure::is_tx_held() {
         return Ok(0);
     }
 
-    let bytes_processed = process_usb_data(usb, tx)?;
-    Ok(bytes_processed)
+    lock_stats::span(LockSite::UsbRx, || {
+        // Staged data must not overtake data already in the ring buffer
+        let direct = TX_STAGING.load(Ordering::Relaxed)
+            && !framer::is_enabled()
+            && !transform::is_active_to_uart()
+            && bridge_mode::routes().host_data == Sink::Uart
+            && tx.lock(|tx| tx.is_empty());
+
+        if direct {
+            if let Some(result) = stage_usb_data(isr, usb, staging) {
+                return result;
+            }
+        }
+        process_usb_data(isr, usb, tx)
+    })
+}
+
+/// Reads incoming USB data straight into the DMA TX staging buffers
+///
+/// # Returns
+/// `None` without reading if the filling buffer has no room for a full
+/// packet; the caller falls back to the ring buffer
+fn stage_usb_data<L: UsbLink>(
+    isr: &IsrContext,
+    usb: &mut impl Mutex<T = L>,
+    staging: &mut impl Mutex<T = TxPingPong>,
+) -> Option<Result<usize, DeviceError>> {
+    lock_stats::lock(LockSite::UsbRx, staging, |staging| {
+        if staging.space() < STAGING_HEADROOM {
+            return None;
+        }
+
+        let spare = staging.spare_mut();
+        let start = command::ESCAPE_LEN - 1;
+        let received = usb.lock(|usb| usb.read_into(&mut spare[start..STAGING_HEADROOM]));
+
+        Some(match received {
+            Ok(0) => Ok(0),
+            Ok(count) => {
+                isr_log!(isr, debug, "USB RX: bytes staged", count);
+                statistics::add_usb_rx(count);
+
+                let count = command::filter(&mut spare[..start + count], start);
+                staging.commit(count);
+                Ok(count)
+            }
+            Err(e) => {
+                isr_log!(isr, error, "USB read failure", e.code());
+                statistics::add_usb_error();
+                Err(e.into())
+            }
+        })
+    })
 }
 
 /// Processes incoming USB data to transmit buffer
 ///
+/// The packet is copied out under the USB lock and pushed under the TX
+/// buffer lock. Bytes consumed by the AT command channel are not pushed,
+/// nor anything while the bridge mode discards host data. The rest passes
+/// the host-to-UART transform.
+///
 /// # Arguments
-/// * `usb` - USB controller instance
-/// * `tx` - Transmit ring buffer
+/// * `isr` - Interrupt context of the OTG FS handler; logs are deferred
+/// * `usb` - USB controller resource
+/// * `tx` - Producer half of the transmit ring buffer
 ///
 /// # Errors
 /// Returns `DeviceError` on:
 /// - USB read failures
 /// - Buffer overflow conditions
-fn process_usb_data(
-    usb: &mut OtgFsController<'static>,
-    tx: &mut RingBuffer,
+fn process_usb_data<L: UsbLink>(
+    isr: &IsrContext,
+    usb: &mut impl Mutex<T = L>,
+    tx: &mut impl Mutex<T = TxProducer>,
 ) -> Result<usize, DeviceError> {
-    match usb.read() {
-        Ok(Some((data, count))) => {
-            #[cfg(feature = "debug")]
-            defmt::debug!("USB RX: {} bytes", count);
+    // Headroom in front for escape characters released by the filter
+    let start = command::ESCAPE_LEN - 1;
+    let mut data = [0u8; STAGING_HEADROOM];
+    let received = lock_stats::lock(LockSite::UsbRx, usb, |usb| {
+        usb.read_into(&mut data[start..])
+    });
 
-            if tx.available_space() < count {
-                #[cfg(feature = "debug")]
-                defmt::error!("TX buffer overflow: {} > {}", count, tx.available_space());
-                return Err(DeviceError::from(UsbError::BufferOverflow));
+    match received {
+        Ok(0) => {
+            isr_log!(isr, trace, "No USB data available");
+            Ok(0)
+        }
+        Ok(count) => {
+            isr_log!(isr, debug, "USB RX: bytes", count);
+            statistics::add_usb_rx(count);
+
+            let count = command::filter(&mut data[..start + count], start);
+            if count == 0 || bridge_mode::routes().host_data == Sink::Discard {
+                return Ok(0);
+            }
+
+            let mut translated = [0u8; 2 * STAGING_HEADROOM];
+            let output = if transform::is_active_to_uart() {
+                let len = transform::to_uart(&data[..count], &mut translated);
+                &translated[..len]
+            } else {
+                &data[..count]
+            };
+            let count = output.len();
+            if count == 0 {
+                return Ok(0);
             }
 
-            tx.push(&data[..count])
-                .map_err(|_| DeviceError::from(UsbError::BufferOverflow))?;
+            lock_stats::lock(LockSite::UsbRx, tx, |tx| {
+                if tx.available_space() < count {
+                    isr_log!(isr, error, "TX buffer overflow, bytes dropped", count);
+                    statistics::add_dropped(count);
+                    return Err(DeviceError::from(UsbError::BufferOverflow));
+                }
+
+                tx.push(output)
+                    .map_err(|_| DeviceError::from(UsbError::BufferOverflow))?;
+                statistics::note_tx_buffer_level(tx.len());
+                Ok(())
+            })?;
             Ok(count)
         }
-        Ok(None) => {
-            #[cfg(feature = "debug")]
-            defmt::trace!("No USB data available");
+        Err(e) => {
+            isr_log!(isr, error, "USB read failure", e.code());
+            statistics::add_usb_error();
+            Err(e.into())
+        }
+    }
+}
+
+/// Feeds incoming USB data to the request decoder of a bridge mode
+///
+/// # Arguments
+/// * `usb` - USB controller resource
+/// * `decoder` - Receiver of the packet, returning the requests it queued
+///
+/// # Returns
+/// Always `Ok(0)` on success: no bytes are left for the UART
+#[cfg(any(feature = "i2c-bridge", feature = "can"))]
+fn divert_usb_data<L: UsbLink>(
+    usb: &mut impl Mutex<T = L>,
+    decoder: fn(&[u8]) -> usize,
+) -> Result<usize, DeviceError> {
+    let mut data = [0u8; DATA_PACKET_SIZE];
+    match usb.lock(|usb| usb.read_into(&mut data)) {
+        Ok(0) => Ok(0),
+        Ok(count) => {
+            statistics::add_usb_rx(count);
+            decoder(&data[..count]);
             Ok(0)
         }
         Err(e) => {
-            #[cfg(feature = "debug")]
-            defmt::error!("USB read failure: {:?}", e);
+            statistics::add_usb_error();
             Err(e.into())
         }
     }
@@ -84,58 +280,62 @@ fn process_usb_data(
 /// Transmits data from receive buffer via USB
 ///
 /// # Arguments
-/// * `usb` - USB controller instance
-/// * `rx` - Receive ring buffer
+/// * `usb` - USB controller resource
+/// * `rx` - Consumer half of the receive ring buffer
 ///
 /// # Returns
 /// - `Ok(bytes_sent)` - Total bytes successfully transmitted
 /// - `Err(DeviceError)` - Transmission failure
 ///
 /// # Behavior
-/// - Handles partial writes by preserving unsent data
-/// - Manages buffer state during retries
-pub fn process_rx_buffer(
-    usb: &mut OtgFsController<'static>,
-    rx: &mut RingBuffer,
+/// - Sends the transform outbox (local echo, translated leftovers) first
+/// - Moves at most `usb.chunk_size()` bytes per call, half as many from the
+///   ring buffer while the UART-to-host transform may double them
+/// - Handles partial writes by preserving unsent data: raw bytes are only
+///   peeked and stay queued until written (`data_path::forward_rx`),
+///   translated ones go back to the outbox
+/// - Peeks, writes and consumes in separate critical sections
+pub fn process_rx_buffer<L: UsbLink>(
+    usb: &mut impl Mutex<T = L>,
+    rx: &mut impl Mutex<T = RxConsumer>,
 ) -> Result<usize, DeviceError> {
-    let mut tx_buffer = [0u8; DATA_PACKET_SIZE];
-    let mut total_sent = 0;
-
-    if rx.is_empty() {
-        #[cfg(feature = "debug")]
-        defmt::trace!("RX buffer empty - nothing to transmit");
-        return Ok(0);
-    }
-
-    let bytes_read = rx.pop(&mut tx_buffer);
-    #[cfg(feature = "debug")]
-    defmt::debug!("Preparing to send {} bytes", bytes_read);
+    lock_stats::span(LockSite::UsbTx, || {
+        let mut tx_buffer = [0u8; DATA_PACKET_SIZE];
 
-    match usb.write(&tx_buffer[..bytes_read]) {
-        Ok(written) => {
-            total_sent += written;
-
-            if written < bytes_read {
-                #[cfg(feature = "debug")]
-                defmt::warn!("Partial write: {}/{} bytes", written, bytes_read);
+        let chunk_size = usb.lock(|usb| usb.chunk_size());
+        let mut bytes_read = transform::take_outbox(&mut tx_buffer[..chunk_size]);
+        if bytes_read == 0 {
+            if !transform::is_active_to_host() || data_port_diverted() {
+                return data_path::forward_rx(usb, rx, &mut tx_buffer[..chunk_size]);
+            }
 
-                let remaining = &tx_buffer[written..bytes_read];
-                rx.push(remaining).map_err(|_| {
-                    #[cfg(feature = "debug")]
-                    defmt::error!("Failed to preserve {} unsent bytes", remaining.len());
-                    DeviceError::from(UsbError::BufferOverflow)
-                })?;
+            let mut raw = [0u8; DATA_PACKET_SIZE / 2];
+            // A chunk may be stripped away entirely
+            loop {
+                let count = lock_stats::lock(LockSite::UsbTx, rx, |rx| {
+                    rx.pop(&mut raw[..chunk_size.div_ceil(2)])
+                });
+                bytes_read = transform::to_host(&raw[..count], &mut tx_buffer);
+                if bytes_read > 0 || count == 0 {
+                    break;
+                }
             }
         }
-        Err(e) => {
+
+        if bytes_read == 0 {
             #[cfg(feature = "debug")]
-            defmt::error!("USB write failure: {:?}", e);
-            return Err(e.into());
+            defmt::trace!("RX buffer empty - nothing to transmit");
+            return Ok(0);
         }
-    }
-
-    #[cfg(feature = "debug")]
-    defmt::info!("Total transmitted: {} bytes", total_sent);
 
-    Ok(total_sent)
-}
\ No newline at end of file
+        let written = data_path::write_usb(usb, &tx_buffer[..bytes_read])?;
+        if written < bytes_read {
+            let dropped = transform::return_to_outbox(&tx_buffer[written..bytes_read]);
+            if dropped > 0 {
+                statistics::add_dropped(dropped);
+                return Err(DeviceError::from(UsbError::BufferOverflow));
+            }
+        }
+        Ok(written)
+    })
+}
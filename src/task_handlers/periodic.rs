@@ -0,0 +1,327 @@
+//! # Periodic Jobs
+//!
+//! Declares the jobs run by the shared periodic scheduler task and the work
+//! each one performs. Adding a periodic subsystem means adding a variant here,
+//! registering it in `register_jobs`, and handling it in the scheduler task.
+
+#[cfg(feature = "usb-msc")]
+use crate::config::MSC_REFRESH_CHECK_MS;
+use crate::config::{
+    ACTIVITY_LED_MS, MAX_PERIODIC_JOBS, MODEM_POLL_MS, SNAPSHOT_INTERVAL_MS,
+    STATS_REPORT_INTERVAL_MS, VBUS_POLL_MS,
+};
+use crate::errors::errors::DeviceError;
+#[cfg(feature = "adc")]
+use crate::peripherals::adc;
+use crate::peripherals::clock_scaling::{self, ClockLevel};
+use crate::peripherals::flash::FlashController;
+use crate::peripherals::modem_lines::ModemLines;
+use crate::peripherals::otg_fs::OtgFsController;
+use crate::peripherals::uart::{Usart3Controller, Usart6Controller};
+use crate::task_handlers::activity_leds::ActivityLeds;
+use crate::task_handlers::auto_baud;
+use crate::task_handlers::baud_negotiation;
+use crate::task_handlers::console::CrLf;
+use crate::task_handlers::error_notify::{self, format_frame, NOTIFY_INTERVAL_MS};
+use crate::task_handlers::safe_mode::{self, BootGuard, GuardVerdict};
+use crate::task_handlers::snapshot::{Snapshot, SnapshotLog};
+use crate::task_handlers::task_registry::Subsystem;
+use crate::utils::budget;
+use crate::utils::clock_health::ClockHealth;
+#[cfg(feature = "adc")]
+use crate::utils::health_monitor;
+#[cfg(feature = "usb-msc")]
+use crate::utils::log_volume;
+use crate::utils::scheduler::Scheduler;
+use crate::utils::statistics;
+use heapless::String;
+
+/// Safe-mode guard check interval (milliseconds)
+const SAFE_MODE_GUARD_MS: u32 = 100;
+
+/// Clock health step interval (milliseconds); measurements are less frequent
+const CLOCK_HEALTH_STEP_MS: u32 = 1_000;
+
+/// Execution budget check interval (milliseconds)
+const BUDGET_CHECK_MS: u32 = 1_000;
+
+/// Die temperature and supply check interval (milliseconds); each check
+/// takes the latest ADC sample
+#[cfg(feature = "adc")]
+const HEALTH_CHECK_MS: u32 = 1_000;
+
+/// Capacity of one statistics report line
+const STATS_REPORT_LEN: usize = 256;
+
+/// Jobs known to the periodic scheduler
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PeriodicJob {
+    /// Next error notification window, with the errors coalesced in the last
+    ErrorNotify,
+    /// Statistics snapshot into flash
+    StatsSnapshot,
+    /// Modem status inputs mirrored to the host
+    ModemStatus,
+    /// Boot error-storm detection and safe-mode retry handling
+    SafeModeGuard,
+    /// LSE start-up and HSE/LSE drift estimation
+    ClockHealth,
+    /// Chronic task budget overrun reporting
+    BudgetCheck,
+    /// Bridge statistics dump
+    StatsReport,
+    /// USB/UART traffic LEDs
+    ActivityLeds,
+    /// USB VBUS sensing and the core clock level that follows it
+    VbusSense,
+    /// Log volume rendering for the USB mass storage function
+    #[cfg(feature = "usb-msc")]
+    LogVolume,
+    /// Die temperature and supply voltage limits
+    #[cfg(feature = "adc")]
+    HealthMonitor,
+}
+
+/// Scheduler type used by the periodic task
+pub type PeriodicScheduler = Scheduler<PeriodicJob, MAX_PERIODIC_JOBS>;
+
+/// Registers all periodic jobs
+///
+/// # Arguments
+/// * `scheduler` - Scheduler owned by the periodic task
+/// * `now_ms` - Current monotonic time
+pub fn register_jobs(scheduler: &mut PeriodicScheduler, now_ms: u32) {
+    let jobs = [
+        (PeriodicJob::ErrorNotify, NOTIFY_INTERVAL_MS, None),
+        (
+            PeriodicJob::StatsSnapshot,
+            SNAPSHOT_INTERVAL_MS,
+            Some(Subsystem::STATS_SNAPSHOT),
+        ),
+        (PeriodicJob::ModemStatus, MODEM_POLL_MS, None),
+        (PeriodicJob::SafeModeGuard, SAFE_MODE_GUARD_MS, None),
+        (PeriodicJob::ClockHealth, CLOCK_HEALTH_STEP_MS, None),
+        (PeriodicJob::BudgetCheck, BUDGET_CHECK_MS, None),
+        (
+            PeriodicJob::StatsReport,
+            STATS_REPORT_INTERVAL_MS,
+            Some(Subsystem::STATS_REPORTER),
+        ),
+        (PeriodicJob::ActivityLeds, ACTIVITY_LED_MS, None),
+        (PeriodicJob::VbusSense, VBUS_POLL_MS, None),
+        #[cfg(feature = "usb-msc")]
+        (PeriodicJob::LogVolume, MSC_REFRESH_CHECK_MS, None),
+        #[cfg(feature = "adc")]
+        (PeriodicJob::HealthMonitor, HEALTH_CHECK_MS, None),
+    ];
+
+    for (job, period_ms, subsystem) in jobs {
+        if scheduler.register(job, period_ms, subsystem, now_ms).is_err() {
+            #[cfg(feature = "debug")]
+            defmt::error!("Periodic scheduler full - job not registered");
+        }
+    }
+}
+
+/// Opens the next error notification window and sends what was coalesced
+///
+/// Write failures are only logged to avoid an error feedback loop.
+pub fn run_error_notify(usb: &mut OtgFsController<'static>) {
+    error_notify::next_window();
+    if let Err(_code) = send_error_notify(usb) {
+        #[cfg(feature = "debug")]
+        defmt::warn!("Error notification for code {} not delivered", _code);
+    }
+}
+
+/// Sends the error notification due in the current window, if any
+///
+/// The frame only goes to the debug console; while no terminal has it open,
+/// the notification stays pending. Also called from the OTG FS handler, so
+/// nothing is logged here.
+///
+/// # Returns
+/// - `Err(code)` if the frame for `code` could not be written
+pub fn send_error_notify(usb: &mut OtgFsController<'static>) -> Result<(), u16> {
+    if !usb.console_connected() {
+        return Ok(());
+    }
+    let Some((code, count)) = error_notify::take_due() else {
+        return Ok(());
+    };
+
+    let frame = format_frame(code, count);
+    usb.console_write(frame.as_bytes()).map_err(|_| code)
+}
+
+/// Dumps the bridge statistics
+///
+/// The report goes to the debug console while a terminal has it open, and to
+/// the debug channel otherwise (with `debug`).
+pub fn run_stats_report(usb: &mut OtgFsController<'static>) {
+    let mut line: String<STATS_REPORT_LEN> = String::new();
+
+    if usb.console_connected() {
+        if statistics::write_report(&mut CrLf(&mut line)).is_err()
+            || usb.console_write(line.as_bytes()).is_err()
+        {
+            #[cfg(feature = "debug")]
+            defmt::warn!("Statistics report not delivered");
+        }
+        return;
+    }
+
+    #[cfg(feature = "debug")]
+    if statistics::write_report(&mut line).is_ok() {
+        defmt::info!("{=str}", line.trim_end());
+    }
+}
+
+/// Shows USB and UART traffic on the activity LEDs
+pub fn run_activity_leds(leds: &mut ActivityLeds) {
+    leds.update();
+}
+
+/// Renders the log volume files once a host attached or a refresh was
+/// requested
+#[cfg(feature = "usb-msc")]
+pub fn run_log_volume() {
+    log_volume::refresh_if_requested();
+}
+
+/// Appends a statistics snapshot to the flash log
+///
+/// # Arguments
+/// * `log` - Snapshot log write position
+/// * `flash` - Flash controller
+/// * `uptime_s` - Seconds since boot
+pub fn run_stats_snapshot(
+    log: &mut SnapshotLog,
+    flash: &mut FlashController,
+    uptime_s: u32,
+) -> Result<(), DeviceError> {
+    let mut snapshot = Snapshot::capture(uptime_s);
+    log.append(flash, &mut snapshot).map_err(DeviceError::from)
+}
+
+/// Samples the modem status inputs and reports changes to the host
+///
+/// # Arguments
+/// * `lines` - Modem status inputs
+/// * `usb` - USB controller
+pub fn run_modem_status(lines: &ModemLines, usb: &mut OtgFsController<'static>) {
+    if !usb.is_configured() {
+        return;
+    }
+
+    if usb.set_serial_state(lines.read()).is_err() {
+        #[cfg(feature = "debug")]
+        defmt::warn!("SERIAL_STATE notification failed");
+    }
+}
+
+/// Enters safe mode on an error storm and handles retry requests
+///
+/// # Arguments
+/// * `guard` - Error-storm detector, re-armed on retry
+/// * `usart` - USART6 controller, suspended while in safe mode
+/// * `usart_3` - USART3 controller, suspended while in safe mode
+/// * `now_ms` - Current monotonic time
+///
+/// # Errors
+/// Returns `DeviceError` if a USART cannot be resumed
+pub fn run_safe_mode_guard(
+    guard: &mut BootGuard,
+    usart: &mut Usart6Controller,
+    usart_3: &mut Usart3Controller,
+    now_ms: u32,
+) -> Result<(), DeviceError> {
+    if safe_mode::is_active() {
+        if safe_mode::take_retry_request() {
+            usart.resume()?;
+            usart_3.resume()?;
+            safe_mode::leave();
+            *guard = BootGuard::new(now_ms);
+        }
+        return Ok(());
+    }
+
+    if guard.check(now_ms) == GuardVerdict::Storm {
+        usart.suspend();
+        usart_3.suspend();
+        safe_mode::enter();
+    }
+    Ok(())
+}
+
+/// Advances clock health monitoring
+///
+/// # Arguments
+/// * `health` - Drift estimator
+/// * `now_ms` - Current monotonic time
+/// * `uptime_ms` - Time since boot
+///
+/// # Errors
+/// Returns `DeviceError::ClockDrift` when the drift first exceeds the limit
+pub fn run_clock_health(
+    health: &mut ClockHealth,
+    now_ms: u32,
+    uptime_ms: u64,
+) -> Result<(), DeviceError> {
+    health.poll(now_ms, uptime_ms)
+}
+
+/// Moves the core clock to `target` and the UART baud divisors with it
+///
+/// `ClockLevel::Low` is not entered while a baud rate detection or
+/// negotiation owns the UART, or when a UART's baud rate cannot be
+/// generated from the HSE; the clock then stays up and the next poll tries
+/// again.
+///
+/// # Arguments
+/// * `target` - Level from `power::vbus_clock_target`
+/// * `usart` - Bridge UART
+/// * `usart_3` - Secondary UART
+///
+/// # Errors
+/// Returns `DeviceError::ClockSwitch` if the PLL did not lock again
+pub fn run_clock_switch(
+    target: ClockLevel,
+    usart: &mut Usart6Controller,
+    usart_3: &mut Usart3Controller,
+) -> Result<(), DeviceError> {
+    if target == ClockLevel::Low
+        && (auto_baud::is_active()
+            || baud_negotiation::is_active()
+            || !usart.fits_clock_level(target)
+            || !usart_3.fits_clock_level(target))
+    {
+        return Ok(());
+    }
+
+    clock_scaling::set_level(target)?;
+    usart.set_clock_level(target)?;
+    usart_3.set_clock_level(target)?;
+    Ok(())
+}
+
+/// Reports tasks that keep overrunning their execution budget
+///
+/// # Errors
+/// Returns `DeviceError::BudgetOverrun` once per overrun streak
+pub fn run_budget_check() -> Result<(), DeviceError> {
+    budget::check()
+}
+
+/// Checks the latest ADC sample against the health limits
+///
+/// # Errors
+/// Returns `DeviceError::SupplyLimit` or `DeviceError::TemperatureLimit`
+/// once per excursion
+#[cfg(feature = "adc")]
+pub fn run_health_monitor() -> Result<(), DeviceError> {
+    match adc::last_sample() {
+        Some(sample) => health_monitor::check(&sample),
+        None => Ok(()),
+    }
+}
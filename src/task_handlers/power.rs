@@ -0,0 +1,195 @@
+//! # Power Profiles
+//!
+//! Chooses how deep `idle` lets the MCU sleep:
+//!
+//! | Profile       | Idle        | USB suspended | No traffic for `POWER_DOWNCLOCK_IDLE_MS` | No VBUS    |
+//! |---------------|-------------|---------------|------------------------------------------|------------|
+//! | `performance` | Run (spins) | Run           | full clock                               | full clock |
+//! | `balanced`    | Sleep (WFI) | Stop          | full clock                               | HSE clock  |
+//! | `low-power`   | Sleep (WFI) | Stop          | half HCLK (`clock_scaling`)              | HSE clock  |
+//!
+//! - Run never halts the core, for the shortest interrupt latency
+//! - Sleep halts the core until the next interrupt; peripherals keep running
+//! - Stop gates all clocks while the host keeps the bus suspended
+//!   (`usb_suspend::sleep`)
+//! - Without VBUS no host can attach, so the periodic `VbusSense` job drops
+//!   SYSCLK to the HSE (`ClockLevel::Low`) and locks the PLL again as soon
+//!   as VBUS returns
+//!
+//! Traffic is any byte counted by `statistics` on either link; the full
+//! clock returns with the first idle pass after it. `balanced` is the
+//! default. The profile is set with the console `power` command or
+//! `AT+POWER=`, and kept by `save`.
+
+use crate::config::POWER_DOWNCLOCK_IDLE_MS;
+use crate::peripherals::clock_scaling::{self, ClockLevel};
+use crate::peripherals::vbus;
+use crate::task_handlers::usb_suspend;
+use crate::utils::statistics;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use rtic_monotonics::systick::prelude::*;
+
+/// Profile in effect, as `PowerProfile as u8`
+static PROFILE: AtomicU8 = AtomicU8::new(PowerProfile::Balanced as u8);
+
+/// `statistics::traffic_bytes` at the previous idle pass
+static LAST_TRAFFIC: AtomicU32 = AtomicU32::new(0);
+
+/// Monotonic time of the last traffic seen by `idle` (milliseconds)
+static LAST_TRAFFIC_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Power profiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerProfile {
+    /// No sleep at all
+    Performance = 0,
+    /// WFI, Stop while suspended
+    Balanced = 1,
+    /// As `Balanced`, and half HCLK after a quiet period
+    LowPower = 2,
+}
+
+impl PowerProfile {
+    /// All profiles, by value
+    pub const ALL: [PowerProfile; 3] = [Self::Performance, Self::Balanced, Self::LowPower];
+
+    /// Name used by the console and, upper-cased, by `AT+POWER`
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Performance => "performance",
+            Self::Balanced => "balanced",
+            Self::LowPower => "low-power",
+        }
+    }
+
+    /// Looks up a profile by name, in any case
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Looks up a profile by value, for the settings store
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        Self::ALL.get(usize::from(bits)).copied()
+    }
+
+    /// `idle` halts the core in WFI instead of spinning
+    pub const fn uses_wfi(self) -> bool {
+        !matches!(self, Self::Performance)
+    }
+
+    /// Stop mode, with all clocks gated, while the USB bus is suspended
+    pub const fn gates_clocks_when_suspended(self) -> bool {
+        !matches!(self, Self::Performance)
+    }
+
+    /// HCLK halves after `POWER_DOWNCLOCK_IDLE_MS` without traffic
+    pub const fn downclocks_when_idle(self) -> bool {
+        matches!(self, Self::LowPower)
+    }
+
+    /// SYSCLK runs from the HSE while VBUS is absent
+    pub const fn drops_clock_without_vbus(self) -> bool {
+        !matches!(self, Self::Performance)
+    }
+}
+
+/// Profile in effect
+pub fn profile() -> PowerProfile {
+    PowerProfile::from_bits(PROFILE.load(Ordering::Relaxed)).unwrap_or(PowerProfile::Balanced)
+}
+
+/// Switches the profile
+///
+/// A profile without downclocking restores the full clock at once; the HSE
+/// clock is left to the next `VbusSense` poll, which re-locks the PLL.
+pub fn set_profile(profile: PowerProfile) {
+    PROFILE.store(profile as u8, Ordering::Relaxed);
+    if !profile.downclocks_when_idle() && clock_scaling::level() == ClockLevel::Half {
+        // Leaving `Half` never fails
+        clock_scaling::set_level(ClockLevel::Full).ok();
+    }
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Power profile: {=str}", profile.name());
+}
+
+/// One pass of the idle loop: sleeps as deep as the profile allows
+///
+/// Returns after the next interrupt, or at once under `performance`.
+pub fn idle() {
+    let profile = profile();
+    let quiet_ms = note_traffic(crate::Mono::now().ticks());
+
+    let target = if profile.downclocks_when_idle() && quiet_ms >= POWER_DOWNCLOCK_IDLE_MS {
+        ClockLevel::Half
+    } else {
+        ClockLevel::Full
+    };
+    // `Low` belongs to `vbus_clock_target`
+    let level = clock_scaling::level();
+    if level != ClockLevel::Low && level != target {
+        // Between `Full` and `Half` a switch never fails
+        clock_scaling::set_level(target).ok();
+    }
+
+    if usb_suspend::is_suspended() && profile.gates_clocks_when_suspended() {
+        usb_suspend::sleep();
+    } else if profile.uses_wfi() {
+        cortex_m::asm::wfi();
+    } else {
+        cortex_m::asm::nop();
+    }
+}
+
+/// Clock level the VBUS state calls for, if the clock has to move
+///
+/// `Low` once VBUS is gone under a profile that drops the clock, `Full` as
+/// soon as VBUS returns (or the profile changes). `Half` counts as the full
+/// clock here; `idle` owns it.
+pub fn vbus_clock_target() -> Option<ClockLevel> {
+    let want_low = !vbus::is_present() && profile().drops_clock_without_vbus();
+    match (clock_scaling::level(), want_low) {
+        (ClockLevel::Low, false) => Some(ClockLevel::Full),
+        (ClockLevel::Full | ClockLevel::Half, true) => Some(ClockLevel::Low),
+        _ => None,
+    }
+}
+
+/// Time since the last traffic on either link (milliseconds)
+fn note_traffic(now_ms: u32) -> u32 {
+    let bytes = statistics::traffic_bytes();
+    if LAST_TRAFFIC.swap(bytes, Ordering::Relaxed) != bytes {
+        LAST_TRAFFIC_MS.store(now_ms, Ordering::Relaxed);
+    }
+    now_ms.wrapping_sub(LAST_TRAFFIC_MS.load(Ordering::Relaxed))
+}
+
+/// Writes the profile, its idle behaviour and the core clock level
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let profile = profile();
+    writeln!(
+        out,
+        "Power profile: {} (idle {}, {} while suspended, downclock {}, HSE without VBUS {})",
+        profile.name(),
+        if profile.uses_wfi() { "WFI" } else { "spin" },
+        if profile.gates_clocks_when_suspended() {
+            "Stop"
+        } else {
+            "run"
+        },
+        if profile.downclocks_when_idle() {
+            "on"
+        } else {
+            "off"
+        },
+        if profile.drops_clock_without_vbus() {
+            "on"
+        } else {
+            "off"
+        }
+    )?;
+    clock_scaling::write_report(out)
+}
@@ -0,0 +1,112 @@
+//! # Safe Mode
+//!
+//! Protects the device from staying unreachable when a fault floods the error
+//! queue right after boot (e.g. miswired UART lines causing DMA error storms):
+//! - `BootGuard` counts errors during the first `BOOT_STORM_WINDOW_MS`
+//! - More than `BOOT_STORM_ERROR_LIMIT` errors suspend both UARTs and enter safe mode
+//! - USB stays active so the host can still reach the device
+//! - The blue LED blinks rapidly (`SAFE_MODE_BLINK_MS`) while in safe mode
+//! - `request_retry` resumes the UART and re-arms the guard for a new window
+
+use crate::config::{BOOT_STORM_ERROR_LIMIT, BOOT_STORM_WINDOW_MS};
+use crate::task_handlers::error_handlers::total_error_count;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Set while safe mode is active
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Set by the control channel to leave safe mode
+static RETRY_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Result of a guard check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuardVerdict {
+    /// Still inside the window, error count within limits
+    Watching,
+    /// Window closed without a storm
+    Passed,
+    /// Error storm detected; safe mode should be entered
+    Storm,
+}
+
+/// Error-storm detector for the window after boot (or after a retry)
+pub struct BootGuard {
+    window_start_ms: u32,
+    errors_at_start: u32,
+    armed: bool,
+}
+
+impl BootGuard {
+    /// Arms the guard
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current monotonic time, start of the window
+    pub fn new(now_ms: u32) -> Self {
+        Self {
+            window_start_ms: now_ms,
+            errors_at_start: total_error_count(),
+            armed: true,
+        }
+    }
+
+    /// Checks the error count against the limit
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current monotonic time
+    pub fn check(&mut self, now_ms: u32) -> GuardVerdict {
+        if !self.armed {
+            return GuardVerdict::Passed;
+        }
+
+        let errors = total_error_count().wrapping_sub(self.errors_at_start);
+        if errors > BOOT_STORM_ERROR_LIMIT {
+            self.armed = false;
+            return GuardVerdict::Storm;
+        }
+
+        if now_ms.wrapping_sub(self.window_start_ms) >= BOOT_STORM_WINDOW_MS {
+            self.armed = false;
+            return GuardVerdict::Passed;
+        }
+
+        GuardVerdict::Watching
+    }
+}
+
+/// Marks safe mode as active
+pub fn enter() {
+    ACTIVE.store(true, Ordering::Release);
+
+    #[cfg(feature = "debug")]
+    defmt::error!("Error storm after boot - entering safe mode, UART disabled");
+}
+
+/// Marks safe mode as left
+pub fn leave() {
+    ACTIVE.store(false, Ordering::Release);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Leaving safe mode - retrying normal operation");
+}
+
+/// Whether safe mode is active
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::Acquire)
+}
+
+/// Requests a retry of normal mode (control channel command)
+///
+/// # Returns
+/// `false` if the device is not in safe mode
+pub fn request_retry() -> bool {
+    if !is_active() {
+        return false;
+    }
+    RETRY_REQUESTED.store(true, Ordering::Release);
+    true
+}
+
+/// Takes a pending retry request
+pub fn take_retry_request() -> bool {
+    RETRY_REQUESTED.swap(false, Ordering::AcqRel)
+}
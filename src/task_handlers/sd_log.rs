@@ -0,0 +1,258 @@
+//! # SD Card Log
+//!
+//! Timestamped traffic and error records appended to `BRIDGE.LOG` on the
+//! microSD card (`sd-log` feature):
+//! - `record_error` queues a line for every reported error; it is called by
+//!   `add_error`, so from any priority
+//! - `record_traffic` queues a line with the bridge counters; the writer task
+//!   calls it every `SD_LOG_TRAFFIC_MS`
+//! - Lines are buffered in a byte queue of `SD_LOG_BUFFER_LEN`; a line that
+//!   does not fit is dropped whole and counted
+//! - `SdLogWriter` moves the queue to the card from the priority-1 writer
+//!   task, so card latency never reaches the bridge path
+//!
+//! A card is mounted when it is inserted. After a failed mount or write the
+//! writer waits for the card to be taken out and inserted again.
+//!
+//! ## Line Format
+//! `<time> <kind> <text>` CR LF, with the RTC time as `YYYY-MM-DDThh:mm:ss`,
+//! or `+<ms>` since boot while the calendar is not set, and the kinds `ERR`
+//! (severity tag, code, mnemonic, description) and `TRAFFIC`.
+
+use crate::config::{SD_LOG_BUFFER_LEN, SD_LOG_LINE_LEN};
+use crate::data_structures::error_queue::Severity;
+use crate::errors::errors::{DeviceError, SdCardError};
+use crate::peripherals::block_device::BLOCK_SIZE;
+use crate::peripherals::rtc;
+use crate::peripherals::sdcard::{LogFile, SdCard};
+use crate::utils::morse::Mnemonic;
+use crate::utils::statistics;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::{Deque, String};
+
+/// Pending line bytes
+static BUFFER: Mutex<RefCell<Deque<u8, SD_LOG_BUFFER_LEN>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+/// Set while a log file is open
+static MOUNTED: AtomicBool = AtomicBool::new(false);
+
+static LINES_QUEUED: AtomicU32 = AtomicU32::new(0);
+static LINES_DROPPED: AtomicU32 = AtomicU32::new(0);
+static BYTES_WRITTEN: AtomicU32 = AtomicU32::new(0);
+static WRITE_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// Size of the open log file, bytes
+static FILE_SIZE: AtomicU32 = AtomicU32::new(0);
+
+/// Formats and queues one line
+///
+/// Text beyond `SD_LOG_LINE_LEN` is cut off.
+///
+/// # Arguments
+/// * `kind` - Record kind, e.g. `ERR`
+/// * `args` - Line text, without a line end
+fn queue(kind: &str, args: fmt::Arguments) {
+    let mut line: String<SD_LOG_LINE_LEN> = String::new();
+    // A line that does not fit is truncated, the line end is always added
+    let _ = match rtc::now() {
+        Some(now) => write!(line, "{} {} ", now, kind),
+        None => write!(line, "+{} {} ", crate::Mono::now().ticks(), kind),
+    };
+    let _ = line.write_fmt(args);
+    if line.len() > SD_LOG_LINE_LEN - 2 {
+        let mut end = SD_LOG_LINE_LEN - 2;
+        while !line.is_char_boundary(end) {
+            end -= 1;
+        }
+        line.truncate(end);
+    }
+    let _ = line.push_str("\r\n");
+
+    let queued = interrupt::free(|cs| {
+        let mut buffer = BUFFER.borrow(cs).borrow_mut();
+        if buffer.capacity() - buffer.len() < line.len() {
+            return false;
+        }
+        for &byte in line.as_bytes() {
+            // Cannot fail, the free space was checked
+            let _ = buffer.push_back(byte);
+        }
+        true
+    });
+
+    if queued {
+        LINES_QUEUED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        LINES_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Queues an error record line
+///
+/// # Arguments
+/// * `code` - Reported error code
+/// * `severity` - Severity it was reported with
+pub fn record_error(code: u16, severity: Severity) {
+    let (mnemonic, description) = match DeviceError::from_code(code) {
+        Some(error) => (error.mnemonic(), error.description()),
+        None => ("", ""),
+    };
+    queue(
+        "ERR",
+        format_args!("{} {} {} {}", severity.tag(), code, mnemonic, description),
+    );
+}
+
+/// Queues a line with the bridge traffic counters
+pub fn record_traffic() {
+    let stats = statistics::get_stats();
+    queue(
+        "TRAFFIC",
+        format_args!(
+            "uart rx {} tx {} usb rx {} tx {} dropped {} dma restarts {} \
+             ore {} fe {} ne {} pe {}",
+            stats.uart_rx_bytes,
+            stats.uart_tx_bytes,
+            stats.usb_rx_bytes,
+            stats.usb_tx_bytes,
+            stats.dropped_bytes,
+            stats.dma_restarts,
+            stats.overrun_errors,
+            stats.framing_errors,
+            stats.noise_errors,
+            stats.parity_errors
+        ),
+    );
+}
+
+/// Moves pending line bytes into `buf`
+///
+/// # Returns
+/// Number of bytes copied; 0 if nothing is pending
+fn take(buf: &mut [u8]) -> usize {
+    interrupt::free(|cs| {
+        let mut buffer = BUFFER.borrow(cs).borrow_mut();
+        let mut count = 0;
+        for slot in buf.iter_mut() {
+            match buffer.pop_front() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    })
+}
+
+/// Log file state of the writer task
+pub struct SdLogWriter {
+    file: Option<LogFile>,
+    /// The card in the slot was already tried
+    tried: bool,
+}
+
+impl Default for SdLogWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SdLogWriter {
+    /// Creates a writer; the card is mounted by the first `service` call
+    pub const fn new() -> Self {
+        Self {
+            file: None,
+            tried: false,
+        }
+    }
+
+    /// Mounts a newly inserted card and appends the queued lines to the log
+    ///
+    /// # Arguments
+    /// * `card` - SD card slot
+    ///
+    /// # Errors
+    /// The mount or write failure; the file is closed and the card is left
+    /// alone until it is inserted again
+    pub fn service(&mut self, card: &mut SdCard) -> Result<(), SdCardError> {
+        if !card.is_inserted() {
+            self.close();
+            self.tried = false;
+            return Ok(());
+        }
+
+        if self.file.is_none() {
+            if self.tried {
+                return Ok(());
+            }
+            self.tried = true;
+            let file = card.mount(rtc::now())?;
+            FILE_SIZE.store(file.size(), Ordering::Relaxed);
+            MOUNTED.store(true, Ordering::Relaxed);
+            self.file = Some(file);
+        }
+
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        let result = append(file, card);
+        FILE_SIZE.store(file.size(), Ordering::Relaxed);
+        if result.is_err() {
+            WRITE_ERRORS.fetch_add(1, Ordering::Relaxed);
+            self.close();
+        }
+        result
+    }
+
+    fn close(&mut self) {
+        self.file = None;
+        MOUNTED.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Writes all queued bytes to the file and flushes it
+fn append(file: &mut LogFile, card: &mut SdCard) -> Result<(), SdCardError> {
+    let mut chunk = [0u8; BLOCK_SIZE];
+    let mut written = 0;
+    loop {
+        let count = take(&mut chunk);
+        if count == 0 {
+            break;
+        }
+        file.write(card, &chunk[..count])?;
+        BYTES_WRITTEN.fetch_add(count as u32, Ordering::Relaxed);
+        written += count;
+    }
+
+    if written > 0 {
+        file.flush(card, rtc::now())?;
+    }
+    Ok(())
+}
+
+/// Writes the card state and the line counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let pending = interrupt::free(|cs| BUFFER.borrow(cs).borrow().len());
+    if MOUNTED.load(Ordering::Relaxed) {
+        writeln!(
+            out,
+            "SD log: BRIDGE.LOG open, {} bytes",
+            FILE_SIZE.load(Ordering::Relaxed)
+        )?;
+    } else {
+        writeln!(out, "SD log: no card mounted")?;
+    }
+    writeln!(
+        out,
+        "  {} lines queued, {} dropped, {} bytes pending, {} written, {} write errors",
+        LINES_QUEUED.load(Ordering::Relaxed),
+        LINES_DROPPED.load(Ordering::Relaxed),
+        pending,
+        BYTES_WRITTEN.load(Ordering::Relaxed),
+        WRITE_ERRORS.load(Ordering::Relaxed)
+    )
+}
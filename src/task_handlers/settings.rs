@@ -0,0 +1,726 @@
+//! # Persistent Settings
+//!
+//! EEPROM emulation in two internal flash sectors for the runtime settings
+//! that should survive a reset:
+//! - UART baud rates, the routed UART and the packet framing mode
+//! - Error code outputs (red LED, buzzer or both)
+//! - USB serial number, taken into use at the next enumeration; the unique
+//!   device ID in hex until another one is saved
+//! - USB vendor/product IDs and manufacturer/product strings, also taken into
+//!   use at the next enumeration, so units can be rebranded without a rebuild
+//! - Die temperature and supply limits of the health monitor (`adc` feature)
+//! - Line ending, strip and local echo options of the transform stage, and
+//!   the XON/XOFF flow control mode
+//! - Power profile
+//! - Polarity of the DTR/RTS modem outputs
+//!
+//! Each setting is a 32-byte key-value record with a CRC-32. Saving appends
+//! only the records whose value changed, so an unchanged `save` costs no
+//! flash wear. When the active sector is full, the live values are copied to
+//! the other sector, which becomes active once its header record is written;
+//! an interrupted copy leaves the old sector in charge.
+//!
+//! Settings are loaded by `init_peripherals` and saved with `AT+SAVE` or the
+//! console `save` command.
+
+use crate::bridge::UartPort;
+use crate::config::{
+    DEFAULT_MODEM_ACTIVE_HIGH, DEFAULT_SIGNAL_OUTPUTS, SERIAL_NUMBER_LEN, SETTINGS_SECTORS,
+    SETTINGS_SECTOR_OFFSETS, SETTINGS_SECTOR_SIZE, USART3_BAUD_RATE, USART6_BAUD_RATE,
+    USB_MANUFACTURER, USB_PID, USB_PRODUCT, USB_STRING_LEN, USB_VID,
+};
+use crate::errors::errors::FlashError;
+use crate::peripherals::crc;
+use crate::peripherals::flash::FlashController;
+use crate::peripherals::modem_lines::{self, ControlLines};
+use crate::protocol::framer;
+use crate::protocol::transform::{self, TransformConfig};
+use crate::protocol::xon_xoff::{self, FlowMode};
+use crate::task_handlers::power::{self, PowerProfile};
+use crate::task_handlers::signal_handler::{self, SignalOutputs};
+use crate::task_handlers::uart_route;
+use crate::utils::device_id;
+#[cfg(feature = "adc")]
+use crate::utils::health_monitor::{self, HealthLimits};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::String;
+
+/// Encoded record size in bytes
+const RECORD_LEN: usize = 32;
+
+/// Value bytes in one record: key, length and CRC take the rest
+const VALUE_LEN: usize = RECORD_LEN - 6;
+
+/// Records that fit into one sector, header included
+const SLOTS_PER_SECTOR: usize = SETTINGS_SECTOR_SIZE / RECORD_LEN;
+
+/// Header marker ("KV")
+const SETTINGS_MAGIC: u16 = 0x4B56;
+
+/// Record layout version
+const SETTINGS_VERSION: u8 = 1;
+
+/// USB serial number text
+pub type SerialNumber = String<SERIAL_NUMBER_LEN>;
+
+/// USB manufacturer or product string
+pub type UsbString = String<USB_STRING_LEN>;
+
+/// Serial number reported at the next enumeration
+static SERIAL_NUMBER: Mutex<RefCell<Option<SerialNumber>>> = Mutex::new(RefCell::new(None));
+
+/// Descriptors reported at the next enumeration
+static USB_DESCRIPTORS: Mutex<RefCell<Option<UsbDescriptors>>> = Mutex::new(RefCell::new(None));
+
+/// USB IDs and descriptor strings the device enumerates with
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsbDescriptors {
+    /// Vendor ID
+    pub vid: u16,
+    /// Product ID
+    pub pid: u16,
+    /// Manufacturer string
+    pub manufacturer: UsbString,
+    /// Product string
+    pub product: UsbString,
+}
+
+impl Default for UsbDescriptors {
+    fn default() -> Self {
+        Self {
+            vid: USB_VID,
+            pid: USB_PID,
+            manufacturer: UsbString::try_from(USB_MANUFACTURER).unwrap_or_default(),
+            product: UsbString::try_from(USB_PRODUCT).unwrap_or_default(),
+        }
+    }
+}
+
+/// Record keys; `Header` marks an active sector
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Header = 0,
+    Usart6Baud = 1,
+    Usart3Baud = 2,
+    Uart = 3,
+    Framing = 4,
+    SignalOutputs = 5,
+    SerialNumber = 6,
+    #[cfg(feature = "adc")]
+    HealthLimits = 7,
+    UsbIds = 8,
+    UsbManufacturer = 9,
+    UsbProduct = 10,
+    Transform = 11,
+    ModemActiveHigh = 12,
+    SoftwareFlow = 13,
+    PowerProfile = 14,
+}
+
+/// Setting keys in record order
+const KEYS: &[Key] = &[
+    Key::Usart6Baud,
+    Key::Usart3Baud,
+    Key::Uart,
+    Key::Framing,
+    Key::SignalOutputs,
+    Key::SerialNumber,
+    #[cfg(feature = "adc")]
+    Key::HealthLimits,
+    Key::UsbIds,
+    Key::UsbManufacturer,
+    Key::UsbProduct,
+    Key::Transform,
+    Key::ModemActiveHigh,
+    Key::SoftwareFlow,
+    Key::PowerProfile,
+];
+
+/// One key-value record
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Record {
+    key: u8,
+    len: u8,
+    value: [u8; VALUE_LEN],
+}
+
+/// State of a single flash slot
+enum SlotState {
+    Erased,
+    Valid(Record),
+    Corrupt,
+}
+
+impl Record {
+    /// Creates a record; `value` is cut to `VALUE_LEN` bytes
+    fn new(key: Key, value: &[u8]) -> Self {
+        let len = value.len().min(VALUE_LEN);
+        let mut record = Self {
+            key: key as u8,
+            len: len as u8,
+            value: [0; VALUE_LEN],
+        };
+        record.value[..len].copy_from_slice(&value[..len]);
+        record
+    }
+
+    /// Value bytes
+    fn value(&self) -> &[u8] {
+        &self.value[..self.len as usize]
+    }
+
+    /// Value as a little-endian `u32`, if it has that length
+    fn value_u32(&self) -> Option<u32> {
+        self.value().try_into().ok().map(u32::from_le_bytes)
+    }
+
+    /// Serializes the record into its flash representation
+    fn encode(&self) -> [u8; RECORD_LEN] {
+        let mut bytes = [0u8; RECORD_LEN];
+        bytes[0] = self.key;
+        bytes[1] = self.len;
+        bytes[2..2 + VALUE_LEN].copy_from_slice(&self.value);
+
+        let checksum = crc::checksum(&bytes[..RECORD_LEN - 4]);
+        bytes[RECORD_LEN - 4..].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Classifies and decodes a flash slot
+    fn decode(bytes: &[u8]) -> SlotState {
+        if bytes.iter().all(|&b| b == 0xFF) {
+            return SlotState::Erased;
+        }
+        if bytes.len() != RECORD_LEN {
+            return SlotState::Corrupt;
+        }
+
+        let stored = u32::from_le_bytes([
+            bytes[RECORD_LEN - 4],
+            bytes[RECORD_LEN - 3],
+            bytes[RECORD_LEN - 2],
+            bytes[RECORD_LEN - 1],
+        ]);
+        if bytes[1] as usize > VALUE_LEN || stored != crc::checksum(&bytes[..RECORD_LEN - 4]) {
+            return SlotState::Corrupt;
+        }
+
+        let mut value = [0u8; VALUE_LEN];
+        value.copy_from_slice(&bytes[2..2 + VALUE_LEN]);
+        SlotState::Valid(Record {
+            key: bytes[0],
+            len: bytes[1],
+            value,
+        })
+    }
+
+    /// Builds the header record of a sector
+    fn header(generation: u32) -> Self {
+        let mut value = [0u8; 7];
+        value[0..2].copy_from_slice(&SETTINGS_MAGIC.to_le_bytes());
+        value[2] = SETTINGS_VERSION;
+        value[3..7].copy_from_slice(&generation.to_le_bytes());
+        Self::new(Key::Header, &value)
+    }
+
+    /// Generation number, if this is a header of the current layout
+    fn generation(&self) -> Option<u32> {
+        let value = self.value();
+        if self.key != Key::Header as u8
+            || value.len() != 7
+            || u16::from_le_bytes([value[0], value[1]]) != SETTINGS_MAGIC
+            || value[2] != SETTINGS_VERSION
+        {
+            return None;
+        }
+        Some(u32::from_le_bytes([value[3], value[4], value[5], value[6]]))
+    }
+}
+
+/// Contents of one settings sector
+struct SectorScan {
+    /// `None` if the sector has no valid header
+    generation: Option<u32>,
+    /// First slot never written
+    next_slot: usize,
+    /// Latest record per key, in `KEYS` order
+    latest: [Option<Record>; KEYS.len()],
+}
+
+impl SectorScan {
+    /// Reads a sector up to its first erased slot
+    ///
+    /// # Arguments
+    /// * `flash` - Flash controller holding the store
+    /// * `index` - Index into `SETTINGS_SECTORS`
+    fn read(flash: &FlashController, index: usize) -> Self {
+        let base = SETTINGS_SECTOR_OFFSETS[index];
+        let mut scan = Self {
+            generation: None,
+            next_slot: 0,
+            latest: [None; KEYS.len()],
+        };
+
+        for slot in 0..SLOTS_PER_SECTOR {
+            let Ok(bytes) = flash.read(base + slot * RECORD_LEN, RECORD_LEN) else {
+                break;
+            };
+
+            match Record::decode(bytes) {
+                SlotState::Erased => break,
+                SlotState::Valid(record) if slot == 0 => scan.generation = record.generation(),
+                SlotState::Valid(record) => {
+                    if let Some(i) = KEYS.iter().position(|&key| key as u8 == record.key) {
+                        scan.latest[i] = Some(record);
+                    }
+                }
+                // Interrupted write: skip the slot but never reuse it
+                SlotState::Corrupt => {}
+            }
+            scan.next_slot = slot + 1;
+        }
+        scan
+    }
+}
+
+/// Finds the active sector: the one with the newest header
+///
+/// # Returns
+/// Index into `SETTINGS_SECTORS` and its contents, `None` for a blank store
+fn active_sector(flash: &FlashController) -> Option<(usize, SectorScan)> {
+    (0..SETTINGS_SECTORS.len())
+        .map(|index| (index, SectorScan::read(flash, index)))
+        .filter(|(_, scan)| scan.generation.is_some())
+        .max_by_key(|(_, scan)| scan.generation)
+}
+
+/// Runtime settings kept in flash
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// USART6 baud rate
+    pub usart6_baud: u32,
+    /// USART3 baud rate
+    pub usart3_baud: u32,
+    /// UART bridged to the data port
+    pub uart: UartPort,
+    /// COBS packet framing on the UART
+    pub framing: bool,
+    /// Outputs that play error codes
+    pub signal_outputs: SignalOutputs,
+    /// USB serial number
+    pub serial_number: SerialNumber,
+    /// Health monitor limits
+    #[cfg(feature = "adc")]
+    pub health_limits: HealthLimits,
+    /// USB IDs and descriptor strings
+    pub usb: UsbDescriptors,
+    /// Transform stage options
+    pub transform: TransformConfig,
+    /// Modem output lines driven high while asserted
+    pub modem_active_high: ControlLines,
+    /// XON/XOFF flow control mode
+    pub flow: FlowMode,
+    /// Power profile
+    pub power: PowerProfile,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            usart6_baud: USART6_BAUD_RATE,
+            usart3_baud: USART3_BAUD_RATE,
+            uart: UartPort::Usart6,
+            framing: cfg!(feature = "framed-uart"),
+            signal_outputs: SignalOutputs::from_bits_truncate(DEFAULT_SIGNAL_OUTPUTS),
+            serial_number: device_id::hex(),
+            #[cfg(feature = "adc")]
+            health_limits: HealthLimits::default(),
+            usb: UsbDescriptors::default(),
+            transform: TransformConfig::default(),
+            modem_active_high: ControlLines::from_bits_truncate(DEFAULT_MODEM_ACTIVE_HIGH),
+            flow: FlowMode::Off,
+            power: PowerProfile::Balanced,
+        }
+    }
+}
+
+impl Settings {
+    /// Collects the settings in effect
+    ///
+    /// # Arguments
+    /// * `usart6_baud` - Current USART6 rate
+    /// * `usart3_baud` - Current USART3 rate
+    pub fn current(usart6_baud: u32, usart3_baud: u32) -> Self {
+        Self {
+            usart6_baud,
+            usart3_baud,
+            uart: uart_route::current(),
+            framing: framer::is_enabled(),
+            signal_outputs: signal_handler::outputs(),
+            serial_number: serial_number(),
+            #[cfg(feature = "adc")]
+            health_limits: health_monitor::limits(),
+            usb: usb_descriptors(),
+            transform: transform::config(),
+            modem_active_high: modem_lines::active_high(),
+            flow: xon_xoff::mode(),
+            power: power::profile(),
+        }
+    }
+
+    /// Applies everything except the baud rates, which belong to the UART
+    /// controllers
+    ///
+    /// The serial number and the USB descriptors are only reported from the
+    /// next USB enumeration on.
+    pub fn apply(&self) {
+        uart_route::select(self.uart);
+        framer::set_enabled(self.framing);
+        signal_handler::set_outputs(self.signal_outputs);
+        set_serial_number(&self.serial_number);
+        #[cfg(feature = "adc")]
+        health_monitor::set_limits(self.health_limits);
+        set_usb_descriptors(&self.usb);
+        transform::set_config(self.transform);
+        modem_lines::set_active_high(self.modem_active_high);
+        xon_xoff::set_mode(self.flow);
+        power::set_profile(self.power);
+    }
+
+    /// Encodes the setting stored under `key`
+    fn record(&self, key: Key) -> Record {
+        match key {
+            Key::Header => Record::new(key, &[]),
+            Key::Usart6Baud => Record::new(key, &self.usart6_baud.to_le_bytes()),
+            Key::Usart3Baud => Record::new(key, &self.usart3_baud.to_le_bytes()),
+            Key::Uart => Record::new(key, &[self.uart.number()]),
+            Key::Framing => Record::new(key, &[self.framing as u8]),
+            Key::SignalOutputs => Record::new(key, &[self.signal_outputs.bits()]),
+            Key::SerialNumber => Record::new(key, self.serial_number.as_bytes()),
+            #[cfg(feature = "adc")]
+            Key::HealthLimits => Record::new(key, &self.health_limits.to_bytes()),
+            Key::UsbIds => {
+                let ids = u32::from(self.usb.vid) | (u32::from(self.usb.pid) << 16);
+                Record::new(key, &ids.to_le_bytes())
+            }
+            Key::UsbManufacturer => Record::new(key, self.usb.manufacturer.as_bytes()),
+            Key::UsbProduct => Record::new(key, self.usb.product.as_bytes()),
+            Key::Transform => Record::new(key, &[self.transform.bits()]),
+            Key::ModemActiveHigh => Record::new(key, &[self.modem_active_high.bits()]),
+            Key::SoftwareFlow => Record::new(key, &[self.flow as u8]),
+            Key::PowerProfile => Record::new(key, &[self.power as u8]),
+        }
+    }
+
+    /// Takes over a stored record; invalid values keep the current one
+    fn update(&mut self, record: &Record) {
+        let value = record.value();
+        match KEYS.iter().copied().find(|&key| key as u8 == record.key) {
+            Some(Key::Usart6Baud) => {
+                if let Some(baud) = record.value_u32().filter(|&baud| baud > 0) {
+                    self.usart6_baud = baud;
+                }
+            }
+            Some(Key::Usart3Baud) => {
+                if let Some(baud) = record.value_u32().filter(|&baud| baud > 0) {
+                    self.usart3_baud = baud;
+                }
+            }
+            Some(Key::Uart) => {
+                if let Some(port) = value.first().copied().and_then(UartPort::from_number) {
+                    self.uart = port;
+                }
+            }
+            Some(Key::Framing) => {
+                if let [flag @ (0 | 1)] = value {
+                    self.framing = *flag == 1;
+                }
+            }
+            Some(Key::SignalOutputs) => {
+                if let [bits] = value {
+                    self.signal_outputs = SignalOutputs::from_bits_truncate(*bits);
+                }
+            }
+            Some(Key::SerialNumber) => {
+                if let Some(serial) = core::str::from_utf8(value)
+                    .ok()
+                    .filter(|text| is_valid_serial_number(text))
+                    .and_then(|text| SerialNumber::try_from(text).ok())
+                {
+                    self.serial_number = serial;
+                }
+            }
+            #[cfg(feature = "adc")]
+            Some(Key::HealthLimits) => {
+                if let Some(limits) = HealthLimits::from_bytes(value) {
+                    self.health_limits = limits;
+                }
+            }
+            Some(Key::UsbIds) => {
+                if let Some(ids) = record.value_u32() {
+                    let (vid, pid) = (ids as u16, (ids >> 16) as u16);
+                    if is_valid_usb_id(vid, pid) {
+                        self.usb.vid = vid;
+                        self.usb.pid = pid;
+                    }
+                }
+            }
+            Some(Key::UsbManufacturer) => {
+                if let Some(text) = usb_string(value) {
+                    self.usb.manufacturer = text;
+                }
+            }
+            Some(Key::UsbProduct) => {
+                if let Some(text) = usb_string(value) {
+                    self.usb.product = text;
+                }
+            }
+            Some(Key::Transform) => {
+                if let Some(config) = value.first().copied().and_then(TransformConfig::from_bits) {
+                    self.transform = config;
+                }
+            }
+            Some(Key::ModemActiveHigh) => {
+                if let Some(&bits) = value.first() {
+                    self.modem_active_high = ControlLines::from_bits_truncate(bits);
+                }
+            }
+            Some(Key::SoftwareFlow) => {
+                if let Some(mode) = value.first().copied().and_then(FlowMode::from_bits) {
+                    self.flow = mode;
+                }
+            }
+            Some(Key::PowerProfile) => {
+                if let Some(profile) = value.first().copied().and_then(PowerProfile::from_bits) {
+                    self.power = profile;
+                }
+            }
+            Some(Key::Header) | None => {}
+        }
+    }
+}
+
+/// Loads the stored settings
+///
+/// Settings that were never saved, or whose record is damaged, keep their
+/// `config` default.
+pub fn load(flash: &FlashController) -> Settings {
+    let mut settings = Settings::default();
+    if let Some((_index, scan)) = active_sector(flash) {
+        for record in scan.latest.iter().flatten() {
+            settings.update(record);
+        }
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "Settings: sector {}, generation {}, {} slots used",
+            SETTINGS_SECTORS[_index],
+            scan.generation.unwrap_or(0),
+            scan.next_slot
+        );
+    }
+    settings
+}
+
+/// Saves the settings, appending the records that changed
+///
+/// # Returns
+/// Number of records written, 0 if flash already held these settings
+///
+/// # Errors
+/// Propagates `FlashError` from erase or program operations
+pub fn save(flash: &mut FlashController, settings: &Settings) -> Result<usize, FlashError> {
+    let active = active_sector(flash);
+    let mut changed: heapless::Vec<Record, { KEYS.len() }> = heapless::Vec::new();
+    for (i, &key) in KEYS.iter().enumerate() {
+        let record = settings.record(key);
+        let stored = active.as_ref().and_then(|(_, scan)| scan.latest[i]);
+        if stored != Some(record) {
+            changed.push(record).ok();
+        }
+    }
+    if changed.is_empty() {
+        return Ok(0);
+    }
+
+    match active {
+        Some((index, scan)) if scan.next_slot + changed.len() <= SLOTS_PER_SECTOR => {
+            let base = SETTINGS_SECTOR_OFFSETS[index];
+            for (slot, record) in (scan.next_slot..).zip(changed.iter()) {
+                flash.program(base + slot * RECORD_LEN, &record.encode())?;
+            }
+            Ok(changed.len())
+        }
+        active => {
+            let (index, generation) = match active {
+                Some((index, scan)) => (
+                    (index + 1) % SETTINGS_SECTORS.len(),
+                    scan.generation.unwrap_or(0).wrapping_add(1),
+                ),
+                None => (0, 0),
+            };
+            compact(flash, index, generation, settings)?;
+            Ok(KEYS.len())
+        }
+    }
+}
+
+/// Writes all settings to a fresh sector and makes it the active one
+///
+/// The header goes last, so the sector only takes over once it is complete.
+fn compact(
+    flash: &mut FlashController,
+    index: usize,
+    generation: u32,
+    settings: &Settings,
+) -> Result<(), FlashError> {
+    #[cfg(feature = "debug")]
+    defmt::info!(
+        "Settings: compacting into sector {}",
+        SETTINGS_SECTORS[index]
+    );
+
+    flash.erase_sector(SETTINGS_SECTORS[index])?;
+
+    let base = SETTINGS_SECTOR_OFFSETS[index];
+    for (slot, &key) in (1..).zip(KEYS.iter()) {
+        flash.program(base + slot * RECORD_LEN, &settings.record(key).encode())?;
+    }
+    flash.program(base, &Record::header(generation).encode())
+}
+
+/// Checks a USB serial number: printable ASCII without spaces, at most
+/// `SERIAL_NUMBER_LEN` characters
+pub fn is_valid_serial_number(text: &str) -> bool {
+    !text.is_empty()
+        && text.len() <= SERIAL_NUMBER_LEN
+        && text.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Returns the serial number for the next USB enumeration
+pub fn serial_number() -> SerialNumber {
+    interrupt::free(|cs| SERIAL_NUMBER.borrow(cs).borrow().clone())
+        .unwrap_or_else(|| Settings::default().serial_number)
+}
+
+/// Sets the serial number for the next USB enumeration
+///
+/// Kept in RAM until the settings are saved.
+///
+/// # Returns
+/// `false` if the text is not a valid serial number
+pub fn set_serial_number(text: &str) -> bool {
+    if !is_valid_serial_number(text) {
+        return false;
+    }
+    let Ok(serial) = SerialNumber::try_from(text) else {
+        return false;
+    };
+    interrupt::free(|cs| *SERIAL_NUMBER.borrow(cs).borrow_mut() = Some(serial));
+    true
+}
+
+/// Checks a USB vendor/product ID pair: 0x0000 and 0xFFFF are not valid IDs
+pub fn is_valid_usb_id(vid: u16, pid: u16) -> bool {
+    [vid, pid].iter().all(|&id| id != 0x0000 && id != 0xFFFF)
+}
+
+/// Checks a USB manufacturer or product string: printable ASCII, spaces
+/// allowed inside, at most `USB_STRING_LEN` characters
+pub fn is_valid_usb_string(text: &str) -> bool {
+    !text.is_empty()
+        && text.len() <= USB_STRING_LEN
+        && text.trim() == text
+        && text.bytes().all(|b| b == b' ' || b.is_ascii_graphic())
+}
+
+/// Decodes a stored USB string, `None` if it is not valid
+fn usb_string(value: &[u8]) -> Option<UsbString> {
+    core::str::from_utf8(value)
+        .ok()
+        .filter(|text| is_valid_usb_string(text))
+        .and_then(|text| UsbString::try_from(text).ok())
+}
+
+/// Returns the USB IDs and strings for the next USB enumeration
+pub fn usb_descriptors() -> UsbDescriptors {
+    interrupt::free(|cs| USB_DESCRIPTORS.borrow(cs).borrow().clone()).unwrap_or_default()
+}
+
+/// Sets the USB IDs and strings for the next USB enumeration
+///
+/// Kept in RAM until the settings are saved.
+///
+/// # Returns
+/// `false` if an ID or string is not valid
+pub fn set_usb_descriptors(descriptors: &UsbDescriptors) -> bool {
+    if !is_valid_usb_id(descriptors.vid, descriptors.pid)
+        || !is_valid_usb_string(&descriptors.manufacturer)
+        || !is_valid_usb_string(&descriptors.product)
+    {
+        return false;
+    }
+    interrupt::free(|cs| *USB_DESCRIPTORS.borrow(cs).borrow_mut() = Some(descriptors.clone()));
+    true
+}
+
+/// Writes the stored settings and the store usage
+///
+/// # Arguments
+/// * `flash` - Flash controller holding the store
+/// * `out` - Text sink
+pub fn write_report<W: Write>(flash: &FlashController, out: &mut W) -> fmt::Result {
+    let Some((index, scan)) = active_sector(flash) else {
+        return writeln!(out, "Settings: none saved, using defaults");
+    };
+
+    let settings = load(flash);
+    writeln!(
+        out,
+        "Settings: sector {}, generation {}, {}/{} slots",
+        SETTINGS_SECTORS[index],
+        scan.generation.unwrap_or(0),
+        scan.next_slot,
+        SLOTS_PER_SECTOR
+    )?;
+    writeln!(out, "  usart6 baud: {}", settings.usart6_baud)?;
+    writeln!(out, "  usart3 baud: {}", settings.usart3_baud)?;
+    writeln!(out, "  uart: {}", settings.uart.number())?;
+    writeln!(
+        out,
+        "  framing: {}",
+        if settings.framing { "on" } else { "off" }
+    )?;
+    writeln!(
+        out,
+        "  signal: {}",
+        signal_handler::outputs_name(settings.signal_outputs)
+    )?;
+    writeln!(out, "  serial: {}", settings.serial_number)?;
+    writeln!(
+        out,
+        "  usb: {:04X}:{:04X}, \"{}\", \"{}\"",
+        settings.usb.vid, settings.usb.pid, settings.usb.manufacturer, settings.usb.product
+    )?;
+    writeln!(out, "  transform: {}", settings.transform)?;
+    writeln!(out, "  flow: {}", settings.flow.name())?;
+    writeln!(out, "  power: {}", settings.power.name())?;
+    writeln!(
+        out,
+        "  modem outputs: dtr active-{}, rts active-{}",
+        modem_lines::polarity_name(settings.modem_active_high, ControlLines::DTR),
+        modem_lines::polarity_name(settings.modem_active_high, ControlLines::RTS)
+    )?;
+    #[cfg(feature = "adc")]
+    writeln!(
+        out,
+        "  health: die {} to {} C, VDDA {} to {} mV",
+        settings.health_limits.temp_min_c,
+        settings.health_limits.temp_max_c,
+        settings.health_limits.vdd_min_mv,
+        settings.health_limits.vdd_max_mv
+    )?;
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! # Orderly Shutdown
+//!
+//! One teardown path for every restart, so no request cuts bridged data off
+//! mid-transfer or leaves the host to find the device gone. The
+//! `orderly_shutdown` task in `main` runs the steps in this order:
+//! 1. Waits `SHUTDOWN_REPLY_MS`, so the host collects the reply to the request
+//! 2. Lets the bridge drain for up to `SHUTDOWN_DRAIN_MS`: the staging and
+//!    ring buffers empty and both USARTs finish sending
+//! 3. `stop_bridge`: stops the UART DMA streams, takes both USARTs off the
+//!    line and detaches from the USB bus (soft disconnect)
+//! 4. Waits `SHUTDOWN_DETACH_MS`, so the host sees the disconnect
+//! 5. `finish`: interrupts off, `quiesce`, then the reset
+//!
+//! `quiesce` stops the DMA users outside the bridge, SPI2 TX, the ADC and
+//! the DAC waveforms, and turns the LEDs, the buzzer and the DAC outputs
+//! off, so no stream or output runs into the reset. It bypasses the
+//! drivers and leaves them unusable, so it is for the reset only; Stop mode
+//! entry (`usb_suspend::sleep`) pauses the DAC through its driver instead.
+//! The SD card has no stream: its block transfers are polled from a task.
+//!
+//! Requested by the console `reboot` and `bootloader`, `AT+RESET`, a USB
+//! DFU_DETACH and the touch reboot gesture. A request while a shutdown runs
+//! is refused by the spawn; the running one still resets.
+//!
+//! ## Safety Considerations
+//! - The IWDG and the WWDG cannot be stopped, so there is no power-off
+//!   target: Standby or an endless Stop ends in a watchdog reset
+//! - Data still queued after `SHUTDOWN_DRAIN_MS` is dropped
+
+use crate::config::{
+    DFU_DETACH_TIMEOUT_MS, SHUTDOWN_DETACH_MS, SHUTDOWN_DRAIN_MS, SHUTDOWN_REPLY_MS,
+};
+#[cfg(feature = "adc")]
+use crate::peripherals::adc;
+#[cfg(feature = "dac")]
+use crate::peripherals::dac;
+#[cfg(feature = "led-pwm")]
+use crate::peripherals::led_pwm::{self, PwmChannel};
+use crate::peripherals::otg_fs::OtgFsController;
+#[cfg(feature = "spi")]
+use crate::peripherals::spi;
+use crate::peripherals::uart::{Usart3Controller, Usart6Controller};
+use crate::peripherals::{buzzer, dfu_runtime, led};
+
+// A DFU_DETACH shutdown must leave the bus within the time the host allows
+const _: () =
+    assert!(SHUTDOWN_REPLY_MS + SHUTDOWN_DRAIN_MS + SHUTDOWN_DETACH_MS < DFU_DETACH_TIMEOUT_MS);
+
+/// Where a shutdown leads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "debug", derive(defmt::Format))]
+pub enum Restart {
+    /// This firmware again
+    Application,
+    /// The bootloader at `DFU_BOOTLOADER_ADDRESS`, for a USB DFU update
+    Bootloader,
+}
+
+/// Takes the bridge down: both UARTs and USB
+///
+/// Stops the UART DMA streams, waiting up to `UART_STOP_TIMEOUT_MS` for a
+/// running TX transfer, takes both USARTs off the line and detaches from
+/// the USB bus. Only a reset brings the bridge back.
+pub fn stop_bridge(
+    usart_6: &mut Usart6Controller,
+    usart_3: &mut Usart3Controller,
+    usb: &mut OtgFsController<'_>,
+) {
+    usart_6.stop_transfer().ok();
+    usart_6.suspend();
+    usart_3.stop_transfer().ok();
+    usart_3.suspend();
+    usb.detach();
+}
+
+/// Stops the DMA users outside the bridge and turns every output off
+///
+/// Only for the restart (`finish`): works without the drivers, which belong
+/// to other tasks, and leaves their state stale. Call with interrupts
+/// disabled, or a driver may start again.
+fn quiesce() {
+    #[cfg(feature = "spi")]
+    spi::force_stop();
+    #[cfg(feature = "adc")]
+    adc::force_stop();
+    #[cfg(feature = "dac")]
+    dac::force_off();
+
+    #[cfg(feature = "led-pwm")]
+    for channel in [PwmChannel::Blue, PwmChannel::Red] {
+        led_pwm::set_level(channel, 0);
+    }
+    led::all_off();
+    buzzer::force_off();
+}
+
+/// Last step of the shutdown: resets into `restart`
+///
+/// Disables interrupts first, so no task or handler runs between the
+/// teardown and the reset. The bridge must already be stopped
+/// (`stop_bridge`).
+pub fn finish(restart: Restart) -> ! {
+    cortex_m::interrupt::disable();
+    quiesce();
+
+    #[cfg(feature = "debug")]
+    defmt::warn!("Shutdown: restarting into {}", restart);
+
+    match restart {
+        Restart::Application => cortex_m::peripheral::SCB::sys_reset(),
+        Restart::Bootloader => dfu_runtime::reboot_to_bootloader(),
+    }
+}
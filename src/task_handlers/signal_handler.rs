@@ -0,0 +1,420 @@
+//! # Error Code Signal Handler
+//!
+//! Plays error codes as Morse code on the red LED, the PWM buzzer, or both,
+//! for installations where the LED cannot be seen. The outputs start from
+//! `DEFAULT_SIGNAL_OUTPUTS` and are changed with the console `signal`
+//! command; the sequence state lives in the `RedLed` either way.
+//! Supports:
+//! - Dot (.) and dash (-) symbols
+//! - Inter-symbol, inter-letter and inter-word spacing
+//! - Error record store, critical errors played before warnings
+//! - Chaining of several pending codes into one run, one code per word
+//!
+//! `DeviceError` codes are played as their letter mnemonic (`BO` for a buffer
+//! overflow, `DM` for a DMA error, ...), other codes as decimal digits. Within
+//! a code the characters are separated by letter gaps (` `); chained codes
+//! are separated by word gaps (`/`). The playback time of a chained run is
+//! bounded by `MORSE_CHAIN_MAX_MS` and reported through [`last_chain`].
+//!
+//! Records are observed with `peek_all`, most severe first, and only consumed
+//! from the error store once their playback ends, so the host error dump sees
+//! them meanwhile. A code reported many times is played once per run; reports
+//! arriving during playback stay pending for the next run.
+//!
+//! Speed and repeat policy come from the LED's `MorseConfig`: a code is
+//! consumed after the number of runs the policy asks for, or never with
+//! `RepeatPolicy::UntilCleared`.
+
+use crate::config::{DEFAULT_SIGNAL_OUTPUTS, MAX_MORSE_LENGTH, MORSE_CHAIN_MAX_MS};
+use crate::data_structures::error_queue::ErrorRecord;
+use crate::peripherals::buzzer::Buzzer;
+use crate::peripherals::red_led::{MorseConfig, MorseState, RedLed};
+use crate::task_handlers::error_handlers::{consume, mark_played, peek_all};
+use crate::utils::morse::error_code_to_morse;
+use bitflags::bitflags;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Vec;
+
+/// Interval between state machine updates while a sequence plays (milliseconds)
+pub const MORSE_UPDATE_MS: u32 = 10;
+
+bitflags! {
+    /// Outputs that play error codes
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct SignalOutputs: u8 {
+        const LED    = 1 << 0; // Red LED (PD5)
+        const BUZZER = 1 << 1; // PWM buzzer (PA7)
+    }
+}
+
+/// Output names accepted by the console `signal` command
+const OUTPUT_NAMES: [(&str, SignalOutputs); 3] = [
+    ("led", SignalOutputs::LED),
+    ("buzzer", SignalOutputs::BUZZER),
+    ("both", SignalOutputs::LED.union(SignalOutputs::BUZZER)),
+];
+
+/// Selected outputs
+static OUTPUTS: AtomicU8 = AtomicU8::new(DEFAULT_SIGNAL_OUTPUTS);
+
+/// Maximum number of codes chained into one run.
+/// Every code takes at least two letters (three symbols) plus a word gap.
+const MAX_CHAIN_CODES: usize = MAX_MORSE_LENGTH / 4 + 1;
+
+/// Records of the run being played, consumed from the error store when it ends
+static CHAIN_CODES: Mutex<RefCell<Vec<ErrorRecord, MAX_CHAIN_CODES>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+/// Number of codes in the most recent chained run
+static LAST_CHAIN_CODES: AtomicU32 = AtomicU32::new(0);
+
+/// Playback time of the most recent chained run (milliseconds)
+static LAST_CHAIN_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Output that can render Morse symbols
+pub trait SignalOutput {
+    /// Starts a dot or dash
+    fn signal_on(&mut self);
+    /// Ends a dot or dash
+    fn signal_off(&mut self);
+}
+
+impl SignalOutput for RedLed {
+    fn signal_on(&mut self) {
+        self.set_low();
+    }
+
+    fn signal_off(&mut self) {
+        self.set_high();
+    }
+}
+
+impl SignalOutput for Buzzer {
+    fn signal_on(&mut self) {
+        self.on();
+    }
+
+    fn signal_off(&mut self) {
+        self.off();
+    }
+}
+
+/// Outputs driven by the Morse state machine
+pub struct Signal<'a> {
+    /// Red LED, also holding the sequence state
+    pub led: &'a mut RedLed,
+    /// Buzzer
+    pub buzzer: &'a mut Buzzer,
+}
+
+impl Signal<'_> {
+    /// Switches the selected outputs on
+    fn on(&mut self) {
+        let outputs = outputs();
+        if outputs.contains(SignalOutputs::LED) {
+            self.led.signal_on();
+        }
+        if outputs.contains(SignalOutputs::BUZZER) {
+            self.buzzer.signal_on();
+        }
+    }
+
+    /// Switches all outputs off
+    ///
+    /// Deselected outputs are included, so a selection change during a
+    /// symbol never leaves one of them on.
+    fn off(&mut self) {
+        self.led.signal_off();
+        self.buzzer.signal_off();
+    }
+}
+
+/// Returns the outputs that play error codes
+pub fn outputs() -> SignalOutputs {
+    SignalOutputs::from_bits_truncate(OUTPUTS.load(Ordering::Relaxed))
+}
+
+/// Selects the outputs that play error codes, from the next symbol on
+pub fn set_outputs(outputs: SignalOutputs) {
+    OUTPUTS.store(outputs.bits(), Ordering::Relaxed);
+}
+
+/// Looks up an output selection by its console name
+///
+/// # Returns
+/// - `Some(SignalOutputs)` for `led`, `buzzer` or `both`
+/// - `None` otherwise
+pub fn outputs_by_name(name: &str) -> Option<SignalOutputs> {
+    OUTPUT_NAMES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|&(_, outputs)| outputs)
+}
+
+/// Console name of an output selection, `none` if nothing is selected
+pub fn outputs_name(selected: SignalOutputs) -> &'static str {
+    OUTPUT_NAMES
+        .iter()
+        .find(|&&(_, outputs)| outputs == selected)
+        .map_or("none", |&(name, _)| name)
+}
+
+/// Writes the selected outputs and a summary of the last chained run
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let chain = last_chain();
+    writeln!(
+        out,
+        "Signal: {}, last run {} code(s), {} ms",
+        outputs_name(outputs()),
+        chain.codes,
+        chain.duration_ms
+    )
+}
+
+/// Summary of a chained Morse run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChainReport {
+    /// Number of error codes played
+    pub codes: u32,
+    /// Total playback time including gaps (milliseconds)
+    pub duration_ms: u32,
+}
+
+/// Returns the summary of the most recently started chained run
+pub fn last_chain() -> ChainReport {
+    ChainReport {
+        codes: LAST_CHAIN_CODES.load(Ordering::Relaxed),
+        duration_ms: LAST_CHAIN_MS.load(Ordering::Relaxed),
+    }
+}
+
+/// Computes the playback time of a Morse sequence
+///
+/// # Arguments
+/// * `symbols` - Morse symbols as played by `update_red_led`
+/// * `config` - Morse speed
+///
+/// # Returns
+/// Duration in milliseconds, including the pause after the last symbol
+pub fn playback_duration(symbols: &[u8], config: &MorseConfig) -> u32 {
+    symbols
+        .iter()
+        .map(|&symbol| match symbol {
+            b'.' => config.dot_ms() + config.symbol_pause_ms(),
+            b'-' => config.dash_ms() + config.symbol_pause_ms(),
+            b' ' => config.letter_pause_ms() - config.symbol_pause_ms(),
+            b'/' => config.word_pause_ms() - config.symbol_pause_ms(),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// Updates the signal outputs based on Morse code timing and error codes
+///
+/// # Arguments
+/// * `signal` - Red LED (holding the sequence state) and buzzer
+/// * `current_time` - System timestamp in milliseconds
+/// * `buffer` - Temporary buffer for Morse code conversion
+///
+/// # State Machine
+/// 1. Idle: Waiting to start new symbol
+/// 2. Signal: Outputs active (dot/dash transmission)
+/// 3. Pause: Outputs inactive (space between symbols/words)
+pub fn update_signal(signal: &mut Signal, current_time: u32, buffer: &mut [u8]) {
+    match signal.led.morse_sequence {
+        Some(_) => handle_active_sequence(signal, current_time),
+        None => start_new_sequence(signal, buffer),
+    }
+}
+
+/// Handles ongoing Morse code transmission
+fn handle_active_sequence(signal: &mut Signal, current_time: u32) {
+    let elapsed = calculate_elapsed(current_time, signal.led.last_toggle);
+
+    match signal.led.morse_state {
+        MorseState::Idle => process_idle_state(signal, current_time),
+        MorseState::Signal => process_signal_state(signal, elapsed),
+        MorseState::Pause => process_pause_state(signal, elapsed, current_time),
+    }
+}
+
+/// Starts new Morse sequence from the error store
+///
+/// Pending codes are chained into one sequence, most severe first and
+/// separated by word gaps, as long as both the sequence buffer and
+/// `MORSE_CHAIN_MAX_MS` allow; the first code is always taken. The records
+/// stay pending until playback ends.
+fn start_new_sequence(signal: &mut Signal, buffer: &mut [u8]) {
+    let mut pending = [ErrorRecord::default(); MAX_CHAIN_CODES];
+    let available = peek_all(&mut pending);
+
+    let mut sequence = [0u8; MAX_MORSE_LENGTH];
+    let mut length = 0;
+    let mut chain: Vec<ErrorRecord, MAX_CHAIN_CODES> = Vec::new();
+    let mut duration = 0u32;
+    let config = signal.led.morse_config();
+
+    for record in &pending[..available] {
+        let code = record.code;
+        let word_len = match error_code_to_morse(code, buffer) {
+            Ok(len) if len <= MAX_MORSE_LENGTH => len,
+            _ => {
+                #[cfg(feature = "debug")]
+                defmt::error!("Morse conversion failed for code {}", code);
+                if chain.is_empty() {
+                    consume(record);
+                    continue;
+                }
+                break;
+            }
+        };
+
+        let gap = if chain.is_empty() { 0 } else { 1 };
+        let word_ms = playback_duration(&buffer[..word_len], &config);
+        let gap_ms = if chain.is_empty() {
+            0
+        } else {
+            playback_duration(b"/", &config)
+        };
+
+        if !chain.is_empty()
+            && (length + gap + word_len > MAX_MORSE_LENGTH
+                || duration + gap_ms + word_ms > MORSE_CHAIN_MAX_MS)
+        {
+            break;
+        }
+
+        if gap > 0 {
+            sequence[length] = b'/';
+        }
+        sequence[length + gap..length + gap + word_len].copy_from_slice(&buffer[..word_len]);
+        length += gap + word_len;
+        duration += gap_ms + word_ms;
+        if chain.push(*record).is_err() {
+            break;
+        }
+    }
+
+    if chain.is_empty() {
+        #[cfg(feature = "debug")]
+        defmt::trace!("No error codes pending");
+        return;
+    }
+
+    let codes = chain.len() as u32;
+    interrupt::free(|cs| *CHAIN_CODES.borrow(cs).borrow_mut() = chain);
+
+    if let Err(e) = signal.led.load_morse_sequence(&sequence[..length]) {
+        #[cfg(feature = "debug")]
+        defmt::error!("Morse init failed: {:?}", e);
+        return finish_sequence(signal);
+    }
+
+    LAST_CHAIN_CODES.store(codes, Ordering::Relaxed);
+    LAST_CHAIN_MS.store(duration, Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Morse chain: {} code(s), {} ms", codes, duration);
+}
+
+/// Ends the current sequence and consumes the records whose repeats are done
+fn finish_sequence(signal: &mut Signal) {
+    signal.off();
+    signal.led.reset_morse_state();
+
+    let repeat = signal.led.morse_config().repeat();
+    let chain = interrupt::free(|cs| core::mem::take(&mut *CHAIN_CODES.borrow(cs).borrow_mut()));
+    for record in &chain {
+        if repeat.is_done(record.plays.saturating_add(1)) {
+            consume(record);
+        } else {
+            mark_played(record);
+        }
+    }
+}
+
+/// Processes IDLE state (waiting to start symbol)
+fn process_idle_state(signal: &mut Signal, current_time: u32) {
+    if signal.led.morse_index >= signal.led.morse_length {
+        finish_sequence(signal);
+        return;
+    }
+
+    if let Some(symbol) = signal.led.current_symbol() {
+        match symbol {
+            '.' | '-' => activate_signal(signal, current_time),
+            ' ' | '/' => start_pause(signal, current_time),
+            _ => handle_invalid_symbol(signal),
+        }
+    }
+}
+
+/// Processes SIGNAL state (outputs active)
+fn process_signal_state(signal: &mut Signal, elapsed: u32) {
+    let duration = match signal.led.current_symbol() {
+        Some('.') => signal.led.morse_config().dot_ms(),
+        Some('-') => signal.led.morse_config().dash_ms(),
+        _ => return finish_sequence(signal),
+    };
+
+    if elapsed >= duration {
+        deactivate_signal(signal);
+    }
+}
+
+/// Processes PAUSE state (outputs inactive)
+fn process_pause_state(signal: &mut Signal, elapsed: u32, current_time: u32) {
+    let config = signal.led.morse_config();
+    let required_pause = match signal.led.current_symbol() {
+        Some('.') | Some('-') => config.symbol_pause_ms(),
+        Some(' ') => config.letter_pause_ms() - config.symbol_pause_ms(),
+        Some('/') => config.word_pause_ms() - config.symbol_pause_ms(),
+        _ => return finish_sequence(signal),
+    };
+
+    if elapsed >= required_pause {
+        advance_sequence(signal, current_time);
+    }
+}
+
+/// Calculates elapsed time with overflow protection
+fn calculate_elapsed(current: u32, last: u32) -> u32 {
+    current.wrapping_sub(last)
+}
+
+/// Switches the outputs on and updates state
+fn activate_signal(signal: &mut Signal, timestamp: u32) {
+    signal.on();
+    signal.led.morse_state = MorseState::Signal;
+    signal.led.last_toggle = timestamp;
+}
+
+/// Switches the outputs off and starts pause
+fn deactivate_signal(signal: &mut Signal) {
+    signal.off();
+    signal.led.morse_state = MorseState::Pause;
+}
+
+/// Advances to next symbol in sequence
+fn advance_sequence(signal: &mut Signal, timestamp: u32) {
+    signal.led.morse_index += 1;
+    signal.led.morse_state = MorseState::Idle;
+    signal.led.last_toggle = timestamp;
+}
+
+/// Starts inter-symbol pause
+fn start_pause(signal: &mut Signal, timestamp: u32) {
+    signal.led.morse_state = MorseState::Pause;
+    signal.led.last_toggle = timestamp;
+}
+
+/// Handles invalid Morse symbols
+fn handle_invalid_symbol(signal: &mut Signal) {
+    #[cfg(feature = "debug")]
+    defmt::warn!("Invalid Morse symbol detected");
+
+    finish_sequence(signal);
+}
@@ -0,0 +1,286 @@
+//! # SLCAN Adapter Mode
+//!
+//! Turns the data port into a CAN adapter (`can` feature): while the mode is
+//! on, host data is not forwarded to the UART but read as SLCAN command
+//! lines (`protocol::slcan`), and frames from CAN1 are sent to the host as
+//! SLCAN lines. Standard tools attach to the CDC port directly, e.g.
+//! `slcand -o -s6 /dev/ttyACM0 can0` or SavvyCAN's LAWICEL driver.
+//!
+//! - The USB interrupt only splits lines and queues the decoded commands
+//!   (`SLCAN_QUEUE_LEN`); a full queue drops the command
+//! - The dispatcher task runs the commands in order on CAN1 and answers
+//!   each one through the RX ring buffer
+//! - The CAN1 RX interrupt forwards received frames the same way
+//! - UART data is dropped while the mode is on, so answers and frames are
+//!   not mixed with bridged bytes
+//!
+//! Switching the mode off closes the channel.
+
+use crate::config::SLCAN_QUEUE_LEN;
+use crate::peripherals::can::{self, Can1, CanFrame, CanId, CanMode};
+use crate::protocol::slcan::{self, Command, Line};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Deque;
+
+/// Answer to `V`: hardware 1.0, software 1.0
+const VERSION: u32 = 0x1010;
+
+/// Answer to `N`
+const SERIAL_NUMBER: u32 = 0xF469;
+
+/// `S` index used until the host selects a bitrate: 500 kbit/s
+const DEFAULT_BITRATE_INDEX: u8 = 6;
+
+/// Decoded command, or `None` for a line that failed to decode
+type Pending = Option<Command>;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+static BITRATE_INDEX: AtomicU8 = AtomicU8::new(DEFAULT_BITRATE_INDEX);
+
+/// Line being received, and whether the line overflowed and is skipped
+static LINE: Mutex<RefCell<(Line, bool)>> = Mutex::new(RefCell::new((Line::new(), false)));
+static QUEUE: Mutex<RefCell<Deque<Pending, SLCAN_QUEUE_LEN>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+/// Data overrun to report with the next `F` answer
+static OVERRUN: AtomicBool = AtomicBool::new(false);
+
+static COMMANDS: AtomicU32 = AtomicU32::new(0);
+static REJECTED: AtomicU32 = AtomicU32::new(0);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+static FORWARDED: AtomicU32 = AtomicU32::new(0);
+
+/// Checks whether host data is read as SLCAN commands
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Switches the data port between the UART bridge and the SLCAN adapter
+///
+/// Partial lines and queued commands are dropped. Switching off queues a
+/// close of the channel: run the dispatcher afterwards.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    interrupt::free(|cs| {
+        let mut line = LINE.borrow(cs).borrow_mut();
+        line.0.clear();
+        line.1 = false;
+        let mut queue = QUEUE.borrow(cs).borrow_mut();
+        queue.clear();
+        if !enabled {
+            queue.push_back(Some(Command::Close)).ok();
+        }
+    });
+
+    #[cfg(feature = "debug")]
+    defmt::info!("SLCAN {=bool}", enabled);
+}
+
+/// Splits host data into lines and queues the decoded commands
+///
+/// # Arguments
+/// * `data` - Bytes received on the data port
+///
+/// # Returns
+/// Number of commands queued
+pub fn receive(data: &[u8]) -> usize {
+    let mut queued = 0;
+    interrupt::free(|cs| {
+        let mut line = LINE.borrow(cs).borrow_mut();
+        let (text, overflowed) = &mut *line;
+        let mut queue = QUEUE.borrow(cs).borrow_mut();
+        for &byte in data {
+            match byte {
+                slcan::OK => {
+                    let pending = if *overflowed {
+                        None
+                    } else {
+                        slcan::parse(text)
+                    };
+                    text.clear();
+                    *overflowed = false;
+                    if queue.push_back(pending).is_ok() {
+                        queued += 1;
+                    } else {
+                        DROPPED.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                // slcand ends lines with CR only; a LF from a terminal is skipped
+                b'\n' => {}
+                _ => {
+                    if text.push(byte).is_err() {
+                        *overflowed = true;
+                    }
+                }
+            }
+        }
+    });
+    queued
+}
+
+/// Checks whether commands wait for the dispatcher
+pub fn has_pending() -> bool {
+    interrupt::free(|cs| !QUEUE.borrow(cs).borrow().is_empty())
+}
+
+/// Runs the oldest queued command
+///
+/// # Arguments
+/// * `can` - CAN1 controller
+///
+/// # Returns
+/// The answer line; `None` once the queue is empty
+pub fn service(can: &mut Can1) -> Option<Line> {
+    let pending = interrupt::free(|cs| QUEUE.borrow(cs).borrow_mut().pop_front())?;
+    COMMANDS.fetch_add(1, Ordering::Relaxed);
+
+    let answer = pending.and_then(|command| execute(can, command));
+    if answer.is_none() {
+        REJECTED.fetch_add(1, Ordering::Relaxed);
+    }
+    Some(answer.unwrap_or_else(|| single(slcan::ERROR)))
+}
+
+/// One-byte answer
+fn single(byte: u8) -> Line {
+    let mut line = Line::new();
+    line.push(byte).ok();
+    line
+}
+
+/// Runs one command
+///
+/// # Returns
+/// The answer, `None` for a failure (answered with BEL)
+fn execute(can: &mut Can1, command: Command) -> Option<Line> {
+    let ok = || Some(single(slcan::OK));
+    match command {
+        Command::SetBitrate(index) => {
+            // The bitrate can only change while the channel is closed
+            let bitrate = slcan::BITRATES[usize::from(index)];
+            if can.channel().is_some() || !can::is_bitrate_supported(bitrate) {
+                return None;
+            }
+            BITRATE_INDEX.store(index, Ordering::Relaxed);
+            ok()
+        }
+        Command::Open | Command::ListenOnly => {
+            if can.channel().is_some() {
+                return None;
+            }
+            let mode = if command == Command::Open {
+                CanMode::Normal
+            } else {
+                CanMode::ListenOnly
+            };
+            let bitrate = slcan::BITRATES[usize::from(BITRATE_INDEX.load(Ordering::Relaxed))];
+            OVERRUN.store(false, Ordering::Relaxed);
+            can.open(bitrate, mode).ok()?;
+            ok()
+        }
+        Command::Close => {
+            can.channel()?;
+            can.close().ok()?;
+            ok()
+        }
+        Command::Transmit(frame) => {
+            can.transmit(&frame).ok()?;
+            let answer: &[u8] = match frame.id {
+                CanId::Standard(_) => b"z\r",
+                CanId::Extended(_) => b"Z\r",
+            };
+            Line::from_slice(answer).ok()
+        }
+        Command::Status => {
+            can.channel()?;
+            Some(slcan::encode_value(b'F', u32::from(status_flags(can)), 2))
+        }
+        Command::Version => Some(slcan::encode_value(b'V', VERSION, 4)),
+        Command::SerialNumber => Some(slcan::encode_value(b'N', SERIAL_NUMBER, 4)),
+        Command::Timestamps(enabled) => {
+            // Like the LAWICEL adapters, only while the channel is closed
+            if can.channel().is_some() {
+                return None;
+            }
+            TIMESTAMPS.store(enabled, Ordering::Relaxed);
+            ok()
+        }
+    }
+}
+
+/// Flags of the `F` answer; the overrun flag is cleared by reading it
+fn status_flags(can: &Can1) -> u8 {
+    let status = can.status();
+    let mut flags = 0;
+    if can.is_rx_full() {
+        flags |= slcan::STATUS_RX_FULL;
+    }
+    if can.is_tx_full() {
+        flags |= slcan::STATUS_TX_FULL;
+    }
+    if status.error_warning {
+        flags |= slcan::STATUS_ERROR_WARNING;
+    }
+    if OVERRUN.swap(false, Ordering::Relaxed) {
+        flags |= slcan::STATUS_OVERRUN;
+    }
+    if status.error_passive {
+        flags |= slcan::STATUS_ERROR_PASSIVE;
+    }
+    if status.bus_off {
+        flags |= slcan::STATUS_BUS_ERROR;
+    }
+    flags
+}
+
+/// Encodes a received frame for the host
+///
+/// # Arguments
+/// * `frame` - Frame from the receive FIFO
+/// * `now_ms` - Receive time, used while timestamps are on
+///
+/// # Returns
+/// `None` while the mode is off: the frame is dropped
+pub fn encode_received(frame: &CanFrame, now_ms: u32) -> Option<Line> {
+    if !is_enabled() {
+        return None;
+    }
+    FORWARDED.fetch_add(1, Ordering::Relaxed);
+    let timestamp = TIMESTAMPS.load(Ordering::Relaxed).then_some(now_ms);
+    Some(slcan::encode_frame(frame, timestamp))
+}
+
+/// Records frames lost on the way to the host, for the `F` answer
+pub fn note_overrun() {
+    OVERRUN.store(true, Ordering::Relaxed);
+}
+
+/// Writes the adapter mode, channel settings and counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let index = usize::from(BITRATE_INDEX.load(Ordering::Relaxed));
+    writeln!(
+        out,
+        "SLCAN: {}, S{} ({} bit/s), timestamps {}",
+        if is_enabled() { "on" } else { "off" },
+        index,
+        slcan::BITRATES[index],
+        if TIMESTAMPS.load(Ordering::Relaxed) {
+            "on"
+        } else {
+            "off"
+        }
+    )?;
+    writeln!(
+        out,
+        "  {} commands, {} rejected, {} dropped, {} frames forwarded",
+        COMMANDS.load(Ordering::Relaxed),
+        REJECTED.load(Ordering::Relaxed),
+        DROPPED.load(Ordering::Relaxed),
+        FORWARDED.load(Ordering::Relaxed)
+    )
+}
@@ -0,0 +1,255 @@
+//! # Periodic Statistics Snapshots
+//!
+//! Appends fixed-size snapshot records to a circular log in internal flash so
+//! devices returned from the field can be analysed without a debugger:
+//! - 32-byte records with magic, sequence number and checksum
+//! - Two sectors used alternately; the older one is erased only when the active
+//!   one is full, keeping erase cycles to a minimum
+//! - Write position recovered at boot by scanning for the first erased slot
+//! - History dump in sequence order for the host (console `snapshots`)
+
+use crate::config::{SNAPSHOT_SECTORS, SNAPSHOT_SECTOR_OFFSETS, SNAPSHOT_SECTOR_SIZE};
+use crate::errors::errors::FlashError;
+use crate::peripherals::flash::FlashController;
+use crate::task_handlers::error_handlers::{
+    last_error_code, pending_error_count, total_error_count,
+};
+use core::fmt::{self, Write};
+
+/// Encoded snapshot size in bytes
+pub const SNAPSHOT_RECORD_LEN: usize = 32;
+
+/// Snapshots that fit into one sector
+const SLOTS_PER_SECTOR: usize = SNAPSHOT_SECTOR_SIZE / SNAPSHOT_RECORD_LEN;
+
+/// Record marker ("SN")
+const SNAPSHOT_MAGIC: u16 = 0x534E;
+
+/// Record layout version
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// Maximum number of records written by `dump`, so they fit a console reply
+pub const SNAPSHOT_DUMP_MAX: usize = 32;
+
+/// Statistics captured in one snapshot
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Snapshot {
+    /// Monotonic record number, assigned on append
+    pub sequence: u32,
+    /// Seconds since boot
+    pub uptime_s: u32,
+    /// Errors reported since boot
+    pub errors_total: u32,
+    /// Errors waiting in the queue
+    pub errors_pending: u16,
+    /// Most recent error code
+    pub last_error: u16,
+}
+
+/// State of a single flash slot
+enum SlotState {
+    Erased,
+    Valid(Snapshot),
+    Corrupt,
+}
+
+impl Snapshot {
+    /// Captures the current system state
+    ///
+    /// # Arguments
+    /// * `uptime_s` - Seconds since boot, tracked by the caller
+    pub fn capture(uptime_s: u32) -> Self {
+        Self {
+            sequence: 0,
+            uptime_s,
+            errors_total: total_error_count(),
+            errors_pending: pending_error_count() as u16,
+            last_error: last_error_code(),
+        }
+    }
+
+    /// Serializes the snapshot into its flash representation
+    fn encode(&self) -> [u8; SNAPSHOT_RECORD_LEN] {
+        let mut record = [0u8; SNAPSHOT_RECORD_LEN];
+        record[0..2].copy_from_slice(&SNAPSHOT_MAGIC.to_le_bytes());
+        record[2] = SNAPSHOT_VERSION;
+        record[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        record[8..12].copy_from_slice(&self.uptime_s.to_le_bytes());
+        record[12..16].copy_from_slice(&self.errors_total.to_le_bytes());
+        record[16..18].copy_from_slice(&self.errors_pending.to_le_bytes());
+        record[18..20].copy_from_slice(&self.last_error.to_le_bytes());
+
+        let checksum = checksum(&record[..SNAPSHOT_RECORD_LEN - 2]);
+        record[SNAPSHOT_RECORD_LEN - 2..].copy_from_slice(&checksum.to_le_bytes());
+        record
+    }
+
+    /// Classifies and decodes a flash slot
+    fn decode(bytes: &[u8]) -> SlotState {
+        if bytes.iter().all(|&b| b == 0xFF) {
+            return SlotState::Erased;
+        }
+
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+
+        if bytes.len() != SNAPSHOT_RECORD_LEN
+            || u16_at(0) != SNAPSHOT_MAGIC
+            || bytes[2] != SNAPSHOT_VERSION
+            || u16_at(SNAPSHOT_RECORD_LEN - 2) != checksum(&bytes[..SNAPSHOT_RECORD_LEN - 2])
+        {
+            return SlotState::Corrupt;
+        }
+
+        SlotState::Valid(Snapshot {
+            sequence: u32_at(4),
+            uptime_s: u32_at(8),
+            errors_total: u32_at(12),
+            errors_pending: u16_at(16),
+            last_error: u16_at(18),
+        })
+    }
+}
+
+/// Fletcher-16 checksum over a record
+fn checksum(data: &[u8]) -> u16 {
+    let (mut a, mut b) = (0u16, 0u16);
+    for &byte in data {
+        a = (a + byte as u16) % 255;
+        b = (b + a) % 255;
+    }
+    (b << 8) | a
+}
+
+/// Circular snapshot log write position
+pub struct SnapshotLog {
+    sector_index: usize,
+    slot: usize,
+    next_sequence: u32,
+}
+
+impl SnapshotLog {
+    /// Recovers the write position from flash contents
+    ///
+    /// The active sector is the one holding the highest sequence number; writing
+    /// resumes after its last non-erased slot.
+    pub fn recover(flash: &FlashController) -> Self {
+        let mut log = Self {
+            sector_index: 0,
+            slot: 0,
+            next_sequence: 0,
+        };
+        let mut found = false;
+
+        for (index, &base) in SNAPSHOT_SECTOR_OFFSETS.iter().enumerate() {
+            for slot in 0..SLOTS_PER_SECTOR {
+                let Ok(bytes) = flash.read(base + slot * SNAPSHOT_RECORD_LEN, SNAPSHOT_RECORD_LEN)
+                else {
+                    break;
+                };
+
+                match Snapshot::decode(bytes) {
+                    SlotState::Erased => break,
+                    SlotState::Valid(s) if !found || s.sequence >= log.next_sequence => {
+                        found = true;
+                        log.sector_index = index;
+                        log.slot = slot + 1;
+                        log.next_sequence = s.sequence.wrapping_add(1);
+                    }
+                    SlotState::Valid(_) => {}
+                    // Interrupted write: skip the slot but never reuse it
+                    SlotState::Corrupt if index == log.sector_index => log.slot = slot + 1,
+                    SlotState::Corrupt => {}
+                }
+            }
+        }
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "Snapshot log: sector {}, slot {}, next seq {}",
+            log.sector_index,
+            log.slot,
+            log.next_sequence
+        );
+
+        log
+    }
+
+    /// Appends a snapshot, rotating sectors when the active one is full
+    ///
+    /// # Errors
+    /// Propagates `FlashError` from erase or program operations
+    pub fn append(
+        &mut self,
+        flash: &mut FlashController,
+        snapshot: &mut Snapshot,
+    ) -> Result<(), FlashError> {
+        if self.slot >= SLOTS_PER_SECTOR {
+            self.sector_index = (self.sector_index + 1) % SNAPSHOT_SECTORS.len();
+            flash.erase_sector(SNAPSHOT_SECTORS[self.sector_index])?;
+            self.slot = 0;
+        }
+
+        snapshot.sequence = self.next_sequence;
+        let offset = SNAPSHOT_SECTOR_OFFSETS[self.sector_index] + self.slot * SNAPSHOT_RECORD_LEN;
+
+        // Consume the slot even on failure so a bad slot is never retried
+        self.slot += 1;
+        flash.program(offset, &snapshot.encode())?;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("Snapshot {} stored", snapshot.sequence);
+
+        Ok(())
+    }
+
+    /// Iterates over stored snapshots, oldest first
+    pub fn history<'a>(&self, flash: &'a FlashController) -> impl Iterator<Item = Snapshot> + 'a {
+        let sectors = SNAPSHOT_SECTOR_OFFSETS.len();
+        let active = self.sector_index;
+        let active_slots = self.slot;
+
+        // Older sector first, then the active one up to the write position
+        (1..=sectors).flat_map(move |step| {
+            let index = (active + step) % sectors;
+            let slots = if index == active {
+                active_slots
+            } else {
+                SLOTS_PER_SECTOR
+            };
+            let base = SNAPSHOT_SECTOR_OFFSETS[index];
+
+            (0..slots).filter_map(move |slot| {
+                let bytes = flash
+                    .read(base + slot * SNAPSHOT_RECORD_LEN, SNAPSHOT_RECORD_LEN)
+                    .ok()?;
+                match Snapshot::decode(bytes) {
+                    SlotState::Valid(s) => Some(s),
+                    _ => None,
+                }
+            })
+        })
+    }
+
+    /// Writes the newest snapshots as text, oldest first, one record per line
+    ///
+    /// At most `SNAPSHOT_DUMP_MAX` records are written, after a header with
+    /// the number of stored ones.
+    ///
+    /// # Arguments
+    /// * `flash` - Flash controller holding the log
+    /// * `out` - Text sink (e.g. a console line writer)
+    pub fn dump<W: Write>(&self, flash: &FlashController, out: &mut W) -> fmt::Result {
+        let stored = self.history(flash).count();
+        writeln!(out, "Snapshots: {} stored", stored)?;
+        for s in self.history(flash).skip(stored.saturating_sub(SNAPSHOT_DUMP_MAX)) {
+            writeln!(
+                out,
+                "#{} up={}s errors={} pending={} last={}",
+                s.sequence, s.uptime_s, s.errors_total, s.errors_pending, s.last_error
+            )?;
+        }
+        Ok(())
+    }
+}
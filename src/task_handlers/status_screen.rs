@@ -0,0 +1,220 @@
+//! # Display Status Screen
+//!
+//! Renders the bridge state on the LTDC/DSI display (`display` feature):
+//! - USB state, the routed UART and its baud rate
+//! - UART/USB byte counters, dropped bytes and DMA restarts
+//! - Pending error records with severity, mnemonic, count and description
+//! - With the `touch` feature, the `input::TOUCH_AREAS` buttons along the
+//!   bottom edge
+//!
+//! Each text line is kept between redraws and only lines whose text changed
+//! are drawn again, so a refresh of an idle bridge costs a few comparisons.
+
+use crate::bridge::UartPort;
+use crate::data_structures::error_queue::{ErrorRecord, Severity};
+use crate::errors::errors::DeviceError;
+use crate::peripherals::display::{color, Display, COLUMNS, ROWS};
+#[cfg(feature = "touch")]
+use crate::peripherals::display::{CELL_HEIGHT, CELL_WIDTH};
+use crate::task_handlers::error_handlers::{peek_all, pending_error_count, total_error_count};
+#[cfg(feature = "touch")]
+use crate::task_handlers::input::TOUCH_AREAS;
+use crate::utils::morse::Mnemonic;
+use crate::utils::statistics;
+use core::fmt::Write;
+use heapless::String;
+
+/// First text row of the error list
+const ERROR_ROW: usize = 9;
+
+/// Text rows taken by the touch button bar
+const BAR_ROWS: usize = if cfg!(feature = "touch") { 2 } else { 0 };
+
+/// Row noting error records that did not fit
+const MORE_ROW: usize = ROWS - BAR_ROWS - 1;
+
+/// Error records shown
+const ERROR_LINES: usize = MORE_ROW - ERROR_ROW;
+
+/// One line of screen text
+type Line = String<COLUMNS>;
+
+/// Bridge state sampled by the display task
+pub struct Status {
+    /// USB device configured by the host
+    pub usb_configured: bool,
+    /// USB bus suspended
+    pub usb_suspended: bool,
+    /// UART routed to the bridge
+    pub port: UartPort,
+    /// Baud rate of the routed UART
+    pub baud_rate: u32,
+}
+
+/// Status page renderer with the text currently on screen
+pub struct StatusScreen {
+    lines: [(Line, u16); ROWS],
+    cleared: bool,
+}
+
+impl Default for StatusScreen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusScreen {
+    /// Creates a renderer; the first `render` clears the screen
+    pub const fn new() -> Self {
+        const EMPTY: (Line, u16) = (String::new(), color::BLACK);
+        Self {
+            lines: [EMPTY; ROWS],
+            cleared: false,
+        }
+    }
+
+    /// Redraws the lines that changed since the last call
+    ///
+    /// # Arguments
+    /// * `display` - Target display
+    /// * `status` - Sampled bridge state
+    pub fn render(&mut self, display: &mut Display, status: &Status) {
+        if !self.cleared {
+            display.clear(color::BLACK);
+            display.draw_line(0, " USB-UART BRIDGE", color::WHITE, color::BLUE);
+            #[cfg(feature = "touch")]
+            draw_buttons(display);
+            self.cleared = true;
+        }
+
+        let mut page: [(Line, u16); ROWS] = Default::default();
+        compose(&mut page, status);
+
+        // Row 0 is the title bar; the button bar rows stay empty
+        for (row, (line, cached)) in page.iter().zip(self.lines.iter_mut()).enumerate().skip(1) {
+            if line != cached {
+                display.draw_line(row, &line.0, line.1, color::BLACK);
+                *cached = line.clone();
+            }
+        }
+    }
+}
+
+/// Fills the page text; lines that do not fit are cut
+fn compose(page: &mut [(Line, u16); ROWS], status: &Status) {
+    let stats = statistics::get_stats();
+
+    let (usb_state, usb_color) = if status.usb_suspended {
+        ("suspended", color::YELLOW)
+    } else if status.usb_configured {
+        ("configured", color::GREEN)
+    } else {
+        ("not connected", color::GREY)
+    };
+    set(
+        &mut page[2],
+        usb_color,
+        format_args!("USB    {}", usb_state),
+    );
+    set(
+        &mut page[3],
+        color::WHITE,
+        format_args!("UART   {} {} baud", status.port.name(), status.baud_rate),
+    );
+    set(
+        &mut page[5],
+        color::WHITE,
+        format_args!(
+            "UART   rx {:>10}  tx {:>10}",
+            stats.uart_rx_bytes, stats.uart_tx_bytes
+        ),
+    );
+    set(
+        &mut page[6],
+        color::WHITE,
+        format_args!(
+            "USB    rx {:>10}  tx {:>10}",
+            stats.usb_rx_bytes, stats.usb_tx_bytes
+        ),
+    );
+    set(
+        &mut page[7],
+        color::WHITE,
+        format_args!(
+            "Dropped {}  DMA restarts {}",
+            stats.dropped_bytes, stats.dma_restarts
+        ),
+    );
+
+    let pending = pending_error_count();
+    let header_color = if pending == 0 {
+        color::GREEN
+    } else {
+        color::RED
+    };
+    set(
+        &mut page[ERROR_ROW - 1],
+        header_color,
+        format_args!("Errors {} pending, {} total", pending, total_error_count()),
+    );
+
+    let mut records = [ErrorRecord::default(); ERROR_LINES];
+    let shown = peek_all(&mut records);
+    for (line, record) in page[ERROR_ROW..].iter_mut().zip(&records[..shown]) {
+        let (mnemonic, description) = match DeviceError::from_code(record.code) {
+            Some(error) => (error.mnemonic(), error.description()),
+            None => ("", ""),
+        };
+        let severity_color = match record.severity {
+            Severity::Critical => color::RED,
+            Severity::Error => color::YELLOW,
+            Severity::Warning => color::WHITE,
+        };
+        set(
+            line,
+            severity_color,
+            format_args!(
+                " {}{:<4} {:<2} x{:<4} {}",
+                record.severity.tag(),
+                record.code,
+                mnemonic,
+                record.count,
+                description
+            ),
+        );
+    }
+    if pending > shown {
+        set(
+            &mut page[MORE_ROW],
+            color::GREY,
+            format_args!(" ... {} more", pending - shown),
+        );
+    }
+}
+
+/// Draws the touch buttons with their captions centered
+#[cfg(feature = "touch")]
+fn draw_buttons(display: &mut Display) {
+    for area in &TOUCH_AREAS {
+        let (x, y) = (area.x as usize, area.y as usize);
+        let (width, height) = (area.width as usize, area.height as usize);
+        display.fill_rect(x, y, width, height, color::BLUE);
+
+        let text_width = area.label.len() * CELL_WIDTH;
+        display.draw_text(
+            x + width.saturating_sub(text_width) / 2,
+            y + height.saturating_sub(CELL_HEIGHT) / 2,
+            area.label,
+            color::WHITE,
+            color::BLUE,
+        );
+    }
+}
+
+/// Formats one line; pieces beyond the line width are dropped
+fn set(line: &mut (Line, u16), fg: u16, args: core::fmt::Arguments) {
+    line.0.clear();
+    // A full line reports an error; what fitted is still shown
+    line.0.write_fmt(args).ok();
+    line.1 = fg;
+}
@@ -0,0 +1,297 @@
+//! # Downstream Target Identification Probe
+//!
+//! Detects which bootloader, if any, listens on the far end of USART6 so host
+//! flashing tools can configure themselves:
+//! - STM32 ROM bootloader: `0x7F` autobaud byte at 8E1, answered by ACK `0x79`
+//!   (or NACK `0x1F` when it has already synchronized)
+//! - AVR STK500 (Optiboot): `STK_GET_SYNC` answered by `STK_INSYNC STK_OK`
+//! - ESP32 ROM bootloader: SLIP-framed `SYNC` command answered by a SYNC response
+//!
+//! Each handshake is tried at each of its standard baud rates with a strict
+//! `PROBE_STEP_TIMEOUT_MS` timeout. The UART is restored to its previous rate
+//! and 8N1 framing afterwards, whatever the outcome.
+//!
+//! ## Safety Considerations
+//! - The probe bytes reach whatever is attached; only run it on request
+//! - Targets must already be in their bootloader (reset/boot straps are not driven)
+
+use crate::config::{
+    PROBE_AVR_BAUD_RATES, PROBE_ESP32_BAUD_RATES, PROBE_STEP_TIMEOUT_MS, PROBE_STM32_BAUD_RATES,
+};
+use crate::peripherals::uart::UartConfig;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Vec;
+
+/// Settling time after reconfiguring the UART before sending (milliseconds)
+pub const SWITCH_SETTLE_MS: u32 = 5;
+
+/// STM32 ROM bootloader autobaud byte
+const STM32_SYNC: &[u8] = &[0x7F];
+
+/// STK500 `Cmnd_STK_GET_SYNC`, `Sync_CRC_EOP`
+const AVR_SYNC: &[u8] = &[0x30, 0x20];
+
+/// ESP32 ROM `SYNC` command (0x08) in a SLIP frame
+const ESP32_SYNC: &[u8] = &[
+    0xC0, 0x00, 0x08, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, 0x07, 0x12, 0x20, 0x55, 0x55,
+    0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55,
+    0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0xC0,
+];
+
+/// Longest expected response pattern
+const MAX_PATTERN_LEN: usize = 3;
+
+/// Set while a probe owns the UART data path
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Outcome of the most recent probe (`None` inside if nothing answered)
+static LAST_RESULT: Mutex<RefCell<Option<Option<ProbeResult>>>> = Mutex::new(RefCell::new(None));
+
+/// Checks whether RX data must be left for the probe
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+/// Returns the outcome of the most recent probe
+///
+/// # Returns
+/// - `None` if no probe has completed since boot
+/// - `Some(None)` if no known target answered
+/// - `Some(Some(result))` with the detected target
+pub fn last_result() -> Option<Option<ProbeResult>> {
+    interrupt::free(|cs| *LAST_RESULT.borrow(cs).borrow())
+}
+
+/// Writes the outcome of the most recent probe, if any
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    match last_result() {
+        None => Ok(()),
+        Some(None) => writeln!(out, "Target probe: no response"),
+        Some(Some(result)) => writeln!(
+            out,
+            "Target probe: {} at {} baud",
+            result.kind.name(),
+            result.baud
+        ),
+    }
+}
+
+/// Target types the probe can recognize
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetKind {
+    /// STM32 system memory bootloader (AN3155)
+    Stm32Rom,
+    /// AVR STK500v1 bootloader (Optiboot, Arduino)
+    AvrStk500,
+    /// ESP32 ROM serial bootloader
+    Esp32Rom,
+}
+
+impl TargetKind {
+    /// Short name used in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Stm32Rom => "stm32-rom",
+            Self::AvrStk500 => "avr-stk500",
+            Self::Esp32Rom => "esp32-rom",
+        }
+    }
+}
+
+/// Detected target and the rate it answered at
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProbeResult {
+    pub kind: TargetKind,
+    pub baud: u32,
+}
+
+/// One known handshake
+struct Handshake {
+    kind: TargetKind,
+    request: &'static [u8],
+    /// Any of these byte sequences identifies the target
+    responses: &'static [&'static [u8]],
+    frame: UartConfig,
+    baud_rates: &'static [u32],
+}
+
+/// Handshakes in probing order
+static HANDSHAKES: [Handshake; 3] = [
+    Handshake {
+        kind: TargetKind::Stm32Rom,
+        request: STM32_SYNC,
+        responses: &[&[0x79], &[0x1F]],
+        frame: UartConfig::EIGHT_E1,
+        baud_rates: PROBE_STM32_BAUD_RATES,
+    },
+    Handshake {
+        kind: TargetKind::AvrStk500,
+        request: AVR_SYNC,
+        responses: &[&[0x14, 0x10]],
+        frame: UartConfig::EIGHT_N1,
+        baud_rates: PROBE_AVR_BAUD_RATES,
+    },
+    Handshake {
+        kind: TargetKind::Esp32Rom,
+        request: ESP32_SYNC,
+        responses: &[&[0xC0, 0x01, 0x08]],
+        frame: UartConfig::EIGHT_N1,
+        baud_rates: PROBE_ESP32_BAUD_RATES,
+    },
+];
+
+/// Single operation requested from the driving task
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// Reprogram the UART rate and framing
+    Configure { baud: u32, frame: UartConfig },
+    /// Transmit bytes and wait until they have left the shift register
+    Send(&'static [u8]),
+    /// Pause before the next operation (milliseconds)
+    Delay(u32),
+    /// Probe over; the UART is back at its previous configuration
+    Finish(Option<ProbeResult>),
+}
+
+/// Operations to execute before waiting for a response
+pub type Ops = Vec<Op, 4>;
+
+/// Probing state machine
+pub struct Prober {
+    handshake: usize,
+    rate: usize,
+    restore_baud: u32,
+    restore_frame: UartConfig,
+    window: Vec<u8, MAX_PATTERN_LEN>,
+    done: bool,
+}
+
+impl Prober {
+    /// Creates a prober and claims the UART data path
+    ///
+    /// # Arguments
+    /// * `restore_baud` - Rate to return to when probing ends
+    /// * `restore_frame` - Frame format to return to when probing ends
+    pub fn new(restore_baud: u32, restore_frame: UartConfig) -> Self {
+        ACTIVE.store(true, Ordering::SeqCst);
+        Self {
+            handshake: 0,
+            rate: 0,
+            restore_baud,
+            restore_frame,
+            window: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// Timeout for the current handshake (milliseconds)
+    pub fn timeout_ms(&self) -> u32 {
+        PROBE_STEP_TIMEOUT_MS
+    }
+
+    /// Produces the operations of the first handshake
+    pub fn start(&mut self) -> Ops {
+        let mut ops = Ops::new();
+        self.attempt(&mut ops);
+        ops
+    }
+
+    /// Feeds one received byte
+    ///
+    /// # Returns
+    /// `Some(ops)` finishing the probe when the byte completes a known response
+    pub fn on_byte(&mut self, byte: u8) -> Option<Ops> {
+        if self.done {
+            return None;
+        }
+
+        if self.window.is_full() {
+            self.window.remove(0);
+        }
+        self.window.push(byte).ok();
+
+        let handshake = &HANDSHAKES[self.handshake];
+        let matched = handshake
+            .responses
+            .iter()
+            .any(|pattern| self.window.ends_with(pattern));
+        if !matched {
+            return None;
+        }
+
+        let result = ProbeResult {
+            kind: handshake.kind,
+            baud: handshake.baud_rates[self.rate],
+        };
+        let mut ops = Ops::new();
+        self.finish(&mut ops, Some(result));
+        Some(ops)
+    }
+
+    /// Moves to the next rate or handshake when no response arrived in time
+    pub fn on_timeout(&mut self) -> Ops {
+        let mut ops = Ops::new();
+        if self.done {
+            return ops;
+        }
+
+        self.rate += 1;
+        if self.rate >= HANDSHAKES[self.handshake].baud_rates.len() {
+            self.rate = 0;
+            self.handshake += 1;
+        }
+
+        if self.handshake < HANDSHAKES.len() {
+            self.attempt(&mut ops);
+        } else {
+            self.finish(&mut ops, None);
+        }
+        ops
+    }
+
+    /// Emits the operations for the current handshake and rate
+    fn attempt(&mut self, ops: &mut Ops) {
+        let handshake = &HANDSHAKES[self.handshake];
+        let baud = handshake.baud_rates[self.rate];
+        self.window.clear();
+
+        ops.push(Op::Configure {
+            baud,
+            frame: handshake.frame,
+        })
+        .ok();
+        ops.push(Op::Delay(SWITCH_SETTLE_MS)).ok();
+        ops.push(Op::Send(handshake.request)).ok();
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("Probe: {=str} at {} baud", handshake.kind.name(), baud);
+    }
+
+    fn finish(&mut self, ops: &mut Ops, result: Option<ProbeResult>) {
+        ops.push(Op::Configure {
+            baud: self.restore_baud,
+            frame: self.restore_frame,
+        })
+        .ok();
+        ops.push(Op::Finish(result)).ok();
+        self.done = true;
+
+        interrupt::free(|cs| *LAST_RESULT.borrow(cs).borrow_mut() = Some(result));
+        ACTIVE.store(false, Ordering::SeqCst);
+
+        #[cfg(feature = "debug")]
+        match result {
+            Some(r) => defmt::info!("Probe: {=str} answered at {} baud", r.kind.name(), r.baud),
+            None => defmt::info!("Probe: no known target answered"),
+        }
+    }
+}
+
+impl Drop for Prober {
+    fn drop(&mut self) {
+        ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
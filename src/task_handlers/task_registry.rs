@@ -0,0 +1,90 @@
+//! # Runtime Task Registry
+//!
+//! Lets optional subsystems be silenced or re-enabled at runtime without a
+//! rebuild. Each subsystem owns one bit in an atomic enable mask:
+//! - Periodic tasks check their bit before doing any work
+//! - Control commands flip bits by subsystem name
+//! - Initial state comes from `DEFAULT_ENABLED_SUBSYSTEMS` in `config`
+
+use crate::config::DEFAULT_ENABLED_SUBSYSTEMS;
+use bitflags::bitflags;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+bitflags! {
+    /// Optional subsystems that can be toggled at runtime
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Subsystem: u32 {
+        const STATS_REPORTER  = 1 << 0; // Periodic statistics output
+        const HEALTH_MONITOR  = 1 << 1; // Temperature/voltage/clock checks
+        const SNIFFER_MIRROR  = 1 << 2; // Traffic mirroring to the host
+        const DISPLAY_REFRESH = 1 << 3; // Status screen redraw
+        const STATS_SNAPSHOT  = 1 << 4; // Periodic snapshot into flash
+    }
+}
+
+/// Subsystem names accepted by control commands
+const SUBSYSTEM_NAMES: [(&str, Subsystem); 5] = [
+    ("stats", Subsystem::STATS_REPORTER),
+    ("health", Subsystem::HEALTH_MONITOR),
+    ("sniffer", Subsystem::SNIFFER_MIRROR),
+    ("display", Subsystem::DISPLAY_REFRESH),
+    ("snapshot", Subsystem::STATS_SNAPSHOT),
+];
+
+/// Current enable mask shared by all tasks
+static ENABLED_MASK: AtomicU32 = AtomicU32::new(DEFAULT_ENABLED_SUBSYSTEMS);
+
+/// Checks whether a subsystem is currently allowed to run
+///
+/// Intended to be called at the top of every periodic iteration.
+#[inline]
+pub fn is_enabled(subsystem: Subsystem) -> bool {
+    ENABLED_MASK.load(Ordering::Relaxed) & subsystem.bits() == subsystem.bits()
+}
+
+/// Enables one or more subsystems
+pub fn enable(subsystem: Subsystem) {
+    ENABLED_MASK.fetch_or(subsystem.bits(), Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Subsystems enabled: {=u32:#x}", subsystem.bits());
+}
+
+/// Disables one or more subsystems
+///
+/// Tasks finish their current iteration and go quiet on the next check.
+pub fn disable(subsystem: Subsystem) {
+    ENABLED_MASK.fetch_and(!subsystem.bits(), Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Subsystems disabled: {=u32:#x}", subsystem.bits());
+}
+
+/// Replaces the whole enable mask
+pub fn set_enabled_mask(subsystems: Subsystem) {
+    ENABLED_MASK.store(subsystems.bits(), Ordering::Relaxed);
+}
+
+/// Returns a snapshot of the enable mask
+pub fn enabled_mask() -> Subsystem {
+    Subsystem::from_bits_truncate(ENABLED_MASK.load(Ordering::Relaxed))
+}
+
+/// Looks up a subsystem by its control-command name
+///
+/// # Returns
+/// - `Some(Subsystem)` for a known name (case-sensitive)
+/// - `None` otherwise
+pub fn subsystem_by_name(name: &str) -> Option<Subsystem> {
+    SUBSYSTEM_NAMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|&(_, s)| s)
+}
+
+/// Iterates over all known subsystem names with their current state
+pub fn states() -> impl Iterator<Item = (&'static str, bool)> {
+    SUBSYSTEM_NAMES
+        .iter()
+        .map(|&(name, s)| (name, is_enabled(s)))
+}
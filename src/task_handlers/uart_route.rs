@@ -0,0 +1,83 @@
+//! # UART Routing
+//!
+//! Selects which UART the CDC data port is bridged to:
+//! - Host data is transmitted on the routed UART only
+//! - RX of the other UART is skipped and never reaches the host
+//! - A switch applies to the next DMA TX transfer; a transfer still running
+//!   on the previous UART completes first
+//!
+//! The route starts at the `BridgeBuilder::uart` choice and is changed with
+//! `AT+UART=` on the data port or `uart` on the debug console.
+//!
+//! The strap window, RX timeout timer, target probe, baud negotiation,
+//! baud rate detection, Modbus master and safe mode are tied to the USART6
+//! wiring; the probe, the negotiation, the detection and a Modbus
+//! transaction route the bridge back to USART6 when they start, and the
+//! host cannot switch away while one runs, nor during a throughput
+//! benchmark.
+
+use crate::bridge::UartPort;
+#[cfg(feature = "modbus")]
+use crate::task_handlers::modbus;
+use crate::task_handlers::{auto_baud, baud_negotiation, benchmark, target_probe};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+/// Routed UART, stored as its `UartPort::number`
+static ROUTE: AtomicU8 = AtomicU8::new(UartPort::Usart6.number());
+
+static SWITCHES: AtomicU32 = AtomicU32::new(0);
+
+/// UART currently bridged to the data port
+#[inline]
+pub fn current() -> UartPort {
+    UartPort::from_number(ROUTE.load(Ordering::Relaxed)).unwrap_or(UartPort::Usart6)
+}
+
+/// Checks whether `port` is the bridged UART
+#[inline]
+pub fn is_routed(port: UartPort) -> bool {
+    current() == port
+}
+
+/// Checks whether a host request may change the route
+///
+/// `false` while the target probe, the baud negotiation, a baud rate
+/// detection or a Modbus transaction owns USART6, or a benchmark owns the
+/// routed UART.
+pub fn can_switch() -> bool {
+    #[cfg(feature = "modbus")]
+    if modbus::is_active() {
+        return false;
+    }
+    !target_probe::is_active()
+        && !baud_negotiation::is_active()
+        && !auto_baud::is_active()
+        && !benchmark::is_active()
+}
+
+/// Bridges the data port to `port`
+///
+/// # Returns
+/// `true` if the route changed
+pub fn select(port: UartPort) -> bool {
+    let previous = ROUTE.swap(port.number(), Ordering::Relaxed);
+    if previous == port.number() {
+        return false;
+    }
+    SWITCHES.fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Data port routed to {=str}", port.name());
+    true
+}
+
+/// Writes the route and the number of switches
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "UART route: {}, switches {}",
+        current().name(),
+        SWITCHES.load(Ordering::Relaxed)
+    )
+}
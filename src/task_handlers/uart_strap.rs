@@ -0,0 +1,120 @@
+//! # UART Strap Configuration
+//!
+//! Lets a test fixture select a configuration preset over the UART at power-up,
+//! when USB control may not be available:
+//! - For `STRAP_WINDOW_MS` after boot, USART6 RX is interrupt-driven (RXNE)
+//! - `STRAP_MAGIC` followed by an ASCII digit selects an entry of `STRAP_PRESETS`
+//! - When the window closes, the preset is applied and DMA reception starts
+//!
+//! Bytes received during the window are consumed by the detector and are not
+//! bridged to USB.
+
+use crate::config::{STRAP_MAGIC, STRAP_PRESETS};
+use crate::errors::errors::UsartError;
+use crate::peripherals::uart::Usart6Controller;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// No preset selected marker
+const NO_PRESET: u8 = u8::MAX;
+
+/// Set while the strap window is open
+static WINDOW_OPEN: AtomicBool = AtomicBool::new(false);
+
+/// Preset index chosen during the window
+static SELECTED: AtomicU8 = AtomicU8::new(NO_PRESET);
+
+/// Configuration preset
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrapPreset {
+    pub baud_rate: u32,
+    pub rx_timeout_bit_times: u16,
+}
+
+/// Incremental matcher for the strap sequence
+pub struct StrapDetector {
+    matched: usize,
+}
+
+impl StrapDetector {
+    /// Creates an idle detector
+    pub const fn new() -> Self {
+        Self { matched: 0 }
+    }
+
+    /// Feeds one received byte
+    ///
+    /// # Returns
+    /// The selected preset index once a complete, valid sequence was received
+    pub fn feed(&mut self, byte: u8) -> Option<usize> {
+        if self.matched == STRAP_MAGIC.len() {
+            self.matched = 0;
+            let index = byte.wrapping_sub(b'0') as usize;
+            if index < STRAP_PRESETS.len() {
+                SELECTED.store(index as u8, Ordering::Release);
+                return Some(index);
+            }
+            return None;
+        }
+
+        if byte == STRAP_MAGIC[self.matched] {
+            self.matched += 1;
+        } else {
+            // The magic has no repeated prefix, so a restart only checks byte 0
+            self.matched = usize::from(byte == STRAP_MAGIC[0]);
+        }
+        None
+    }
+}
+
+/// Opens the strap window and switches RX to interrupt mode
+///
+/// Called from `init`, before DMA reception is started.
+pub fn open_window(usart: &mut Usart6Controller) {
+    SELECTED.store(NO_PRESET, Ordering::Relaxed);
+    WINDOW_OPEN.store(true, Ordering::Release);
+    usart.set_rx_interrupt(true);
+}
+
+/// Whether the strap window is open
+pub fn is_window_open() -> bool {
+    WINDOW_OPEN.load(Ordering::Acquire)
+}
+
+/// Closes the window, applies the selected preset and starts DMA reception
+///
+/// # Returns
+/// The applied preset, if one was selected
+///
+/// # Errors
+/// Returns `UsartError` if the preset cannot be applied or DMA fails to start
+pub fn close_window(usart: &mut Usart6Controller) -> Result<Option<StrapPreset>, UsartError> {
+    WINDOW_OPEN.store(false, Ordering::Release);
+    usart.set_rx_interrupt(false);
+
+    let preset = selected_preset();
+    if let Some(preset) = preset {
+        usart.set_baud_rate(preset.baud_rate)?;
+        usart.set_rx_timeout(preset.rx_timeout_bit_times)?;
+
+        #[cfg(feature = "debug")]
+        defmt::info!(
+            "Strap preset applied: {} baud, RX timeout {} bit times",
+            preset.baud_rate,
+            preset.rx_timeout_bit_times
+        );
+    }
+
+    usart.start_dma_rx()?;
+    Ok(preset)
+}
+
+/// Preset selected during the window, if any
+pub fn selected_preset() -> Option<StrapPreset> {
+    let index = SELECTED.load(Ordering::Acquire);
+    STRAP_PRESETS
+        .get(index as usize)
+        .map(|&(baud_rate, rx_timeout_bit_times)| StrapPreset {
+            baud_rate,
+            rx_timeout_bit_times,
+        })
+}
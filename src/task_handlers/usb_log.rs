@@ -0,0 +1,169 @@
+//! # USB Log Transport
+//!
+//! Plain-text log sink for field units without a debug probe, so they can be
+//! debugged over the same USB cable:
+//! - Log lines are formatted into frames and buffered in a byte ring of
+//!   `USB_LOG_BUFFER_LEN`; a frame that does not fit is dropped whole and
+//!   counted, so the host never sees a torn line
+//! - The console task ships the buffer on the debug console port while a
+//!   terminal has it open
+//! - Deferred interrupt records (`isr_log!`) are forwarded from `idle`
+//! - On by default (`USB_LOG_ENABLED`), toggled with the console `log`
+//!   command
+//!
+//! The interface count is bounded by the endpoint budget of the OTG FS core:
+//! a third CDC port needs two more IN endpoints, while only one is left after
+//! both CDC ports, so the log shares the console port instead.
+//!
+//! ## Frame Format
+//! `ESC "!LOG " <level> " " <ms> " " <text> CR LF`, with the level as one of
+//! `T D I W E` and the monotonic time in milliseconds. The leading escape byte
+//! lets the host separate log frames from console replies, as for error
+//! notifications.
+//!
+//! Built with the `usb-log` feature.
+
+use crate::config::{USB_LOG_BUFFER_LEN, USB_LOG_ENABLED, USB_LOG_LINE_LEN};
+use crate::data_structures::log_queue::{self, DeferredRecord, LogLevel};
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::{Deque, String};
+
+/// Pending frame bytes
+static BUFFER: Mutex<RefCell<Deque<u8, USB_LOG_BUFFER_LEN>>> =
+    Mutex::new(RefCell::new(Deque::new()));
+
+/// Runtime enable flag
+static ENABLED: AtomicBool = AtomicBool::new(USB_LOG_ENABLED);
+
+static FRAMES_QUEUED: AtomicU32 = AtomicU32::new(0);
+static FRAMES_DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Enables or disables the USB log; disabling discards pending frames
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        interrupt::free(|cs| BUFFER.borrow(cs).borrow_mut().clear());
+    }
+}
+
+/// Checks whether the USB log is enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Single-letter tag of a level
+fn level_tag(level: LogLevel) -> char {
+    match level {
+        LogLevel::Trace => 'T',
+        LogLevel::Debug => 'D',
+        LogLevel::Info => 'I',
+        LogLevel::Warn => 'W',
+        LogLevel::Error => 'E',
+    }
+}
+
+/// Formats and queues one log frame
+///
+/// Text beyond `USB_LOG_LINE_LEN` is cut off.
+///
+/// # Arguments
+/// * `level` - Severity of the line
+/// * `args` - Line text, without a line end
+pub fn log(level: LogLevel, args: fmt::Arguments) {
+    if !is_enabled() {
+        return;
+    }
+
+    let mut frame: String<USB_LOG_LINE_LEN> = String::new();
+    let now_ms = crate::Mono::now().ticks();
+    // A line that does not fit is truncated, the line end is always added
+    let _ = write!(frame, "\x1b!LOG {} {} ", level_tag(level), now_ms);
+    let _ = frame.write_fmt(args);
+    if frame.len() > USB_LOG_LINE_LEN - 2 {
+        let mut end = USB_LOG_LINE_LEN - 2;
+        while !frame.is_char_boundary(end) {
+            end -= 1;
+        }
+        frame.truncate(end);
+    }
+    let _ = frame.push_str("\r\n");
+
+    let queued = interrupt::free(|cs| {
+        let mut buffer = BUFFER.borrow(cs).borrow_mut();
+        if buffer.capacity() - buffer.len() < frame.len() {
+            return false;
+        }
+        for &byte in frame.as_bytes() {
+            // Cannot fail, the free space was checked
+            let _ = buffer.push_back(byte);
+        }
+        true
+    });
+
+    if queued {
+        FRAMES_QUEUED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        FRAMES_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Queues a deferred interrupt record
+pub fn log_record(record: &DeferredRecord) {
+    match record.value {
+        Some(value) => log(record.level, format_args!("{}: {}", record.message, value)),
+        None => log(record.level, format_args!("{}", record.message)),
+    }
+}
+
+/// Forwards records deferred by interrupt handlers
+///
+/// Called from `idle` in builds without the `debug` feature; with it, the RTT
+/// drain forwards each record as well.
+pub fn drain_deferred() {
+    while let Some(record) = log_queue::pop() {
+        log_record(&record);
+    }
+
+    let dropped = log_queue::take_dropped();
+    if dropped > 0 {
+        log(
+            LogLevel::Warn,
+            format_args!("{} deferred log records dropped", dropped),
+        );
+    }
+}
+
+/// Moves pending frame bytes into `buf`
+///
+/// # Returns
+/// Number of bytes copied; 0 if nothing is pending
+pub fn take(buf: &mut [u8]) -> usize {
+    interrupt::free(|cs| {
+        let mut buffer = BUFFER.borrow(cs).borrow_mut();
+        let mut count = 0;
+        for slot in buf.iter_mut() {
+            match buffer.pop_front() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    })
+}
+
+/// Writes the enable state and frame counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let pending = interrupt::free(|cs| BUFFER.borrow(cs).borrow().len());
+    writeln!(
+        out,
+        "USB log: {}, {} frames queued, {} dropped, {} bytes pending",
+        if is_enabled() { "on" } else { "off" },
+        FRAMES_QUEUED.load(Ordering::Relaxed),
+        FRAMES_DROPPED.load(Ordering::Relaxed),
+        pending
+    )
+}
@@ -0,0 +1,132 @@
+//! # USB Suspend Handling
+//!
+//! Puts the bridge into low power while the host has suspended the bus:
+//! - On suspend, the blue LED task stops and UART TX DMA is gated; host data
+//!   already received stays in the staging and ring buffers
+//! - `sleep` (called from `idle` unless the power profile is `performance`)
+//!   enters Stop mode until USB resume, UART activity, the user button or the
+//!   RTC wakeup timer, and reloads the IWDG after each period. The DAC
+//!   waveforms are paused for the period and resumed after it (`dac`
+//!   feature); SPI2 and ADC transfers complete before their driver calls
+//!   return, so no other stream outlasts a task into Stop
+//! - UART activity keeps the MCU awake for `SUSPEND_UART_HOLD_MS`, so a
+//!   burst lands in the RX ring buffer; it is forwarded after resume
+//! - UART data also wakes the host through USB remote wakeup, if the host
+//!   enabled it
+//! - On resume, the LED task is restarted and the buffered data flows again
+//!
+//! Without a running RTC (neither LSE nor LSI) there is no wakeup timer to
+//! keep the IWDG fed, so the MCU only sleeps (WFI) while suspended.
+
+use crate::config::{SUSPEND_UART_HOLD_MS, SUSPEND_WAKE_MS};
+#[cfg(feature = "dac")]
+use crate::peripherals::dac;
+use crate::peripherals::{iwdg, low_power};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use rtic_monotonics::systick::prelude::*;
+
+/// Set while the host keeps the bus suspended
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Monotonic time of the last UART activity (milliseconds)
+static LAST_UART_ACTIVITY_MS: AtomicU32 = AtomicU32::new(0);
+
+static SUSPENDS: AtomicU32 = AtomicU32::new(0);
+static STOP_PERIODS: AtomicU32 = AtomicU32::new(0);
+static REMOTE_WAKEUPS: AtomicU32 = AtomicU32::new(0);
+
+/// Checks whether the bus is suspended
+///
+/// Tasks that drive the UART or the status LED back off while `true`.
+#[inline]
+pub fn is_suspended() -> bool {
+    SUSPENDED.load(Ordering::Relaxed)
+}
+
+/// Records a bus suspend reported by the USB controller
+///
+/// Called from the OTG FS handler, which logs the event.
+pub fn on_suspend() {
+    SUSPENDED.store(true, Ordering::Relaxed);
+    SUSPENDS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records the end of a bus suspend
+///
+/// Called from the OTG FS handler, which logs the event.
+pub fn on_resume() {
+    SUSPENDED.store(false, Ordering::Relaxed);
+}
+
+/// Records UART activity, postponing the next Stop entry
+pub fn note_uart_activity() {
+    LAST_UART_ACTIVITY_MS.store(crate::Mono::now().ticks(), Ordering::Relaxed);
+}
+
+/// Counts a remote wakeup signalled to the host
+pub fn note_remote_wakeup() {
+    REMOTE_WAKEUPS.fetch_add(1, Ordering::Relaxed);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("USB remote wakeup signalled");
+}
+
+/// Sleeps until the next interrupt
+///
+/// Called from `power::idle`. While suspended and the UART is quiet, the MCU enters
+/// Stop mode for at most `SUSPEND_WAKE_MS`; otherwise it waits in WFI.
+pub fn sleep() {
+    let now_ms = crate::Mono::now().ticks();
+    let quiet =
+        now_ms.wrapping_sub(LAST_UART_ACTIVITY_MS.load(Ordering::Relaxed)) >= SUSPEND_UART_HOLD_MS;
+
+    // Runs with interrupts disabled, so the DAC task cannot start a
+    // waveform between the pause and Stop
+    let mut paused = false;
+    let enter = || {
+        let suspended = is_suspended();
+        if suspended {
+            pause_outputs();
+            paused = true;
+        }
+        suspended
+    };
+    let stopped = quiet && low_power::stop(SUSPEND_WAKE_MS, enter);
+    // Also when the RTC wakeup timer was unavailable after the pause
+    if paused {
+        resume_outputs();
+    }
+
+    if stopped {
+        // No task ran while stopped; the Stop loop itself proves liveness
+        iwdg::reload_in_stop();
+        STOP_PERIODS.fetch_add(1, Ordering::Relaxed);
+    } else {
+        cortex_m::asm::wfi();
+    }
+}
+
+/// Holds the outputs that would run on after Stop entry
+fn pause_outputs() {
+    #[cfg(feature = "dac")]
+    dac::pause();
+}
+
+/// Restarts the outputs held by `pause_outputs`
+fn resume_outputs() {
+    #[cfg(feature = "dac")]
+    dac::resume();
+}
+
+/// Writes the suspend state and counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "USB suspend: {}, suspends {}, stop periods {}, remote wakeups {}",
+        if is_suspended() { "yes" } else { "no" },
+        SUSPENDS.load(Ordering::Relaxed),
+        STOP_PERIODS.load(Ordering::Relaxed),
+        REMOTE_WAKEUPS.load(Ordering::Relaxed)
+    )
+}
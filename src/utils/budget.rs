@@ -0,0 +1,248 @@
+//! # Execution Budget Checker
+//!
+//! Turns the task priority table into measurable soft real-time guarantees:
+//! - Every time-critical task declares a worst-case execution budget (`Task::budget_us`)
+//! - Each run is timed with the DWT cycle counter through a `BudgetGuard`,
+//!   which also profiles the run for the CPU load report
+//! - Overruns are counted per task; a streak of `BUDGET_CHRONIC_STREAK`
+//!   consecutive overruns marks the task as chronically late
+//! - Chronic overruns are raised once per streak as `DeviceError::BudgetOverrun`
+//!   by the periodic `BudgetCheck` job, and all counters appear in SYSINFO
+//!
+//! For async tasks only the code between two `await` points is measured;
+//! time spent waiting does not count against the budget.
+//!
+//! ## Safety Considerations
+//! - Requires the DWT cycle counter to be enabled during init
+//! - Measured times include preemption by higher priority tasks, so a budget
+//!   must cover the interference the priority table allows
+
+use crate::config::{BUDGET_CHRONIC_STREAK, SYSCLK};
+use crate::errors::errors::DeviceError;
+use crate::utils::profiler::ProfileGuard;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::peripheral::DWT;
+
+/// Tasks with a declared budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    /// USB OTG FS interrupt (priority 4)
+    OtgFs,
+    /// USART6 interrupt (priority 3)
+    Usart6,
+    /// USART3 interrupt (priority 3)
+    Usart3,
+    /// DMA2 stream 1 / DMA1 stream 1, UART RX (priority 3)
+    DmaRx,
+    /// DMA2 stream 6 / DMA1 stream 3, UART TX (priority 3)
+    DmaTx,
+    /// TIM3 RX timeout (priority 3)
+    RxTimeout,
+    /// RX ring buffer to USB (priority 3)
+    RxToUsb,
+    /// TX ring buffer to USART DMA (priority 3)
+    TxToUart,
+    /// One red LED Morse state machine step (priority 5)
+    ErrorDisplay,
+}
+
+/// Number of tasks with a budget
+const TASK_COUNT: usize = 9;
+
+impl Task {
+    /// All budgeted tasks, in report order
+    pub const ALL: [Task; TASK_COUNT] = [
+        Self::OtgFs,
+        Self::Usart6,
+        Self::Usart3,
+        Self::DmaRx,
+        Self::DmaTx,
+        Self::RxTimeout,
+        Self::RxToUsb,
+        Self::TxToUart,
+        Self::ErrorDisplay,
+    ];
+
+    /// Worst-case execution budget (microseconds)
+    pub fn budget_us(self) -> u32 {
+        match self {
+            Self::OtgFs => 100,
+            Self::Usart6 => 50,
+            Self::Usart3 => 50,
+            Self::DmaRx => 50,
+            Self::DmaTx => 20,
+            Self::RxTimeout => 50,
+            Self::RxToUsb => 100,
+            Self::TxToUart => 50,
+            Self::ErrorDisplay => 20,
+        }
+    }
+
+    /// Short name used in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OtgFs => "otg_fs",
+            Self::Usart6 => "usart6",
+            Self::Usart3 => "usart3",
+            Self::DmaRx => "dma_rx",
+            Self::DmaTx => "dma_tx",
+            Self::RxTimeout => "rx_timeout",
+            Self::RxToUsb => "rx_to_usb",
+            Self::TxToUart => "tx_to_uart",
+            Self::ErrorDisplay => "error_display",
+        }
+    }
+
+    fn budget_cycles(self) -> u32 {
+        self.budget_us() * (SYSCLK / 1_000_000)
+    }
+}
+
+/// Per-task counters
+struct TaskBudget {
+    runs: AtomicU32,
+    max_cycles: AtomicU32,
+    overruns: AtomicU32,
+    streak: AtomicU32,
+    chronic: AtomicBool,
+}
+
+impl TaskBudget {
+    const fn new() -> Self {
+        Self {
+            runs: AtomicU32::new(0),
+            max_cycles: AtomicU32::new(0),
+            overruns: AtomicU32::new(0),
+            streak: AtomicU32::new(0),
+            chronic: AtomicBool::new(false),
+        }
+    }
+}
+
+static BUDGETS: [TaskBudget; TASK_COUNT] = [
+    TaskBudget::new(),
+    TaskBudget::new(),
+    TaskBudget::new(),
+    TaskBudget::new(),
+    TaskBudget::new(),
+    TaskBudget::new(),
+    TaskBudget::new(),
+    TaskBudget::new(),
+    TaskBudget::new(),
+];
+
+/// Snapshot of one task's counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BudgetStats {
+    /// Measured runs
+    pub runs: u32,
+    /// Longest run (cycles)
+    pub max_cycles: u32,
+    /// Runs over budget
+    pub overruns: u32,
+}
+
+/// Times one run of a task; the measurement ends when the guard is dropped
+pub struct BudgetGuard {
+    task: Task,
+    start: u32,
+    _profile: ProfileGuard,
+}
+
+impl BudgetGuard {
+    /// Starts timing a run
+    ///
+    /// # Arguments
+    /// * `task` - Task being run
+    pub fn start(task: Task) -> Self {
+        Self {
+            task,
+            start: DWT::cycle_count(),
+            _profile: ProfileGuard::start(task.into()),
+        }
+    }
+}
+
+impl Drop for BudgetGuard {
+    fn drop(&mut self) {
+        record(self.task, DWT::cycle_count().wrapping_sub(self.start));
+    }
+}
+
+/// Runs `f` as one measured run of `task`
+pub fn measure<R>(task: Task, f: impl FnOnce() -> R) -> R {
+    let _guard = BudgetGuard::start(task);
+    f()
+}
+
+/// Records one run of `cycles` length
+fn record(task: Task, cycles: u32) {
+    let budget = &BUDGETS[task as usize];
+    budget.runs.fetch_add(1, Ordering::Relaxed);
+    budget.max_cycles.fetch_max(cycles, Ordering::Relaxed);
+
+    if cycles <= task.budget_cycles() {
+        budget.streak.store(0, Ordering::Relaxed);
+        return;
+    }
+
+    budget.overruns.fetch_add(1, Ordering::Relaxed);
+    if budget.streak.fetch_add(1, Ordering::Relaxed) + 1 == BUDGET_CHRONIC_STREAK {
+        budget.chronic.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Returns the counters of a task
+pub fn stats(task: Task) -> BudgetStats {
+    let budget = &BUDGETS[task as usize];
+    BudgetStats {
+        runs: budget.runs.load(Ordering::Relaxed),
+        max_cycles: budget.max_cycles.load(Ordering::Relaxed),
+        overruns: budget.overruns.load(Ordering::Relaxed),
+    }
+}
+
+/// Reports tasks that became chronically late since the last check
+///
+/// # Errors
+/// Returns `DeviceError::BudgetOverrun` if any task reached the overrun streak
+pub fn check() -> Result<(), DeviceError> {
+    let mut chronic = false;
+    for task in Task::ALL {
+        let budget = &BUDGETS[task as usize];
+        if budget.chronic.swap(false, Ordering::Relaxed) {
+            chronic = true;
+
+            #[cfg(feature = "debug")]
+            defmt::warn!(
+                "Task {=str} chronically over its {} us budget",
+                task.name(),
+                task.budget_us()
+            );
+        }
+    }
+
+    if chronic {
+        Err(DeviceError::BudgetOverrun)
+    } else {
+        Ok(())
+    }
+}
+
+/// Writes max run time against budget and overrun count per task
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    write!(out, "Budgets (max/budget us, overruns):")?;
+    for task in Task::ALL {
+        let stats = stats(task);
+        write!(
+            out,
+            " {}={}/{},{}",
+            task.name(),
+            stats.max_cycles / (SYSCLK / 1_000_000),
+            task.budget_us(),
+            stats.overruns
+        )?;
+    }
+    writeln!(out)
+}
@@ -0,0 +1,186 @@
+//! # Clock Health Monitoring
+//!
+//! Cross-checks the SysTick time base (HSE crystal through the PLL) against the
+//! RTC running from the LSE crystal:
+//! - Elapsed time of both clocks is accumulated between periodic checks
+//! - The relative drift is estimated in parts per million
+//! - Drift above `CLOCK_DRIFT_LIMIT_PPM` raises `DeviceError::ClockDrift` once
+//!   per excursion
+//! - The estimate is published for the SYSINFO report
+//! - An RTC running from the LSI fallback is too inaccurate to compare with;
+//!   no estimate is made then
+//!
+//! Resolution improves with uptime: 1 ms SysTick ticks over a 60 s window give
+//! roughly 17 ppm, over an hour below 1 ppm.
+
+use crate::config::{CLOCK_CHECK_INTERVAL_MS, CLOCK_DRIFT_LIMIT_PPM, LSE_STARTUP_TIMEOUT_MS};
+use crate::errors::errors::DeviceError;
+use crate::peripherals::rtc::{self, Rtc, RtcClock, RtcState, RTC_TICKS_PER_SECOND};
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicI32, AtomicU32, AtomicU8, Ordering};
+use rtic_monotonics::systick::prelude::*;
+
+/// Published monitor status
+const STATUS_STARTING: u8 = 0;
+const STATUS_MEASURING: u8 = 1;
+const STATUS_ABSENT: u8 = 2;
+
+static STATUS: AtomicU8 = AtomicU8::new(STATUS_STARTING);
+static DRIFT_PPM: AtomicI32 = AtomicI32::new(0);
+static MEASURED_S: AtomicU32 = AtomicU32::new(0);
+
+/// Paired readings of both clocks
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    mono_ms: u32,
+    rtc_ticks: u32,
+}
+
+/// Drift estimator state
+pub struct ClockHealth {
+    rtc: Rtc,
+    last: Option<Sample>,
+    last_check_ms: u32,
+    mono_total_ms: u64,
+    rtc_total_ticks: u64,
+    over_limit: bool,
+}
+
+impl ClockHealth {
+    /// Creates the monitor
+    ///
+    /// # Arguments
+    /// * `rtc` - RTC with its LSE start-up already initiated
+    pub fn new(rtc: Rtc) -> Self {
+        Self {
+            rtc,
+            last: None,
+            last_check_ms: 0,
+            mono_total_ms: 0,
+            rtc_total_ticks: 0,
+            over_limit: false,
+        }
+    }
+
+    /// Runs one monitor step
+    ///
+    /// Call about once per second; measurements are taken every
+    /// `CLOCK_CHECK_INTERVAL_MS`.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current monotonic time
+    /// * `uptime_ms` - Time since boot, bounds the LSE start-up wait
+    ///
+    /// # Errors
+    /// Returns `DeviceError::ClockDrift` when the drift first exceeds the limit
+    pub fn poll(&mut self, now_ms: u32, uptime_ms: u64) -> Result<(), DeviceError> {
+        match self
+            .rtc
+            .poll_startup(uptime_ms >= LSE_STARTUP_TIMEOUT_MS as u64)
+        {
+            RtcState::Starting => return Ok(()),
+            RtcState::Running if rtc::clock_source() == Some(RtcClock::Lse) => {}
+            RtcState::Running | RtcState::Absent => {
+                STATUS.store(STATUS_ABSENT, Ordering::Relaxed);
+                return Ok(());
+            }
+        }
+
+        let Some(previous) = self.last else {
+            self.last = self.sample();
+            self.last_check_ms = now_ms;
+            STATUS.store(STATUS_MEASURING, Ordering::Relaxed);
+            return Ok(());
+        };
+
+        if now_ms.wrapping_sub(self.last_check_ms) < CLOCK_CHECK_INTERVAL_MS {
+            return Ok(());
+        }
+        self.last_check_ms = now_ms;
+
+        let Some(current) = self.sample() else {
+            return Ok(());
+        };
+
+        let rtc_delta = if current.rtc_ticks >= previous.rtc_ticks {
+            current.rtc_ticks - previous.rtc_ticks
+        } else {
+            current.rtc_ticks + Rtc::ticks_per_day() - previous.rtc_ticks
+        };
+        self.mono_total_ms += current.mono_ms.wrapping_sub(previous.mono_ms) as u64;
+        self.rtc_total_ticks += rtc_delta as u64;
+        self.last = Some(current);
+
+        self.evaluate()
+    }
+
+    /// Reads both clocks back to back
+    fn sample(&self) -> Option<Sample> {
+        cortex_m::interrupt::free(|_| {
+            let rtc_ticks = self.rtc.day_ticks()?;
+            Some(Sample {
+                mono_ms: crate::Mono::now().ticks(),
+                rtc_ticks,
+            })
+        })
+    }
+
+    /// Updates the published estimate and checks the limit
+    fn evaluate(&mut self) -> Result<(), DeviceError> {
+        if self.rtc_total_ticks == 0 {
+            return Ok(());
+        }
+
+        // Both sides in units of ms * ticks/s
+        let mono_scaled = (self.mono_total_ms * RTC_TICKS_PER_SECOND as u64) as i64;
+        let rtc_scaled = (self.rtc_total_ticks * 1_000) as i64;
+        let ppm = (mono_scaled - rtc_scaled) * 1_000_000 / rtc_scaled;
+
+        DRIFT_PPM.store(ppm as i32, Ordering::Relaxed);
+        MEASURED_S.store(
+            (self.rtc_total_ticks / RTC_TICKS_PER_SECOND as u64) as u32,
+            Ordering::Relaxed,
+        );
+
+        #[cfg(feature = "debug")]
+        defmt::debug!("Clock drift HSE vs LSE: {} ppm", ppm);
+
+        let exceeded = ppm.unsigned_abs() > CLOCK_DRIFT_LIMIT_PPM as u64;
+        let newly_exceeded = exceeded && !self.over_limit;
+        self.over_limit = exceeded;
+
+        if newly_exceeded {
+            #[cfg(feature = "debug")]
+            defmt::warn!("Clock drift {} ppm exceeds limit", ppm);
+            return Err(DeviceError::ClockDrift);
+        }
+        Ok(())
+    }
+}
+
+/// Latest drift estimate in ppm (positive: SysTick runs fast against the LSE)
+///
+/// # Returns
+/// `None` until a first measurement interval has completed
+pub fn drift_ppm() -> Option<i32> {
+    (MEASURED_S.load(Ordering::Relaxed) > 0).then(|| DRIFT_PPM.load(Ordering::Relaxed))
+}
+
+/// Writes the clock health line of the SYSINFO report
+///
+/// # Arguments
+/// * `out` - Text sink
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    match STATUS.load(Ordering::Relaxed) {
+        STATUS_ABSENT => writeln!(out, "Clock drift: LSE not present"),
+        _ => match drift_ppm() {
+            Some(ppm) => writeln!(
+                out,
+                "Clock drift: {:+} ppm HSE vs LSE over {} s",
+                ppm,
+                MEASURED_S.load(Ordering::Relaxed)
+            ),
+            None => writeln!(out, "Clock drift: measuring"),
+        },
+    }
+}
@@ -0,0 +1,122 @@
+//! # Calendar Date and Time
+//!
+//! Date and time of the RTC calendar (`peripherals::rtc`), also used for the
+//! error store timestamps and FAT directory entries:
+//! - Years 2000 to 2099, the range of the two BCD year digits of the RTC
+//! - Validated on creation (`new`, `parse`), so every value is a real date
+//! - ISO 8601 `YYYY-MM-DDThh:mm:ss` for display
+//!
+//! Plain calendar arithmetic without register access, so it builds on the
+//! host as well.
+
+use core::fmt;
+
+/// First year of the calendar (RTC DR year 00)
+pub const EPOCH_YEAR: u16 = 2000;
+
+/// Last year the two BCD year digits can hold
+const LAST_YEAR: u16 = 2099;
+
+/// Calendar date and time, years 2000 to 2099
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Creates a validated date and time
+    ///
+    /// # Returns
+    /// `None` for a field out of range or a day past the end of the month
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        let valid = (EPOCH_YEAR..=LAST_YEAR).contains(&year)
+            && (1..=12).contains(&month)
+            && day >= 1
+            && day <= days_in_month(year, month)
+            && hour < 24
+            && minute < 60
+            && second < 60;
+        valid.then_some(Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// Parses `YYYY-MM-DDThh:mm:ss` (a space instead of `T` is accepted)
+    pub fn parse(text: &str) -> Option<Self> {
+        let (date, time) = text.trim().split_once(['T', ' '])?;
+        let mut date = date.split('-');
+        let mut time = time.split(':');
+
+        let year = date.next()?.parse().ok()?;
+        let month = date.next()?.parse().ok()?;
+        let day = date.next()?.parse().ok()?;
+        let hour = time.next()?.parse().ok()?;
+        let minute = time.next()?.parse().ok()?;
+        let second = time.next()?.parse().ok()?;
+        if date.next().is_some() || time.next().is_some() {
+            return None;
+        }
+        Self::new(year, month, day, hour, minute, second)
+    }
+
+    /// FAT directory entry (date, time), two-second resolution
+    pub fn to_fat(&self) -> (u16, u16) {
+        let date =
+            (self.year.saturating_sub(1980) << 9) | ((self.month as u16) << 5) | self.day as u16;
+        let time =
+            ((self.hour as u16) << 11) | ((self.minute as u16) << 5) | (self.second as u16 / 2);
+        (date, time)
+    }
+
+    /// Day of the week as stored by the RTC: 1 = Monday to 7 = Sunday
+    pub fn weekday(&self) -> u8 {
+        // 2000-01-01 was a Saturday
+        ((self.days_since_epoch() + 5) % 7 + 1) as u8
+    }
+
+    fn days_since_epoch(&self) -> u32 {
+        let years: u32 = (EPOCH_YEAR..self.year)
+            .map(|year| if is_leap_year(year) { 366 } else { 365 })
+            .sum();
+        let months: u32 = (1..self.month)
+            .map(|month| days_in_month(self.year, month) as u32)
+            .sum();
+        years + months + self.day as u32 - 1
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    // Every fourth year within 2000..=2099, 2000 included
+    year % 4 == 0
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
@@ -0,0 +1,59 @@
+//! # Unique Device ID
+//!
+//! Access to the 96-bit unique device ID of the STM32F469:
+//! - Read from the device electronic signature (RM0386) at 0x1FFF_7A10,
+//!   programmed by ST and never changed
+//! - Formatted as 24 upper-case hex digits, most significant word first,
+//!   which is the default USB serial number
+//!
+//! The ID is unique per chip but not secret; it names a board, it does not
+//! authenticate it.
+
+use core::fmt::{self, Write};
+use heapless::String;
+
+/// Unique device ID base address
+const UID_BASE: usize = 0x1FFF_7A10;
+
+/// Unique device ID length in bytes
+pub const UID_LEN: usize = 12;
+
+/// Length of the ID in hex digits
+pub const HEX_LEN: usize = 2 * UID_LEN;
+
+/// Reads the unique device ID
+///
+/// # Returns
+/// The three ID words in memory order (offsets 0, 4, 8), little-endian
+pub fn read() -> [u8; UID_LEN] {
+    let mut uid = [0u8; UID_LEN];
+    for (i, byte) in uid.iter_mut().enumerate() {
+        // SAFETY: The signature area is read-only system memory, always mapped
+        *byte = unsafe { core::ptr::read_volatile((UID_BASE + i) as *const u8) };
+    }
+    uid
+}
+
+/// Writes the ID as hex digits, most significant word first
+pub fn write_hex<W: Write>(out: &mut W) -> fmt::Result {
+    let uid = read();
+    for word in uid.chunks_exact(4).rev() {
+        write!(
+            out,
+            "{:08X}",
+            u32::from_le_bytes([word[0], word[1], word[2], word[3]])
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the ID as hex text
+///
+/// Used at init for the default USB serial number. `N` should be at least
+/// `HEX_LEN`; a shorter string holds only the leading words.
+pub fn hex<const N: usize>() -> String<N> {
+    let mut text = String::new();
+    // Stops at the first word that does not fit
+    let _ = write_hex(&mut text);
+    text
+}
@@ -0,0 +1,245 @@
+//! # Die Temperature and Supply Monitoring
+//!
+//! Checks the internal ADC channels of the latest sample against limits:
+//! - The sampler always scans VREFINT and the temperature sensor, whatever
+//!   channels the stream selects
+//! - A die temperature or VDDA outside its limits raises
+//!   `DeviceError::TemperatureLimit` or `DeviceError::SupplyLimit` once per
+//!   excursion; the alarm clears once the reading is back inside the limits
+//!   by the hysteresis margin
+//! - The limits are part of the persistent settings (`save` keeps them)
+//! - The latest readings are published for the `stats` and `health` reports
+//!
+//! Built with the `adc` feature.
+
+use crate::config::{
+    HEALTH_TEMP_HYSTERESIS_C, HEALTH_TEMP_MAX_C, HEALTH_TEMP_MIN_C, HEALTH_VDD_HYSTERESIS_MV,
+    HEALTH_VDD_MAX_MV, HEALTH_VDD_MIN_MV,
+};
+use crate::errors::errors::DeviceError;
+use crate::peripherals::adc::AdcSample;
+use core::cell::Cell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+
+/// Encoded length of `HealthLimits`
+pub const LIMITS_LEN: usize = 8;
+
+/// Alarm thresholds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HealthLimits {
+    /// Lowest acceptable die temperature (degrees C)
+    pub temp_min_c: i16,
+    /// Highest acceptable die temperature (degrees C)
+    pub temp_max_c: i16,
+    /// Lowest acceptable VDDA (millivolts)
+    pub vdd_min_mv: u16,
+    /// Highest acceptable VDDA (millivolts)
+    pub vdd_max_mv: u16,
+}
+
+impl Default for HealthLimits {
+    fn default() -> Self {
+        Self {
+            temp_min_c: HEALTH_TEMP_MIN_C,
+            temp_max_c: HEALTH_TEMP_MAX_C,
+            vdd_min_mv: HEALTH_VDD_MIN_MV,
+            vdd_max_mv: HEALTH_VDD_MAX_MV,
+        }
+    }
+}
+
+impl HealthLimits {
+    /// Checks that each range is wider than twice its hysteresis
+    pub fn is_valid(&self) -> bool {
+        i32::from(self.temp_max_c) - i32::from(self.temp_min_c) > 2 * HEALTH_TEMP_HYSTERESIS_C
+            && u32::from(self.vdd_max_mv)
+                > u32::from(self.vdd_min_mv) + 2 * HEALTH_VDD_HYSTERESIS_MV
+    }
+
+    /// Little-endian encoding for the settings store
+    pub fn to_bytes(&self) -> [u8; LIMITS_LEN] {
+        let mut bytes = [0u8; LIMITS_LEN];
+        bytes[0..2].copy_from_slice(&self.temp_min_c.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.temp_max_c.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.vdd_min_mv.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.vdd_max_mv.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes `to_bytes`; `None` for a wrong length or invalid limits
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; LIMITS_LEN] = bytes.try_into().ok()?;
+        let limits = Self {
+            temp_min_c: i16::from_le_bytes([bytes[0], bytes[1]]),
+            temp_max_c: i16::from_le_bytes([bytes[2], bytes[3]]),
+            vdd_min_mv: u16::from_le_bytes([bytes[4], bytes[5]]),
+            vdd_max_mv: u16::from_le_bytes([bytes[6], bytes[7]]),
+        };
+        limits.is_valid().then_some(limits)
+    }
+}
+
+static LIMITS: Mutex<Cell<Option<HealthLimits>>> = Mutex::new(Cell::new(None));
+
+/// Latest readings and the time of the sample they came from
+static TEMPERATURE_CENTI: AtomicI32 = AtomicI32::new(0);
+static VDD_MV: AtomicU32 = AtomicU32::new(0);
+static CHECKED_MS: AtomicU32 = AtomicU32::new(0);
+static MEASURED: AtomicBool = AtomicBool::new(false);
+
+/// Excursion in progress
+static TEMP_ALARM: AtomicBool = AtomicBool::new(false);
+static VDD_ALARM: AtomicBool = AtomicBool::new(false);
+
+/// Excursions since boot
+static TEMP_EXCURSIONS: AtomicU32 = AtomicU32::new(0);
+static VDD_EXCURSIONS: AtomicU32 = AtomicU32::new(0);
+
+/// Limits in effect
+pub fn limits() -> HealthLimits {
+    interrupt::free(|cs| LIMITS.borrow(cs).get()).unwrap_or_default()
+}
+
+/// Sets the limits; alarms are re-evaluated with the next sample
+///
+/// Kept in RAM until the settings are saved.
+///
+/// # Returns
+/// `false` if the limits are invalid
+pub fn set_limits(limits: HealthLimits) -> bool {
+    if !limits.is_valid() {
+        return false;
+    }
+    interrupt::free(|cs| LIMITS.borrow(cs).set(Some(limits)));
+    true
+}
+
+/// Checks a sample against the limits
+///
+/// A sample that was already checked, or lacks the internal channels, is
+/// skipped. When both readings leave their limits at once, the supply alarm
+/// is raised first and the temperature alarm at the next check.
+///
+/// # Errors
+/// `DeviceError::SupplyLimit` or `DeviceError::TemperatureLimit` when a
+/// reading first leaves its limits
+pub fn check(sample: &AdcSample) -> Result<(), DeviceError> {
+    let checked = CHECKED_MS.load(Ordering::Relaxed) == sample.timestamp_ms;
+    if checked && MEASURED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let (Some(vdd_mv), Some(centi)) = (sample.vdda_mv(), sample.temperature_centi()) else {
+        return Ok(());
+    };
+    TEMPERATURE_CENTI.store(centi, Ordering::Relaxed);
+    VDD_MV.store(vdd_mv, Ordering::Relaxed);
+    CHECKED_MS.store(sample.timestamp_ms, Ordering::Relaxed);
+    MEASURED.store(true, Ordering::Relaxed);
+
+    let limits = limits();
+    let vdd = (
+        i32::from(limits.vdd_min_mv),
+        i32::from(limits.vdd_max_mv),
+        HEALTH_VDD_HYSTERESIS_MV as i32,
+    );
+    if update_alarm(&VDD_ALARM, vdd_mv as i32, vdd) {
+        VDD_EXCURSIONS.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "debug")]
+        defmt::warn!("VDDA {} mV outside its limits", vdd_mv);
+        return Err(DeviceError::SupplyLimit);
+    }
+
+    let temperature = (
+        i32::from(limits.temp_min_c) * 100,
+        i32::from(limits.temp_max_c) * 100,
+        HEALTH_TEMP_HYSTERESIS_C * 100,
+    );
+    if update_alarm(&TEMP_ALARM, centi, temperature) {
+        TEMP_EXCURSIONS.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "debug")]
+        defmt::warn!("Die temperature {} cC outside its limits", centi);
+        return Err(DeviceError::TemperatureLimit);
+    }
+    Ok(())
+}
+
+/// Updates one alarm flag
+///
+/// # Arguments
+/// * `alarm` - Flag of the reading
+/// * `value` - Reading
+/// * `(min, max, hysteresis)` - Limits in the unit of the reading
+///
+/// # Returns
+/// `true` when the reading has just left its limits
+fn update_alarm(alarm: &AtomicBool, value: i32, (min, max, hysteresis): (i32, i32, i32)) -> bool {
+    if alarm.load(Ordering::Relaxed) {
+        if value >= min + hysteresis && value <= max - hysteresis {
+            alarm.store(false, Ordering::Relaxed);
+        }
+        false
+    } else if value < min || value > max {
+        alarm.store(true, Ordering::Relaxed);
+        true
+    } else {
+        false
+    }
+}
+
+/// Latest readings: die temperature (hundredths of a degree C) and VDDA
+/// (millivolts)
+///
+/// # Returns
+/// `None` until a sample with the internal channels was checked
+pub fn readings() -> Option<(i32, u32)> {
+    MEASURED.load(Ordering::Relaxed).then(|| {
+        (
+            TEMPERATURE_CENTI.load(Ordering::Relaxed),
+            VDD_MV.load(Ordering::Relaxed),
+        )
+    })
+}
+
+/// Writes the latest readings on one line, for the `stats` report
+pub fn write_readings<W: Write>(out: &mut W) -> fmt::Result {
+    let Some((centi, vdd_mv)) = readings() else {
+        return writeln!(out, "Health: no reading yet");
+    };
+    writeln!(
+        out,
+        "Health: die {}{}.{:02} C{}, VDDA {} mV{}",
+        if centi < 0 { "-" } else { "" },
+        centi.unsigned_abs() / 100,
+        centi.unsigned_abs() % 100,
+        if TEMP_ALARM.load(Ordering::Relaxed) {
+            " (alarm)"
+        } else {
+            ""
+        },
+        vdd_mv,
+        if VDD_ALARM.load(Ordering::Relaxed) {
+            " (alarm)"
+        } else {
+            ""
+        }
+    )
+}
+
+/// Writes the readings, limits and excursion counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    write_readings(out)?;
+    let limits = limits();
+    writeln!(
+        out,
+        "  limits: die {} to {} C, VDDA {} to {} mV",
+        limits.temp_min_c, limits.temp_max_c, limits.vdd_min_mv, limits.vdd_max_mv
+    )?;
+    writeln!(
+        out,
+        "  excursions: temperature {}, supply {}",
+        TEMP_EXCURSIONS.load(Ordering::Relaxed),
+        VDD_EXCURSIONS.load(Ordering::Relaxed)
+    )
+}
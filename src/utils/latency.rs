@@ -0,0 +1,129 @@
+//! # Interrupt Latency Measurement
+//!
+//! Measures real interrupt entry latency with the DWT cycle counter:
+//! - A software-triggered EXTI line 3 interrupt is pended via SWIER
+//! - The trigger time and the handler entry time are both taken from CYCCNT
+//! - Repeated samples are reduced to min/avg/max
+//!
+//! ## Safety Considerations
+//! - EXTI line 3 is reserved for this probe; no GPIO may be routed to it
+//! - Requires the DWT cycle counter to be enabled during init
+
+use crate::config::SYSCLK;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::DWT;
+use stm32f4xx_hal::pac::EXTI;
+
+/// EXTI line used for the probe
+const PROBE_LINE: u32 = 1 << 3;
+
+/// Cycle count captured just before pending the interrupt
+static TRIGGER_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+/// Latency of the most recent sample in cycles
+static SAMPLE_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+/// Set by the handler when a new sample is available
+static SAMPLE_READY: AtomicBool = AtomicBool::new(false);
+
+/// Result of the most recent measurement run
+static LAST_REPORT: Mutex<RefCell<Option<LatencyStats>>> = Mutex::new(RefCell::new(None));
+
+/// Accumulated latency statistics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyStats {
+    pub samples: u32,
+    pub min_cycles: u32,
+    pub max_cycles: u32,
+    pub total_cycles: u64,
+}
+
+impl LatencyStats {
+    /// Adds one sample
+    pub fn add(&mut self, cycles: u32) {
+        if self.samples == 0 || cycles < self.min_cycles {
+            self.min_cycles = cycles;
+        }
+        self.max_cycles = self.max_cycles.max(cycles);
+        self.total_cycles += cycles as u64;
+        self.samples += 1;
+    }
+
+    /// Average latency in cycles
+    pub fn avg_cycles(&self) -> u32 {
+        match self.samples {
+            0 => 0,
+            n => (self.total_cycles / n as u64) as u32,
+        }
+    }
+
+    /// Writes a min/avg/max summary in cycles and nanoseconds
+    pub fn write_report<W: Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(
+            out,
+            "IRQ latency over {} samples: min {} / avg {} / max {} cycles ({} / {} / {} ns)",
+            self.samples,
+            self.min_cycles,
+            self.avg_cycles(),
+            self.max_cycles,
+            cycles_to_ns(self.min_cycles),
+            cycles_to_ns(self.avg_cycles()),
+            cycles_to_ns(self.max_cycles)
+        )
+    }
+}
+
+/// Converts CPU cycles to nanoseconds at SYSCLK
+pub fn cycles_to_ns(cycles: u32) -> u32 {
+    ((cycles as u64 * 1_000_000_000) / SYSCLK as u64) as u32
+}
+
+/// Unmasks the probe line in the EXTI controller
+pub fn init() {
+    // SAFETY: Line 3 is reserved for the probe; only its IMR bit is touched
+    let exti = unsafe { &*EXTI::ptr() };
+    exti.imr().modify(|r, w| unsafe { w.bits(r.bits() | PROBE_LINE) });
+}
+
+/// Pends the probe interrupt and records the trigger time
+pub fn trigger() {
+    SAMPLE_READY.store(false, Ordering::SeqCst);
+    TRIGGER_CYCLES.store(DWT::cycle_count(), Ordering::SeqCst);
+
+    // SAFETY: Write-one-to-set register; only the probe line bit is set
+    let exti = unsafe { &*EXTI::ptr() };
+    exti.swier().write(|w| unsafe { w.bits(PROBE_LINE) });
+}
+
+/// Records the handler entry time; call first thing in the EXTI3 handler
+pub fn on_interrupt() {
+    let entry = DWT::cycle_count();
+
+    // SAFETY: Write-one-to-clear register; only the probe line bit is cleared
+    let exti = unsafe { &*EXTI::ptr() };
+    exti.pr().write(|w| unsafe { w.bits(PROBE_LINE) });
+
+    let cycles = entry.wrapping_sub(TRIGGER_CYCLES.load(Ordering::SeqCst));
+    SAMPLE_CYCLES.store(cycles, Ordering::SeqCst);
+    SAMPLE_READY.store(true, Ordering::SeqCst);
+}
+
+/// Takes the latest sample if the handler has run since the last trigger
+pub fn take_sample() -> Option<u32> {
+    SAMPLE_READY
+        .swap(false, Ordering::SeqCst)
+        .then(|| SAMPLE_CYCLES.load(Ordering::SeqCst))
+}
+
+/// Stores the result of a completed measurement run
+pub fn store_report(stats: LatencyStats) {
+    cortex_m::interrupt::free(|cs| *LAST_REPORT.borrow(cs).borrow_mut() = Some(stats));
+}
+
+/// Returns the result of the most recent measurement run
+pub fn last_report() -> Option<LatencyStats> {
+    cortex_m::interrupt::free(|cs| *LAST_REPORT.borrow(cs).borrow())
+}
@@ -0,0 +1,136 @@
+//! # Lock Hold Time Measurement
+//!
+//! Measures how long the data-path handlers keep RTIC resources locked:
+//! - Every resource is locked through [`lock`], one resource per critical section
+//! - The hold time of each critical section is taken from the DWT cycle counter
+//! - [`span`] measures a whole operation, i.e. what a nested lock pyramid would hold
+//!
+//! Comparing the maximum hold time with the maximum span of an operation shows
+//! how much shorter the critical sections became. Measurement is compiled in
+//! with the `lock-stats` feature only; otherwise both helpers are plain calls.
+//!
+//! ## Safety Considerations
+//! - Requires the DWT cycle counter to be enabled during init
+//! - Spans include preemption by higher priority tasks, holds do not exceed the
+//!   critical section itself
+
+#[cfg(feature = "lock-stats")]
+use core::fmt::{self, Write};
+#[cfg(feature = "lock-stats")]
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(feature = "lock-stats")]
+use cortex_m::peripheral::DWT;
+use rtic_core::Mutex;
+
+/// Data-path operations whose locks are measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockSite {
+    /// UART DMA RX into the RX ring buffer
+    DmaRx,
+    /// TX ring buffer into UART DMA TX
+    DmaTx,
+    /// USB OUT packets into the TX ring buffer
+    UsbRx,
+    /// RX ring buffer into USB IN packets
+    UsbTx,
+}
+
+impl LockSite {
+    /// All measured sites, in report order
+    pub const ALL: [LockSite; 4] = [Self::DmaRx, Self::DmaTx, Self::UsbRx, Self::UsbTx];
+
+    /// Human-readable site name
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DmaRx => "dma_rx",
+            Self::DmaTx => "dma_tx",
+            Self::UsbRx => "usb_rx",
+            Self::UsbTx => "usb_tx",
+        }
+    }
+}
+
+/// Longest single critical section per site (cycles)
+#[cfg(feature = "lock-stats")]
+static HOLD_MAX: [AtomicU32; 4] = [
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+];
+
+/// Longest complete operation per site (cycles)
+#[cfg(feature = "lock-stats")]
+static SPAN_MAX: [AtomicU32; 4] = [
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+];
+
+/// Runs `f` and returns its result together with the elapsed cycles
+#[cfg(feature = "lock-stats")]
+fn timed<R>(f: impl FnOnce() -> R) -> (R, u32) {
+    let start = DWT::cycle_count();
+    let result = f();
+    (result, DWT::cycle_count().wrapping_sub(start))
+}
+
+/// Locks one RTIC resource, recording the hold time for `site`
+///
+/// # Arguments
+/// * `site` - Operation the critical section belongs to
+/// * `mutex` - RTIC resource proxy
+/// * `f` - Critical section; keep it to the one resource
+pub fn lock<M: Mutex, R>(site: LockSite, mutex: &mut M, f: impl FnOnce(&mut M::T) -> R) -> R {
+    #[cfg(feature = "lock-stats")]
+    {
+        mutex.lock(|value| {
+            let (result, cycles) = timed(|| f(value));
+            HOLD_MAX[site as usize].fetch_max(cycles, Ordering::Relaxed);
+            result
+        })
+    }
+
+    #[cfg(not(feature = "lock-stats"))]
+    {
+        let _ = site;
+        mutex.lock(f)
+    }
+}
+
+/// Runs a complete data-path operation, recording its duration for `site`
+///
+/// # Arguments
+/// * `site` - Operation being measured
+/// * `f` - The operation, taking its locks through [`lock`]
+pub fn span<R>(site: LockSite, f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "lock-stats")]
+    {
+        let (result, cycles) = timed(f);
+        SPAN_MAX[site as usize].fetch_max(cycles, Ordering::Relaxed);
+        result
+    }
+
+    #[cfg(not(feature = "lock-stats"))]
+    {
+        let _ = site;
+        f()
+    }
+}
+
+/// Writes max hold and max span per site in cycles
+#[cfg(feature = "lock-stats")]
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    write!(out, "Lock hold/span (cycles):")?;
+    for site in LockSite::ALL {
+        write!(
+            out,
+            " {}={}/{}",
+            site.name(),
+            HOLD_MAX[site as usize].load(Ordering::Relaxed),
+            SPAN_MAX[site as usize].load(Ordering::Relaxed)
+        )?;
+    }
+    writeln!(out)
+}
@@ -0,0 +1,392 @@
+//! # Log Volume
+//!
+//! Read-only FAT12 volume with the device reports as text files, exposed by
+//! the USB mass storage function so support staff can copy them without any
+//! tooling:
+//! - `ERRORS.TXT`: pending error records and the persistent error log
+//! - `STATS.TXT`: bridge traffic counters
+//! - `SYSINFO.TXT`: the full system report
+//!
+//! Only the file contents live in RAM, rendered into `MSC_IMAGE_LEN` bytes
+//! by `refresh`. The boot sector, FATs and root directory are generated on
+//! the fly for each read, from the file table of the last rendering.
+//!
+//! Rendering formats every report, which is too slow for the USB interrupt:
+//! the function only requests it (on bus reset), and the periodic task calls
+//! `refresh_if_requested`. The medium reads as not present until the first
+//! rendering and as changed after every further one.
+//!
+//! ## Layout
+//! 512-byte sectors, one sector per cluster, `VOLUME_SECTORS` in total:
+//! boot sector, two one-sector FATs, one root directory sector, then data.
+//! Files are stored in consecutive clusters in table order.
+
+use crate::config::MSC_IMAGE_LEN;
+use crate::peripherals::block_device::{Block, BlockDevice, BlockError, BLOCK_SIZE};
+use crate::peripherals::rtc::{self, DateTime};
+use crate::task_handlers::console::CrLf;
+use crate::task_handlers::{error_handlers, error_log};
+use crate::utils::statistics;
+use crate::utils::sysinfo::write_sysinfo;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use cortex_m::interrupt::{self, Mutex};
+
+/// Total sectors of the volume (64 KB)
+pub const VOLUME_SECTORS: u32 = 128;
+
+/// Sector of the first FAT
+const FAT_SECTOR: u32 = 1;
+/// Number of FAT copies
+const FAT_COUNT: u32 = 2;
+/// Sector of the root directory
+const ROOT_SECTOR: u32 = FAT_SECTOR + FAT_COUNT;
+/// Root directory entries, one sector
+const ROOT_ENTRIES: u16 = (BLOCK_SIZE / DIR_ENTRY_LEN) as u16;
+/// Sector of cluster 2, the first data cluster
+const DATA_SECTOR: u32 = ROOT_SECTOR + 1;
+
+/// Bytes per directory entry
+const DIR_ENTRY_LEN: usize = 32;
+/// FAT12 end-of-chain marker
+const END_OF_CHAIN: u16 = 0xFFF;
+/// Fixed disk media descriptor
+const MEDIA_DESCRIPTOR: u8 = 0xF8;
+
+/// Directory entry attributes
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+
+/// Volume label, 11 characters
+const VOLUME_LABEL: &[u8; 11] = b"BRIDGE LOGS";
+
+/// Timestamp used while the RTC is not running
+const DEFAULT_TIME: DateTime = DateTime {
+    year: 2025,
+    month: 1,
+    day: 1,
+    hour: 0,
+    minute: 0,
+    second: 0,
+};
+
+/// Files on the volume, in 8.3 directory form
+const FILE_NAMES: [&[u8; 11]; 3] = [b"ERRORS  TXT", b"STATS   TXT", b"SYSINFO TXT"];
+
+/// One rendered file
+#[derive(Debug, Clone, Copy, Default)]
+struct FileEntry {
+    /// Offset into the image buffer, a multiple of `BLOCK_SIZE`
+    offset: usize,
+    /// Length in bytes
+    len: usize,
+}
+
+impl FileEntry {
+    fn clusters(&self) -> usize {
+        self.len.div_ceil(BLOCK_SIZE)
+    }
+}
+
+/// Rendered file contents
+struct Image {
+    data: [u8; MSC_IMAGE_LEN],
+    files: [FileEntry; FILE_NAMES.len()],
+    /// FAT date and time of the rendering
+    stamp: (u16, u16),
+}
+
+static IMAGE: Mutex<RefCell<Image>> = Mutex::new(RefCell::new(Image {
+    data: [0; MSC_IMAGE_LEN],
+    files: [FileEntry { offset: 0, len: 0 }; FILE_NAMES.len()],
+    stamp: (0, 0),
+}));
+
+/// Number of renderings, 0 before the first
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// Set by the USB function, taken by the periodic task
+static REFRESH_REQUESTED: AtomicBool = AtomicBool::new(true);
+
+/// Set while `refresh` rewrites the image
+static RENDERING: AtomicBool = AtomicBool::new(false);
+
+/// Writer into the image buffer from `offset`, stopping at its end
+///
+/// Each piece is copied in its own short critical section, so rendering a
+/// report does not hold off the bridge interrupts.
+struct ImageWriter {
+    offset: usize,
+    len: usize,
+}
+
+impl Write for ImageWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let at = self.offset + self.len;
+        let count = s.len().min(MSC_IMAGE_LEN - at);
+        interrupt::free(|cs| {
+            IMAGE.borrow(cs).borrow_mut().data[at..at + count]
+                .copy_from_slice(&s.as_bytes()[..count]);
+        });
+        self.len += count;
+        if count < s.len() {
+            Err(fmt::Error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Asks for the files to be rendered again
+///
+/// Cheap; safe to call from the USB interrupt.
+pub fn request_refresh() {
+    REFRESH_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Renders the files if a refresh was requested
+///
+/// Called from the periodic task.
+pub fn refresh_if_requested() {
+    if REFRESH_REQUESTED.swap(false, Ordering::Relaxed) {
+        refresh();
+    }
+}
+
+/// Renders all files into the image
+///
+/// The medium reads as not ready meanwhile; the new generation is published
+/// once all files are complete.
+pub fn refresh() {
+    RENDERING.store(true, Ordering::Release);
+
+    let mut files = [FileEntry::default(); FILE_NAMES.len()];
+    let mut offset = 0;
+    for (index, file) in files.iter_mut().enumerate() {
+        let mut writer = ImageWriter { offset, len: 0 };
+        if render_file(index, &mut CrLf(&mut writer)).is_err() && writer.len > 0 {
+            // Out of space: mark the cut at the end of the file
+            let marker = "\r\n[truncated]\r\n";
+            writer.len = writer.len.saturating_sub(marker.len());
+            let _ = writer.write_str(marker);
+        }
+
+        *file = FileEntry {
+            offset,
+            len: writer.len,
+        };
+        offset = (offset + writer.len)
+            .next_multiple_of(BLOCK_SIZE)
+            .min(MSC_IMAGE_LEN);
+    }
+
+    let stamp = rtc::now().unwrap_or(DEFAULT_TIME).to_fat();
+    interrupt::free(|cs| {
+        let mut image = IMAGE.borrow(cs).borrow_mut();
+        image.files = files;
+        image.stamp = stamp;
+    });
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    RENDERING.store(false, Ordering::Release);
+
+    #[cfg(feature = "debug")]
+    defmt::info!("Log volume rendered, {} bytes", offset);
+}
+
+fn render_file<W: Write>(index: usize, out: &mut W) -> fmt::Result {
+    match index {
+        0 => {
+            writeln!(out, "Pending errors")?;
+            error_handlers::write_error_dump(out)?;
+            writeln!(out)?;
+            writeln!(out, "Persistent error log")?;
+            error_log::write_log(out)
+        }
+        1 => statistics::write_report(out),
+        _ => write_sysinfo(out),
+    }
+}
+
+/// Writes the rendering state and the file sizes
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let files = interrupt::free(|cs| IMAGE.borrow(cs).borrow().files);
+    let state = if RENDERING.load(Ordering::Acquire) {
+        "rendering"
+    } else if GENERATION.load(Ordering::Relaxed) == 0 {
+        "not rendered"
+    } else {
+        "ready"
+    };
+    writeln!(
+        out,
+        "Log volume: {}, generation {}, {} of {} bytes used",
+        state,
+        GENERATION.load(Ordering::Relaxed),
+        files
+            .iter()
+            .map(|file| file.clusters() * BLOCK_SIZE)
+            .sum::<usize>(),
+        MSC_IMAGE_LEN
+    )?;
+    for (file, name) in files.iter().zip(FILE_NAMES) {
+        let (stem, ext) = name.split_at(8);
+        // 8.3 names are ASCII
+        let stem = core::str::from_utf8(stem).unwrap_or("?").trim_end();
+        let ext = core::str::from_utf8(ext).unwrap_or("?");
+        writeln!(out, "  {}.{} {} bytes", stem, ext, file.len)?;
+    }
+    Ok(())
+}
+
+/// The log volume as a read-only block device
+#[derive(Debug, Default)]
+pub struct LogVolume;
+
+impl LogVolume {
+    /// Creates the block device view of the volume
+    pub const fn new() -> Self {
+        Self
+    }
+}
+
+impl BlockDevice for LogVolume {
+    fn block_count(&self) -> u32 {
+        VOLUME_SECTORS
+    }
+
+    fn is_ready(&self) -> bool {
+        GENERATION.load(Ordering::Relaxed) > 0 && !RENDERING.load(Ordering::Acquire)
+    }
+
+    fn generation(&self) -> u32 {
+        GENERATION.load(Ordering::Relaxed)
+    }
+
+    fn refresh(&mut self) {
+        request_refresh();
+    }
+
+    fn read_block(&mut self, lba: u32, block: &mut Block) -> Result<(), BlockError> {
+        if lba >= VOLUME_SECTORS {
+            return Err(BlockError::OutOfRange);
+        }
+        if !self.is_ready() {
+            return Err(BlockError::NotReady);
+        }
+
+        block.fill(0);
+        interrupt::free(|cs| {
+            let image = IMAGE.borrow(cs).borrow();
+            match lba {
+                0 => write_boot_sector(block),
+                l if (FAT_SECTOR..ROOT_SECTOR).contains(&l) => write_fat(&image.files, block),
+                ROOT_SECTOR => write_root_dir(&image.files, image.stamp, block),
+                l => write_data(&image, l - DATA_SECTOR, block),
+            }
+        });
+        Ok(())
+    }
+}
+
+fn write_boot_sector(block: &mut Block) {
+    let fat_sectors = 1u16;
+    block[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    block[3..11].copy_from_slice(b"MSDOS5.0");
+    block[11..13].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+    block[13] = 1; // Sectors per cluster
+    block[14..16].copy_from_slice(&(FAT_SECTOR as u16).to_le_bytes());
+    block[16] = FAT_COUNT as u8;
+    block[17..19].copy_from_slice(&ROOT_ENTRIES.to_le_bytes());
+    block[19..21].copy_from_slice(&(VOLUME_SECTORS as u16).to_le_bytes());
+    block[21] = MEDIA_DESCRIPTOR;
+    block[22..24].copy_from_slice(&fat_sectors.to_le_bytes());
+    block[24..26].copy_from_slice(&1u16.to_le_bytes()); // Sectors per track
+    block[26..28].copy_from_slice(&1u16.to_le_bytes()); // Heads
+    block[36] = 0x80; // Drive number
+    block[38] = 0x29; // Extended boot signature
+    block[39..43].copy_from_slice(&0x4C4F_4753u32.to_le_bytes()); // Volume ID
+    block[43..54].copy_from_slice(VOLUME_LABEL);
+    block[54..62].copy_from_slice(b"FAT12   ");
+    block[510] = 0x55;
+    block[511] = 0xAA;
+}
+
+/// Sets FAT12 entry `cluster` in a one-sector FAT
+fn set_fat_entry(fat: &mut Block, cluster: usize, value: u16) {
+    let at = cluster * 3 / 2;
+    if at + 1 >= fat.len() {
+        return;
+    }
+    if cluster % 2 == 0 {
+        fat[at] = value as u8;
+        fat[at + 1] = (fat[at + 1] & 0xF0) | ((value >> 8) as u8 & 0x0F);
+    } else {
+        fat[at] = (fat[at] & 0x0F) | ((value << 4) as u8);
+        fat[at + 1] = (value >> 4) as u8;
+    }
+}
+
+fn write_fat(files: &[FileEntry], block: &mut Block) {
+    set_fat_entry(block, 0, 0xF00 | MEDIA_DESCRIPTOR as u16);
+    set_fat_entry(block, 1, END_OF_CHAIN);
+
+    for file in files {
+        let first = first_cluster(file);
+        let clusters = file.clusters();
+        for i in 0..clusters {
+            let next = if i + 1 == clusters {
+                END_OF_CHAIN
+            } else {
+                (first + i + 1) as u16
+            };
+            set_fat_entry(block, first + i, next);
+        }
+    }
+}
+
+/// First cluster of a file; files follow each other from cluster 2
+fn first_cluster(file: &FileEntry) -> usize {
+    2 + file.offset / BLOCK_SIZE
+}
+
+fn write_root_dir(files: &[FileEntry], stamp: (u16, u16), block: &mut Block) {
+    let (date, time) = stamp;
+    let mut entries = block.chunks_exact_mut(DIR_ENTRY_LEN);
+
+    // Cannot fail, the sector holds `ROOT_ENTRIES` entries
+    let Some(label) = entries.next() else {
+        return;
+    };
+    label[0..11].copy_from_slice(VOLUME_LABEL);
+    label[11] = ATTR_VOLUME_ID;
+    label[22..24].copy_from_slice(&time.to_le_bytes());
+    label[24..26].copy_from_slice(&date.to_le_bytes());
+
+    for ((file, name), entry) in files.iter().zip(FILE_NAMES).zip(entries) {
+        entry[0..11].copy_from_slice(name);
+        entry[11] = ATTR_READ_ONLY;
+        // Created, written and accessed at the rendering
+        entry[14..16].copy_from_slice(&time.to_le_bytes());
+        entry[16..18].copy_from_slice(&date.to_le_bytes());
+        entry[18..20].copy_from_slice(&date.to_le_bytes());
+        entry[22..24].copy_from_slice(&time.to_le_bytes());
+        entry[24..26].copy_from_slice(&date.to_le_bytes());
+        let cluster = if file.len == 0 {
+            0
+        } else {
+            first_cluster(file) as u16
+        };
+        entry[26..28].copy_from_slice(&cluster.to_le_bytes());
+        entry[28..32].copy_from_slice(&(file.len as u32).to_le_bytes());
+    }
+}
+
+fn write_data(image: &Image, sector: u32, block: &mut Block) {
+    let start = sector as usize * BLOCK_SIZE;
+    if start >= image.data.len() {
+        return;
+    }
+    let end = (start + BLOCK_SIZE).min(image.data.len());
+    block[..end - start].copy_from_slice(&image.data[start..end]);
+}
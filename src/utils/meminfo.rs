@@ -0,0 +1,106 @@
+//! # Memory Usage Reporting
+//!
+//! Keeps an inventory of statically allocated buffers and estimates remaining
+//! RAM from linker symbols, so users enabling optional subsystems can see what
+//! still fits:
+//! - Owners register their buffers once during init
+//! - Static RAM usage (`.data` + `.bss`) taken from cortex-m-rt symbols
+//! - Free RAM estimated as the gap between the end of `.bss` and the stack pointer
+
+use crate::config::MEMINFO_MAX_ENTRIES;
+use core::cell::RefCell;
+use core::fmt::{self, Write};
+use cortex_m::interrupt::{self, Mutex};
+use heapless::Vec;
+
+/// RAM start address (see `memory.x`)
+const RAM_ORIGIN: usize = 0x2000_0000;
+
+extern "C" {
+    /// End of `.bss`, start of the unused region (cortex-m-rt)
+    static __sheap: u32;
+    /// Initial stack pointer, top of RAM (memory.x)
+    static _stack_start: u32;
+}
+
+/// Single registered buffer
+#[derive(Debug, Clone, Copy)]
+pub struct BufferInfo {
+    /// Subsystem owning the buffer
+    pub owner: &'static str,
+    /// Size in bytes
+    pub size: usize,
+}
+
+/// RAM usage summary
+#[derive(Debug, Clone, Copy)]
+pub struct RamUsage {
+    /// Bytes used by `.data` and `.bss`
+    pub static_bytes: usize,
+    /// Bytes between end of `.bss` and the current stack pointer
+    pub free_bytes: usize,
+    /// Bytes of stack in use at the time of the call
+    pub stack_bytes: usize,
+    /// Total RAM size
+    pub total_bytes: usize,
+}
+
+/// Registered buffer inventory
+static INVENTORY: Mutex<RefCell<Vec<BufferInfo, MEMINFO_MAX_ENTRIES>>> =
+    Mutex::new(RefCell::new(Vec::new()));
+
+/// Registers a statically allocated buffer
+///
+/// # Arguments
+/// * `owner` - Short owner name shown in reports
+/// * `size` - Buffer size in bytes
+///
+/// Entries beyond `MEMINFO_MAX_ENTRIES` are silently ignored.
+pub fn register(owner: &'static str, size: usize) {
+    interrupt::free(|cs| {
+        if INVENTORY.borrow(cs).borrow_mut().push(BufferInfo { owner, size }).is_err() {
+            #[cfg(feature = "debug")]
+            defmt::warn!("meminfo inventory full, {=str} not recorded", owner);
+        }
+    });
+}
+
+/// Total bytes of all registered buffers
+pub fn registered_bytes() -> usize {
+    interrupt::free(|cs| INVENTORY.borrow(cs).borrow().iter().map(|b| b.size).sum())
+}
+
+/// Computes RAM usage from linker symbols and the current stack pointer
+pub fn ram_usage() -> RamUsage {
+    // SAFETY: only the addresses of the linker symbols are taken
+    let heap_start = unsafe { core::ptr::addr_of!(__sheap) as usize };
+    let stack_top = unsafe { core::ptr::addr_of!(_stack_start) as usize };
+    let stack_pointer = cortex_m::register::msp::read() as usize;
+
+    RamUsage {
+        static_bytes: heap_start - RAM_ORIGIN,
+        free_bytes: stack_pointer.saturating_sub(heap_start),
+        stack_bytes: stack_top.saturating_sub(stack_pointer),
+        total_bytes: stack_top - RAM_ORIGIN,
+    }
+}
+
+/// Writes the buffer inventory and RAM estimate as text
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let usage = ram_usage();
+
+    writeln!(
+        out,
+        "RAM: {} total, {} static, {} stack, ~{} free",
+        usage.total_bytes, usage.static_bytes, usage.stack_bytes, usage.free_bytes
+    )?;
+
+    interrupt::free(|cs| {
+        for buffer in INVENTORY.borrow(cs).borrow().iter() {
+            writeln!(out, "  {:<16} {:>6}", buffer.owner, buffer.size)?;
+        }
+        Ok(())
+    })?;
+
+    writeln!(out, "  {:<16} {:>6}", "(registered)", registered_bytes())
+}
@@ -1 +1,27 @@
+#[cfg(target_os = "none")]
+pub mod budget;
+#[cfg(target_os = "none")]
+pub mod clock_health;
+pub mod datetime;
+#[cfg(target_os = "none")]
+pub mod device_id;
+#[cfg(all(target_os = "none", feature = "adc"))]
+pub mod health_monitor;
+#[cfg(target_os = "none")]
+pub mod latency;
+pub mod lock_stats;
+#[cfg(all(target_os = "none", feature = "usb-msc"))]
+pub mod log_volume;
+#[cfg(target_os = "none")]
+pub mod meminfo;
+pub mod retry;
 pub mod morse;
+#[cfg(target_os = "none")]
+pub mod profiler;
+#[cfg(target_os = "none")]
+pub mod scheduler;
+pub mod statistics;
+#[cfg(target_os = "none")]
+pub mod sysinfo;
+#[cfg(target_os = "none")]
+pub mod timeout;
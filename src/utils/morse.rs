@@ -1,3 +1,9 @@
+use crate::errors::errors::{
+    AdcError, CanError, DacError, DeviceError, DisplayError, DmaError, FlashError, I2cError,
+    LedError, QspiError, RingBufferError, RngError, SdCardError, SdramError, SpiError, TouchError,
+    UsartError, UsbError,
+};
+
 /// Converts a digit to the corresponding Morse code.
 ///
 /// # Arguments
@@ -21,6 +27,47 @@ fn digit_to_morse(digit: u8) -> &'static str {
     }
 }
 
+/// Converts a letter to the corresponding Morse code.
+///
+/// # Arguments
+/// * `letter` - An ASCII letter, either case.
+///
+/// # Returns
+/// * `Some(&'static str)` - The Morse code for the letter.
+/// * `None` - If `letter` is not in A-Z.
+fn letter_to_morse(letter: u8) -> Option<&'static str> {
+    let code = match letter.to_ascii_uppercase() {
+        b'A' => ".-",
+        b'B' => "-...",
+        b'C' => "-.-.",
+        b'D' => "-..",
+        b'E' => ".",
+        b'F' => "..-.",
+        b'G' => "--.",
+        b'H' => "....",
+        b'I' => "..",
+        b'J' => ".---",
+        b'K' => "-.-",
+        b'L' => ".-..",
+        b'M' => "--",
+        b'N' => "-.",
+        b'O' => "---",
+        b'P' => ".--.",
+        b'Q' => "--.-",
+        b'R' => ".-.",
+        b'S' => "...",
+        b'T' => "-",
+        b'U' => "..-",
+        b'V' => "...-",
+        b'W' => ".--",
+        b'X' => "-..-",
+        b'Y' => "-.--",
+        b'Z' => "--..",
+        _ => return None,
+    };
+    Some(code)
+}
+
 /// Converts a number into a Morse code string.
 ///
 /// # Arguments
@@ -58,6 +105,313 @@ pub fn number_to_morse(number: u16, buffer: &mut [u8]) -> Result<usize, &'static
     Ok(writer.index) // Return the length of the written data
 }
 
+/// Converts text into a Morse code string.
+///
+/// Letters and digits are separated by letter gaps (` `), words by word gaps
+/// (`/`).
+///
+/// # Arguments
+/// * `text` - Letters A-Z (either case), digits and spaces.
+/// * `buffer` - A mutable buffer for writing the Morse code representation.
+///
+/// # Returns
+/// * `Ok(usize)` - The length of the data written to the buffer.
+/// * `Err(&'static str)` - An error if the buffer is too small or a character
+///   has no Morse code.
+///
+/// # Example
+/// ```
+/// let mut buffer = [0u8; 64];
+/// let length = text_to_morse("UB", &mut buffer).unwrap();
+/// assert_eq!(&buffer[..length], b"..- -...");
+/// ```
+pub fn text_to_morse(text: &str, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    let mut writer = BufferWriter::new(buffer);
+
+    for (index, word) in text.split_ascii_whitespace().enumerate() {
+        if index > 0 {
+            writer.write_byte(b'/')?;
+        }
+        for (position, byte) in word.bytes().enumerate() {
+            let code = match byte {
+                b'0'..=b'9' => digit_to_morse(byte - b'0'),
+                _ => letter_to_morse(byte).ok_or("Unsupported character")?,
+            };
+            if position > 0 {
+                writer.write_byte(b' ')?;
+            }
+            writer.write_str(code)?;
+        }
+    }
+
+    Ok(writer.index)
+}
+
+/// Converts an error code into the Morse code shown on the red LED.
+///
+/// `DeviceError` codes are played as their mnemonic, any other code as its
+/// decimal digits.
+///
+/// # Arguments
+/// * `code` - The error code, as recorded in the error store.
+/// * `buffer` - A mutable buffer for writing the Morse code representation.
+pub fn error_code_to_morse(code: u16, buffer: &mut [u8]) -> Result<usize, &'static str> {
+    match DeviceError::from_code(code) {
+        Some(error) => text_to_morse(error.mnemonic(), buffer),
+        None => number_to_morse(code, buffer),
+    }
+}
+
+/// Short human-readable name of an error, blinked in Morse code.
+///
+/// Mnemonics are two or three letters, so they never read like the five
+/// symbol digits of a numeric code. The leading letters name the domain,
+/// e.g. `U` for USB, `S` for the serial port, `D` for DMA; the rest name the
+/// error within it.
+pub trait Mnemonic {
+    /// Returns the mnemonic, letters A-Z only
+    fn mnemonic(&self) -> &'static str;
+}
+
+impl Mnemonic for DeviceError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            DeviceError::Usb(error) => error.mnemonic(),
+            DeviceError::Usart(UsartError::Overrun) => "OR",
+            DeviceError::Usart(UsartError::Framing) => "FE",
+            DeviceError::Usart(UsartError::Noise) => "NE",
+            DeviceError::Usart(UsartError::Parity) => "PE",
+            DeviceError::Usart(error) => error.mnemonic(),
+            DeviceError::Dma(error) => error.mnemonic(),
+            DeviceError::RingBuffer(error) => error.mnemonic(),
+            DeviceError::Led(error) => error.mnemonic(),
+            DeviceError::Flash(error) => error.mnemonic(),
+            DeviceError::Qspi(error) => error.mnemonic(),
+            DeviceError::Sdram(error) => error.mnemonic(),
+            DeviceError::Display(error) => error.mnemonic(),
+            DeviceError::Touch(error) => error.mnemonic(),
+            DeviceError::I2c(error) => error.mnemonic(),
+            DeviceError::Spi(error) => error.mnemonic(),
+            DeviceError::Can(error) => error.mnemonic(),
+            DeviceError::Adc(error) => error.mnemonic(),
+            DeviceError::Dac(error) => error.mnemonic(),
+            DeviceError::Rng(error) => error.mnemonic(),
+            DeviceError::SdCard(error) => error.mnemonic(),
+            DeviceError::Timeout => "TO",
+            DeviceError::ClockDrift => "CD",
+            DeviceError::BudgetOverrun => "BU",
+            DeviceError::ImageCorrupt => "IC",
+            DeviceError::TemperatureLimit => "HT",
+            DeviceError::SupplyLimit => "SV",
+            DeviceError::ClockSwitch => "PL",
+            DeviceError::WatchdogEarlyRefresh => "WE",
+            DeviceError::WatchdogTimeout => "WT",
+            DeviceError::SupplyDip => "LV",
+        }
+    }
+}
+
+impl Mnemonic for UsartError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            UsartError::DmaError => "SD",
+            UsartError::TransferError => "SX",
+            UsartError::Timeout => "ST",
+            UsartError::NotInitialized => "SN",
+            UsartError::BufferOverflow => "SB",
+            UsartError::FlagNotSet => "SF",
+            UsartError::InvalidConfig => "SC",
+            UsartError::Overrun => "SOR",
+            UsartError::Framing => "SFE",
+            UsartError::Noise => "SNE",
+            UsartError::Parity => "SPE",
+        }
+    }
+}
+
+impl Mnemonic for UsbError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            UsbError::NotInitialized => "UN",
+            UsbError::ReadError => "UR",
+            UsbError::WriteError => "UW",
+            UsbError::BufferOverflow => "UB",
+            UsbError::InitError => "UI",
+            UsbError::PollError => "UP",
+        }
+    }
+}
+
+impl Mnemonic for DmaError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            DmaError::InitError => "DI",
+            DmaError::TransferError => "DX",
+            DmaError::RetryLimitExceeded => "DRL",
+            DmaError::BufferOverflow => "DBO",
+            DmaError::BufferUnderflow => "DBU",
+            DmaError::WriteError => "DW",
+            DmaError::ReadError => "DR",
+            DmaError::SequenceViolation => "DSQ",
+        }
+    }
+}
+
+impl Mnemonic for RingBufferError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            RingBufferError::BufferOverflow => "BO",
+            RingBufferError::InsufficientSpace => "BS",
+            RingBufferError::BufferEmpty => "BE",
+        }
+    }
+}
+
+impl Mnemonic for LedError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            LedError::SetStateError => "LS",
+            LedError::ReadStateError => "LR",
+        }
+    }
+}
+
+impl Mnemonic for FlashError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            FlashError::EraseError => "FLE",
+            FlashError::ProgramError => "FLP",
+            FlashError::OutOfBounds => "FLB",
+            FlashError::NotErased => "FLN",
+        }
+    }
+}
+
+impl Mnemonic for QspiError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            QspiError::Timeout => "QT",
+            QspiError::OutOfBounds => "QB",
+            QspiError::WrongId => "QI",
+            QspiError::EraseError => "QE",
+            QspiError::ProgramError => "QP",
+            QspiError::WriteProtected => "QW",
+            QspiError::MemoryMapped => "QM",
+        }
+    }
+}
+
+impl Mnemonic for SdramError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            SdramError::Timeout => "MT",
+            SdramError::DataBus => "MD",
+            SdramError::AddressBus => "MA",
+        }
+    }
+}
+
+impl Mnemonic for DisplayError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            DisplayError::NoFramebuffer => "DSF",
+            DisplayError::DsiHost => "DSH",
+            DisplayError::Panel => "DSP",
+        }
+    }
+}
+
+impl Mnemonic for TouchError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            TouchError::NotFound => "TN",
+            TouchError::Bus => "TB",
+        }
+    }
+}
+
+impl Mnemonic for I2cError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            I2cError::Timeout => "IT",
+            I2cError::Nack => "IN",
+            I2cError::ArbitrationLost => "IA",
+            I2cError::Bus => "IB",
+            I2cError::Busy => "IBY",
+        }
+    }
+}
+
+impl Mnemonic for SpiError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            SpiError::Timeout => "SPT",
+            SpiError::ModeFault => "SPM",
+            SpiError::Overrun => "SPO",
+            SpiError::Dma => "SPD",
+            SpiError::InvalidChipSelect => "SPC",
+            SpiError::LoopbackMismatch => "SPL",
+        }
+    }
+}
+
+impl Mnemonic for CanError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            CanError::Timeout => "CT",
+            CanError::Bitrate => "CB",
+            CanError::MailboxFull => "CM",
+            CanError::BusOff => "CO",
+            CanError::Closed => "CC",
+        }
+    }
+}
+
+impl Mnemonic for AdcError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            AdcError::Timeout => "AT",
+            AdcError::Overrun => "AO",
+            AdcError::Dma => "AD",
+            AdcError::NoChannels => "AN",
+        }
+    }
+}
+
+impl Mnemonic for DacError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            DacError::Level => "DAL",
+            DacError::Frequency => "DAF",
+        }
+    }
+}
+
+impl Mnemonic for RngError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            RngError::Seed => "RS",
+            RngError::Clock => "RC",
+            RngError::Repeated => "RR",
+            RngError::Timeout => "RT",
+            RngError::Busy => "RB",
+        }
+    }
+}
+
+impl Mnemonic for SdCardError {
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            SdCardError::NoCard => "MCN",
+            SdCardError::Init => "MCI",
+            SdCardError::Io => "MCX",
+            SdCardError::NoFilesystem => "MCF",
+            SdCardError::DirectoryFull => "MCD",
+            SdCardError::DiskFull => "MCS",
+        }
+    }
+}
+
 /// Helper structure for writing to a buffer.
 struct BufferWriter<'a> {
     buffer: &'a mut [u8],
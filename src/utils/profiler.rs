@@ -0,0 +1,323 @@
+//! # CPU Load Profiler
+//!
+//! Accounts where the CPU time goes, per interrupt handler and task:
+//! - Each profiled run is timed with the DWT cycle counter through a
+//!   `ProfileGuard`; every `BudgetGuard` carries one, other tasks start their
+//!   own
+//! - Times are exclusive: cycles spent in handlers that preempted a run are
+//!   charged to the preempting handler only, so the slots add up to the busy
+//!   time and a long maximum points at the slot itself, not at its preemptors
+//! - The idle loop is profiled while awake; sleep is the remainder of the
+//!   wall-clock window, taken from the monotonic, as the cycle counter stops
+//!   while the core sleeps in WFI. Unprofiled low-priority tasks are part of
+//!   that remainder
+//! - Totals are 64-bit, so a window can span days at `SYSCLK`
+//! - `profile` on the console prints the report, `profile reset` starts a new
+//!   window
+//!
+//! For async tasks only the code between two `await` points is measured.
+//!
+//! ## Safety Considerations
+//! - Requires the DWT cycle counter to be enabled during init
+//! - Stop mode halts both the cycle counter and the monotonic, so suspended
+//!   periods are missing from the window
+
+use crate::config::SYSCLK;
+use crate::utils::budget::Task;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::DWT;
+
+/// Profiled interrupt handlers and tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// USB OTG FS interrupt (priority 4)
+    OtgFs,
+    /// USART6 interrupt (priority 3)
+    Usart6,
+    /// USART3 interrupt (priority 3)
+    Usart3,
+    /// UART RX DMA streams (priority 3)
+    DmaRx,
+    /// UART TX DMA streams (priority 3)
+    DmaTx,
+    /// TIM3 RX timeout (priority 3)
+    RxTimeout,
+    /// RX ring buffer to USB (priority 3)
+    RxToUsb,
+    /// TX ring buffer to USART DMA (priority 3)
+    TxToUart,
+    /// Red LED Morse state machine (priority 5)
+    ErrorDisplay,
+    /// User button EXTI0 interrupt (priority 2)
+    Button,
+    /// Debug console command handling (priority 1)
+    Console,
+    /// Periodic maintenance jobs (priority 1)
+    Periodic,
+    /// Status screen redraw (priority 1, `display` feature)
+    Display,
+    /// Idle loop while awake, outside WFI
+    Idle,
+}
+
+/// Number of profiled slots
+const SLOT_COUNT: usize = 14;
+
+impl Slot {
+    /// All slots, in report order
+    pub const ALL: [Slot; SLOT_COUNT] = [
+        Self::OtgFs,
+        Self::Usart6,
+        Self::Usart3,
+        Self::DmaRx,
+        Self::DmaTx,
+        Self::RxTimeout,
+        Self::RxToUsb,
+        Self::TxToUart,
+        Self::ErrorDisplay,
+        Self::Button,
+        Self::Console,
+        Self::Periodic,
+        Self::Display,
+        Self::Idle,
+    ];
+
+    /// Short name used in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OtgFs => "otg_fs",
+            Self::Usart6 => "usart6",
+            Self::Usart3 => "usart3",
+            Self::DmaRx => "dma_rx",
+            Self::DmaTx => "dma_tx",
+            Self::RxTimeout => "rx_timeout",
+            Self::RxToUsb => "rx_to_usb",
+            Self::TxToUart => "tx_to_uart",
+            Self::ErrorDisplay => "error_display",
+            Self::Button => "button",
+            Self::Console => "console",
+            Self::Periodic => "periodic",
+            Self::Display => "display",
+            Self::Idle => "idle",
+        }
+    }
+}
+
+impl From<Task> for Slot {
+    fn from(task: Task) -> Self {
+        match task {
+            Task::OtgFs => Self::OtgFs,
+            Task::Usart6 => Self::Usart6,
+            Task::Usart3 => Self::Usart3,
+            Task::DmaRx => Self::DmaRx,
+            Task::DmaTx => Self::DmaTx,
+            Task::RxTimeout => Self::RxTimeout,
+            Task::RxToUsb => Self::RxToUsb,
+            Task::TxToUart => Self::TxToUart,
+            Task::ErrorDisplay => Self::ErrorDisplay,
+        }
+    }
+}
+
+/// Lock-free 64-bit counter from two 32-bit halves
+struct Counter64 {
+    low: AtomicU32,
+    high: AtomicU32,
+}
+
+impl Counter64 {
+    const fn new() -> Self {
+        Self {
+            low: AtomicU32::new(0),
+            high: AtomicU32::new(0),
+        }
+    }
+
+    fn add(&self, value: u32) {
+        let previous = self.low.fetch_add(value, Ordering::Relaxed);
+        if previous.checked_add(value).is_none() {
+            self.high.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Reads both halves, retrying if a carry happened in between
+    fn get(&self) -> u64 {
+        loop {
+            let high = self.high.load(Ordering::Relaxed);
+            let low = self.low.load(Ordering::Relaxed);
+            if self.high.load(Ordering::Relaxed) == high {
+                return ((high as u64) << 32) | low as u64;
+            }
+        }
+    }
+
+    fn clear(&self) {
+        self.low.store(0, Ordering::Relaxed);
+        self.high.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Per-slot counters
+struct SlotStats {
+    runs: AtomicU32,
+    cycles: Counter64,
+    max_cycles: AtomicU32,
+}
+
+impl SlotStats {
+    const fn new() -> Self {
+        Self {
+            runs: AtomicU32::new(0),
+            cycles: Counter64::new(),
+            max_cycles: AtomicU32::new(0),
+        }
+    }
+}
+
+static SLOTS: [SlotStats; SLOT_COUNT] = [
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+    SlotStats::new(),
+];
+
+/// Exclusive cycles of all finished runs, wrapping
+///
+/// A run subtracts the growth during its lifetime, which is exactly the time
+/// of the runs that preempted it.
+static FINISHED_CYCLES: AtomicU32 = AtomicU32::new(0);
+
+/// Monotonic time the window started (milliseconds)
+static WINDOW_START_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Snapshot of one slot's counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlotSnapshot {
+    /// Measured runs
+    pub runs: u32,
+    /// Exclusive time of all runs (cycles)
+    pub cycles: u64,
+    /// Longest exclusive run (cycles)
+    pub max_cycles: u32,
+}
+
+/// Times one run of a slot; the measurement ends when the guard is dropped
+pub struct ProfileGuard {
+    slot: Slot,
+    start: u32,
+    finished_at_start: u32,
+}
+
+impl ProfileGuard {
+    /// Starts timing a run
+    ///
+    /// # Arguments
+    /// * `slot` - Handler or task being run
+    pub fn start(slot: Slot) -> Self {
+        Self {
+            slot,
+            finished_at_start: FINISHED_CYCLES.load(Ordering::Relaxed),
+            start: DWT::cycle_count(),
+        }
+    }
+}
+
+impl Drop for ProfileGuard {
+    fn drop(&mut self) {
+        let elapsed = DWT::cycle_count().wrapping_sub(self.start);
+        let preempted = FINISHED_CYCLES
+            .load(Ordering::Relaxed)
+            .wrapping_sub(self.finished_at_start);
+        let exclusive = elapsed.saturating_sub(preempted);
+        FINISHED_CYCLES.fetch_add(exclusive, Ordering::Relaxed);
+
+        let stats = &SLOTS[self.slot as usize];
+        stats.runs.fetch_add(1, Ordering::Relaxed);
+        stats.cycles.add(exclusive);
+        stats.max_cycles.fetch_max(exclusive, Ordering::Relaxed);
+    }
+}
+
+/// Runs `f` as one profiled run of `slot`
+pub fn measure<R>(slot: Slot, f: impl FnOnce() -> R) -> R {
+    let _guard = ProfileGuard::start(slot);
+    f()
+}
+
+/// Returns the counters of a slot
+pub fn snapshot(slot: Slot) -> SlotSnapshot {
+    let stats = &SLOTS[slot as usize];
+    SlotSnapshot {
+        runs: stats.runs.load(Ordering::Relaxed),
+        cycles: stats.cycles.get(),
+        max_cycles: stats.max_cycles.load(Ordering::Relaxed),
+    }
+}
+
+/// Clears all counters and starts a new window
+///
+/// Runs in progress finish into the new window.
+pub fn reset() {
+    for stats in &SLOTS {
+        stats.runs.store(0, Ordering::Relaxed);
+        stats.cycles.clear();
+        stats.max_cycles.store(0, Ordering::Relaxed);
+    }
+    WINDOW_START_MS.store(crate::Mono::now().ticks(), Ordering::Relaxed);
+}
+
+/// Writes the CPU load and the time per slot in the current window
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    let cycles_per_us = (SYSCLK / 1_000_000) as u64;
+    let window_ms = crate::Mono::now()
+        .ticks()
+        .wrapping_sub(WINDOW_START_MS.load(Ordering::Relaxed));
+    let window_us = (window_ms as u64 * 1_000).max(1);
+
+    let idle_us = snapshot(Slot::Idle).cycles / cycles_per_us;
+    let busy_us: u64 = Slot::ALL
+        .iter()
+        .filter(|&&slot| slot != Slot::Idle)
+        .map(|&slot| snapshot(slot).cycles / cycles_per_us)
+        .sum();
+    let load = busy_us.min(window_us) * 1_000 / window_us;
+
+    writeln!(
+        out,
+        "CPU load: {}.{} % over {} ms (slot runs, total us, max cycles)",
+        load / 10,
+        load % 10,
+        window_ms
+    )?;
+    for slot in Slot::ALL {
+        let stats = snapshot(slot);
+        if stats.runs == 0 {
+            continue;
+        }
+        writeln!(
+            out,
+            "  {:<14}{:>9}{:>12}{:>9}",
+            slot.name(),
+            stats.runs,
+            stats.cycles / cycles_per_us,
+            stats.max_cycles
+        )?;
+    }
+    writeln!(
+        out,
+        "  {:<14}{:>21}",
+        "sleep+other",
+        window_us.saturating_sub(busy_us + idle_us)
+    )
+}
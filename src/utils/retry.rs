@@ -0,0 +1,257 @@
+//! # Retry and Backoff Policies
+//!
+//! Shared retry logic for operations that can fail transiently:
+//! - A `RetryPolicy` holds the attempt limit, backoff schedule and jitter
+//! - A `RetryState` tracks consecutive failures of one operation instance
+//! - Each policy counts retries, recoveries and exhausted attempts
+//!
+//! Policies are `static` and declared next to the code they protect, each with
+//! parameters tuned for its subsystem. Two usage styles are supported:
+//! - `run` retries a synchronous operation in place (busy-wait backoff)
+//! - `on_failure`/`on_success` leave the waiting to the caller, e.g. an ISR
+//!   retrying on its next invocation or an async task awaiting the delay
+//!
+//! ## Safety Considerations
+//! - Jitter is drawn from the RNG, or the DWT cycle counter while the RNG is
+//!   unavailable; without jitter the delays are exact
+//! - `run` blocks the caller for the whole backoff; keep synchronous delays short
+//! - Host builds draw jitter from a fixed sequence and do not wait
+
+#[cfg(target_os = "none")]
+use crate::config::SYSCLK;
+#[cfg(target_os = "none")]
+use crate::peripherals::rng;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(target_os = "none")]
+use cortex_m::peripheral::DWT;
+
+/// Delay schedule between attempts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backoff {
+    /// Retry right away
+    Immediate,
+    /// Same delay before every retry (milliseconds)
+    Fixed(u32),
+    /// Delay doubling from `base_ms` up to `max_ms`
+    Exponential { base_ms: u32, max_ms: u32 },
+}
+
+/// Decision after a failed attempt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    /// Try again after the given delay (milliseconds)
+    Retry { delay_ms: u32 },
+    /// Attempt limit reached; the failure counter has been reset
+    Exhausted,
+}
+
+/// Counters of one policy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RetryStats {
+    /// Failed attempts followed by another try
+    pub retries: u32,
+    /// Operations that succeeded after at least one failure
+    pub recoveries: u32,
+    /// Operations that ran out of attempts
+    pub exhausted: u32,
+}
+
+/// Consecutive failures of one operation instance
+#[derive(Debug, Default)]
+pub struct RetryState {
+    failures: u8,
+}
+
+impl RetryState {
+    /// Creates a state with no failures recorded
+    pub const fn new() -> Self {
+        Self { failures: 0 }
+    }
+
+    /// Consecutive failures so far
+    pub fn failures(&self) -> u8 {
+        self.failures
+    }
+}
+
+/// Retry policy with per-policy statistics
+pub struct RetryPolicy {
+    name: &'static str,
+    max_attempts: u8,
+    backoff: Backoff,
+    jitter_percent: u8,
+    retries: AtomicU32,
+    recoveries: AtomicU32,
+    exhausted: AtomicU32,
+}
+
+impl RetryPolicy {
+    /// Creates a policy
+    ///
+    /// # Arguments
+    /// * `name` - Name used in reports
+    /// * `max_attempts` - Attempts including the first one
+    /// * `backoff` - Delay schedule between attempts
+    /// * `jitter_percent` - Random spread applied to each delay (0-100)
+    pub const fn new(
+        name: &'static str,
+        max_attempts: u8,
+        backoff: Backoff,
+        jitter_percent: u8,
+    ) -> Self {
+        Self {
+            name,
+            max_attempts,
+            backoff,
+            jitter_percent,
+            retries: AtomicU32::new(0),
+            recoveries: AtomicU32::new(0),
+            exhausted: AtomicU32::new(0),
+        }
+    }
+
+    /// Records a failed attempt
+    ///
+    /// # Returns
+    /// - `Verdict::Retry` with the delay to wait before the next attempt
+    /// - `Verdict::Exhausted` once `max_attempts` failures happened in a row
+    pub fn on_failure(&self, state: &mut RetryState) -> Verdict {
+        state.failures = state.failures.saturating_add(1);
+
+        if state.failures >= self.max_attempts {
+            state.failures = 0;
+            self.exhausted.fetch_add(1, Ordering::Relaxed);
+
+            #[cfg(feature = "debug")]
+            defmt::warn!("Retry policy {=str} exhausted", self.name);
+            return Verdict::Exhausted;
+        }
+
+        self.retries.fetch_add(1, Ordering::Relaxed);
+        Verdict::Retry {
+            delay_ms: self.delay_ms(state.failures),
+        }
+    }
+
+    /// Records a successful attempt and resets the failure counter
+    pub fn on_success(&self, state: &mut RetryState) {
+        if state.failures > 0 {
+            self.recoveries.fetch_add(1, Ordering::Relaxed);
+        }
+        state.failures = 0;
+    }
+
+    /// Runs an operation until it succeeds, fails permanently or runs out of attempts
+    ///
+    /// # Arguments
+    /// * `op` - Operation to attempt
+    /// * `retryable` - Returns `true` for errors worth another attempt
+    ///
+    /// # Errors
+    /// Returns the last error of the operation
+    pub fn run<T, E>(
+        &self,
+        mut op: impl FnMut() -> Result<T, E>,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut state = RetryState::new();
+        loop {
+            match op() {
+                Ok(value) => {
+                    self.on_success(&mut state);
+                    return Ok(value);
+                }
+                Err(e) if !retryable(&e) => return Err(e),
+                Err(e) => match self.on_failure(&mut state) {
+                    Verdict::Exhausted => return Err(e),
+                    Verdict::Retry { delay_ms } => busy_wait_us(delay_ms.saturating_mul(1000)),
+                },
+            }
+        }
+    }
+
+    /// Delay before the retry following the given number of failures
+    fn delay_ms(&self, failures: u8) -> u32 {
+        let delay = match self.backoff {
+            Backoff::Immediate => 0,
+            Backoff::Fixed(ms) => ms,
+            Backoff::Exponential { base_ms, max_ms } => {
+                let shift = u32::from(failures.saturating_sub(1)).min(31);
+                base_ms.saturating_mul(1 << shift).min(max_ms)
+            }
+        };
+
+        let spread = delay * u32::from(self.jitter_percent.min(100)) / 100;
+        if spread == 0 {
+            return delay;
+        }
+        let offset = random_u32() % (2 * spread + 1);
+        delay - spread + offset
+    }
+
+    /// Policy name
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Current counters
+    pub fn stats(&self) -> RetryStats {
+        RetryStats {
+            retries: self.retries.load(Ordering::Relaxed),
+            recoveries: self.recoveries.load(Ordering::Relaxed),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Random number for jitter
+///
+/// From the RNG if it is free, otherwise the DWT cycle counter, which is
+/// enough to keep retries of different contexts apart.
+#[cfg(target_os = "none")]
+pub fn random_u32() -> u32 {
+    rng::next_u32().unwrap_or_else(|_| DWT::cycle_count())
+}
+
+/// Random number for jitter, from a xorshift sequence in host builds
+#[cfg(not(target_os = "none"))]
+pub fn random_u32() -> u32 {
+    static STATE: AtomicU32 = AtomicU32::new(0x2545_F491);
+    let mut x = STATE.load(Ordering::Relaxed);
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    STATE.store(x, Ordering::Relaxed);
+    x
+}
+
+/// Busy-waits for a backoff delay; host builds return at once
+pub fn busy_wait_us(us: u32) {
+    #[cfg(target_os = "none")]
+    cortex_m::asm::delay(us.saturating_mul(SYSCLK / 1_000_000));
+
+    #[cfg(not(target_os = "none"))]
+    let _ = us;
+}
+
+/// Writes one line of counters per policy
+///
+/// # Arguments
+/// * `out` - Text sink
+/// * `policies` - Policies to report
+pub fn write_report<W: Write>(out: &mut W, policies: &[&RetryPolicy]) -> fmt::Result {
+    write!(out, "Retries (retry/recovered/exhausted):")?;
+    for policy in policies {
+        let stats = policy.stats();
+        write!(
+            out,
+            " {}={}/{}/{}",
+            policy.name(),
+            stats.retries,
+            stats.recoveries,
+            stats.exhausted
+        )?;
+    }
+    writeln!(out)
+}
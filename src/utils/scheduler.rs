@@ -0,0 +1,112 @@
+//! # Periodic Job Scheduler
+//!
+//! Lets subsystems run periodic work from a single RTIC task instead of each
+//! declaring its own task:
+//! - Jobs are registered with a period and an optional `Subsystem` gate
+//! - `poll` returns the jobs that are due and reschedules them drift-free
+//! - `time_to_next` tells the owning task how long it may sleep
+//!
+//! Jobs are identified by a caller-defined `Copy` type (usually an enum), so the
+//! owning task can give each job exactly the RTIC resources it needs.
+
+use crate::task_handlers::task_registry::{self, Subsystem};
+use heapless::Vec;
+
+/// Single registered job
+#[derive(Debug, Clone, Copy)]
+struct Entry<J> {
+    job: J,
+    period_ms: u32,
+    next_due_ms: u32,
+    subsystem: Option<Subsystem>,
+}
+
+/// Fixed-capacity periodic scheduler
+pub struct Scheduler<J: Copy, const N: usize> {
+    entries: Vec<Entry<J>, N>,
+}
+
+impl<J: Copy, const N: usize> Scheduler<J, N> {
+    /// Creates an empty scheduler
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Registers a periodic job
+    ///
+    /// # Arguments
+    /// * `job` - Job identifier returned by `poll` when due
+    /// * `period_ms` - Interval between runs (minimum 1 ms)
+    /// * `subsystem` - Registry gate; the job is skipped while it is disabled
+    /// * `now_ms` - Current monotonic time; first run is one period from now
+    ///
+    /// # Errors
+    /// Returns the job back if the scheduler is full
+    pub fn register(
+        &mut self,
+        job: J,
+        period_ms: u32,
+        subsystem: Option<Subsystem>,
+        now_ms: u32,
+    ) -> Result<(), J> {
+        let period_ms = period_ms.max(1);
+        self.entries
+            .push(Entry {
+                job,
+                period_ms,
+                next_due_ms: now_ms.wrapping_add(period_ms),
+                subsystem,
+            })
+            .map_err(|entry| entry.job)
+    }
+
+    /// Collects due jobs and schedules their next run
+    ///
+    /// Jobs whose subsystem is disabled are rescheduled but not returned.
+    pub fn poll(&mut self, now_ms: u32) -> Vec<J, N> {
+        let mut due = Vec::new();
+
+        for entry in self.entries.iter_mut() {
+            if !is_due(now_ms, entry.next_due_ms) {
+                continue;
+            }
+
+            // Keep the phase unless we fell more than a period behind
+            entry.next_due_ms = entry.next_due_ms.wrapping_add(entry.period_ms);
+            if is_due(now_ms, entry.next_due_ms) {
+                entry.next_due_ms = now_ms.wrapping_add(entry.period_ms);
+            }
+
+            let enabled = entry.subsystem.map_or(true, task_registry::is_enabled);
+            if enabled {
+                // Cannot overflow: at most one entry per registered job
+                let _ = due.push(entry.job);
+            }
+        }
+
+        due
+    }
+
+    /// Milliseconds until the next job is due (0 if one is overdue)
+    pub fn time_to_next(&self, now_ms: u32) -> u32 {
+        self.entries
+            .iter()
+            .map(|entry| {
+                if is_due(now_ms, entry.next_due_ms) {
+                    0
+                } else {
+                    entry.next_due_ms.wrapping_sub(now_ms)
+                }
+            })
+            .min()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+/// Wrap-safe "deadline reached" comparison
+#[inline]
+fn is_due(now_ms: u32, due_ms: u32) -> bool {
+    (now_ms.wrapping_sub(due_ms) as i32) >= 0
+}
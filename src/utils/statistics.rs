@@ -0,0 +1,183 @@
+//! # Bridge Statistics
+//!
+//! Telemetry counters of the data path, updated with relaxed atomics from
+//! interrupt and task context alike:
+//! - Bytes received and sent on the UART and USB sides
+//! - High-water marks of the RX and TX ring buffers
+//! - DMA stream restarts, USB transfer errors and bytes dropped on overflow
+//! - UART receive errors by class: overrun, framing, noise and parity
+//!
+//! `get_stats` returns a snapshot; the periodic `StatsReport` job (subsystem
+//! `stats`) dumps it to the debug console or the debug channel, and the
+//! console `stats` command prints it on demand.
+
+use crate::peripherals::traits::LineError;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+static UART_RX_BYTES: AtomicU32 = AtomicU32::new(0);
+static UART_TX_BYTES: AtomicU32 = AtomicU32::new(0);
+static USB_RX_BYTES: AtomicU32 = AtomicU32::new(0);
+static USB_TX_BYTES: AtomicU32 = AtomicU32::new(0);
+static RX_BUFFER_HIGH_WATER: AtomicU32 = AtomicU32::new(0);
+static TX_BUFFER_HIGH_WATER: AtomicU32 = AtomicU32::new(0);
+static DMA_RESTARTS: AtomicU32 = AtomicU32::new(0);
+static USB_ERRORS: AtomicU32 = AtomicU32::new(0);
+static DROPPED_BYTES: AtomicU32 = AtomicU32::new(0);
+static OVERRUN_ERRORS: AtomicU32 = AtomicU32::new(0);
+static FRAMING_ERRORS: AtomicU32 = AtomicU32::new(0);
+static NOISE_ERRORS: AtomicU32 = AtomicU32::new(0);
+static PARITY_ERRORS: AtomicU32 = AtomicU32::new(0);
+
+/// Snapshot of all counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BridgeStats {
+    /// Bytes received from the UART (DMA RX)
+    pub uart_rx_bytes: u32,
+    /// Bytes handed to the UART (DMA TX)
+    pub uart_tx_bytes: u32,
+    /// Bytes received from the USB host
+    pub usb_rx_bytes: u32,
+    /// Bytes sent to the USB host
+    pub usb_tx_bytes: u32,
+    /// Highest RX ring buffer fill level (bytes)
+    pub rx_buffer_high_water: u32,
+    /// Highest TX ring buffer fill level (bytes)
+    pub tx_buffer_high_water: u32,
+    /// DMA stream restarts after transfer errors
+    pub dma_restarts: u32,
+    /// Failed USB reads, writes and polls
+    pub usb_errors: u32,
+    /// Bytes discarded because a buffer was full
+    pub dropped_bytes: u32,
+    /// UART receiver overruns (a byte lost before the DMA read it)
+    pub overrun_errors: u32,
+    /// UART framing errors (stop bit missing)
+    pub framing_errors: u32,
+    /// UART noise errors
+    pub noise_errors: u32,
+    /// UART parity errors
+    pub parity_errors: u32,
+}
+
+/// Counts bytes received from the UART
+pub fn add_uart_rx(bytes: usize) {
+    UART_RX_BYTES.fetch_add(bytes as u32, Ordering::Relaxed);
+}
+
+/// Counts bytes handed to the UART
+pub fn add_uart_tx(bytes: usize) {
+    UART_TX_BYTES.fetch_add(bytes as u32, Ordering::Relaxed);
+}
+
+/// Counts bytes received from the USB host
+pub fn add_usb_rx(bytes: usize) {
+    USB_RX_BYTES.fetch_add(bytes as u32, Ordering::Relaxed);
+}
+
+/// Counts bytes sent to the USB host
+pub fn add_usb_tx(bytes: usize) {
+    USB_TX_BYTES.fetch_add(bytes as u32, Ordering::Relaxed);
+}
+
+/// Records the RX ring buffer fill level after a push
+pub fn note_rx_buffer_level(len: usize) {
+    RX_BUFFER_HIGH_WATER.fetch_max(len as u32, Ordering::Relaxed);
+}
+
+/// Records the TX ring buffer fill level after a push
+pub fn note_tx_buffer_level(len: usize) {
+    TX_BUFFER_HIGH_WATER.fetch_max(len as u32, Ordering::Relaxed);
+}
+
+/// Counts a DMA stream restart
+pub fn add_dma_restart() {
+    DMA_RESTARTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a failed USB transfer or poll
+pub fn add_usb_error() {
+    USB_ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts bytes discarded on a full buffer
+pub fn add_dropped(bytes: usize) {
+    DROPPED_BYTES.fetch_add(bytes as u32, Ordering::Relaxed);
+}
+
+/// Counts the UART receive errors of one status check, one per class
+pub fn add_line_errors(errors: LineError) {
+    for (flag, counter) in [
+        (LineError::ORE, &OVERRUN_ERRORS),
+        (LineError::FE, &FRAMING_ERRORS),
+        (LineError::NE, &NOISE_ERRORS),
+        (LineError::PE, &PARITY_ERRORS),
+    ] {
+        if errors.contains(flag) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Bytes moved on either link in either direction, wrapping
+///
+/// Cheaper than a snapshot for callers that only look for a change.
+pub fn traffic_bytes() -> u32 {
+    [&UART_RX_BYTES, &UART_TX_BYTES, &USB_RX_BYTES, &USB_TX_BYTES]
+        .into_iter()
+        .fold(0, |sum, counter| {
+            sum.wrapping_add(counter.load(Ordering::Relaxed))
+        })
+}
+
+/// Returns a snapshot of all counters
+///
+/// Counters are read one by one; a snapshot taken under traffic may mix
+/// values from slightly different instants.
+pub fn get_stats() -> BridgeStats {
+    BridgeStats {
+        uart_rx_bytes: UART_RX_BYTES.load(Ordering::Relaxed),
+        uart_tx_bytes: UART_TX_BYTES.load(Ordering::Relaxed),
+        usb_rx_bytes: USB_RX_BYTES.load(Ordering::Relaxed),
+        usb_tx_bytes: USB_TX_BYTES.load(Ordering::Relaxed),
+        rx_buffer_high_water: RX_BUFFER_HIGH_WATER.load(Ordering::Relaxed),
+        tx_buffer_high_water: TX_BUFFER_HIGH_WATER.load(Ordering::Relaxed),
+        dma_restarts: DMA_RESTARTS.load(Ordering::Relaxed),
+        usb_errors: USB_ERRORS.load(Ordering::Relaxed),
+        dropped_bytes: DROPPED_BYTES.load(Ordering::Relaxed),
+        overrun_errors: OVERRUN_ERRORS.load(Ordering::Relaxed),
+        framing_errors: FRAMING_ERRORS.load(Ordering::Relaxed),
+        noise_errors: NOISE_ERRORS.load(Ordering::Relaxed),
+        parity_errors: PARITY_ERRORS.load(Ordering::Relaxed),
+    }
+}
+
+impl BridgeStats {
+    /// Writes the counters as one line
+    pub fn write_report<W: Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(
+            out,
+            "Stats: uart rx/tx {}/{}, usb rx/tx {}/{}, high-water rx/tx {}/{}, \
+             dma restarts {}, usb errors {}, dropped {}, \
+             line errors ore/fe/ne/pe {}/{}/{}/{}",
+            self.uart_rx_bytes,
+            self.uart_tx_bytes,
+            self.usb_rx_bytes,
+            self.usb_tx_bytes,
+            self.rx_buffer_high_water,
+            self.tx_buffer_high_water,
+            self.dma_restarts,
+            self.usb_errors,
+            self.dropped_bytes,
+            self.overrun_errors,
+            self.framing_errors,
+            self.noise_errors,
+            self.parity_errors
+        )
+    }
+}
+
+/// Writes the current counters
+pub fn write_report<W: Write>(out: &mut W) -> fmt::Result {
+    get_stats().write_report(out)
+}
@@ -0,0 +1,105 @@
+//! # System Information Report
+//!
+//! Aggregates a human-readable SYSINFO report from the subsystems:
+//! - Firmware version, unique device ID and clock configuration
+//! - Runtime subsystem enable states
+//! - Bridge traffic counters and buffer high-water marks
+//! - Ring buffer backpressure (USB NAK and RTS holds)
+//! - Memory inventory and free RAM estimate
+//! - Map of GPIOs parked as unused
+//! - RTC calendar time and clock source
+//! - Crystal drift estimate
+//! - Retry policy statistics
+//! - Task execution budgets and overruns
+//! - Watchdog missed check-in windows, window watchdog timing and reset causes
+//! - Result of the last downstream target probe
+//! - User button press-to-action mapping
+//! - UART framing mode and frame counters
+//! - CRC32 unit usage
+//! - USB suspend state and Stop mode periods
+//! - Power profile, core clock level and VBUS state
+//! - Supply voltage detector, brown-out level and reset cause
+//! - Clock outputs on the MCO pins
+//! - UART routing of the data port
+//! - UART break conditions sent for the host
+//! - DTR/RTS outputs and modem status inputs
+//! - Lock hold times (with `lock-stats`)
+//! - Pending error records, the signal outputs and the last chained Morse run
+//! - Size of the persistent error log
+
+use crate::config::{SYSCLK, USART3_BAUD_RATE, USART6_BAUD_RATE};
+use crate::peripherals::{
+    crc, flash, iwdg, mco, modem_lines, pin_parking, pvd, rng, rtc, vbus, wwdg,
+};
+use crate::protocol::framer;
+use crate::task_handlers::{
+    backpressure, bridge_mode, button, data_path, error_handlers, error_log, line_break, otg_fs,
+    power, signal_handler, target_probe, task_registry, uart_route, usb_suspend,
+};
+use crate::utils::{budget, clock_health, device_id, meminfo, retry, statistics};
+use core::fmt::{self, Write};
+
+/// Writes the complete SYSINFO report
+///
+/// # Arguments
+/// * `out` - Text sink (console, log buffer, ...)
+pub fn write_sysinfo<W: Write>(out: &mut W) -> fmt::Result {
+    writeln!(
+        out,
+        "{} v{}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    write!(out, "Device ID: ")?;
+    device_id::write_hex(out)?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "SYSCLK: {} Hz, USART6: {} baud, USART3: {} baud",
+        SYSCLK, USART6_BAUD_RATE, USART3_BAUD_RATE
+    )?;
+
+    write!(out, "Subsystems:")?;
+    for (name, enabled) in task_registry::states() {
+        write!(out, " {}={}", name, if enabled { "on" } else { "off" })?;
+    }
+    writeln!(out)?;
+
+    error_handlers::write_error_dump(out)?;
+    error_log::write_report(out)?;
+    signal_handler::write_report(out)?;
+
+    statistics::write_report(out)?;
+    backpressure::write_report(out)?;
+    framer::write_report(out)?;
+    crc::write_report(out)?;
+    rng::write_report(out)?;
+    rtc::write_report(out)?;
+    clock_health::write_report(out)?;
+    target_probe::write_report(out)?;
+    button::write_report(out)?;
+    usb_suspend::write_report(out)?;
+    power::write_report(out)?;
+    vbus::write_report(out)?;
+    pvd::write_report(out)?;
+    mco::write_report(out)?;
+    uart_route::write_report(out)?;
+    bridge_mode::write_report(out)?;
+    line_break::write_report(out)?;
+    modem_lines::write_report(out)?;
+    retry::write_report(
+        out,
+        &[
+            &data_path::DMA_RETRY,
+            &otg_fs::USB_RECONNECT,
+            &flash::FLASH_RETRY,
+        ],
+    )?;
+    budget::write_report(out)?;
+    meminfo::write_report(out)?;
+    iwdg::write_report(out)?;
+    wwdg::write_report(out)?;
+    #[cfg(feature = "lock-stats")]
+    crate::utils::lock_stats::write_report(out)?;
+    pin_parking::write_report(out)
+}
@@ -0,0 +1,79 @@
+//! # Timeouts
+//!
+//! Deadlines for waits on hardware flags, so a wedged peripheral ends in an
+//! error the recovery logic can act on instead of a hung handler:
+//! - A `Deadline` is measured on the `Mono` timebase (1 ms ticks) and
+//!   rounded up by one tick, so it never expires early
+//! - Busy-waits poll through `Deadline::poll`, which also counts the polls
+//!   (each at least a microsecond): the wait ends even where the SysTick
+//!   interrupt cannot advance `Mono`, e.g. in a critical section
+//! - Async waits check `is_expired` between their `Mono::delay` steps
+//! - `Expired` converts into the `Timeout` variant of the caller's error
+//!   domain
+
+use crate::config::SYSCLK;
+use crate::errors::errors::UsartError;
+use crate::Mono;
+use rtic_monotonics::systick::prelude::*;
+
+/// CPU cycles spent per busy-wait poll (one microsecond)
+const POLL_STEP_CYCLES: u32 = SYSCLK / 1_000_000;
+
+/// A wait ran past its deadline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expired;
+
+impl From<Expired> for UsartError {
+    fn from(_: Expired) -> Self {
+        UsartError::Timeout
+    }
+}
+
+/// Point in time a wait must end by
+#[derive(Debug, Clone, Copy)]
+pub struct Deadline {
+    start_ms: u32,
+    timeout_ms: u32,
+    polls_left: u32,
+}
+
+impl Deadline {
+    /// Creates a deadline `timeout_ms` milliseconds from now
+    pub fn after_ms(timeout_ms: u32) -> Self {
+        Self {
+            start_ms: Mono::now().ticks(),
+            timeout_ms: timeout_ms.saturating_add(1),
+            polls_left: timeout_ms.saturating_mul(1_000),
+        }
+    }
+
+    /// Checks whether the deadline passed on the `Mono` timebase
+    pub fn is_expired(&self) -> bool {
+        Mono::now().ticks().wrapping_sub(self.start_ms) >= self.timeout_ms
+    }
+
+    /// One step of a busy-wait: pauses about a microsecond
+    ///
+    /// # Errors
+    /// `Expired` once the deadline passed or the poll budget is used up
+    pub fn poll(&mut self) -> Result<(), Expired> {
+        if self.polls_left == 0 || self.is_expired() {
+            return Err(Expired);
+        }
+        self.polls_left -= 1;
+        cortex_m::asm::delay(POLL_STEP_CYCLES);
+        Ok(())
+    }
+}
+
+/// Busy-waits until `done` holds
+///
+/// # Errors
+/// `Expired` if `done` still fails after `timeout_ms` milliseconds
+pub fn busy_wait_until(timeout_ms: u32, mut done: impl FnMut() -> bool) -> Result<(), Expired> {
+    let mut deadline = Deadline::after_ms(timeout_ms);
+    while !done() {
+        deadline.poll()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+//! Link-level data path steps against the mock UART and USB device
+
+use stm32f469_base_rtic::config::RX_RING_BUFFER_LEN;
+use stm32f469_base_rtic::data_structures::log_queue::IsrContext;
+use stm32f469_base_rtic::data_structures::spsc::{RxConsumer, RxProducer, RxQueue};
+use stm32f469_base_rtic::errors::errors::{DeviceError, DmaError, UsartError, UsbError};
+use stm32f469_base_rtic::peripherals::mock::{MockSerial, MockUsb, Shared};
+use stm32f469_base_rtic::peripherals::traits::{LineError, SerialLink};
+use stm32f469_base_rtic::task_handlers::data_path::{
+    self, forward_rx, handle_usart_error, read_rx, store_rx,
+};
+use stm32f469_base_rtic::utils::retry::RetryState;
+use stm32f469_base_rtic::utils::statistics;
+
+/// RX queue halves as resources
+fn rx_queue() -> (Shared<RxProducer>, Shared<RxConsumer>) {
+    let (producer, consumer) = Box::leak(Box::new(RxQueue::new())).split();
+    (Shared(producer), Shared(consumer))
+}
+
+#[test]
+fn uart_bytes_reach_the_host() {
+    let mut usart = Shared(MockSerial::new());
+    let mut usb = Shared(MockUsb::new());
+    let (mut producer, mut consumer) = rx_queue();
+    usart.0.receive(b"hello, host");
+
+    let mut buffer = [0u8; 64];
+    let len = read_rx(&mut usart, &mut buffer).unwrap();
+    assert_eq!(&buffer[..len], b"hello, host");
+    store_rx(&mut producer, &buffer[..len]).unwrap();
+
+    let mut chunk = [0u8; 64];
+    assert_eq!(forward_rx(&mut usb, &mut consumer, &mut chunk), Ok(len));
+    assert_eq!(usb.0.received.as_slice(), b"hello, host");
+    assert!(consumer.0.is_empty());
+    assert_eq!(forward_rx(&mut usb, &mut consumer, &mut chunk), Ok(0));
+}
+
+#[test]
+fn overflowing_data_is_dropped_whole() {
+    let (mut producer, consumer) = rx_queue();
+    store_rx(&mut producer, &[0x55; RX_RING_BUFFER_LEN - 4]).unwrap();
+
+    let before = statistics::get_stats().dropped_bytes;
+    assert_eq!(
+        store_rx(&mut producer, &[0xAA; 8]),
+        Err(DmaError::BufferOverflow)
+    );
+    assert!(statistics::get_stats().dropped_bytes >= before + 8);
+    assert_eq!(consumer.0.len(), RX_RING_BUFFER_LEN - 4);
+}
+
+#[test]
+fn partial_writes_keep_the_rest_queued() {
+    let mut usb = Shared(MockUsb::new());
+    let (mut producer, mut consumer) = rx_queue();
+    usb.0.write_limit = 3;
+    store_rx(&mut producer, b"abcdefgh").unwrap();
+
+    let mut chunk = [0u8; 16];
+    assert_eq!(forward_rx(&mut usb, &mut consumer, &mut chunk), Ok(3));
+    assert_eq!(consumer.0.len(), 5);
+    while forward_rx(&mut usb, &mut consumer, &mut chunk).unwrap() > 0 {}
+    assert_eq!(usb.0.received.as_slice(), b"abcdefgh");
+}
+
+#[test]
+fn failed_writes_lose_nothing() {
+    let mut usb = Shared(MockUsb::new());
+    let (mut producer, mut consumer) = rx_queue();
+    store_rx(&mut producer, b"retry me").unwrap();
+
+    usb.0.fail_write = true;
+    let before = statistics::get_stats().usb_errors;
+    let mut chunk = [0u8; 16];
+    assert_eq!(
+        forward_rx(&mut usb, &mut consumer, &mut chunk),
+        Err(DeviceError::from(UsbError::WriteError))
+    );
+    assert!(statistics::get_stats().usb_errors > before);
+    assert_eq!(consumer.0.len(), 8);
+
+    usb.0.fail_write = false;
+    assert_eq!(forward_rx(&mut usb, &mut consumer, &mut chunk), Ok(8));
+    assert_eq!(usb.0.received.as_slice(), b"retry me");
+}
+
+#[test]
+fn dma_errors_restart_their_stream() {
+    let mut usart = MockSerial::new();
+    let mut retry = RetryState::new();
+    let isr = IsrContext::enter();
+    usart.rx_dma_error = true;
+    usart.tx_dma_error = true;
+
+    assert_eq!(handle_usart_error(&isr, &mut usart, &mut retry), Ok(()));
+    assert_eq!((usart.rx_restarts, usart.tx_restarts), (1, 1));
+    assert!(usart.errors_cleared >= 1);
+    assert!(!usart.check_dma_rx_error().unwrap());
+    assert_eq!(retry.failures(), 2);
+
+    // An error-free check clears the failure count
+    assert_eq!(handle_usart_error(&isr, &mut usart, &mut retry), Ok(()));
+    assert_eq!(retry.failures(), 0);
+}
+
+#[test]
+fn persistent_dma_errors_exhaust_the_retries() {
+    let mut usart = MockSerial::new();
+    let mut retry = RetryState::new();
+    let isr = IsrContext::enter();
+    for _ in 0..3 {
+        usart.rx_dma_error = true;
+        assert_eq!(handle_usart_error(&isr, &mut usart, &mut retry), Ok(()));
+    }
+    assert_eq!(usart.rx_restarts, 3);
+
+    usart.rx_dma_error = true;
+    assert_eq!(
+        handle_usart_error(&isr, &mut usart, &mut retry),
+        Err(DeviceError::from(DmaError::RetryLimitExceeded))
+    );
+    assert_eq!(usart.rx_restarts, 3);
+    assert!(data_path::DMA_RETRY.stats().exhausted >= 1);
+}
+
+#[test]
+fn failed_restarts_are_reported() {
+    let mut usart = MockSerial::new();
+    let mut retry = RetryState::new();
+    let isr = IsrContext::enter();
+    usart.tx_dma_error = true;
+    usart.fail_restart = true;
+
+    assert_eq!(
+        handle_usart_error(&isr, &mut usart, &mut retry),
+        Err(DeviceError::from(DmaError::InitError))
+    );
+    assert!(usart.tx_dma_error);
+}
+
+#[test]
+fn line_errors_are_counted_and_classified() {
+    let mut usart = MockSerial::new();
+    let mut retry = RetryState::new();
+    let isr = IsrContext::enter();
+    usart.line_errors = LineError::ORE | LineError::FE;
+
+    let before = statistics::get_stats();
+    assert_eq!(
+        handle_usart_error(&isr, &mut usart, &mut retry),
+        Err(DeviceError::Usart(UsartError::Framing))
+    );
+    let after = statistics::get_stats();
+    assert!(after.overrun_errors > before.overrun_errors);
+    assert!(after.framing_errors > before.framing_errors);
+
+    // Taken by the first check
+    assert_eq!(handle_usart_error(&isr, &mut usart, &mut retry), Ok(()));
+}
+
+#[test]
+fn dma_transfers_collect_on_the_line() {
+    let mut usart = MockSerial::new();
+    usart.hold_tx = true;
+    usart
+        .get_tx_buffer_slice(4)
+        .unwrap()
+        .copy_from_slice(b"ping");
+    usart.write_dma(4).unwrap();
+    assert!(usart.is_tx_busy());
+    assert!(usart.write_dma(4).is_err());
+
+    usart.complete_tx();
+    // SAFETY: the mock copies the data before returning
+    unsafe { usart.transmit_external(b"pong").unwrap() };
+    assert_eq!(usart.sent.as_slice(), b"pingpong");
+}
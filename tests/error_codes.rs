@@ -0,0 +1,60 @@
+//! Error code registry, on the host
+
+use stm32f469_base_rtic::errors::errors::{
+    domain, DeviceError, DmaError, InitError, UsartError, UsbError,
+};
+
+#[test]
+fn codes_sit_in_their_domain_block() {
+    assert_eq!(UsbError::NotInitialized.code(), 101);
+    assert_eq!(UsbError::WriteError.code(), 103);
+    assert_eq!(DmaError::InitError.code(), 201);
+    assert_eq!(UsartError::Parity.code(), 311);
+    assert_eq!(InitError::UsbError.code(), 2102);
+
+    for error in UsartError::ALL {
+        assert_eq!(error.code() / domain::SIZE * domain::SIZE, UsartError::BASE);
+    }
+    assert_eq!(domain::name(UsbError::PollError.code()), Some("USB"));
+    assert_eq!(domain::name(9_999), None);
+}
+
+#[test]
+fn device_errors_keep_the_source_error() {
+    let error = DeviceError::from(UsbError::WriteError);
+    assert_eq!(error, DeviceError::Usb(UsbError::WriteError));
+    assert_eq!(error.code(), UsbError::WriteError.code());
+    assert_eq!(error.description(), "Failed to write to USB");
+
+    // USART errors stay USART errors, not DMA errors
+    let error = DeviceError::from(UsartError::Timeout);
+    assert_eq!(error.code(), 303);
+    assert_eq!(
+        DeviceError::from(UsartError::Framing).code(),
+        UsartError::Framing.code()
+    );
+    assert_eq!(DeviceError::SupplyDip.code(), 12);
+}
+
+#[test]
+fn every_code_maps_back_to_its_error() {
+    let mut count = 0;
+    for code in 0..=u16::MAX {
+        if let Some(error) = DeviceError::from_code(code) {
+            assert_eq!(error.code(), code);
+            count += 1;
+        }
+    }
+    assert!(count > DmaError::ALL.len() + UsbError::ALL.len());
+
+    for &error in UsbError::ALL {
+        assert_eq!(UsbError::from_code(error.code()), Some(error));
+    }
+    assert_eq!(UsbError::from_code(0), None);
+    assert_eq!(DeviceError::from_code(5), None);
+    assert_eq!(
+        DeviceError::from_code(DmaError::InitError.code()),
+        Some(DeviceError::Dma(DmaError::InitError))
+    );
+    assert_eq!(DeviceError::from_code(domain::SPI), None);
+}
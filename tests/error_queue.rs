@@ -0,0 +1,134 @@
+//! Error record store and its calendar timestamps, on the host
+
+use stm32f469_base_rtic::data_structures::error_queue::{
+    ErrorRecord, ErrorStore, Severity, ERROR_RECORD_CAPACITY,
+};
+use stm32f469_base_rtic::utils::datetime::DateTime;
+
+fn at(second: u8) -> Option<DateTime> {
+    DateTime::new(2025, 3, 14, 12, 0, second)
+}
+
+#[test]
+fn repeats_of_a_code_share_one_record() {
+    let mut store = ErrorStore::new();
+    assert!(store.record(7, Severity::Warning, at(1)));
+    assert!(store.record(7, Severity::Critical, at(2)));
+    assert!(store.record(7, Severity::Error, at(3)));
+
+    let record = store.get(7).unwrap();
+    assert_eq!(store.len(), 1);
+    assert_eq!(record.count, 3);
+    assert_eq!(record.severity, Severity::Critical);
+    assert_eq!(record.first_seen, at(1));
+    assert_eq!(record.last_seen, at(3));
+}
+
+#[test]
+fn full_store_evicts_the_oldest_lower_severity_record() {
+    let mut store = ErrorStore::new();
+    for code in 0..ERROR_RECORD_CAPACITY as u16 {
+        let severity = if code == 3 {
+            Severity::Warning
+        } else {
+            Severity::Error
+        };
+        assert!(store.record(code, severity, None));
+    }
+
+    assert!(store.record(100, Severity::Error, None));
+    assert!(store.get(3).is_none());
+    assert!(store.get(100).is_some());
+
+    // No warning left to make room for another error
+    assert!(!store.record(101, Severity::Error, None));
+    assert!(store.record(101, Severity::Critical, None));
+    assert!(store.get(0).is_none());
+}
+
+#[test]
+fn records_are_copied_most_severe_first() {
+    let mut store = ErrorStore::new();
+    store.record(1, Severity::Warning, None);
+    store.record(2, Severity::Error, None);
+    store.record(3, Severity::Critical, None);
+    store.record(4, Severity::Error, None);
+
+    let mut records = [ErrorRecord::default(); 8];
+    let count = store.copy_by_priority(&mut records);
+    let codes: Vec<u16> = records[..count].iter().map(|r| r.code).collect();
+    assert_eq!(codes, [3, 2, 4, 1]);
+
+    let mut first_two = [ErrorRecord::default(); 2];
+    assert_eq!(store.copy_by_priority(&mut first_two), 2);
+    assert_eq!(first_two[1].code, 2);
+}
+
+#[test]
+fn consume_keeps_reports_that_arrived_later() {
+    let mut store = ErrorStore::new();
+    for _ in 0..3 {
+        store.record(9, Severity::Error, None);
+    }
+    assert!(store.mark_played(9));
+    assert!(store.consume(9, 2));
+
+    let record = store.get(9).unwrap();
+    assert_eq!(record.count, 1);
+    assert_eq!(record.plays, 0);
+
+    assert!(store.consume(9, 1));
+    assert!(store.is_empty());
+    assert!(!store.consume(9, 1));
+    assert!(!store.mark_played(9));
+}
+
+#[test]
+fn take_first_removes_the_most_severe_record() {
+    let mut store = ErrorStore::new();
+    store.record(1, Severity::Error, None);
+    store.record(2, Severity::Critical, None);
+
+    assert_eq!(store.take_first().map(|r| r.code), Some(2));
+    assert_eq!(store.take_first().map(|r| r.code), Some(1));
+    assert_eq!(store.take_first(), None);
+
+    store.record(5, Severity::Warning, None);
+    assert_eq!(store.clear(), 1);
+}
+
+#[test]
+fn severity_tags() {
+    let tags: String = Severity::BY_PRIORITY.iter().map(|s| s.tag()).collect();
+    assert_eq!(tags, "CEW");
+}
+
+#[test]
+fn date_time_is_validated() {
+    assert!(DateTime::new(2024, 2, 29, 23, 59, 59).is_some());
+    assert!(DateTime::new(2023, 2, 29, 0, 0, 0).is_none());
+    assert!(DateTime::new(2025, 4, 31, 0, 0, 0).is_none());
+    assert!(DateTime::new(1999, 12, 31, 0, 0, 0).is_none());
+    assert!(DateTime::new(2025, 1, 1, 24, 0, 0).is_none());
+}
+
+#[test]
+fn date_time_parses_and_prints_iso_8601() {
+    let time = DateTime::parse("2025-03-14 09:26:53").unwrap();
+    assert_eq!(time.to_string(), "2025-03-14T09:26:53");
+    assert_eq!(DateTime::parse("2025-03-14T09:26:53"), Some(time));
+    assert_eq!(DateTime::parse("2025-03-14T09:26"), None);
+    assert_eq!(DateTime::parse("2025-13-01T00:00:00"), None);
+}
+
+#[test]
+fn date_time_weekday_and_fat_fields() {
+    // 2000-01-01 was a Saturday, 2025-03-14 a Friday
+    assert_eq!(DateTime::new(2000, 1, 1, 0, 0, 0).unwrap().weekday(), 6);
+    let time = DateTime::new(2025, 3, 14, 9, 26, 53).unwrap();
+    assert_eq!(time.weekday(), 5);
+    assert_eq!(
+        time.to_fat(),
+        ((45 << 9) | (3 << 5) | 14, (9 << 11) | (26 << 5) | 26)
+    );
+}
@@ -0,0 +1,127 @@
+//! COBS, CRC32 and the packet framer of the UART link, on the host
+
+use stm32f469_base_rtic::config::FRAME_MAX_PAYLOAD_LEN;
+use stm32f469_base_rtic::errors::errors::ProtocolError;
+use stm32f469_base_rtic::protocol::framer::{
+    encode_packet, FrameDecoder, DELIMITER, MAX_FRAME_LEN,
+};
+use stm32f469_base_rtic::protocol::{cobs, crc32};
+
+/// Feeds a byte stream, collecting the outcome of every frame
+fn decode_stream(decoder: &mut FrameDecoder, stream: &[u8]) -> Vec<Result<Vec<u8>, ProtocolError>> {
+    let mut payload = [0u8; FRAME_MAX_PAYLOAD_LEN];
+    stream
+        .iter()
+        .filter_map(|&byte| {
+            let result = decoder.push(byte, &mut payload)?;
+            Some(result.map(|len| payload[..len].to_vec()))
+        })
+        .collect()
+}
+
+#[test]
+fn crc32_matches_the_mpeg2_check_value() {
+    assert_eq!(crc32::checksum(b"123456789"), 0x0376_E6E7);
+    let split = crc32::update(crc32::update(crc32::INIT, b"1234"), b"56789");
+    assert_eq!(split, 0x0376_E6E7);
+}
+
+#[test]
+fn cobs_removes_zero_bytes() {
+    let mut encoded = [0u8; 16];
+    let len = cobs::encode(&[0x11, 0x00, 0x00, 0x22], &mut encoded).unwrap();
+    assert_eq!(&encoded[..len], [0x02, 0x11, 0x01, 0x02, 0x22]);
+
+    let mut decoded = [0u8; 16];
+    let len = cobs::decode(&encoded[..len], &mut decoded).unwrap();
+    assert_eq!(&decoded[..len], [0x11, 0x00, 0x00, 0x22]);
+}
+
+#[test]
+fn cobs_round_trips_long_runs() {
+    let data: Vec<u8> = (0..600u32).map(|i| (i % 255 + 1) as u8).collect();
+    let mut encoded = vec![0u8; cobs::max_encoded_len(data.len())];
+    let len = cobs::encode(&data, &mut encoded).unwrap();
+    assert!(!encoded[..len].contains(&0));
+
+    let mut decoded = vec![0u8; data.len()];
+    assert_eq!(cobs::decode(&encoded[..len], &mut decoded), Ok(data.len()));
+    assert_eq!(decoded, data);
+}
+
+#[test]
+fn cobs_rejects_bad_input() {
+    let mut decoded = [0u8; 4];
+    assert_eq!(
+        cobs::decode(&[0x03, 0x11], &mut decoded),
+        Err(ProtocolError::Malformed)
+    );
+    assert_eq!(
+        cobs::decode(&[0x02, 0x00], &mut decoded),
+        Err(ProtocolError::Malformed)
+    );
+    assert_eq!(
+        cobs::decode(&[0x06, 1, 2, 3, 4, 5], &mut decoded),
+        Err(ProtocolError::BufferTooSmall)
+    );
+    assert_eq!(
+        cobs::encode(&[1, 2, 3], &mut [0u8; 3]),
+        Err(ProtocolError::BufferTooSmall)
+    );
+}
+
+#[test]
+fn packets_survive_the_framer() {
+    let mut stream = Vec::new();
+    let mut frame = [0u8; MAX_FRAME_LEN + 1];
+    for payload in [&b"\x00hello\x00"[..], b"", b"x"] {
+        let len = encode_packet(payload, &mut frame).unwrap();
+        assert_eq!(frame[len - 1], DELIMITER);
+        assert!(!frame[..len - 1].contains(&DELIMITER));
+        stream.extend_from_slice(&frame[..len]);
+    }
+
+    let mut decoder = FrameDecoder::new();
+    let results = decode_stream(&mut decoder, &stream);
+    assert_eq!(
+        results,
+        [
+            Ok(b"\x00hello\x00".to_vec()),
+            Ok(Vec::new()),
+            Ok(b"x".to_vec())
+        ]
+    );
+}
+
+#[test]
+fn corrupted_frames_are_dropped_and_the_stream_resynchronizes() {
+    let mut frame = [0u8; MAX_FRAME_LEN + 1];
+    let len = encode_packet(b"payload", &mut frame).unwrap();
+    let mut stream = frame[..len].to_vec();
+    stream[2] ^= 0x40;
+    stream.extend_from_slice(&[DELIMITER, DELIMITER]);
+    stream.extend_from_slice(&frame[..len]);
+
+    let mut decoder = FrameDecoder::new();
+    let results = decode_stream(&mut decoder, &stream);
+    assert_eq!(
+        results,
+        [Err(ProtocolError::CrcMismatch), Ok(b"payload".to_vec())]
+    );
+}
+
+#[test]
+fn oversized_packets_are_rejected() {
+    let mut frame = [0u8; MAX_FRAME_LEN + 1];
+    let payload = vec![0x55; FRAME_MAX_PAYLOAD_LEN + 1];
+    assert_eq!(
+        encode_packet(&payload, &mut frame),
+        Err(ProtocolError::FrameTooLong)
+    );
+
+    let mut decoder = FrameDecoder::new();
+    let mut stream = vec![0x55; MAX_FRAME_LEN + 1];
+    stream.push(DELIMITER);
+    let results = decode_stream(&mut decoder, &stream);
+    assert_eq!(results, [Err(ProtocolError::FrameTooLong)]);
+}
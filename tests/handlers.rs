@@ -0,0 +1,162 @@
+//! Task handler logic without peripheral access, on the host
+
+use stm32f469_base_rtic::config::{NEGOTIATION_BAUD_RATES, USART6_BAUD_RATE};
+use stm32f469_base_rtic::task_handlers::auto_baud::{self, Outcome};
+use stm32f469_base_rtic::task_handlers::baud_negotiation::{
+    Frame, FrameAssembler, FrameKind, Negotiator, Op, Ops, Role,
+};
+use stm32f469_base_rtic::task_handlers::error_notify;
+use stm32f469_base_rtic::task_handlers::task_registry::{self, Subsystem};
+
+/// Frames sent by `ops`, passed through the wire format
+fn sent(ops: &Ops) -> Vec<Frame> {
+    let mut assembler = FrameAssembler::default();
+    ops.iter()
+        .filter_map(|op| match op {
+            Op::Send(frame) => Some(frame.encode()),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|byte| assembler.feed(byte))
+        .collect()
+}
+
+/// Rate an operation list finishes the negotiation at
+fn finished(ops: &Ops) -> Option<u32> {
+    ops.iter().find_map(|op| match op {
+        Op::Finish(baud) => Some(*baud),
+        _ => None,
+    })
+}
+
+#[test]
+fn negotiation_settles_on_the_fastest_rate() {
+    let mut initiator = Negotiator::new(Role::Initiator, 0x1234_5678);
+    let mut responder = Negotiator::new(Role::Responder, 0);
+    assert_eq!(
+        responder.start().as_slice(),
+        [Op::SwitchBaud(USART6_BAUD_RATE)]
+    );
+
+    let mut to_responder = sent(&initiator.start());
+    let (mut initiator_rate, mut responder_rate) = (None, None);
+    while let Some(frame) = to_responder.pop() {
+        let reply = responder.on_frame(frame);
+        responder_rate = responder_rate.or(finished(&reply));
+        for frame in sent(&reply) {
+            let ops = initiator.on_frame(frame);
+            initiator_rate = initiator_rate.or(finished(&ops));
+            to_responder.extend(sent(&ops));
+        }
+    }
+
+    assert_eq!(initiator_rate, Some(NEGOTIATION_BAUD_RATES[0]));
+    assert_eq!(responder_rate, Some(NEGOTIATION_BAUD_RATES[0]));
+}
+
+#[test]
+fn negotiation_falls_back_to_the_safe_rate() {
+    let mut initiator = Negotiator::new(Role::Initiator, 1);
+    let mut proposed: Vec<u32> = sent(&initiator.start()).iter().map(|f| f.baud).collect();
+
+    let rate = loop {
+        let ops = initiator.on_timeout();
+        assert_eq!(ops[0], Op::SwitchBaud(USART6_BAUD_RATE));
+        if let Some(rate) = finished(&ops) {
+            break rate;
+        }
+        proposed.extend(sent(&ops).iter().map(|f| f.baud));
+    };
+
+    assert_eq!(proposed, NEGOTIATION_BAUD_RATES);
+    assert_eq!(rate, USART6_BAUD_RATE);
+}
+
+#[test]
+fn negotiation_ignores_frames_of_another_nonce() {
+    let mut initiator = Negotiator::new(Role::Initiator, 7);
+    initiator.start();
+    let stale = Frame {
+        kind: FrameKind::Accept,
+        baud: NEGOTIATION_BAUD_RATES[0],
+        nonce: 8,
+    };
+    assert!(initiator.on_frame(stale).is_empty());
+}
+
+#[test]
+fn frame_assembler_resynchronizes_on_sync() {
+    let frame = Frame {
+        kind: FrameKind::Test,
+        baud: 230_400,
+        nonce: 0xCAFE,
+    };
+    let mut corrupted = frame.encode();
+    corrupted[10] ^= 1;
+
+    let mut assembler = FrameAssembler::default();
+    let stream = [0x00, 0x42]
+        .into_iter()
+        .chain(corrupted)
+        .chain(frame.encode());
+    let frames: Vec<Frame> = stream.filter_map(|byte| assembler.feed(byte)).collect();
+    assert_eq!(frames, [frame]);
+}
+
+#[test]
+fn auto_baud_snaps_to_standard_rates() {
+    assert_eq!(auto_baud::snap(115_000), Some(115_200));
+    assert_eq!(auto_baud::snap(9_700), Some(9_600));
+    assert_eq!(auto_baud::snap(100_000), None);
+
+    assert_eq!(Outcome::from_measurement(57_000).baud(), Some(57_600));
+    assert_eq!(
+        Outcome::from_measurement(75_000).to_string(),
+        "measured 75000 baud, no standard rate, kept"
+    );
+}
+
+#[test]
+fn error_notification_frame() {
+    assert_eq!(
+        error_notify::format_frame(12, 3).as_str(),
+        "\x1b!ERR 12 x3\r\n"
+    );
+    assert_eq!(
+        error_notify::format_frame(u16::MAX, u32::MAX).as_str(),
+        "\x1b!ERR 65535 x4294967295\r\n"
+    );
+}
+
+#[test]
+fn error_notification_rate_window() {
+    error_notify::set_enabled(true);
+    error_notify::next_window();
+
+    // The first error is due at once, later ones wait for the next window
+    assert!(error_notify::record(7));
+    assert_eq!(error_notify::take_due(), Some((7, 1)));
+    assert!(!error_notify::record(8));
+    assert!(!error_notify::record(9));
+    assert_eq!(error_notify::take_due(), None);
+
+    error_notify::next_window();
+    assert_eq!(error_notify::take_due(), Some((9, 2)));
+    assert_eq!(error_notify::take_due(), None);
+
+    error_notify::set_enabled(false);
+    assert!(!error_notify::record(10));
+}
+
+#[test]
+fn task_registry_toggles_subsystems_by_name() {
+    let health = task_registry::subsystem_by_name("health").unwrap();
+    assert_eq!(health, Subsystem::HEALTH_MONITOR);
+    assert_eq!(task_registry::subsystem_by_name("Health"), None);
+
+    task_registry::disable(health);
+    assert!(!task_registry::is_enabled(health));
+    assert!(task_registry::states().any(|state| state == ("health", false)));
+    task_registry::enable(health);
+    assert!(task_registry::is_enabled(health));
+}
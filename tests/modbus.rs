@@ -0,0 +1,144 @@
+//! Modbus RTU framing and console requests, on the host (`modbus` feature)
+
+use stm32f469_base_rtic::errors::errors::ModbusError;
+use stm32f469_base_rtic::protocol::modbus::{
+    crc16, decode_response, is_complete, silence_bit_times, Function, Request, Response, Values,
+};
+use stm32f469_base_rtic::task_handlers::modbus::{parse, Transaction};
+
+#[test]
+fn read_request_frame() {
+    let request = Request::read(1, Function::ReadHoldingRegisters, 0, 2).unwrap();
+    assert_eq!(
+        request.encode().as_slice(),
+        [0x01, 0x03, 0x00, 0x00, 0x00, 0x02, 0xC4, 0x0B]
+    );
+    assert_eq!(request.response_len(), 9);
+}
+
+#[test]
+fn requests_outside_the_limits_are_refused() {
+    let read = |slave, count| Request::read(slave, Function::ReadInputRegisters, 0, count);
+    assert_eq!(read(0, 1), Err(ModbusError::InvalidRequest));
+    assert_eq!(read(248, 1), Err(ModbusError::InvalidRequest));
+    assert_eq!(read(1, 0), Err(ModbusError::InvalidRequest));
+    assert_eq!(read(1, 65), Err(ModbusError::InvalidRequest));
+    assert_eq!(
+        Request::read(1, Function::ReadCoils, 0xFFFF, 2),
+        Err(ModbusError::InvalidRequest)
+    );
+    assert_eq!(
+        Request::write(1, true, 0, &[1, 0]),
+        Err(ModbusError::InvalidRequest)
+    );
+}
+
+#[test]
+fn register_response_is_decoded() {
+    let request = Request::read(1, Function::ReadHoldingRegisters, 0, 2).unwrap();
+    let frame = [0x01, 0x03, 0x04, 0x00, 0x11, 0x00, 0x04, 0xAB, 0xF5];
+    assert!(!is_complete(&request, &frame[..8]));
+    assert!(is_complete(&request, &frame));
+
+    let values = Values::from_slice(&[0x11, 0x04]).unwrap();
+    assert_eq!(
+        decode_response(&request, &frame),
+        Ok(Response::Values(values))
+    );
+
+    let mut corrupted = frame;
+    corrupted[4] = 0x12;
+    assert_eq!(
+        decode_response(&request, &corrupted),
+        Err(ModbusError::CrcMismatch)
+    );
+}
+
+#[test]
+fn coil_response_is_unpacked_to_bits() {
+    let request = Request::read(1, Function::ReadCoils, 0, 3).unwrap();
+    let frame = [0x01, 0x01, 0x01, 0x05, 0x91, 0x8B];
+    let values = Values::from_slice(&[1, 0, 1]).unwrap();
+    assert_eq!(
+        decode_response(&request, &frame),
+        Ok(Response::Values(values))
+    );
+}
+
+#[test]
+fn exception_response_ends_early() {
+    let request = Request::read(1, Function::ReadHoldingRegisters, 0, 10).unwrap();
+    let frame = [0x01, 0x83, 0x02, 0xC0, 0xF1];
+    assert!(is_complete(&request, &frame));
+    assert_eq!(
+        decode_response(&request, &frame),
+        Ok(Response::Exception(2))
+    );
+
+    let other_slave = Request::read(2, Function::ReadHoldingRegisters, 0, 10).unwrap();
+    assert_eq!(
+        decode_response(&other_slave, &frame),
+        Err(ModbusError::UnexpectedReply)
+    );
+}
+
+#[test]
+fn write_requests_and_their_echo() {
+    let coil = parse("17 coil 172 on").unwrap();
+    assert_eq!(coil.function, Function::WriteSingleCoil);
+    assert_eq!(
+        coil.encode().as_slice(),
+        [0x11, 0x05, 0x00, 0xAC, 0xFF, 0x00, 0x4E, 0x8B]
+    );
+
+    let registers = parse("1 write 0x10 10 0x0102").unwrap();
+    assert_eq!(registers.function, Function::WriteMultipleRegisters);
+    assert_eq!(registers.written(), [10, 0x0102]);
+    let echo = [0x01, 0x10, 0x00, 0x10, 0x00, 0x02, 0x40, 0x0D];
+    assert_eq!(decode_response(&registers, &echo), Ok(Response::Written));
+}
+
+#[test]
+fn console_requests_in_both_forms() {
+    let ascii = parse("1 holding 0 2").unwrap();
+    let json = parse(r#"{"slave":1, "fn":3, "addr":0, "count":2}"#).unwrap();
+    assert_eq!(ascii, json);
+
+    let json = parse(r#"{"slave":5,"fn":16,"addr":4,"values":[1,2,3]}"#).unwrap();
+    assert_eq!(json.written(), [1, 2, 3]);
+
+    assert!(parse("1 holding").is_err());
+    assert!(parse("300 holding 0").is_err());
+    assert!(parse(r#"{"slave":1,"fn":7,"addr":0}"#).is_err());
+    assert!(parse(r#"{"slave":1,"fn":6,"addr":0,"values":[1,2]}"#).is_err());
+}
+
+#[test]
+fn transaction_is_reported_as_json() {
+    let request = parse("1 holding 0 2").unwrap();
+    let values = Values::from_slice(&[17, 4]).unwrap();
+    let transaction = Transaction {
+        request,
+        result: Ok(Response::Values(values)),
+    };
+    assert_eq!(
+        transaction.to_string(),
+        r#"{"slave":1,"fn":3,"addr":0,"values":[17,4]}"#
+    );
+
+    let transaction = Transaction {
+        request,
+        result: Ok(Response::Exception(2)),
+    };
+    assert_eq!(
+        transaction.to_string(),
+        r#"{"slave":1,"fn":3,"addr":0,"exception":2,"text":"illegal data address"}"#
+    );
+}
+
+#[test]
+fn frame_gap_and_crc() {
+    assert_eq!(silence_bit_times(9_600), 39);
+    assert_eq!(silence_bit_times(115_200), 202);
+    assert_eq!(crc16(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x02]), 0x0BC4);
+}
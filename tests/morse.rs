@@ -0,0 +1,78 @@
+//! Morse encoder of the red LED error codes, on the host
+
+use stm32f469_base_rtic::errors::errors::{
+    DeviceError, DmaError, I2cError, RingBufferError, SpiError, UsbError,
+};
+use stm32f469_base_rtic::utils::morse::{
+    error_code_to_morse, number_to_morse, text_to_morse, Mnemonic,
+};
+
+fn encode<'a>(result: Result<usize, &'static str>, buffer: &'a [u8]) -> &'a str {
+    std::str::from_utf8(&buffer[..result.unwrap()]).unwrap()
+}
+
+#[test]
+fn numbers_are_sent_digit_by_digit() {
+    let mut buffer = [0u8; 64];
+    let result = number_to_morse(123, &mut buffer);
+    assert_eq!(encode(result, &buffer), ".---- ..--- ...--");
+
+    let result = number_to_morse(0, &mut buffer);
+    assert_eq!(encode(result, &buffer), "-----");
+
+    let result = number_to_morse(60_005, &mut buffer);
+    assert_eq!(encode(result, &buffer), "-.... ----- ----- ----- .....");
+}
+
+#[test]
+fn words_are_separated_by_word_gaps() {
+    let mut buffer = [0u8; 64];
+    let result = text_to_morse("sos 5", &mut buffer);
+    assert_eq!(encode(result, &buffer), "... --- .../.....");
+}
+
+#[test]
+fn unsupported_characters_and_short_buffers_fail() {
+    let mut buffer = [0u8; 64];
+    assert!(text_to_morse("A!", &mut buffer).is_err());
+    assert!(number_to_morse(88, &mut buffer[..6]).is_err());
+}
+
+#[test]
+fn device_errors_play_as_their_mnemonic() {
+    let mut buffer = [0u8; 64];
+    let code = DeviceError::from(RingBufferError::BufferOverflow).code();
+    let result = error_code_to_morse(code, &mut buffer);
+    assert_eq!(encode(result, &buffer), "-... ---");
+
+    let result = error_code_to_morse(9_999, &mut buffer);
+    assert_eq!(encode(result, &buffer), "----. ----. ----. ----.");
+}
+
+#[test]
+fn mnemonics_are_letters_only() {
+    assert_eq!(UsbError::WriteError.mnemonic(), "UW");
+    let mut seen = Vec::new();
+    for error in (0..=u16::MAX).filter_map(DeviceError::from_code) {
+        let mnemonic = error.mnemonic();
+        assert!((2..=3).contains(&mnemonic.len()));
+        assert!(mnemonic.bytes().all(|b| b.is_ascii_uppercase()));
+        assert!(!seen.contains(&mnemonic), "{mnemonic} used twice");
+        seen.push(mnemonic);
+    }
+    assert!(!seen.is_empty());
+}
+
+#[test]
+fn wrapped_errors_keep_their_own_mnemonic() {
+    let mut buffer = [0u8; 64];
+    let code = DeviceError::from(DmaError::SequenceViolation).code();
+    let result = error_code_to_morse(code, &mut buffer);
+    assert_eq!(encode(result, &buffer), "-.. ... --.-");
+
+    assert_eq!(DeviceError::from(DmaError::InitError).mnemonic(), "DI");
+    assert_ne!(
+        DeviceError::from(SpiError::Timeout).mnemonic(),
+        DeviceError::from(I2cError::Timeout).mnemonic()
+    );
+}
@@ -0,0 +1,139 @@
+//! NMEA 0183 sentences and the GNSS fix, on the host (`nmea` feature)
+
+use stm32f469_base_rtic::errors::errors::NmeaError;
+use stm32f469_base_rtic::protocol::nmea::{
+    self, Date, Position, Sentence, SentenceReader, UtcTime, MAX_SENTENCE_LEN,
+};
+use stm32f469_base_rtic::task_handlers::gnss::Fix;
+
+const GGA: &str = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47\r\n";
+const RMC: &str = "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n";
+const RMC_NO_FIX: &str = "$GNRMC,081836,V,,,,,,,010125,,*2C\r\n";
+
+const TIME: UtcTime = UtcTime {
+    hour: 12,
+    minute: 35,
+    second: 19,
+};
+
+const POSITION: Position = Position {
+    latitude_e7: 481_173_000,
+    longitude_e7: 115_166_666,
+};
+
+/// Sentences completed by `text`
+fn read(reader: &mut SentenceReader, text: &str) -> Vec<Result<Sentence, NmeaError>> {
+    text.bytes().filter_map(|byte| reader.push(byte)).collect()
+}
+
+#[test]
+fn gga_is_decoded() {
+    let sentences = read(&mut SentenceReader::new(), GGA);
+    assert_eq!(
+        sentences,
+        [Ok(Sentence::Gga {
+            time: Some(TIME),
+            position: Some(POSITION),
+            quality: 1,
+            satellites: 8,
+            altitude_dm: Some(5_454),
+        })]
+    );
+}
+
+#[test]
+fn rmc_is_decoded() {
+    let sentences = read(&mut SentenceReader::new(), RMC);
+    let date = Date {
+        year: 2094,
+        month: 3,
+        day: 23,
+    };
+    assert_eq!(
+        sentences,
+        [Ok(Sentence::Rmc {
+            time: Some(TIME),
+            valid: true,
+            position: Some(POSITION),
+            date: Some(date),
+        })]
+    );
+}
+
+#[test]
+fn sentences_are_assembled_across_reads() {
+    let mut reader = SentenceReader::new();
+    let (head, tail) = GGA.split_at(20);
+    assert!(read(&mut reader, "noise*00\r\n").is_empty());
+    assert!(read(&mut reader, head).is_empty());
+    assert_eq!(read(&mut reader, tail).len(), 1);
+
+    // A `$` starts over, dropping the broken sentence
+    let sentences = read(&mut reader, &format!("$GPGGA,1235{}", RMC));
+    assert!(matches!(sentences[..], [Ok(Sentence::Rmc { .. })]));
+}
+
+#[test]
+fn bad_sentences_are_reported() {
+    let mut reader = SentenceReader::new();
+    let corrupted = GGA.replace("545.4", "545.5");
+    assert_eq!(read(&mut reader, &corrupted), [Err(NmeaError::Checksum)]);
+    assert_eq!(
+        read(&mut reader, "$GPGSV,1,1,00\r\n"),
+        [Err(NmeaError::Malformed)]
+    );
+    let bad_hemisphere = "$GPGGA,123519,4807.038,X,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*51\r\n";
+    assert_eq!(
+        read(&mut reader, bad_hemisphere),
+        [Err(NmeaError::Malformed)]
+    );
+    let long = format!("${}\r\n", "A".repeat(MAX_SENTENCE_LEN));
+    assert_eq!(read(&mut reader, &long), [Err(NmeaError::TooLong)]);
+    assert_eq!(
+        read(&mut reader, "$GPGSV,1,1,00*79\r\n"),
+        [Ok(Sentence::Other)]
+    );
+}
+
+#[test]
+fn fix_merges_gga_and_rmc() {
+    let mut reader = SentenceReader::new();
+    let mut fix = Fix::new();
+    assert!(!fix.has_fix());
+
+    for sentence in read(&mut reader, &format!("{}{}", GGA, RMC)) {
+        fix.apply(sentence.unwrap());
+    }
+    assert!(fix.has_fix());
+    assert_eq!(fix.position, Some(POSITION));
+    assert_eq!(fix.altitude_dm, Some(5_454));
+    assert_eq!(fix.satellites, 8);
+    assert_eq!(
+        fix.date.map(|date| date.to_string()).as_deref(),
+        Some("2094-03-23")
+    );
+
+    // The receiver lost the fix: time and date stay, the position goes
+    for sentence in read(&mut reader, RMC_NO_FIX) {
+        fix.apply(sentence.unwrap());
+    }
+    assert!(!fix.valid);
+    assert_eq!(fix.position, None);
+    assert_eq!(
+        fix.time.map(|time| time.to_string()).as_deref(),
+        Some("08:18:36")
+    );
+    assert_eq!(
+        fix.date.map(|date| date.to_string()).as_deref(),
+        Some("2025-01-01")
+    );
+}
+
+#[test]
+fn fixed_point_values_are_printed_with_all_decimals() {
+    assert_eq!(POSITION.to_string(), "48.1173000,11.5166666");
+    let mut text = String::new();
+    nmea::write_fixed(&mut text, -5, 1).unwrap();
+    assert_eq!(text, "-0.5");
+    assert_eq!(nmea::checksum("GPGSV,1,1,00"), 0x79);
+}
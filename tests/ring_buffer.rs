@@ -0,0 +1,154 @@
+//! Ring buffer and SPSC queue, on the host
+
+use std::sync::Mutex;
+
+use stm32f469_base_rtic::data_structures::ring_buffer::{FillLevel, RingBuffer, Watermarks};
+use stm32f469_base_rtic::data_structures::spsc::SpscQueue;
+use stm32f469_base_rtic::errors::errors::RingBufferError;
+
+/// Crossings reported by `record_crossing`
+static CROSSINGS: Mutex<Vec<FillLevel>> = Mutex::new(Vec::new());
+
+fn record_crossing(level: FillLevel) {
+    CROSSINGS.lock().unwrap().push(level);
+}
+
+#[test]
+fn push_then_pop_returns_bytes_in_order() {
+    let mut buffer = RingBuffer::<8>::new();
+    buffer.push(b"abc").unwrap();
+    buffer.push(b"de").unwrap();
+    assert_eq!(buffer.len(), 5);
+    assert_eq!(buffer.available_space(), 3);
+
+    let mut out = [0u8; 8];
+    assert_eq!(buffer.pop(&mut out), 5);
+    assert_eq!(&out[..5], b"abcde");
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn data_wraps_around_the_end() {
+    let mut buffer = RingBuffer::<8>::new();
+    let mut out = [0u8; 8];
+    buffer.push(b"123456").unwrap();
+    assert_eq!(buffer.pop(&mut out[..4]), 4);
+
+    buffer.push(b"789ab").unwrap();
+    assert_eq!(buffer.len(), 7);
+    assert_eq!(buffer.pop(&mut out), 7);
+    assert_eq!(&out[..7], b"56789ab");
+}
+
+#[test]
+fn push_beyond_capacity_is_rejected_whole() {
+    let mut buffer = RingBuffer::<4>::new();
+    buffer.push(b"ab").unwrap();
+    assert_eq!(buffer.push(b"cde"), Err(RingBufferError::BufferOverflow));
+    assert_eq!(buffer.len(), 2);
+    buffer.push(b"cd").unwrap();
+    assert_eq!(buffer.available_space(), 0);
+}
+
+#[test]
+fn pop_n_is_bounded_by_count_and_vector_capacity() {
+    let mut buffer = RingBuffer::<16>::new();
+    buffer.push(b"0123456789").unwrap();
+
+    let head = buffer.pop_n::<4>(8);
+    assert_eq!(head.as_slice(), b"0123");
+    let rest = buffer.pop_n::<16>(3);
+    assert_eq!(rest.as_slice(), b"456");
+    assert_eq!(buffer.len(), 3);
+}
+
+#[test]
+fn clear_empties_the_buffer() {
+    let mut buffer = RingBuffer::<8>::new();
+    buffer.push(b"xyz").unwrap();
+    buffer.clear();
+    assert!(buffer.is_empty());
+    assert_eq!(buffer.pop(&mut [0u8; 4]), 0);
+}
+
+#[test]
+fn watermarks_report_each_crossing_once() {
+    let mut buffer = RingBuffer::<16>::with_watermarks(Watermarks::new(12, 4, record_crossing));
+    let mut out = [0u8; 16];
+
+    buffer.push(&[0; 10]).unwrap();
+    buffer.push(&[0; 2]).unwrap();
+    assert!(buffer.is_above_high());
+    buffer.push(&[0; 2]).unwrap();
+    buffer.pop(&mut out[..8]);
+    assert!(buffer.is_above_high());
+    buffer.pop(&mut out[..2]);
+    assert!(!buffer.is_above_high());
+
+    assert_eq!(
+        *CROSSINGS.lock().unwrap(),
+        [FillLevel::High, FillLevel::Low]
+    );
+}
+
+#[test]
+fn watermark_low_is_clamped_below_high() {
+    let marks = Watermarks::new(8, 20, record_crossing);
+    assert_eq!(marks.crossing(8, false), Some(FillLevel::High));
+    assert_eq!(marks.crossing(7, true), Some(FillLevel::Low));
+    assert_eq!(marks.crossing(8, true), None);
+}
+
+#[test]
+fn spsc_halves_hand_bytes_over() {
+    let mut queue = SpscQueue::<8>::new();
+    let (mut producer, mut consumer) = queue.split();
+
+    producer.push(b"hello").unwrap();
+    assert_eq!(consumer.len(), 5);
+    assert_eq!(producer.available_space(), 3);
+    assert_eq!(
+        producer.push(b"world"),
+        Err(RingBufferError::BufferOverflow)
+    );
+
+    let mut out = [0u8; 8];
+    assert_eq!(consumer.peek(&mut out[..2]), 2);
+    assert_eq!(&out[..2], b"he");
+    assert_eq!(consumer.pop(&mut out), 5);
+    assert_eq!(&out[..5], b"hello");
+    assert!(producer.is_empty());
+}
+
+#[test]
+fn spsc_grants_end_at_the_buffer_end() {
+    let mut queue = SpscQueue::<8>::new();
+    let (mut producer, mut consumer) = queue.split();
+    producer.push(b"abcdef").unwrap();
+    assert_eq!(consumer.release(4), 4);
+
+    // Free space wraps: 2 bytes up to the end, then 4 from the start
+    let grant = producer.grant_max_remaining(8);
+    assert_eq!(grant.len(), 2);
+    grant.copy_from_slice(b"gh");
+    assert_eq!(producer.commit(2), 2);
+    let grant = producer.grant_max_remaining(3);
+    assert_eq!(grant.len(), 3);
+    grant.copy_from_slice(b"ijk");
+    producer.commit(3);
+
+    assert_eq!(consumer.read(), b"efgh");
+    consumer.release(4);
+    assert_eq!(consumer.read(), b"ijk");
+    consumer.clear();
+    assert!(consumer.is_empty());
+}
+
+#[test]
+fn spsc_commit_is_bounded_by_free_space() {
+    let mut queue = SpscQueue::<4>::new();
+    let (mut producer, consumer) = queue.split();
+    assert_eq!(producer.commit(10), 4);
+    assert_eq!(consumer.len(), 4);
+    assert!(producer.grant_max_remaining(4).is_empty());
+}